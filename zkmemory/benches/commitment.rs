@@ -0,0 +1,86 @@
+//! Scaling of the commitments with the number of threads of the `parallel` feature.
+//! Run with `cargo bench -p zkmemory --features parallel --bench commitment`
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use halo2_proofs::halo2curves::bn256::Fr;
+use rayon::ThreadPoolBuilder;
+use std::thread::available_parallelism;
+use zkmemory::{
+    commitment::merkle::{HashKind, MerkleTree},
+    CommitmentScheme, ConvertedTraceRecord, KZGMemoryCommitment, MemoryInstruction,
+    MerkleTraceCommitment, TraceRecord, B256,
+};
+
+// Number of leaves of the Merkle tree
+const TREE_LEAVES: usize = 1 << 18;
+// Number of records of the committed traces
+const TRACE_RECORDS: usize = 1 << 12;
+
+// Thread counts from one up to the cores of the host
+fn thread_counts() -> Vec<usize> {
+    let cores = available_parallelism().map_or(1, |cores| cores.get());
+    let mut counts: Vec<usize> = (0..)
+        .map(|power| 1 << power)
+        .take_while(|count| *count < cores)
+        .collect();
+    counts.push(cores);
+    counts
+}
+
+// Records of a trace writing distinct cells
+fn records(len: usize) -> Vec<ConvertedTraceRecord<Fr>> {
+    (0..len as u64)
+        .map(|i| {
+            let record = TraceRecord::<B256, B256, 32, 32>::new(
+                i,
+                0,
+                MemoryInstruction::Write,
+                B256::from(32 * i),
+                B256::from(i + 1),
+            );
+            ConvertedTraceRecord::from_trace_record(&record).unwrap()
+        })
+        .collect()
+}
+
+fn bench_commitments(c: &mut Criterion) {
+    let leaves: Vec<[u8; 32]> = (0..TREE_LEAVES)
+        .map(|i| {
+            let mut leaf = [0u8; 32];
+            leaf[..8].copy_from_slice(&(i as u64).to_be_bytes());
+            leaf
+        })
+        .collect();
+    let records = records(TRACE_RECORDS);
+    let merkle = MerkleTraceCommitment::new(HashKind::Blake2b);
+    let kzg = KZGMemoryCommitment::<B256, B256, 32, 32>::default();
+
+    let mut group = c.benchmark_group("commitment");
+    group.sample_size(10);
+    for threads in thread_counts() {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("merkle_tree", threads),
+            &threads,
+            |b, _| {
+                b.iter(|| {
+                    pool.install(|| MerkleTree::new(HashKind::Blake2b, leaves.clone()).root())
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("merkle_trace", threads),
+            &threads,
+            |b, _| b.iter(|| pool.install(|| merkle.commit(&records).unwrap())),
+        );
+        group.bench_with_input(BenchmarkId::new("kzg_trace", threads), &threads, |b, _| {
+            b.iter(|| pool.install(|| CommitmentScheme::commit(&kzg, &records).unwrap()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_commitments);
+criterion_main!(benches);
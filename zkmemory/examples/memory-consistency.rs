@@ -8,8 +8,8 @@ use zkmemory::{
     error::Error,
     impl_register_machine, impl_stack_machine, impl_state_machine,
     machine::{
-        AbstractContext, AbstractInstruction, AbstractMachine, CellInteraction, Register,
-        TraceRecord,
+        state::MachineState, AbstractContext, AbstractInstruction, AbstractMachine,
+        AbstractTraceRecord, CellInteraction, Register, TraceRecord,
     },
 };
 
@@ -54,7 +54,7 @@ where
 {
     // Memory
     memory: RBTree<K, V>,
-    memory_allocated: AllocatedSection<K>,
+    state: MachineState<K, V, S, T>,
     word_size: K,
     time_log: u64,
 
@@ -89,6 +89,8 @@ where
     V: Base<T>,
     M: AbstractMachine<K, V, Machine = StateMachine<K, V, S, T>>,
 {
+    type Memory = RBTree<K, V>;
+
     fn set_stack_depth(&mut self, stack_depth: u64) {
         self.stack_depth = stack_depth;
     }
@@ -116,6 +118,10 @@ where
     fn memory(&mut self) -> &'_ mut RBTree<K, V> {
         &mut self.memory
     }
+
+    fn ro_memory(&self) -> &'_ RBTree<K, V> {
+        &self.memory
+    }
 }
 
 impl<M, K, V, const S: usize, const T: usize> AbstractInstruction<M, K, V>
@@ -126,89 +132,73 @@ where
     V: Base<T>,
     M: AbstractMachine<K, V, Machine = StateMachine<K, V, S, T>>,
 {
-    fn exec(&self, machine: &mut M::Machine) {
+    fn exec(&self, machine: &mut M::Machine) -> Result<(), Error> {
         match self {
-            MyInstruction::Invalid(_) => {
-                panic!("Invalid instruction")
-            }
+            MyInstruction::Invalid(_) => return Err(Error::MemoryInvalidInteraction),
             MyInstruction::Read(addr) => {
-                if !machine.memory_allocated.contain(*addr) {
-                    panic!("{}", Error::MemoryAccessDeinied);
-                } else {
-                    machine.read(*addr).expect("Unable to read to memory");
-                }
+                machine.check_bounds(*addr)?;
+                machine.read(*addr)?;
             }
             MyInstruction::Write(addr, val) => {
-                if !machine.memory_allocated.contain(*addr) {
-                    panic!("{}", Error::MemoryAccessDeinied);
-                } else {
-                    machine
-                        .write(*addr, *val)
-                        .expect("Unable to write to memory");
-                }
+                machine.check_bounds(*addr)?;
+                machine.write(*addr, *val)?;
             }
             MyInstruction::Push(value) => {
-                machine.push(*value).expect("Unable to push value to stack");
+                machine.push(*value)?;
             }
             MyInstruction::Pop(_) => {
-                machine.pop().expect("Unable to pop value from stack");
+                machine.pop()?;
             }
             MyInstruction::Mov(reg1, reg2) => {
-                match machine.get(*reg2).expect("Unable to access register 1") {
+                // Mov value from register 2 to register 1
+                match machine.get(*reg2)? {
                     CellInteraction::SingleCell(_, _, value) => {
-                        machine.set(*reg1, value).expect("Unable to set register 2");
+                        machine.set(*reg1, value)?;
                     }
-                    _ => panic!("Register unable to be two cells"),
+                    _ => return Err(Error::RegisterUnableToRead),
                 }
-                // Mov value from register 2 to register 1
             }
             MyInstruction::Swap(reg) => {
-                match machine.pop().expect("Unable to pop value from stack") {
+                match machine.pop()? {
                     (_, CellInteraction::SingleCell(_op, _addr, value)) => {
-                        machine
-                            .push(value)
-                            .expect("Unable to push register's value to stack");
-                        machine.set(*reg, value).expect("Unable to set register");
+                        machine.push(value)?;
+                        machine.set(*reg, value)?;
                     }
-                    _ => panic!("Stack unable to be two cells"),
+                    _ => return Err(Error::MemoryInvalidInteraction),
                 };
             }
             MyInstruction::Load(reg, addr) => {
-                match machine.read(*addr).expect("Unable to read memory") {
+                machine.check_bounds(*addr)?;
+                match machine.read(*addr)? {
                     CellInteraction::SingleCell(_, _, value) => {
-                        machine.set(*reg, value).expect("Unable to set register");
+                        machine.set(*reg, value)?;
                     }
                     CellInteraction::DoubleCell(_, _, cvalue, _, _, _, _) => {
-                        machine.set(*reg, cvalue).expect("Unable to set register");
+                        machine.set(*reg, cvalue)?;
                     }
                 };
             }
             MyInstruction::Save(address, reg) => {
-                match machine.get(*reg).expect("Unable to access register") {
+                machine.check_bounds(*address)?;
+                match machine.get(*reg)? {
                     CellInteraction::SingleCell(_, _, value) => {
-                        machine
-                            .write(*address, value)
-                            .expect("Unable to write to memory");
+                        machine.write(*address, value)?;
                     }
-                    _ => panic!("Register unable to be two cells"),
+                    _ => return Err(Error::RegisterUnableToRead),
                 }
             }
-            MyInstruction::Add(reg1, reg2) => {
-                match machine.get(*reg1).expect("Unable to access register 1") {
-                    CellInteraction::SingleCell(_, _, value1) => {
-                        match machine.get(*reg2).expect("Unable to access register 2") {
-                            CellInteraction::SingleCell(_, _, value2) => {
-                                machine
-                                    .set(*reg1, value1 + value2)
-                                    .expect("Unable to set register 1");
-                            }
-                            _ => panic!("Register unable to be two cells"),
-                        }
-                    }
-                    _ => panic!("Register unable to be two cells"),
+            MyInstruction::Add(reg1, reg2) => match (machine.get(*reg1)?, machine.get(*reg2)?) {
+                (
+                    CellInteraction::SingleCell(_, _, value1),
+                    CellInteraction::SingleCell(_, _, value2),
+                ) => {
+                    let sum = value1.checked_add(value2).ok_or(Error::ValueOutOfRange)?;
+                    machine.set(*reg1, sum)?;
                 }
-            }
+                _ => return Err(Error::RegisterUnableToRead),
+            },
         }
+        Ok(())
     }
 }
 
@@ -223,7 +213,7 @@ where
         Self {
             // Memory section
             memory: RBTree::new(),
-            memory_allocated: config.memory,
+            state: MachineState::new(config),
             word_size: config.word_size,
             time_log: 0,
 
@@ -249,8 +239,8 @@ where
     pub fn show_sections_maps(&self) {
         println!(
             "Memory section map: from {} to {}",
-            self.memory_allocated.low(),
-            self.memory_allocated.high()
+            self.state.memory_section().low(),
+            self.state.memory_section().high()
         );
         println!(
             "Register section map: from {} to {}",
@@ -299,16 +289,32 @@ where
         self.execution_trace.keys().copied().collect()
     }
 
-    fn exec(&mut self, instruction: &Self::Instruction) {
-        instruction.exec(self);
+    fn trace_iter(&self) -> Box<dyn Iterator<Item = &'_ Self::TraceRecord> + '_> {
+        Box::new(self.execution_trace.keys())
+    }
+
+    fn truncate_trace(&mut self, time_log: u64) {
+        while let Some((record, _)) = self.execution_trace.get_last() {
+            if record.time_log() < time_log {
+                break;
+            }
+            self.execution_trace.pop_last();
+        }
+    }
+
+    fn exec(&mut self, instruction: &Self::Instruction) -> Result<(), Error> {
+        instruction.exec(self)
     }
 
     fn base_address(&self) -> K {
-        self.memory_allocated.low()
+        self.state.memory_section().low()
     }
 
     fn get_memory_address(&self) -> (K, K) {
-        (self.memory_allocated.low(), self.memory_allocated.high())
+        (
+            self.state.memory_section().low(),
+            self.state.memory_section().high(),
+        )
     }
 
     fn get_stack_depth(&self) -> u64 {
@@ -322,7 +328,7 @@ where
 
 impl_register_machine!(StateMachine);
 impl_stack_machine!(StateMachine);
-impl_state_machine!(StateMachine);
+impl_state_machine!(StateMachine, state);
 
 fn main() {
     // Define the desired machine configuration
@@ -376,7 +382,9 @@ fn main() {
     let mut trace_record = vec![];
     // Execute the program
     for instruction in program1 {
-        machine.exec(&instruction);
+        machine
+            .exec(&instruction)
+            .expect("Unable to execute the instruction");
     }
     // Print the trace record (prettified), sorted by ascending time by default
     for x in machine.trace().into_iter() {
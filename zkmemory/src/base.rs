@@ -1,7 +1,19 @@
+extern crate alloc;
+use crate::error::Error;
+use alloc::vec::Vec;
+#[cfg(feature = "borsh")]
+use borsh::{io, BorshDeserialize, BorshSerialize};
 use core::fmt::{Debug, Display};
+use core::iter::FusedIterator;
 use core::ops::{Add, Div, Mul, Rem, Sub};
 use core::usize;
 use ethnum::U256;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// 512-bit integer backing [B512]
+mod u512;
+pub use u512::U512;
 
 /// Base trait for memory address and value
 pub trait Base<const S: usize, T = Self>:
@@ -19,6 +31,10 @@ pub trait Base<const S: usize, T = Self>:
     + Into<usize>
     + From<u64>
     + Into<u64>
+    + TryFrom<u128>
+    + TryInto<u128, Error = Error>
+    + TryFrom<i64, Error = Error>
+    + TryInto<i64, Error = Error>
     + From<[u8; S]>
     + Into<[u8; S]>
     + Add<T, Output = T>
@@ -26,6 +42,11 @@ pub trait Base<const S: usize, T = Self>:
     + Sub<T, Output = T>
     + Rem<T, Output = T>
     + Div<T, Output = T>
+    + for<'a> Add<&'a T, Output = T>
+    + for<'a> Mul<&'a T, Output = T>
+    + for<'a> Sub<&'a T, Output = T>
+    + for<'a> Rem<&'a T, Output = T>
+    + for<'a> Div<&'a T, Output = T>
 {
     /// The max value of the cell
     const MAX: Self;
@@ -39,10 +60,87 @@ pub trait Base<const S: usize, T = Self>:
     fn is_zero(&self) -> bool;
     /// Get the zero value
     fn zero() -> Self;
-    /// To big endian bytes
+    /// To big endian bytes, values wider than 256 bits keep their 32 least significant bytes
     fn fixed_be_bytes(&self) -> [u8; 32];
-    /// To little endian bytes
+    /// To little endian bytes, values wider than 256 bits keep their 32 least significant bytes
     fn fixed_le_bytes(&self) -> [u8; 32];
+    /// Number of leading zero bits
+    fn leading_zeros(&self) -> u32;
+    /// Number of bits needed to represent the value, zero has bit length 0
+    fn bit_len(&self) -> u32 {
+        (S * 8) as u32 - self.leading_zeros()
+    }
+    /// Floor of the base 2 logarithm, return [None] for zero
+    fn checked_log2(&self) -> Option<u32> {
+        match self.bit_len() {
+            0 => None,
+            bit_len => Some(bit_len - 1),
+        }
+    }
+    /// Parse a size from a configuration string. Supported notations are decimal (`4096`),
+    /// hexadecimal (`0x1000`), binary suffixes (`4KiB`, `16MiB`, `4GiB`, `1TiB`) and powers of two (`2^32`)
+    fn parse_size(input: &str) -> Result<Self, Error> {
+        let input = input.trim();
+        if let Some(exponent) = input.strip_prefix("2^") {
+            let exponent: u32 = exponent
+                .trim()
+                .parse()
+                .map_err(|_| Error::InvalidSizeFormat)?;
+            return (0..exponent).try_fold(Self::from(1u64), |value, _| {
+                value
+                    .checked_mul(Self::from(2u64))
+                    .ok_or(Error::ValueOutOfRange)
+            });
+        }
+        let (digits, multiplier) = [("KiB", 10u32), ("MiB", 20), ("GiB", 30), ("TiB", 40)]
+            .iter()
+            .find_map(|(suffix, shift)| {
+                input
+                    .strip_suffix(suffix)
+                    .map(|digits| (digits.trim(), 1u64 << shift))
+            })
+            .unwrap_or((input, 1));
+        let (digits, radix) = match digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            Some(digits) => (digits, 16),
+            None => (digits, 10),
+        };
+        if digits.is_empty() {
+            return Err(Error::InvalidSizeFormat);
+        }
+        let value = digits.chars().try_fold(Self::zero(), |value, digit| {
+            let digit = digit.to_digit(radix).ok_or(Error::InvalidSizeFormat)?;
+            value
+                .checked_mul(Self::from(radix as u64))
+                .and_then(|value| value.checked_add(Self::from(digit as u64)))
+                .ok_or(Error::ValueOutOfRange)
+        })?;
+        value
+            .checked_mul(Self::try_from(multiplier as u128).map_err(|_| Error::ValueOutOfRange)?)
+            .ok_or(Error::ValueOutOfRange)
+    }
+    /// Checked addition, return [None] on overflow
+    fn checked_add(&self, rhs: Self) -> Option<Self>;
+    /// Checked subtraction, return [None] on underflow
+    fn checked_sub(&self, rhs: Self) -> Option<Self>;
+    /// Checked multiplication, return [None] on overflow
+    fn checked_mul(&self, rhs: Self) -> Option<Self>;
+    /// Compute `self * num / den` rounded down with a double width intermediate product,
+    /// return [None] if `den` is zero or the result does not fit
+    fn mul_div(&self, num: Self, den: Self) -> Option<Self>;
+    /// Percentage of the value in `total` rounded down, return [None] if `total` is zero
+    fn percent_of(&self, total: Self) -> Option<Self> {
+        self.mul_div(Self::from(100u64), total)
+    }
+    /// Quotient and remainder of the division in one call, panic if `rhs` is zero
+    fn div_rem(&self, rhs: Self) -> (Self, Self);
+    /// Quotient of the division rounded up, panic if `rhs` is zero
+    fn div_ceil(&self, rhs: Self) -> Self;
+    /// Smallest multiple of `rhs` that is greater than or equal to the value,
+    /// return [None] if it exceeds [Base::MAX], panic if `rhs` is zero
+    fn next_multiple_of(&self, rhs: Self) -> Option<Self>;
 }
 
 /// Convert from/to [`core::usize`]
@@ -63,6 +161,31 @@ impl<T: Display> Display for Uint<T> {
     }
 }
 
+// A value is written as the `0x` prefixed big endian hex string of its full width, the
+// integers of the text formats such as TOML do not reach 64 bits
+#[cfg(feature = "serde")]
+fn serialize_hex<Z: serde::Serializer, const S: usize>(
+    bytes: [u8; S],
+    serializer: Z,
+) -> Result<Z::Ok, Z::Error> {
+    serializer.serialize_str(&alloc::format!("0x{}", hex::encode(bytes)))
+}
+
+// Read a value written by [serialize_hex], the string must hold exactly `S` bytes
+#[cfg(feature = "serde")]
+fn deserialize_hex<'de, D: serde::Deserializer<'de>, const S: usize>(
+    deserializer: D,
+) -> Result<[u8; S], D::Error> {
+    use serde::de::Error as _;
+    let text = <alloc::string::String as serde::Deserialize>::deserialize(deserializer)?;
+    let digits = text
+        .strip_prefix("0x")
+        .ok_or_else(|| D::Error::custom("missing 0x prefix"))?;
+    let mut bytes = [0u8; S];
+    hex::decode_to_slice(digits, &mut bytes).map_err(D::Error::custom)?;
+    Ok(bytes)
+}
+
 impl<T: Div<Output = T>> Div for Uint<T> {
     type Output = Self;
 
@@ -103,7 +226,320 @@ impl<T: Mul<Output = T>> Mul for Uint<T> {
     }
 }
 
+// Full 512-bit product of two 256-bit values, returned as (high, low)
+fn mul_wide_u256(a: U256, b: U256) -> (U256, U256) {
+    let (a_hi, a_lo) = a.into_words();
+    let (b_hi, b_lo) = b.into_words();
+    let low = U256::from(a_lo) * U256::from(b_lo);
+    let high = U256::from(a_hi) * U256::from(b_hi);
+    let (middle, middle_carry) =
+        (U256::from(a_lo) * U256::from(b_hi)).overflowing_add(U256::from(a_hi) * U256::from(b_lo));
+    let (low, low_carry) = low.overflowing_add(middle << 128);
+    let high = high
+        + (middle >> 128)
+        + U256::from(low_carry as u8)
+        + if middle_carry {
+            U256::ONE << 128
+        } else {
+            U256::ZERO
+        };
+    (high, low)
+}
+
+// Compute a * b / den rounded down with a 512-bit intermediate product
+fn mul_div_u256(a: U256, b: U256, den: U256) -> Option<U256> {
+    if den == U256::ZERO {
+        return None;
+    }
+    let (high, low) = mul_wide_u256(a, b);
+    // The quotient does not fit in 256 bits
+    if high >= den {
+        return None;
+    }
+    // Shift-subtract long division of (high, low) by den, the remainder stays below den
+    let mut remainder = high;
+    let mut quotient = U256::ZERO;
+    for bit in (0..256).rev() {
+        let carry = remainder >> 255u32 == U256::ONE;
+        remainder = (remainder << 1u32) | ((low >> bit as u32) & U256::ONE);
+        if carry || remainder >= den {
+            remainder = remainder.wrapping_sub(den);
+            quotient |= U256::ONE << bit as u32;
+        }
+    }
+    Some(quotient)
+}
+
+/// Implement an arithmetic operator of [Uint] for every combination of value and reference operands
+macro_rules! ref_operator {
+    ($operator:ident, $method:ident) => {
+        impl<'a, T: Copy + $operator<Output = T>> $operator<&'a Uint<T>> for Uint<T> {
+            type Output = Uint<T>;
+
+            fn $method(self, rhs: &'a Uint<T>) -> Self::Output {
+                Uint(self.0.$method(rhs.0))
+            }
+        }
+
+        impl<'a, T: Copy + $operator<Output = T>> $operator<Uint<T>> for &'a Uint<T> {
+            type Output = Uint<T>;
+
+            fn $method(self, rhs: Uint<T>) -> Self::Output {
+                Uint(self.0.$method(rhs.0))
+            }
+        }
+
+        impl<'a, 'b, T: Copy + $operator<Output = T>> $operator<&'b Uint<T>> for &'a Uint<T> {
+            type Output = Uint<T>;
+
+            fn $method(self, rhs: &'b Uint<T>) -> Self::Output {
+                Uint(self.0.$method(rhs.0))
+            }
+        }
+    };
+}
+
+ref_operator!(Add, add);
+ref_operator!(Sub, sub);
+ref_operator!(Mul, mul);
+ref_operator!(Div, div);
+ref_operator!(Rem, rem);
+
+/// Checked arithmetic and division helpers of [Base], written to never overflow near [Base::MAX]
+macro_rules! checked_helpers {
+    () => {
+        fn checked_add(&self, rhs: Self) -> Option<Self> {
+            self.0.checked_add(rhs.0).map(Self)
+        }
+
+        fn checked_sub(&self, rhs: Self) -> Option<Self> {
+            self.0.checked_sub(rhs.0).map(Self)
+        }
+
+        fn checked_mul(&self, rhs: Self) -> Option<Self> {
+            self.0.checked_mul(rhs.0).map(Self)
+        }
+
+        fn div_rem(&self, rhs: Self) -> (Self, Self) {
+            (*self / rhs, *self % rhs)
+        }
+
+        fn div_ceil(&self, rhs: Self) -> Self {
+            // The quotient is at most MAX / rhs, adding one can not overflow when rhs > 1
+            match self.div_rem(rhs) {
+                (quotient, remainder) if remainder.is_zero() => quotient,
+                (quotient, _) => quotient + Self::from(1u64),
+            }
+        }
+
+        fn next_multiple_of(&self, rhs: Self) -> Option<Self> {
+            let remainder = *self % rhs;
+            if remainder.is_zero() {
+                return Some(*self);
+            }
+            let gap = rhs - remainder;
+            if *self > Self::MAX - gap {
+                None
+            } else {
+                Some(*self + gap)
+            }
+        }
+    };
+}
+
 macro_rules! new_base {
+    (U512, $byte_size: expr) => {
+        impl Base<$byte_size> for Uint<U512> {
+            const MAX: Self = Self(U512::MAX);
+
+            const MIN: Self = Self(U512::ZERO);
+
+            const WORD_SIZE: Self = Self(U512::from_words(U256::ZERO, U256::new($byte_size)));
+
+            fn is_zero(&self) -> bool {
+                self.0 == U512::ZERO
+            }
+
+            fn zero() -> Self {
+                Self(U512::ZERO)
+            }
+
+            // The fixed width encodings keep the 32 least significant bytes
+            fn fixed_be_bytes(&self) -> [u8; 32] {
+                self.0.into_words().1.to_be_bytes()
+            }
+
+            fn fixed_le_bytes(&self) -> [u8; 32] {
+                self.0.into_words().1.to_le_bytes()
+            }
+
+            fn leading_zeros(&self) -> u32 {
+                self.0.leading_zeros()
+            }
+
+            checked_helpers!();
+
+            fn mul_div(&self, num: Self, den: Self) -> Option<Self> {
+                u512::mul_div_u512(self.0, num.0, den.0).map(Self)
+            }
+        }
+
+        impl From<i32> for Uint<U512> {
+            fn from(value: i32) -> Self {
+                Self(U512::from(U256::new(value as u128)))
+            }
+        }
+
+        impl From<usize> for Uint<U512> {
+            fn from(value: usize) -> Self {
+                Self(U512::from(U256::new(value as u128)))
+            }
+        }
+
+        impl From<u64> for Uint<U512> {
+            fn from(value: u64) -> Self {
+                Self(U512::from(U256::new(value as u128)))
+            }
+        }
+
+        impl From<u128> for Uint<U512> {
+            fn from(value: u128) -> Self {
+                Self(U512::from(U256::new(value)))
+            }
+        }
+
+        impl From<Uint<U256>> for Uint<U512> {
+            fn from(value: Uint<U256>) -> Self {
+                Self(U512::from(value.0))
+            }
+        }
+
+        impl Uint<U512> {
+            /// Create a new value in a `const` context
+            pub const fn new(value: u64) -> Self {
+                Self(U512::from_words(U256::ZERO, U256::new(value as u128)))
+            }
+
+            /// Create a new value from its most and least significant 256-bit halves
+            pub const fn from_halves(high: Uint<U256>, low: Uint<U256>) -> Self {
+                Self(U512::from_words(high.0, low.0))
+            }
+
+            /// Split the value into its most and least significant 256-bit halves
+            pub const fn into_halves(self) -> (Uint<U256>, Uint<U256>) {
+                let (high, low) = self.0.into_words();
+                (Uint(high), Uint(low))
+            }
+        }
+
+        #[cfg(feature = "borsh")]
+        impl BorshSerialize for Uint<U512> {
+            fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+                writer.write_all(&self.0.to_le_bytes())
+            }
+        }
+
+        #[cfg(feature = "borsh")]
+        impl BorshDeserialize for Uint<U512> {
+            fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+                let bytes = <[u8; $byte_size]>::deserialize_reader(reader)?;
+                Ok(Self(U512::from_le_bytes(bytes)))
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for Uint<U512> {
+            fn serialize<Z: serde::Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+                serialize_hex(<[u8; $byte_size]>::from(*self), serializer)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for Uint<U512> {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize_hex::<D, $byte_size>(deserializer).map(Self::from)
+            }
+        }
+
+        #[cfg(feature = "zeroize")]
+        impl Zeroize for Uint<U512> {
+            fn zeroize(&mut self) {
+                let (mut high, mut low) = self.0.into_words();
+                high.0.zeroize();
+                low.0.zeroize();
+                self.0 = U512::from_words(high, low);
+            }
+        }
+
+        impl TryFrom<i64> for Uint<U512> {
+            type Error = Error;
+
+            fn try_from(value: i64) -> Result<Self, Self::Error> {
+                if value < 0 {
+                    Err(Error::ValueOutOfRange)
+                } else {
+                    Ok(Self::from(value as u64))
+                }
+            }
+        }
+
+        impl TryFrom<Uint<U512>> for Uint<U256> {
+            type Error = Error;
+
+            fn try_from(value: Uint<U512>) -> Result<Self, Self::Error> {
+                match value.into_halves() {
+                    (high, low) if high.is_zero() => Ok(low),
+                    _ => Err(Error::ValueOutOfRange),
+                }
+            }
+        }
+
+        impl TryFrom<Uint<U512>> for u128 {
+            type Error = Error;
+
+            fn try_from(value: Uint<U512>) -> Result<Self, Self::Error> {
+                u128::try_from(Uint::<U256>::try_from(value)?)
+            }
+        }
+
+        impl TryFrom<Uint<U512>> for i64 {
+            type Error = Error;
+
+            fn try_from(value: Uint<U512>) -> Result<Self, Self::Error> {
+                i64::try_from(u128::try_from(value)?).map_err(|_| Error::ValueOutOfRange)
+            }
+        }
+
+        impl From<Uint<U512>> for i32 {
+            fn from(value: Uint<U512>) -> Self {
+                value.0.into_words().1.as_i32()
+            }
+        }
+
+        impl From<Uint<U512>> for usize {
+            fn from(value: Uint<U512>) -> Self {
+                value.0.into_words().1.as_usize()
+            }
+        }
+
+        impl From<Uint<U512>> for u64 {
+            fn from(value: Uint<U512>) -> Self {
+                value.0.into_words().1.as_u64()
+            }
+        }
+
+        impl From<Uint<U512>> for [u8; $byte_size] {
+            fn from(value: Uint<U512>) -> Self {
+                value.0.to_be_bytes()
+            }
+        }
+
+        impl From<[u8; $byte_size]> for Uint<U512> {
+            fn from(value: [u8; $byte_size]) -> Self {
+                Self(U512::from_be_bytes(value))
+            }
+        }
+    };
     (U256, $byte_size: expr) => {
         impl Base<$byte_size> for Uint<U256> {
             const MAX: Self = Self(U256::MAX);
@@ -127,6 +563,16 @@ macro_rules! new_base {
             fn fixed_le_bytes(&self) -> [u8; 32] {
                 self.0.to_le_bytes()
             }
+
+            fn leading_zeros(&self) -> u32 {
+                self.0.leading_zeros()
+            }
+
+            checked_helpers!();
+
+            fn mul_div(&self, num: Self, den: Self) -> Option<Self> {
+                mul_div_u256(self.0, num.0, den.0).map(Self)
+            }
         }
 
         impl From<i32> for Uint<U256> {
@@ -147,6 +593,95 @@ macro_rules! new_base {
             }
         }
 
+        impl Uint<U256> {
+            /// Create a new value in a `const` context
+            pub const fn new(value: u64) -> Self {
+                Self(U256::new(value as u128))
+            }
+
+            /// Create a new value from its 64-bit limbs in a `const` context,
+            /// the least significant limb comes first
+            pub const fn from_limbs(limbs: [u64; 4]) -> Self {
+                Self(U256::from_words(
+                    ((limbs[3] as u128) << 64) | limbs[2] as u128,
+                    ((limbs[1] as u128) << 64) | limbs[0] as u128,
+                ))
+            }
+        }
+
+        #[cfg(feature = "borsh")]
+        impl BorshSerialize for Uint<U256> {
+            fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+                writer.write_all(&self.0.to_le_bytes())
+            }
+        }
+
+        #[cfg(feature = "borsh")]
+        impl BorshDeserialize for Uint<U256> {
+            fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+                let bytes = <[u8; $byte_size]>::deserialize_reader(reader)?;
+                Ok(Self(U256::from_le_bytes(bytes)))
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for Uint<U256> {
+            fn serialize<Z: serde::Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+                serialize_hex(<[u8; $byte_size]>::from(*self), serializer)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for Uint<U256> {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize_hex::<D, $byte_size>(deserializer).map(Self::from)
+            }
+        }
+
+        #[cfg(feature = "zeroize")]
+        impl Zeroize for Uint<U256> {
+            fn zeroize(&mut self) {
+                self.0 .0.zeroize();
+            }
+        }
+
+        impl From<u128> for Uint<U256> {
+            fn from(value: u128) -> Self {
+                Self(U256::new(value))
+            }
+        }
+
+        impl TryFrom<i64> for Uint<U256> {
+            type Error = Error;
+
+            fn try_from(value: i64) -> Result<Self, Self::Error> {
+                if value < 0 {
+                    Err(Error::ValueOutOfRange)
+                } else {
+                    Ok(Self(U256::new(value as u128)))
+                }
+            }
+        }
+
+        impl TryFrom<Uint<U256>> for u128 {
+            type Error = Error;
+
+            fn try_from(value: Uint<U256>) -> Result<Self, Self::Error> {
+                match value.0.into_words() {
+                    (0, low) => Ok(low),
+                    _ => Err(Error::ValueOutOfRange),
+                }
+            }
+        }
+
+        impl TryFrom<Uint<U256>> for i64 {
+            type Error = Error;
+
+            fn try_from(value: Uint<U256>) -> Result<Self, Self::Error> {
+                i64::try_from(u128::try_from(value)?).map_err(|_| Error::ValueOutOfRange)
+            }
+        }
+
         impl From<Uint<U256>> for i32 {
             fn from(value: Uint<U256>) -> Self {
                 value.0.as_i32()
@@ -204,6 +739,21 @@ macro_rules! new_base {
                 buf[..$byte_size].copy_from_slice(&self.0.to_le_bytes());
                 buf
             }
+
+            fn leading_zeros(&self) -> u32 {
+                self.0.leading_zeros()
+            }
+
+            checked_helpers!();
+
+            fn mul_div(&self, num: Self, den: Self) -> Option<Self> {
+                // Every primitive product fits in 256 bits
+                if den.0 == 0 {
+                    return None;
+                }
+                let quotient = U256::from(self.0) * U256::from(num.0) / U256::from(den.0);
+                $primitive::try_from(quotient).ok().map(Self)
+            }
         }
 
         impl From<i32> for Uint<$primitive> {
@@ -224,6 +774,78 @@ macro_rules! new_base {
             }
         }
 
+        impl Uint<$primitive> {
+            /// Create a new value in a `const` context, panic if the value does not fit
+            pub const fn new(value: u64) -> Self {
+                assert!(
+                    value as u128 <= $primitive::MAX as u128,
+                    "Value out of range"
+                );
+                Self(value as $primitive)
+            }
+        }
+
+        #[cfg(feature = "borsh")]
+        impl BorshSerialize for Uint<$primitive> {
+            fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+                self.0.serialize(writer)
+            }
+        }
+
+        #[cfg(feature = "borsh")]
+        impl BorshDeserialize for Uint<$primitive> {
+            fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+                $primitive::deserialize_reader(reader).map(Self)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for Uint<$primitive> {
+            fn serialize<Z: serde::Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+                serialize_hex(<[u8; $byte_size]>::from(*self), serializer)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for Uint<$primitive> {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize_hex::<D, $byte_size>(deserializer).map(Self::from)
+            }
+        }
+
+        #[cfg(feature = "zeroize")]
+        impl Zeroize for Uint<$primitive> {
+            fn zeroize(&mut self) {
+                self.0.zeroize();
+            }
+        }
+
+        impl TryFrom<i64> for Uint<$primitive> {
+            type Error = Error;
+
+            fn try_from(value: i64) -> Result<Self, Self::Error> {
+                $primitive::try_from(value)
+                    .map(Self)
+                    .map_err(|_| Error::ValueOutOfRange)
+            }
+        }
+
+        impl TryFrom<Uint<$primitive>> for u128 {
+            type Error = Error;
+
+            fn try_from(value: Uint<$primitive>) -> Result<Self, Self::Error> {
+                u128::try_from(value.0).map_err(|_| Error::ValueOutOfRange)
+            }
+        }
+
+        impl TryFrom<Uint<$primitive>> for i64 {
+            type Error = Error;
+
+            fn try_from(value: Uint<$primitive>) -> Result<Self, Self::Error> {
+                i64::try_from(value.0).map_err(|_| Error::ValueOutOfRange)
+            }
+        }
+
         impl From<Uint<$primitive>> for i32 {
             fn from(value: Uint<$primitive>) -> Self {
                 value.0 as i32
@@ -256,12 +878,42 @@ macro_rules! new_base {
     };
 }
 
+/// Conversion from [u128](core::u128), lossless for the wide types and checked for the narrow ones
+macro_rules! u128_conversion {
+    (lossless, $primitive:ident) => {
+        impl From<u128> for Uint<$primitive> {
+            fn from(value: u128) -> Self {
+                Self(value)
+            }
+        }
+    };
+    (checked, $primitive:ident) => {
+        impl TryFrom<u128> for Uint<$primitive> {
+            type Error = Error;
+
+            fn try_from(value: u128) -> Result<Self, Self::Error> {
+                $primitive::try_from(value)
+                    .map(Self)
+                    .map_err(|_| Error::ValueOutOfRange)
+            }
+        }
+    };
+}
+
+new_base!(U512, 64);
 new_base!(U256, 32);
 new_base!(u128, 16);
 new_base!(u64, 8);
 new_base!(u32, 4);
 new_base!(u16, 2);
 
+u128_conversion!(lossless, u128);
+u128_conversion!(checked, u64);
+u128_conversion!(checked, u32);
+u128_conversion!(checked, u16);
+
+/// Uint512 is a wrapper of [U512] to implement [Base]
+pub type B512 = Uint<U512>;
 /// Uint256 is a wrapper of [U256] to implement [Base]
 pub type B256 = Uint<U256>;
 /// Uint128 is a wrapper of [u128](core::u128) to implement [Base]
@@ -273,9 +925,98 @@ pub type B32 = Uint<u32>;
 /// Uint16 is a wrapper of [u16](core::u16) to implement [Base]
 pub type B16 = Uint<u16>;
 
+/// Split a byte slice into words of `S` bytes, yielding the byte offset of every word.
+/// The last chunk is zero-padded on the right when the length is not a multiple of `S`
+pub fn chunks_of_words<B: Base<S>, const S: usize>(
+    bytes: &[u8],
+) -> impl Iterator<Item = (usize, B)> + '_ {
+    bytes.chunks(S).enumerate().map(|(index, chunk)| {
+        let mut word = [0u8; S];
+        word[..chunk.len()].copy_from_slice(chunk);
+        (index * S, B::from(word))
+    })
+}
+
+/// Iterator over cell addresses, stepping by [Base::WORD_SIZE].
+/// The iteration terminates instead of wrapping around when the next address overflows
+#[derive(Debug, Clone)]
+pub struct AddressIterator<B: Base<S>, const S: usize> {
+    // Next address to yield
+    next: Option<B>,
+    // Last address allowed, inclusive
+    end: Option<B>,
+    // Number of addresses left to yield
+    remaining: Option<usize>,
+}
+
+impl<B: Base<S>, const S: usize> AddressIterator<B, S> {
+    /// Iterate over `cells` consecutive cells starting at `start`
+    pub fn new(start: B, cells: usize) -> Self {
+        Self {
+            next: Some(start),
+            end: None,
+            remaining: Some(cells),
+        }
+    }
+
+    /// Iterate over the cells in `start..end`
+    pub fn range(start: B, end: B) -> Self {
+        if end.is_zero() {
+            return Self::new(start, 0);
+        }
+        Self::range_inclusive(start, end - B::from(1u64))
+    }
+
+    /// Iterate over the cells in `start..=end`
+    pub fn range_inclusive(start: B, end: B) -> Self {
+        Self {
+            next: Some(start),
+            end: Some(end),
+            remaining: None,
+        }
+    }
+}
+
+impl<B: Base<S>, const S: usize> Iterator for AddressIterator<B, S> {
+    type Item = B;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        let current = self.next?;
+        if self.end.map(|end| current > end).unwrap_or(false) {
+            self.next = None;
+            return None;
+        }
+        self.next = if current > B::MAX - B::WORD_SIZE {
+            None
+        } else {
+            Some(current + B::WORD_SIZE)
+        };
+        self.remaining = self.remaining.map(|remaining| remaining - 1);
+        Some(current)
+    }
+}
+
+impl<B: Base<S>, const S: usize> FusedIterator for AddressIterator<B, S> {}
+
+/// Concatenate the bytes of the words, inverse of [chunks_of_words] up to the tail padding
+pub fn words_to_bytes<B: Base<S>, const S: usize>(words: &[B]) -> Vec<u8> {
+    words
+        .iter()
+        .flat_map(|word| Into::<[u8; S]>::into(*word))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::base::{Base, B128, B256, B32, B64};
+    use crate::base::{
+        chunks_of_words, words_to_bytes, AddressIterator, Base, B128, B16, B256, B32, B512, B64,
+    };
+    use crate::error::Error;
+    extern crate alloc;
+    use alloc::{format, vec::Vec};
 
     #[test]
     fn base_struct_test() {
@@ -419,4 +1160,538 @@ mod tests {
         assert_eq!(num.fixed_be_bytes(), chunk_be);
         assert_eq!(num.fixed_le_bytes(), chunk_le);
     }
+
+    #[test]
+    fn base_bit_length_test() {
+        // Zero has no bit and no logarithm
+        assert_eq!(B256::zero().bit_len(), 0);
+        assert_eq!(B256::zero().leading_zeros(), 256);
+        assert_eq!(B256::zero().checked_log2(), None);
+        assert_eq!(B16::zero().bit_len(), 0);
+        assert_eq!(B16::zero().checked_log2(), None);
+
+        // Powers of two and their neighbours
+        assert_eq!(B256::from(1).bit_len(), 1);
+        assert_eq!(B256::from(1).checked_log2(), Some(0));
+        assert_eq!(B64::from(1024).bit_len(), 11);
+        assert_eq!(B64::from(1023).checked_log2(), Some(9));
+        assert_eq!(B32::from(1025).checked_log2(), Some(10));
+        assert_eq!(B128::from(u64::MAX).bit_len(), 64);
+        assert_eq!(B128::from(u64::MAX).leading_zeros(), 64);
+
+        // Maximum values use all the bits
+        assert_eq!(B256::MAX.bit_len(), 256);
+        assert_eq!(B256::MAX.checked_log2(), Some(255));
+        assert_eq!(B128::MAX.bit_len(), 128);
+        assert_eq!(B64::MAX.bit_len(), 64);
+        assert_eq!(B32::MAX.bit_len(), 32);
+        assert_eq!(B16::MAX.bit_len(), 16);
+        assert_eq!(B16::MAX.leading_zeros(), 0);
+
+        // Values above 64 bits
+        let mut bytes = [0u8; 32];
+        bytes[15] = 1;
+        assert_eq!(B256::from(bytes).bit_len(), 129);
+        assert_eq!(B256::from(bytes).checked_log2(), Some(128));
+    }
+
+    #[test]
+    fn base_wide_conversion_test() {
+        // u128 into the wide types is lossless
+        assert_eq!(u128::try_from(B256::from(u128::MAX)), Ok(u128::MAX));
+        assert_eq!(u128::try_from(B128::from(u128::MAX)), Ok(u128::MAX));
+        assert_eq!(u128::try_from(B256::MAX), Err(Error::ValueOutOfRange));
+
+        // u128 into the narrow types is checked
+        assert_eq!(B64::try_from(u64::MAX as u128), Ok(B64::MAX));
+        assert_eq!(
+            B64::try_from(u64::MAX as u128 + 1),
+            Err(Error::ValueOutOfRange)
+        );
+        assert_eq!(B32::try_from(1u128 << 32), Err(Error::ValueOutOfRange));
+        assert_eq!(B16::try_from(65535u128), Ok(B16::MAX));
+
+        // Negative i64 are rejected
+        assert_eq!(B256::try_from(-1i64), Err(Error::ValueOutOfRange));
+        assert_eq!(B64::try_from(-1i64), Err(Error::ValueOutOfRange));
+        assert_eq!(B256::try_from(i64::MAX), Ok(B256::from(i64::MAX as u64)));
+        assert_eq!(B16::try_from(70000i64), Err(Error::ValueOutOfRange));
+        assert_eq!(B16::try_from(7000i64), Ok(B16::from(7000)));
+
+        // Back to i64 and u128
+        assert_eq!(i64::try_from(B64::MAX), Err(Error::ValueOutOfRange));
+        assert_eq!(i64::try_from(B256::from(42)), Ok(42));
+        assert_eq!(
+            i64::try_from(B128::from(u128::MAX)),
+            Err(Error::ValueOutOfRange)
+        );
+        assert_eq!(u128::try_from(B16::MAX), Ok(65535));
+    }
+
+    // Generic code relies on the conversions through the Base trait bounds
+    fn roundtrip_i64<const S: usize, T: Base<S>>(value: i64) -> Result<i64, Error> {
+        T::try_from(value)?.try_into()
+    }
+
+    #[test]
+    fn base_generic_conversion_test() {
+        assert_eq!(roundtrip_i64::<32, B256>(1234), Ok(1234));
+        assert_eq!(roundtrip_i64::<2, B16>(1234), Ok(1234));
+        assert_eq!(roundtrip_i64::<2, B16>(-1), Err(Error::ValueOutOfRange));
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn base_zeroize_test() {
+        use zeroize::Zeroize;
+        let mut secret = B256::from([0xa5u8; 32]);
+        secret.zeroize();
+        let bytes: [u8; 32] = secret.into();
+        assert_eq!(bytes, [0u8; 32]);
+
+        let mut secret = B64::MAX;
+        secret.zeroize();
+        assert!(secret.is_zero());
+
+        let mut secret = B512::MAX;
+        secret.zeroize();
+        assert!(secret.is_zero());
+    }
+
+    #[test]
+    fn base_division_helpers_test() {
+        // Exact and inexact divisions
+        assert_eq!(
+            B16::from(12).div_rem(B16::from(4)),
+            (B16::from(3), B16::zero())
+        );
+        assert_eq!(
+            B16::from(13).div_rem(B16::from(4)),
+            (B16::from(3), B16::from(1))
+        );
+        assert_eq!(B16::from(12).div_ceil(B16::from(4)), B16::from(3));
+        assert_eq!(B16::from(13).div_ceil(B16::from(4)), B16::from(4));
+        assert_eq!(B16::zero().div_ceil(B16::from(4)), B16::zero());
+        assert_eq!(
+            B16::from(13).next_multiple_of(B16::from(4)),
+            Some(B16::from(16))
+        );
+        assert_eq!(
+            B16::from(16).next_multiple_of(B16::from(4)),
+            Some(B16::from(16))
+        );
+
+        // Values near MAX must not overflow
+        assert_eq!(B16::MAX.div_rem(B16::MAX), (B16::from(1), B16::zero()));
+        assert_eq!(B16::MAX.div_ceil(B16::from(1)), B16::MAX);
+        assert_eq!(B16::MAX.div_ceil(B16::from(2)), B16::from(32768));
+        assert_eq!(B16::MAX.div_ceil(B16::MAX), B16::from(1));
+        assert_eq!(B16::MAX.next_multiple_of(B16::from(1)), Some(B16::MAX));
+        assert_eq!(B16::MAX.next_multiple_of(B16::from(2)), None);
+        assert_eq!(
+            B16::from(65532).next_multiple_of(B16::from(5)),
+            Some(B16::from(65535))
+        );
+        assert_eq!(B16::from(65532).next_multiple_of(B16::from(8)), None);
+
+        assert_eq!(
+            B256::MAX.div_rem(B256::from(2)),
+            (B256::MAX / B256::from(2), B256::from(1))
+        );
+        assert_eq!(
+            B256::MAX.div_ceil(B256::from(2)),
+            B256::MAX / B256::from(2) + B256::from(1)
+        );
+        assert_eq!(B256::MAX.div_ceil(B256::MAX), B256::from(1));
+        assert_eq!(
+            (B256::MAX - B256::from(1)).div_ceil(B256::MAX),
+            B256::from(1)
+        );
+        assert_eq!(B256::MAX.next_multiple_of(B256::from(32)), None);
+        assert_eq!(B256::MAX.next_multiple_of(B256::MAX), Some(B256::MAX));
+        assert_eq!(
+            (B256::MAX - B256::from(40)).next_multiple_of(B256::from(32)),
+            Some(B256::MAX - B256::from(31))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn base_div_ceil_by_zero_test() {
+        B256::from(1).div_ceil(B256::zero());
+    }
+
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn base_borsh_test() {
+        let value = B256::from([0x5au8; 32]) - B256::from(1);
+        let bytes = borsh::to_vec(&value).unwrap();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(borsh::from_slice::<B256>(&bytes).unwrap(), value);
+
+        // Narrow types use the little endian encoding of their primitive
+        assert_eq!(borsh::to_vec(&B16::from(0x0102)).unwrap(), [0x02, 0x01]);
+        assert_eq!(
+            borsh::from_slice::<B64>(&[1, 0, 0, 0, 0, 0, 0, 0]).unwrap(),
+            B64::from(1)
+        );
+        assert!(borsh::from_slice::<B32>(&[1, 0]).is_err());
+
+        let wide = B512::from_halves(B256::from(1), B256::MAX);
+        let bytes = borsh::to_vec(&wide).unwrap();
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(borsh::from_slice::<B512>(&bytes).unwrap(), wide);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn base_serde_test() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Message {
+            narrow: B16,
+            address: B64,
+            wide: B512,
+        }
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Narrow {
+            narrow: B16,
+        }
+
+        let message = Message {
+            narrow: B16::from(0x0102),
+            address: B64::MAX,
+            wide: B512::from_halves(B256::from(1), B256::MAX),
+        };
+        let encoded = toml::to_string(&message).unwrap();
+        assert!(encoded.contains("narrow = \"0x0102\""));
+        assert!(encoded.contains("address = \"0xffffffffffffffff\""));
+        assert_eq!(toml::from_str::<Message>(&encoded).unwrap(), message);
+
+        // The string holds the full width of the value
+        for encoded in [
+            "narrow = \"0x01\"",
+            "narrow = \"0x010203\"",
+            "narrow = \"0102\"",
+        ] {
+            assert!(toml::from_str::<Narrow>(encoded).is_err());
+        }
+        assert_eq!(
+            toml::from_str::<Narrow>("narrow = \"0x00ff\"").unwrap(),
+            Narrow {
+                narrow: B16::from(0xff)
+            }
+        );
+    }
+
+    // Sum a slice without copying the operands, as generic machine code does
+    fn sum_by_ref<const S: usize, T: Base<S>>(values: &[T]) -> T {
+        values.iter().fold(T::zero(), |sum, value| sum + value)
+    }
+
+    #[test]
+    #[allow(clippy::op_ref)]
+    fn base_ref_operators_test() {
+        let a = B256::from(1000);
+        let b = B256::from(7);
+        assert_eq!(&a + &b, a + b);
+        assert_eq!(&a - &b, a - b);
+        assert_eq!(&a * &b, a * b);
+        assert_eq!(&a / &b, a / b);
+        assert_eq!(&a % &b, a % b);
+        assert_eq!(a + &b, &a + b);
+        assert_eq!(&B16::MAX - B16::from(1), B16::from(65534));
+
+        assert_eq!(
+            sum_by_ref(&[B64::from(1), B64::from(2), B64::from(3)]),
+            B64::from(6)
+        );
+        assert_eq!(sum_by_ref::<32, B256>(&[]), B256::zero());
+    }
+
+    #[test]
+    fn base_chunks_of_words_test() {
+        // Empty input has no word
+        assert_eq!(chunks_of_words::<B256, 32>(&[]).count(), 0);
+        assert!(words_to_bytes::<B256, 32>(&[]).is_empty());
+
+        // Exact multiple of the word size
+        let bytes: Vec<u8> = (0..64u8).collect();
+        let words: Vec<(usize, B256)> = chunks_of_words(&bytes).collect();
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].0, 0);
+        assert_eq!(words[1].0, 32);
+        let first: [u8; 32] = words[0].1.into();
+        assert_eq!(first[..], bytes[..32]);
+        let plain: Vec<B256> = words.iter().map(|(_, word)| *word).collect();
+        assert_eq!(words_to_bytes(&plain), bytes);
+
+        // One byte tail is padded on the right
+        let mut bytes = bytes;
+        bytes.push(0xff);
+        let words: Vec<(usize, B256)> = chunks_of_words(&bytes).collect();
+        assert_eq!(words.len(), 3);
+        assert_eq!(words[2].0, 64);
+        let mut tail = [0u8; 32];
+        tail[0] = 0xff;
+        assert_eq!(words[2].1, B256::from(tail));
+        let plain: Vec<B256> = words.iter().map(|(_, word)| *word).collect();
+        let restored = words_to_bytes(&plain);
+        assert_eq!(restored.len(), 96);
+        assert_eq!(restored[..bytes.len()], bytes[..]);
+        assert!(restored[bytes.len()..].iter().all(|byte| *byte == 0));
+
+        // Narrow words
+        let words: Vec<(usize, B16)> = chunks_of_words(&[1, 2, 3]).collect();
+        assert_eq!(words, [(0, B16::from(0x0102)), (2, B16::from(0x0300))]);
+    }
+
+    #[test]
+    fn base_address_iterator_test() {
+        // Fixed number of cells
+        let addresses: Vec<B64> = AddressIterator::new(B64::from(16), 3).collect();
+        assert_eq!(addresses, [B64::from(16), B64::from(24), B64::from(32)]);
+        assert_eq!(AddressIterator::new(B64::from(16), 0).count(), 0);
+
+        // Exclusive and inclusive ranges
+        let addresses: Vec<B64> = AddressIterator::range(B64::from(0), B64::from(24)).collect();
+        assert_eq!(addresses, [B64::from(0), B64::from(8), B64::from(16)]);
+        assert_eq!(
+            AddressIterator::range_inclusive(B64::from(0), B64::from(24)).count(),
+            4
+        );
+        assert_eq!(
+            AddressIterator::range(B64::from(8), B64::from(8)).count(),
+            0
+        );
+        assert_eq!(AddressIterator::range(B64::from(0), B64::zero()).count(), 0);
+
+        // Near MAX the iteration stops instead of wrapping around
+        let start = B64::MAX - B64::from(20);
+        let addresses: Vec<B64> = AddressIterator::new(start, 10).collect();
+        assert_eq!(
+            addresses,
+            [start, start + B64::from(8), start + B64::from(16)]
+        );
+        assert_eq!(AddressIterator::range_inclusive(start, B64::MAX).count(), 3);
+        let mut iterator = AddressIterator::new(B64::MAX, 2);
+        assert_eq!(iterator.next(), Some(B64::MAX));
+        assert_eq!(iterator.next(), None);
+        assert_eq!(iterator.next(), None);
+
+        // B256 address spaces above 2^64
+        let start = B256::from(u64::MAX) * B256::from(4);
+        let addresses: Vec<B256> = AddressIterator::new(start, 2).collect();
+        assert_eq!(addresses, [start, start + B256::from(32)]);
+        assert_eq!(
+            AddressIterator::new(B256::MAX - B256::from(31), 5).count(),
+            1
+        );
+    }
+
+    const STACK_BASE: B256 = B256::from_limbs([0, 0, 1, 0]);
+    const HEAP_BASE: B64 = B64::new(0x1000);
+
+    #[test]
+    fn base_const_constructors_test() {
+        assert_eq!(
+            STACK_BASE,
+            B256::from(u64::MAX) * B256::from(u64::MAX)
+                + B256::from(u64::MAX) * B256::from(2)
+                + B256::from(1)
+        );
+        assert_eq!(HEAP_BASE, B64::from(0x1000));
+        assert_eq!(B256::new(42), B256::from(42));
+        assert_eq!(B256::from_limbs([42, 0, 0, 0]), B256::from(42));
+        assert_eq!(B256::from_limbs([u64::MAX; 4]), B256::MAX);
+        assert_eq!(B128::new(u64::MAX), B128::from(u64::MAX));
+        assert_eq!(B32::new(u32::MAX as u64), B32::MAX);
+        assert_eq!(B16::new(7), B16::from(7));
+    }
+
+    #[test]
+    #[should_panic]
+    fn base_const_constructor_out_of_range_test() {
+        B16::new(65536);
+    }
+
+    #[test]
+    fn base_checked_arithmetic_test() {
+        assert_eq!(B16::MAX.checked_add(B16::from(1)), None);
+        assert_eq!(B16::from(1).checked_add(B16::from(1)), Some(B16::from(2)));
+        assert_eq!(B16::zero().checked_sub(B16::from(1)), None);
+        assert_eq!(B16::from(3).checked_sub(B16::from(1)), Some(B16::from(2)));
+        assert_eq!(B16::from(256).checked_mul(B16::from(256)), None);
+        assert_eq!(B256::MAX.checked_add(B256::from(1)), None);
+        assert_eq!(B256::zero().checked_sub(B256::from(32)), None);
+        assert_eq!(B256::MAX.checked_mul(B256::from(2)), None);
+        assert_eq!(B256::MAX.checked_mul(B256::from(1)), Some(B256::MAX));
+    }
+
+    #[test]
+    fn base_parse_size_test() {
+        assert_eq!(B64::parse_size("4096"), Ok(B64::from(4096)));
+        assert_eq!(B64::parse_size(" 0x1000 "), Ok(B64::from(4096)));
+        assert_eq!(B64::parse_size("4KiB"), Ok(B64::from(4096)));
+        assert_eq!(B64::parse_size("4 GiB"), Ok(B64::from(4u64 << 30)));
+        assert_eq!(B64::parse_size("0x10MiB"), Ok(B64::from(16u64 << 20)));
+        assert_eq!(B64::parse_size("2^32"), Ok(B64::from(1u64 << 32)));
+        assert_eq!(B32::parse_size("2^32"), Err(Error::ValueOutOfRange));
+        assert_eq!(B32::parse_size("4GiB"), Err(Error::ValueOutOfRange));
+        assert_eq!(B16::parse_size("65536"), Err(Error::ValueOutOfRange));
+
+        // Powers of two beyond 64 bits
+        assert_eq!(B128::parse_size("2^70"), Ok(B128::from(1u128 << 70)));
+        assert_eq!(B64::parse_size("2^70"), Err(Error::ValueOutOfRange));
+        assert_eq!(
+            B256::parse_size("2^255"),
+            Ok(B256::from_limbs([0, 0, 0, 1 << 63]))
+        );
+        assert_eq!(B256::parse_size("2^256"), Err(Error::ValueOutOfRange));
+        assert_eq!(
+            B256::parse_size("0xffffffffffffffffffffffffffffffff1"),
+            Ok(B256::from(u128::MAX) * B256::from(16) + B256::from(1))
+        );
+
+        // Malformed inputs
+        assert_eq!(B64::parse_size(""), Err(Error::InvalidSizeFormat));
+        assert_eq!(B64::parse_size("KiB"), Err(Error::InvalidSizeFormat));
+        assert_eq!(B64::parse_size("12ab"), Err(Error::InvalidSizeFormat));
+        assert_eq!(B64::parse_size("0x"), Err(Error::InvalidSizeFormat));
+        assert_eq!(B64::parse_size("2^x"), Err(Error::InvalidSizeFormat));
+        assert_eq!(B64::parse_size("-1"), Err(Error::InvalidSizeFormat));
+    }
+
+    #[test]
+    fn base_512_bit_test() {
+        // Byte round trips, the most significant half comes first
+        let mut bytes = [0u8; 64];
+        bytes[0] = 0x80;
+        bytes[31] = 1;
+        bytes[63] = 2;
+        let value = B512::from(bytes);
+        assert_eq!(Into::<[u8; 64]>::into(value), bytes);
+        assert_eq!(
+            value.into_halves(),
+            (B256::from_limbs([1, 0, 0, 1 << 63]), B256::from(2))
+        );
+        assert_eq!(B512::from_halves(B256::MAX, B256::MAX), B512::MAX);
+        assert_eq!(value.fixed_be_bytes(), B256::from(2).fixed_be_bytes());
+        assert_eq!(value.leading_zeros(), 0);
+        assert_eq!(B512::from(1).bit_len(), 1);
+        assert_eq!(
+            B512::from_halves(B256::from(1), B256::zero()).bit_len(),
+            257
+        );
+        assert_eq!(B512::zero().leading_zeros(), 512);
+
+        // Arithmetic carries across the halves
+        let low_max = B512::from_halves(B256::zero(), B256::MAX);
+        let carried = B512::from_halves(B256::from(1), B256::zero());
+        assert_eq!(low_max + B512::from(1), carried);
+        assert_eq!(carried - B512::from(1), low_max);
+        assert_eq!(
+            low_max * low_max,
+            B512::from_halves(B256::MAX - B256::from(1), B256::from(1))
+        );
+        assert_eq!(
+            carried / B512::from(2),
+            B512::from_halves(B256::zero(), B256::MAX / B256::from(2) + B256::from(1))
+        );
+        assert_eq!((carried + B512::from(5)) % B512::from(4), B512::from(1));
+        assert_eq!((B512::MAX / B512::from(3)) * B512::from(3), B512::MAX);
+        assert_eq!(
+            B512::from(1000).div_rem(B512::from(7)),
+            (B512::from(142), B512::from(6))
+        );
+        assert!(B512::from(1) < carried && low_max < carried);
+
+        // Checked arithmetic and wide multiplication
+        assert_eq!(B512::MAX.checked_add(B512::from(1)), None);
+        assert_eq!(B512::zero().checked_sub(B512::from(1)), None);
+        assert_eq!(carried.checked_mul(carried), None);
+        assert_eq!(B512::MAX.checked_mul(B512::from(1)), Some(B512::MAX));
+        assert_eq!(B512::MAX.mul_div(B512::MAX, B512::MAX), Some(B512::MAX));
+        assert_eq!(B512::MAX.mul_div(B512::from(2), B512::from(1)), None);
+        assert_eq!(
+            carried.mul_div(carried, carried * B512::from(2)),
+            Some(B512::from_halves(
+                B256::zero(),
+                B256::MAX / B256::from(2) + B256::from(1)
+            ))
+        );
+        assert_eq!(B512::from(1).mul_div(B512::from(1), B512::zero()), None);
+        assert_eq!(B512::MAX.next_multiple_of(B512::from(64)), None);
+        assert_eq!(
+            B512::parse_size("2^511"),
+            Ok(B512::from_halves(
+                B256::from_limbs([0, 0, 0, 1 << 63]),
+                B256::zero()
+            ))
+        );
+        assert_eq!(B512::parse_size("2^512"), Err(Error::ValueOutOfRange));
+
+        // Narrow conversions
+        assert_eq!(u128::try_from(B512::from(u128::MAX)), Ok(u128::MAX));
+        assert_eq!(u128::try_from(carried), Err(Error::ValueOutOfRange));
+        assert_eq!(B256::try_from(low_max), Ok(B256::MAX));
+        assert_eq!(B256::try_from(carried), Err(Error::ValueOutOfRange));
+        assert_eq!(B512::try_from(-1i64), Err(Error::ValueOutOfRange));
+        assert_eq!(i64::try_from(B512::from(42)), Ok(42));
+        assert_eq!(B512::new(42), B512::from(B256::from(42)));
+
+        // Decimal display
+        assert_eq!(format!("{}", B512::zero()), "0");
+        assert_eq!(format!("{:>5}", B512::from(42)), "   42");
+        assert_eq!(
+            format!("{}", carried),
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936"
+        );
+        assert_eq!(
+            format!("{}", B512::MAX),
+            "13407807929942597099574024998205846127479365820592393377723561443721764030073546976801874298166903427690031858186486050853753882811946569946433649006084095"
+        );
+    }
+
+    #[test]
+    fn base_mul_div_test() {
+        // The naive product overflows but the result fits
+        assert_eq!(B64::MAX.mul_div(B64::MAX, B64::MAX), Some(B64::MAX));
+        assert_eq!(
+            B64::MAX.mul_div(B64::from(3), B64::from(4)),
+            Some(B64::from(u64::MAX / 4 * 3 + 2))
+        );
+        assert_eq!(
+            B16::from(60000).mul_div(B16::from(50000), B16::from(60000)),
+            Some(B16::from(50000))
+        );
+        assert_eq!(
+            B128::MAX.mul_div(B128::from(7), B128::from(7)),
+            Some(B128::MAX)
+        );
+        assert_eq!(B256::MAX.mul_div(B256::MAX, B256::MAX), Some(B256::MAX));
+        assert_eq!(
+            B256::MAX.mul_div(B256::from(2), B256::from(4)),
+            Some(B256::MAX / B256::from(2))
+        );
+        let large = B256::from(u128::MAX) * B256::from(u128::MAX);
+        assert_eq!(
+            large.mul_div(B256::from(u128::MAX), B256::from(u128::MAX)),
+            Some(large)
+        );
+
+        // Results that do not fit and division by zero
+        assert_eq!(B64::MAX.mul_div(B64::from(2), B64::from(1)), None);
+        assert_eq!(B256::MAX.mul_div(B256::from(2), B256::from(1)), None);
+        assert_eq!(B256::from(1).mul_div(B256::from(1), B256::zero()), None);
+        assert_eq!(B16::from(1).mul_div(B16::from(1), B16::zero()), None);
+
+        // Percentages
+        assert_eq!(B64::from(1).percent_of(B64::from(3)), Some(B64::from(33)));
+        assert_eq!(B256::MAX.percent_of(B256::MAX), Some(B256::from(100)));
+        assert_eq!(
+            B16::from(65535).percent_of(B16::from(65535)),
+            Some(B16::from(100))
+        );
+        assert_eq!(B16::from(1).percent_of(B16::zero()), None);
+    }
 }
@@ -0,0 +1,295 @@
+//! A minimal 512-bit unsigned integer made of two [U256] limbs, used by [B512](crate::base::B512).
+//! Only the operations needed by [Base](crate::base::Base) are provided, the arithmetic
+//! operators panic on overflow, underflow and division by zero like the primitive types.
+use super::mul_wide_u256;
+use core::cmp::Ordering;
+use core::fmt::{Display, Formatter};
+use core::ops::{Add, BitOr, Div, Mul, Rem, Sub};
+use ethnum::U256;
+
+/// 512-bit unsigned integer
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct U512 {
+    // Most significant 256 bits
+    high: U256,
+    // Least significant 256 bits
+    low: U256,
+}
+
+impl Ord for U512 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.high, self.low).cmp(&(other.high, other.low))
+    }
+}
+
+impl PartialOrd for U512 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Add the values and the carries, return the sum and the number of overflows
+fn add_with_carries(values: &[U256]) -> (U256, U256) {
+    values
+        .iter()
+        .fold((U256::ZERO, U256::ZERO), |(sum, carries), value| {
+            let (sum, overflow) = sum.overflowing_add(*value);
+            (sum, carries + U256::from(overflow as u8))
+        })
+}
+
+impl U512 {
+    /// The zero value
+    pub const ZERO: Self = Self::from_words(U256::ZERO, U256::ZERO);
+    /// The one value
+    pub const ONE: Self = Self::from_words(U256::ZERO, U256::ONE);
+    /// The largest value
+    pub const MAX: Self = Self::from_words(U256::MAX, U256::MAX);
+    /// The number of bits
+    pub const BITS: u32 = 512;
+
+    /// Create a value from its most and least significant 256-bit words
+    pub const fn from_words(high: U256, low: U256) -> Self {
+        Self { high, low }
+    }
+
+    /// Split the value into its most and least significant 256-bit words
+    pub const fn into_words(self) -> (U256, U256) {
+        (self.high, self.low)
+    }
+
+    /// Create a value from its big endian bytes
+    pub fn from_be_bytes(bytes: [u8; 64]) -> Self {
+        let mut high = [0u8; 32];
+        let mut low = [0u8; 32];
+        high.copy_from_slice(&bytes[..32]);
+        low.copy_from_slice(&bytes[32..]);
+        Self::from_words(U256::from_be_bytes(high), U256::from_be_bytes(low))
+    }
+
+    /// Create a value from its little endian bytes
+    pub fn from_le_bytes(bytes: [u8; 64]) -> Self {
+        let mut low = [0u8; 32];
+        let mut high = [0u8; 32];
+        low.copy_from_slice(&bytes[..32]);
+        high.copy_from_slice(&bytes[32..]);
+        Self::from_words(U256::from_le_bytes(high), U256::from_le_bytes(low))
+    }
+
+    /// Get the big endian bytes of the value
+    pub fn to_be_bytes(self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&self.high.to_be_bytes());
+        bytes[32..].copy_from_slice(&self.low.to_be_bytes());
+        bytes
+    }
+
+    /// Get the little endian bytes of the value
+    pub fn to_le_bytes(self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&self.low.to_le_bytes());
+        bytes[32..].copy_from_slice(&self.high.to_le_bytes());
+        bytes
+    }
+
+    /// Number of leading zero bits
+    pub fn leading_zeros(self) -> u32 {
+        if self.high == U256::ZERO {
+            256 + self.low.leading_zeros()
+        } else {
+            self.high.leading_zeros()
+        }
+    }
+
+    /// Checked addition, return [None] on overflow
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        let (low, carry) = self.low.overflowing_add(rhs.low);
+        let high = self
+            .high
+            .checked_add(rhs.high)?
+            .checked_add(U256::from(carry as u8))?;
+        Some(Self::from_words(high, low))
+    }
+
+    /// Checked subtraction, return [None] on underflow
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        if self < rhs {
+            return None;
+        }
+        Some(self.wrapping_sub(rhs))
+    }
+
+    /// Checked multiplication, return [None] on overflow
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        let (high, low) = mul_wide(self, rhs);
+        if high == Self::ZERO {
+            Some(low)
+        } else {
+            None
+        }
+    }
+
+    /// Quotient and remainder of the division, return [None] if `rhs` is zero
+    pub fn checked_div_rem(self, rhs: Self) -> Option<(Self, Self)> {
+        long_div(Self::ZERO, self, rhs)
+    }
+
+    fn wrapping_sub(self, rhs: Self) -> Self {
+        let (low, borrow) = self.low.overflowing_sub(rhs.low);
+        let high = self
+            .high
+            .wrapping_sub(rhs.high)
+            .wrapping_sub(U256::from(borrow as u8));
+        Self::from_words(high, low)
+    }
+
+    // Shift left by one bit, the most significant bit is dropped
+    fn shl1(self) -> Self {
+        Self::from_words((self.high << 1u32) | (self.low >> 255u32), self.low << 1u32)
+    }
+
+    // Get the bit at the position, the least significant bit is at position 0
+    fn bit(self, position: u32) -> bool {
+        let word = if position < 256 { self.low } else { self.high };
+        (word >> (position % 256)) & U256::ONE == U256::ONE
+    }
+
+    // Set the bit at the position
+    fn with_bit(self, position: u32) -> Self {
+        if position < 256 {
+            Self::from_words(self.high, self.low | (U256::ONE << position))
+        } else {
+            Self::from_words(self.high | (U256::ONE << (position - 256)), self.low)
+        }
+    }
+}
+
+// Full 1024-bit product of two 512-bit values, returned as (high, low)
+fn mul_wide(a: U512, b: U512) -> (U512, U512) {
+    let (lo_lo_hi, lo_lo_lo) = mul_wide_u256(a.low, b.low);
+    let (lo_hi_hi, lo_hi_lo) = mul_wide_u256(a.low, b.high);
+    let (hi_lo_hi, hi_lo_lo) = mul_wide_u256(a.high, b.low);
+    let (hi_hi_hi, hi_hi_lo) = mul_wide_u256(a.high, b.high);
+    let (word1, carry1) = add_with_carries(&[lo_lo_hi, lo_hi_lo, hi_lo_lo]);
+    let (word2, carry2) = add_with_carries(&[lo_hi_hi, hi_lo_hi, hi_hi_lo, carry1]);
+    // The product fits in 1024 bits, the last word can not overflow
+    let word3 = hi_hi_hi + carry2;
+    (
+        U512::from_words(word3, word2),
+        U512::from_words(word1, lo_lo_lo),
+    )
+}
+
+// Shift-subtract long division of (high, low) by den, return [None] if den is zero or
+// the quotient does not fit in 512 bits. The remainder stays below den
+fn long_div(high: U512, low: U512, den: U512) -> Option<(U512, U512)> {
+    if den == U512::ZERO || high >= den {
+        return None;
+    }
+    let mut remainder = high;
+    let mut quotient = U512::ZERO;
+    for position in (0..U512::BITS).rev() {
+        let carry = remainder.bit(U512::BITS - 1);
+        remainder = remainder.shl1();
+        if low.bit(position) {
+            remainder = remainder | U512::ONE;
+        }
+        if carry || remainder >= den {
+            remainder = remainder.wrapping_sub(den);
+            quotient = quotient.with_bit(position);
+        }
+    }
+    Some((quotient, remainder))
+}
+
+// Compute a * b / den rounded down with a 1024-bit intermediate product
+pub(crate) fn mul_div_u512(a: U512, b: U512, den: U512) -> Option<U512> {
+    let (high, low) = mul_wide(a, b);
+    long_div(high, low, den).map(|(quotient, _)| quotient)
+}
+
+impl Add for U512 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        self.checked_add(rhs).expect("attempt to add with overflow")
+    }
+}
+
+impl Sub for U512 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.checked_sub(rhs)
+            .expect("attempt to subtract with overflow")
+    }
+}
+
+impl Mul for U512 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        self.checked_mul(rhs)
+            .expect("attempt to multiply with overflow")
+    }
+}
+
+impl Div for U512 {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        self.checked_div_rem(rhs)
+            .expect("attempt to divide by zero")
+            .0
+    }
+}
+
+impl Rem for U512 {
+    type Output = Self;
+
+    fn rem(self, rhs: Self) -> Self::Output {
+        self.checked_div_rem(rhs)
+            .expect("attempt to calculate the remainder with a divisor of zero")
+            .1
+    }
+}
+
+impl BitOr for U512 {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self::from_words(self.high | rhs.high, self.low | rhs.low)
+    }
+}
+
+impl From<U256> for U512 {
+    fn from(value: U256) -> Self {
+        Self::from_words(U256::ZERO, value)
+    }
+}
+
+impl Display for U512 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        // 2^512 has 155 decimal digits
+        let mut digits = [0u8; 155];
+        let mut start = digits.len();
+        let mut value = *self;
+        let ten = Self::from(U256::new(10));
+        loop {
+            let (quotient, digit) = value
+                .checked_div_rem(ten)
+                .expect("Division by a non zero constant");
+            start -= 1;
+            digits[start] = b'0' + digit.low.as_u64() as u8;
+            value = quotient;
+            if value == Self::ZERO {
+                break;
+            }
+        }
+        f.pad_integral(
+            true,
+            "",
+            core::str::from_utf8(&digits[start..]).expect("Decimal digits are valid UTF-8"),
+        )
+    }
+}
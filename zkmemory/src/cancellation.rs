@@ -0,0 +1,87 @@
+//! Cooperative cancellation for long running proving tasks.
+//! A [CancellationToken] is checked at the stage boundaries that this crate controls:
+//! between batches of witness generation, between chunks of trace commitment and
+//! between the key generation and proving stages. The inner halo2 prover is not
+//! interruptible, a cancellation requested while it runs is reported as soon as it returns.
+//! Cancelled tasks never publish partial results, so no temporary state is left behind.
+extern crate alloc;
+extern crate std;
+use crate::error::Error;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Shared cancellation flag with an optional deadline
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    /// Create a new token that is only cancelled by [CancellationToken::cancel]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new token that is cancelled once the timeout elapsed
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            deadline: Instant::now().checked_add(timeout),
+        }
+    }
+
+    /// Request the cancellation, every clone of this token observes it
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Check if the cancellation was requested or the deadline was reached
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+            || self
+                .deadline
+                .map(|deadline| Instant::now() >= deadline)
+                .unwrap_or(false)
+    }
+
+    /// Return [Error::Cancelled] if the task must be aborted
+    pub fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CancellationToken;
+    use crate::error::Error;
+    extern crate std;
+    use std::time::Duration;
+
+    #[test]
+    fn test_cancel_shared_between_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert_eq!(clone.check(), Ok(()));
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert_eq!(clone.check(), Err(Error::Cancelled));
+    }
+
+    #[test]
+    fn test_timeout() {
+        assert_eq!(
+            CancellationToken::with_timeout(Duration::ZERO).check(),
+            Err(Error::Cancelled)
+        );
+        assert_eq!(
+            CancellationToken::with_timeout(Duration::from_secs(3600)).check(),
+            Ok(())
+        );
+    }
+}
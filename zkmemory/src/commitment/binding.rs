@@ -0,0 +1,177 @@
+//! Commit-then-challenge binding of the original and the sorted trace.
+//! The consistency circuits compress the limbs of a record with the powers of a challenge
+//! alpha. [bind_traces] commits to both traces with any [CommitmentScheme] before alpha is
+//! known and derives alpha from the commitments, so the traces cannot be chosen after it.
+//! The transcript is the number of records of the original trace as a big endian u64, the
+//! bytes of its commitment, then the same for the sorted trace. The bytes of a commitment are
+//! given by [TranscriptCommitment]. Alpha is the 64 bytes of BLAKE2b personalized with
+//! `zkmemory-alpha` of the transcript, reduced modulo the order of the field.
+extern crate alloc;
+use crate::{
+    commitment::{merkle::MerkleRoot, CommitmentScheme},
+    constraints::gadgets::ConvertedTraceRecord,
+    error::Error,
+};
+use alloc::vec::Vec;
+use blake2b_simd::Params;
+use ff::{Field, FromUniformBytes, PrimeField};
+use group::GroupEncoding;
+use halo2_proofs::halo2curves::bn256::G1Affine;
+
+// Personalization of the hash of the transcript
+const PERSONAL: &[u8] = b"zkmemory-alpha";
+
+/// Commitment absorbed by the transcript of [bind_traces]
+pub trait TranscriptCommitment {
+    /// Append the canonical bytes of the commitment to the transcript
+    fn append_to(&self, transcript: &mut Vec<u8>);
+}
+
+/// The 32 bytes of the root
+impl TranscriptCommitment for MerkleRoot {
+    fn append_to(&self, transcript: &mut Vec<u8>) {
+        transcript.extend_from_slice(self);
+    }
+}
+
+/// The 32 bytes of the compressed point
+impl TranscriptCommitment for G1Affine {
+    fn append_to(&self, transcript: &mut Vec<u8>) {
+        transcript.extend_from_slice(self.to_bytes().as_ref());
+    }
+}
+
+/// The number of commitments as a big endian u64 followed by the commitments
+impl<C: TranscriptCommitment> TranscriptCommitment for Vec<C> {
+    fn append_to(&self, transcript: &mut Vec<u8>) {
+        transcript.extend_from_slice(&(self.len() as u64).to_be_bytes());
+        for commitment in self.iter() {
+            commitment.append_to(transcript);
+        }
+    }
+}
+
+/// Commitments to the original and the sorted trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceBinding<C> {
+    /// Commitment to the trace in time order
+    pub original: C,
+    /// Commitment to the trace sorted by address and time
+    pub sorted: C,
+}
+
+/// Commit to the original and the sorted trace and derive the challenge alpha of the
+/// circuits from the commitments. Return [Error::MalformedTrace] if the traces do not have
+/// the same number of records and the error of the scheme if it cannot commit a trace
+pub fn bind_traces<F, S>(
+    scheme: &S,
+    original: &[ConvertedTraceRecord<F>],
+    sorted: &[ConvertedTraceRecord<F>],
+) -> Result<(TraceBinding<S::Commitment>, F), Error>
+where
+    F: Field + PrimeField + FromUniformBytes<64>,
+    S: CommitmentScheme<F>,
+    S::Commitment: TranscriptCommitment,
+{
+    if original.len() != sorted.len() {
+        return Err(Error::MalformedTrace);
+    }
+    let binding = TraceBinding {
+        original: scheme.commit(original)?,
+        sorted: scheme.commit(sorted)?,
+    };
+    let mut transcript = Vec::new();
+    transcript.extend_from_slice(&(original.len() as u64).to_be_bytes());
+    binding.original.append_to(&mut transcript);
+    transcript.extend_from_slice(&(sorted.len() as u64).to_be_bytes());
+    binding.sorted.append_to(&mut transcript);
+    let digest = Params::new()
+        .hash_length(64)
+        .personal(PERSONAL)
+        .hash(&transcript);
+    let mut bytes = [0u8; 64];
+    bytes.copy_from_slice(digest.as_bytes());
+    Ok((binding, F::from_uniform_bytes(&bytes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bind_traces;
+    use crate::{
+        base::B256,
+        commitment::{
+            kzg::KZGMemoryCommitment,
+            merkle::{HashKind, MerkleTraceCommitment},
+            CommitmentScheme,
+        },
+        constraints::{gadgets::ConvertedTraceRecord, helper::sort_trace},
+        error::Error,
+        machine::{MemoryInstruction, TraceRecord},
+    };
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use ff::{Field, FromUniformBytes, PrimeField};
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use halo2curves::pasta::Fp;
+
+    // Check that the challenge is deterministic and follows every record of both traces
+    fn check_binding<F, S>(scheme: &S)
+    where
+        F: Field + PrimeField + FromUniformBytes<64>,
+        S: CommitmentScheme<F>,
+        S::Commitment: super::TranscriptCommitment + PartialEq + core::fmt::Debug,
+    {
+        let trace: Vec<TraceRecord<B256, B256, 32, 32>> = (0..6u64)
+            .map(|i| {
+                TraceRecord::new(
+                    i,
+                    0,
+                    MemoryInstruction::Write,
+                    B256::from(32 * (i % 3)),
+                    B256::from(i + 1),
+                )
+            })
+            .collect();
+        let convert = |trace: &[TraceRecord<B256, B256, 32, 32>]| -> Vec<ConvertedTraceRecord<F>> {
+            trace
+                .iter()
+                .map(ConvertedTraceRecord::from_trace_record)
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+        let original = convert(&trace);
+        let sorted = convert(&sort_trace(trace));
+        let (binding, alpha) = bind_traces(scheme, &original, &sorted).unwrap();
+        assert_eq!(
+            bind_traces(scheme, &original, &sorted),
+            Ok((binding, alpha))
+        );
+
+        for index in 0..original.len() {
+            let mut changed = original.clone();
+            changed[index].value[31] += F::ONE;
+            let (_, other) = bind_traces(scheme, &changed, &sorted).unwrap();
+            assert_ne!(other, alpha);
+            let mut changed = sorted.clone();
+            changed[index].instruction += F::ONE;
+            let (_, other) = bind_traces(scheme, &original, &changed).unwrap();
+            assert_ne!(other, alpha);
+        }
+        // Swapping the traces is another transcript
+        let (_, swapped) = bind_traces(scheme, &sorted, &original).unwrap();
+        assert_ne!(swapped, alpha);
+        assert_eq!(
+            bind_traces(scheme, &original, &sorted[1..]),
+            Err(Error::MalformedTrace)
+        );
+    }
+
+    #[test]
+    fn test_bind_traces() {
+        for hash in [HashKind::Blake2b, HashKind::Poseidon, HashKind::Keccak256] {
+            check_binding::<Fp, _>(&MerkleTraceCommitment::new(hash));
+        }
+        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(3, [7; 32]);
+        check_binding::<Fr, _>(&kzg_scheme);
+    }
+}
@@ -0,0 +1,83 @@
+//! Witness of the openings for the circuits checking them.
+//! An [Extractor] lays out an opening of a [CommitmentScheme] as a flat list of field
+//! elements, so a circuit consumes the openings of a backend without knowing its types.
+//! The layout of every backend is documented on its implementation. The words which do not
+//! fit a field element, e.g. the nodes of a Merkle tree or the coordinates of a curve point,
+//! are split into two limbs of 128 bits by [to_limbs].
+extern crate alloc;
+use crate::{commitment::CommitmentScheme, error::Error};
+use alloc::vec::Vec;
+use ff::{Field, PrimeField};
+
+/// Inputs of a circuit checking an opening, see [Extractor]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpeningWitness<F: Field + PrimeField> {
+    /// Private inputs, the opened record and the proof of its opening
+    pub witness: Vec<F>,
+    /// Public inputs, the commitment the opening is checked against
+    pub public_inputs: Vec<F>,
+}
+
+/// Lay out the openings of a [CommitmentScheme] for a circuit
+pub trait Extractor<F: Field + PrimeField>: CommitmentScheme<F> {
+    /// Get the inputs of a circuit checking the opening against the commitment, the opening
+    /// itself is not checked. Return [Error::OpeningOutOfRange] if the commitment has no
+    /// record at the index of the opening
+    fn extract(
+        &self,
+        commitment: &Self::Commitment,
+        opening: &Self::Opening,
+    ) -> Result<OpeningWitness<F>, Error>;
+}
+
+/// Split 32 big endian bytes into two limbs of 128 bits, the most significant limb first
+pub fn to_limbs<F: PrimeField>(bytes: &[u8; 32]) -> [F; 2] {
+    let mut high = [0u8; 16];
+    let mut low = [0u8; 16];
+    high.copy_from_slice(&bytes[..16]);
+    low.copy_from_slice(&bytes[16..]);
+    [
+        F::from_u128(u128::from_be_bytes(high)),
+        F::from_u128(u128::from_be_bytes(low)),
+    ]
+}
+
+/// Join two limbs of [to_limbs] into 32 big endian bytes, None if a limb does not fit in
+/// 128 bits
+pub fn from_limbs<F: PrimeField>(limbs: &[F; 2]) -> Option<[u8; 32]> {
+    let mut bytes = [0u8; 32];
+    for (chunk, limb) in bytes.chunks_mut(16).zip(limbs) {
+        // The representations of the fields of the crate are little endian
+        let repr = limb.to_repr();
+        let (low, high) = repr.as_ref().split_at(16);
+        if high.iter().any(|byte| *byte != 0) {
+            return None;
+        }
+        chunk.copy_from_slice(low);
+        chunk.reverse();
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_limbs, to_limbs};
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use rand::Rng;
+
+    #[test]
+    fn test_limbs_round_trip() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..10 {
+            let bytes: [u8; 32] = rng.gen();
+            let limbs: [Fr; 2] = to_limbs(&bytes);
+            assert_eq!(from_limbs(&limbs), Some(bytes));
+        }
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        assert_eq!(to_limbs::<Fr>(&one), [Fr::ZERO, Fr::ONE]);
+        // A limb wider than 128 bits is not a limb
+        assert_eq!(from_limbs(&[Fr::ZERO, -Fr::ONE]), None);
+    }
+}
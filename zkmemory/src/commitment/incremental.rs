@@ -0,0 +1,83 @@
+//! Incremental updates of a commitment to the memory image.
+//! A state transition is committed by applying the cells changed between two
+//! [crate::machine::MemorySnapshot] instead of committing to the whole image again.
+use crate::{error::Error, machine::CellDelta};
+
+/// Commitment to the memory image that is updated one cell at a time,
+/// e.g. a Merkle tree with one leaf per memory cell
+pub trait IncrementalCommitment<K, V> {
+    /// Replace the leaf of a cell, implementations may check the previous value
+    fn update_cell(&mut self, delta: &CellDelta<K, V>) -> Result<(), Error>;
+
+    /// Apply the changes of [crate::machine::MemorySnapshot::diff] in address order,
+    /// stop at the first rejected change
+    fn apply_diff(&mut self, diff: &[CellDelta<K, V>]) -> Result<(), Error> {
+        diff.iter().try_for_each(|delta| self.update_cell(delta))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IncrementalCommitment;
+    use crate::{
+        base::{Base, B256},
+        config::DefaultConfig,
+        error::Error,
+        machine::{tests::StateMachine, AbstractMachine, AbstractMemoryMachine, CellDelta},
+    };
+    extern crate alloc;
+    use alloc::collections::BTreeMap;
+
+    // Leaves of the committed image, a delta is rejected if its previous value is stale
+    #[derive(Default)]
+    struct Leaves(BTreeMap<B256, B256>);
+
+    impl IncrementalCommitment<B256, B256> for Leaves {
+        fn update_cell(&mut self, delta: &CellDelta<B256, B256>) -> Result<(), Error> {
+            let current = self.0.get(&delta.addr).copied().unwrap_or(B256::zero());
+            if current != delta.before {
+                return Err(Error::MemoryInvalidInteraction);
+            }
+            if delta.after.is_zero() {
+                self.0.remove(&delta.addr);
+            } else {
+                self.0.insert(delta.addr, delta.after);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_apply_diff() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let cell = |i: usize| base + B256::from(i) * B256::WORD_SIZE;
+        let mut leaves = Leaves::default();
+
+        let genesis = sm.snapshot();
+        for i in 0..10 {
+            sm.write(cell(i), B256::from(i + 1)).unwrap();
+        }
+        let first = sm.snapshot();
+        leaves.apply_diff(&genesis.diff(&first)).unwrap();
+
+        sm.write(cell(3), B256::from(30)).unwrap();
+        sm.write(cell(20), B256::from(200)).unwrap();
+        let diff = sm.dirty_cells_since(&first);
+        assert_eq!(diff.len(), 2);
+        leaves.apply_diff(&diff).unwrap();
+
+        let expected = sm.snapshot();
+        assert!(leaves
+            .0
+            .iter()
+            .map(|(address, value)| (*address, *value))
+            .eq(expected.cells().iter().copied()));
+
+        // A diff computed from another starting point does not apply
+        assert_eq!(
+            leaves.apply_diff(&genesis.diff(&first)),
+            Err(Error::MemoryInvalidInteraction)
+        );
+    }
+}
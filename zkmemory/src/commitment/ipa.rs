@@ -0,0 +1,534 @@
+//! Commit to the trace records with a Pedersen vector commitment opened by an inner product
+//! argument. The generators are hashed to the curve from a domain separation string, so the
+//! setup is transparent: there is no secret like the one of the KZG params to trust.
+//! A record is laid out as [RECORD_WIDTH] elements of the vector: the time log, the
+//! instruction, the two limbs of 128 bits of the address and of the value then two zeros.
+//! The vector of a trace is padded with zeros to a power of two and committed without
+//! blinding. A record is opened by proving the inner product of the vector with the powers of
+//! a challenge at the positions of the record, the proof holds two points per halving of the
+//! vector so it grows with the logarithm of the length of the trace.
+//! The challenges are the 64 bytes of BLAKE2b personalized with `zkmemory-ipa` of the
+//! transcript, reduced modulo the order of the field.
+extern crate alloc;
+use crate::{
+    commitment::CommitmentScheme,
+    constraints::gadgets::ConvertedTraceRecord,
+    error::{Error, VerifyError},
+};
+use alloc::vec;
+use alloc::vec::Vec;
+use blake2b_simd::{Params, State};
+use ff::{Field, FromUniformBytes, PrimeField};
+use group::{prime::PrimeCurveAffine, Curve, GroupEncoding};
+use halo2_proofs::{
+    arithmetic::{best_multiexp, CurveAffine},
+    halo2curves::bn256::{Fq, Fr, G1Affine, G1},
+};
+
+/// Number of elements of the vector holding a record
+pub const RECORD_WIDTH: usize = 8;
+
+/// Domain separation string of the generators of [IPAMemoryCommitment::new]
+pub const IPA_DOMAIN: &[u8] = b"zkmemory-ipa-generators";
+
+// Personalization of the hash to the curve of the generators
+const GENERATOR_PERSONAL: &[u8] = b"zkmemory-ipa-gen";
+
+// Personalization of the hash of the transcript of an opening
+const TRANSCRIPT_PERSONAL: &[u8] = b"zkmemory-ipa";
+
+/// A Pedersen vector commitment to the trace records with a transparent setup
+#[derive(Debug, Clone)]
+pub struct IPAMemoryCommitment {
+    // Generators of the elements of the vector
+    generators: Vec<G1Affine>,
+    // Generator of the inner product
+    product_generator: G1Affine,
+}
+
+/// Commitment of [IPAMemoryCommitment] to a list of records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IPACommitment {
+    /// Pedersen commitment to the vector of the records
+    pub commitment: G1Affine,
+    /// Number of records of the list
+    pub len: usize,
+}
+
+/// Inner product argument of an [IPAOpening]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IPAProof {
+    /// Commitment to the cross terms of the lower half of every halving
+    pub left: Vec<G1Affine>,
+    /// Commitment to the cross terms of the upper half of every halving
+    pub right: Vec<G1Affine>,
+    /// The element the vector is folded to
+    pub scalar: Fr,
+}
+
+/// Opening of a record of a list committed by [IPAMemoryCommitment]
+#[derive(Debug, Clone)]
+pub struct IPAOpening {
+    /// Index of the record in the list
+    pub index: usize,
+    /// The opened record
+    pub record: ConvertedTraceRecord<Fr>,
+    /// Proof of the opening
+    pub proof: IPAProof,
+}
+
+impl IPAMemoryCommitment {
+    /// Hash 2^k generators to the curve from [IPA_DOMAIN], they commit lists of at most
+    /// 2^k / [RECORD_WIDTH] records
+    pub fn new(k: u32) -> Self {
+        Self::setup_with_domain(k, IPA_DOMAIN)
+    }
+
+    /// Hash 2^k generators to the curve from a domain separation string. The same k and
+    /// domain always give the same generators and different domains give unrelated ones
+    pub fn setup_with_domain(k: u32, domain: &[u8]) -> Self {
+        let size = 1u64 << k;
+        Self {
+            generators: (0..size)
+                .map(|index| hash_to_curve(domain, index))
+                .collect(),
+            product_generator: hash_to_curve(domain, u64::MAX),
+        }
+    }
+
+    /// Number of records a commitment holds at most
+    pub fn capacity(&self) -> usize {
+        self.generators.len() / RECORD_WIDTH
+    }
+
+    // Commit the record at the index of a list, the commitment to a list is the sum of the
+    // commitments to its records. Return [Error::IPAVectorTooLong] past the capacity
+    pub(crate) fn commit_record(
+        &self,
+        index: usize,
+        record: &ConvertedTraceRecord<Fr>,
+    ) -> Result<G1, Error> {
+        if index >= self.capacity() {
+            return Err(Error::IPAVectorTooLong {
+                capacity: self.capacity(),
+            });
+        }
+        let first = index * RECORD_WIDTH;
+        Ok(best_multiexp(
+            &record_elements(record),
+            &self.generators[first..first + RECORD_WIDTH],
+        ))
+    }
+
+    // Lay out the records as a vector padded with zeros to a power of two, return
+    // [Error::IPAVectorTooLong] if there are not enough generators to commit it
+    fn vector(&self, records: &[ConvertedTraceRecord<Fr>]) -> Result<Vec<Fr>, Error> {
+        let size = (records.len() * RECORD_WIDTH).next_power_of_two();
+        if size > self.generators.len() {
+            return Err(Error::IPAVectorTooLong {
+                capacity: self.capacity(),
+            });
+        }
+        let mut vector: Vec<Fr> = records.iter().flat_map(record_elements).collect();
+        vector.resize(size, Fr::ZERO);
+        Ok(vector)
+    }
+
+    // Fold the vector and the weights in halves until a single element is left, the cross
+    // terms of every halving are committed in the proof
+    fn prove_inner_product(
+        &self,
+        mut vector: Vec<Fr>,
+        mut weights: Vec<Fr>,
+        product_generator: G1,
+        transcript: &mut IPATranscript,
+    ) -> IPAProof {
+        let mut generators = self.generators[..vector.len()].to_vec();
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        while vector.len() > 1 {
+            let half = vector.len() / 2;
+            let (vector_lo, vector_hi) = vector.split_at(half);
+            let (weights_lo, weights_hi) = weights.split_at(half);
+            let (generators_lo, generators_hi) = generators.split_at(half);
+            let cross_left = (best_multiexp(vector_lo, generators_hi)
+                + product_generator * inner_product(vector_lo, weights_hi))
+            .to_affine();
+            let cross_right = (best_multiexp(vector_hi, generators_lo)
+                + product_generator * inner_product(vector_hi, weights_lo))
+            .to_affine();
+            transcript.absorb_point(&cross_left);
+            transcript.absorb_point(&cross_right);
+            let challenge = transcript.challenge();
+            // The challenges are never zero
+            let inverse = challenge.invert().unwrap();
+
+            vector = vector_lo
+                .iter()
+                .zip(vector_hi)
+                .map(|(lo, hi)| *lo * challenge + *hi * inverse)
+                .collect();
+            weights = weights_lo
+                .iter()
+                .zip(weights_hi)
+                .map(|(lo, hi)| *lo * inverse + *hi * challenge)
+                .collect();
+            let folded: Vec<G1> = generators_lo
+                .iter()
+                .zip(generators_hi)
+                .map(|(lo, hi)| *lo * inverse + *hi * challenge)
+                .collect();
+            generators = vec![G1Affine::identity(); half];
+            G1::batch_normalize(&folded, &mut generators);
+            left.push(cross_left);
+            right.push(cross_right);
+        }
+        IPAProof {
+            left,
+            right,
+            scalar: vector[0],
+        }
+    }
+}
+
+impl CommitmentScheme<Fr> for IPAMemoryCommitment {
+    type Commitment = IPACommitment;
+    type Opening = IPAOpening;
+    /// The k and the domain separation string of the generators, see
+    /// [IPAMemoryCommitment::setup_with_domain]
+    type PublicParams = (u32, Vec<u8>);
+
+    fn setup((k, domain): (u32, Vec<u8>)) -> Self {
+        Self::setup_with_domain(k, &domain)
+    }
+
+    fn commit(&self, records: &[ConvertedTraceRecord<Fr>]) -> Result<IPACommitment, Error> {
+        let vector = self.vector(records)?;
+        Ok(IPACommitment {
+            commitment: best_multiexp(&vector, &self.generators[..vector.len()]).to_affine(),
+            len: records.len(),
+        })
+    }
+
+    fn open(
+        &self,
+        records: &[ConvertedTraceRecord<Fr>],
+        index: usize,
+    ) -> Result<IPAOpening, Error> {
+        let record = records.get(index).ok_or(Error::OpeningOutOfRange {
+            index,
+            len: records.len(),
+        })?;
+        let commitment = self.commit(records)?;
+        let vector = self.vector(records)?;
+        let mut transcript = IPATranscript::new(&commitment, index, &record_elements(record));
+        let rho = transcript.challenge();
+        let product_generator = self.product_generator * transcript.challenge();
+        let mut weights = vec![Fr::ZERO; vector.len()];
+        let mut power = Fr::ONE;
+        for weight in weights[index * RECORD_WIDTH..(index + 1) * RECORD_WIDTH].iter_mut() {
+            *weight = power;
+            power *= rho;
+        }
+        Ok(IPAOpening {
+            index,
+            record: record.clone(),
+            proof: self.prove_inner_product(vector, weights, product_generator, &mut transcript),
+        })
+    }
+
+    fn verify(&self, commitment: &IPACommitment, opening: &IPAOpening) -> Result<(), VerifyError> {
+        if opening.index >= commitment.len {
+            return Err(VerifyError::IndexOutOfRange {
+                index: opening.index,
+                len: commitment.len,
+            });
+        }
+        if commitment.len > self.capacity() {
+            return Err(VerifyError::ParameterMismatch);
+        }
+        let size = (commitment.len * RECORD_WIDTH).next_power_of_two();
+        let rounds = size.trailing_zeros() as usize;
+        for found in [opening.proof.left.len(), opening.proof.right.len()] {
+            if found != rounds {
+                return Err(VerifyError::PathLengthMismatch {
+                    expected: rounds,
+                    found,
+                });
+            }
+        }
+        let elements = record_elements(&opening.record);
+        let mut transcript = IPATranscript::new(commitment, opening.index, &elements);
+        let rho = transcript.challenge();
+        let product_generator = self.product_generator * transcript.challenge();
+        // The claimed inner product is the record weighted by the powers of rho
+        let claimed = elements
+            .iter()
+            .rev()
+            .fold(Fr::ZERO, |sum, element| sum * rho + element);
+
+        let mut folded = G1::from(commitment.commitment) + product_generator * claimed;
+        let mut challenges = Vec::with_capacity(rounds);
+        for (cross_left, cross_right) in opening.proof.left.iter().zip(&opening.proof.right) {
+            transcript.absorb_point(cross_left);
+            transcript.absorb_point(cross_right);
+            let challenge = transcript.challenge();
+            let inverse = challenge.invert().unwrap();
+            folded += *cross_left * challenge.square() + *cross_right * inverse.square();
+            challenges.push((challenge, inverse));
+        }
+        // The generator the vector is folded to weights the i-th generator with the product
+        // of the challenges of the halvings putting it in the upper half and of the inverses
+        // of the others, the first halving decides the most significant bit of i
+        let mut scalars = vec![Fr::ONE];
+        for (challenge, inverse) in challenges.iter().rev() {
+            scalars = scalars
+                .iter()
+                .map(|scalar| *scalar * inverse)
+                .chain(scalars.iter().map(|scalar| *scalar * challenge))
+                .collect();
+        }
+        let generator = best_multiexp(&scalars, &self.generators[..size]);
+        let first = opening.index * RECORD_WIDTH;
+        let weight = scalars[first..first + RECORD_WIDTH]
+            .iter()
+            .rev()
+            .fold(Fr::ZERO, |sum, scalar| sum * rho + scalar);
+        let scalar = opening.proof.scalar;
+        if folded == generator * scalar + product_generator * (scalar * weight) {
+            Ok(())
+        } else {
+            Err(VerifyError::InnerProductCheckFailed)
+        }
+    }
+}
+
+// Fiat-Shamir transcript of an opening
+struct IPATranscript {
+    state: State,
+}
+
+impl IPATranscript {
+    // Absorb the commitment, the number of records and the index as big endian u64 and the
+    // opened elements
+    fn new(commitment: &IPACommitment, index: usize, elements: &[Fr; RECORD_WIDTH]) -> Self {
+        let mut transcript = Self {
+            state: Params::new()
+                .hash_length(64)
+                .personal(TRANSCRIPT_PERSONAL)
+                .to_state(),
+        };
+        transcript.absorb_point(&commitment.commitment);
+        transcript
+            .state
+            .update(&(commitment.len as u64).to_be_bytes());
+        transcript.state.update(&(index as u64).to_be_bytes());
+        for element in elements {
+            transcript.state.update(element.to_repr().as_ref());
+        }
+        transcript
+    }
+
+    // Absorb the bytes of the compressed point
+    fn absorb_point(&mut self, point: &G1Affine) {
+        self.state.update(point.to_bytes().as_ref());
+    }
+
+    // Squeeze a non zero challenge, its digest is absorbed so the next one differs
+    fn challenge(&mut self) -> Fr {
+        loop {
+            let digest = self.state.finalize();
+            self.state.update(digest.as_bytes());
+            let mut bytes = [0u8; 64];
+            bytes.copy_from_slice(digest.as_bytes());
+            let challenge = Fr::from_uniform_bytes(&bytes);
+            if !bool::from(challenge.is_zero()) {
+                return challenge;
+            }
+        }
+    }
+}
+
+// Hash the index to a point of G1 by try and increment: the x coordinate is the hash of the
+// domain, the index and a counter, for the first counter giving a point of the curve. The
+// cofactor of G1 is one so every point of the curve is in the group
+fn hash_to_curve(domain: &[u8], index: u64) -> G1Affine {
+    let mut counter = 0u64;
+    loop {
+        let digest = Params::new()
+            .hash_length(32)
+            .personal(GENERATOR_PERSONAL)
+            .to_state()
+            .update(&(domain.len() as u64).to_be_bytes())
+            .update(domain)
+            .update(&index.to_be_bytes())
+            .update(&counter.to_be_bytes())
+            .finalize();
+        // The representation is little endian and the modulus is below 2^254
+        let mut repr = <Fq as PrimeField>::Repr::default();
+        repr.as_mut().copy_from_slice(digest.as_bytes());
+        repr.as_mut()[31] &= 0x3f;
+        let point = Option::<Fq>::from(Fq::from_repr(repr)).and_then(|x| {
+            Option::<Fq>::from((x.square() * x + G1Affine::b()).sqrt())
+                .and_then(|y| Option::from(G1Affine::from_xy(x, y)))
+        });
+        if let Some(point) = point {
+            return point;
+        }
+        counter += 1;
+    }
+}
+
+// Get the elements of the vector holding a record, the limbs of the record are bytes so the
+// limbs of 128 bits are their big endian sums
+fn record_elements(record: &ConvertedTraceRecord<Fr>) -> [Fr; RECORD_WIDTH] {
+    let join = |limbs: &[Fr]| {
+        limbs
+            .iter()
+            .fold(Fr::ZERO, |joined, limb| joined * Fr::from(256) + limb)
+    };
+    [
+        join(&record.time_log),
+        record.instruction,
+        join(&record.address[..16]),
+        join(&record.address[16..]),
+        join(&record.value[..16]),
+        join(&record.value[16..]),
+        Fr::ZERO,
+        Fr::ZERO,
+    ]
+}
+
+// Inner product of two vectors of the same length
+fn inner_product(left: &[Fr], right: &[Fr]) -> Fr {
+    left.iter()
+        .zip(right)
+        .fold(Fr::ZERO, |sum, (left, right)| sum + *left * right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IPACommitment, IPAMemoryCommitment, RECORD_WIDTH};
+    use crate::{
+        base::B256,
+        commitment::CommitmentScheme,
+        constraints::gadgets::ConvertedTraceRecord,
+        error::{Error, VerifyError},
+        machine::{MemoryInstruction, TraceRecord},
+    };
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use ff::Field;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    // Records writing then reading distinct cells
+    fn records(len: u64) -> Vec<ConvertedTraceRecord<Fr>> {
+        (0..len)
+            .map(|i| {
+                let instruction = if i % 2 == 0 {
+                    MemoryInstruction::Write
+                } else {
+                    MemoryInstruction::Read
+                };
+                let record = TraceRecord::<B256, B256, 32, 32>::new(
+                    i,
+                    0,
+                    instruction,
+                    B256::from(32 * (i / 2)),
+                    B256::from(u64::MAX - i / 2),
+                );
+                ConvertedTraceRecord::from_trace_record(&record).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_commit_open_verify() {
+        let scheme = IPAMemoryCommitment::new(7);
+        let records = records(11);
+        let commitment = scheme.commit(&records).unwrap();
+        assert_eq!(commitment, scheme.commit(&records).unwrap());
+        for index in 0..records.len() {
+            let opening = scheme.open(&records, index).unwrap();
+            assert_eq!(scheme.verify(&commitment, &opening), Ok(()));
+            // The vector of 11 records is padded to 128 elements, halved 7 times
+            assert_eq!(opening.proof.left.len(), 7);
+            assert_eq!(opening.proof.right.len(), 7);
+        }
+    }
+
+    #[test]
+    fn test_reject_wrong_value() {
+        let scheme = IPAMemoryCommitment::new(6);
+        let records = records(5);
+        let commitment = scheme.commit(&records).unwrap();
+        let opening = scheme.open(&records, 3).unwrap();
+
+        let rejected = Err(VerifyError::InnerProductCheckFailed);
+        let mut wrong = opening.clone();
+        wrong.record.value[31] += Fr::ONE;
+        assert_eq!(scheme.verify(&commitment, &wrong), rejected);
+        // The record of another index is not the one at the index of the opening
+        let mut wrong = opening.clone();
+        wrong.record = records[2].clone();
+        assert_eq!(scheme.verify(&commitment, &wrong), rejected);
+        let mut wrong = opening.clone();
+        wrong.proof.scalar += Fr::ONE;
+        assert_eq!(scheme.verify(&commitment, &wrong), rejected);
+
+        // The 5 records are padded to 64 elements, halved 6 times
+        let mut wrong = opening.clone();
+        wrong.proof.left.pop();
+        assert_eq!(
+            scheme.verify(&commitment, &wrong),
+            Err(VerifyError::PathLengthMismatch {
+                expected: 6,
+                found: 5
+            })
+        );
+        let mut wrong = opening.clone();
+        wrong.index = 5;
+        assert_eq!(
+            scheme.verify(&commitment, &wrong),
+            Err(VerifyError::IndexOutOfRange { index: 5, len: 5 })
+        );
+        // The generators commit at most 8 records
+        let longer = IPACommitment {
+            len: 9,
+            ..commitment
+        };
+        assert_eq!(
+            scheme.verify(&longer, &opening),
+            Err(VerifyError::ParameterMismatch)
+        );
+    }
+
+    #[test]
+    fn test_transparent_setup() {
+        let records = records(4);
+        let too_long = self::records(5);
+        let scheme = IPAMemoryCommitment::new(5);
+        let commitment = scheme.commit(&records).unwrap();
+        // The generators are derived from the domain only
+        let same =
+            <IPAMemoryCommitment as CommitmentScheme<Fr>>::setup((5, super::IPA_DOMAIN.to_vec()));
+        assert_eq!(same.commit(&records), Ok(commitment));
+        let other = IPAMemoryCommitment::setup_with_domain(5, b"another domain");
+        assert_ne!(other.commit(&records), Ok(commitment));
+        let opening = other.open(&records, 1).unwrap();
+        assert_eq!(
+            scheme.verify(&commitment, &opening),
+            Err(VerifyError::InnerProductCheckFailed)
+        );
+        assert_eq!(
+            other.verify(&other.commit(&records).unwrap(), &opening),
+            Ok(())
+        );
+
+        assert_eq!(scheme.capacity(), 32 / RECORD_WIDTH);
+        assert_eq!(
+            scheme.commit(&too_long),
+            Err(Error::IPAVectorTooLong { capacity: 4 })
+        );
+    }
+}
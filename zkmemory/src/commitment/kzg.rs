@@ -1,20 +1,40 @@
 //! Commit to the trace record using KZG commitment scheme.
 //! We convert the trace into a polynomial and apply the algorithms in
-//! [PSE 's KZG implementation](https://github.com/privacy-scaling-explorations/halo2/tree/main/halo2_backend/src/poly/kzg) to commit, open and verify the polynomial
+//! [PSE 's KZG implementation](https://github.com/privacy-scaling-explorations/halo2/tree/main/halo2_backend/src/poly/kzg) to commit, open and verify the polynomial.
+//! Blinding factors are sampled for every commitment and opening and are never stored,
+//! so with the `zeroize` feature only the trace records need to be wiped after use, see
+//! `zeroize::Zeroizing` to wipe them on drop.
+//! The structured reference string is a [KZGParams], with the `std` feature it can be
+//! persisted and cached on disk so the proofs of different runs use the same one, or be
+//! imported from the powers of tau of a public ceremony, see [CeremonyPowers].
+//! [KZGMemoryCommitment::commit_trace] commits a whole trace to one polynomial over the
+//! domain of the params and its records are opened by their index in the trace.
+//! The openings of the chunks of a trace are aggregated into a single proof checked with the
+//! state roots linking the chunks, see [KZGMemoryCommitment::aggregate_chunk_proofs].
 
 extern crate alloc;
-use crate::{base::Base, machine::MemoryInstruction, machine::TraceRecord};
+use crate::{
+    base::Base,
+    cancellation::CancellationToken,
+    commitment::{
+        extractor::{to_limbs, Extractor, OpeningWitness},
+        merkle::{commit_image, MerkleRoot},
+    },
+    constraints::gadgets::ConvertedTraceRecord,
+    error::VerifyError,
+    machine::{chunk::TraceChunk, TraceRecord},
+};
 use alloc::vec;
 use alloc::vec::Vec;
-use core::marker::PhantomData;
-use ff::{Field, WithSmallOrderMulGroup};
-use group::Curve;
+use core::{fmt::Debug, marker::PhantomData};
+use ff::{Field, PrimeField, WithSmallOrderMulGroup};
+use group::{Curve, Group, GroupEncoding};
 use halo2_proofs::{
-    arithmetic::{eval_polynomial, lagrange_interpolate},
-    halo2curves::bn256::{Bn256, Fr, G1Affine},
+    arithmetic::{best_multiexp, eval_polynomial, lagrange_interpolate},
+    halo2curves::bn256::{Bn256, Fq, Fr, G1Affine, G2Affine, G1},
     plonk::Error,
     poly::{
-        commitment::{Blind, CommitmentScheme, ParamsProver, Prover, Verifier},
+        commitment::{Blind, CommitmentScheme, Params as _, ParamsProver, Prover, Verifier},
         kzg::{
             commitment::{KZGCommitmentScheme, ParamsKZG},
             multiopen::{ProverSHPLONK, VerifierSHPLONK},
@@ -23,11 +43,24 @@ use halo2_proofs::{
         {Coeff, EvaluationDomain, Polynomial, ProverQuery, VerificationStrategy, VerifierQuery},
     },
     transcript::{
-        Blake2bRead, Blake2bWrite, Challenge255, EncodedChallenge, TranscriptReadBuffer,
-        TranscriptWriterBuffer,
+        Blake2bRead, Blake2bWrite, Challenge255, EncodedChallenge, Transcript,
+        TranscriptReadBuffer, TranscriptWriterBuffer,
     },
 };
-use rand_core::OsRng;
+use rand_chacha::ChaCha20Rng;
+use rand_core::{OsRng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "std")]
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+};
+
+// Minimum number of records committed in parallel with the `parallel` feature
+#[cfg(feature = "parallel")]
+const PARALLEL_MIN_RECORDS: usize = 64;
 
 /// Omega power omega^0 to omega^7
 const OMEGA_POWER: [Fr; 8] = [
@@ -41,6 +74,321 @@ const OMEGA_POWER: [Fr; 8] = [
     Fr::from_raw([0x0c90f7, 0, 0, 0]),
 ];
 
+/// Number of trace records committed between two cancellation checks
+const COMMITMENT_CHUNK_SIZE: usize = 64;
+
+/// Number of streamed coefficients committed by a single multiexponentiation
+const COMMITMENT_STREAM_CHUNK: usize = 1 << 12;
+
+/// Magic bytes at the start of persisted [KZGParams]
+pub const KZG_PARAMS_MAGIC: [u8; 8] = *b"ZKMKZGPR";
+
+// Size of the checksum of persisted params
+#[cfg(feature = "std")]
+const CHECKSUM_SIZE: usize = 32;
+
+// Personalization of the checksum of persisted params
+#[cfg(feature = "std")]
+const PARAMS_PERSONAL: &[u8] = b"zkmemory-kzg-srs";
+
+// Get the checksum of the halo2 encoding of params
+#[cfg(feature = "std")]
+fn checksum(encoded: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let mut checksum = [0u8; CHECKSUM_SIZE];
+    checksum.copy_from_slice(
+        blake2b_simd::Params::new()
+            .hash_length(CHECKSUM_SIZE)
+            .personal(PARAMS_PERSONAL)
+            .hash(encoded)
+            .as_bytes(),
+    );
+    checksum
+}
+
+/// Structured reference string of the KZG commitments, the powers of a secret in G1 and G2.
+/// It is persisted as [KZG_PARAMS_MAGIC], the BLAKE2b checksum of the halo2 encoding of the
+/// params and that encoding
+#[derive(Debug, Clone)]
+pub struct KZGParams(ParamsKZG<Bn256>);
+
+impl KZGParams {
+    /// Generate the params of the polynomials of up to `2^k` coefficients from a random secret
+    /// of the operating system. The secret is dropped but nobody else can check it, and two
+    /// provers get different params, see [KZGParams::setup_with_seed]
+    pub fn generate_insecure_random(k: u32) -> Self {
+        Self(ParamsKZG::<Bn256>::new(k))
+    }
+
+    /// Generate the params of the polynomials of up to `2^k` coefficients from a secret drawn
+    /// by ChaCha20 from the seed, the same seed gives the same params. Whoever knows the seed
+    /// knows the secret and can forge openings, the params of untrusted provers are imported
+    /// from a ceremony instead, see [CeremonyPowers]
+    pub fn setup_with_seed(k: u32, seed: [u8; 32]) -> Self {
+        Self(ParamsKZG::<Bn256>::setup(k, ChaCha20Rng::from_seed(seed)))
+    }
+
+    /// Get the k of the params
+    pub fn k(&self) -> u32 {
+        self.0.k()
+    }
+
+    /// Shrink the params to the polynomials of up to `2^k` coefficients, return
+    /// [Error::KZGParamsTooSmall](crate::error::Error::KZGParamsTooSmall) if they are smaller
+    pub fn downsize(&mut self, k: u32) -> Result<(), crate::error::Error> {
+        if k > self.k() {
+            return Err(crate::error::Error::KZGParamsTooSmall {
+                found: self.k(),
+                requested: k,
+            });
+        }
+        if k < self.k() {
+            self.0.downsize(k);
+        }
+        Ok(())
+    }
+
+    /// Write the params to the writer, return
+    /// [Error::KZGParamsIo](crate::error::Error::KZGParamsIo) if the writer fails
+    #[cfg(feature = "std")]
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), crate::error::Error> {
+        let mut encoded = Vec::new();
+        self.0
+            .write(&mut encoded)
+            .and_then(|_| writer.write_all(&KZG_PARAMS_MAGIC))
+            .and_then(|_| writer.write_all(&checksum(&encoded)))
+            .and_then(|_| writer.write_all(&encoded))
+            .and_then(|_| writer.flush())
+            .map_err(|_| crate::error::Error::KZGParamsIo)
+    }
+
+    /// Read params written by [KZGParams::write], return
+    /// [Error::KZGParamsIo](crate::error::Error::KZGParamsIo) if the reader fails and
+    /// [Error::CorruptedKZGParams](crate::error::Error::CorruptedKZGParams) if the bytes are
+    /// not params or do not match their checksum
+    #[cfg(feature = "std")]
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, crate::error::Error> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .map_err(|_| crate::error::Error::KZGParamsIo)?;
+        let header = KZG_PARAMS_MAGIC.len() + CHECKSUM_SIZE;
+        if bytes.len() < header || bytes[..KZG_PARAMS_MAGIC.len()] != KZG_PARAMS_MAGIC {
+            return Err(crate::error::Error::CorruptedKZGParams);
+        }
+        let (expected, mut encoded) = bytes[KZG_PARAMS_MAGIC.len()..].split_at(CHECKSUM_SIZE);
+        if expected != checksum(encoded) {
+            return Err(crate::error::Error::CorruptedKZGParams);
+        }
+        match ParamsKZG::<Bn256>::read(&mut encoded) {
+            Ok(params) if encoded.is_empty() => Ok(Self(params)),
+            _ => Err(crate::error::Error::CorruptedKZGParams),
+        }
+    }
+
+    /// Load the params cached in the file at the path, or generate them for the given k and
+    /// cache them if there is no such file. Loaded params larger than requested are
+    /// downsized to k, see [KZGParams::downsize] and [KZGParams::read] for the errors
+    #[cfg(feature = "std")]
+    pub fn load_or_generate(path: impl AsRef<Path>, k: u32) -> Result<Self, crate::error::Error> {
+        match File::open(path.as_ref()) {
+            Ok(file) => {
+                let mut params = Self::read(BufReader::new(file))?;
+                params.downsize(k)?;
+                Ok(params)
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                let params = Self::generate_insecure_random(k);
+                let file = File::create(path).map_err(|_| crate::error::Error::KZGParamsIo)?;
+                params.write(BufWriter::new(file))?;
+                Ok(params)
+            }
+            Err(_) => Err(crate::error::Error::KZGParamsIo),
+        }
+    }
+
+    /// Import the params of the polynomials of `2^k` coefficients from the JSON transcript of
+    /// a powers of tau ceremony, from its first sub-ceremony with at least `2^k` powers in G1.
+    /// Return [Error::KZGParamsTooSmall](crate::error::Error::KZGParamsTooSmall) if there is
+    /// none, see [CeremonyPowers::from_json] for the other errors
+    pub fn from_ceremony_json(json: &[u8], k: u32) -> Result<Self, crate::error::Error> {
+        let powers = CeremonyPowers::<Bn256>::from_json(json, 1 << k)?;
+        let g = powers.g1[..1 << k].to_vec();
+        // The params are only built from their parts by an existing instance
+        Ok(Self(ParamsKZG::<Bn256>::new(0).from_parts(
+            k,
+            g,
+            None,
+            powers.g2[0],
+            powers.g2[1],
+        )))
+    }
+}
+
+/// Curve of the powers of tau of a ceremony, the points are hex strings of their compressed
+/// encoding
+pub trait CeremonyCurve {
+    /// Point of G1
+    type G1: Copy + Debug + Eq;
+    /// Point of G2
+    type G2: Copy + Debug + Eq;
+
+    /// Decode a point of G1, None if it is not a point of the prime order subgroup
+    fn g1_from_bytes(bytes: &[u8]) -> Option<Self::G1>;
+
+    /// Decode a point of G2, None if it is not a point of the prime order subgroup
+    fn g2_from_bytes(bytes: &[u8]) -> Option<Self::G2>;
+}
+
+// Decode the compressed encoding of a point of a halo2 curve
+fn decode_point<C: GroupEncoding>(bytes: &[u8]) -> Option<C> {
+    let mut repr = C::Repr::default();
+    if repr.as_ref().len() != bytes.len() {
+        return None;
+    }
+    repr.as_mut().copy_from_slice(bytes);
+    C::from_bytes(&repr).into()
+}
+
+/// The points of bn254 are in the compressed encoding of halo2curves, G1 has a cofactor of 1
+/// so only the points of G2 are checked against the order of the subgroup
+impl CeremonyCurve for Bn256 {
+    type G1 = G1Affine;
+    type G2 = G2Affine;
+
+    fn g1_from_bytes(bytes: &[u8]) -> Option<Self::G1> {
+        decode_point(bytes)
+    }
+
+    fn g2_from_bytes(bytes: &[u8]) -> Option<Self::G2> {
+        // (r - 1) * P + P is the identity if and only if the order of P divides r
+        decode_point(bytes)
+            .filter(|point: &G2Affine| bool::from((*point * -Fr::ONE + point).is_identity()))
+    }
+}
+
+/// Powers of tau `[tau^i]G1` and `[tau^i]G2` of a sub-ceremony of a powers of tau ceremony,
+/// like the KZG ceremony of Ethereum. Their JSON transcript lists the sub-ceremonies as
+/// `{"powersOfTau": {"G1Powers": [..], "G2Powers": [..]}, ..}`, only these arrays are read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CeremonyPowers<C: CeremonyCurve> {
+    /// Powers of tau in G1, starting from the generator
+    pub g1: Vec<C::G1>,
+    /// Powers of tau in G2, starting from the generator
+    pub g2: Vec<C::G2>,
+}
+
+impl<C: CeremonyCurve> CeremonyPowers<C> {
+    /// Read the first sub-ceremony of the transcript with at least the given number of powers
+    /// in G1 and two powers in G2. Return
+    /// [Error::TruncatedTranscript](crate::error::Error::TruncatedTranscript) if the
+    /// transcript ends inside the powers,
+    /// [Error::MalformedTranscript](crate::error::Error::MalformedTranscript) if they are not
+    /// arrays of hex strings,
+    /// [Error::TranscriptPointNotInSubgroup](crate::error::Error::TranscriptPointNotInSubgroup)
+    /// if one of the selected powers is not a point of the subgroup and
+    /// [Error::KZGParamsTooSmall](crate::error::Error::KZGParamsTooSmall) if every
+    /// sub-ceremony has less powers
+    pub fn from_json(json: &[u8], g1_powers: usize) -> Result<Self, crate::error::Error> {
+        let g1 = json_string_arrays(json, b"\"G1Powers\"")?;
+        let g2 = json_string_arrays(json, b"\"G2Powers\"")?;
+        if g1.is_empty() || g1.len() != g2.len() {
+            return Err(crate::error::Error::MalformedTranscript);
+        }
+        let largest = g1.iter().map(Vec::len).max().unwrap_or(0);
+        let (g1, g2) = g1
+            .into_iter()
+            .zip(g2)
+            .find(|(g1, g2)| g1.len() >= g1_powers && g2.len() >= 2)
+            .ok_or(crate::error::Error::KZGParamsTooSmall {
+                found: largest.checked_ilog2().unwrap_or(0),
+                requested: g1_powers.checked_ilog2().unwrap_or(0),
+            })?;
+        Ok(Self {
+            g1: decode_powers(&g1, 1, C::g1_from_bytes)?,
+            g2: decode_powers(&g2, 2, C::g2_from_bytes)?,
+        })
+    }
+}
+
+// Decode the hex strings of the powers of tau of a group
+fn decode_powers<P>(
+    powers: &[&[u8]],
+    group: u8,
+    decode: impl Fn(&[u8]) -> Option<P>,
+) -> Result<Vec<P>, crate::error::Error> {
+    powers
+        .iter()
+        .enumerate()
+        .map(|(index, power)| {
+            let power = power.strip_prefix(b"0x").unwrap_or(*power);
+            let bytes = hex::decode(power).map_err(|_| crate::error::Error::MalformedTranscript)?;
+            decode(&bytes).ok_or(crate::error::Error::TranscriptPointNotInSubgroup { group, index })
+        })
+        .collect()
+}
+
+// Get the next byte of a JSON document which is not a whitespace
+fn next_token(json: &[u8], position: &mut usize) -> Result<u8, crate::error::Error> {
+    let offset = json[*position..]
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .ok_or(crate::error::Error::TruncatedTranscript)?;
+    *position += offset + 1;
+    Ok(json[*position - 1])
+}
+
+// Skip the next byte of a JSON document which is not a whitespace, it must be the expected one
+fn expect_token(
+    json: &[u8],
+    position: &mut usize,
+    expected: u8,
+) -> Result<(), crate::error::Error> {
+    match next_token(json, position)? {
+        byte if byte == expected => Ok(()),
+        _ => Err(crate::error::Error::MalformedTranscript),
+    }
+}
+
+// Get the arrays of strings following every occurrence of the key in a JSON document,
+// the rest of the document is not parsed
+fn json_string_arrays<'a>(
+    json: &'a [u8],
+    key: &[u8],
+) -> Result<Vec<Vec<&'a [u8]>>, crate::error::Error> {
+    let mut arrays = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = json[position..]
+        .windows(key.len())
+        .position(|window| window == key)
+    {
+        position += offset + key.len();
+        expect_token(json, &mut position, b':')?;
+        expect_token(json, &mut position, b'[')?;
+        let mut array = Vec::new();
+        loop {
+            match next_token(json, &mut position)? {
+                b']' if array.is_empty() => break,
+                b'"' => {
+                    let end = json[position..]
+                        .iter()
+                        .position(|byte| *byte == b'"')
+                        .ok_or(crate::error::Error::TruncatedTranscript)?;
+                    array.push(&json[position..position + end]);
+                    position += end + 1;
+                }
+                _ => return Err(crate::error::Error::MalformedTranscript),
+            }
+            match next_token(json, &mut position)? {
+                b',' => continue,
+                b']' => break,
+                _ => return Err(crate::error::Error::MalformedTranscript),
+            }
+        }
+        arrays.push(array);
+    }
+    Ok(arrays)
+}
+
 /// A KZG module that commit to the memory trace through the execution trace
 #[derive(Debug, Clone)]
 pub struct KZGMemoryCommitment<K, V, const S: usize, const T: usize>
@@ -65,7 +413,7 @@ where
 {
     fn default() -> Self {
         // K = 3 since we need the poly degree to be 2^3 = 8
-        Self::new(3)
+        Self::new_insecure_random(3)
     }
 }
 
@@ -76,11 +424,23 @@ where
     halo2_proofs::halo2curves::bn256::Fr: From<K>,
     halo2_proofs::halo2curves::bn256::Fr: From<V>,
 {
-    /// Initialize KZG parameters
-    pub fn new(k: u32) -> Self {
+    /// Initialize the scheme with random KZG parameters, see
+    /// [KZGParams::generate_insecure_random]
+    pub fn new_insecure_random(k: u32) -> Self {
+        Self::from_params(KZGParams::generate_insecure_random(k))
+    }
+
+    /// Initialize the scheme with the KZG parameters of the seed, the same seed gives the same
+    /// commitments, see [KZGParams::setup_with_seed]
+    pub fn setup_with_seed(k: u32, seed: [u8; 32]) -> Self {
+        Self::from_params(KZGParams::setup_with_seed(k, seed))
+    }
+
+    /// Create the scheme with the given params, e.g. loaded with [KZGParams::load_or_generate]
+    pub fn from_params(params: KZGParams) -> Self {
         Self {
-            kzg_params: ParamsKZG::<Bn256>::new(k),
-            domain: EvaluationDomain::new(1, k),
+            domain: EvaluationDomain::new(1, params.k()),
+            kzg_params: params.0,
             phantom_data: PhantomData,
         }
     }
@@ -88,39 +448,78 @@ where
     /// Commit a trace record in an execution trace
     /// This function, given input a trace record,
     /// outputs the commitment of the trace
-    pub fn commit(&mut self, trace: TraceRecord<K, V, S, T>) -> G1Affine {
+    pub fn commit(&self, trace: TraceRecord<K, V, S, T>) -> G1Affine {
         self.kzg_params
             .commit(&self.poly_from_trace(trace), Blind(Fr::random(OsRng)))
             .to_affine()
     }
 
+    /// Commit the polynomial of the streamed coefficients, from the constant one, without
+    /// holding the polynomial in memory. The coefficients are committed by chunks and the
+    /// commitments of the chunks are summed into the commitment of the whole polynomial.
+    /// Return [Error::KZGStreamTooLong](crate::error::Error::KZGStreamTooLong) if there are
+    /// more coefficients than powers in the params
+    pub fn commit_stream(
+        &self,
+        coefficients: impl IntoIterator<Item = Fr>,
+    ) -> Result<G1Affine, crate::error::Error> {
+        let bases = self.kzg_params.get_g();
+        let mut coefficients = coefficients.into_iter();
+        let mut commitment = G1::identity();
+        let mut offset = 0;
+        loop {
+            let chunk: Vec<Fr> = coefficients
+                .by_ref()
+                .take(COMMITMENT_STREAM_CHUNK)
+                .collect();
+            if chunk.is_empty() {
+                return Ok(commitment.to_affine());
+            }
+            let end = offset + chunk.len();
+            if end > bases.len() {
+                return Err(crate::error::Error::KZGStreamTooLong {
+                    capacity: bases.len(),
+                });
+            }
+            commitment += best_multiexp(&chunk, &bases[offset..end]);
+            offset = end;
+        }
+    }
+
+    /// Commit all trace records of an execution trace,
+    /// the cancellation token is checked between chunks of records
+    pub fn commit_trace_with_cancellation(
+        &mut self,
+        trace: impl IntoIterator<Item = TraceRecord<K, V, S, T>>,
+        token: &CancellationToken,
+    ) -> Result<Vec<G1Affine>, crate::error::Error> {
+        let trace = trace.into_iter();
+        let mut commitments = Vec::with_capacity(trace.size_hint().0);
+        for (index, record) in trace.enumerate() {
+            if index % COMMITMENT_CHUNK_SIZE == 0 {
+                token.check()?;
+            }
+            commitments.push(self.commit(record));
+        }
+        Ok(commitments)
+    }
+
     // Convert a trace record to 8 field elements
     // The last 3 elements will be ZERO
     fn trace_to_field(&self, trace: TraceRecord<K, V, S, T>) -> [Fr; 8] {
         let (time_log, stack_depth, instruction, address, value) = trace.get_tuple();
-        // Encode instruction to number : 1 for Write, 0 for Read
-        match instruction {
-            MemoryInstruction::Read => [
-                Fr::from(time_log),
-                Fr::from(stack_depth),
-                Fr::ZERO,
-                Fr::from(address),
-                Fr::from(value),
-                Fr::ZERO,
-                Fr::ZERO,
-                Fr::ZERO,
-            ],
-            MemoryInstruction::Write => [
-                Fr::from(time_log),
-                Fr::from(stack_depth),
-                Fr::ONE,
-                Fr::from(address),
-                Fr::from(value),
-                Fr::ZERO,
-                Fr::ZERO,
-                Fr::ZERO,
-            ],
-        }
+        // Encode the instruction with the codes of the trace formats and the circuits, see
+        // [MemoryInstruction::code]
+        [
+            Fr::from(time_log),
+            Fr::from(stack_depth),
+            Fr::from(instruction.code()),
+            Fr::from(address),
+            Fr::from(value),
+            Fr::ZERO,
+            Fr::ZERO,
+            Fr::ZERO,
+        ]
     }
 
     // Convert the trace record into a polynomial
@@ -198,7 +597,7 @@ where
     // This function, given the list of points x_1,x_2,...,x_n,
     // a list of openings p_1(x_1),p_2(x_2),...,p_n(x_n)
     // and a list of commitment c_1,c_2,..c_n
-    // then returns True or False to determine the correctness of the opening.
+    // then returns the reason of the rejection if the opening is not correct.
     // Used as a misc function to help verifying the trace record
     fn verify_kzg_proof<
         'a,
@@ -219,24 +618,71 @@ where
         commitments: Vec<Scheme::Curve>,
         // The proof of opening
         proof: &'a [u8],
-    ) -> bool {
+    ) -> Result<(), VerifyError> {
+        let strategy = self.accumulate_kzg_proof::<Scheme, Vr, E, Tr, Strategy>(
+            params,
+            points_list,
+            eval,
+            commitments,
+            proof,
+            Strategy::new(params),
+        )?;
+        if strategy.finalize() {
+            Ok(())
+        } else {
+            Err(VerifyError::PairingCheckFailed)
+        }
+    }
+
+    // Add the check of KZG openings to the accumulated checks of a strategy,
+    // the accumulated checks are finalized with a single pairing.
+    // This function returns VerifyError::DeserializeError if the proof is malformed
+    // and VerifyError::EvaluationMismatch if it does not open the given evaluations
+    // of the given commitments
+    fn accumulate_kzg_proof<
+        'a,
+        'params,
+        Scheme: CommitmentScheme,
+        Vr: Verifier<'params, Scheme>,
+        E: EncodedChallenge<Scheme::Curve>,
+        Tr: TranscriptReadBuffer<&'a [u8], Scheme::Curve, E>,
+        Strategy: VerificationStrategy<'params, Scheme, Vr, Output = Strategy>,
+    >(
+        &self,
+        params: &'params Scheme::ParamsVerifier,
+        // A list of points x_1,x_2,...x_n
+        points_list: Vec<Scheme::Scalar>,
+        // The evaluation of p_1(x_1),p_2(x_2),...,p_n(x_n)
+        eval: Vec<Scheme::Scalar>,
+        // The commitments of the polynomials p_1(x),p_2(x),...,p_n(x)
+        commitments: Vec<Scheme::Curve>,
+        // The proof of opening
+        proof: &'a [u8],
+        // The checks accumulated so far
+        strategy: Strategy,
+    ) -> Result<Strategy, VerifyError> {
         let verifier = Vr::new(params);
         let mut transcript = Tr::init(proof);
         let mut check = true;
         let mut eval_list = Vec::new();
         let mut queries = Vec::new();
 
-        let commitment_list: Vec<<Scheme as CommitmentScheme>::Curve> = points_list
+        let commitment_list = points_list
             .iter()
-            .map(|_| transcript.read_point().expect("Unable to read point"))
-            .collect();
+            .map(|_| transcript.read_point())
+            .collect::<Result<Vec<<Scheme as CommitmentScheme>::Curve>, _>>()
+            .map_err(|_| VerifyError::DeserializeError)?;
 
         for (i, point) in points_list.iter().enumerate() {
             // Check if commitment list input matches the commitment list from the Prover's proof
             check = check && (commitments[i] == commitment_list[i]);
 
             // Read the eval list from transcript
-            eval_list.push(transcript.read_scalar().expect("Unable to read scalar"));
+            eval_list.push(
+                transcript
+                    .read_scalar()
+                    .map_err(|_| VerifyError::DeserializeError)?,
+            );
 
             // Check if eval list input matches the eval list from the Prover's proof
             check = check && (eval[i] == eval_list[i]);
@@ -248,16 +694,17 @@ where
             ));
         }
 
-        // Apply the verify function from SHPLONK to return the result
-        check
-            && Strategy::new(params)
-                .process(|msm_accumulator| {
-                    verifier
-                        .verify_proof(&mut transcript, queries, msm_accumulator)
-                        .map_err(|_| Error::Opening)
-                })
-                .expect("Unable to verify proof")
-                .finalize()
+        // Apply the verify function from SHPLONK to accumulate the check
+        if !check {
+            return Err(VerifyError::EvaluationMismatch);
+        }
+        strategy
+            .process(|msm_accumulator| {
+                verifier
+                    .verify_proof(&mut transcript, queries, msm_accumulator)
+                    .map_err(|_| Error::Opening)
+            })
+            .map_err(|_| VerifyError::DeserializeError)
     }
 
     /// Open all fields from the trace record
@@ -296,13 +743,13 @@ where
     /// Verify the correctness of the trace record.
     /// This function, given input a trace record,
     /// it commitment and the proof of correctness opening,
-    /// returns True or False to determine the correctness of the opening
+    /// returns the reason of the rejection if the opening is not correct
     pub fn verify_trace_record(
         &self,
         trace: TraceRecord<K, V, S, T>,
         commitment: <KZGCommitmentScheme<Bn256> as CommitmentScheme>::Curve,
         proof: Vec<u8>,
-    ) -> bool {
+    ) -> Result<(), VerifyError> {
         // Create the commitment list of the trace
         let commitment_list = vec![commitment; 5];
 
@@ -321,15 +768,679 @@ where
         commitment_list,
         proof.as_slice())
     }
+
+    /// Open the records of a trace at the given indices with a single proof, the opened
+    /// records are given with their commitments in the order of the indices.
+    /// Return [Error::OpeningOutOfRange](crate::error::Error::OpeningOutOfRange) if an index
+    /// is out of the trace or of its commitments
+    pub fn open_batch(
+        &self,
+        trace: &[TraceRecord<K, V, S, T>],
+        commitment: &[G1Affine],
+        indices: &[usize],
+    ) -> Result<BatchOpening<K, V, S, T>, crate::error::Error> {
+        let len = trace.len().min(commitment.len());
+        let opened = indices
+            .iter()
+            .map(|index| {
+                trace
+                    .get(*index)
+                    .zip(commitment.get(*index))
+                    .ok_or(crate::error::Error::OpeningOutOfRange { index: *index, len })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let records: Vec<TraceRecord<K, V, S, T>> =
+            opened.iter().map(|(record, _)| **record).collect();
+        if records.is_empty() {
+            return Ok(BatchOpening {
+                indices: Vec::new(),
+                records,
+                proof: Vec::new(),
+            });
+        }
+        // Every record is opened at the same points as in [Self::prove_trace_record]
+        let mut points_list = Vec::new();
+        let mut polynomial_list = Vec::new();
+        let mut commitment_list = Vec::new();
+        for (record, commitment) in &opened {
+            let poly = self.poly_from_trace(**record);
+            for point in &OMEGA_POWER[0..5] {
+                points_list.push(*point);
+                polynomial_list.push(poly.clone());
+                commitment_list.push(**commitment);
+            }
+        }
+        let proof = self.create_kzg_proof::<
+        KZGCommitmentScheme<Bn256>,
+        ProverSHPLONK<'_,Bn256>,
+        Challenge255<G1Affine>,
+        Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>>(
+        &self.kzg_params,
+        points_list,
+        polynomial_list,
+        commitment_list);
+        Ok(BatchOpening {
+            indices: indices.to_vec(),
+            records,
+            proof,
+        })
+    }
+
+    /// Verify the batch opening of records of a trace against the commitments of the trace.
+    /// The challenges of the proof are derived from its transcript. An opening without one
+    /// record per index, or with a proof but no record, is rejected as malformed
+    pub fn verify_batch(
+        &self,
+        commitment: &[G1Affine],
+        opening: &BatchOpening<K, V, S, T>,
+    ) -> Result<(), VerifyError> {
+        if opening.indices.len() != opening.records.len()
+            || (opening.indices.is_empty() && !opening.proof.is_empty())
+        {
+            return Err(VerifyError::DeserializeError);
+        }
+        if opening.indices.is_empty() {
+            return Ok(());
+        }
+        let mut points_list = Vec::new();
+        let mut eval = Vec::new();
+        let mut commitment_list = Vec::new();
+        for (index, record) in opening.indices.iter().zip(&opening.records) {
+            let Some(committed) = commitment.get(*index) else {
+                return Err(VerifyError::IndexOutOfRange {
+                    index: *index,
+                    len: commitment.len(),
+                });
+            };
+            let evals = self.trace_to_field(*record);
+            for (point, value) in OMEGA_POWER[0..5].iter().zip(evals) {
+                points_list.push(*point);
+                eval.push(value);
+                commitment_list.push(*committed);
+            }
+        }
+        self.verify_kzg_proof::<
+        KZGCommitmentScheme<Bn256>,
+        VerifierSHPLONK<'_,Bn256>,
+        Challenge255<G1Affine>,
+        Blake2bRead<&'_[u8], G1Affine, Challenge255<G1Affine>>,
+        AccumulatorStrategy<'_,Bn256>,
+        >(&self.kzg_params, points_list,
+        eval,
+        commitment_list,
+        opening.proof.as_slice())
+    }
+
+    /// Commit a whole trace to a single polynomial, the record of index i is laid out on the
+    /// points `omega^(8i)` to `omega^(8i + 7)` of the domain of the params like the record of
+    /// [Self::commit] on its own points. Return
+    /// [Error::KZGParamsTooSmall](crate::error::Error::KZGParamsTooSmall) if the domain has
+    /// less than 8 points per record
+    pub fn commit_trace(
+        &self,
+        records: &[ConvertedTraceRecord<Fr>],
+    ) -> Result<KZGTraceCommitment, crate::error::Error> {
+        let poly = self.poly_from_records(records)?;
+        Ok(KZGTraceCommitment {
+            commitment: self
+                .kzg_params
+                .commit(&poly, Blind(Fr::random(OsRng)))
+                .to_affine(),
+            len: records.len(),
+        })
+    }
+
+    /// Open the record of the index of a trace committed by [Self::commit_trace], get the
+    /// time log, the stack depth which is zero like in the witness form, the instruction, the
+    /// address and the value of the record with the proof of their opening. Return
+    /// [Error::OpeningOutOfRange](crate::error::Error::OpeningOutOfRange) if the trace has no
+    /// record at the index
+    pub fn open_at_index(
+        &self,
+        records: &[ConvertedTraceRecord<Fr>],
+        index: usize,
+    ) -> Result<([Fr; 5], Vec<u8>), crate::error::Error> {
+        let record = records
+            .get(index)
+            .ok_or(crate::error::Error::OpeningOutOfRange {
+                index,
+                len: records.len(),
+            })?;
+        let fields = self.record_fields(record)?;
+        let poly = self.poly_from_records(records)?;
+        let commitment = self
+            .kzg_params
+            .commit(&poly, Blind(Fr::random(OsRng)))
+            .to_affine();
+        let proof = self.create_kzg_proof::<
+        KZGCommitmentScheme<Bn256>,
+        ProverSHPLONK<'_,Bn256>,
+        Challenge255<G1Affine>,
+        Blake2bWrite<Vec<u8>, G1Affine, Challenge255<G1Affine>>>(
+        &self.kzg_params,
+        self.index_points(index),
+        vec![poly; 5],
+        vec![commitment; 5]);
+        Ok((fields, proof))
+    }
+
+    /// Verify the opening of the record of the index of a trace committed by
+    /// [Self::commit_trace]. An index out of the trace is rejected with
+    /// [VerifyError::IndexOutOfRange] and a trace with more records than the domain holds
+    /// with [VerifyError::ParameterMismatch]
+    pub fn verify_index(
+        &self,
+        commitment: &KZGTraceCommitment,
+        index: usize,
+        fields: &[Fr; 5],
+        proof: &[u8],
+    ) -> Result<(), VerifyError> {
+        if index >= commitment.len {
+            return Err(VerifyError::IndexOutOfRange {
+                index,
+                len: commitment.len,
+            });
+        }
+        if commitment.len > self.domain_size() / OMEGA_POWER.len() {
+            return Err(VerifyError::ParameterMismatch);
+        }
+        self.verify_kzg_proof::<
+        KZGCommitmentScheme<Bn256>,
+        VerifierSHPLONK<'_,Bn256>,
+        Challenge255<G1Affine>,
+        Blake2bRead<&'_[u8], G1Affine, Challenge255<G1Affine>>,
+        AccumulatorStrategy<'_,Bn256>,
+        >(&self.kzg_params, self.index_points(index),
+        fields.to_vec(),
+        vec![commitment.commitment; 5],
+        proof)
+    }
+
+    /// Commit the records of a chunk with [Self::commit_trace] and open the records of the
+    /// indices in the chunk. The state roots of its boundaries are the roots of
+    /// [commit_image](crate::commitment::merkle::commit_image) of its entry and exit state.
+    /// Return [Error::OpeningOutOfRange](crate::error::Error::OpeningOutOfRange) if the chunk
+    /// has no record at an index and
+    /// [Error::WitnessWordTooWide](crate::error::Error::WitnessWordTooWide) if its words are
+    /// wider than 256 bits
+    pub fn prove_chunk(
+        &self,
+        chunk: &TraceChunk<K, V, S, T>,
+        indices: &[usize],
+    ) -> Result<ChunkProofBundle, crate::error::Error> {
+        let records: Vec<ConvertedTraceRecord<Fr>> = chunk
+            .records
+            .iter()
+            .map(ConvertedTraceRecord::from_trace_record)
+            .collect::<Result<_, _>>()?;
+        let openings = indices
+            .iter()
+            .map(|index| {
+                let record = records
+                    .get(*index)
+                    .ok_or(crate::error::Error::OpeningOutOfRange {
+                        index: *index,
+                        len: records.len(),
+                    })?;
+                Ok((*index, self.record_fields(record)?))
+            })
+            .collect::<Result<Vec<_>, crate::error::Error>>()?;
+        Ok(ChunkProofBundle {
+            claim: ChunkClaim {
+                entry_root: commit_image(&chunk.entry_state),
+                exit_root: commit_image(&chunk.exit_state),
+                commitment: self.commit_trace(&records)?,
+                openings,
+            },
+            records,
+        })
+    }
+
+    /// Aggregate the openings of the chunks of a trace into a single proof. The claims of
+    /// every chunk, its state roots, its commitment and its opened fields, are absorbed by one
+    /// transcript and the SHPLONK opening of all of them combines the openings with random
+    /// challenges of that transcript. See [Self::commit_trace] for the errors
+    pub fn aggregate_chunk_proofs(
+        &self,
+        bundles: Vec<ChunkProofBundle>,
+    ) -> Result<AggregateProof, crate::error::Error> {
+        let polys = bundles
+            .iter()
+            .map(|bundle| self.poly_from_records(&bundle.records))
+            .collect::<Result<Vec<_>, _>>()?;
+        let chunks: Vec<ChunkClaim> = bundles.into_iter().map(|bundle| bundle.claim).collect();
+        let blind = Blind::new(&mut OsRng);
+        let queries: Vec<ProverQuery<'_, G1Affine>> = chunks
+            .iter()
+            .zip(&polys)
+            .flat_map(|(chunk, poly)| {
+                chunk.openings.iter().flat_map(move |(index, _)| {
+                    self.index_points(*index)
+                        .into_iter()
+                        .map(move |point| ProverQuery::new(point, poly, blind))
+                })
+            })
+            .collect();
+        if queries.is_empty() {
+            return Ok(AggregateProof {
+                chunks,
+                proof: Vec::new(),
+            });
+        }
+        let mut transcript =
+            Blake2bWrite::<Vec<u8>, G1Affine, Challenge255<G1Affine>>::init(Vec::new());
+        absorb_chunks(&mut transcript, &chunks).expect("Unable to write scalar to transcript");
+        ProverSHPLONK::<'_, Bn256>::new(&self.kzg_params)
+            .create_proof(&mut OsRng, &mut transcript, queries)
+            .expect("Unable to create proof");
+        Ok(AggregateProof {
+            chunks,
+            proof: transcript.finalize(),
+        })
+    }
+
+    /// Verify an aggregate of [Self::aggregate_chunk_proofs], every chunk must start from the
+    /// exit root of the previous chunk and the proof must open the claimed fields of every
+    /// chunk. Return [Error::ChunkBoundaryMismatch](crate::error::Error::ChunkBoundaryMismatch)
+    /// for the first chunk whose exit root is not the entry root of the next chunk and
+    /// [Error::AggregateProofRejected](crate::error::Error::AggregateProofRejected) if the
+    /// proof does not open the claims
+    pub fn verify_aggregate(&self, aggregate: &AggregateProof) -> Result<(), crate::error::Error> {
+        if let Some(index) = aggregate
+            .chunks
+            .windows(2)
+            .position(|pair| pair[0].exit_root != pair[1].entry_root)
+        {
+            return Err(crate::error::Error::ChunkBoundaryMismatch { index });
+        }
+        let in_range = aggregate.chunks.iter().all(|chunk| {
+            chunk.commitment.len <= self.domain_size() / OMEGA_POWER.len()
+                && chunk
+                    .openings
+                    .iter()
+                    .all(|(index, _)| *index < chunk.commitment.len)
+        });
+        if !in_range {
+            return Err(crate::error::Error::AggregateProofRejected);
+        }
+        let mut queries = Vec::new();
+        for chunk in &aggregate.chunks {
+            for (index, fields) in &chunk.openings {
+                for (point, eval) in self.index_points(*index).into_iter().zip(fields) {
+                    queries.push(VerifierQuery::new_commitment(
+                        &chunk.commitment.commitment,
+                        point,
+                        *eval,
+                    ));
+                }
+            }
+        }
+        if queries.is_empty() {
+            if aggregate.proof.is_empty() {
+                return Ok(());
+            }
+            return Err(crate::error::Error::AggregateProofRejected);
+        }
+        let mut transcript = Blake2bRead::<&[u8], G1Affine, Challenge255<G1Affine>>::init(
+            aggregate.proof.as_slice(),
+        );
+        absorb_chunks(&mut transcript, &aggregate.chunks)
+            .ok_or(crate::error::Error::AggregateProofRejected)?;
+        let verifier = VerifierSHPLONK::<'_, Bn256>::new(&self.kzg_params);
+        let strategy = VerificationStrategy::<
+            '_,
+            KZGCommitmentScheme<Bn256>,
+            VerifierSHPLONK<'_, Bn256>,
+        >::process(AccumulatorStrategy::new(&self.kzg_params), |msm| {
+            verifier
+                .verify_proof(&mut transcript, queries, msm)
+                .map_err(|_| Error::Opening)
+        });
+        let finalize = VerificationStrategy::<
+            '_,
+            KZGCommitmentScheme<Bn256>,
+            VerifierSHPLONK<'_, Bn256>,
+        >::finalize;
+        if strategy.is_ok_and(finalize) {
+            return Ok(());
+        }
+        Err(crate::error::Error::AggregateProofRejected)
+    }
+
+    // Get the time log, the stack depth, the instruction, the address and the value of a
+    // record in the witness form
+    fn record_fields(
+        &self,
+        record: &ConvertedTraceRecord<Fr>,
+    ) -> Result<[Fr; 5], crate::error::Error> {
+        let mut fields = [Fr::ZERO; 5];
+        fields.copy_from_slice(&self.trace_to_field(record.try_into_trace_record()?)[..5]);
+        Ok(fields)
+    }
+
+    // Get the number of points of the domain of the params
+    fn domain_size(&self) -> usize {
+        1 << self.kzg_params.k()
+    }
+
+    // Get the points of the domain holding the fields of the record of the index
+    fn index_points(&self, index: usize) -> Vec<Fr> {
+        let first = index * OMEGA_POWER.len();
+        (first..first + 5)
+            .map(|point| self.domain.get_omega().pow_vartime([point as u64]))
+            .collect()
+    }
+
+    // Interpolate the polynomial of a trace over the domain, the points after the last
+    // record are zero
+    fn poly_from_records(
+        &self,
+        records: &[ConvertedTraceRecord<Fr>],
+    ) -> Result<Polynomial<Fr, Coeff>, crate::error::Error> {
+        let points = records.len() * OMEGA_POWER.len();
+        if points > self.domain_size() {
+            return Err(crate::error::Error::KZGParamsTooSmall {
+                found: self.kzg_params.k(),
+                requested: points.next_power_of_two().ilog2(),
+            });
+        }
+        let mut evals = vec![Fr::ZERO; self.domain_size()];
+        for (slot, record) in evals.chunks_mut(OMEGA_POWER.len()).zip(records) {
+            slot.copy_from_slice(&self.trace_to_field(record.try_into_trace_record()?));
+        }
+        Ok(self
+            .domain
+            .lagrange_to_coeff(self.domain.lagrange_from_vec(evals)))
+    }
+}
+
+/// Commitment of [KZGMemoryCommitment::commit_trace] to a whole trace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KZGTraceCommitment {
+    /// Commitment to the polynomial of the trace
+    pub commitment: G1Affine,
+    /// Number of records of the trace
+    pub len: usize,
+}
+
+/// Claims of a chunk of a trace in an [AggregateProof]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkClaim {
+    /// Root of the state of the memory before the first record of the chunk
+    pub entry_root: MerkleRoot,
+    /// Root of the state of the memory after the last record of the chunk
+    pub exit_root: MerkleRoot,
+    /// Commitment to the records of the chunk
+    pub commitment: KZGTraceCommitment,
+    /// Opened records by their index in the chunk, see [KZGMemoryCommitment::open_at_index]
+    pub openings: Vec<(usize, [Fr; 5])>,
+}
+
+/// Claims of a chunk with its records, made by [KZGMemoryCommitment::prove_chunk] for
+/// [KZGMemoryCommitment::aggregate_chunk_proofs]
+#[derive(Debug, Clone)]
+pub struct ChunkProofBundle {
+    /// Claims of the chunk
+    pub claim: ChunkClaim,
+    /// Records of the chunk in the witness form
+    pub records: Vec<ConvertedTraceRecord<Fr>>,
+}
+
+/// Openings of the chunks of a trace checked with a single proof, see
+/// [KZGMemoryCommitment::verify_aggregate]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AggregateProof {
+    /// Claims of the chunks in time order
+    pub chunks: Vec<ChunkClaim>,
+    /// Proof of the openings of all chunks, empty if no record is opened
+    pub proof: Vec<u8>,
+}
+
+// Absorb the claims of the chunks of an aggregate in its transcript, None if the transcript
+// fails. The commitments are absorbed as the limbs of their compressed encoding, so the
+// commitment to an empty chunk is absorbed like any other
+fn absorb_chunks<Tr: Transcript<G1Affine, Challenge255<G1Affine>>>(
+    transcript: &mut Tr,
+    chunks: &[ChunkClaim],
+) -> Option<()> {
+    transcript
+        .common_scalar(Fr::from(chunks.len() as u64))
+        .ok()?;
+    for chunk in chunks {
+        let mut commitment = [0u8; 32];
+        commitment.copy_from_slice(chunk.commitment.commitment.to_bytes().as_ref());
+        let mut scalars = vec![Fr::from(chunk.commitment.len as u64)];
+        for bytes in [&chunk.entry_root, &chunk.exit_root, &commitment] {
+            scalars.extend(to_limbs::<Fr>(bytes));
+        }
+        for (index, fields) in &chunk.openings {
+            scalars.push(Fr::from(*index as u64));
+            scalars.extend(fields);
+        }
+        for scalar in scalars {
+            transcript.common_scalar(scalar).ok()?;
+        }
+    }
+    Some(())
+}
+
+/// Opening of several records of a trace committed by [KZGMemoryCommitment] with a single
+/// proof, see [KZGMemoryCommitment::open_batch]
+#[derive(Debug, Clone)]
+pub struct BatchOpening<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Indices of the opened records in the trace
+    pub indices: Vec<usize>,
+    /// The opened records, in the order of the indices
+    pub records: Vec<TraceRecord<K, V, S, T>>,
+    /// Proof of the openings, the challenges are derived from its Blake2b transcript
+    pub proof: Vec<u8>,
+}
+
+/// Opening of a record of a trace committed by [KZGMemoryCommitment] as a
+/// [CommitmentScheme](crate::commitment::CommitmentScheme)
+#[derive(Debug, Clone)]
+pub struct KZGOpening<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Index of the record in the trace
+    pub index: usize,
+    /// The opened record, its stack depth is zero like in the witness form
+    pub record: TraceRecord<K, V, S, T>,
+    /// Proof of the opening, see [KZGMemoryCommitment::prove_trace_record]
+    pub proof: Vec<u8>,
+}
+
+// Commit the polynomials of the evaluations of records like [KZGMemoryCommitment::commit],
+// on all cores with the `parallel` feature. The MSM and the FFT of halo2 use its own threads
+fn commit_evals(
+    params: &ParamsKZG<Bn256>,
+    domain: &EvaluationDomain<Fr>,
+    evals: &[[Fr; 8]],
+) -> Vec<G1Affine> {
+    let commit = |evals: &[Fr; 8]| {
+        let poly = domain.coeff_from_vec(lagrange_interpolate(&OMEGA_POWER, evals));
+        params.commit(&poly, Blind(Fr::random(OsRng))).to_affine()
+    };
+    #[cfg(feature = "parallel")]
+    if evals.len() >= PARALLEL_MIN_RECORDS {
+        return evals.par_iter().map(commit).collect();
+    }
+    evals.iter().map(commit).collect()
+}
+
+// Every record is committed on its own, the commitment to a trace is the list of the
+// commitments to its records
+impl<K, V, const S: usize, const T: usize> crate::commitment::CommitmentScheme<Fr>
+    for KZGMemoryCommitment<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+    halo2_proofs::halo2curves::bn256::Fr: From<K>,
+    halo2_proofs::halo2curves::bn256::Fr: From<V>,
+{
+    type Commitment = Vec<G1Affine>;
+    type Opening = KZGOpening<K, V, S, T>;
+    /// The k and the seed of the KZG parameters, see [KZGMemoryCommitment::setup_with_seed]
+    type PublicParams = (u32, [u8; 32]);
+
+    fn setup((k, seed): (u32, [u8; 32])) -> Self {
+        Self::setup_with_seed(k, seed)
+    }
+
+    fn commit(
+        &self,
+        records: &[ConvertedTraceRecord<Fr>],
+    ) -> Result<Vec<G1Affine>, crate::error::Error> {
+        let evals = records
+            .iter()
+            .map(|record| Ok(self.trace_to_field(record.try_into_trace_record()?)))
+            .collect::<Result<Vec<[Fr; 8]>, crate::error::Error>>()?;
+        Ok(commit_evals(&self.kzg_params, &self.domain, &evals))
+    }
+
+    fn open(
+        &self,
+        records: &[ConvertedTraceRecord<Fr>],
+        index: usize,
+    ) -> Result<KZGOpening<K, V, S, T>, crate::error::Error> {
+        let record = records
+            .get(index)
+            .ok_or(crate::error::Error::OpeningOutOfRange {
+                index,
+                len: records.len(),
+            })?
+            .try_into_trace_record()?;
+        Ok(KZGOpening {
+            index,
+            record,
+            proof: self.prove_trace_record(record, self.commit(record)),
+        })
+    }
+
+    fn verify(
+        &self,
+        commitment: &Vec<G1Affine>,
+        opening: &KZGOpening<K, V, S, T>,
+    ) -> Result<(), VerifyError> {
+        let committed = commitment
+            .get(opening.index)
+            .ok_or(VerifyError::IndexOutOfRange {
+                index: opening.index,
+                len: commitment.len(),
+            })?;
+        self.verify_trace_record(opening.record, *committed, opening.proof.clone())
+    }
+
+    // The proofs are accumulated with random scalars and checked with a single pairing,
+    // the openings are verified one by one to find the rejected one if the check fails
+    fn verify_many(
+        &self,
+        commitment: &Vec<G1Affine>,
+        openings: &[KZGOpening<K, V, S, T>],
+    ) -> Result<(), crate::error::Error> {
+        let mut strategy = Some(AccumulatorStrategy::new(&self.kzg_params));
+        for opening in openings {
+            let (Some(accumulated), Some(committed)) = (strategy, commitment.get(opening.index))
+            else {
+                strategy = None;
+                break;
+            };
+            strategy = self.accumulate_kzg_proof::<
+            KZGCommitmentScheme<Bn256>,
+            VerifierSHPLONK<'_,Bn256>,
+            Challenge255<G1Affine>,
+            Blake2bRead<&'_[u8], G1Affine, Challenge255<G1Affine>>,
+            AccumulatorStrategy<'_,Bn256>,
+            >(&self.kzg_params, OMEGA_POWER[0..5].to_vec(),
+            Vec::from(self.trace_to_field(opening.record)),
+            vec![*committed; 5],
+            opening.proof.as_slice(),
+            accumulated).ok();
+        }
+        // The accumulator is a strategy of every KZG verifier, name the one of the proofs
+        let finalize = VerificationStrategy::<
+            '_,
+            KZGCommitmentScheme<Bn256>,
+            VerifierSHPLONK<'_, Bn256>,
+        >::finalize;
+        if strategy.is_some_and(finalize) {
+            return Ok(());
+        }
+        match openings.iter().position(|opening| {
+            crate::commitment::CommitmentScheme::verify(self, commitment, opening).is_err()
+        }) {
+            Some(index) => Err(crate::error::Error::BatchOpeningRejected { index }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The witness is the evaluations of the polynomial of the record at the powers of omega,
+/// the time log, the stack depth, the instruction, the address, the value and three zeros.
+/// The public inputs are the index of the record then the two limbs of the x and of the y
+/// coordinate of its commitment. The commitment is the sum of the commitments to the
+/// Lagrange basis weighted by the evaluations, so a circuit checks it without the proof
+impl<K, V, const S: usize, const T: usize> Extractor<Fr> for KZGMemoryCommitment<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+    halo2_proofs::halo2curves::bn256::Fr: From<K>,
+    halo2_proofs::halo2curves::bn256::Fr: From<V>,
+{
+    fn extract(
+        &self,
+        commitment: &Vec<G1Affine>,
+        opening: &KZGOpening<K, V, S, T>,
+    ) -> Result<OpeningWitness<Fr>, crate::error::Error> {
+        let committed =
+            commitment
+                .get(opening.index)
+                .ok_or(crate::error::Error::OpeningOutOfRange {
+                    index: opening.index,
+                    len: commitment.len(),
+                })?;
+        let mut public_inputs = vec![Fr::from(opening.index as u64)];
+        public_inputs.extend(coordinate_limbs(committed.x));
+        public_inputs.extend(coordinate_limbs(committed.y));
+        Ok(OpeningWitness {
+            witness: self.trace_to_field(opening.record).to_vec(),
+            public_inputs,
+        })
+    }
+}
+
+// Split a coordinate of a point into two limbs of 128 bits, its representation is little endian
+fn coordinate_limbs(coordinate: Fq) -> [Fr; 2] {
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(coordinate.to_repr().as_ref());
+    bytes.reverse();
+    to_limbs(&bytes)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{base::B256, machine::AbstractTraceRecord};
-    use ff::PrimeField;
+    use crate::{
+        base::B256,
+        commitment::CommitmentScheme as _,
+        constraints::gadgets::InstructionCode,
+        machine::{chunk::split_trace_into_chunks, AbstractTraceRecord, MemoryInstruction},
+    };
+    use alloc::{format, string::String};
+    use core::iter::successors;
+    use group::prime::PrimeCurveAffine;
+    use halo2_proofs::halo2curves::bn256::Fq2;
     use rand::{thread_rng, Rng};
 
+    // Seed of the KZG params of the tests
+    const SEED: [u8; 32] = [7; 32];
+
     // Generate a trace record
     fn generate_trace_record() -> TraceRecord<B256, B256, 32, 32> {
         let mut rng = rand::thread_rng();
@@ -370,7 +1481,7 @@ mod test {
     }
     #[test]
     fn test_record_polynomial_conversion() {
-        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::default();
+        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(3, SEED);
 
         // Initialize a random trace record
         let trace = generate_trace_record();
@@ -388,11 +1499,30 @@ mod test {
             assert_eq!(base_index, OMEGA_POWER[i]);
             base_index *= Fr::MULTIPLICATIVE_GENERATOR;
         }
+
+        // The instruction is encoded like in the circuits, a push and a pop keep their own
+        // code since the commitment does not fold them
+        for instruction in [
+            MemoryInstruction::Read,
+            MemoryInstruction::Write,
+            MemoryInstruction::Push,
+            MemoryInstruction::Pop,
+        ] {
+            let record = TraceRecord::new(0, 0, instruction, B256::from(0), B256::from(0));
+            let code = InstructionCode::from(instruction);
+            assert_eq!(
+                kzg_scheme.trace_to_field(record)[2],
+                Fr::from(instruction.code())
+            );
+            if MemoryInstruction::from(code) == instruction {
+                assert_eq!(kzg_scheme.trace_to_field(record)[2], code.to_field());
+            }
+        }
     }
 
     #[test]
     fn test_correct_trace_opening() {
-        let mut kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::default();
+        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(3, SEED);
 
         // Initialize a random trace record
         let trace = generate_trace_record();
@@ -404,13 +1534,22 @@ mod test {
         let proof = kzg_scheme.prove_trace_record(trace, commitment);
 
         //Verify the correctness of the trace, should return True
-        assert!(kzg_scheme.verify_trace_record(trace, commitment, proof));
+        assert_eq!(
+            kzg_scheme.verify_trace_record(trace, commitment, proof.clone()),
+            Ok(())
+        );
+
+        // A truncated proof cannot be read
+        assert_eq!(
+            kzg_scheme.verify_trace_record(trace, commitment, proof[..40].to_vec()),
+            Err(VerifyError::DeserializeError)
+        );
     }
 
     // Check that two different trace records cannot have the same commitment
     #[test]
     fn test_false_trace_opening() {
-        let mut kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::default();
+        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(3, SEED);
 
         // Initialize a random trace record
         let trace = generate_trace_record();
@@ -423,7 +1562,554 @@ mod test {
         let false_trace = generate_trace_record();
         let false_proof = kzg_scheme.prove_trace_record(false_trace, commitment);
 
-        // Verify the correctness of the false trace given the commitment "commitment", should return False
-        assert!(!kzg_scheme.verify_trace_record(false_trace, commitment, false_proof));
+        // Verify the correctness of the false trace given the commitment "commitment", should
+        // fail the pairing check since the proof opens the false trace
+        assert_eq!(
+            kzg_scheme.verify_trace_record(false_trace, commitment, false_proof.clone()),
+            Err(VerifyError::PairingCheckFailed)
+        );
+        // The opened values must be the ones of the proof
+        assert_eq!(
+            kzg_scheme.verify_trace_record(trace, commitment, false_proof),
+            Err(VerifyError::EvaluationMismatch)
+        );
+    }
+
+    // Check an extracted witness against its public inputs like a circuit would, by summing
+    // the commitments to the Lagrange basis weighted by the evaluations
+    fn check_witness(
+        kzg_scheme: &KZGMemoryCommitment<B256, B256, 32, 32>,
+        extracted: &OpeningWitness<Fr>,
+    ) -> bool {
+        let commitment = extracted
+            .witness
+            .iter()
+            .enumerate()
+            .fold(G1::identity(), |sum, (i, eval)| {
+                let mut basis = [Fr::ZERO; 8];
+                basis[i] = Fr::ONE;
+                let basis = kzg_scheme
+                    .kzg_params
+                    .commit(&kzg_scheme.poly_from_evals(basis), Blind(Fr::ONE));
+                sum + basis * eval
+            })
+            .to_affine();
+        let mut expected = coordinate_limbs(commitment.x).to_vec();
+        expected.extend(coordinate_limbs(commitment.y));
+        extracted.witness.len() == 8 && extracted.public_inputs[1..] == expected[..]
+    }
+
+    #[test]
+    fn test_extract_witness() {
+        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(3, SEED);
+        let records: Vec<ConvertedTraceRecord<Fr>> = (0..3)
+            .map(|_| ConvertedTraceRecord::from_trace_record(&generate_trace_record()).unwrap())
+            .collect();
+        let commitments: Vec<G1Affine> = records
+            .iter()
+            .map(|record| kzg_scheme.commit(record.try_into_trace_record().unwrap()))
+            .collect();
+        for index in 0..records.len() {
+            let opening = kzg_scheme.open(&records, index).unwrap();
+            assert_eq!(kzg_scheme.verify(&commitments, &opening), Ok(()));
+            let extracted = kzg_scheme.extract(&commitments, &opening).unwrap();
+            assert_eq!(extracted.public_inputs.len(), 5);
+            assert_eq!(extracted.public_inputs[0], Fr::from(index as u64));
+            assert!(check_witness(&kzg_scheme, &extracted));
+
+            // A changed value does not re-verify
+            let mut tampered = extracted.clone();
+            tampered.witness[4] += Fr::ONE;
+            assert!(!check_witness(&kzg_scheme, &tampered));
+        }
+
+        let opening = kzg_scheme.open(&records, 2).unwrap();
+        assert_eq!(
+            kzg_scheme.verify(&commitments[..2].to_vec(), &opening),
+            Err(VerifyError::IndexOutOfRange { index: 2, len: 2 })
+        );
+        let extracted = kzg_scheme.extract(&commitments[..2], &opening);
+        assert!(matches!(
+            extracted,
+            Err(crate::error::Error::OpeningOutOfRange { index: 2, len: 2 })
+        ));
+        // The commitment of another record does not re-verify
+        let mut swapped = commitments.clone();
+        swapped.swap(1, 2);
+        let extracted = kzg_scheme.extract(&swapped, &opening).unwrap();
+        assert!(!check_witness(&kzg_scheme, &extracted));
+    }
+
+    #[test]
+    fn test_commit_stream() {
+        let mut rng = thread_rng();
+        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::from_params(
+            KZGParams::setup_with_seed(13, SEED),
+        );
+        for len in [0, 1, 8, COMMITMENT_STREAM_CHUNK + 3, 1 << 13] {
+            let coefficients: Vec<Fr> = (0..len).map(|_| Fr::random(&mut rng)).collect();
+            let mut poly = kzg_scheme.domain.empty_coeff();
+            poly[..len].copy_from_slice(&coefficients);
+            let expected = kzg_scheme
+                .kzg_params
+                .commit(&poly, Blind(Fr::ONE))
+                .to_affine();
+            assert_eq!(kzg_scheme.commit_stream(coefficients), Ok(expected));
+        }
+
+        // The polynomial of a record is committed like the record
+        let trace = generate_trace_record();
+        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(3, SEED);
+        let poly = kzg_scheme.poly_from_trace(trace);
+        assert_eq!(
+            kzg_scheme.commit_stream(poly.iter().copied()),
+            Ok(kzg_scheme.commit(trace))
+        );
+        assert_eq!(
+            kzg_scheme.commit_stream(core::iter::repeat(Fr::ONE).take(9)),
+            Err(crate::error::Error::KZGStreamTooLong { capacity: 8 })
+        );
+    }
+
+    #[test]
+    fn test_batch_opening() {
+        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(3, SEED);
+        let trace: Vec<TraceRecord<B256, B256, 32, 32>> =
+            (0..100).map(|_| generate_trace_record()).collect();
+        let commitments: Vec<G1Affine> = trace
+            .iter()
+            .map(|record| kzg_scheme.commit(*record))
+            .collect();
+
+        for indices in [vec![7], vec![3, 42], (0..100).rev().collect()] {
+            let opening = kzg_scheme
+                .open_batch(&trace, &commitments, &indices)
+                .expect("The indices are in the trace");
+            assert_eq!(opening.indices, indices);
+            assert_eq!(kzg_scheme.verify_batch(&commitments, &opening), Ok(()));
+
+            // A tampered claimed value is rejected
+            let mut tampered = opening.clone();
+            let last = tampered.records.len() - 1;
+            let (time_log, stack_depth, instruction, address, value) =
+                tampered.records[last].get_tuple();
+            tampered.records[last] = TraceRecord::new(
+                time_log,
+                stack_depth,
+                instruction,
+                address,
+                if value.is_zero() {
+                    B256::from(1)
+                } else {
+                    B256::zero()
+                },
+            );
+            assert_eq!(
+                kzg_scheme.verify_batch(&commitments, &tampered),
+                Err(VerifyError::EvaluationMismatch)
+            );
+
+            // An index without a record is malformed
+            let mut malformed = opening.clone();
+            malformed.indices.push(0);
+            assert_eq!(
+                kzg_scheme.verify_batch(&commitments, &malformed),
+                Err(VerifyError::DeserializeError)
+            );
+            assert_eq!(
+                kzg_scheme.verify_batch(&commitments[..3], &opening),
+                Err(VerifyError::IndexOutOfRange {
+                    index: *indices.iter().find(|index| **index >= 3).unwrap(),
+                    len: 3
+                })
+            );
+        }
+
+        assert_eq!(
+            kzg_scheme
+                .open_batch(&trace, &commitments[..10], &[3, 10])
+                .map(|opening| opening.indices),
+            Err(crate::error::Error::OpeningOutOfRange { index: 10, len: 10 })
+        );
+    }
+
+    // Write the JSON transcript of a ceremony with the given secret, with a sub-ceremony
+    // for every number of powers in G1
+    fn ceremony_transcript(tau: Fr, sizes: &[usize]) -> String {
+        let powers = |size| successors(Some(Fr::ONE), |power| Some(*power * tau)).take(size);
+        let quote = |bytes: &[u8]| format!("\"0x{}\"", hex::encode(bytes));
+        let g2: Vec<String> = powers(3)
+            .map(|power| (G2Affine::generator() * power).to_affine())
+            .map(|point| quote(point.to_bytes().as_ref()))
+            .collect();
+        let sub_ceremonies: Vec<String> = sizes
+            .iter()
+            .map(|size| {
+                let g1: Vec<String> = powers(*size)
+                    .map(|power| (G1Affine::generator() * power).to_affine())
+                    .map(|point| quote(point.to_bytes().as_ref()))
+                    .collect();
+                let powers_of_tau = format!(
+                    r#"{{"G1Powers": [{}], "G2Powers": [{}]}}"#,
+                    g1.join(", "),
+                    g2.join(",\n")
+                );
+                format!(
+                    r#"{{"numG1Powers": {}, "powersOfTau": {}}}"#,
+                    size, powers_of_tau
+                )
+            })
+            .collect();
+        format!(r#"{{"transcripts": [{}]}}"#, sub_ceremonies.join(", "))
+    }
+
+    #[test]
+    fn test_ceremony_transcript() {
+        let tau = Fr::from(5);
+        let json = ceremony_transcript(tau, &[4, 8]);
+        assert_eq!(
+            KZGParams::from_ceremony_json(json.as_bytes(), 2)
+                .unwrap()
+                .k(),
+            2
+        );
+        let params = KZGParams::from_ceremony_json(json.as_bytes(), 3).unwrap();
+        assert_eq!(params.k(), 3);
+
+        // The commitment of a record is its polynomial evaluated at tau
+        let scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::from_params(params);
+        let trace = generate_trace_record();
+        let commitment = scheme.commit(trace);
+        let evaluation = eval_polynomial(&scheme.poly_from_trace(trace), tau);
+        assert_eq!(commitment, (G1Affine::generator() * evaluation).to_affine());
+        let proof = scheme.prove_trace_record(trace, commitment);
+        assert_eq!(scheme.verify_trace_record(trace, commitment, proof), Ok(()));
+
+        assert!(matches!(
+            KZGParams::from_ceremony_json(json.as_bytes(), 4),
+            Err(crate::error::Error::KZGParamsTooSmall {
+                found: 3,
+                requested: 4
+            })
+        ));
+        assert!(matches!(
+            KZGParams::from_ceremony_json(&json.as_bytes()[..json.len() - 40], 3),
+            Err(crate::error::Error::TruncatedTranscript)
+        ));
+        let malformed = json.replacen("0x", "0xzz", 1);
+        assert!(matches!(
+            KZGParams::from_ceremony_json(malformed.as_bytes(), 2),
+            Err(crate::error::Error::MalformedTranscript)
+        ));
+
+        // A point of the twist outside of the subgroup is rejected
+        let generator = G2Affine::generator();
+        let b = generator.y.square() - generator.x.square() * generator.x;
+        let point = (1..)
+            .find_map(|x| {
+                let x = Fq2 {
+                    c0: Fq::from(x),
+                    c1: Fq::ONE,
+                };
+                Option::from((x.square() * x + b).sqrt()).map(|y| G2Affine { x, y })
+            })
+            .unwrap();
+        let tau_g2 = (generator * tau).to_affine();
+        let outside = json.replace(
+            &hex::encode(tau_g2.to_bytes().as_ref()),
+            &hex::encode(point.to_bytes().as_ref()),
+        );
+        assert!(matches!(
+            KZGParams::from_ceremony_json(outside.as_bytes(), 3),
+            Err(crate::error::Error::TranscriptPointNotInSubgroup { group: 2, index: 1 })
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_params_round_trip() {
+        let params = KZGParams::setup_with_seed(3, SEED);
+        let mut bytes = Vec::new();
+        params.write(&mut bytes).unwrap();
+        let read = KZGParams::read(bytes.as_slice()).unwrap();
+        assert_eq!(read.k(), 3);
+
+        // The same params give the same commitments and proofs
+        let trace = generate_trace_record();
+        let original = KZGMemoryCommitment::<B256, B256, 32, 32>::from_params(params);
+        let restored = KZGMemoryCommitment::<B256, B256, 32, 32>::from_params(read);
+        let commitment = original.commit(trace);
+        assert_eq!(restored.commit(trace), commitment);
+        let proof = restored.prove_trace_record(trace, commitment);
+        assert_eq!(
+            original.verify_trace_record(trace, commitment, proof),
+            Ok(())
+        );
+
+        // A corrupted, truncated or extended file is rejected
+        let mut flipped = bytes.clone();
+        flipped[bytes.len() / 2] ^= 1;
+        let mut magic = bytes.clone();
+        magic[0] ^= 1;
+        let mut extended = bytes.clone();
+        extended.push(0);
+        for corrupted in [flipped, magic, extended, bytes[..bytes.len() - 1].to_vec()] {
+            assert!(matches!(
+                KZGParams::read(corrupted.as_slice()),
+                Err(crate::error::Error::CorruptedKZGParams)
+            ));
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_load_or_generate_params() {
+        let path =
+            std::env::temp_dir().join(std::format!("zkmemory-kzg-{}.srs", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let generated = KZGParams::load_or_generate(&path, 4).unwrap();
+        let loaded = KZGParams::load_or_generate(&path, 4).unwrap();
+        let bytes = |params: &KZGParams| {
+            let mut bytes = Vec::new();
+            params.write(&mut bytes).unwrap();
+            bytes
+        };
+        assert_eq!(loaded.k(), 4);
+        assert_eq!(bytes(&loaded), bytes(&generated));
+
+        // Larger params are downsized to the 8 points of a record, smaller ones are rejected
+        let trace = generate_trace_record();
+        let commit = |params: KZGParams| {
+            KZGMemoryCommitment::<B256, B256, 32, 32>::from_params(params).commit(trace)
+        };
+        let mut downsized = generated;
+        downsized.downsize(3).unwrap();
+        let loaded = KZGParams::load_or_generate(&path, 3).unwrap();
+        assert_eq!(loaded.k(), 3);
+        assert_eq!(commit(loaded), commit(downsized));
+        assert!(matches!(
+            KZGParams::load_or_generate(&path, 5),
+            Err(crate::error::Error::KZGParamsTooSmall {
+                found: 4,
+                requested: 5
+            })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cancel_trace_commitment() {
+        let mut kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(3, SEED);
+        let trace: Vec<TraceRecord<B256, B256, 32, 32>> =
+            (0..100).map(|_| generate_trace_record()).collect();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        assert_eq!(
+            kzg_scheme.commit_trace_with_cancellation(trace.iter().copied(), &token),
+            Err(crate::error::Error::Cancelled)
+        );
+
+        // A cancellation requested during the work stops it at the next chunk
+        let token = CancellationToken::new();
+        let consumed = core::cell::Cell::new(0);
+        let records = trace.iter().map(|record| {
+            consumed.set(consumed.get() + 1);
+            if consumed.get() == 10 {
+                token.cancel();
+            }
+            *record
+        });
+        assert_eq!(
+            kzg_scheme.commit_trace_with_cancellation(records, &token),
+            Err(crate::error::Error::Cancelled)
+        );
+        assert_eq!(consumed.get(), COMMITMENT_CHUNK_SIZE + 1);
+
+        // The scheme is left in a usable state after the cancellation
+        let commitments = kzg_scheme
+            .commit_trace_with_cancellation(trace.iter().copied(), &CancellationToken::new())
+            .expect("Commitment must not be cancelled");
+        assert_eq!(commitments.len(), trace.len());
+        let proof = kzg_scheme.prove_trace_record(trace[99], commitments[99]);
+        assert_eq!(
+            kzg_scheme.verify_trace_record(trace[99], commitments[99], proof),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_open_at_index() {
+        // 16 records of 8 points fill the domain of k = 7
+        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(7, SEED);
+        let records: Vec<ConvertedTraceRecord<Fr>> = (0..16)
+            .map(|_| ConvertedTraceRecord::from_trace_record(&generate_trace_record()).unwrap())
+            .collect();
+        let commitment = kzg_scheme.commit_trace(&records).unwrap();
+        assert_eq!(commitment.len, 16);
+
+        for (index, record) in records.iter().enumerate() {
+            let (fields, proof) = kzg_scheme.open_at_index(&records, index).unwrap();
+            let expected = kzg_scheme.trace_to_field(record.try_into_trace_record().unwrap());
+            assert_eq!(fields[..], expected[..5]);
+            assert_eq!(
+                kzg_scheme.verify_index(&commitment, index, &fields, &proof),
+                Ok(())
+            );
+
+            // The fields are bound to the index and to their values
+            let other = (index + 1) % records.len();
+            assert_eq!(
+                kzg_scheme.verify_index(&commitment, other, &fields, &proof),
+                Err(VerifyError::PairingCheckFailed)
+            );
+            let mut tampered = fields;
+            tampered[4] += Fr::ONE;
+            assert_eq!(
+                kzg_scheme.verify_index(&commitment, index, &tampered, &proof),
+                Err(VerifyError::EvaluationMismatch)
+            );
+        }
+
+        // The index after the last record is out of range
+        assert_eq!(
+            kzg_scheme.open_at_index(&records, 16),
+            Err(crate::error::Error::OpeningOutOfRange { index: 16, len: 16 })
+        );
+        let (fields, proof) = kzg_scheme.open_at_index(&records, 15).unwrap();
+        assert_eq!(
+            kzg_scheme.verify_index(&commitment, 16, &fields, &proof),
+            Err(VerifyError::IndexOutOfRange { index: 16, len: 16 })
+        );
+        let longer = KZGTraceCommitment {
+            len: 17,
+            ..commitment
+        };
+        assert_eq!(
+            kzg_scheme.verify_index(&longer, 15, &fields, &proof),
+            Err(VerifyError::ParameterMismatch)
+        );
+
+        // A record more does not fit in the domain
+        let mut records = records;
+        let first = records[0].clone();
+        records.push(first);
+        assert_eq!(
+            kzg_scheme.commit_trace(&records),
+            Err(crate::error::Error::KZGParamsTooSmall {
+                found: 7,
+                requested: 8
+            })
+        );
+    }
+
+    #[test]
+    fn test_aggregate_chunk_proofs() {
+        // Chunks of 8 records of 8 points fill the domain of k = 6
+        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(6, SEED);
+        let trace: Vec<TraceRecord<B256, B256, 32, 32>> =
+            (0..24).map(|_| generate_trace_record()).collect();
+        let bundles: Vec<ChunkProofBundle> = split_trace_into_chunks(&trace, 8)
+            .iter()
+            .map(|chunk| kzg_scheme.prove_chunk(chunk, &[0, 3, 7]).unwrap())
+            .collect();
+        assert_eq!(bundles.len(), 3);
+        let aggregate = kzg_scheme.aggregate_chunk_proofs(bundles.clone()).unwrap();
+        assert_eq!(kzg_scheme.verify_aggregate(&aggregate), Ok(()));
+        let rejected = Err(crate::error::Error::AggregateProofRejected);
+
+        for index in 0..3 {
+            // A changed field, commitment or record of any chunk is rejected
+            let mut tampered = aggregate.clone();
+            tampered.chunks[index].openings[1].1[3] += Fr::ONE;
+            assert_eq!(kzg_scheme.verify_aggregate(&tampered), rejected);
+            let mut tampered = aggregate.clone();
+            let commitment = &mut tampered.chunks[index].commitment.commitment;
+            *commitment = (*commitment + G1Affine::generator()).to_affine();
+            assert_eq!(kzg_scheme.verify_aggregate(&tampered), rejected);
+            let mut tampered = aggregate.clone();
+            tampered.chunks[index].openings[2].0 = 6;
+            assert_eq!(kzg_scheme.verify_aggregate(&tampered), rejected);
+            let mut corrupted = bundles.clone();
+            corrupted[index].records.swap(0, 3);
+            let corrupted = kzg_scheme.aggregate_chunk_proofs(corrupted).unwrap();
+            assert_eq!(kzg_scheme.verify_aggregate(&corrupted), rejected);
+        }
+
+        // Every chunk starts from the exit state of the previous one
+        for index in 0..2 {
+            let mut tampered = aggregate.clone();
+            tampered.chunks[index].exit_root[0] ^= 1;
+            assert_eq!(
+                kzg_scheme.verify_aggregate(&tampered),
+                Err(crate::error::Error::ChunkBoundaryMismatch { index })
+            );
+        }
+        let mut reordered = aggregate.clone();
+        reordered.chunks.swap(1, 2);
+        assert_eq!(
+            kzg_scheme.verify_aggregate(&reordered),
+            Err(crate::error::Error::ChunkBoundaryMismatch { index: 0 })
+        );
+        let mut dropped = aggregate.clone();
+        dropped.chunks.remove(1);
+        assert_eq!(
+            kzg_scheme.verify_aggregate(&dropped),
+            Err(crate::error::Error::ChunkBoundaryMismatch { index: 0 })
+        );
+
+        // The outer roots are bound by the transcript
+        let mut tampered = aggregate.clone();
+        tampered.chunks[0].entry_root[0] ^= 1;
+        assert_eq!(kzg_scheme.verify_aggregate(&tampered), rejected);
+        let mut tampered = aggregate;
+        tampered.chunks[2].exit_root[0] ^= 1;
+        assert_eq!(kzg_scheme.verify_aggregate(&tampered), rejected);
+    }
+
+    #[test]
+    fn test_seeded_setup() {
+        use crate::commitment::CommitmentScheme;
+
+        let records: Vec<ConvertedTraceRecord<Fr>> = (0..4)
+            .map(|_| ConvertedTraceRecord::from_trace_record(&generate_trace_record()).unwrap())
+            .collect();
+        let commit = |seed: [u8; 32]| {
+            let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(3, seed);
+            CommitmentScheme::commit(&kzg_scheme, &records).unwrap()
+        };
+        assert_eq!(commit(SEED), commit(SEED));
+        let mut other = SEED;
+        other[0] ^= 1;
+        assert_ne!(commit(other), commit(SEED));
+
+        // The setup of the trait is seeded
+        let kzg_scheme =
+            <KZGMemoryCommitment<B256, B256, 32, 32> as CommitmentScheme<Fr>>::setup((3, SEED));
+        assert_eq!(
+            CommitmentScheme::commit(&kzg_scheme, &records),
+            Ok(commit(SEED))
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_commit() {
+        let kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(3, SEED);
+        let trace: Vec<TraceRecord<B256, B256, 32, 32>> = (0..PARALLEL_MIN_RECORDS * 4 + 1)
+            .map(|_| generate_trace_record())
+            .collect();
+        let records: Vec<ConvertedTraceRecord<Fr>> = trace
+            .iter()
+            .map(|record| ConvertedTraceRecord::from_trace_record(record).unwrap())
+            .collect();
+        let serial: Vec<G1Affine> = trace
+            .iter()
+            .map(|record| kzg_scheme.commit(*record))
+            .collect();
+        assert_eq!(
+            crate::commitment::CommitmentScheme::commit(&kzg_scheme, &records),
+            Ok(serial)
+        );
     }
 }
@@ -0,0 +1,2092 @@
+//! Merkle tree commitment to the memory image.
+//! Every cell holding a non zero value is a leaf, the leaves are ordered by address and
+//! hashed with BLAKE2b. Cells holding zero are left out, so an image is committed like the
+//! memory it describes where the cells that were never written are read as zero.
+//! The last node of a level with an odd number of nodes is moved up unchanged.
+//! [MerkleTraceCommitment] commits a list of trace records with the same tree, the leaves
+//! are the records in their order and a node has up to [MAX_MERKLE_ARITY] children, two by
+//! default. A [MerkleProof] is the opening of a leaf without its record, it has a versioned
+//! wire format to be sent to a remote verifier.
+//! The nodes are hashed with BLAKE2b by default, [HashKind::Poseidon] hashes them with the
+//! Poseidon hash of [poseidon](crate::commitment::poseidon) to check the openings in a circuit
+//! and [HashKind::Keccak256] hashes them with Keccak256 to check the proofs on the EVM.
+//! [MerkleTree] keeps the levels of a tree so a leaf is updated by hashing its path only,
+//! a [MerkleUpdateProof] proves such an update between the roots before and after it.
+extern crate alloc;
+use crate::{
+    base::Base,
+    commitment::{
+        extractor::{to_limbs, Extractor, OpeningWitness},
+        poseidon,
+        selector::{CommitmentKind, StateCommitter},
+        store::{Node, NodeStore},
+        CommitmentScheme,
+    },
+    constraints::gadgets::ConvertedTraceRecord,
+    error::{Error, VerifyError},
+};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use blake2b_simd::Params;
+use core::{iter::once, ops::Range};
+use ff::{Field, FromUniformBytes, PrimeField};
+use halo2_proofs::halo2curves::bn256::Fr;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use tiny_keccak::{Hasher, Keccak};
+
+/// Root of the Merkle tree of a memory image
+pub type MerkleRoot = [u8; 32];
+
+/// Maximum number of cells walked by [CommitmentBackend::Dense]
+pub const DENSE_COMMITMENT_MAX_CELLS: u64 = 1 << 20;
+
+/// Minimum number of nodes of a level, or records of a list, hashed in parallel with the
+/// `parallel` feature, the smaller levels are hashed on the current thread
+pub const PARALLEL_MIN_NODES: usize = 1 << 10;
+
+/// Version of the wire format of [MerkleProof]
+pub const MERKLE_PROOF_VERSION: u8 = 3;
+
+/// Maximum number of children of a node of [MerkleTraceCommitment], the path of a leaf of a
+/// list of up to `2^64` leaves then has at most 240 siblings
+pub const MAX_MERKLE_ARITY: usize = 16;
+
+/// Walk of the memory image committed by [commit_image], both walks give the same root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitmentBackend {
+    /// Commit the stored cells
+    Sparse,
+    /// Commit every cell from the lowest to the highest stored cell, the cells that were never
+    /// written are committed as zero. The walk is limited to [DENSE_COMMITMENT_MAX_CELLS] cells
+    Dense,
+}
+
+impl CommitmentBackend {
+    /// Commit the cells of a memory with the given cell size, the cells must be sorted by
+    /// address. Return [Error::DenseCommitmentTooLarge] if the dense walk is too long
+    pub fn commit<K, V, const S: usize, const T: usize>(
+        &self,
+        cells: &[(K, V)],
+        word_size: K,
+    ) -> Result<MerkleRoot, Error>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        let committer = StateCommitter::new(CommitmentKind::default())?;
+        self.commit_with(&committer, cells, word_size)
+    }
+
+    /// Commit the cells of a memory like [CommitmentBackend::commit] with the backend of a
+    /// [CommitmentKind], see [StateCommitter::commit_image] for its errors
+    pub fn commit_with<K, V, const S: usize, const T: usize>(
+        &self,
+        committer: &StateCommitter,
+        cells: &[(K, V)],
+        word_size: K,
+    ) -> Result<MerkleRoot, Error>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        let (low, high) = match (self, cells.first(), cells.last()) {
+            (CommitmentBackend::Dense, Some((low, _)), Some((high, _))) => (*low, *high),
+            _ => return committer.commit_image(cells),
+        };
+        if (high - low) / word_size >= K::from(DENSE_COMMITMENT_MAX_CELLS) {
+            return Err(Error::DenseCommitmentTooLarge);
+        }
+        let mut stored = cells.iter().peekable();
+        let mut dense = Vec::new();
+        let mut address = low;
+        loop {
+            let value = match stored.next_if(|(cell, _)| *cell == address) {
+                Some((_, value)) => *value,
+                None => V::zero(),
+            };
+            dense.push((address, value));
+            if address >= high {
+                break;
+            }
+            address = address + word_size;
+        }
+        committer.commit_image(&dense)
+    }
+}
+
+// Personalization of every hash of the tree
+const PERSONAL: &[u8] = b"zkmemory-merkle";
+
+// Domain separation of the leaves, the inner nodes, the empty tree and the lists of records
+const LEAF_TAG: u8 = 0;
+const NODE_TAG: u8 = 1;
+const EMPTY_TAG: u8 = 2;
+const LIST_TAG: u8 = 3;
+
+// Hash the tag followed by the parts
+fn hash(tag: u8, parts: &[&[u8]]) -> MerkleRoot {
+    let mut state = Params::new().hash_length(32).personal(PERSONAL).to_state();
+    state.update(&[tag]);
+    for part in parts {
+        state.update(part);
+    }
+    let mut digest = [0u8; 32];
+    digest.copy_from_slice(state.finalize().as_bytes());
+    digest
+}
+
+// Hash the concatenated parts with Keccak256
+fn keccak(parts: &[&[u8]]) -> MerkleRoot {
+    let mut hasher = Keccak::v256();
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut digest = [0u8; 32];
+    hasher.finalize(&mut digest);
+    digest
+}
+
+// Pad big endian bytes of at most 32 bytes to a word of 32 bytes, like a uint256 of Solidity
+fn evm_word(bytes: &[u8]) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[32 - bytes.len()..].copy_from_slice(bytes);
+    word
+}
+
+// Get the capacity element of a Poseidon sponge, the tag and the number of inputs
+fn poseidon_capacity(tag: u8, inputs: usize) -> Fr {
+    Fr::from_u128(((inputs as u128) << 64) | u128::from(tag))
+}
+
+// Read a node of a Poseidon tree, reduced modulo the order of the field
+fn poseidon_node(node: &MerkleRoot) -> Fr {
+    let mut bytes = [0u8; 64];
+    bytes[..32].copy_from_slice(node);
+    Fr::from_uniform_bytes(&bytes)
+}
+
+/// Hash of the nodes of the Merkle trees
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashKind {
+    /// BLAKE2b of the bytes of the nodes
+    #[default]
+    Blake2b,
+    /// Poseidon over the scalar field of bn256, the nodes are the little endian
+    /// representations of field elements. An inner node is the Poseidon hash of circomlib of
+    /// its children. A leaf is the sponge of its inputs, with the number of inputs times
+    /// `2^64` as capacity element, a record absorbs its limbs in the order address, time log,
+    /// instruction and value, every limb by its little endian representation, a cell absorbs
+    /// its address then its value as big endian chunks of 128 bits. The empty tree is the
+    /// sponge of no input with 2 as capacity element and a list binds the root to its length
+    /// with the sponge of both with 3 as capacity element. An inner node of more than two
+    /// children is the sponge of its children, with their number times `2^64` plus one as
+    /// capacity element
+    Poseidon,
+    /// Keccak256 without domain separation, so the trees are checked with the `keccak256` of
+    /// Solidity. Every word is padded to 32 big endian bytes like a `uint256`. A cell is
+    /// `keccak256(address || value)`, an inner node is `keccak256(left || right)` and a node
+    /// of more than two children is the Keccak256 of its concatenated children. A record is
+    /// the Keccak256 of its limbs in the order address, time log, instruction and value,
+    /// every limb by its canonical representation as a word. The empty tree is the Keccak256
+    /// of no input and a list is `keccak256(root || len)`.
+    /// The vectors of `tests/vectors/keccak256_merkle.txt` check an implementation
+    Keccak256,
+}
+
+impl HashKind {
+    // Hash two children into their parent
+    pub(crate) fn node(&self, left: &MerkleRoot, right: &MerkleRoot) -> MerkleRoot {
+        match self {
+            HashKind::Blake2b => hash(NODE_TAG, &[left, right]),
+            HashKind::Poseidon => {
+                poseidon::hash_two(poseidon_node(left), poseidon_node(right)).to_repr()
+            }
+            HashKind::Keccak256 => keccak(&[left, right]),
+        }
+    }
+
+    // Hash the children of a node into their parent, a single child is moved up unchanged
+    pub(crate) fn parent(&self, children: &[MerkleRoot]) -> MerkleRoot {
+        match (self, children) {
+            (_, [child]) => *child,
+            (_, [left, right]) => self.node(left, right),
+            (HashKind::Blake2b, _) => {
+                let parts: Vec<&[u8]> = children.iter().map(|child| child.as_slice()).collect();
+                hash(NODE_TAG, &parts)
+            }
+            (HashKind::Keccak256, _) => {
+                let parts: Vec<&[u8]> = children.iter().map(|child| child.as_slice()).collect();
+                keccak(&parts)
+            }
+            (HashKind::Poseidon, _) => {
+                let inputs: Vec<Fr> = children.iter().map(poseidon_node).collect();
+                let capacity = poseidon_capacity(NODE_TAG, inputs.len());
+                poseidon::sponge(capacity, &inputs).to_repr()
+            }
+        }
+    }
+
+    // Get the root of the empty tree
+    pub(crate) fn empty(&self) -> MerkleRoot {
+        match self {
+            HashKind::Blake2b => hash(EMPTY_TAG, &[]),
+            HashKind::Poseidon => poseidon::sponge(poseidon_capacity(EMPTY_TAG, 0), &[]).to_repr(),
+            HashKind::Keccak256 => keccak(&[]),
+        }
+    }
+
+    // Hash a cell into a leaf
+    pub(crate) fn cell_leaf(&self, address: &[u8], value: &[u8]) -> MerkleRoot {
+        match self {
+            HashKind::Blake2b => hash(LEAF_TAG, &[address, value]),
+            HashKind::Poseidon => {
+                let inputs: Vec<Fr> = address
+                    .chunks(16)
+                    .chain(value.chunks(16))
+                    .map(|chunk| {
+                        let mut bytes = [0u8; 16];
+                        bytes[16 - chunk.len()..].copy_from_slice(chunk);
+                        Fr::from_u128(u128::from_be_bytes(bytes))
+                    })
+                    .collect();
+                let capacity = poseidon_capacity(LEAF_TAG, inputs.len());
+                poseidon::sponge(capacity, &inputs).to_repr()
+            }
+            HashKind::Keccak256 => keccak(&[&evm_word(address), &evm_word(value)]),
+        }
+    }
+
+    // Hash a record into a leaf, every limb by its canonical representation
+    fn record_leaf<F: Field + PrimeField>(&self, record: &ConvertedTraceRecord<F>) -> MerkleRoot {
+        let limbs = record
+            .address
+            .iter()
+            .chain(record.time_log.iter())
+            .chain(once(&record.instruction))
+            .chain(record.value.iter());
+        match self {
+            HashKind::Blake2b => {
+                let reprs: Vec<F::Repr> = limbs.map(|limb| limb.to_repr()).collect();
+                let parts: Vec<&[u8]> = reprs.iter().map(|repr| repr.as_ref()).collect();
+                hash(LEAF_TAG, &parts)
+            }
+            HashKind::Poseidon => {
+                let inputs: Vec<Fr> = limbs.map(poseidon::to_fr).collect();
+                let capacity = poseidon_capacity(LEAF_TAG, inputs.len());
+                poseidon::sponge(capacity, &inputs).to_repr()
+            }
+            HashKind::Keccak256 => {
+                // The representations of the fields of the crate are little endian
+                let words: Vec<[u8; 32]> = limbs
+                    .map(|limb| {
+                        let mut repr = limb.to_repr().as_ref().to_vec();
+                        repr.reverse();
+                        evm_word(&repr)
+                    })
+                    .collect();
+                let parts: Vec<&[u8]> = words.iter().map(|word| word.as_slice()).collect();
+                keccak(&parts)
+            }
+        }
+    }
+
+    // Bind the root of the tree of a list to the length of the list
+    fn list(&self, root: &MerkleRoot, len: usize) -> MerkleRoot {
+        match self {
+            HashKind::Blake2b => hash(LIST_TAG, &[root, &(len as u64).to_be_bytes()]),
+            HashKind::Poseidon => {
+                let inputs = [poseidon_node(root), Fr::from(len as u64)];
+                poseidon::sponge(poseidon_capacity(LIST_TAG, 0), &inputs).to_repr()
+            }
+            HashKind::Keccak256 => keccak(&[root, &evm_word(&(len as u64).to_be_bytes())]),
+        }
+    }
+
+    // Hash the groups of nodes of a level, the last group holds the remaining nodes and a
+    // single remaining node is moved up unchanged
+    fn next_level(&self, arity: usize, level: &[MerkleRoot]) -> Vec<MerkleRoot> {
+        #[cfg(feature = "parallel")]
+        if level.len() >= PARALLEL_MIN_NODES {
+            return level
+                .par_chunks(arity)
+                .map(|children| self.parent(children))
+                .collect();
+        }
+        level
+            .chunks(arity)
+            .map(|children| self.parent(children))
+            .collect()
+    }
+
+    // Compute the root of the tree of the leaves
+    fn tree_root(&self, arity: usize, mut level: Vec<MerkleRoot>) -> MerkleRoot {
+        if level.is_empty() {
+            return self.empty();
+        }
+        while level.len() > 1 {
+            level = self.next_level(arity, &level);
+        }
+        level[0]
+    }
+
+    // Compute the root of the tree of the streamed leaves, the nodes of every level which
+    // wait for the rest of their group are the only nodes kept
+    fn stream_root(
+        &self,
+        arity: usize,
+        leaves: impl IntoIterator<Item = MerkleRoot>,
+    ) -> MerkleRoot {
+        let mut pending: Vec<Vec<MerkleRoot>> = Vec::new();
+        for leaf in leaves {
+            let mut node = leaf;
+            let mut level = 0;
+            loop {
+                if level == pending.len() {
+                    pending.push(Vec::with_capacity(arity));
+                }
+                pending[level].push(node);
+                if pending[level].len() < arity {
+                    break;
+                }
+                node = self.parent(&pending[level]);
+                pending[level].clear();
+                level += 1;
+            }
+        }
+        // The last node of a level ends the group waiting on the level above, it is moved up
+        // unchanged if no node waits there
+        pending
+            .into_iter()
+            .fold(None, |last, mut group| {
+                group.extend(last);
+                (!group.is_empty()).then(|| self.parent(&group))
+            })
+            .unwrap_or_else(|| self.empty())
+    }
+}
+
+/// Compute the root of the Merkle tree of the image, the cells can be given in any order.
+/// If a cell is given more than once its last value is committed
+pub fn commit_image<K, V, const S: usize, const T: usize>(image: &[(K, V)]) -> MerkleRoot
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    commit_image_with(HashKind::Blake2b, image)
+}
+
+/// Compute the root of the Merkle tree of the image with the given hash, see [commit_image]
+pub fn commit_image_with<K, V, const S: usize, const T: usize>(
+    hash: HashKind,
+    image: &[(K, V)],
+) -> MerkleRoot
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let cells: BTreeMap<K, V> = image.iter().copied().collect();
+    hash.tree_root(
+        2,
+        cells
+            .into_iter()
+            .filter(|(_, value)| !value.is_zero())
+            .map(|(address, value)| {
+                let address: [u8; S] = address.into();
+                let value: [u8; T] = value.into();
+                hash.cell_leaf(&address, &value)
+            })
+            .collect(),
+    )
+}
+
+/// Binary Merkle tree of a list of leaves which keeps its levels, so updating a leaf only
+/// hashes the path of the leaf. The root is the root of the same leaves by [commit_image_with]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleTree {
+    hash: HashKind,
+    // Levels of the tree from the leaves up to the root
+    levels: Vec<Vec<MerkleRoot>>,
+}
+
+impl MerkleTree {
+    /// Build the tree of the leaves with the given hash
+    pub fn new(hash: HashKind, leaves: Vec<MerkleRoot>) -> Self {
+        let mut levels = vec![leaves];
+        while levels[levels.len() - 1].len() > 1 {
+            let next = hash.next_level(2, &levels[levels.len() - 1]);
+            levels.push(next);
+        }
+        Self { hash, levels }
+    }
+
+    /// Compute the root of the tree of the leaves without building the tree. A single node
+    /// is kept per level, so the leaves can be streamed from a source larger than the memory
+    pub fn root_from_leaf_iter(
+        hash: HashKind,
+        leaves: impl IntoIterator<Item = MerkleRoot>,
+    ) -> MerkleRoot {
+        hash.stream_root(2, leaves)
+    }
+
+    /// Get the hash of the tree
+    pub fn hash_kind(&self) -> HashKind {
+        self.hash
+    }
+
+    /// Get the number of leaves
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Check whether the tree has no leaf
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    /// Get the leaves in their order
+    pub fn leaves(&self) -> &[MerkleRoot] {
+        &self.levels[0]
+    }
+
+    /// Get the root of the tree
+    pub fn root(&self) -> MerkleRoot {
+        match self.levels[self.levels.len() - 1].first() {
+            Some(root) => *root,
+            None => self.hash.empty(),
+        }
+    }
+
+    /// Replace the leaf of the index and return the new root, only the nodes above the leaf
+    /// are hashed again. Return [Error::LeafOutOfRange] if the tree has no such leaf
+    pub fn update_leaf(&mut self, index: usize, leaf: MerkleRoot) -> Result<MerkleRoot, Error> {
+        if index >= self.len() {
+            return Err(Error::LeafOutOfRange {
+                index,
+                len: self.len(),
+            });
+        }
+        self.levels[0][index] = leaf;
+        let mut node = index / 2;
+        for level in 1..self.levels.len() {
+            let below = &self.levels[level - 1];
+            let parent = match below.get(2 * node + 1) {
+                Some(right) => self.hash.node(&below[2 * node], right),
+                None => below[2 * node],
+            };
+            self.levels[level][node] = parent;
+            node /= 2;
+        }
+        Ok(self.root())
+    }
+
+    /// Prove that replacing the leaf of the index with the new leaf moves the tree from its
+    /// root to the root of [MerkleTree::update_leaf], the tree is not changed. Return
+    /// [Error::LeafOutOfRange] if the tree has no such leaf
+    pub fn prove_update(&self, index: usize, leaf: MerkleRoot) -> Result<MerkleUpdateProof, Error> {
+        if index >= self.len() {
+            return Err(Error::LeafOutOfRange {
+                index,
+                len: self.len(),
+            });
+        }
+        let mut path = Vec::new();
+        let mut node = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(node ^ 1) {
+                path.push(*sibling);
+            }
+            node /= 2;
+        }
+        let (old_leaf, len) = (self.levels[0][index], self.len());
+        let new_root = fold_tree(self.hash, 2, leaf, index, len, &path).map_err(|_| {
+            Error::MerklePathLengthMismatch {
+                expected: path_length(index, len, 2),
+                found: path.len(),
+            }
+        })?;
+        Ok(MerkleUpdateProof {
+            hash: self.hash,
+            index,
+            len,
+            old_leaf,
+            new_leaf: leaf,
+            path,
+            old_root: self.root(),
+            new_root,
+        })
+    }
+
+    /// Put the nodes of the tree with two children in the store, the tree is loaded back from
+    /// its root and its number of leaves by [MerkleTree::load]
+    pub fn save<N: NodeStore>(&self, store: &mut N) -> Result<(), Error> {
+        for level in 1..self.levels.len() {
+            let below = &self.levels[level - 1];
+            for (children, parent) in below.chunks(2).zip(&self.levels[level]) {
+                if let [left, right] = children {
+                    store.put(
+                        *parent,
+                        Node::Branch {
+                            left: *left,
+                            right: *right,
+                        },
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the tree of the root with the number of leaves from a store it was saved to by
+    /// [MerkleTree::save]. Return [Error::MissingMerkleNode] if a node is not in the store
+    pub fn load<N: NodeStore>(
+        hash: HashKind,
+        store: &N,
+        root: MerkleRoot,
+        len: usize,
+    ) -> Result<Self, Error> {
+        if len == 0 {
+            if root != hash.empty() {
+                return Err(Error::MissingMerkleNode);
+            }
+            return Ok(Self::new(hash, Vec::new()));
+        }
+        let mut sizes = vec![len];
+        while sizes[sizes.len() - 1] > 1 {
+            sizes.push(sizes[sizes.len() - 1].div_ceil(2));
+        }
+        let mut levels = vec![vec![root]];
+        for size in sizes.iter().rev().skip(1) {
+            let above = &levels[levels.len() - 1];
+            let mut level = Vec::with_capacity(*size);
+            for (node, parent) in above.iter().enumerate() {
+                // The last node of a level with an odd number of nodes was moved up unchanged
+                if 2 * node + 1 == *size {
+                    level.push(*parent);
+                    continue;
+                }
+                let Some(Node::Branch { left, right }) = store.get(parent)? else {
+                    return Err(Error::MissingMerkleNode);
+                };
+                level.extend([left, right]);
+            }
+            levels.push(level);
+        }
+        levels.reverse();
+        Ok(Self { hash, levels })
+    }
+}
+
+/// Proof that a leaf of a [MerkleTree] changed from an old leaf to a new leaf between two
+/// roots. A single path proves both roots since the siblings of the leaf do not change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleUpdateProof {
+    /// Hash of the tree
+    pub hash: HashKind,
+    /// Index of the leaf in the tree
+    pub index: usize,
+    /// Number of leaves of the tree
+    pub len: usize,
+    /// Leaf before the update
+    pub old_leaf: MerkleRoot,
+    /// Leaf after the update
+    pub new_leaf: MerkleRoot,
+    /// Siblings of the nodes from the leaf up to the root, see [MerkleOpening::path]
+    pub path: Vec<MerkleRoot>,
+    /// Root before the update
+    pub old_root: MerkleRoot,
+    /// Root after the update
+    pub new_root: MerkleRoot,
+}
+
+impl MerkleUpdateProof {
+    /// Check that the path hashes the old leaf to the old root and the new leaf to the new root
+    pub fn verify_update(&self) -> Result<(), VerifyError> {
+        let fold = |leaf| fold_tree(self.hash, 2, leaf, self.index, self.len, &self.path);
+        check_root(&self.old_root, fold(self.old_leaf)?)?;
+        check_root(&self.new_root, fold(self.new_leaf)?)
+    }
+
+    /// Check the proof like [MerkleUpdateProof::verify_update], without the reason of a
+    /// rejection
+    pub fn is_valid(&self) -> bool {
+        self.verify_update().is_ok()
+    }
+}
+
+// Check that the root computed from a proof is the expected root
+pub(crate) fn check_root(expected: &MerkleRoot, computed: MerkleRoot) -> Result<(), VerifyError> {
+    if computed == *expected {
+        Ok(())
+    } else {
+        Err(VerifyError::RootMismatch {
+            expected: *expected,
+            computed,
+        })
+    }
+}
+
+// Get the nodes of a level hashed with the node into their parent
+fn group(node: usize, size: usize, arity: usize) -> Range<usize> {
+    let first = node - node % arity;
+    first..(first + arity).min(size)
+}
+
+// Get the number of siblings on the path of a leaf, a node moved up unchanged has none
+fn path_length(index: usize, len: usize, arity: usize) -> usize {
+    let (mut node, mut size, mut length) = (index, len, 0);
+    while size > 1 {
+        length += group(node, size, arity).len() - 1;
+        node /= arity;
+        size = size.div_ceil(arity);
+    }
+    length
+}
+
+// Hash a leaf with its path up to the root of the tree, the index must be in the tree, the
+// arity between 2 and [MAX_MERKLE_ARITY] and the path must have the siblings of every level
+// of the leaf
+fn fold_tree(
+    hash: HashKind,
+    arity: usize,
+    leaf: MerkleRoot,
+    index: usize,
+    len: usize,
+    path: &[MerkleRoot],
+) -> Result<MerkleRoot, VerifyError> {
+    if index >= len {
+        return Err(VerifyError::IndexOutOfRange { index, len });
+    }
+    if !(2..=MAX_MERKLE_ARITY).contains(&arity) {
+        return Err(VerifyError::ParameterMismatch);
+    }
+    let expected = path_length(index, len, arity);
+    let mismatch = VerifyError::PathLengthMismatch {
+        expected,
+        found: path.len(),
+    };
+    if path.len() != expected {
+        return Err(mismatch);
+    }
+    let mut root = leaf;
+    let (mut node, mut size) = (index, len);
+    let mut path = path.iter();
+    while size > 1 {
+        let children = group(node, size, arity)
+            .map(|child| {
+                if child == node {
+                    Some(root)
+                } else {
+                    path.next().copied()
+                }
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(mismatch)?;
+        root = hash.parent(&children);
+        node /= arity;
+        size = size.div_ceil(arity);
+    }
+    Ok(root)
+}
+
+// Hash a leaf with its path up to the commitment of the list, see [fold_tree]
+fn fold_path(
+    hash: HashKind,
+    arity: usize,
+    leaf: MerkleRoot,
+    index: usize,
+    len: usize,
+    path: &[MerkleRoot],
+) -> Result<MerkleRoot, VerifyError> {
+    fold_tree(hash, arity, leaf, index, len, path).map(|root| hash.list(&root, len))
+}
+
+/// Merkle tree commitment to a list of trace records, see [CommitmentScheme].
+/// The commitment binds the root of the tree to the number of records. A node has up to
+/// `arity` children, two by default. The nodes of a level are hashed by groups of `arity`
+/// nodes, the last group holds the remaining nodes and is not padded, a single remaining
+/// node is moved up unchanged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MerkleTraceCommitment {
+    hash: HashKind,
+    arity: usize,
+}
+
+impl Default for MerkleTraceCommitment {
+    fn default() -> Self {
+        Self::new(HashKind::default())
+    }
+}
+
+impl MerkleTraceCommitment {
+    /// Create the commitment hashing the binary trees with the given hash
+    pub fn new(hash: HashKind) -> Self {
+        Self { hash, arity: 2 }
+    }
+
+    /// Create the commitment hashing the trees of the given arity with the given hash.
+    /// Return [Error::InvalidMerkleArity] if the arity is not between 2 and
+    /// [MAX_MERKLE_ARITY]
+    pub fn with_arity(hash: HashKind, arity: usize) -> Result<Self, Error> {
+        if !(2..=MAX_MERKLE_ARITY).contains(&arity) {
+            return Err(Error::InvalidMerkleArity { arity });
+        }
+        Ok(Self { hash, arity })
+    }
+
+    /// Get the hash of the trees
+    pub fn hash_kind(&self) -> HashKind {
+        self.hash
+    }
+
+    /// Get the number of children of a node of the trees
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+
+    /// Get the leaf of a record, the leaf of a [MerkleProof] of the record
+    pub fn leaf<F: Field + PrimeField>(&self, record: &ConvertedTraceRecord<F>) -> MerkleRoot {
+        self.hash.record_leaf(record)
+    }
+
+    // Get the leaves of the records in their order
+    fn leaves<F: Field + PrimeField>(
+        &self,
+        records: &[ConvertedTraceRecord<F>],
+    ) -> Vec<MerkleRoot> {
+        #[cfg(feature = "parallel")]
+        if records.len() >= PARALLEL_MIN_NODES {
+            return records.par_iter().map(|record| self.leaf(record)).collect();
+        }
+        records.iter().map(|record| self.leaf(record)).collect()
+    }
+
+    /// Commit a list of records streamed in their order, the commitment of
+    /// [CommitmentScheme::commit] is computed with the nodes of a single group per level of
+    /// the tree, see [MerkleTree::root_from_leaf_iter]
+    pub fn commit_stream<F: Field + PrimeField>(
+        &self,
+        records: impl IntoIterator<Item = ConvertedTraceRecord<F>>,
+    ) -> MerkleRoot {
+        let mut len = 0;
+        let leaves = records.into_iter().map(|record| {
+            len += 1;
+            self.leaf(&record)
+        });
+        let root = self.hash.stream_root(self.arity, leaves);
+        self.hash.list(&root, len)
+    }
+
+    // Verify an opening like [CommitmentScheme::verify], the path stops hashing at the first
+    // node verified by a previous opening and its remaining siblings are compared with the
+    // verified nodes. The nodes and the siblings of a verified path are added to the verified
+    // nodes, keyed by the length of the list, their level and their index in the level
+    fn verify_shared<F: Field + PrimeField>(
+        &self,
+        commitment: &MerkleRoot,
+        opening: &MerkleOpening<F>,
+        verified: &mut BTreeMap<(usize, usize, usize), MerkleRoot>,
+    ) -> Result<(), VerifyError> {
+        let (index, len) = (opening.index, opening.len);
+        if opening.hash != self.hash || opening.arity != self.arity {
+            return Err(VerifyError::ParameterMismatch);
+        }
+        if index >= len {
+            return Err(VerifyError::IndexOutOfRange { index, len });
+        }
+        let expected = path_length(index, len, self.arity);
+        if opening.path.len() != expected {
+            return Err(VerifyError::PathLengthMismatch {
+                expected,
+                found: opening.path.len(),
+            });
+        }
+        let mut nodes = Vec::new();
+        let mut root = self.leaf(&opening.record);
+        let (mut node, mut size, mut level) = (index, len, 0);
+        let mut path = opening.path.iter();
+        let mut shared = false;
+        loop {
+            if !shared {
+                match verified.get(&(len, level, node)) {
+                    Some(known) if *known != root => {
+                        return Err(VerifyError::RootMismatch {
+                            expected: *known,
+                            computed: root,
+                        })
+                    }
+                    Some(_) => shared = true,
+                    None => nodes.push(((len, level, node), root)),
+                }
+            }
+            if size == 1 {
+                break;
+            }
+            let mut children = Vec::with_capacity(self.arity);
+            for child in group(node, size, self.arity) {
+                if child == node {
+                    children.push(root);
+                    continue;
+                }
+                let Some(sibling) = path.next() else {
+                    return Err(VerifyError::DeserializeError);
+                };
+                if shared {
+                    // The siblings above a verified node were verified with it
+                    let known = verified.get(&(len, level, child)).copied();
+                    if known != Some(*sibling) {
+                        return Err(VerifyError::RootMismatch {
+                            expected: known.unwrap_or_default(),
+                            computed: *sibling,
+                        });
+                    }
+                } else {
+                    nodes.push(((len, level, child), *sibling));
+                    children.push(*sibling);
+                }
+            }
+            if !shared {
+                root = self.hash.parent(&children);
+            }
+            node /= self.arity;
+            size = size.div_ceil(self.arity);
+            level += 1;
+        }
+        if !shared {
+            check_root(commitment, self.hash.list(&root, len))?;
+        }
+        verified.extend(nodes);
+        Ok(())
+    }
+}
+
+/// Opening of a record of a list committed by [MerkleTraceCommitment]
+#[derive(Debug, Clone)]
+pub struct MerkleOpening<F: Field + PrimeField> {
+    /// Index of the record in the list
+    pub index: usize,
+    /// Number of records of the list
+    pub len: usize,
+    /// Hash of the tree
+    pub hash: HashKind,
+    /// Number of children of a node of the tree
+    pub arity: usize,
+    /// The opened record
+    pub record: ConvertedTraceRecord<F>,
+    /// Siblings of the nodes from the leaf up to the root, a level at a time and in their
+    /// order in the level, a node moved up unchanged has none
+    pub path: Vec<MerkleRoot>,
+}
+
+impl<F: Field + PrimeField> MerkleOpening<F> {
+    /// Get the proof of the opening, without its record. Return [Error::OpeningOutOfRange] if
+    /// the index is out of the list, [Error::InvalidMerkleArity] if the arity is not between 2
+    /// and [MAX_MERKLE_ARITY] and [Error::MerklePathLengthMismatch] if the path does not fit
+    /// the index
+    pub fn to_proof(&self) -> Result<MerkleProof, Error> {
+        if self.index >= self.len {
+            return Err(Error::OpeningOutOfRange {
+                index: self.index,
+                len: self.len,
+            });
+        }
+        if !(2..=MAX_MERKLE_ARITY).contains(&self.arity) {
+            return Err(Error::InvalidMerkleArity { arity: self.arity });
+        }
+        let leaf = self.hash.record_leaf(&self.record);
+        let root = fold_path(
+            self.hash, self.arity, leaf, self.index, self.len, &self.path,
+        )
+        .map_err(|_| Error::MerklePathLengthMismatch {
+            expected: path_length(self.index, self.len, self.arity),
+            found: self.path.len(),
+        })?;
+        Ok(MerkleProof {
+            index: self.index,
+            len: self.len,
+            hash: self.hash,
+            arity: self.arity,
+            leaf,
+            path: self.path.clone(),
+            root,
+        })
+    }
+}
+
+/// Proof that a leaf is in a list committed by [MerkleTraceCommitment].
+/// The wire format is [MERKLE_PROOF_VERSION], the hash as a byte, 0 for BLAKE2b, 1 for
+/// Poseidon and 2 for Keccak256, the arity as a byte, the index and the length of the list as
+/// big endian u64, the leaf, the root, the number of siblings as a byte and the siblings. The
+/// proofs of version 2 have no arity byte and are binary, the proofs of version 1 have no hash
+/// byte either and are hashed with BLAKE2b.
+/// With the `serde` feature the proof is serialized as the bytes of its wire format
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "Vec<u8>", into = "Vec<u8>")
+)]
+pub struct MerkleProof {
+    /// Index of the leaf in the list
+    pub index: usize,
+    /// Number of leaves of the list
+    pub len: usize,
+    /// Hash of the tree
+    pub hash: HashKind,
+    /// Number of children of a node of the tree
+    pub arity: usize,
+    /// The proven leaf, see [MerkleTraceCommitment::leaf]
+    pub leaf: MerkleRoot,
+    /// Siblings of the nodes from the leaf up to the root, see [MerkleOpening::path]
+    pub path: Vec<MerkleRoot>,
+    /// The commitment of the list
+    pub root: MerkleRoot,
+}
+
+// Take the next bytes of a proof
+fn take<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N], Error> {
+    if bytes.len() < N {
+        return Err(Error::TruncatedMerkleProof);
+    }
+    let (head, tail) = bytes.split_at(N);
+    let mut taken = [0u8; N];
+    taken.copy_from_slice(head);
+    *bytes = tail;
+    Ok(taken)
+}
+
+impl MerkleProof {
+    /// Check that the leaf and the path hash to the root
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let root = fold_path(
+            self.hash, self.arity, self.leaf, self.index, self.len, &self.path,
+        )?;
+        check_root(&self.root, root)
+    }
+
+    /// Check the proof like [MerkleProof::verify], without the reason of a rejection
+    pub fn is_valid(&self) -> bool {
+        self.verify().is_ok()
+    }
+
+    /// Encode the proof in its wire format
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(84 + 32 * self.path.len());
+        bytes.push(MERKLE_PROOF_VERSION);
+        bytes.push(match self.hash {
+            HashKind::Blake2b => 0,
+            HashKind::Poseidon => 1,
+            HashKind::Keccak256 => 2,
+        });
+        bytes.push(self.arity as u8);
+        bytes.extend_from_slice(&(self.index as u64).to_be_bytes());
+        bytes.extend_from_slice(&(self.len as u64).to_be_bytes());
+        bytes.extend_from_slice(&self.leaf);
+        bytes.extend_from_slice(&self.root);
+        bytes.push(self.path.len() as u8);
+        for sibling in self.path.iter() {
+            bytes.extend_from_slice(sibling);
+        }
+        bytes
+    }
+
+    /// Encode the path as the flat array of `bytes32` taken by the Solidity verifiers, the
+    /// siblings from the leaf up without a length. The verifier finds the position of every
+    /// sibling from the index and the length of the list, see [MerkleOpening::path]
+    pub fn to_bytes32_array(&self) -> Vec<u8> {
+        self.path.concat()
+    }
+
+    /// Decode a proof from its wire format. Return [Error::TruncatedMerkleProof] if the bytes
+    /// end inside the proof, [Error::MerklePathLengthMismatch] if the number of siblings does
+    /// not fit the index and [Error::MalformedMerkleProof] if the version or the hash is
+    /// unknown, the arity is not between 2 and [MAX_MERKLE_ARITY], the index is out of the
+    /// list or the bytes go on after the proof
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut bytes = bytes;
+        let [version] = take::<1>(&mut bytes)?;
+        let hash = match version {
+            1 => HashKind::Blake2b,
+            2 | MERKLE_PROOF_VERSION => match take::<1>(&mut bytes)? {
+                [0] => HashKind::Blake2b,
+                [1] => HashKind::Poseidon,
+                [2] => HashKind::Keccak256,
+                _ => return Err(Error::MalformedMerkleProof),
+            },
+            _ => return Err(Error::MalformedMerkleProof),
+        };
+        let arity = match version {
+            MERKLE_PROOF_VERSION => usize::from(take::<1>(&mut bytes)?[0]),
+            _ => 2,
+        };
+        if !(2..=MAX_MERKLE_ARITY).contains(&arity) {
+            return Err(Error::MalformedMerkleProof);
+        }
+        let index = u64::from_be_bytes(take(&mut bytes)?);
+        let len = u64::from_be_bytes(take(&mut bytes)?);
+        let leaf = take(&mut bytes)?;
+        let root = take(&mut bytes)?;
+        let [found] = take::<1>(&mut bytes)?;
+        let (Ok(index), Ok(len)) = (usize::try_from(index), usize::try_from(len)) else {
+            return Err(Error::MalformedMerkleProof);
+        };
+        if index >= len {
+            return Err(Error::MalformedMerkleProof);
+        }
+        let expected = path_length(index, len, arity);
+        if usize::from(found) != expected {
+            return Err(Error::MerklePathLengthMismatch {
+                expected,
+                found: found.into(),
+            });
+        }
+        let path = (0..expected)
+            .map(|_| take(&mut bytes))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !bytes.is_empty() {
+            return Err(Error::MalformedMerkleProof);
+        }
+        Ok(Self {
+            index,
+            len,
+            hash,
+            arity,
+            leaf,
+            path,
+            root,
+        })
+    }
+}
+
+impl From<MerkleProof> for Vec<u8> {
+    fn from(proof: MerkleProof) -> Self {
+        proof.to_bytes()
+    }
+}
+
+impl TryFrom<Vec<u8>> for MerkleProof {
+    type Error = Error;
+
+    fn try_from(bytes: Vec<u8>) -> Result<Self, Error> {
+        Self::from_bytes(&bytes)
+    }
+}
+
+/// Check that the proof encoded in the bytes proves the leaf in the list committed by the root.
+/// Return [VerifyError::PathLengthMismatch] if the number of siblings does not fit the index
+/// and [VerifyError::DeserializeError] for the other errors of [MerkleProof::from_bytes]
+pub fn verify_bytes(root: &MerkleRoot, leaf: &MerkleRoot, proof: &[u8]) -> Result<(), VerifyError> {
+    let proof = MerkleProof::from_bytes(proof).map_err(|error| match error {
+        Error::MerklePathLengthMismatch { expected, found } => {
+            VerifyError::PathLengthMismatch { expected, found }
+        }
+        _ => VerifyError::DeserializeError,
+    })?;
+    if proof.leaf != *leaf {
+        return Err(VerifyError::LeafMismatch {
+            expected: *leaf,
+            found: proof.leaf,
+        });
+    }
+    proof.verify()?;
+    check_root(root, proof.root)
+}
+
+impl<F: Field + PrimeField> CommitmentScheme<F> for MerkleTraceCommitment {
+    type Commitment = MerkleRoot;
+    type Opening = MerkleOpening<F>;
+    type PublicParams = HashKind;
+
+    fn setup(hash: HashKind) -> Self {
+        Self::new(hash)
+    }
+
+    fn commit(&self, records: &[ConvertedTraceRecord<F>]) -> Result<MerkleRoot, Error> {
+        let root = self.hash.tree_root(self.arity, self.leaves(records));
+        Ok(self.hash.list(&root, records.len()))
+    }
+
+    fn open(
+        &self,
+        records: &[ConvertedTraceRecord<F>],
+        index: usize,
+    ) -> Result<MerkleOpening<F>, Error> {
+        let record = records.get(index).ok_or(Error::OpeningOutOfRange {
+            index,
+            len: records.len(),
+        })?;
+        let mut level = self.leaves(records);
+        let mut node = index;
+        let mut path = Vec::new();
+        while level.len() > 1 {
+            let siblings = group(node, level.len(), self.arity).filter(|child| *child != node);
+            path.extend(siblings.map(|sibling| level[sibling]));
+            level = self.hash.next_level(self.arity, &level);
+            node /= self.arity;
+        }
+        Ok(MerkleOpening {
+            index,
+            len: records.len(),
+            hash: self.hash,
+            arity: self.arity,
+            record: record.clone(),
+            path,
+        })
+    }
+
+    fn verify(
+        &self,
+        commitment: &MerkleRoot,
+        opening: &MerkleOpening<F>,
+    ) -> Result<(), VerifyError> {
+        if opening.hash != self.hash || opening.arity != self.arity {
+            return Err(VerifyError::ParameterMismatch);
+        }
+        let leaf = self.leaf(&opening.record);
+        let (index, len) = (opening.index, opening.len);
+        check_root(
+            commitment,
+            fold_path(self.hash, self.arity, leaf, index, len, &opening.path)?,
+        )
+    }
+
+    // The openings are verified by index, so the paths of neighbouring records meet early
+    // and every node of the tree is hashed at most once
+    fn verify_many(
+        &self,
+        commitment: &MerkleRoot,
+        openings: &[MerkleOpening<F>],
+    ) -> Result<(), Error> {
+        let mut order: Vec<usize> = (0..openings.len()).collect();
+        order.sort_by_key(|position| openings[*position].index);
+        let mut verified = BTreeMap::new();
+        let rejected = order
+            .into_iter()
+            .filter(|position| {
+                self.verify_shared(commitment, &openings[*position], &mut verified)
+                    .is_err()
+            })
+            .min();
+        match rejected {
+            Some(index) => Err(Error::BatchOpeningRejected { index }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The witness is the index of the record, the limbs of the record in the order address,
+/// time log, instruction and value, then every level of the path from the leaf up where the
+/// node has siblings as the position of the node among its siblings followed by the two
+/// limbs of every sibling. In a binary tree the position is one if the sibling is the left
+/// child. The public inputs are the two limbs of the commitment followed by the number of
+/// records. Return [Error::InvalidMerkleArity] if the arity of the opening is not the arity
+/// of the commitment and [Error::MerklePathLengthMismatch] if the path does not have the
+/// siblings of every level of the leaf
+impl<F: Field + PrimeField> Extractor<F> for MerkleTraceCommitment {
+    fn extract(
+        &self,
+        commitment: &MerkleRoot,
+        opening: &MerkleOpening<F>,
+    ) -> Result<OpeningWitness<F>, Error> {
+        let (index, len) = (opening.index, opening.len);
+        if index >= len {
+            return Err(Error::OpeningOutOfRange { index, len });
+        }
+        if opening.arity != self.arity {
+            return Err(Error::InvalidMerkleArity {
+                arity: opening.arity,
+            });
+        }
+        let expected = path_length(index, len, self.arity);
+        if opening.path.len() != expected {
+            return Err(Error::MerklePathLengthMismatch {
+                expected,
+                found: opening.path.len(),
+            });
+        }
+        let record = &opening.record;
+        let mut witness = vec![F::from(index as u64)];
+        witness.extend(
+            record
+                .address
+                .iter()
+                .chain(record.time_log.iter())
+                .chain(once(&record.instruction))
+                .chain(record.value.iter()),
+        );
+        let (mut node, mut size) = (index, len);
+        let mut path = opening.path.iter();
+        while size > 1 {
+            let siblings = group(node, size, self.arity).len() - 1;
+            if siblings > 0 {
+                witness.push(F::from((node % self.arity) as u64));
+            }
+            for _ in 0..siblings {
+                // The length of the path was checked
+                let sibling = path.next().ok_or(Error::MalformedMerkleProof)?;
+                witness.extend(to_limbs::<F>(sibling));
+            }
+            node /= self.arity;
+            size = size.div_ceil(self.arity);
+        }
+        let mut public_inputs = to_limbs::<F>(commitment).to_vec();
+        public_inputs.push(F::from(len as u64));
+        Ok(OpeningWitness {
+            witness,
+            public_inputs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        commit_image, commit_image_with, path_length, verify_bytes, CommitmentBackend, HashKind,
+        MerkleOpening, MerkleProof, MerkleRoot, MerkleTraceCommitment, MerkleTree,
+        DENSE_COMMITMENT_MAX_CELLS, MAX_MERKLE_ARITY,
+    };
+    use crate::{
+        base::{Base, B256, B32},
+        commitment::{
+            extractor::{from_limbs, Extractor, OpeningWitness},
+            store::MemoryNodeStore,
+            CommitmentScheme,
+        },
+        constraints::gadgets::ConvertedTraceRecord,
+        error::{Error, VerifyError},
+        machine::{AbstractTraceRecord, MemoryInstruction, TraceRecord},
+    };
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use ff::{Field, PrimeField};
+    use halo2_proofs::halo2curves::bn256::Fr;
+    use halo2curves::pasta::Fp;
+    use rand::{seq::SliceRandom, Rng};
+
+    fn image(cells: usize) -> Vec<(B256, B256)> {
+        (0..cells)
+            .map(|i| (B256::from(i * 32), B256::from(i + 1)))
+            .collect()
+    }
+
+    #[test]
+    fn test_commit_image() {
+        // Every image size up to a few levels of odd nodes gives its own root
+        let roots: Vec<_> = (0..8).map(|cells| commit_image(&image(cells))).collect();
+        for (i, root) in roots.iter().enumerate() {
+            assert!(roots[i + 1..].iter().all(|other| other != root));
+        }
+
+        // The order of the cells and the zero cells do not change the root
+        let mut shuffled = image(5);
+        shuffled.reverse();
+        shuffled.push((B256::from(0x1000), B256::from(0)));
+        assert_eq!(commit_image(&shuffled), roots[5]);
+        assert_eq!(
+            commit_image(&[(B256::from(0), B256::from(0))]),
+            commit_image::<B256, B256, 32, 32>(&[])
+        );
+
+        // A changed value or address changes the root
+        let mut changed = image(5);
+        changed[2].1 = B256::from(9);
+        assert_ne!(commit_image(&changed), roots[5]);
+        changed = image(5);
+        changed[4].0 = B256::from(0x1000);
+        assert_ne!(commit_image(&changed), roots[5]);
+
+        // The width of the cells is committed
+        assert_ne!(
+            commit_image(&[(B32::from(0u64), B32::from(1u64))]),
+            commit_image(&[(B256::from(0), B256::from(1))])
+        );
+    }
+    #[test]
+    fn test_dense_and_sparse_backends() {
+        let cells = image(5);
+        let word_size = B256::WORD_SIZE;
+        for backend in [CommitmentBackend::Sparse, CommitmentBackend::Dense] {
+            assert_eq!(backend.commit(&cells, word_size), Ok(commit_image(&cells)));
+            assert_eq!(
+                backend.commit::<B256, B256, 32, 32>(&[], word_size),
+                Ok(commit_image::<B256, B256, 32, 32>(&[]))
+            );
+        }
+
+        // The dense walk fills the gaps with zero cells and is bounded
+        let far = B256::from(DENSE_COMMITMENT_MAX_CELLS) * word_size;
+        let gapped = [(B256::zero(), B256::from(1)), (far, B256::from(2))];
+        assert_eq!(
+            CommitmentBackend::Dense.commit(&gapped, word_size),
+            Err(Error::DenseCommitmentTooLarge)
+        );
+        let near = [gapped[0], (B256::from(100) * word_size, B256::from(2))];
+        assert_eq!(
+            CommitmentBackend::Dense.commit(&near, word_size),
+            Ok(commit_image(&near))
+        );
+    }
+
+    #[test]
+    fn test_poseidon_known_answers() {
+        let root = |decimal| Fr::from_str_vartime(decimal).unwrap().to_repr();
+        let leaves = |len: u64| -> Vec<[u8; 32]> {
+            (1..=len).map(|leaf| Fr::from(leaf).to_repr()).collect()
+        };
+        let poseidon = HashKind::Poseidon;
+        assert_eq!(poseidon.tree_root(2, leaves(1)), Fr::ONE.to_repr());
+        assert_eq!(
+            poseidon.tree_root(2, leaves(2)),
+            root("7853200120776062878684798364095072458815029376092732009249414926327459813530")
+        );
+        assert_eq!(
+            poseidon.tree_root(2, leaves(3)),
+            root("13816780880028945690020260331303642730075999758909899334839547418969502592169")
+        );
+        assert_eq!(
+            poseidon.tree_root(2, leaves(4)),
+            root("3330844108758711782672220159612173083623710937399719017074673646455206473965")
+        );
+        assert_eq!(
+            poseidon.tree_root(2, leaves(5)),
+            root("11512324111804726054755717642058292259866309947044530224809882918003853859592")
+        );
+
+        // Lists of records
+        let scheme = MerkleTraceCommitment::new(poseidon);
+        let record = TraceRecord::<B256, B256, 32, 32>::new(
+            0,
+            0,
+            MemoryInstruction::Write,
+            B256::zero(),
+            B256::from(1),
+        );
+        let records = [ConvertedTraceRecord::<Fp>::from_trace_record(&record).unwrap()];
+        assert_eq!(
+            scheme.leaf(&records[0]),
+            root("18860526976342492724092124765787819213216544185290097292985852425469245991315")
+        );
+        assert_eq!(
+            scheme.commit(&records),
+            Ok(root(
+                "13585356414592189684958872885100974554269129662159766104030667481490099838548"
+            ))
+        );
+        assert_eq!(
+            scheme.commit(&records[..0]),
+            Ok(root(
+                "8044281464665679090464077631371363958685853755581444236527942678530645975597"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_poseidon_image_order() {
+        let mut rng = rand::thread_rng();
+        for cells in 0..12 {
+            let mut cells = image(cells);
+            let root = commit_image_with(HashKind::Poseidon, &cells);
+            cells.shuffle(&mut rng);
+            assert_eq!(commit_image_with(HashKind::Poseidon, &cells), root);
+            // Zero cells are left out
+            cells.push((B256::from(0x1000), B256::zero()));
+            cells.shuffle(&mut rng);
+            assert_eq!(commit_image_with(HashKind::Poseidon, &cells), root);
+            assert_ne!(commit_image(&cells), root);
+            assert_eq!(
+                commit_image_with(HashKind::Blake2b, &cells),
+                commit_image(&cells)
+            );
+        }
+    }
+
+    #[test]
+    fn test_update_leaf() {
+        let mut rng = rand::thread_rng();
+        for (hash, updates) in [(HashKind::Blake2b, 10_000), (HashKind::Poseidon, 1_000)] {
+            let mut leaves: Vec<MerkleRoot> = (0..1000).map(|_| rng.gen()).collect();
+            let mut tree = MerkleTree::new(hash, leaves.clone());
+            for i in 0..updates {
+                let index = rng.gen_range(0..leaves.len());
+                leaves[index] = rng.gen();
+                let root = tree.update_leaf(index, leaves[index]).unwrap();
+                if i % 500 == 0 {
+                    assert_eq!(root, MerkleTree::new(hash, leaves.clone()).root());
+                }
+            }
+            // The incremental tree is the tree rebuilt from scratch
+            assert_eq!(tree, MerkleTree::new(hash, leaves.clone()));
+            assert_eq!(tree.root(), hash.tree_root(2, leaves));
+            assert_eq!(
+                tree.update_leaf(1000, [0u8; 32]),
+                Err(Error::LeafOutOfRange {
+                    index: 1000,
+                    len: 1000
+                })
+            );
+
+            // The tree of the leaves of an image has the root of the image
+            let cells = image(5);
+            let leaves: Vec<MerkleRoot> = cells
+                .iter()
+                .map(|(address, value)| {
+                    let address: [u8; 32] = (*address).into();
+                    let value: [u8; 32] = (*value).into();
+                    hash.cell_leaf(&address, &value)
+                })
+                .collect();
+            let mut tree = MerkleTree::new(hash, leaves);
+            assert_eq!(tree.root(), commit_image_with(hash, &cells));
+            let leaf = hash.cell_leaf(&[0u8; 32], &B256::from(9).fixed_be_bytes());
+            let mut updated = cells.clone();
+            updated[0].1 = B256::from(9);
+            assert_eq!(
+                tree.update_leaf(0, leaf),
+                Ok(commit_image_with(hash, &updated))
+            );
+
+            let empty = MerkleTree::new(hash, Vec::new());
+            assert!(empty.is_empty());
+            assert_eq!(
+                empty.root(),
+                commit_image_with::<B256, B256, 32, 32>(hash, &[])
+            );
+        }
+    }
+
+    #[test]
+    fn test_prove_update() {
+        let mut rng = rand::thread_rng();
+        for hash in [HashKind::Blake2b, HashKind::Poseidon, HashKind::Keccak256] {
+            for len in [1, 2, 7, 16, 33] {
+                let leaves: Vec<MerkleRoot> = (0..len).map(|_| rng.gen()).collect();
+                let mut tree = MerkleTree::new(hash, leaves);
+                for index in 0..len {
+                    let leaf: MerkleRoot = rng.gen();
+                    let proof = tree.prove_update(index, leaf).unwrap();
+                    assert_eq!(proof.old_leaf, tree.leaves()[index]);
+                    assert_eq!(proof.old_root, tree.root());
+                    assert_eq!(proof.verify_update(), Ok(()));
+                    assert!(proof.is_valid());
+                    assert_eq!(tree.update_leaf(index, leaf), Ok(proof.new_root));
+
+                    // Swapped leaves and a wrong path are rejected
+                    let mut swapped = proof.clone();
+                    swapped.old_leaf = proof.new_leaf;
+                    swapped.new_leaf = proof.old_leaf;
+                    assert!(!swapped.is_valid());
+                    if !proof.path.is_empty() {
+                        let mut tampered = proof.clone();
+                        tampered.path[0][0] ^= 1;
+                        assert!(matches!(
+                            tampered.verify_update(),
+                            Err(VerifyError::RootMismatch { expected, .. })
+                                if expected == proof.old_root
+                        ));
+                    }
+                    let mut moved = proof.clone();
+                    moved.index = (index + 1) % len;
+                    assert_eq!(moved.is_valid(), len == 1);
+                }
+                assert_eq!(
+                    tree.prove_update(len, [0u8; 32]),
+                    Err(Error::LeafOutOfRange { index: len, len })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut rng = rand::thread_rng();
+        for hash in [HashKind::Blake2b, HashKind::Poseidon, HashKind::Keccak256] {
+            let mut store = MemoryNodeStore::new();
+            for len in [0, 1, 2, 7, 16, 33] {
+                let leaves: Vec<MerkleRoot> = (0..len).map(|_| rng.gen()).collect();
+                let mut tree = MerkleTree::new(hash, leaves);
+                tree.save(&mut store).unwrap();
+                let loaded = MerkleTree::load(hash, &store, tree.root(), len).unwrap();
+                assert_eq!(loaded, tree);
+
+                // The updated tree shares the nodes off the path of the leaf
+                if len > 1 {
+                    let before = store.len();
+                    tree.update_leaf(len / 2, rng.gen()).unwrap();
+                    tree.save(&mut store).unwrap();
+                    assert!(store.len() - before < len);
+                    let loaded = MerkleTree::load(hash, &store, tree.root(), len).unwrap();
+                    assert_eq!(loaded, tree);
+                }
+            }
+            assert_eq!(
+                MerkleTree::load(hash, &MemoryNodeStore::new(), [1u8; 32], 4),
+                Err(Error::MissingMerkleNode)
+            );
+            assert_eq!(
+                MerkleTree::load(hash, &store, [1u8; 32], 0),
+                Err(Error::MissingMerkleNode)
+            );
+        }
+    }
+
+    #[test]
+    fn test_streamed_root() {
+        let mut rng = rand::thread_rng();
+        for hash in [HashKind::Blake2b, HashKind::Poseidon] {
+            let lengths = (0..70).chain([1 << 10, 1000]);
+            for len in lengths {
+                let leaves: Vec<MerkleRoot> = (0..len).map(|_| rng.gen()).collect();
+                assert_eq!(
+                    MerkleTree::root_from_leaf_iter(hash, leaves.iter().copied()),
+                    MerkleTree::new(hash, leaves).root()
+                );
+            }
+
+            // A streamed trace is committed like the trace
+            let scheme = MerkleTraceCommitment::new(hash);
+            let records: Vec<ConvertedTraceRecord<Fp>> = (0..37u64)
+                .map(|i| {
+                    let record = TraceRecord::<B256, B256, 32, 32>::new(
+                        i,
+                        0,
+                        MemoryInstruction::Write,
+                        B256::from(32 * i),
+                        B256::from(rng.gen::<u64>()),
+                    );
+                    ConvertedTraceRecord::from_trace_record(&record).unwrap()
+                })
+                .collect();
+            for len in [0, 1, 2, 36, 37] {
+                assert_eq!(
+                    scheme.commit_stream(records[..len].iter().cloned()),
+                    scheme.commit(&records[..len]).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_merkle_proof_wire_format() {
+        let mut rng = rand::thread_rng();
+        let records: Vec<ConvertedTraceRecord<Fp>> = (0..40u64)
+            .map(|i| {
+                let record = TraceRecord::<B256, B256, 32, 32>::new(
+                    i,
+                    0,
+                    MemoryInstruction::Write,
+                    B256::from(32 * i),
+                    B256::from(rng.gen::<u64>()),
+                );
+                ConvertedTraceRecord::from_trace_record(&record).unwrap()
+            })
+            .collect();
+
+        let mut deepest = None;
+        for hash in [HashKind::Blake2b, HashKind::Poseidon] {
+            let scheme = MerkleTraceCommitment::new(hash);
+
+            // The proofs of the lists agree with the verification of their openings
+            for len in 1..=records.len() {
+                let commitment = scheme.commit(&records[..len]).unwrap();
+                let index = rng.gen_range(0..len);
+                let proof = scheme
+                    .open(&records[..len], index)
+                    .unwrap()
+                    .to_proof()
+                    .unwrap();
+                assert_eq!(proof.root, commitment);
+                let bytes = proof.to_bytes();
+                assert_eq!(MerkleProof::from_bytes(&bytes), Ok(proof));
+                let leaf = scheme.leaf(&records[index]);
+                assert_eq!(verify_bytes(&commitment, &leaf, &bytes), Ok(()));
+                let other = scheme.leaf(&records[(index + 1) % len]);
+                let expected = if len == 1 {
+                    Ok(())
+                } else {
+                    Err(VerifyError::LeafMismatch {
+                        expected: other,
+                        found: leaf,
+                    })
+                };
+                assert_eq!(verify_bytes(&commitment, &other, &bytes), expected);
+                assert_eq!(
+                    verify_bytes(&[0u8; 32], &leaf, &bytes),
+                    Err(VerifyError::RootMismatch {
+                        expected: [0u8; 32],
+                        computed: commitment
+                    })
+                );
+            }
+
+            // Trees up to depth 32 with random siblings
+            for depth in 1..=32 {
+                let len = rng.gen_range((1usize << (depth - 1)) + 1..=1usize << depth);
+                let index = rng.gen_range(0..len);
+                let opening = MerkleOpening {
+                    index,
+                    len,
+                    hash,
+                    arity: 2,
+                    record: records[0].clone(),
+                    path: (0..path_length(index, len, 2)).map(|_| rng.gen()).collect(),
+                };
+                let proof = opening.to_proof().unwrap();
+                assert!(proof.path.len() <= depth);
+                assert_eq!(scheme.verify(&proof.root, &opening), Ok(()));
+                let mut bytes = proof.to_bytes();
+                assert_eq!(MerkleProof::from_bytes(&bytes), Ok(proof.clone()));
+                assert_eq!(verify_bytes(&proof.root, &proof.leaf, &bytes), Ok(()));
+
+                // A changed sibling is rejected by both verifications
+                let mut tampered = opening.clone();
+                tampered.path[0][0] ^= 1;
+                assert!(matches!(
+                    scheme.verify(&proof.root, &tampered),
+                    Err(VerifyError::RootMismatch { expected, .. }) if expected == proof.root
+                ));
+                *bytes.last_mut().unwrap() ^= 1;
+                assert!(matches!(
+                    verify_bytes(&proof.root, &proof.leaf, &bytes),
+                    Err(VerifyError::RootMismatch { expected, .. }) if expected == proof.root
+                ));
+                deepest = Some(proof);
+            }
+        }
+
+        // Truncated, extended and inconsistent proofs are rejected
+        let mut proof = deepest.unwrap();
+        let bytes = proof.to_bytes();
+        for end in 0..bytes.len() {
+            assert_eq!(
+                MerkleProof::from_bytes(&bytes[..end]),
+                Err(Error::TruncatedMerkleProof)
+            );
+        }
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert_eq!(
+            MerkleProof::from_bytes(&extended),
+            Err(Error::MalformedMerkleProof)
+        );
+        let mut unknown = bytes.clone();
+        unknown[0] += 1;
+        assert_eq!(
+            MerkleProof::from_bytes(&unknown),
+            Err(Error::MalformedMerkleProof)
+        );
+        unknown = bytes.clone();
+        unknown[1] = 3;
+        assert_eq!(
+            MerkleProof::from_bytes(&unknown),
+            Err(Error::MalformedMerkleProof)
+        );
+
+        for arity in [0, 1, MAX_MERKLE_ARITY + 1] {
+            unknown = bytes.clone();
+            unknown[2] = arity as u8;
+            assert_eq!(
+                MerkleProof::from_bytes(&unknown),
+                Err(Error::MalformedMerkleProof)
+            );
+        }
+
+        // The proofs of version 2 are binary and the proofs of version 1 are hashed with
+        // BLAKE2b
+        let mut version_2 = proof.to_bytes();
+        version_2.remove(2);
+        version_2[0] = 2;
+        assert_eq!(MerkleProof::from_bytes(&version_2), Ok(proof.clone()));
+        let mut blake2b = proof.clone();
+        blake2b.hash = HashKind::Blake2b;
+        let mut version_1 = blake2b.to_bytes();
+        version_1.drain(1..3);
+        version_1[0] = 1;
+        assert_eq!(MerkleProof::from_bytes(&version_1), Ok(blake2b));
+        let expected = proof.path.len();
+        proof.path.pop();
+        assert_eq!(
+            MerkleProof::from_bytes(&proof.to_bytes()),
+            Err(Error::MerklePathLengthMismatch {
+                expected,
+                found: expected - 1
+            })
+        );
+
+        // The verifications name the first inconsistent part of a proof
+        assert_eq!(
+            verify_bytes(&proof.root, &proof.leaf, &proof.to_bytes()),
+            Err(VerifyError::PathLengthMismatch {
+                expected,
+                found: expected - 1
+            })
+        );
+        assert_eq!(
+            proof.verify(),
+            Err(VerifyError::PathLengthMismatch {
+                expected,
+                found: expected - 1
+            })
+        );
+        assert_eq!(
+            verify_bytes(&proof.root, &proof.leaf, &extended),
+            Err(VerifyError::DeserializeError)
+        );
+        let mut wide = proof.clone();
+        wide.arity = MAX_MERKLE_ARITY + 1;
+        assert_eq!(wide.verify(), Err(VerifyError::ParameterMismatch));
+        proof.index = proof.len;
+        assert_eq!(
+            proof.verify(),
+            Err(VerifyError::IndexOutOfRange {
+                index: proof.len,
+                len: proof.len
+            })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_merkle_proof_serde() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Message {
+            proof: MerkleProof,
+        }
+
+        let record = TraceRecord::<B256, B256, 32, 32>::new(
+            0,
+            0,
+            MemoryInstruction::Write,
+            B256::zero(),
+            B256::from(1),
+        );
+        let records: Vec<ConvertedTraceRecord<Fp>> = (0..3)
+            .map(|_| ConvertedTraceRecord::from_trace_record(&record).unwrap())
+            .collect();
+        let message = Message {
+            proof: MerkleTraceCommitment::new(HashKind::Poseidon)
+                .open(&records, 2)
+                .unwrap()
+                .to_proof()
+                .unwrap(),
+        };
+        let encoded = toml::to_string(&message).unwrap();
+        assert_eq!(toml::from_str::<Message>(&encoded).unwrap(), message);
+
+        // The proof is checked when it is deserialized
+        let mut inconsistent = message;
+        inconsistent.proof.path.pop();
+        let encoded = toml::to_string(&inconsistent).unwrap();
+        assert!(toml::from_str::<Message>(&encoded).is_err());
+    }
+
+    // Check an extracted witness against its public inputs like a circuit would
+    fn check_witness<F: Field + PrimeField>(hash: HashKind, extracted: &OpeningWitness<F>) -> bool {
+        let (witness, public) = (&extracted.witness, &extracted.public_inputs);
+        let record = ConvertedTraceRecord {
+            address: witness[1..33].try_into().unwrap(),
+            time_log: witness[33..41].try_into().unwrap(),
+            instruction: witness[41],
+            value: witness[42..74].try_into().unwrap(),
+        };
+        let mut node = hash.record_leaf(&record);
+        for step in witness[74..].chunks(3) {
+            let Some(sibling) = from_limbs(&[step[1], step[2]]) else {
+                return false;
+            };
+            node = if step[0] == F::ONE {
+                hash.node(&sibling, &node)
+            } else {
+                hash.node(&node, &sibling)
+            };
+        }
+        let mut len = [0u8; 8];
+        len.copy_from_slice(&public[2].to_repr().as_ref()[..8]);
+        let root = hash.list(&node, u64::from_le_bytes(len) as usize);
+        from_limbs(&[public[0], public[1]]) == Some(root)
+    }
+
+    #[test]
+    fn test_extract_witness() {
+        let mut rng = rand::thread_rng();
+        let records: Vec<ConvertedTraceRecord<Fp>> = (0..13u64)
+            .map(|i| {
+                let record = TraceRecord::<B256, B256, 32, 32>::new(
+                    i,
+                    0,
+                    MemoryInstruction::Read,
+                    B256::from(32 * i),
+                    B256::from(rng.gen::<u64>()),
+                );
+                ConvertedTraceRecord::from_trace_record(&record).unwrap()
+            })
+            .collect();
+        for hash in [HashKind::Blake2b, HashKind::Poseidon] {
+            let scheme = MerkleTraceCommitment::new(hash);
+            let commitment = scheme.commit(&records).unwrap();
+            for index in 0..records.len() {
+                let opening = scheme.open(&records, index).unwrap();
+                let extracted = scheme.extract(&commitment, &opening).unwrap();
+                assert_eq!(extracted.witness.len(), 74 + 3 * opening.path.len());
+                assert_eq!(extracted.public_inputs.len(), 3);
+                assert!(check_witness(hash, &extracted));
+
+                // A changed limb of the record does not re-verify
+                let mut tampered = extracted.clone();
+                tampered.witness[41] += Fp::ONE;
+                assert!(!check_witness(hash, &tampered));
+            }
+
+            // Openings of another list are laid out but do not re-verify
+            let opening = scheme.open(&records[..12], 5).unwrap();
+            let extracted = scheme.extract(&commitment, &opening).unwrap();
+            assert!(!check_witness(hash, &extracted));
+
+            let mut opening = scheme.open(&records, 12).unwrap();
+            opening.path.push([0u8; 32]);
+            assert_eq!(
+                scheme.extract(&commitment, &opening),
+                Err(Error::MerklePathLengthMismatch {
+                    expected: 2,
+                    found: 3
+                })
+            );
+            opening.index = 13;
+            assert_eq!(
+                scheme.extract(&commitment, &opening),
+                Err(Error::OpeningOutOfRange { index: 13, len: 13 })
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_many_shared_paths() {
+        let mut rng = rand::thread_rng();
+        let records: Vec<ConvertedTraceRecord<Fp>> = (0..100u64)
+            .map(|i| {
+                let record = TraceRecord::<B256, B256, 32, 32>::new(
+                    i,
+                    0,
+                    MemoryInstruction::Write,
+                    B256::from(32 * i),
+                    B256::from(rng.gen::<u64>()),
+                );
+                ConvertedTraceRecord::from_trace_record(&record).unwrap()
+            })
+            .collect();
+        for hash in [HashKind::Blake2b, HashKind::Poseidon] {
+            let scheme = MerkleTraceCommitment::new(hash);
+            let commitment = scheme.commit(&records).unwrap();
+            // Neighbouring and duplicated records share their paths
+            let openings: Vec<MerkleOpening<Fp>> = (0..40)
+                .map(|_| scheme.open(&records, rng.gen_range(0..20)).unwrap())
+                .collect();
+            assert_eq!(scheme.verify_many(&commitment, &openings), Ok(()));
+
+            // A changed sibling is found even above a node verified by another opening
+            for _ in 0..20 {
+                let index = rng.gen_range(0..openings.len());
+                let mut batch = openings.clone();
+                let sibling = rng.gen_range(0..batch[index].path.len());
+                batch[index].path[sibling][0] ^= 1;
+                assert_eq!(
+                    scheme.verify_many(&commitment, &batch),
+                    Err(Error::BatchOpeningRejected { index })
+                );
+                assert!(matches!(
+                    scheme.verify(&commitment, &batch[index]),
+                    Err(VerifyError::RootMismatch { expected, .. }) if expected == commitment
+                ));
+            }
+
+            // The openings of a shorter list are verified on their own
+            let prefix = scheme.commit(&records[..99]).unwrap();
+            let mut batch: Vec<MerkleOpening<Fp>> = (97..99)
+                .map(|index| scheme.open(&records[..99], index).unwrap())
+                .collect();
+            assert_eq!(scheme.verify_many(&prefix, &batch), Ok(()));
+            batch.push(openings[0].clone());
+            assert_eq!(
+                scheme.verify_many(&prefix, &batch),
+                Err(Error::BatchOpeningRejected { index: 2 })
+            );
+        }
+    }
+
+    #[test]
+    fn test_arity() {
+        let mut rng = rand::thread_rng();
+        let records: Vec<ConvertedTraceRecord<Fp>> = (0..70u64)
+            .map(|i| {
+                let record = TraceRecord::<B256, B256, 32, 32>::new(
+                    i,
+                    0,
+                    MemoryInstruction::Write,
+                    B256::from(32 * i),
+                    B256::from(rng.gen::<u64>()),
+                );
+                ConvertedTraceRecord::from_trace_record(&record).unwrap()
+            })
+            .collect();
+        for hash in [HashKind::Blake2b, HashKind::Poseidon] {
+            let binary = MerkleTraceCommitment::new(hash).commit(&records).unwrap();
+            for arity in [2, 3, 4, 8, MAX_MERKLE_ARITY] {
+                let scheme = MerkleTraceCommitment::with_arity(hash, arity).unwrap();
+                assert_eq!(scheme.arity(), arity);
+                let commitment = scheme.commit(&records).unwrap();
+                assert_eq!(commitment == binary, arity == 2);
+
+                // A full tree has arity - 1 siblings per level
+                for depth in 0..4 {
+                    let len = arity.pow(depth);
+                    let length = (arity - 1) * depth as usize;
+                    assert_eq!(path_length(len - 1, len, arity), length);
+                }
+
+                for len in [1, 5, 17, records.len()] {
+                    let commitment = scheme.commit(&records[..len]).unwrap();
+                    let streamed = scheme.commit_stream(records[..len].iter().cloned());
+                    assert_eq!(streamed, commitment);
+                    let mut depth = 0;
+                    while arity.pow(depth) < len {
+                        depth += 1;
+                    }
+                    let openings: Vec<MerkleOpening<Fp>> = (0..len)
+                        .map(|index| scheme.open(&records[..len], index).unwrap())
+                        .collect();
+                    for opening in openings.iter() {
+                        assert!(opening.path.len() <= (arity - 1) * depth as usize);
+                        assert_eq!(scheme.verify(&commitment, opening), Ok(()));
+                        let proof = opening.to_proof().unwrap();
+                        assert_eq!(proof.root, commitment);
+                        assert_eq!(MerkleProof::from_bytes(&proof.to_bytes()), Ok(proof));
+                        let extracted = scheme.extract(&commitment, opening).unwrap();
+                        // A position per level with siblings
+                        let positions = extracted.witness.len() - 74 - 2 * opening.path.len();
+                        assert!(positions <= depth as usize);
+                    }
+                    assert_eq!(scheme.verify_many(&commitment, &openings), Ok(()));
+
+                    // The openings are bound to the arity of the tree
+                    let other = if arity == 2 { 4 } else { 2 };
+                    let other = MerkleTraceCommitment::with_arity(hash, other).unwrap();
+                    assert_eq!(
+                        other.verify(&commitment, &openings[0]),
+                        Err(VerifyError::ParameterMismatch)
+                    );
+                    assert_eq!(
+                        other.extract(&commitment, &openings[0]),
+                        Err(Error::InvalidMerkleArity { arity })
+                    );
+                }
+            }
+        }
+
+        for arity in [0, 1, MAX_MERKLE_ARITY + 1] {
+            assert_eq!(
+                MerkleTraceCommitment::with_arity(HashKind::Blake2b, arity),
+                Err(Error::InvalidMerkleArity { arity })
+            );
+        }
+    }
+
+    // Read a word of the test vectors
+    fn word(hex: &str) -> MerkleRoot {
+        hex::decode(hex.trim_start_matches("0x"))
+            .unwrap()
+            .try_into()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_keccak256_vectors() {
+        let hash = HashKind::Keccak256;
+        let vectors = include_str!("../../tests/vectors/keccak256_merkle.txt");
+        let mut checked = 0;
+        for line in vectors.lines().filter(|line| !line.starts_with('#')) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields[0] {
+                "leaf" => {
+                    let (address, value) = (word(fields[1]), word(fields[2]));
+                    assert_eq!(hash.cell_leaf(&address, &value), word(fields[3]));
+                    let image = [(B256::from(address), B256::from(value))];
+                    assert_eq!(commit_image_with(hash, &image), word(fields[3]));
+                }
+                "image" => {
+                    let image: Vec<(B256, B256)> = fields[2..]
+                        .chunks(2)
+                        .map(|cell| (B256::from(word(cell[0])), B256::from(word(cell[1]))))
+                        .collect();
+                    assert_eq!(commit_image_with(hash, &image), word(fields[1]));
+                }
+                "proof" => {
+                    let path = hex::decode(fields[5].trim_start_matches("0x")).unwrap();
+                    let proof = MerkleProof {
+                        index: fields[1].parse().unwrap(),
+                        len: fields[2].parse().unwrap(),
+                        hash,
+                        arity: 2,
+                        leaf: word(fields[3]),
+                        path: path
+                            .chunks(32)
+                            .map(|sibling| sibling.try_into().unwrap())
+                            .collect(),
+                        root: word(fields[4]),
+                    };
+                    assert_eq!(proof.verify(), Ok(()));
+                    assert_eq!(proof.to_bytes32_array(), path);
+                    let bytes = proof.to_bytes();
+                    assert_eq!(bytes[1], 2);
+                    assert_eq!(MerkleProof::from_bytes(&bytes), Ok(proof));
+                }
+                _ => panic!("unknown test vector {}", fields[0]),
+            }
+            checked += 1;
+        }
+        assert_eq!(checked, 14);
+
+        // The words of narrower machines are padded to 32 bytes
+        let (address, value) = (B32::from(0x20u64), B32::from(0xdeadbeefu64));
+        assert_eq!(
+            commit_image_with(hash, &[(address, value)]),
+            commit_image_with(hash, &[(B256::from(0x20u64), B256::from(0xdeadbeefu64))])
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_commit() {
+        use super::PARALLEL_MIN_NODES;
+
+        let mut rng = rand::thread_rng();
+        for hash in [HashKind::Blake2b, HashKind::Poseidon, HashKind::Keccak256] {
+            // The streamed root is computed on the current thread
+            let leaves: Vec<MerkleRoot> =
+                (0..PARALLEL_MIN_NODES * 5 + 3).map(|_| rng.gen()).collect();
+            assert_eq!(
+                MerkleTree::new(hash, leaves.clone()).root(),
+                MerkleTree::root_from_leaf_iter(hash, leaves)
+            );
+
+            let records: Vec<ConvertedTraceRecord<Fp>> = (0..PARALLEL_MIN_NODES as u64 + 1)
+                .map(|i| {
+                    let record = TraceRecord::<B256, B256, 32, 32>::new(
+                        i,
+                        0,
+                        MemoryInstruction::Write,
+                        B256::from(32 * i),
+                        B256::from(rng.gen::<u64>()),
+                    );
+                    ConvertedTraceRecord::from_trace_record(&record).unwrap()
+                })
+                .collect();
+            for arity in [2, 4] {
+                let scheme = MerkleTraceCommitment::with_arity(hash, arity).unwrap();
+                let commitment = scheme.commit(&records).unwrap();
+                assert_eq!(scheme.commit_stream(records.iter().cloned()), commitment);
+                let opening = scheme
+                    .open(&records, rng.gen_range(0..records.len()))
+                    .unwrap();
+                assert_eq!(scheme.verify(&commitment, &opening), Ok(()));
+            }
+        }
+    }
+}
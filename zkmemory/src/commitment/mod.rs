@@ -1,4 +1,241 @@
+use crate::{
+    constraints::gadgets::ConvertedTraceRecord,
+    error::{Error, VerifyError},
+};
+use ff::{Field, PrimeField};
+
+/// Commit-then-challenge binding of the original and the sorted trace
+pub mod binding;
 /// Extend Fr field
+#[doc(hidden)]
 pub mod extends;
+/// Witness of the openings for the circuits checking them
+pub mod extractor;
+/// Incremental updates of a memory commitment
+pub mod incremental;
+/// Pedersen vector commitment with inner product openings
+pub mod ipa;
 /// KZG commitment scheme
 pub mod kzg;
+/// Merkle tree commitment to the memory image
+pub mod merkle;
+/// Poseidon hash of the Merkle trees checked in a circuit
+pub mod poseidon;
+/// Commitment backend selected by the config of a machine
+pub mod selector;
+/// Sparse Merkle tree over the address space of a memory
+pub mod sparse;
+/// Persistent storage of the nodes of the Merkle trees
+pub mod store;
+
+/// Commitment to a list of trace records in the witness form of the circuits.
+/// Every backend implements it, so the code that commits and opens a trace does not
+/// change when the backend is swapped
+pub trait CommitmentScheme<F: Field + PrimeField> {
+    /// Commitment to a list of records
+    type Commitment;
+    /// Record at an index of a committed list with the proof that it was committed
+    type Opening;
+    /// Parameters shared by the committer and the verifier
+    type PublicParams;
+
+    /// Create the scheme from its public parameters
+    fn setup(params: Self::PublicParams) -> Self;
+
+    /// Commit a list of records in their order, return an error if the backend cannot
+    /// commit one of the records
+    fn commit(&self, records: &[ConvertedTraceRecord<F>]) -> Result<Self::Commitment, Error>;
+
+    /// Open the record at the index of the list, return [Error::OpeningOutOfRange] if the
+    /// list has no record at the index
+    fn open(
+        &self,
+        records: &[ConvertedTraceRecord<F>],
+        index: usize,
+    ) -> Result<Self::Opening, Error>;
+
+    /// Check that the opening is the one of a record of the committed list, return the
+    /// reason of the rejection otherwise
+    fn verify(
+        &self,
+        commitment: &Self::Commitment,
+        opening: &Self::Opening,
+    ) -> Result<(), VerifyError>;
+
+    /// Check the opening like [CommitmentScheme::verify], without the reason of a rejection
+    fn is_valid(&self, commitment: &Self::Commitment, opening: &Self::Opening) -> bool {
+        self.verify(commitment, opening).is_ok()
+    }
+
+    /// Check that every opening is the one of a record of the committed list, return
+    /// [Error::BatchOpeningRejected] with the position of the first rejected opening.
+    /// The openings are verified one by one unless the backend checks them together
+    fn verify_many(
+        &self,
+        commitment: &Self::Commitment,
+        openings: &[Self::Opening],
+    ) -> Result<(), Error> {
+        match openings
+            .iter()
+            .position(|opening| self.verify(commitment, opening).is_err())
+        {
+            Some(index) => Err(Error::BatchOpeningRejected { index }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ipa::IPAMemoryCommitment,
+        kzg::KZGMemoryCommitment,
+        merkle::{HashKind, MerkleTraceCommitment},
+        CommitmentScheme,
+    };
+    use crate::{
+        base::B256,
+        constraints::gadgets::ConvertedTraceRecord,
+        error::Error,
+        machine::{MemoryInstruction, TraceRecord},
+    };
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use ff::{Field, PrimeField};
+
+    // Records of a short execution, the values are small enough for every field
+    fn trace_records<F: Field + PrimeField>(seed: u64) -> Vec<ConvertedTraceRecord<F>> {
+        (0..5u64)
+            .map(|i| {
+                let instruction = match i % 2 {
+                    0 => MemoryInstruction::Write,
+                    _ => MemoryInstruction::Read,
+                };
+                let record = TraceRecord::<B256, B256, 32, 32>::new(
+                    i,
+                    0,
+                    instruction,
+                    B256::from(32 * (i / 2)),
+                    B256::from(seed + i / 2),
+                );
+                ConvertedTraceRecord::from_trace_record(&record).unwrap()
+            })
+            .collect()
+    }
+
+    // Run the suite of the trait against a backend
+    macro_rules! commitment_scheme_suite {
+        ($backend:ident, $field:ty, $params:expr, $scheme:ty) => {
+            mod $backend {
+                use super::*;
+
+                fn scheme() -> $scheme {
+                    <$scheme as CommitmentScheme<$field>>::setup($params)
+                }
+
+                #[test]
+                fn test_open_every_record() {
+                    let scheme = scheme();
+                    let records = trace_records::<$field>(1);
+                    let commitment = scheme.commit(&records).unwrap();
+                    for index in 0..records.len() {
+                        let opening = scheme.open(&records, index).unwrap();
+                        assert_eq!(scheme.verify(&commitment, &opening), Ok(()));
+                        assert!(scheme.is_valid(&commitment, &opening));
+                    }
+                }
+
+                #[test]
+                fn test_reject_other_list() {
+                    let scheme = scheme();
+                    let records = trace_records::<$field>(1);
+                    let other = trace_records::<$field>(2);
+                    let commitment = scheme.commit(&records).unwrap();
+                    let opening = scheme.open(&other, 2).unwrap();
+                    assert!(scheme.verify(&commitment, &opening).is_err());
+                    assert!(!scheme.is_valid(&commitment, &opening));
+                    // A shorter list does not hold the same records
+                    let prefix = scheme.commit(&records[..3]).unwrap();
+                    let opening = scheme.open(&records, 4).unwrap();
+                    assert!(scheme.verify(&prefix, &opening).is_err());
+                }
+
+                #[test]
+                fn test_verify_many() {
+                    let scheme = scheme();
+                    let records = trace_records::<$field>(1);
+                    let other = trace_records::<$field>(2);
+                    let commitment = scheme.commit(&records).unwrap();
+                    let mut openings: Vec<_> = (0..records.len())
+                        .rev()
+                        .map(|index| scheme.open(&records, index).unwrap())
+                        .collect();
+                    assert_eq!(scheme.verify_many(&commitment, &openings), Ok(()));
+                    assert_eq!(scheme.verify_many(&commitment, &openings[..0]), Ok(()));
+
+                    // A rejected opening is found wherever it is in the batch
+                    let rejected = scheme.open(&other, 2).unwrap();
+                    for index in 0..openings.len() {
+                        let mut batch = openings.clone();
+                        batch[index] = rejected.clone();
+                        assert_eq!(
+                            scheme.verify_many(&commitment, &batch),
+                            Err(Error::BatchOpeningRejected { index })
+                        );
+                    }
+                    openings[3] = rejected.clone();
+                    openings[1] = rejected;
+                    assert_eq!(
+                        scheme.verify_many(&commitment, &openings),
+                        Err(Error::BatchOpeningRejected { index: 1 })
+                    );
+                }
+
+                #[test]
+                fn test_opening_out_of_range() {
+                    let scheme = scheme();
+                    let records = trace_records::<$field>(1);
+                    assert!(matches!(
+                        scheme.open(&records, records.len()),
+                        Err(Error::OpeningOutOfRange { index: 5, len: 5 })
+                    ));
+                    assert!(matches!(
+                        scheme.open(&records[..0], 0),
+                        Err(Error::OpeningOutOfRange { index: 0, len: 0 })
+                    ));
+                }
+            }
+        };
+    }
+
+    commitment_scheme_suite!(
+        kzg,
+        halo2_proofs::halo2curves::bn256::Fr,
+        (3, [7; 32]),
+        KZGMemoryCommitment<B256, B256, 32, 32>
+    );
+    commitment_scheme_suite!(
+        ipa,
+        halo2_proofs::halo2curves::bn256::Fr,
+        (6, b"zkmemory-ipa-suite".to_vec()),
+        IPAMemoryCommitment
+    );
+    commitment_scheme_suite!(
+        merkle,
+        halo2curves::pasta::Fp,
+        HashKind::Blake2b,
+        MerkleTraceCommitment
+    );
+    commitment_scheme_suite!(
+        merkle_poseidon,
+        halo2curves::pasta::Fp,
+        HashKind::Poseidon,
+        MerkleTraceCommitment
+    );
+    commitment_scheme_suite!(
+        merkle_keccak256,
+        halo2curves::pasta::Fp,
+        HashKind::Keccak256,
+        MerkleTraceCommitment
+    );
+}
@@ -0,0 +1,545 @@
+//! Commitment backend selected by the config of a machine.
+//! A [CommitmentKind] names a backend and its parameters, the machine instantiates it with
+//! [StateCommitter::new] on first use, so switching the scheme that commits the memory image
+//! and the trace is a change of
+//! [ConfigBuilder::commitment](crate::config::ConfigBuilder::commitment).
+//! The memory image is committed as the list of its non zero cells in address order, every
+//! cell is a write at time zero like in
+//! [commit_final_state](crate::machine::AbstractMemoryMachine::commit_final_state). The
+//! commitment of every backend is given as 32 bytes, the root of the Merkle backends or the
+//! compressed point of the KZG and the Pedersen backends.
+extern crate alloc;
+use crate::{
+    base::{Base, B256},
+    commitment::{
+        ipa::IPAMemoryCommitment,
+        kzg::KZGMemoryCommitment,
+        merkle::{commit_image_with, HashKind, MerkleRoot},
+        sparse::SparseMerkleTree,
+        CommitmentScheme,
+    },
+    constraints::gadgets::ConvertedTraceRecord,
+    error::Error,
+    machine::{
+        sink::{HashingSink, TraceSink},
+        MemoryInstruction, TraceRecord,
+    },
+};
+use alloc::{boxed::Box, collections::BTreeMap, rc::Rc, vec::Vec};
+use blake2b_simd::{Params, State};
+use core::cell::{OnceCell, RefCell};
+use group::{Curve, Group, GroupEncoding};
+use halo2_proofs::halo2curves::bn256::{Fr, G1};
+
+// Personalization of the hash of the KZG commitments of [CommitmentSink]
+const KZG_SINK_PERSONAL: &[u8] = b"zkmemory-kzgsink";
+
+// Widest word committed by the backends over a field, the witness has 32 byte limbs
+const FIELD_WORD_BITS: usize = 256;
+
+/// Backend committing the memory image and the trace of a machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitmentKind {
+    /// Merkle tree of the cells with the given hash, see [commit_image_with]
+    Merkle {
+        /// Hash of the nodes
+        hash: HashKind,
+    },
+    /// Sparse Merkle tree hashed with BLAKE2b over the address space, see [SparseMerkleTree]
+    SparseMerkle,
+    /// KZG commitment over bn256 with the params of [KZGMemoryCommitment::setup_with_seed],
+    /// the image is committed with [KZGMemoryCommitment::commit_trace]
+    Kzg {
+        /// The k of the params, they commit up to `2^k / 8` cells
+        k: u32,
+        /// Seed of the secret of the params
+        seed: [u8; 32],
+    },
+    /// Verkle tree, it is not supported by the machines
+    Verkle,
+    /// Pedersen commitment with the transparent generators of [IPAMemoryCommitment::new]
+    Pedersen {
+        /// There are `2^k` generators, they commit up to `2^k / 8` cells
+        k: u32,
+    },
+}
+
+impl Default for CommitmentKind {
+    fn default() -> Self {
+        CommitmentKind::Merkle {
+            hash: HashKind::Blake2b,
+        }
+    }
+}
+
+impl CommitmentKind {
+    /// Check that the backend commits the words of a machine with addresses of
+    /// `address_bytes` bytes and cells of `word_bits` bits. Return
+    /// [Error::CommitmentUnsupported] for [CommitmentKind::Verkle] and
+    /// [Error::CommitmentWordTooWide] if the KZG or the Pedersen backend would truncate a
+    /// word to the 256 bits of the witness
+    pub fn check_words(&self, address_bytes: usize, word_bits: usize) -> Result<(), Error> {
+        let bits = word_bits.max(address_bytes * 8);
+        match self {
+            CommitmentKind::Verkle => Err(Error::CommitmentUnsupported),
+            CommitmentKind::Kzg { .. } | CommitmentKind::Pedersen { .. }
+                if bits > FIELD_WORD_BITS =>
+            {
+                Err(Error::CommitmentWordTooWide { bits })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A backend of a [CommitmentKind] with its parameters
+#[derive(Debug, Clone)]
+pub struct StateCommitter {
+    scheme: Scheme,
+}
+
+// Instantiated backend, the records are converted to the witness of 256 bit words
+#[derive(Debug, Clone)]
+enum Scheme {
+    Merkle(HashKind),
+    SparseMerkle,
+    Kzg(Box<KZGMemoryCommitment<B256, B256, 32, 32>>),
+    Pedersen(IPAMemoryCommitment),
+}
+
+impl StateCommitter {
+    /// Instantiate the backend, the KZG params and the Pedersen generators are computed here.
+    /// Return [Error::CommitmentUnsupported] for [CommitmentKind::Verkle]
+    pub fn new(kind: CommitmentKind) -> Result<Self, Error> {
+        let scheme = match kind {
+            CommitmentKind::Merkle { hash } => Scheme::Merkle(hash),
+            CommitmentKind::SparseMerkle => Scheme::SparseMerkle,
+            CommitmentKind::Kzg { k, seed } => {
+                Scheme::Kzg(Box::new(KZGMemoryCommitment::setup_with_seed(k, seed)))
+            }
+            CommitmentKind::Verkle => return Err(Error::CommitmentUnsupported),
+            CommitmentKind::Pedersen { k } => Scheme::Pedersen(IPAMemoryCommitment::new(k)),
+        };
+        Ok(Self { scheme })
+    }
+
+    /// Commit the memory image, the cells can be given in any order and if a cell is given
+    /// more than once its last value is committed. Return the error of the KZG or the
+    /// Pedersen backend if the image has more cells than it commits and
+    /// [Error::WitnessWordTooWide] if it commits words wider than 256 bits
+    pub fn commit_image<K, V, const S: usize, const T: usize>(
+        &self,
+        image: &[(K, V)],
+    ) -> Result<MerkleRoot, Error>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        let mut cells: BTreeMap<K, V> = image.iter().copied().collect();
+        cells.retain(|_, value| !value.is_zero());
+        let records = || -> Result<Vec<ConvertedTraceRecord<Fr>>, Error> {
+            cells
+                .iter()
+                .map(|(address, value)| {
+                    let record = TraceRecord::<K, V, S, T>::new(
+                        0,
+                        0,
+                        MemoryInstruction::Write,
+                        *address,
+                        *value,
+                    );
+                    ConvertedTraceRecord::from_trace_record(&record)
+                })
+                .collect()
+        };
+        let mut root = [0u8; 32];
+        match &self.scheme {
+            Scheme::Merkle(hash) => return Ok(commit_image_with(*hash, image)),
+            Scheme::SparseMerkle => {
+                let mut tree = SparseMerkleTree::<K, V, S, T>::new(HashKind::Blake2b);
+                for (address, value) in cells.iter() {
+                    tree.update(*address, *value);
+                }
+                return Ok(tree.root());
+            }
+            Scheme::Kzg(scheme) => {
+                let commitment = scheme.commit_trace(&records()?)?.commitment;
+                root.copy_from_slice(commitment.to_bytes().as_ref());
+            }
+            Scheme::Pedersen(scheme) => {
+                let commitment = scheme.commit(&records()?)?.commitment;
+                root.copy_from_slice(commitment.to_bytes().as_ref());
+            }
+        }
+        Ok(root)
+    }
+}
+
+/// Get the backend kept by the cell, instantiate it on first use
+pub(crate) fn instantiate(
+    cell: &OnceCell<StateCommitter>,
+    kind: CommitmentKind,
+) -> Result<&StateCommitter, Error> {
+    if let Some(committer) = cell.get() {
+        return Ok(committer);
+    }
+    let committer = StateCommitter::new(kind)?;
+    Ok(cell.get_or_init(|| committer))
+}
+
+/// Sink that only keeps a commitment to the records with the backend of a [CommitmentKind],
+/// the backend is instantiated by the first record. The Merkle backends hash the records
+/// like a [HashingSink]. The KZG backend hashes the commitments of the records of
+/// [CommitmentScheme::commit], then their number. The Pedersen backend adds up the
+/// commitments of the records, its digest is the point of [IPAMemoryCommitment] for the
+/// whole trace. [CommitmentKind::Verkle] rejects every record. Clones are handles to the
+/// same state, [CommitmentSink::push_shared] commits through any of them
+#[derive(Debug, Clone)]
+pub struct CommitmentSink {
+    kind: CommitmentKind,
+    committer: Rc<OnceCell<StateCommitter>>,
+    state: Rc<RefCell<SinkState>>,
+}
+
+// Commitment to the records pushed so far and their number
+#[derive(Debug, Clone)]
+enum SinkState {
+    Hashing(HashingSink),
+    Kzg { state: State, len: u64 },
+    Pedersen { sum: G1, len: u64 },
+    Unsupported,
+}
+
+impl CommitmentSink {
+    /// Create a sink that has not committed any record
+    pub fn new(kind: CommitmentKind) -> Self {
+        Self::with_committer(kind, Rc::new(OnceCell::new()))
+    }
+
+    // Create a sink sharing the backend of a machine
+    pub(crate) fn with_committer(
+        kind: CommitmentKind,
+        committer: Rc<OnceCell<StateCommitter>>,
+    ) -> Self {
+        let state = match kind {
+            CommitmentKind::Merkle { .. } | CommitmentKind::SparseMerkle => {
+                SinkState::Hashing(HashingSink::new())
+            }
+            CommitmentKind::Kzg { .. } => SinkState::Kzg {
+                state: Params::new()
+                    .hash_length(32)
+                    .personal(KZG_SINK_PERSONAL)
+                    .to_state(),
+                len: 0,
+            },
+            CommitmentKind::Verkle => SinkState::Unsupported,
+            CommitmentKind::Pedersen { .. } => SinkState::Pedersen {
+                sum: G1::identity(),
+                len: 0,
+            },
+        };
+        Self {
+            kind,
+            committer,
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    /// Copy the sink into one that does not share its state, the copy goes on from the records
+    /// committed so far
+    pub fn detached(&self) -> Self {
+        let state = match &*self.state.borrow() {
+            SinkState::Hashing(hasher) => SinkState::Hashing(hasher.detached()),
+            state => state.clone(),
+        };
+        Self {
+            kind: self.kind,
+            committer: self.committer.clone(),
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    /// Get the number of records committed so far
+    pub fn len(&self) -> u64 {
+        match &*self.state.borrow() {
+            SinkState::Hashing(hasher) => hasher.len(),
+            SinkState::Kzg { len, .. } | SinkState::Pedersen { len, .. } => *len,
+            SinkState::Unsupported => 0,
+        }
+    }
+
+    /// Check if no record was committed
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the commitment to the records pushed so far, zero for [CommitmentKind::Verkle]
+    pub fn digest(&self) -> [u8; 32] {
+        let mut digest = [0u8; 32];
+        match &*self.state.borrow() {
+            SinkState::Hashing(hasher) => return hasher.digest(),
+            SinkState::Kzg { state, len } => {
+                let mut state = state.clone();
+                state.update(&len.to_le_bytes());
+                digest.copy_from_slice(state.finalize().as_bytes());
+            }
+            SinkState::Pedersen { sum, .. } => {
+                digest.copy_from_slice(sum.to_affine().to_bytes().as_ref());
+            }
+            SinkState::Unsupported => {}
+        }
+        digest
+    }
+
+    /// Commit the records of one access through a shared handle, every clone of the sink
+    /// sees them. Every record is committed before the state is updated, so either all of
+    /// them are committed or, if one can not be, none is
+    pub fn push_shared<K, V, const S: usize, const T: usize>(
+        &self,
+        records: &[TraceRecord<K, V, S, T>],
+    ) -> Result<(), Error>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        let mut state = self.state.borrow_mut();
+        let converted = || -> Result<Vec<ConvertedTraceRecord<Fr>>, Error> {
+            records
+                .iter()
+                .map(ConvertedTraceRecord::from_trace_record)
+                .collect()
+        };
+        match (
+            &mut *state,
+            &instantiate(&self.committer, self.kind)?.scheme,
+        ) {
+            (SinkState::Hashing(hasher), _) => TraceSink::<K, V, S, T>::push_all(hasher, records),
+            (SinkState::Kzg { state, len }, Scheme::Kzg(scheme)) => {
+                // The scheme has an inherent commit of a trace record
+                let commitments = converted()?
+                    .iter()
+                    .map(|record| {
+                        CommitmentScheme::commit(&**scheme, core::slice::from_ref(record))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                for commitment in commitments.iter().flatten() {
+                    state.update(commitment.to_bytes().as_ref());
+                }
+                *len += records.len() as u64;
+                Ok(())
+            }
+            (SinkState::Pedersen { sum, len }, Scheme::Pedersen(scheme)) => {
+                let terms = converted()?
+                    .iter()
+                    .enumerate()
+                    .map(|(index, record)| scheme.commit_record(*len as usize + index, record))
+                    .collect::<Result<Vec<_>, _>>()?;
+                *sum += terms
+                    .into_iter()
+                    .fold(G1::identity(), |sum, term| sum + term);
+                *len += records.len() as u64;
+                Ok(())
+            }
+            _ => Err(Error::CommitmentUnsupported),
+        }
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> TraceSink<K, V, S, T> for CommitmentSink
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn push(&mut self, record: TraceRecord<K, V, S, T>) -> Result<(), Error> {
+        self.push_shared(&[record])
+    }
+
+    fn push_all(&mut self, records: &[TraceRecord<K, V, S, T>]) -> Result<(), Error> {
+        self.push_shared(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommitmentKind, CommitmentSink, StateCommitter};
+    use crate::{
+        base::B256,
+        commitment::{
+            ipa::IPAMemoryCommitment,
+            merkle::{commit_image, HashKind},
+            CommitmentScheme,
+        },
+        constraints::gadgets::ConvertedTraceRecord,
+        error::Error,
+        machine::{
+            sink::{HashingSink, TraceSink},
+            MemoryInstruction, TraceRecord,
+        },
+    };
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use group::GroupEncoding;
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    // Records writing distinct cells
+    fn trace(len: u64) -> Vec<TraceRecord<B256, B256, 32, 32>> {
+        (0..len)
+            .map(|i| {
+                TraceRecord::new(
+                    i,
+                    0,
+                    MemoryInstruction::Write,
+                    B256::from(32 * i),
+                    B256::from(i + 1),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_commit_image() {
+        let image: Vec<(B256, B256)> = (0..4u64)
+            .map(|i| (B256::from(32 * i), B256::from(i)))
+            .collect();
+        let default = StateCommitter::new(CommitmentKind::default()).unwrap();
+        assert_eq!(default.commit_image(&image), Ok(commit_image(&image)));
+
+        let kinds = [
+            CommitmentKind::Merkle {
+                hash: HashKind::Poseidon,
+            },
+            CommitmentKind::SparseMerkle,
+            CommitmentKind::Kzg {
+                k: 5,
+                seed: [3; 32],
+            },
+            CommitmentKind::Pedersen { k: 5 },
+        ];
+        let mut roots = Vec::new();
+        for kind in kinds {
+            let committer = StateCommitter::new(kind).unwrap();
+            let root = committer.commit_image(&image).unwrap();
+            // The zero cell is not committed and the order of the cells does not matter
+            let mut shuffled = image[1..].to_vec();
+            shuffled.reverse();
+            assert_eq!(committer.commit_image(&shuffled), Ok(root));
+            assert!(!roots.contains(&root));
+            roots.push(root);
+        }
+        assert_eq!(
+            StateCommitter::new(CommitmentKind::Verkle).map(|_| ()),
+            Err(Error::CommitmentUnsupported)
+        );
+        // The Pedersen generators commit 4 cells
+        let committer = StateCommitter::new(CommitmentKind::Pedersen { k: 5 }).unwrap();
+        let image: Vec<(B256, B256)> = (1..6u64)
+            .map(|i| (B256::from(32 * i), B256::from(i)))
+            .collect();
+        assert_eq!(
+            committer.commit_image(&image),
+            Err(Error::IPAVectorTooLong { capacity: 4 })
+        );
+    }
+
+    #[test]
+    fn test_commitment_sink() {
+        let trace = trace(5);
+        let push_all = |sink: &CommitmentSink| {
+            for record in trace.iter() {
+                sink.push_shared(&[*record]).unwrap();
+            }
+        };
+        // The Merkle backends hash the records
+        let sink = CommitmentSink::new(CommitmentKind::SparseMerkle);
+        push_all(&sink);
+        let hasher = HashingSink::new();
+        for record in trace.iter() {
+            TraceSink::push(&mut hasher.clone(), *record).unwrap();
+        }
+        assert_eq!(sink.digest(), hasher.digest());
+        assert_eq!(sink.len(), 5);
+
+        // The Pedersen backend gives the commitment of the whole trace
+        let sink = CommitmentSink::new(CommitmentKind::Pedersen { k: 6 });
+        assert!(sink.is_empty());
+        let detached = sink.detached();
+        push_all(&sink);
+        let records: Vec<ConvertedTraceRecord<Fr>> = trace
+            .iter()
+            .map(ConvertedTraceRecord::from_trace_record)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let commitment = IPAMemoryCommitment::new(6).commit(&records).unwrap();
+        assert_eq!(
+            sink.digest().as_slice(),
+            commitment.commitment.to_bytes().as_ref()
+        );
+        assert!(detached.is_empty());
+        assert_ne!(detached.digest(), sink.digest());
+        // The generators hold 8 records
+        let mut full = sink.clone();
+        for record in trace.iter().take(3) {
+            full.push(*record).unwrap();
+        }
+        assert_eq!(
+            full.push(trace[0]),
+            Err(Error::IPAVectorTooLong { capacity: 8 })
+        );
+        // The records of one access are committed together or not at all
+        let mut nearly_full = sink.detached();
+        nearly_full.push_all(&trace[..2]).unwrap();
+        let digest = nearly_full.digest();
+        assert_eq!(
+            nearly_full.push_all(&trace[..2]),
+            Err(Error::IPAVectorTooLong { capacity: 8 })
+        );
+        assert_eq!((nearly_full.len(), nearly_full.digest()), (7, digest));
+        nearly_full.push_all(&trace[..1]).unwrap();
+        assert_eq!(nearly_full.len(), 8);
+
+        let kzg = CommitmentSink::new(CommitmentKind::Kzg {
+            k: 4,
+            seed: [3; 32],
+        });
+        push_all(&kzg);
+        assert_eq!(kzg.len(), 5);
+        let mut other = kzg.detached();
+        other.push(trace[0]).unwrap();
+        assert_ne!(other.digest(), kzg.digest());
+
+        let mut verkle = CommitmentSink::new(CommitmentKind::Verkle);
+        assert_eq!(verkle.push(trace[0]), Err(Error::CommitmentUnsupported));
+    }
+
+    #[test]
+    fn test_check_words() {
+        for kind in [
+            CommitmentKind::default(),
+            CommitmentKind::SparseMerkle,
+            CommitmentKind::Kzg {
+                k: 4,
+                seed: [0; 32],
+            },
+            CommitmentKind::Pedersen { k: 4 },
+        ] {
+            assert_eq!(kind.check_words(32, 256), Ok(()));
+        }
+        assert_eq!(CommitmentKind::default().check_words(64, 512), Ok(()));
+        assert_eq!(
+            CommitmentKind::Kzg {
+                k: 4,
+                seed: [0; 32]
+            }
+            .check_words(64, 256),
+            Err(Error::CommitmentWordTooWide { bits: 512 })
+        );
+        assert_eq!(
+            CommitmentKind::Pedersen { k: 4 }.check_words(64, 512),
+            Err(Error::CommitmentWordTooWide { bits: 512 })
+        );
+        assert_eq!(
+            CommitmentKind::Verkle.check_words(32, 256),
+            Err(Error::CommitmentUnsupported)
+        );
+    }
+}
@@ -0,0 +1,860 @@
+//! Sparse Merkle tree over the whole address space of a memory.
+//! The tree has one leaf per address, the depth is the bit length of the address and the
+//! leaf of an address is below the left child of a node when its bit of the level is zero,
+//! from the least significant bit at the leaves to the most significant one below the root.
+//! An address holding zero is an empty leaf, so an address that was never written is proven
+//! absent. The subtrees without a stored cell hash to the precomputed default of their level
+//! and are not stored, an empty tree is built with one hash per level.
+//! A [RangeEmptinessProof] proves that every address of a range holds zero.
+//! A [SparseUpdateProof] proves the change of the value of an address between two roots.
+//! [LiveRoot] installs a tree as the observer of a machine to follow its root during the
+//! execution.
+extern crate alloc;
+use crate::{
+    base::Base,
+    commitment::{
+        incremental::IncrementalCommitment,
+        merkle::{check_root, HashKind, MerkleRoot},
+    },
+    error::{Error, VerifyError},
+    machine::{observer::MemoryObserver, CellDelta},
+};
+use alloc::{collections::BTreeMap, rc::Rc, vec::Vec};
+use core::cell::RefCell;
+
+// Get the bit of the address of a level of the tree
+pub(crate) fn bit(address: &[u8], level: usize) -> bool {
+    (address[address.len() - 1 - level / 8] >> (level % 8)) & 1 == 1
+}
+
+// Get the hash of the empty subtree of every level of a tree of the depth, from the leaves
+// to the root
+pub(crate) fn default_nodes(hash: HashKind, depth: usize) -> Vec<MerkleRoot> {
+    let mut defaults = Vec::with_capacity(depth + 1);
+    defaults.push(hash.empty());
+    for level in 0..depth {
+        defaults.push(hash.node(&defaults[level], &defaults[level]));
+    }
+    defaults
+}
+
+// Get the index of the node of a level above the address, the bits below the level are cleared
+fn prefix<const S: usize>(mut address: [u8; S], level: usize) -> [u8; S] {
+    let cleared = (level / 8).min(S);
+    address[S - cleared..].fill(0);
+    if cleared < S {
+        address[S - 1 - cleared] &= 0xff << (level % 8);
+    }
+    address
+}
+
+// Get the index of the sibling of a node of a level
+fn sibling<const S: usize>(mut index: [u8; S], level: usize) -> [u8; S] {
+    index[S - 1 - level / 8] ^= 1 << (level % 8);
+    index
+}
+
+// Get the last address below the node of a level, the bits below the level are set
+fn last<const S: usize>(mut index: [u8; S], level: usize) -> [u8; S] {
+    let set = (level / 8).min(S);
+    index[S - set..].fill(0xff);
+    if set < S {
+        index[S - 1 - set] |= !(0xffu8 << (level % 8));
+    }
+    index
+}
+
+// Get the address following an address, None after the last address
+fn next<const S: usize>(mut index: [u8; S]) -> Option<[u8; S]> {
+    for byte in index.iter_mut().rev() {
+        if *byte == 0xff {
+            *byte = 0;
+        } else {
+            *byte += 1;
+            return Some(index);
+        }
+    }
+    None
+}
+
+// Get the fewest subtrees covering the addresses from lo to hi by level and index, from the
+// lowest addresses. Each subtree is the highest node starting at the first uncovered address
+// which does not go past hi
+fn cover<const S: usize>(lo: [u8; S], hi: [u8; S]) -> Vec<(usize, [u8; S])> {
+    let mut subtrees = Vec::new();
+    let mut start = Some(lo).filter(|start| *start <= hi);
+    while let Some(index) = start {
+        let mut level = 0;
+        while level < S * 8 && prefix(index, level + 1) == index && last(index, level + 1) <= hi {
+            level += 1;
+        }
+        subtrees.push((level, index));
+        start = next(last(index, level)).filter(|start| *start <= hi);
+    }
+    subtrees
+}
+
+/// Sparse Merkle tree of the cells of a memory keyed by address, see the
+/// [module](crate::commitment::sparse). The leaves of the stored cells are hashed like the
+/// leaves of [commit_image](crate::commitment::merkle::commit_image)
+#[derive(Debug, Clone)]
+pub struct SparseMerkleTree<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    hash: HashKind,
+    // Hash of the empty subtree of every level, from the leaves to the root
+    defaults: Vec<MerkleRoot>,
+    // Nodes which are not the default of their level, by level and index
+    nodes: BTreeMap<(usize, [u8; S]), MerkleRoot>,
+    cells: BTreeMap<K, V>,
+}
+
+impl<K, V, const S: usize, const T: usize> SparseMerkleTree<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Depth of the tree, the bit length of the addresses
+    pub const DEPTH: usize = S * 8;
+
+    /// Create the empty tree hashed with the given hash
+    pub fn new(hash: HashKind) -> Self {
+        Self {
+            hash,
+            defaults: default_nodes(hash, Self::DEPTH),
+            nodes: BTreeMap::new(),
+            cells: BTreeMap::new(),
+        }
+    }
+
+    /// Get the root of the tree
+    pub fn root(&self) -> MerkleRoot {
+        self.node(Self::DEPTH, [0u8; S])
+    }
+
+    /// Get the value of an address, zero if it was never written
+    pub fn get(&self, address: K) -> V {
+        self.cells.get(&address).copied().unwrap_or(V::zero())
+    }
+
+    /// Set the value of an address and return its previous value, writing zero removes the
+    /// leaf of the address
+    pub fn update(&mut self, address: K, value: V) -> V {
+        let index: [u8; S] = address.into();
+        let (previous, mut node) = if value.is_zero() {
+            (self.cells.remove(&address), self.defaults[0])
+        } else {
+            let bytes: [u8; T] = value.into();
+            let leaf = self.hash.cell_leaf(&index, &bytes);
+            (self.cells.insert(address, value), leaf)
+        };
+        for level in 0..Self::DEPTH {
+            let position = prefix(index, level);
+            self.set_node(level, position, node);
+            let sibling = self.node(level, sibling(position, level));
+            node = if bit(&index, level) {
+                self.hash.node(&sibling, &node)
+            } else {
+                self.hash.node(&node, &sibling)
+            };
+        }
+        self.set_node(Self::DEPTH, [0u8; S], node);
+        previous.unwrap_or(V::zero())
+    }
+
+    /// Get the value of an address with the proof of its inclusion, None if the address holds
+    /// zero, see [SparseMerkleTree::prove_absence]
+    pub fn get_with_proof(&self, address: K) -> Option<(V, SparseMerkleProof)> {
+        let value = *self.cells.get(&address)?;
+        Some((value, self.proof(address)))
+    }
+
+    /// Prove that an address holds zero, None if it holds a value
+    pub fn prove_absence(&self, address: K) -> Option<SparseMerkleProof> {
+        if self.cells.contains_key(&address) {
+            return None;
+        }
+        Some(self.proof(address))
+    }
+
+    /// Prove that every address from lo to hi holds zero, None if one of them holds a value or
+    /// lo is above hi. An aligned range is a single subtree, so its proof is a single path
+    pub fn prove_empty_range(&self, lo: K, hi: K) -> Option<RangeEmptinessProof> {
+        if lo > hi || self.cells.range(lo..=hi).next().is_some() {
+            return None;
+        }
+        let paths = cover::<S>(lo.into(), hi.into())
+            .into_iter()
+            .map(|(level, index)| {
+                (level..Self::DEPTH)
+                    .map(|level| self.node(level, sibling(prefix(index, level), level)))
+                    .collect()
+            })
+            .collect();
+        Some(RangeEmptinessProof {
+            hash: self.hash,
+            paths,
+        })
+    }
+
+    /// Prove that setting the address to the new value moves the tree from its root to the
+    /// root of [SparseMerkleTree::update], the tree is not changed. An address holding zero is
+    /// an empty slot, so an insertion is proven from zero and a removal to zero
+    pub fn prove_update(&self, address: K, value: V) -> SparseUpdateProof<K, V> {
+        let proof = self.proof(address);
+        let index: [u8; S] = address.into();
+        let new_root = proof
+            .fold(&index, proof.leaf(&index, value))
+            .unwrap_or_else(|_| self.root());
+        SparseUpdateProof {
+            address,
+            old_value: self.get(address),
+            new_value: value,
+            siblings: proof.siblings,
+            hash: self.hash,
+            old_root: self.root(),
+            new_root,
+        }
+    }
+
+    // Get the siblings of the path of an address
+    fn proof(&self, address: K) -> SparseMerkleProof {
+        let index: [u8; S] = address.into();
+        SparseMerkleProof {
+            hash: self.hash,
+            siblings: (0..Self::DEPTH)
+                .map(|level| self.node(level, sibling(prefix(index, level), level)))
+                .collect(),
+        }
+    }
+
+    // Get a node of the tree
+    fn node(&self, level: usize, index: [u8; S]) -> MerkleRoot {
+        self.nodes
+            .get(&(level, index))
+            .copied()
+            .unwrap_or(self.defaults[level])
+    }
+
+    // Set a node of the tree, the defaults are not stored
+    fn set_node(&mut self, level: usize, index: [u8; S], node: MerkleRoot) {
+        if node == self.defaults[level] {
+            self.nodes.remove(&(level, index));
+        } else {
+            self.nodes.insert((level, index), node);
+        }
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> Default for SparseMerkleTree<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn default() -> Self {
+        Self::new(HashKind::default())
+    }
+}
+
+/// A delta is rejected with [Error::MemoryInvalidInteraction] if its previous value is not
+/// the value of the tree
+impl<K, V, const S: usize, const T: usize> IncrementalCommitment<K, V>
+    for SparseMerkleTree<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn update_cell(&mut self, delta: &CellDelta<K, V>) -> Result<(), Error> {
+        if self.get(delta.addr) != delta.before {
+            return Err(Error::MemoryInvalidInteraction);
+        }
+        self.update(delta.addr, delta.after);
+        Ok(())
+    }
+}
+
+/// Observer keeping the root of the memory of a machine up to date during the execution,
+/// every write updates the path of its cell in a [SparseMerkleTree]. Clones share the tree,
+/// keep a clone to read the root of the tree installed on the machine
+#[derive(Debug, Clone)]
+pub struct LiveRoot<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    tree: Rc<RefCell<SparseMerkleTree<K, V, S, T>>>,
+}
+
+impl<K, V, const S: usize, const T: usize> LiveRoot<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Follow the writes from the tree of the memory before the execution
+    pub fn new(tree: SparseMerkleTree<K, V, S, T>) -> Self {
+        Self {
+            tree: Rc::new(RefCell::new(tree)),
+        }
+    }
+
+    /// Get the root of the memory after the last write
+    pub fn root(&self) -> MerkleRoot {
+        self.tree.borrow().root()
+    }
+
+    /// Get a copy of the followed tree, e.g. to prove a cell
+    pub fn tree(&self) -> SparseMerkleTree<K, V, S, T> {
+        self.tree.borrow().clone()
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> MemoryObserver<K, V> for LiveRoot<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn on_read(&mut self, _address: K, _value: V, _time_log: u64) {}
+
+    fn on_write(&mut self, address: K, _old: V, new: V, _time_log: u64) {
+        self.tree.borrow_mut().update(address, new);
+    }
+}
+
+/// Proof of the leaf of an address in a [SparseMerkleTree]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseMerkleProof {
+    /// Hash of the tree
+    pub hash: HashKind,
+    /// Siblings of the nodes from the leaf up to the root, one per level
+    pub siblings: Vec<MerkleRoot>,
+}
+
+impl SparseMerkleProof {
+    /// Check that the address holds the value in the tree of the root, the value is not zero
+    pub fn verify_inclusion<K, V, const S: usize, const T: usize>(
+        &self,
+        root: &MerkleRoot,
+        address: K,
+        value: V,
+    ) -> Result<(), VerifyError>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        if value.is_zero() {
+            return Err(VerifyError::ZeroValue);
+        }
+        let address: [u8; S] = address.into();
+        let bytes: [u8; T] = value.into();
+        check_root(
+            root,
+            self.fold(&address, self.hash.cell_leaf(&address, &bytes))?,
+        )
+    }
+
+    /// Check that the address holds zero in the tree of the root
+    pub fn verify_absence<K: Base<S>, const S: usize>(
+        &self,
+        root: &MerkleRoot,
+        address: K,
+    ) -> Result<(), VerifyError> {
+        let address: [u8; S] = address.into();
+        check_root(root, self.fold(&address, self.hash.empty())?)
+    }
+
+    // Get the leaf of an address holding the value, the empty leaf for zero
+    fn leaf<V: Base<T>, const T: usize>(&self, address: &[u8], value: V) -> MerkleRoot {
+        if value.is_zero() {
+            self.hash.empty()
+        } else {
+            let bytes: [u8; T] = value.into();
+            self.hash.cell_leaf(address, &bytes)
+        }
+    }
+
+    // Hash the leaf of the address up to the root, the proof must be as deep as the address
+    // is long
+    fn fold(&self, address: &[u8], leaf: MerkleRoot) -> Result<MerkleRoot, VerifyError> {
+        if self.siblings.len() != address.len() * 8 {
+            return Err(VerifyError::PathLengthMismatch {
+                expected: address.len() * 8,
+                found: self.siblings.len(),
+            });
+        }
+        let root = self
+            .siblings
+            .iter()
+            .enumerate()
+            .fold(leaf, |node, (level, sibling)| {
+                if bit(address, level) {
+                    self.hash.node(sibling, &node)
+                } else {
+                    self.hash.node(&node, sibling)
+                }
+            });
+        Ok(root)
+    }
+}
+
+/// Proof that a range of addresses of a [SparseMerkleTree] holds zero. The range is covered
+/// by the fewest subtrees and each of them is shown to hash to the empty subtree of its level
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeEmptinessProof {
+    /// Hash of the tree
+    pub hash: HashKind,
+    /// Siblings of the nodes from the root of every covering subtree up to the root of the
+    /// tree, from the subtree of the lowest addresses
+    pub paths: Vec<Vec<MerkleRoot>>,
+}
+
+impl RangeEmptinessProof {
+    /// Check that every address from lo to hi holds zero in the tree of the root
+    pub fn verify_empty_range<K: Base<S>, const S: usize>(
+        &self,
+        root: &MerkleRoot,
+        lo: K,
+        hi: K,
+    ) -> Result<(), VerifyError> {
+        if lo > hi {
+            return Err(VerifyError::InvalidRange);
+        }
+        let subtrees = cover::<S>(lo.into(), hi.into());
+        if subtrees.len() != self.paths.len() {
+            return Err(VerifyError::PathLengthMismatch {
+                expected: subtrees.len(),
+                found: self.paths.len(),
+            });
+        }
+        let defaults = default_nodes(self.hash, S * 8);
+        subtrees
+            .iter()
+            .zip(self.paths.iter())
+            .try_for_each(|((level, index), path)| {
+                if path.len() != S * 8 - level {
+                    return Err(VerifyError::PathLengthMismatch {
+                        expected: S * 8 - level,
+                        found: path.len(),
+                    });
+                }
+                let node =
+                    path.iter()
+                        .zip(*level..)
+                        .fold(defaults[*level], |node, (sibling, level)| {
+                            if bit(index, level) {
+                                self.hash.node(sibling, &node)
+                            } else {
+                                self.hash.node(&node, sibling)
+                            }
+                        });
+                check_root(root, node)
+            })
+    }
+}
+
+/// Proof that an address of a [SparseMerkleTree] changed from an old value to a new value
+/// between two roots. A single path proves both roots since the siblings of the address do not
+/// change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseUpdateProof<K, V> {
+    /// The updated address
+    pub address: K,
+    /// Value of the address before the update, zero for an empty slot
+    pub old_value: V,
+    /// Value of the address after the update
+    pub new_value: V,
+    /// Siblings of the nodes from the leaf up to the root, one per level
+    pub siblings: Vec<MerkleRoot>,
+    /// Hash of the tree
+    pub hash: HashKind,
+    /// Root before the update
+    pub old_root: MerkleRoot,
+    /// Root after the update
+    pub new_root: MerkleRoot,
+}
+
+impl<K, V> SparseUpdateProof<K, V> {
+    /// Check that the siblings hash the old value to the old root and the new value to the
+    /// new root
+    pub fn verify_update<const S: usize, const T: usize>(&self) -> Result<(), VerifyError>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        let proof = SparseMerkleProof {
+            hash: self.hash,
+            siblings: self.siblings.clone(),
+        };
+        let address: [u8; S] = self.address.into();
+        let fold = |value: V| proof.fold(&address, proof.leaf(&address, value));
+        check_root(&self.old_root, fold(self.old_value)?)?;
+        check_root(&self.new_root, fold(self.new_value)?)
+    }
+
+    /// Check the proof like [SparseUpdateProof::verify_update], without the reason of a
+    /// rejection
+    pub fn is_valid<const S: usize, const T: usize>(&self) -> bool
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        self.verify_update::<S, T>().is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LiveRoot, SparseMerkleTree};
+    use crate::{
+        base::{Base, B256, B32},
+        commitment::{incremental::IncrementalCommitment, merkle::HashKind},
+        config::DefaultConfig,
+        error::{Error, VerifyError},
+        machine::{tests::StateMachine, AbstractMachine, AbstractMemoryMachine},
+    };
+    extern crate alloc;
+    use alloc::{boxed::Box, vec::Vec};
+    use rand::{seq::SliceRandom, Rng};
+
+    fn cells(len: u64) -> Vec<(B256, B256)> {
+        (0..len)
+            .map(|i| (B256::from(i * 32), B256::from(i + 1)))
+            .collect()
+    }
+
+    #[test]
+    fn test_inclusion_and_exclusion() {
+        let mut tree = SparseMerkleTree::<B256, B256, 32, 32>::default();
+        assert_eq!(SparseMerkleTree::<B256, B256, 32, 32>::DEPTH, 256);
+        let empty = tree.root();
+        let proof = tree.prove_absence(B256::from(64)).unwrap();
+        assert_eq!(proof.verify_absence(&empty, B256::from(64)), Ok(()));
+        assert!(tree.get_with_proof(B256::from(64)).is_none());
+
+        for (address, value) in cells(5) {
+            assert_eq!(tree.update(address, value), B256::zero());
+        }
+        let root = tree.root();
+        assert_ne!(root, empty);
+        for (address, value) in cells(5) {
+            let (found, proof) = tree.get_with_proof(address).unwrap();
+            assert_eq!(found, value);
+            assert_eq!(proof.siblings.len(), 256);
+            assert_eq!(proof.verify_inclusion(&root, address, value), Ok(()));
+            assert!(matches!(
+                proof.verify_inclusion(&root, address, value + B256::from(1)),
+                Err(VerifyError::RootMismatch { expected, .. }) if expected == root
+            ));
+            assert!(matches!(
+                proof.verify_inclusion(&empty, address, value),
+                Err(VerifyError::RootMismatch { expected, computed })
+                    if expected == empty && computed == root
+            ));
+            assert!(proof.verify_absence(&root, address).is_err());
+            assert_eq!(
+                proof.verify_inclusion(&root, address, B256::zero()),
+                Err(VerifyError::ZeroValue)
+            );
+            assert!(tree.prove_absence(address).is_none());
+        }
+
+        // The addresses next to, between and far from the cells were never written
+        let mut rng = rand::thread_rng();
+        let mut absent: Vec<B256> = [5 * 32, 16, 1, 31].into_iter().map(B256::from).collect();
+        absent.push(B256::from(rng.gen::<u64>()) * B256::from(1u64 << 40) + B256::from(1));
+        for address in absent {
+            let proof = tree.prove_absence(address).unwrap();
+            assert_eq!(proof.verify_absence(&root, address), Ok(()));
+            assert!(proof.verify_absence(&empty, address).is_err());
+            assert!(proof
+                .verify_inclusion(&root, address, B256::from(1))
+                .is_err());
+        }
+
+        // A proof of another depth is rejected
+        let mut proof = tree.prove_absence(B256::from(16)).unwrap();
+        proof.siblings.pop();
+        assert_eq!(
+            proof.verify_absence(&root, B256::from(16)),
+            Err(VerifyError::PathLengthMismatch {
+                expected: 256,
+                found: 255
+            })
+        );
+    }
+
+    #[test]
+    fn test_update_then_prove() {
+        for hash in [HashKind::Blake2b, HashKind::Poseidon, HashKind::Keccak256] {
+            let mut tree = SparseMerkleTree::<B32, B32, 4, 4>::new(hash);
+            let empty = tree.root();
+            let address = B32::from(8u64);
+            tree.update(B32::from(4u64), B32::from(7u64));
+            tree.update(address, B32::from(1u64));
+            let (_, before) = tree.get_with_proof(address).unwrap();
+            let first = tree.root();
+
+            assert_eq!(tree.update(address, B32::from(2u64)), B32::from(1u64));
+            let (value, after) = tree.get_with_proof(address).unwrap();
+            assert_eq!(value, B32::from(2u64));
+            assert_eq!(after.verify_inclusion(&tree.root(), address, value), Ok(()));
+            assert!(before
+                .verify_inclusion(&tree.root(), address, B32::from(1u64))
+                .is_err());
+            assert_eq!(
+                before.verify_inclusion(&first, address, B32::from(1u64)),
+                Ok(())
+            );
+
+            // Writing zero removes the leaf
+            assert_eq!(tree.update(address, B32::zero()), B32::from(2u64));
+            assert_eq!(
+                tree.prove_absence(address)
+                    .unwrap()
+                    .verify_absence(&tree.root(), address),
+                Ok(())
+            );
+            tree.update(B32::from(4u64), B32::zero());
+            assert_eq!(tree.root(), empty);
+            assert!(tree.nodes.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_root_stability() {
+        let mut rng = rand::thread_rng();
+        for hash in [HashKind::Blake2b, HashKind::Poseidon, HashKind::Keccak256] {
+            let mut cells = cells(12);
+            let mut tree = SparseMerkleTree::<B256, B256, 32, 32>::new(hash);
+            cells.iter().for_each(|(address, value)| {
+                tree.update(*address, *value);
+            });
+            let root = tree.root();
+            for _ in 0..3 {
+                cells.shuffle(&mut rng);
+                let mut shuffled = SparseMerkleTree::<B256, B256, 32, 32>::new(hash);
+                // Overwritten and removed cells do not change the root
+                shuffled.update(B256::from(0x1000), B256::from(9));
+                for (address, value) in cells.iter() {
+                    shuffled.update(*address, *value + B256::from(1));
+                    shuffled.update(*address, *value);
+                }
+                shuffled.update(B256::from(0x1000), B256::zero());
+                assert_eq!(shuffled.root(), root);
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply_diff() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let mut tree = SparseMerkleTree::<B256, B256, 32, 32>::default();
+        let genesis = sm.snapshot();
+        for i in 0..6 {
+            sm.write(base + B256::from(i * 32), B256::from(i + 1))
+                .unwrap();
+        }
+        let snapshot = sm.snapshot();
+        tree.apply_diff(&genesis.diff(&snapshot)).unwrap();
+
+        let mut expected = SparseMerkleTree::<B256, B256, 32, 32>::default();
+        for (address, value) in snapshot.cells() {
+            expected.update(*address, *value);
+        }
+        assert_eq!(tree.root(), expected.root());
+        assert_eq!(
+            tree.apply_diff(&genesis.diff(&snapshot)),
+            Err(Error::MemoryInvalidInteraction)
+        );
+    }
+
+    #[test]
+    fn test_live_root() {
+        let mut rng = rand::thread_rng();
+        let mut sm = StateMachine::<B32, B32, 4, 4>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let live = LiveRoot::new(SparseMerkleTree::default());
+        sm.set_observer(Box::new(live.clone()));
+        for _ in 0..10_000 {
+            let address = base + B32::from(rng.gen_range(0..64u64) * 4);
+            // Some writes clear their cell
+            sm.write(address, B32::from(rng.gen_range(0..4u64)))
+                .unwrap();
+        }
+
+        // The root followed during the execution is the root of the final memory
+        let snapshot = sm.snapshot();
+        let mut rebuilt = SparseMerkleTree::<B32, B32, 4, 4>::default();
+        for (address, value) in snapshot.cells() {
+            rebuilt.update(*address, *value);
+        }
+        assert_eq!(live.root(), rebuilt.root());
+        let tree = live.tree();
+        for (address, value) in snapshot
+            .cells()
+            .iter()
+            .filter(|(_, value)| !value.is_zero())
+        {
+            let (found, proof) = tree.get_with_proof(*address).unwrap();
+            assert_eq!(found, *value);
+            assert_eq!(
+                proof.verify_inclusion(&live.root(), *address, *value),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn test_prove_update() {
+        for hash in [HashKind::Blake2b, HashKind::Poseidon, HashKind::Keccak256] {
+            let mut tree = SparseMerkleTree::<B32, B32, 4, 4>::new(hash);
+            tree.update(B32::from(4u64), B32::from(7u64));
+            let address = B32::from(8u64);
+            // Insertion into an empty slot, a change of value and a removal
+            for value in [3u64, 5, 0] {
+                let value = B32::from(value);
+                let proof = tree.prove_update(address, value);
+                assert_eq!(proof.old_value, tree.get(address));
+                assert_eq!(proof.old_root, tree.root());
+                assert_eq!(proof.verify_update(), Ok(()));
+                assert!(proof.is_valid());
+                tree.update(address, value);
+                assert_eq!(proof.new_root, tree.root());
+
+                // The values are bound to their roots
+                let mut swapped = proof.clone();
+                swapped.old_value = proof.new_value;
+                swapped.new_value = proof.old_value;
+                assert!(!swapped.is_valid());
+                let mut wrong = proof.clone();
+                wrong.new_value = value + B32::from(1u64);
+                assert!(matches!(
+                    wrong.verify_update(),
+                    Err(VerifyError::RootMismatch { expected, .. }) if expected == proof.new_root
+                ));
+
+                // The path is the one of the address
+                let mut tampered = proof.clone();
+                tampered.siblings[2][0] ^= 1;
+                assert!(matches!(
+                    tampered.verify_update(),
+                    Err(VerifyError::RootMismatch { expected, .. }) if expected == proof.old_root
+                ));
+                let mut moved = proof.clone();
+                moved.address = B32::from(12u64);
+                assert!(!moved.is_valid());
+                let mut short = proof.clone();
+                short.siblings.pop();
+                assert_eq!(
+                    short.verify_update(),
+                    Err(VerifyError::PathLengthMismatch {
+                        expected: 32,
+                        found: 31
+                    })
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_range() {
+        fn address(address: u64) -> B32 {
+            B32::from(address)
+        }
+        for hash in [HashKind::Blake2b, HashKind::Poseidon, HashKind::Keccak256] {
+            let mut tree = SparseMerkleTree::<B32, B32, 4, 4>::new(hash);
+            let whole = tree
+                .prove_empty_range(address(0), address(0xffff_ffff))
+                .unwrap();
+            assert_eq!(whole.paths, [Vec::new()]);
+            assert_eq!(
+                whole.verify_empty_range(&tree.root(), address(0), address(0xffff_ffff)),
+                Ok(())
+            );
+
+            // The writes next to the ranges do not break them
+            for (cell, value) in [(0xff, 1), (0x200, 2), (0x1_0000, 3)] {
+                tree.update(address(cell), address(value));
+            }
+            let root = tree.root();
+            let aligned = tree
+                .prove_empty_range(address(0x100), address(0x1ff))
+                .unwrap();
+            assert_eq!(aligned.paths.len(), 1);
+            assert_eq!(aligned.paths[0].len(), 24);
+            assert_eq!(
+                aligned.verify_empty_range(&root, address(0x100), address(0x1ff)),
+                Ok(())
+            );
+            let unaligned = tree
+                .prove_empty_range(address(0x201), address(0xfffe))
+                .unwrap();
+            assert!(unaligned.paths.len() > 1 && unaligned.paths.len() <= 2 * 32);
+            assert_eq!(
+                unaligned.verify_empty_range(&root, address(0x201), address(0xfffe)),
+                Ok(())
+            );
+            assert!(matches!(
+                whole.verify_empty_range(&root, address(0), address(0xffff_ffff)),
+                Err(VerifyError::RootMismatch { expected, .. }) if expected == root
+            ));
+
+            // The proof is bound to its range and its root
+            assert_eq!(
+                aligned.verify_empty_range(&root, address(0x100), address(0x17f)),
+                Err(VerifyError::PathLengthMismatch {
+                    expected: 25,
+                    found: 24
+                })
+            );
+            assert!(unaligned
+                .verify_empty_range(&root, address(0x202), address(0xfffe))
+                .is_err());
+            assert_eq!(
+                aligned.verify_empty_range(&root, address(0x1ff), address(0x100)),
+                Err(VerifyError::InvalidRange)
+            );
+            let mut tampered = unaligned.clone();
+            tampered.paths[1][0][0] ^= 1;
+            assert!(matches!(
+                tampered.verify_empty_range(&root, address(0x201), address(0xfffe)),
+                Err(VerifyError::RootMismatch { expected, .. }) if expected == root
+            ));
+            let mut short = aligned.clone();
+            short.paths[0].pop();
+            assert_eq!(
+                short.verify_empty_range(&root, address(0x100), address(0x1ff)),
+                Err(VerifyError::PathLengthMismatch {
+                    expected: 24,
+                    found: 23
+                })
+            );
+
+            // A range holding a value has no proof
+            assert!(tree
+                .prove_empty_range(address(0x100), address(0x200))
+                .is_none());
+            assert!(tree
+                .prove_empty_range(address(0x1ff), address(0x100))
+                .is_none());
+
+            // A single write anywhere in the range breaks its proof until it is cleared
+            for cell in [0x201, 0x7ff, 0x1234, 0xfffe] {
+                tree.update(address(cell), address(5));
+                assert!(tree
+                    .prove_empty_range(address(0x201), address(0xfffe))
+                    .is_none());
+                assert!(unaligned
+                    .verify_empty_range(&tree.root(), address(0x201), address(0xfffe))
+                    .is_err());
+                tree.update(address(cell), B32::zero());
+                assert_eq!(
+                    unaligned.verify_empty_range(&tree.root(), address(0x201), address(0xfffe)),
+                    Ok(())
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,684 @@
+//! Persistent storage of the nodes of the Merkle trees.
+//! A [NodeStore] keeps the nodes of trees by their hash, the children of an inner node and
+//! the address and the value of the leaf of a cell. A tree is reopened from its root alone and
+//! the trees of a store share their common subtrees, so with a persisted store a tree outlives
+//! the prover which built it. [MemoryNodeStore] keeps the nodes in memory and, with the `std`
+//! feature, [FileNodeStore] appends them to a file.
+//! [StoredSparseTree] is a [SparseMerkleTree](crate::commitment::sparse::SparseMerkleTree)
+//! reading and writing its nodes through a store, with the roots and the proofs of the
+//! in-memory tree. A [MerkleTree](crate::commitment::merkle::MerkleTree) is saved to a store
+//! and loaded back from its root.
+//! An update leaves the nodes of the previous root in the store, so the previous versions of a
+//! tree can still be opened until [StoredSparseTree::compact] removes them.
+extern crate alloc;
+use crate::{
+    base::Base,
+    commitment::{
+        merkle::{HashKind, MerkleRoot},
+        sparse::{bit, default_nodes, SparseMerkleProof},
+    },
+    error::Error,
+};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec,
+    vec::Vec,
+};
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// Magic bytes at the start of the file of a [FileNodeStore]
+#[cfg(feature = "std")]
+pub const NODE_STORE_MAGIC: [u8; 8] = *b"ZKMNODES";
+
+// Tag of an encoded branch
+#[cfg(feature = "std")]
+const BRANCH_TAG: u8 = 0;
+
+// Tag of an encoded leaf
+#[cfg(feature = "std")]
+const LEAF_TAG: u8 = 1;
+
+/// Node of a Merkle tree, stored under its hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    /// Inner node, the hash of the node of its children
+    Branch {
+        /// Hash of the left child
+        left: MerkleRoot,
+        /// Hash of the right child
+        right: MerkleRoot,
+    },
+    /// Leaf of a cell of a sparse tree, the hash of the cell
+    Leaf {
+        /// Bytes of the address
+        address: Vec<u8>,
+        /// Bytes of the value
+        value: Vec<u8>,
+    },
+}
+
+/// Storage of the nodes of Merkle trees keyed by their hash, see the
+/// [module](crate::commitment::store). The empty subtrees are not stored
+pub trait NodeStore {
+    /// Get the node of a hash, None if the store has no such node
+    fn get(&self, hash: &MerkleRoot) -> Result<Option<Node>, Error>;
+
+    /// Store a node under its hash, a node stored twice is kept once
+    fn put(&mut self, hash: MerkleRoot, node: Node) -> Result<(), Error>;
+
+    /// Get the hashes of all stored nodes
+    fn hashes(&self) -> Result<Vec<MerkleRoot>, Error>;
+
+    /// Remove the nodes of the hashes from the store
+    fn remove(&mut self, hashes: &BTreeSet<MerkleRoot>) -> Result<(), Error>;
+}
+
+/// Node store in memory, it cannot fail
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryNodeStore {
+    nodes: BTreeMap<MerkleRoot, Node>,
+}
+
+impl MemoryNodeStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the number of stored nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Check whether the store has no node
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl NodeStore for MemoryNodeStore {
+    fn get(&self, hash: &MerkleRoot) -> Result<Option<Node>, Error> {
+        Ok(self.nodes.get(hash).cloned())
+    }
+
+    fn put(&mut self, hash: MerkleRoot, node: Node) -> Result<(), Error> {
+        self.nodes.entry(hash).or_insert(node);
+        Ok(())
+    }
+
+    fn hashes(&self) -> Result<Vec<MerkleRoot>, Error> {
+        Ok(self.nodes.keys().copied().collect())
+    }
+
+    fn remove(&mut self, hashes: &BTreeSet<MerkleRoot>) -> Result<(), Error> {
+        self.nodes.retain(|hash, _| !hashes.contains(hash));
+        Ok(())
+    }
+}
+
+/// Node store appending the nodes to a file, only the offsets of the nodes are kept in
+/// memory. The file is [NODE_STORE_MAGIC] followed by the nodes, a node is its hash, a tag
+/// byte, then the hashes of the children of a branch or the length of the address as a big
+/// endian u32, the address, the length of the value and the value of a leaf.
+/// Removing nodes rewrites the file
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct FileNodeStore {
+    path: PathBuf,
+    file: File,
+    // Offset of the tag of every stored node
+    offsets: BTreeMap<MerkleRoot, u64>,
+}
+
+#[cfg(feature = "std")]
+impl FileNodeStore {
+    /// Open the store of the file at the path, the file is created if there is none. Return
+    /// [Error::NodeStoreIo] if the file cannot be read and [Error::CorruptedNodeStore] if it
+    /// is not a node store or ends inside a node
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .map_err(|_| Error::NodeStoreIo)?;
+        let len = file.metadata().map_err(|_| Error::NodeStoreIo)?.len();
+        if len == 0 {
+            file.write_all(&NODE_STORE_MAGIC)
+                .map_err(|_| Error::NodeStoreIo)?;
+        }
+        let offsets = read_offsets(&file)?;
+        Ok(Self {
+            path,
+            file,
+            offsets,
+        })
+    }
+
+    /// Get the number of stored nodes
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Check whether the store has no node
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+}
+
+#[cfg(feature = "std")]
+impl NodeStore for FileNodeStore {
+    fn get(&self, hash: &MerkleRoot) -> Result<Option<Node>, Error> {
+        let Some(offset) = self.offsets.get(hash) else {
+            return Ok(None);
+        };
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(*offset))
+            .map_err(|_| Error::NodeStoreIo)?;
+        let (node, _) = read_node(&mut BufReader::new(file))?;
+        Ok(Some(node))
+    }
+
+    fn put(&mut self, hash: MerkleRoot, node: Node) -> Result<(), Error> {
+        if self.offsets.contains_key(&hash) {
+            return Ok(());
+        }
+        let offset = self
+            .file
+            .seek(SeekFrom::End(0))
+            .map_err(|_| Error::NodeStoreIo)?;
+        self.file
+            .write_all(&encode_node(&hash, &node))
+            .map_err(|_| Error::NodeStoreIo)?;
+        self.offsets.insert(hash, offset + 32);
+        Ok(())
+    }
+
+    fn hashes(&self) -> Result<Vec<MerkleRoot>, Error> {
+        Ok(self.offsets.keys().copied().collect())
+    }
+
+    // The kept nodes are copied to a new file which then replaces the file of the store
+    fn remove(&mut self, hashes: &BTreeSet<MerkleRoot>) -> Result<(), Error> {
+        let compacted = self.path.with_extension("compacting");
+        let file = File::create(&compacted).map_err(|_| Error::NodeStoreIo)?;
+        let mut writer = BufWriter::new(file);
+        writer
+            .write_all(&NODE_STORE_MAGIC)
+            .map_err(|_| Error::NodeStoreIo)?;
+        for hash in self.offsets.keys().filter(|hash| !hashes.contains(*hash)) {
+            let node = self.get(hash)?.ok_or(Error::MissingMerkleNode)?;
+            writer
+                .write_all(&encode_node(hash, &node))
+                .map_err(|_| Error::NodeStoreIo)?;
+        }
+        writer
+            .into_inner()
+            .map_err(|_| Error::NodeStoreIo)?
+            .sync_all()
+            .map_err(|_| Error::NodeStoreIo)?;
+        std::fs::rename(&compacted, &self.path).map_err(|_| Error::NodeStoreIo)?;
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+}
+
+// Read the offsets of the nodes of the file of a store from its start
+#[cfg(feature = "std")]
+fn read_offsets(mut file: &File) -> Result<BTreeMap<MerkleRoot, u64>, Error> {
+    file.seek(SeekFrom::Start(0))
+        .map_err(|_| Error::NodeStoreIo)?;
+    let mut reader = BufReader::new(file);
+    let mut magic = [0u8; 8];
+    read_bytes(&mut reader, &mut magic)?;
+    if magic != NODE_STORE_MAGIC {
+        return Err(Error::CorruptedNodeStore);
+    }
+    let mut offsets = BTreeMap::new();
+    let mut offset = NODE_STORE_MAGIC.len() as u64;
+    while !reader
+        .fill_buf()
+        .map_err(|_| Error::NodeStoreIo)?
+        .is_empty()
+    {
+        let mut hash = [0u8; 32];
+        read_bytes(&mut reader, &mut hash)?;
+        let (_, size) = read_node(&mut reader)?;
+        offsets.insert(hash, offset + 32);
+        offset += 32 + size;
+    }
+    Ok(offsets)
+}
+
+// Encode a node with its hash for a file
+#[cfg(feature = "std")]
+fn encode_node(hash: &MerkleRoot, node: &Node) -> Vec<u8> {
+    let mut encoded = hash.to_vec();
+    match node {
+        Node::Branch { left, right } => {
+            encoded.push(BRANCH_TAG);
+            encoded.extend_from_slice(left);
+            encoded.extend_from_slice(right);
+        }
+        Node::Leaf { address, value } => {
+            encoded.push(LEAF_TAG);
+            for bytes in [address, value] {
+                encoded.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+                encoded.extend_from_slice(bytes);
+            }
+        }
+    }
+    encoded
+}
+
+// Read an encoded node from its tag, get the node and the number of bytes it was read from
+#[cfg(feature = "std")]
+fn read_node<R: Read>(reader: &mut R) -> Result<(Node, u64), Error> {
+    let mut tag = [0u8; 1];
+    read_bytes(reader, &mut tag)?;
+    match tag[0] {
+        BRANCH_TAG => {
+            let (mut left, mut right) = ([0u8; 32], [0u8; 32]);
+            read_bytes(reader, &mut left)?;
+            read_bytes(reader, &mut right)?;
+            Ok((Node::Branch { left, right }, 65))
+        }
+        LEAF_TAG => {
+            let mut parts = [Vec::new(), Vec::new()];
+            for part in parts.iter_mut() {
+                let mut len = [0u8; 4];
+                read_bytes(reader, &mut len)?;
+                let len = u32::from_be_bytes(len) as u64;
+                // A corrupted length does not allocate more than the rest of the file
+                reader
+                    .by_ref()
+                    .take(len)
+                    .read_to_end(part)
+                    .map_err(|_| Error::NodeStoreIo)?;
+                if part.len() as u64 != len {
+                    return Err(Error::CorruptedNodeStore);
+                }
+            }
+            let size = 9 + (parts[0].len() + parts[1].len()) as u64;
+            let [address, value] = parts;
+            Ok((Node::Leaf { address, value }, size))
+        }
+        _ => Err(Error::CorruptedNodeStore),
+    }
+}
+
+// Fill the bytes from the reader, a file ending before them is corrupted
+#[cfg(feature = "std")]
+fn read_bytes<R: Read>(reader: &mut R, bytes: &mut [u8]) -> Result<(), Error> {
+    reader.read_exact(bytes).map_err(|error| {
+        if error.kind() == ErrorKind::UnexpectedEof {
+            Error::CorruptedNodeStore
+        } else {
+            Error::NodeStoreIo
+        }
+    })
+}
+
+/// Sparse Merkle tree of the cells of a memory keeping its nodes in a [NodeStore], see the
+/// [module](crate::commitment::store). The cells, the roots and the proofs are the ones of a
+/// [SparseMerkleTree](crate::commitment::sparse::SparseMerkleTree) with the same writes.
+/// Return [Error::MissingMerkleNode] if a node below the root is not in the store
+#[derive(Debug, Clone)]
+pub struct StoredSparseTree<K, V, const S: usize, const T: usize, N>
+where
+    K: Base<S>,
+    V: Base<T>,
+    N: NodeStore,
+{
+    hash: HashKind,
+    // Hash of the empty subtree of every level, from the leaves to the root
+    defaults: Vec<MerkleRoot>,
+    root: MerkleRoot,
+    store: N,
+    phantom_data: PhantomData<(K, V)>,
+}
+
+impl<K, V, const S: usize, const T: usize, N> StoredSparseTree<K, V, S, T, N>
+where
+    K: Base<S>,
+    V: Base<T>,
+    N: NodeStore,
+{
+    /// Depth of the tree, the bit length of the addresses
+    pub const DEPTH: usize = S * 8;
+
+    /// Create the empty tree hashed with the given hash on top of the store
+    pub fn new(hash: HashKind, store: N) -> Self {
+        let defaults = default_nodes(hash, Self::DEPTH);
+        Self::open(hash, store, defaults[Self::DEPTH])
+    }
+
+    /// Open the tree of the root in the store, e.g. the root of a tree of a previous run
+    pub fn open(hash: HashKind, store: N, root: MerkleRoot) -> Self {
+        Self {
+            hash,
+            defaults: default_nodes(hash, Self::DEPTH),
+            root,
+            store,
+            phantom_data: PhantomData,
+        }
+    }
+
+    /// Get the root of the tree
+    pub fn root(&self) -> MerkleRoot {
+        self.root
+    }
+
+    /// Get the store of the tree
+    pub fn store(&self) -> &N {
+        &self.store
+    }
+
+    /// Get the store back from the tree
+    pub fn into_store(self) -> N {
+        self.store
+    }
+
+    /// Get the value of an address, zero if it was never written
+    pub fn get(&self, address: K) -> Result<V, Error> {
+        let index: [u8; S] = address.into();
+        let (leaf, _) = self.path(&index)?;
+        self.value(&index, leaf)
+    }
+
+    /// Set the value of an address and return its previous value, writing zero removes the
+    /// leaf of the address. The nodes of the new path are added to the store, the nodes of
+    /// the previous path are kept for the previous root
+    pub fn update(&mut self, address: K, value: V) -> Result<V, Error> {
+        let index: [u8; S] = address.into();
+        let (leaf, siblings) = self.path(&index)?;
+        let previous = self.value(&index, leaf)?;
+        let mut node = if value.is_zero() {
+            self.defaults[0]
+        } else {
+            let bytes: [u8; T] = value.into();
+            let leaf = self.hash.cell_leaf(&index, &bytes);
+            self.store.put(
+                leaf,
+                Node::Leaf {
+                    address: index.to_vec(),
+                    value: bytes.to_vec(),
+                },
+            )?;
+            leaf
+        };
+        for (level, sibling) in siblings.iter().enumerate() {
+            let (left, right) = if bit(&index, level) {
+                (*sibling, node)
+            } else {
+                (node, *sibling)
+            };
+            node = self.hash.node(&left, &right);
+            if node != self.defaults[level + 1] {
+                self.store.put(node, Node::Branch { left, right })?;
+            }
+        }
+        self.root = node;
+        Ok(previous)
+    }
+
+    /// Get the value of an address with the proof of its inclusion, None if the address holds
+    /// zero, see [StoredSparseTree::prove_absence]
+    pub fn get_with_proof(&self, address: K) -> Result<Option<(V, SparseMerkleProof)>, Error> {
+        let index: [u8; S] = address.into();
+        let (leaf, siblings) = self.path(&index)?;
+        let value = self.value(&index, leaf)?;
+        if value.is_zero() {
+            return Ok(None);
+        }
+        Ok(Some((
+            value,
+            SparseMerkleProof {
+                hash: self.hash,
+                siblings,
+            },
+        )))
+    }
+
+    /// Prove that an address holds zero, None if it holds a value
+    pub fn prove_absence(&self, address: K) -> Result<Option<SparseMerkleProof>, Error> {
+        let index: [u8; S] = address.into();
+        let (leaf, siblings) = self.path(&index)?;
+        if leaf != self.defaults[0] {
+            return Ok(None);
+        }
+        Ok(Some(SparseMerkleProof {
+            hash: self.hash,
+            siblings,
+        }))
+    }
+
+    /// Remove the nodes which are neither below the root of the tree nor below one of the
+    /// kept roots, e.g. the roots of other trees of the store, and return their number.
+    /// The previous roots of the tree cannot be opened after unless they are kept
+    pub fn compact(&mut self, keep: &[MerkleRoot]) -> Result<usize, Error> {
+        let mut reachable = BTreeSet::new();
+        let mut pending = keep.to_vec();
+        pending.push(self.root);
+        while let Some(hash) = pending.pop() {
+            if reachable.insert(hash) {
+                if let Some(Node::Branch { left, right }) = self.store.get(&hash)? {
+                    pending.push(left);
+                    pending.push(right);
+                }
+            }
+        }
+        let orphans: BTreeSet<MerkleRoot> = self
+            .store
+            .hashes()?
+            .into_iter()
+            .filter(|hash| !reachable.contains(hash))
+            .collect();
+        self.store.remove(&orphans)?;
+        Ok(orphans.len())
+    }
+
+    // Walk from the root down to the leaf of an address, get the leaf and the siblings of its
+    // path from the leaf up to the root
+    fn path(&self, index: &[u8; S]) -> Result<(MerkleRoot, Vec<MerkleRoot>), Error> {
+        let mut siblings = vec![[0u8; 32]; Self::DEPTH];
+        let mut node = self.root;
+        for level in (0..Self::DEPTH).rev() {
+            // Below an empty subtree every node and sibling is the default of its level
+            if node == self.defaults[level + 1] {
+                siblings[..=level].copy_from_slice(&self.defaults[..=level]);
+                return Ok((self.defaults[0], siblings));
+            }
+            let Some(Node::Branch { left, right }) = self.store.get(&node)? else {
+                return Err(Error::MissingMerkleNode);
+            };
+            (node, siblings[level]) = if bit(index, level) {
+                (right, left)
+            } else {
+                (left, right)
+            };
+        }
+        Ok((node, siblings))
+    }
+
+    // Get the value of the leaf of an address, zero for the empty leaf
+    fn value(&self, index: &[u8; S], leaf: MerkleRoot) -> Result<V, Error> {
+        if leaf == self.defaults[0] {
+            return Ok(V::zero());
+        }
+        match self.store.get(&leaf)? {
+            Some(Node::Leaf { address, value }) if address[..] == index[..] && value.len() == T => {
+                let mut bytes = [0u8; T];
+                bytes.copy_from_slice(&value);
+                Ok(V::from(bytes))
+            }
+            _ => Err(Error::MissingMerkleNode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryNodeStore, NodeStore, StoredSparseTree};
+    use crate::{
+        base::B32,
+        commitment::{merkle::HashKind, sparse::SparseMerkleTree},
+        error::Error,
+    };
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use rand::Rng;
+
+    // Random writes to 16 addresses, a zero removes the cell
+    fn writes(len: usize) -> Vec<(B32, B32)> {
+        let mut rng = rand::thread_rng();
+        (0..len)
+            .map(|_| {
+                let address = B32::from(4 * rng.gen_range(0..16u64));
+                (address, B32::from(rng.gen_range(0..4u64)))
+            })
+            .collect()
+    }
+
+    // Check that the stored tree has the root, the cells and the proofs of the tree
+    fn check_same<N: NodeStore>(
+        stored: &StoredSparseTree<B32, B32, 4, 4, N>,
+        tree: &SparseMerkleTree<B32, B32, 4, 4>,
+    ) {
+        assert_eq!(stored.root(), tree.root());
+        for address in (0..16u64).map(|i| B32::from(4 * i)) {
+            assert_eq!(stored.get(address), Ok(tree.get(address)));
+            assert_eq!(
+                stored.get_with_proof(address),
+                Ok(tree.get_with_proof(address))
+            );
+            assert_eq!(
+                stored.prove_absence(address),
+                Ok(tree.prove_absence(address))
+            );
+        }
+    }
+
+    #[test]
+    fn test_same_as_memory_tree() {
+        for hash in [HashKind::Blake2b, HashKind::Poseidon, HashKind::Keccak256] {
+            let mut tree = SparseMerkleTree::<B32, B32, 4, 4>::new(hash);
+            let mut stored = StoredSparseTree::new(hash, MemoryNodeStore::new());
+            check_same(&stored, &tree);
+            for (address, value) in writes(64) {
+                assert_eq!(
+                    stored.update(address, value),
+                    Ok(tree.update(address, value))
+                );
+                check_same(&stored, &tree);
+            }
+
+            // The tree is reopened from its root
+            let root = stored.root();
+            let reopened = StoredSparseTree::open(hash, stored.into_store(), root);
+            check_same(&reopened, &tree);
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        let hash = HashKind::Blake2b;
+        let mut tree = SparseMerkleTree::<B32, B32, 4, 4>::new(hash);
+        let mut stored = StoredSparseTree::new(hash, MemoryNodeStore::new());
+        for i in 0..8u64 {
+            tree.update(B32::from(4 * i), B32::from(i + 1));
+            stored.update(B32::from(4 * i), B32::from(i + 1)).unwrap();
+        }
+        let (previous, previous_root) = (tree.clone(), stored.root());
+        for value in [9u64, 10, 0] {
+            tree.update(B32::from(0u64), B32::from(value));
+            stored.update(B32::from(0u64), B32::from(value)).unwrap();
+        }
+
+        // Only the paths of the intermediate roots are orphaned while the previous root is kept
+        assert!(stored.compact(&[previous_root]).unwrap() > 0);
+        check_same(&stored, &tree);
+        let reopened = StoredSparseTree::open(hash, stored.store().clone(), previous_root);
+        check_same(&reopened, &previous);
+
+        // The store is left with the nodes of the current root only
+        assert!(stored.compact(&[]).unwrap() > 0);
+        assert_eq!(stored.compact(&[]), Ok(0));
+        check_same(&stored, &tree);
+        let reopened = StoredSparseTree::<B32, B32, 4, 4, _>::open(
+            hash,
+            stored.store().clone(),
+            previous_root,
+        );
+        assert_eq!(reopened.get(B32::from(0u64)), Err(Error::MissingMerkleNode));
+        let mut rebuilt = StoredSparseTree::new(hash, MemoryNodeStore::new());
+        for i in 1..8u64 {
+            rebuilt.update(B32::from(4 * i), B32::from(i + 1)).unwrap();
+        }
+        rebuilt.compact(&[]).unwrap();
+        assert_eq!(rebuilt.store(), stored.store());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_file_store() {
+        use super::{FileNodeStore, NODE_STORE_MAGIC};
+        use std::io::Write;
+
+        let path =
+            std::env::temp_dir().join(std::format!("zkmemory-nodes-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let hash = HashKind::Blake2b;
+        let mut tree = SparseMerkleTree::<B32, B32, 4, 4>::new(hash);
+        let mut stored = StoredSparseTree::new(hash, FileNodeStore::open(&path).unwrap());
+        let mut memory = StoredSparseTree::new(hash, MemoryNodeStore::new());
+        for (address, value) in writes(32) {
+            tree.update(address, value);
+            stored.update(address, value).unwrap();
+            memory.update(address, value).unwrap();
+        }
+        check_same(&stored, &tree);
+
+        // The tree survives a restart, before and after a compaction
+        let root = stored.root();
+        drop(stored);
+        let mut stored = StoredSparseTree::open(hash, FileNodeStore::open(&path).unwrap(), root);
+        check_same(&stored, &tree);
+        assert_eq!(stored.compact(&[]), memory.compact(&[]));
+        assert_eq!(stored.store().len(), memory.store().len());
+        drop(stored);
+        let stored = StoredSparseTree::open(hash, FileNodeStore::open(&path).unwrap(), root);
+        check_same(&stored, &tree);
+        drop(stored);
+
+        // A file ending inside a node or without the magic is not a store
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(&[0u8; 40]).unwrap();
+        drop(file);
+        assert!(matches!(
+            FileNodeStore::open(&path),
+            Err(Error::CorruptedNodeStore)
+        ));
+        std::fs::write(&path, [NODE_STORE_MAGIC, *b"NOTNODES"].concat()).unwrap();
+        assert!(matches!(
+            FileNodeStore::open(&path),
+            Err(Error::CorruptedNodeStore)
+        ));
+        std::fs::write(&path, b"ZKMKZGPR").unwrap();
+        assert!(matches!(
+            FileNodeStore::open(&path),
+            Err(Error::CorruptedNodeStore)
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,5 +1,74 @@
-use crate::base::Base;
-use crate::machine::Register;
+extern crate alloc;
+use crate::base::{AddressIterator, Base, B256, B32};
+use crate::commitment::selector::CommitmentKind;
+use crate::constraints::helper::minimal_k;
+use crate::error::Error;
+use crate::machine::{MemoryInstruction, Register};
+use alloc::{vec, vec::Vec};
+use blake2b_simd::Params;
+use core::ops::RangeInclusive;
+
+// Loading of the config from TOML files
+#[cfg(feature = "serde")]
+mod file;
+
+/// Stack depth of [ConfigArgs::minimal], in cells
+pub const MINIMAL_STACK_DEPTH: u64 = 64;
+/// Number of registers of [ConfigArgs::minimal]
+pub const MINIMAL_NO_REGISTER: u64 = 8;
+/// Size of the memory section the builder defaults to with the `small-memory` feature
+pub const MINIMAL_MEMORY_SIZE: u64 = 1 << 15;
+
+/// Default stack depth, in cells, [MINIMAL_STACK_DEPTH] with the `small-memory` feature
+#[cfg(not(feature = "small-memory"))]
+pub const DEFAULT_STACK_DEPTH: u64 = 1024;
+/// Default stack depth, in cells, [MINIMAL_STACK_DEPTH] with the `small-memory` feature
+#[cfg(feature = "small-memory")]
+pub const DEFAULT_STACK_DEPTH: u64 = MINIMAL_STACK_DEPTH;
+/// Default number of registers, [MINIMAL_NO_REGISTER] with the `small-memory` feature
+#[cfg(not(feature = "small-memory"))]
+pub const DEFAULT_NO_REGISTER: u64 = 32;
+/// Default number of registers, [MINIMAL_NO_REGISTER] with the `small-memory` feature
+#[cfg(feature = "small-memory")]
+pub const DEFAULT_NO_REGISTER: u64 = MINIMAL_NO_REGISTER;
+/// Default size of the memory section in bytes, [None] to extend it to the end of the
+/// address space. It is [MINIMAL_MEMORY_SIZE] with the `small-memory` feature
+#[cfg(not(feature = "small-memory"))]
+pub const DEFAULT_MEMORY_SIZE: Option<u64> = None;
+/// Default size of the memory section in bytes, [None] to extend it to the end of the
+/// address space. It is [MINIMAL_MEMORY_SIZE] with the `small-memory` feature
+#[cfg(feature = "small-memory")]
+pub const DEFAULT_MEMORY_SIZE: Option<u64> = Some(MINIMAL_MEMORY_SIZE);
+/// Default size of the buffer between two sections
+pub const DEFAULT_BUFFER_SIZE: u64 = 32;
+
+/// Stack base address of the default layout of a 256-bit machine
+pub const DEFAULT_STACK_BASE: B256 = B256::new(0);
+/// Register base address of the default layout of a 256-bit machine
+pub const DEFAULT_REGISTER_BASE: B256 = B256::new(DEFAULT_STACK_DEPTH * 32 + DEFAULT_BUFFER_SIZE);
+/// Memory base address of the default layout of a 256-bit machine
+///
+/// ```
+/// use zkmemory::config::{DEFAULT_MEMORY_BASE, DEFAULT_REGISTER_BASE};
+/// use zkmemory::base::B256;
+///
+/// const HEAP_BASE: B256 = DEFAULT_MEMORY_BASE;
+/// const SCRATCH_BASE: B256 = B256::from_limbs([0, 0, 0, 1]);
+/// assert!(HEAP_BASE > DEFAULT_REGISTER_BASE);
+/// assert!(SCRATCH_BASE > HEAP_BASE);
+/// ```
+pub const DEFAULT_MEMORY_BASE: B256 =
+    B256::new((DEFAULT_STACK_DEPTH + DEFAULT_NO_REGISTER) * 32 + 2 * DEFAULT_BUFFER_SIZE);
+
+/// Base address of the calldata region of [Config::evm]
+pub const EVM_CALLDATA_BASE: B256 = B256::new(1 << 40);
+/// Size of the calldata region of [Config::evm], in bytes
+pub const EVM_CALLDATA_SIZE: u64 = 1 << 20;
+/// Maximum number of reserved ranges of a [MemoryLayout]
+pub const MAX_RESERVED_RANGES: usize = 4;
+
+// Personalization of the config fingerprint
+const FINGERPRINT_PERSONAL: &[u8] = b"zkmemory-config";
 
 /// Memory section
 #[derive(Debug, Clone, Copy)]
@@ -9,6 +78,11 @@ impl<T> AllocatedSection<T>
 where
     T: PartialEq + PartialOrd + Copy,
 {
+    /// Create a new section from the low and high address
+    pub fn new(low: T, high: T) -> Self {
+        Self(low, high)
+    }
+
     /// Check if the address is in the section
     pub fn contain(&self, address: T) -> bool {
         address >= self.0 && address <= self.1
@@ -25,6 +99,558 @@ where
     }
 }
 
+impl<T> AllocatedSection<T> {
+    /// Iterate over the cell addresses of the section
+    pub fn cells<const S: usize>(&self) -> AddressIterator<T, S>
+    where
+        T: Base<S>,
+    {
+        AddressIterator::range_inclusive(self.0, self.1)
+    }
+}
+
+/// Policy of the machine for addresses that are not a multiple of the word size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum AlignmentPolicy {
+    /// Reject the access with [Error::UnalignedAccess]
+    Strict,
+    /// Access the cell that contains the address
+    RoundDown,
+    /// Split the access into the two aligned cells around the address
+    #[default]
+    Emulate,
+}
+
+/// Semantics of reading a cell that was never written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum ReadPolicy {
+    /// The cell holds zero, the first access to a cell may be a read of zero
+    #[default]
+    ZeroOnUninit,
+    /// Reject the read with [Error::UninitializedRead], the first access to a cell is a write
+    ErrorOnUninit,
+}
+
+/// Byte order of the cells in the memory. The byte at offset `i` of a cell is the `i`-th most
+/// significant byte of its value in big endian and the `i`-th least significant one in little
+/// endian. It applies where the memory is seen as bytes, the values of the trace records and
+/// their witness limbs do not depend on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Endianness {
+    /// Most significant byte first
+    #[default]
+    Big,
+    /// Least significant byte first
+    Little,
+}
+
+impl Endianness {
+    /// Get the bytes of a word in this byte order
+    pub fn to_bytes<B: Base<N>, const N: usize>(self, word: B) -> [u8; N] {
+        let mut bytes: [u8; N] = word.into();
+        if self == Endianness::Little {
+            bytes.reverse();
+        }
+        bytes
+    }
+
+    /// Get the word of bytes in this byte order
+    pub fn from_bytes<B: Base<N>, const N: usize>(self, mut bytes: [u8; N]) -> B {
+        if self == Endianness::Little {
+            bytes.reverse();
+        }
+        B::from(bytes)
+    }
+
+    /// Get the code of the byte order in the binary trace format
+    pub const fn code(self) -> u8 {
+        match self {
+            Endianness::Big => 0,
+            Endianness::Little => 1,
+        }
+    }
+
+    /// Get the byte order of a code, [None] if the code is unknown
+    pub const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Endianness::Big),
+            1 => Some(Endianness::Little),
+            _ => None,
+        }
+    }
+}
+
+/// Granularity of the trace collected by a machine. The accesses are executed the same way in
+/// every mode, the reduced modes only save the memory of the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceMode {
+    /// Keep every record in the trace, or push it to the trace sink of the machine
+    #[default]
+    Full,
+    /// Commit every record with the backend of [Config::commitment] and drop it, see
+    /// [trace_commitment](crate::machine::AbstractMemoryMachine::trace_commitment)
+    CommitmentsOnly,
+    /// Drop every record, the machine is a plain emulator
+    Disabled,
+}
+
+impl core::fmt::Display for TraceMode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TraceMode::Full => write!(f, "full"),
+            TraceMode::CommitmentsOnly => write!(f, "commitments-only"),
+            TraceMode::Disabled => write!(f, "disabled"),
+        }
+    }
+}
+
+/// Size of the circuits a trace is proved with, the traces that do not fit in one circuit
+/// are split into chunks by [plan_chunks](crate::constraints::helper::plan_chunks)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProverBudget {
+    /// Largest k of a circuit of `2^k` rows
+    pub max_k: u32,
+}
+
+/// Limits on the resources used by a machine, [None] leaves the resource unlimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct ResourceLimits {
+    /// Maximum number of records in the execution trace
+    pub max_trace_len: Option<u64>,
+    /// Maximum number of cells stored in the memory
+    pub max_distinct_cells: Option<u64>,
+    /// Maximum number of bytes written since the machine started,
+    /// every written cell counts for its full width
+    pub max_bytes_written: Option<u64>,
+}
+
+/// Gas charged for every access, by the instruction recorded in the trace.
+/// An access costs the same whether it touches one or two cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct CostModel {
+    /// Gas of a read
+    pub read: u64,
+    /// Gas of a write
+    pub write: u64,
+    /// Gas of a push
+    pub push: u64,
+    /// Gas of a pop
+    pub pop: u64,
+    /// Gas of every cell added to the memory section by
+    /// [grow](crate::machine::AbstractMemoryMachine::grow)
+    pub grow: u64,
+}
+
+impl CostModel {
+    /// Get the gas of an access recording the instruction
+    pub const fn cost(&self, instruction: MemoryInstruction) -> u64 {
+        match instruction {
+            MemoryInstruction::Read => self.read,
+            MemoryInstruction::Write => self.write,
+            MemoryInstruction::Push => self.push,
+            MemoryInstruction::Pop => self.pop,
+        }
+    }
+}
+
+/// Resource of the machine bounded by the [ResourceLimits]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// Number of records in the execution trace
+    TraceLen,
+    /// Number of cells stored in the memory
+    DistinctCells,
+    /// Number of bytes written
+    BytesWritten,
+}
+
+impl core::fmt::Display for ResourceKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ResourceKind::TraceLen => write!(f, "trace length"),
+            ResourceKind::DistinctCells => write!(f, "distinct cells"),
+            ResourceKind::BytesWritten => write!(f, "bytes written"),
+        }
+    }
+}
+
+/// Name of a region of the [MemoryLayout]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum RegionKind {
+    /// Program image
+    Code,
+    /// Stack of the machine
+    Stack,
+    /// Register file of the machine
+    Register,
+    /// General purpose memory
+    Heap,
+    /// Input and output buffers
+    Io,
+}
+
+impl RegionKind {
+    // Number of region kinds
+    const COUNT: usize = 5;
+
+    // Position of the region in the layout
+    const fn index(&self) -> usize {
+        match self {
+            RegionKind::Code => 0,
+            RegionKind::Stack => 1,
+            RegionKind::Register => 2,
+            RegionKind::Heap => 3,
+            RegionKind::Io => 4,
+        }
+    }
+}
+
+impl core::fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RegionKind::Code => write!(f, "code"),
+            RegionKind::Stack => write!(f, "stack"),
+            RegionKind::Register => write!(f, "register"),
+            RegionKind::Heap => write!(f, "heap"),
+            RegionKind::Io => write!(f, "io"),
+        }
+    }
+}
+
+/// Access permission of a region of the [MemoryLayout]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Permission {
+    /// Only reads are allowed
+    Read,
+    /// Only writes are allowed
+    Write,
+    /// Reads and writes are allowed
+    #[default]
+    ReadWrite,
+}
+
+impl Permission {
+    /// Check if this permission grants the requested access
+    pub const fn allows(&self, access: Permission) -> bool {
+        matches!(
+            (self, access),
+            (Permission::ReadWrite, _)
+                | (Permission::Read, Permission::Read)
+                | (Permission::Write, Permission::Write)
+        )
+    }
+}
+
+impl core::fmt::Display for Permission {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Permission::Read => write!(f, "read"),
+            Permission::Write => write!(f, "write"),
+            Permission::ReadWrite => write!(f, "read-write"),
+        }
+    }
+}
+
+/// Named region of the address space, `length` bytes starting at `base`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion<T> {
+    /// Name of the region
+    pub kind: RegionKind,
+    /// First address of the region
+    pub base: T,
+    /// Size of the region in bytes
+    pub length: T,
+    /// Accesses allowed in the region
+    pub permission: Permission,
+    /// Every cell of the region is written at most once, a second checked write to a cell
+    /// is rejected with [Error::WriteOnceViolation]
+    pub write_once: bool,
+    /// Size in bytes of the cells of the region, [None] for the word size of the machine.
+    /// A narrower size must divide the word size of the machine
+    pub word_size: Option<T>,
+}
+
+impl<T> MemoryRegion<T> {
+    /// Create a new readable and writable region
+    pub fn new(kind: RegionKind, base: T, length: T) -> Self {
+        Self {
+            kind,
+            base,
+            length,
+            permission: Permission::ReadWrite,
+            write_once: false,
+            word_size: None,
+        }
+    }
+
+    /// Set the accesses allowed in the region
+    pub fn with_permission(mut self, permission: Permission) -> Self {
+        self.permission = permission;
+        self
+    }
+
+    /// Set whether the cells of the region are written at most once
+    pub fn with_write_once(mut self, write_once: bool) -> Self {
+        self.write_once = write_once;
+        self
+    }
+
+    /// Set the size in bytes of the cells of the region
+    pub fn with_word_size(mut self, word_size: T) -> Self {
+        self.word_size = Some(word_size);
+        self
+    }
+}
+
+/// Map of the named regions of the address space, every access outside of a region is rejected.
+/// Each kind appears at most once and the regions never overlap. The reserved ranges are never
+/// accessible, see [MemoryLayout::reserve]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLayout<T> {
+    // Regions indexed by kind, with their inclusive last address
+    regions: [Option<(MemoryRegion<T>, T)>; RegionKind::COUNT],
+    // Reserved ranges in the order they were added, as their first and last address
+    reserved: [Option<(T, T)>; MAX_RESERVED_RANGES],
+}
+
+impl<T: Copy> MemoryLayout<T> {
+    /// Create a new layout, reject empty regions, regions that do not fit
+    /// in the address space, duplicated kinds and overlapping regions
+    pub fn new<const S: usize>(regions: &[MemoryRegion<T>]) -> Result<Self, Error>
+    where
+        T: Base<S>,
+    {
+        let mut layout = Self {
+            regions: [None; RegionKind::COUNT],
+            reserved: [None; MAX_RESERVED_RANGES],
+        };
+        for region in regions {
+            let last = match region.length.checked_sub(T::from(1u64)) {
+                Some(offset) => region.base.checked_add(offset),
+                None => None,
+            }
+            .ok_or(Error::InvalidRegion {
+                region: region.kind,
+            })?;
+            if layout.regions[region.kind.index()].is_some() {
+                return Err(Error::InvalidRegion {
+                    region: region.kind,
+                });
+            }
+            if let Some((other, _)) = layout
+                .regions()
+                .find(|(other, other_last)| region.base <= *other_last && other.base <= last)
+            {
+                return Err(Error::OverlappingRegions {
+                    first: other.kind,
+                    second: region.kind,
+                });
+            }
+            layout.regions[region.kind.index()] = Some((*region, last));
+        }
+        Ok(layout)
+    }
+
+    /// Reserve an inclusive range of addresses outside of the regions, e.g. the null page.
+    /// Any access to a cell that touches the range is rejected with [Error::ReservedAddress].
+    /// Return [Error::ReservedRangeOverlap] if the range overlaps a region and
+    /// [Error::InvalidReservedRange] if it is empty, overlaps another reserved range or the
+    /// layout already has [MAX_RESERVED_RANGES] ranges
+    pub fn reserve<const S: usize>(mut self, range: RangeInclusive<T>) -> Result<Self, Error>
+    where
+        T: Base<S>,
+    {
+        let (first, last) = range.into_inner();
+        if first > last {
+            return Err(Error::InvalidReservedRange);
+        }
+        if let Some((region, _)) = self
+            .regions()
+            .find(|(region, region_last)| first <= *region_last && region.base <= last)
+        {
+            return Err(Error::ReservedRangeOverlap {
+                region: region.kind,
+            });
+        }
+        if self
+            .reserved()
+            .any(|(other_first, other_last)| first <= other_last && other_first <= last)
+        {
+            return Err(Error::InvalidReservedRange);
+        }
+        let slot = self
+            .reserved
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(Error::InvalidReservedRange)?;
+        *slot = Some((first, last));
+        Ok(self)
+    }
+
+    /// Get the reserved ranges in the order they were added
+    pub fn reserved_ranges(&self) -> Vec<RangeInclusive<T>> {
+        self.reserved().map(|(first, last)| first..=last).collect()
+    }
+
+    /// Get the region of the given kind
+    pub fn region(&self, kind: RegionKind) -> Option<MemoryRegion<T>> {
+        self.regions[kind.index()].map(|(region, _)| region)
+    }
+
+    /// Get the region that contains the whole cell starting at the address
+    pub fn region_of<const S: usize>(&self, address: T, word_size: T) -> Option<MemoryRegion<T>>
+    where
+        T: Base<S>,
+    {
+        self.regions()
+            .find(|(region, last)| {
+                address >= region.base
+                    && address <= *last
+                    && *last - address >= word_size - T::from(1u64)
+            })
+            .map(|(region, _)| region)
+    }
+
+    /// Check that the whole cell starting at the address is inside a region, return
+    /// [Error::ReservedAddress] if the cell touches a reserved range and
+    /// [Error::AccessOutsideRegion] if it is outside of the regions otherwise
+    pub fn check<const S: usize>(&self, address: T, word_size: T) -> Result<MemoryRegion<T>, Error>
+    where
+        T: Base<S>,
+    {
+        // The cell may end past the last address, the reserved ranges do not
+        let cell_last = address
+            .checked_add(word_size - T::from(1u64))
+            .unwrap_or(T::MAX);
+        if let Some((first, _)) = self
+            .reserved()
+            .find(|(first, last)| address <= *last && *first <= cell_last)
+        {
+            return Err(Error::ReservedAddress {
+                addr: address.max(first).fixed_be_bytes(),
+            });
+        }
+        self.region_of(address, word_size)
+            .ok_or_else(|| Error::AccessOutsideRegion {
+                addr: address.fixed_be_bytes(),
+                region: self
+                    .regions()
+                    .filter(|(region, _)| region.base <= address)
+                    .max_by(|(a, _), (b, _)| a.base.cmp(&b.base))
+                    .map(|(region, _)| region.kind),
+            })
+    }
+
+    /// Get the regions in the order of their kind
+    pub fn all_regions(&self) -> Vec<MemoryRegion<T>> {
+        self.regions().map(|(region, _)| region).collect()
+    }
+
+    /// Get the write-once regions in the order of their kind, the constraints can skip the
+    /// read consistency check of their cells since a cell holds a single value
+    pub fn write_once_regions(&self) -> Vec<MemoryRegion<T>> {
+        self.regions()
+            .map(|(region, _)| region)
+            .filter(|region| region.write_once)
+            .collect()
+    }
+
+    // Iterate over the regions and their inclusive last address
+    fn regions(&self) -> impl Iterator<Item = (MemoryRegion<T>, T)> + '_ {
+        self.regions.iter().flatten().copied()
+    }
+
+    // Iterate over the first and last address of the reserved ranges
+    fn reserved(&self) -> impl Iterator<Item = (T, T)> + '_ {
+        self.reserved.iter().flatten().copied()
+    }
+
+    // Append the canonical encoding of the layout, every region slot in the order of the
+    // kinds then the reserved ranges in the order they were added
+    fn encode<const S: usize>(&self, bytes: &mut Vec<u8>)
+    where
+        T: Base<S>,
+    {
+        for slot in self.regions.iter() {
+            match slot {
+                Some((region, _)) => {
+                    bytes.push(1);
+                    push_word(bytes, region.base);
+                    push_word(bytes, region.length);
+                    bytes.extend_from_slice(&[region.permission as u8, region.write_once as u8]);
+                    push_optional_word(bytes, region.word_size);
+                }
+                None => bytes.push(0),
+            }
+        }
+        bytes.push(self.reserved().count() as u8);
+        for (first, last) in self.reserved() {
+            push_word(bytes, first);
+            push_word(bytes, last);
+        }
+    }
+}
+
+/// Check of the config fingerprint written in a persisted trace, snapshot or checkpoint,
+/// see [Config::config_fingerprint]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FingerprintCheck {
+    /// Reject any other fingerprint with [Error::ConfigMismatch]
+    Expect([u8; 32]),
+    /// Accept any fingerprint, for migration tooling that converts data between configs
+    Force,
+}
+
+impl FingerprintCheck {
+    /// Check the fingerprint found in the persisted data
+    pub fn check(&self, found: [u8; 32]) -> Result<(), Error> {
+        match self {
+            FingerprintCheck::Expect(expected) if *expected != found => {
+                Err(Error::ConfigMismatch {
+                    expected: *expected,
+                    found,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 /// Config for RAM machine
 #[derive(Debug, Clone, Copy)]
 pub struct Config<T, const S: usize> {
@@ -36,10 +662,37 @@ pub struct Config<T, const S: usize> {
     pub buffer_size: T,
     /// Base address of memory
     pub memory: AllocatedSection<T>,
+    /// Whether the memory section can grow with
+    /// [grow](crate::machine::AbstractMemoryMachine::grow)
+    pub growable: bool,
+    /// Maximum size of the memory section in bytes, it never grows past it
+    pub max_memory_size: T,
     /// Stack base address
     pub stack: AllocatedSection<T>,
     /// Register base address
     pub register: AllocatedSection<T>,
+    /// Guard cells above the top of the stack, [None] if there is none
+    pub stack_guard: Option<AllocatedSection<T>>,
+    /// Policy for unaligned accesses
+    pub alignment: AlignmentPolicy,
+    /// Semantics of reading a cell that was never written
+    pub read_policy: ReadPolicy,
+    /// Byte order of the cells
+    pub endianness: Endianness,
+    /// Limits on the resources used by the machine
+    pub limits: ResourceLimits,
+    /// Gas charged for every access
+    pub cost_model: CostModel,
+    /// Maximum gas used since the machine started, [None] leaves the gas unlimited
+    pub gas_budget: Option<u64>,
+    /// Named regions of the address space, [None] to allow every access
+    pub layout: Option<MemoryLayout<T>>,
+    /// Granularity of the trace collected by the machine
+    pub trace_mode: TraceMode,
+    /// Size of the circuits the trace is proved with, [None] if it is not bounded
+    pub prover_budget: Option<ProverBudget>,
+    /// Backend committing the memory image and the trace
+    pub commitment: CommitmentKind,
 }
 
 /// Config arguments for RAM machine
@@ -53,6 +706,494 @@ pub struct ConfigArgs<T> {
     pub no_register: T,
     /// Buffer size
     pub buffer_size: T,
+    /// Base address of the register section, [None] to place it right after the stack.
+    /// A custom base must not overlap the stack and memory sections
+    pub register_base: Option<T>,
+    /// Base address of the stack section, [None] to place it by the layout.
+    /// A custom base must not overlap the register and memory sections
+    pub stack_base: Option<T>,
+    /// Number of guard cells right above the top of the stack, the stack grows upward
+    /// so a push into them is rejected with [Error::StackOverflow]
+    pub guard_cells: T,
+    /// Policy for unaligned accesses
+    pub alignment: AlignmentPolicy,
+    /// Semantics of reading a cell that was never written
+    pub read_policy: ReadPolicy,
+    /// Byte order of the cells
+    pub endianness: Endianness,
+    /// Limits on the resources used by the machine
+    pub limits: ResourceLimits,
+    /// Gas charged for every access
+    pub cost_model: CostModel,
+    /// Maximum gas used since the machine started, [None] leaves the gas unlimited
+    pub gas_budget: Option<u64>,
+    /// Named regions of the address space, [None] to allow every access
+    pub layout: Option<MemoryLayout<T>>,
+}
+
+impl<T> ConfigArgs<T> {
+    /// Get the arguments of a small machine for targets with a small heap, a shallow stack
+    /// and a few registers. The cells are only allocated when they are written, so the
+    /// memory section does not need to be bounded to fit in the heap
+    pub fn minimal<const S: usize>() -> Self
+    where
+        T: Base<S>,
+    {
+        Self {
+            head_layout: true,
+            stack_depth: T::from(MINIMAL_STACK_DEPTH),
+            no_register: T::from(MINIMAL_NO_REGISTER),
+            buffer_size: T::from(DEFAULT_BUFFER_SIZE),
+            register_base: None,
+            stack_base: None,
+            guard_cells: T::zero(),
+            alignment: AlignmentPolicy::default(),
+            read_policy: ReadPolicy::default(),
+            endianness: Endianness::default(),
+            limits: ResourceLimits::default(),
+            cost_model: CostModel::default(),
+            gas_budget: None,
+            layout: None,
+        }
+    }
+
+    /// Build the config arguments from user provided strings, every size accepts the
+    /// notations of [Base::parse_size], e.g. `"1KiB"` or `"2^10"`
+    pub fn parse<const S: usize>(
+        head_layout: bool,
+        stack_depth: &str,
+        no_register: &str,
+        buffer_size: &str,
+    ) -> Result<Self, Error>
+    where
+        T: Base<S>,
+    {
+        Ok(Self {
+            head_layout,
+            stack_depth: T::parse_size(stack_depth)?,
+            no_register: T::parse_size(no_register)?,
+            buffer_size: T::parse_size(buffer_size)?,
+            ..ConfigBuilder::<T, S>::new().into_args()
+        })
+    }
+
+    /// Check the arguments for a word size in bytes, every violated rule is reported:
+    /// - the word size is positive and fits in the address word
+    /// - the stack holds at least one cell and fits in the address space with its guard cells
+    /// - the sections of the layout and a custom register section fit in the address space
+    /// - the stack, register and memory sections start on a multiple of the word size
+    /// - the stack and its guard cells do not overlap the register section
+    /// - the word size of a region of the layout divides the word size and aligns its base
+    pub fn validate<const S: usize>(&self, word_size: T) -> Result<(), Vec<Error>>
+    where
+        T: Base<S>,
+    {
+        if word_size.is_zero() || word_size > T::from(S) {
+            let bytes: usize = word_size.min(T::from(usize::MAX / 8)).into();
+            return Err(vec![Error::InvalidWordSize { bits: bytes * 8 }]);
+        }
+        let mut errors = Vec::new();
+        if self.stack_depth.is_zero() {
+            errors.push(Error::EmptyStack);
+        }
+        // Size of the stack and its guard cells
+        let guarded_size = self
+            .guard_cells
+            .checked_add(self.stack_depth)
+            .and_then(|cells| cells.checked_mul(word_size));
+        if let Some(base) = self.stack_base {
+            if guarded_size
+                .and_then(|size| base.checked_add(size))
+                .is_none()
+            {
+                errors.push(Error::StackOutOfAddressSpace);
+            }
+        }
+        let register_size = self.no_register.checked_mul(word_size);
+        // Size of the stack, the guard cells, the registers and the buffers, see [Config::new]
+        let sections = if self.head_layout {
+            register_size
+                .zip(guarded_size)
+                .and_then(|(registers, stack)| registers.checked_add(stack))
+                .and_then(|size| size.checked_add(self.buffer_size))
+                .and_then(|size| size.checked_add(self.buffer_size))
+        } else {
+            self.stack_depth
+                .checked_add(self.guard_cells)
+                .and_then(|size| size.checked_add(self.no_register))
+                .and_then(|size| size.checked_add(self.buffer_size))
+                .and_then(|size| size.checked_add(self.buffer_size))
+                .and_then(|size| size.checked_mul(word_size))
+        };
+        let registers_fit = match self.register_base {
+            Some(base) => register_size
+                .and_then(|size| base.checked_add(size))
+                .is_some(),
+            None => true,
+        };
+        if sections.is_none() || !registers_fit {
+            errors.push(Error::SectionsOverflow);
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let layout = Config::<T, S>::layout(word_size, self);
+        let stack_lo = self.stack_base.unwrap_or(layout.stack.low());
+        let register_lo = self.register_base.unwrap_or(layout.register.low());
+        for (region, base) in [
+            (RegionKind::Stack, stack_lo),
+            (RegionKind::Register, register_lo),
+            (RegionKind::Heap, layout.memory.low()),
+        ] {
+            if !(base % word_size).is_zero() {
+                errors.push(Error::MisalignedSection { region });
+            }
+        }
+        // Both sizes fit since the sections do
+        let stack_end = stack_lo + guarded_size.unwrap_or(T::zero());
+        let register_end = register_lo + register_size.unwrap_or(T::zero());
+        if stack_lo < register_end && register_lo < stack_end {
+            errors.push(Error::OverlappingRegions {
+                first: RegionKind::Stack,
+                second: RegionKind::Register,
+            });
+        }
+        for region in self.layout.iter().flat_map(MemoryLayout::all_regions) {
+            if let Some(cell_size) = region.word_size {
+                if cell_size.is_zero() || !(word_size % cell_size).is_zero() {
+                    errors.push(Error::InvalidRegionWordSize {
+                        region: region.kind,
+                    });
+                } else if !(region.base % cell_size).is_zero() {
+                    errors.push(Error::MisalignedSection {
+                        region: region.kind,
+                    });
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Builder of a validated [Config], every option not set keeps the value of
+/// [DefaultConfig::default_config]
+///
+/// ```
+/// use zkmemory::base::B256;
+/// use zkmemory::config::{AlignmentPolicy, ConfigBuilder};
+///
+/// let config = ConfigBuilder::<B256, 32>::new()
+///     .word_size(256)
+///     .stack_depth(1024)
+///     .memory_size(1 << 20)
+///     .alignment(AlignmentPolicy::Strict)
+///     .build()
+///     .unwrap();
+/// assert_eq!(config.memory.high() - config.memory.low(), B256::from((1 << 20) - 1));
+/// ```
+#[derive(Debug)]
+pub struct ConfigBuilder<T, const S: usize> {
+    args: ConfigArgs<T>,
+    // Size of a cell in bits
+    word_bits: usize,
+    memory_size: Option<T>,
+    growable: bool,
+    max_memory_size: Option<T>,
+    sparse_storage: bool,
+    trace_mode: TraceMode,
+    prover_budget: Option<ProverBudget>,
+    commitment: CommitmentKind,
+}
+
+impl<T: Base<S>, const S: usize> Default for ConfigBuilder<T, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Base<S>, const S: usize> ConfigBuilder<T, S> {
+    /// Create a builder with the default options
+    pub fn new() -> Self {
+        Self {
+            args: ConfigArgs {
+                head_layout: true,
+                stack_depth: T::from(DEFAULT_STACK_DEPTH),
+                no_register: T::from(DEFAULT_NO_REGISTER),
+                buffer_size: T::from(DEFAULT_BUFFER_SIZE),
+                register_base: None,
+                stack_base: None,
+                guard_cells: T::zero(),
+                alignment: AlignmentPolicy::default(),
+                read_policy: ReadPolicy::default(),
+                endianness: Endianness::default(),
+                limits: ResourceLimits::default(),
+                cost_model: CostModel::default(),
+                gas_budget: None,
+                layout: None,
+            },
+            word_bits: S * 8,
+            memory_size: DEFAULT_MEMORY_SIZE.map(T::from),
+            growable: false,
+            max_memory_size: None,
+            sparse_storage: true,
+            trace_mode: TraceMode::Full,
+            prover_budget: None,
+            commitment: CommitmentKind::default(),
+        }
+    }
+
+    /// Set the size of a cell in bits, a multiple of 8 that is at most the address size
+    pub fn word_size(mut self, bits: usize) -> Self {
+        self.word_bits = bits;
+        self
+    }
+
+    /// Set the stack depth, in cells
+    pub fn stack_depth(mut self, depth: u64) -> Self {
+        self.args.stack_depth = T::from(depth);
+        self
+    }
+
+    /// Set the base address of the stack section
+    pub fn stack_base(mut self, base: T) -> Self {
+        self.args.stack_base = Some(base);
+        self
+    }
+
+    /// Set the number of guard cells above the top of the stack
+    pub fn guard_cells(mut self, count: u64) -> Self {
+        self.args.guard_cells = T::from(count);
+        self
+    }
+
+    /// Set the number of registers
+    pub fn registers(mut self, count: u64) -> Self {
+        self.args.no_register = T::from(count);
+        self
+    }
+
+    /// Set the size of the buffer between two sections
+    pub fn buffer_size(mut self, size: u64) -> Self {
+        self.args.buffer_size = T::from(size);
+        self
+    }
+
+    /// Place the stack and the registers at the start of the address space if true,
+    /// at its end otherwise
+    pub fn head_layout(mut self, head_layout: bool) -> Self {
+        self.args.head_layout = head_layout;
+        self
+    }
+
+    /// Set the base address of the register section
+    pub fn register_base(mut self, base: T) -> Self {
+        self.args.register_base = Some(base);
+        self
+    }
+
+    /// Limit the memory section to the given number of bytes,
+    /// by default it spans the rest of the address space
+    pub fn memory_size(mut self, size: u64) -> Self {
+        self.memory_size = Some(T::from(size));
+        self
+    }
+
+    /// Let the memory section grow with [grow](crate::machine::AbstractMemoryMachine::grow),
+    /// it keeps its base and its end moves up to [ConfigBuilder::max_memory_size]
+    pub fn growable(mut self, growable: bool) -> Self {
+        self.growable = growable;
+        self
+    }
+
+    /// Limit the size the memory section can grow to, in bytes.
+    /// By default it grows up to the end of the address space left by the other sections
+    pub fn max_memory_size(mut self, size: u64) -> Self {
+        self.max_memory_size = Some(T::from(size));
+        self
+    }
+
+    /// Set the policy for unaligned accesses
+    pub fn alignment(mut self, alignment: AlignmentPolicy) -> Self {
+        self.args.alignment = alignment;
+        self
+    }
+
+    /// Set the semantics of reading a cell that was never written
+    pub fn read_policy(mut self, read_policy: ReadPolicy) -> Self {
+        self.args.read_policy = read_policy;
+        self
+    }
+
+    /// Set the byte order of the cells
+    pub fn endianness(mut self, endianness: Endianness) -> Self {
+        self.args.endianness = endianness;
+        self
+    }
+
+    /// Set the limits on the resources used by the machine
+    pub fn limits(mut self, limits: ResourceLimits) -> Self {
+        self.args.limits = limits;
+        self
+    }
+
+    /// Set the gas charged for every access
+    pub fn cost_model(mut self, cost_model: CostModel) -> Self {
+        self.args.cost_model = cost_model;
+        self
+    }
+
+    /// Set the maximum gas used since the machine started
+    pub fn gas_budget(mut self, budget: u64) -> Self {
+        self.args.gas_budget = Some(budget);
+        self
+    }
+
+    /// Set the named regions of the address space
+    pub fn layout(mut self, layout: MemoryLayout<T>) -> Self {
+        self.args.layout = Some(layout);
+        self
+    }
+
+    /// Keep the memory in a sparse map, only written cells are stored.
+    /// The machines have no dense storage, so `false` is rejected by [ConfigBuilder::build]
+    pub fn sparse_storage(mut self, sparse: bool) -> Self {
+        self.sparse_storage = sparse;
+        self
+    }
+
+    /// Set the granularity of the trace collected by the machine
+    pub fn trace_mode(mut self, mode: TraceMode) -> Self {
+        self.trace_mode = mode;
+        self
+    }
+
+    /// Bound the size of the circuits the trace is proved with, the budget must hold the
+    /// lookup tables of the circuits
+    pub fn prover_budget(mut self, budget: ProverBudget) -> Self {
+        self.prover_budget = Some(budget);
+        self
+    }
+
+    /// Select the backend committing the memory image and the trace, the Merkle tree hashed
+    /// with BLAKE2b by default. The backend is instantiated by the machine on first use
+    pub fn commitment(mut self, kind: CommitmentKind) -> Self {
+        self.commitment = kind;
+        self
+    }
+
+    /// Get the config arguments without validating them
+    pub fn into_args(self) -> ConfigArgs<T> {
+        self.args
+    }
+
+    /// Validate the options and build the config, return every violated constraint
+    pub fn build(self) -> Result<Config<T, S>, Vec<Error>> {
+        let mut errors = Vec::new();
+        let word_size = T::from(self.word_bits / 8);
+        let valid_word = self.word_bits != 0 && self.word_bits % 8 == 0 && self.word_bits / 8 <= S;
+        if !valid_word {
+            errors.push(Error::InvalidWordSize {
+                bits: self.word_bits,
+            });
+        }
+        if !self.sparse_storage {
+            errors.push(Error::DenseStorageUnsupported);
+        }
+        let min_k = minimal_k(0);
+        if let Some(budget) = self.prover_budget.filter(|budget| budget.max_k < min_k) {
+            errors.push(Error::ProverBudgetTooSmall {
+                max_k: budget.max_k,
+                min_k,
+            });
+        }
+        if let Err(error) = self.commitment.check_words(S, self.word_bits) {
+            errors.push(error);
+        }
+        // The sections can only be checked for a valid word size
+        if valid_word {
+            if let Err(args_errors) = self.args.validate(word_size) {
+                errors.extend(args_errors);
+            }
+        }
+        let stack_size = self.args.stack_depth.checked_mul(word_size);
+        match (self.memory_size, stack_size) {
+            (Some(memory), _) if memory.is_zero() => errors.push(Error::EmptyMemorySection),
+            (Some(memory), _) if valid_word && !(memory % word_size).is_zero() => {
+                errors.push(Error::MisalignedSection {
+                    region: RegionKind::Heap,
+                })
+            }
+            (Some(memory), Some(stack)) if stack > memory => {
+                errors.push(Error::StackLargerThanMemory)
+            }
+            _ => {}
+        }
+        let misaligned_max = |max: T| valid_word && !(max % word_size).is_zero();
+        if self.max_memory_size.is_some_and(misaligned_max) {
+            errors.push(Error::MisalignedSection {
+                region: RegionKind::Heap,
+            });
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let custom_stack = self.args.stack_base.is_some();
+        let mut config = Config::new(word_size, self.args);
+        let low = config.memory.low();
+        // Last address of the memory section in the layout, it can grow up to there
+        let layout_last = config.memory.high();
+        // The memory section keeps its base and ends after the requested size
+        if let Some(size) = self.memory_size {
+            match low.checked_add(size - T::from(1u64)) {
+                Some(high) if high <= layout_last => {
+                    config.memory = AllocatedSection(low, high);
+                }
+                _ => return Err(vec![Error::SectionsOverflow]),
+            }
+        }
+        let growth_last = match self.max_memory_size {
+            Some(max) => {
+                if section_size(low, config.memory.high()) > max {
+                    return Err(vec![Error::MemoryLargerThanMax]);
+                }
+                config.max_memory_size = max;
+                match low.checked_add(max - T::from(1u64)) {
+                    Some(last) if last <= layout_last => last,
+                    _ => return Err(vec![Error::SectionsOverflow]),
+                }
+            }
+            None => layout_last,
+        };
+        config.growable = self.growable;
+        config.trace_mode = self.trace_mode;
+        config.prover_budget = self.prover_budget;
+        config.commitment = self.commitment;
+        let memory_last = if self.growable {
+            growth_last
+        } else {
+            config.memory.high()
+        };
+        // A custom stack and its guard cells must stay clear of the memory section and the
+        // addresses it can grow to, the register section was checked by [ConfigArgs::validate]
+        if custom_stack {
+            let last = match config.stack_guard {
+                Some(guard) => guard.high(),
+                None => config.stack.high() - T::from(1u64),
+            };
+            if config.stack.low() <= memory_last && low <= last {
+                return Err(vec![Error::OverlappingRegions {
+                    first: RegionKind::Stack,
+                    second: RegionKind::Heap,
+                }]);
+            }
+        }
+        Ok(config)
+    }
 }
 
 /// Default config
@@ -61,12 +1202,7 @@ pub struct DefaultConfig;
 impl DefaultConfig {
     /// Create a default config
     pub fn default_config<const S: usize, T: Base<S>>() -> ConfigArgs<T> {
-        ConfigArgs {
-            head_layout: true,
-            stack_depth: T::from(1024),
-            no_register: T::from(32),
-            buffer_size: T::from(32),
-        }
+        ConfigBuilder::<T, S>::new().into_args()
     }
 }
 
@@ -74,12 +1210,40 @@ impl<T, const S: usize> Config<T, S>
 where
     T: Base<S>,
 {
-    /// Create a new config for given arguments
+    /// Create a new config for given arguments after checking them, return every violated
+    /// rule of [ConfigArgs::validate]
+    pub fn try_new(word_size: T, args: ConfigArgs<T>) -> Result<Self, Vec<Error>> {
+        args.validate(word_size)?;
+        Ok(Self::new(word_size, args))
+    }
+
+    /// Create a new config for given arguments, they are not checked
     pub fn new(word_size: T, args: ConfigArgs<T>) -> Self {
+        let mut config = Self::layout(word_size, &args);
+        if let Some(register_lo) = args.register_base {
+            config.register =
+                AllocatedSection(register_lo, register_lo + (args.no_register * word_size));
+        }
+        if let Some(stack_lo) = args.stack_base {
+            config.stack = AllocatedSection(stack_lo, stack_lo + (args.stack_depth * word_size));
+        }
+        // The guard cells start at the cell a push on a full stack would write
+        if !args.guard_cells.is_zero() {
+            let guard_lo = config.stack.high();
+            config.stack_guard = Some(AllocatedSection(
+                guard_lo,
+                guard_lo + (args.guard_cells * word_size) - T::from(1u64),
+            ));
+        }
+        config
+    }
+
+    // Compute the default layout of the sections
+    fn layout(word_size: T, args: &ConfigArgs<T>) -> Self {
         if args.head_layout {
             let stack_lo = T::MIN;
             let stack_hi = stack_lo + (args.stack_depth * word_size);
-            let register_lo = stack_hi + args.buffer_size;
+            let register_lo = stack_hi + (args.guard_cells * word_size) + args.buffer_size;
             let register_hi = register_lo + (args.no_register * word_size);
             let memory_lo = register_hi + args.buffer_size;
             let memory_hi = T::MAX;
@@ -89,17 +1253,33 @@ where
                 buffer_size: args.buffer_size,
                 stack: AllocatedSection(stack_lo, stack_hi),
                 register: AllocatedSection(register_lo, register_hi),
+                stack_guard: None,
                 memory: AllocatedSection(memory_lo, memory_hi),
+                growable: false,
+                max_memory_size: section_size(memory_lo, memory_hi),
+                alignment: args.alignment,
+                read_policy: args.read_policy,
+                endianness: args.endianness,
+                limits: args.limits,
+                cost_model: args.cost_model,
+                gas_budget: args.gas_budget,
+                layout: args.layout,
+                trace_mode: TraceMode::Full,
+                prover_budget: None,
+                commitment: CommitmentKind::default(),
             }
         } else {
-            let length =
-                (args.stack_depth + args.no_register + args.buffer_size + args.buffer_size)
-                    * word_size;
+            let length = (args.stack_depth
+                + args.guard_cells
+                + args.no_register
+                + args.buffer_size
+                + args.buffer_size)
+                * word_size;
             let stack_lo = T::MAX - length;
             let remain = stack_lo % word_size;
             let stack_lo = stack_lo - remain + word_size;
             let stack_hi = stack_lo + (args.stack_depth * word_size);
-            let register_lo = stack_hi + args.buffer_size;
+            let register_lo = stack_hi + (args.guard_cells * word_size) + args.buffer_size;
             let register_hi = register_lo + (args.no_register * word_size);
             let memory_lo = T::MIN;
             let memory_hi = T::MAX - length;
@@ -110,7 +1290,20 @@ where
                 buffer_size: args.buffer_size,
                 stack: AllocatedSection(stack_lo, stack_hi),
                 register: AllocatedSection(register_lo, register_hi),
+                stack_guard: None,
                 memory: AllocatedSection(memory_lo, memory_hi),
+                growable: false,
+                max_memory_size: section_size(memory_lo, memory_hi),
+                alignment: args.alignment,
+                read_policy: args.read_policy,
+                endianness: args.endianness,
+                limits: args.limits,
+                cost_model: args.cost_model,
+                gas_budget: args.gas_budget,
+                layout: args.layout,
+                trace_mode: TraceMode::Full,
+                prover_budget: None,
+                commitment: CommitmentKind::default(),
             }
         }
     }
@@ -122,13 +1315,242 @@ where
             self.register.low() + (T::from(index) * self.word_size),
         )
     }
+
+    /// Get the fingerprint of the config, the BLAKE2b hash of its canonical encoding.
+    /// It is written in the persisted traces, snapshots and checkpoints so they are not
+    /// loaded under another config, see [FingerprintCheck]. The resource limits, the cost
+    /// model, the gas budget and the prover budget only bound an execution or its proof, the
+    /// commitment backend only commits it, they are not part of the fingerprint
+    pub fn config_fingerprint(&self) -> [u8; 32] {
+        let mut fingerprint = [0u8; 32];
+        fingerprint.copy_from_slice(
+            Params::new()
+                .hash_length(32)
+                .personal(FINGERPRINT_PERSONAL)
+                .hash(&self.canonical_bytes())
+                .as_bytes(),
+        );
+        fingerprint
+    }
+
+    // Encode the fields of the config that give a meaning to a trace, in a fixed order with
+    // big endian words and a presence byte before every optional field
+    fn canonical_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![S as u8];
+        for word in [
+            self.word_size,
+            self.stack_depth,
+            self.buffer_size,
+            self.max_memory_size,
+        ] {
+            push_word(&mut bytes, word);
+        }
+        for section in [self.memory, self.stack, self.register] {
+            push_word(&mut bytes, section.low());
+            push_word(&mut bytes, section.high());
+        }
+        push_optional_word(&mut bytes, self.stack_guard.map(|guard| guard.low()));
+        push_optional_word(&mut bytes, self.stack_guard.map(|guard| guard.high()));
+        bytes.extend_from_slice(&[
+            self.growable as u8,
+            self.alignment as u8,
+            self.read_policy as u8,
+            self.endianness.code(),
+        ]);
+        match self.layout {
+            Some(layout) => {
+                bytes.push(1);
+                layout.encode(&mut bytes);
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+}
+
+// Append the big endian bytes of the word
+fn push_word<T: Base<S>, const S: usize>(bytes: &mut Vec<u8>, word: T) {
+    let word: [u8; S] = word.into();
+    bytes.extend_from_slice(&word);
+}
+
+// Append a presence byte followed by the word if there is one
+fn push_optional_word<T: Base<S>, const S: usize>(bytes: &mut Vec<u8>, word: Option<T>) {
+    match word {
+        Some(word) => {
+            bytes.push(1);
+            push_word(bytes, word);
+        }
+        None => bytes.push(0),
+    }
+}
+
+/// Get the size of a section in bytes from its first and last address,
+/// the maximum address if the section spans the whole address space
+pub(crate) fn section_size<T: Base<S>, const S: usize>(low: T, high: T) -> T {
+    (high - low).checked_add(T::from(1u64)).unwrap_or(T::MAX)
+}
+
+// Build a preset, its layout maps the stack, the registers if `registers` is set and the
+// memory section up to `heap_last` followed by the extra regions
+fn preset<T, const S: usize>(
+    builder: impl Fn() -> ConfigBuilder<T, S>,
+    registers: bool,
+    heap_last: T,
+    extra: &[MemoryRegion<T>],
+) -> Config<T, S>
+where
+    T: Base<S>,
+{
+    let sections = builder().build().expect("The presets are valid");
+    // The high address of the stack and register sections is the first address after them
+    let region = |kind, section: AllocatedSection<T>| {
+        MemoryRegion::new(kind, section.low(), section.high() - section.low())
+    };
+    let mut regions = vec![region(RegionKind::Stack, sections.stack)];
+    if registers {
+        regions.push(region(RegionKind::Register, sections.register));
+    }
+    regions.push(MemoryRegion::new(
+        RegionKind::Heap,
+        sections.memory.low(),
+        heap_last - sections.memory.low() + T::from(1u64),
+    ));
+    regions.extend_from_slice(extra);
+    let layout = MemoryLayout::new(&regions).expect("The regions of the presets do not overlap");
+    builder()
+        .layout(layout)
+        .build()
+        .expect("The presets are valid")
+}
+
+impl Config<B256, 32> {
+    /// Preset of an EVM machine: 256-bit words, a stack of 1024 words, a byte-addressable
+    /// memory and a read-only calldata region of [EVM_CALLDATA_SIZE] bytes at
+    /// [EVM_CALLDATA_BASE], loaded by the host with
+    /// [init_region](crate::machine::AbstractMemoryMachine::init_region)
+    ///
+    /// ```
+    /// use zkmemory::base::{Base, B256};
+    /// use zkmemory::config::{Config, Permission, RegionKind, EVM_CALLDATA_BASE};
+    ///
+    /// let config = Config::evm();
+    /// let layout = config.layout.unwrap();
+    /// let word = B256::WORD_SIZE;
+    /// // PUSH1 0x20, MSTORE at an unaligned offset, CALLDATALOAD 0: every touched cell is mapped
+    /// assert_eq!(config.stack_depth, B256::from(1024));
+    /// assert_eq!(layout.check(config.stack.low(), word).unwrap().kind, RegionKind::Stack);
+    /// let store = config.memory.low() + B256::from(0x41);
+    /// for cell in [store - B256::from(1), store + B256::from(31)] {
+    ///     assert_eq!(layout.check(cell, word).unwrap().kind, RegionKind::Heap);
+    /// }
+    /// let calldata = layout.check(EVM_CALLDATA_BASE, word).unwrap();
+    /// assert_eq!(calldata.kind, RegionKind::Io);
+    /// assert!(!calldata.permission.allows(Permission::Write));
+    /// ```
+    pub fn evm() -> Self {
+        let calldata = MemoryRegion::new(
+            RegionKind::Io,
+            EVM_CALLDATA_BASE,
+            B256::from(EVM_CALLDATA_SIZE),
+        )
+        .with_permission(Permission::Read);
+        preset(
+            || {
+                ConfigBuilder::new()
+                    .word_size(256)
+                    .stack_depth(1024)
+                    .alignment(AlignmentPolicy::Emulate)
+            },
+            false,
+            EVM_CALLDATA_BASE - B256::from(1u64),
+            &[calldata],
+        )
+    }
+}
+
+impl Config<B32, 4> {
+    /// Preset of a RISC-V RV32 machine: 32-bit little endian words, aligned accesses only and
+    /// a register region of 32 registers
+    ///
+    /// ```
+    /// use zkmemory::base::{Base, B32};
+    /// use zkmemory::config::{AlignmentPolicy, Config, RegionKind};
+    ///
+    /// let config = Config::riscv32();
+    /// let layout = config.layout.unwrap();
+    /// // `addi x1, x0, 5` then `sw x1, 8(x2)`: a register and an aligned word of the memory
+    /// let x1 = config.create_register(1).address();
+    /// assert_eq!(layout.check(x1, B32::WORD_SIZE).unwrap().kind, RegionKind::Register);
+    /// let store = config.memory.low() + B32::from(8);
+    /// assert_eq!(layout.check(store, B32::WORD_SIZE).unwrap().kind, RegionKind::Heap);
+    /// assert_eq!(config.alignment, AlignmentPolicy::Strict);
+    /// ```
+    pub fn riscv32() -> Self {
+        preset(
+            || {
+                ConfigBuilder::new()
+                    .word_size(32)
+                    .registers(32)
+                    .alignment(AlignmentPolicy::Strict)
+                    .endianness(Endianness::Little)
+            },
+            true,
+            B32::MAX,
+            &[],
+        )
+    }
+
+    /// Preset of a WebAssembly machine with a 32-bit linear memory: 32-bit little endian words,
+    /// a value stack of 1024 words, unaligned accesses and no register region
+    ///
+    /// ```
+    /// use zkmemory::base::{Base, B32};
+    /// use zkmemory::config::{Config, RegionKind};
+    ///
+    /// let config = Config::wasm32();
+    /// let layout = config.layout.unwrap();
+    /// // `i32.const 7` then `i32.store offset=2`: the value stack and two cells of the memory
+    /// let word = B32::WORD_SIZE;
+    /// assert_eq!(layout.check(config.stack.low(), word).unwrap().kind, RegionKind::Stack);
+    /// let store = config.memory.low() + B32::from(2);
+    /// assert!(layout.check(store - B32::from(2), word).is_ok());
+    /// assert!(layout.check(store + B32::from(2), word).is_ok());
+    /// assert_eq!(layout.region(RegionKind::Register), None);
+    /// ```
+    pub fn wasm32() -> Self {
+        preset(
+            || {
+                ConfigBuilder::new()
+                    .word_size(32)
+                    .stack_depth(1024)
+                    .alignment(AlignmentPolicy::Emulate)
+                    .endianness(Endianness::Little)
+            },
+            false,
+            B32::MAX,
+            &[],
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::ConfigArgs;
-    use crate::base::{Base, B256};
-    use crate::config::{Config, DefaultConfig};
+    use super::{
+        AlignmentPolicy, ConfigArgs, ConfigBuilder, CostModel, Endianness, ProverBudget,
+        ReadPolicy, ResourceLimits,
+    };
+    use crate::base::{Base, B256, B32, B512, B64};
+    use crate::commitment::{merkle::HashKind, selector::CommitmentKind};
+    use crate::config::{
+        AllocatedSection, Config, DefaultConfig, MemoryLayout, MemoryRegion, RegionKind,
+        DEFAULT_MEMORY_BASE, DEFAULT_MEMORY_SIZE, DEFAULT_REGISTER_BASE, DEFAULT_STACK_BASE,
+        DEFAULT_STACK_DEPTH, EVM_CALLDATA_BASE, MAX_RESERVED_RANGES, MINIMAL_NO_REGISTER,
+        MINIMAL_STACK_DEPTH,
+    };
+    use crate::error::Error;
+    extern crate alloc;
+    use alloc::{vec, vec::Vec};
 
     impl PartialEq for ConfigArgs<B256> {
         fn eq(&self, other: &Self) -> bool {
@@ -136,6 +1558,16 @@ mod tests {
                 && self.stack_depth == other.stack_depth
                 && self.no_register == other.no_register
                 && self.buffer_size == other.buffer_size
+                && self.register_base == other.register_base
+                && self.stack_base == other.stack_base
+                && self.guard_cells == other.guard_cells
+                && self.alignment == other.alignment
+                && self.read_policy == other.read_policy
+                && self.endianness == other.endianness
+                && self.limits == other.limits
+                && self.cost_model == other.cost_model
+                && self.gas_budget == other.gas_budget
+                && self.layout == other.layout
         }
     }
 
@@ -146,6 +1578,16 @@ mod tests {
             stack_depth: B256::from(1024),
             no_register: B256::from(32),
             buffer_size: B256::from(32),
+            register_base: None,
+            stack_base: None,
+            guard_cells: B256::zero(),
+            alignment: AlignmentPolicy::Emulate,
+            read_policy: ReadPolicy::ZeroOnUninit,
+            endianness: Endianness::Big,
+            limits: ResourceLimits::default(),
+            cost_model: CostModel::default(),
+            gas_budget: None,
+            layout: None,
         };
         assert_eq!(config, DefaultConfig::default_config());
     }
@@ -164,6 +1606,16 @@ mod tests {
                 stack_depth: B256::from(1024),
                 no_register: B256::from(32),
                 buffer_size: B256::from(32),
+                register_base: None,
+                stack_base: None,
+                guard_cells: B256::zero(),
+                alignment: AlignmentPolicy::Emulate,
+                read_policy: ReadPolicy::ZeroOnUninit,
+                endianness: Endianness::Big,
+                limits: ResourceLimits::default(),
+                cost_model: CostModel::default(),
+                gas_budget: None,
+                layout: None,
             },
         );
         assert!(config.memory.contain(B256::from(0x10000f)));
@@ -172,4 +1624,727 @@ mod tests {
         config.create_register(0);
         assert!(!config.register.contain(B256::from(10)));
     }
+
+    #[test]
+    fn test_section_cells() {
+        let section = AllocatedSection::new(B64::from(16), B64::from(40));
+        let cells: Vec<B64> = section.cells().collect();
+        assert_eq!(
+            cells,
+            [B64::from(16), B64::from(24), B64::from(32), B64::from(40)]
+        );
+
+        // The last section of the address space ends at MAX
+        let section = AllocatedSection::new(B64::MAX - B64::from(15), B64::MAX);
+        assert_eq!(section.cells().count(), 2);
+    }
+
+    #[test]
+    fn test_default_layout_consts() {
+        let config = Config::new(B256::WORD_SIZE, DefaultConfig::default_config());
+        assert_eq!(config.stack.low(), DEFAULT_STACK_BASE);
+        assert_eq!(config.register.low(), DEFAULT_REGISTER_BASE);
+        assert_eq!(config.memory.low(), DEFAULT_MEMORY_BASE);
+    }
+
+    #[test]
+    fn test_parse_config_args() {
+        let config = ConfigArgs::<B256>::parse(true, "1KiB", "32", "0x20").unwrap();
+        assert_eq!(config, DefaultConfig::default_config());
+        assert_eq!(
+            ConfigArgs::<B64>::parse(false, "2^64", "32", "32").unwrap_err(),
+            Error::ValueOutOfRange
+        );
+        assert_eq!(
+            ConfigArgs::<B64>::parse(false, "1024", "many", "32").unwrap_err(),
+            Error::InvalidSizeFormat
+        );
+    }
+
+    #[test]
+    fn test_config_builder() {
+        // The defaults of the builder are the default config
+        let built = ConfigBuilder::<B256, 32>::new().build().unwrap();
+        let config = Config::<B256, 32>::new(B256::WORD_SIZE, DefaultConfig::default_config());
+        assert_eq!(built.memory.low(), config.memory.low());
+        assert_eq!(built.memory.high(), B256::MAX);
+        assert_eq!(built.stack.high(), config.stack.high());
+
+        let built = ConfigBuilder::<B64, 8>::new()
+            .word_size(64)
+            .stack_depth(16)
+            .registers(4)
+            .buffer_size(8)
+            .memory_size(1 << 20)
+            .alignment(AlignmentPolicy::Strict)
+            .build()
+            .unwrap();
+        assert_eq!(built.stack.high(), B64::from(16 * 8));
+        assert_eq!(built.register.low(), B64::from(16 * 8 + 8));
+        let memory_low = B64::from(16 * 8 + 8 + 4 * 8 + 8);
+        assert_eq!(built.memory.low(), memory_low);
+        assert_eq!(built.memory.high(), memory_low + B64::from((1 << 20) - 1));
+        assert_eq!(built.alignment, AlignmentPolicy::Strict);
+    }
+
+    #[test]
+    fn test_minimal_config() {
+        use crate::machine::{tests::StateMachine, AbstractMemoryMachine};
+
+        let config = Config::<B256, 32>::try_new(B256::WORD_SIZE, ConfigArgs::minimal()).unwrap();
+        assert_eq!(config.stack_depth, B256::from(MINIMAL_STACK_DEPTH));
+        assert_eq!(
+            config.register.high() - config.register.low(),
+            B256::from(MINIMAL_NO_REGISTER * 32)
+        );
+        // Only the written cells are allocated, however large the memory section is
+        let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(config);
+        sm.write(config.memory.low(), B256::from(1)).unwrap();
+        sm.write(config.memory.low() + B256::from(1u64 << 40), B256::from(2))
+            .unwrap();
+        assert_eq!(sm.snapshot().cells().len(), 2);
+
+        // The defaults of the builder follow the `small-memory` feature
+        let built = ConfigBuilder::<B256, 32>::new().build().unwrap();
+        assert_eq!(built.stack_depth, B256::from(DEFAULT_STACK_DEPTH));
+        match DEFAULT_MEMORY_SIZE {
+            Some(size) => assert_eq!(
+                built.memory.high() - built.memory.low() + B256::from(1),
+                B256::from(size)
+            ),
+            None => assert_eq!(built.memory.high(), B256::MAX),
+        }
+    }
+
+    #[test]
+    fn test_config_builder_conflicts() {
+        // Every violated constraint is reported
+        assert_eq!(
+            ConfigBuilder::<B64, 8>::new()
+                .word_size(12)
+                .stack_depth(1024)
+                .memory_size(256)
+                .sparse_storage(false)
+                .build()
+                .unwrap_err(),
+            vec![
+                Error::InvalidWordSize { bits: 12 },
+                Error::DenseStorageUnsupported,
+                Error::StackLargerThanMemory,
+            ]
+        );
+        assert_eq!(
+            ConfigBuilder::<B64, 8>::new()
+                .word_size(128)
+                .build()
+                .unwrap_err(),
+            vec![Error::InvalidWordSize { bits: 128 }]
+        );
+        assert_eq!(
+            ConfigBuilder::<B64, 8>::new()
+                .stack_depth(u64::MAX)
+                .memory_size(0)
+                .build()
+                .unwrap_err(),
+            vec![Error::SectionsOverflow, Error::EmptyMemorySection]
+        );
+        // The memory section must end inside the address space
+        assert_eq!(
+            ConfigBuilder::<B64, 8>::new()
+                .memory_size(u64::MAX - 7)
+                .build()
+                .unwrap_err(),
+            vec![Error::SectionsOverflow]
+        );
+        // The memory section holds whole cells
+        assert_eq!(
+            ConfigBuilder::<B64, 8>::new()
+                .memory_size(100)
+                .stack_depth(1)
+                .build()
+                .unwrap_err(),
+            vec![Error::MisalignedSection {
+                region: RegionKind::Heap
+            }]
+        );
+        // The circuits of the prover budget hold the lookup tables
+        assert_eq!(
+            ConfigBuilder::<B64, 8>::new()
+                .prover_budget(ProverBudget { max_k: 9 })
+                .build()
+                .unwrap_err(),
+            vec![Error::ProverBudgetTooSmall {
+                max_k: 9,
+                min_k: 10
+            }]
+        );
+        let budget = ProverBudget { max_k: 10 };
+        let built = ConfigBuilder::<B64, 8>::new()
+            .prover_budget(budget)
+            .build()
+            .unwrap();
+        assert_eq!(built.prover_budget, Some(budget));
+
+        // The backends over a field do not commit words wider than the witness
+        let kind = CommitmentKind::Merkle {
+            hash: HashKind::Keccak256,
+        };
+        let built = ConfigBuilder::<B512, 64>::new()
+            .commitment(kind)
+            .build()
+            .unwrap();
+        assert_eq!(built.commitment, kind);
+        for kind in [
+            CommitmentKind::Kzg {
+                k: 8,
+                seed: [0; 32],
+            },
+            CommitmentKind::Pedersen { k: 8 },
+        ] {
+            assert_eq!(
+                ConfigBuilder::<B512, 64>::new()
+                    .commitment(kind)
+                    .build()
+                    .unwrap_err(),
+                vec![Error::CommitmentWordTooWide { bits: 512 }]
+            );
+            assert!(ConfigBuilder::<B256, 32>::new()
+                .commitment(kind)
+                .build()
+                .is_ok());
+        }
+        assert_eq!(
+            ConfigBuilder::<B64, 8>::new()
+                .commitment(CommitmentKind::Verkle)
+                .build()
+                .unwrap_err(),
+            vec![Error::CommitmentUnsupported]
+        );
+    }
+
+    #[test]
+    fn test_growable_memory_config() {
+        let built = ConfigBuilder::<B64, 8>::new()
+            .memory_size(0x100)
+            .growable(true)
+            .max_memory_size(0x1000)
+            .build()
+            .unwrap();
+        assert!(built.growable);
+        assert_eq!(built.max_memory_size, B64::from(0x1000));
+        assert_eq!(built.memory.high() - built.memory.low(), B64::from(0xff));
+        // Without a maximum the memory section grows up to the end of the address space
+        let growable = || {
+            ConfigBuilder::<B64, 8>::new()
+                .memory_size(0x100)
+                .growable(true)
+        };
+        let built = growable().build().unwrap();
+        assert_eq!(
+            built.max_memory_size,
+            B64::MAX - built.memory.low() + B64::from(1)
+        );
+        assert!(!ConfigBuilder::<B64, 8>::new().build().unwrap().growable);
+
+        // The maximum size holds whole cells and the memory section, and ends in the address space
+        for (max_memory_size, error) in [
+            (
+                0x104,
+                Error::MisalignedSection {
+                    region: RegionKind::Heap,
+                },
+            ),
+            (0x80, Error::MemoryLargerThanMax),
+            (u64::MAX - 7, Error::SectionsOverflow),
+        ] {
+            assert_eq!(
+                growable()
+                    .max_memory_size(max_memory_size)
+                    .build()
+                    .unwrap_err(),
+                vec![error]
+            );
+        }
+
+        // The memory section of a tail layout never grows into the stack
+        let built = growable().head_layout(false).build().unwrap();
+        let growth_last = built.memory.low() + (built.max_memory_size - B64::from(1));
+        assert!(growth_last < built.stack.low());
+        // A custom stack must stay clear of the addresses the memory section can grow to
+        let custom_stack = || growable().stack_base(B64::from(0x10000));
+        assert!(custom_stack().max_memory_size(0x1000).build().is_ok());
+        assert_eq!(
+            custom_stack().build().unwrap_err(),
+            vec![Error::OverlappingRegions {
+                first: RegionKind::Stack,
+                second: RegionKind::Heap,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validate_config_args() {
+        let args = || ConfigArgs::<B64> {
+            ..DefaultConfig::default_config()
+        };
+        let misaligned = |region| Error::MisalignedSection { region };
+        let cases: Vec<(&str, u64, ConfigArgs<B64>, Vec<Error>)> = vec![
+            (
+                "zero word",
+                0,
+                args(),
+                vec![Error::InvalidWordSize { bits: 0 }],
+            ),
+            (
+                "wide word",
+                16,
+                args(),
+                vec![Error::InvalidWordSize { bits: 128 }],
+            ),
+            (
+                "empty stack",
+                8,
+                ConfigArgs {
+                    stack_depth: B64::zero(),
+                    ..args()
+                },
+                vec![Error::EmptyStack],
+            ),
+            (
+                "stack out of address space",
+                8,
+                ConfigArgs {
+                    stack_base: Some(B64::from(u64::MAX - 7)),
+                    ..args()
+                },
+                vec![Error::StackOutOfAddressSpace],
+            ),
+            (
+                "sections overflow",
+                8,
+                ConfigArgs {
+                    stack_depth: B64::MAX,
+                    ..args()
+                },
+                vec![Error::SectionsOverflow],
+            ),
+            (
+                "registers overflow",
+                8,
+                ConfigArgs {
+                    register_base: Some(B64::from(u64::MAX - 7)),
+                    ..args()
+                },
+                vec![Error::SectionsOverflow],
+            ),
+            (
+                "misaligned stack",
+                8,
+                ConfigArgs {
+                    stack_base: Some(B64::from(4u64)),
+                    ..args()
+                },
+                vec![misaligned(RegionKind::Stack)],
+            ),
+            (
+                "misaligned registers",
+                8,
+                ConfigArgs {
+                    buffer_size: B64::from(12u64),
+                    ..args()
+                },
+                vec![misaligned(RegionKind::Register)],
+            ),
+            (
+                "stack overlapping the registers",
+                8,
+                ConfigArgs {
+                    register_base: Some(B64::from(0x10u64)),
+                    ..args()
+                },
+                vec![Error::OverlappingRegions {
+                    first: RegionKind::Stack,
+                    second: RegionKind::Register,
+                }],
+            ),
+            (
+                "region word size",
+                8,
+                ConfigArgs {
+                    layout: Some(
+                        MemoryLayout::new(&[MemoryRegion::new(
+                            RegionKind::Io,
+                            B64::from(0x50000u64),
+                            B64::from(0x40u64),
+                        )
+                        .with_word_size(B64::from(3u64))])
+                        .unwrap(),
+                    ),
+                    ..args()
+                },
+                vec![Error::InvalidRegionWordSize {
+                    region: RegionKind::Io,
+                }],
+            ),
+            (
+                "every size violation",
+                8,
+                ConfigArgs {
+                    stack_depth: B64::zero(),
+                    register_base: Some(B64::from(u64::MAX - 7)),
+                    ..args()
+                },
+                vec![Error::EmptyStack, Error::SectionsOverflow],
+            ),
+            (
+                "every alignment violation",
+                8,
+                ConfigArgs {
+                    stack_base: Some(B64::from(4u64)),
+                    buffer_size: B64::from(12u64),
+                    ..args()
+                },
+                vec![
+                    misaligned(RegionKind::Stack),
+                    misaligned(RegionKind::Register),
+                ],
+            ),
+        ];
+        for (name, word_size, args, errors) in cases {
+            assert_eq!(
+                args.validate(B64::from(word_size)).unwrap_err(),
+                errors,
+                "{}",
+                name
+            );
+            assert_eq!(
+                Config::<B64, 8>::try_new(B64::from(word_size), args).unwrap_err(),
+                errors,
+                "{}",
+                name
+            );
+        }
+        assert_eq!(args().validate(B64::from(8u64)), Ok(()));
+        assert!(Config::<B64, 8>::try_new(B64::from(8u64), args()).is_ok());
+    }
+
+    #[test]
+    fn test_stack_config() {
+        // The guard cells sit between the stack and the registers
+        let built = ConfigBuilder::<B64, 8>::new()
+            .stack_depth(16)
+            .guard_cells(2)
+            .registers(4)
+            .buffer_size(8)
+            .build()
+            .unwrap();
+        let guard = built.stack_guard.unwrap();
+        assert_eq!(guard.low(), B64::from(16 * 8));
+        assert_eq!(guard.high(), B64::from(18 * 8 - 1));
+        assert_eq!(built.register.low(), B64::from(18 * 8 + 8));
+        assert!(ConfigBuilder::<B64, 8>::new()
+            .build()
+            .unwrap()
+            .stack_guard
+            .is_none());
+
+        let built = ConfigBuilder::<B64, 8>::new()
+            .memory_size(0x1000)
+            .stack_base(B64::from(0x8000))
+            .stack_depth(1)
+            .build()
+            .unwrap();
+        assert_eq!(built.stack.low(), B64::from(0x8000));
+        assert_eq!(built.stack.high(), B64::from(0x8008));
+
+        // Misconfigured stacks are rejected
+        assert_eq!(
+            ConfigBuilder::<B64, 8>::new()
+                .stack_depth(0)
+                .build()
+                .unwrap_err(),
+            vec![Error::EmptyStack]
+        );
+        assert_eq!(
+            ConfigBuilder::<B64, 8>::new()
+                .stack_base(B64::MAX - B64::from(15))
+                .stack_depth(2)
+                .guard_cells(1)
+                .build()
+                .unwrap_err(),
+            vec![Error::StackOutOfAddressSpace]
+        );
+        // The memory section spans the rest of the address space by default
+        assert_eq!(
+            ConfigBuilder::<B64, 8>::new()
+                .stack_base(B64::from(0x8000))
+                .build()
+                .unwrap_err(),
+            vec![Error::OverlappingRegions {
+                first: RegionKind::Stack,
+                second: RegionKind::Heap,
+            }]
+        );
+        let register = ConfigBuilder::<B64, 8>::new().build().unwrap().register;
+        assert_eq!(
+            ConfigBuilder::<B64, 8>::new()
+                .memory_size(0x1000)
+                .stack_base(register.low())
+                .build()
+                .unwrap_err(),
+            vec![Error::OverlappingRegions {
+                first: RegionKind::Stack,
+                second: RegionKind::Register,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_config_presets() {
+        let evm = Config::evm();
+        let riscv = Config::riscv32();
+        let wasm = Config::wasm32();
+        let layouts = [
+            evm.layout.unwrap(),
+            riscv.layout.unwrap(),
+            wasm.layout.unwrap(),
+        ];
+        // The layouts pass the overlap validation again and map the stack of their config
+        for layout in layouts.iter() {
+            assert_eq!(MemoryLayout::new(&layout.all_regions()), Ok(*layout));
+        }
+        assert_eq!(
+            layouts[0].region(RegionKind::Stack).unwrap().length,
+            B256::from(1024 * 32)
+        );
+        assert_eq!(
+            layouts[0].region(RegionKind::Io).unwrap().base,
+            EVM_CALLDATA_BASE
+        );
+        assert_eq!(layouts[0].region(RegionKind::Register), None);
+        assert_eq!(riscv.word_size, B32::from(4));
+        assert_eq!(
+            [evm.endianness, riscv.endianness, wasm.endianness],
+            [Endianness::Big, Endianness::Little, Endianness::Little]
+        );
+        assert_eq!(
+            layouts[1].region(RegionKind::Register).unwrap().base,
+            riscv.register.low()
+        );
+        assert_eq!(wasm.alignment, AlignmentPolicy::Emulate);
+        assert_eq!(
+            layouts[2].region(RegionKind::Heap).unwrap().base,
+            wasm.memory.low()
+        );
+    }
+
+    #[test]
+    fn test_custom_register_base() {
+        let base = B256::from(0x100000);
+        let config = Config::<B256, 32>::new(
+            B256::WORD_SIZE,
+            ConfigArgs {
+                register_base: Some(base),
+                ..DefaultConfig::default_config()
+            },
+        );
+        assert_eq!(config.register.low(), base);
+        assert_eq!(config.register.high(), base + B256::from(32 * 32));
+        assert_eq!(config.create_register(1).address(), base + B256::from(32));
+        // The other sections keep the default layout
+        assert_eq!(config.stack.low(), DEFAULT_STACK_BASE);
+        assert_eq!(config.memory.low(), DEFAULT_MEMORY_BASE);
+    }
+
+    #[test]
+    fn test_memory_layout() {
+        let layout = MemoryLayout::new(&[
+            MemoryRegion::new(RegionKind::Code, B256::from(0x1000), B256::from(0x1000)),
+            MemoryRegion::new(RegionKind::Heap, B256::from(0x4000), B256::from(0x4000)),
+            MemoryRegion::new(RegionKind::Stack, B256::from(0x2000), B256::from(0x800)),
+        ])
+        .unwrap();
+        let word_size = B256::WORD_SIZE;
+        assert_eq!(
+            layout.region_of(B256::from(0x1fe0), word_size),
+            layout.region(RegionKind::Code)
+        );
+        // The cell must be fully inside the region
+        assert_eq!(layout.region_of(B256::from(0x1fe1), word_size), None);
+        assert_eq!(layout.region(RegionKind::Io), None);
+        assert_eq!(
+            layout.check(B256::from(0x3000), word_size),
+            Err(Error::AccessOutsideRegion {
+                addr: B256::from(0x3000).fixed_be_bytes(),
+                region: Some(RegionKind::Stack),
+            })
+        );
+        assert_eq!(
+            layout.check(B256::zero(), word_size),
+            Err(Error::AccessOutsideRegion {
+                addr: [0u8; 32],
+                region: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_memory_layout() {
+        let code = MemoryRegion::new(RegionKind::Code, B64::from(0x1000), B64::from(0x1000));
+        assert_eq!(
+            MemoryLayout::new(&[
+                code,
+                MemoryRegion::new(RegionKind::Io, B64::from(0x1ff8), B64::from(8))
+            ]),
+            Err(Error::OverlappingRegions {
+                first: RegionKind::Code,
+                second: RegionKind::Io,
+            })
+        );
+        assert_eq!(
+            MemoryLayout::new(&[
+                MemoryRegion::new(RegionKind::Heap, B64::zero(), B64::from(0x2000)),
+                code
+            ]),
+            Err(Error::OverlappingRegions {
+                first: RegionKind::Heap,
+                second: RegionKind::Code,
+            })
+        );
+        assert_eq!(
+            MemoryLayout::new(&[code, code]),
+            Err(Error::InvalidRegion {
+                region: RegionKind::Code
+            })
+        );
+        assert_eq!(
+            MemoryLayout::new(&[MemoryRegion::new(RegionKind::Io, B64::from(8), B64::zero())]),
+            Err(Error::InvalidRegion {
+                region: RegionKind::Io
+            })
+        );
+        assert_eq!(
+            MemoryLayout::new(&[MemoryRegion::new(RegionKind::Io, B64::MAX, B64::from(2))]),
+            Err(Error::InvalidRegion {
+                region: RegionKind::Io
+            })
+        );
+        // A region may end at the last address
+        assert!(
+            MemoryLayout::new(&[MemoryRegion::new(RegionKind::Io, B64::MAX, B64::from(1))]).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_config_fingerprint() {
+        let builder = || ConfigBuilder::<B64, 8>::new().memory_size(1 << 16);
+        let fingerprint = builder().build().unwrap().config_fingerprint();
+        assert_eq!(builder().build().unwrap().config_fingerprint(), fingerprint);
+
+        // The limits of an execution do not change the fingerprint
+        let limited = builder()
+            .limits(ResourceLimits {
+                max_trace_len: Some(10),
+                ..ResourceLimits::default()
+            })
+            .gas_budget(100)
+            .prover_budget(ProverBudget { max_k: 12 })
+            .build()
+            .unwrap();
+        assert_eq!(limited.config_fingerprint(), fingerprint);
+
+        // The word size, the sections, the byte order and the layout do
+        let heap = MemoryRegion::new(RegionKind::Heap, B64::from(0x10000), B64::from(0x1000));
+        let layout = MemoryLayout::new(&[heap]).unwrap();
+        let others = [
+            builder().word_size(32).build().unwrap(),
+            builder().stack_depth(16).build().unwrap(),
+            builder().memory_size(1 << 15).build().unwrap(),
+            builder().endianness(Endianness::Little).build().unwrap(),
+            builder().layout(layout).build().unwrap(),
+            builder()
+                .layout(layout.reserve(B64::zero()..=B64::from(0xfff)).unwrap())
+                .build()
+                .unwrap(),
+        ];
+        let mut fingerprints: Vec<[u8; 32]> = others
+            .iter()
+            .map(|config| config.config_fingerprint())
+            .collect();
+        fingerprints.push(fingerprint);
+        fingerprints.sort();
+        fingerprints.dedup();
+        assert_eq!(fingerprints.len(), others.len() + 1);
+        assert_ne!(
+            Config::<B256, 32>::evm().config_fingerprint(),
+            ConfigBuilder::<B256, 32>::new()
+                .build()
+                .unwrap()
+                .config_fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_reserved_ranges() {
+        let code = MemoryRegion::new(RegionKind::Code, B64::from(0x1000), B64::from(0x1000));
+        let layout = MemoryLayout::new(&[code])
+            .unwrap()
+            .reserve(B64::zero()..=B64::from(0xfff))
+            .unwrap()
+            .reserve(B64::from(0x3000)..=B64::from(0x3fff))
+            .unwrap();
+        assert_eq!(
+            layout.reserved_ranges(),
+            vec![
+                B64::zero()..=B64::from(0xfff),
+                B64::from(0x3000)..=B64::from(0x3fff)
+            ]
+        );
+        let word_size = B64::WORD_SIZE;
+        let reserved = |address: u64| {
+            Err(Error::ReservedAddress {
+                addr: B64::from(address).fixed_be_bytes(),
+            })
+        };
+        // First and last address of the ranges, and a cell that only ends in a range
+        assert_eq!(layout.check(B64::zero(), word_size), reserved(0));
+        assert_eq!(layout.check(B64::from(0xff8), word_size), reserved(0xff8));
+        assert_eq!(
+            layout.check(B64::from(0xfff), B64::from(1)),
+            reserved(0xfff)
+        );
+        assert_eq!(layout.check(B64::from(0x2ffc), word_size), reserved(0x3000));
+        assert_eq!(layout.check(B64::from(0x3ff8), word_size), reserved(0x3ff8));
+        // Right outside of the ranges
+        assert_eq!(layout.check(B64::from(0x1000), word_size), Ok(code));
+        assert!(matches!(
+            layout.check(B64::from(0x4000), word_size),
+            Err(Error::AccessOutsideRegion { .. })
+        ));
+        assert!(matches!(
+            layout.check(B64::from(0x2ff8), word_size),
+            Err(Error::AccessOutsideRegion { .. })
+        ));
+
+        assert_eq!(
+            layout.reserve(B64::from(0x1ff8)..=B64::from(0x2fff)),
+            Err(Error::ReservedRangeOverlap {
+                region: RegionKind::Code
+            })
+        );
+        assert_eq!(
+            layout.reserve(B64::from(0x3fff)..=B64::from(0x4fff)),
+            Err(Error::InvalidReservedRange)
+        );
+        let empty = B64::from(0x5000)..=B64::from(0x4fff);
+        assert_eq!(layout.reserve(empty), Err(Error::InvalidReservedRange));
+        let full = (0..MAX_RESERVED_RANGES as u64 - 2).try_fold(layout, |layout, i| {
+            let base = B64::from(0x10000 * (i + 1));
+            layout.reserve(base..=base)
+        });
+        assert_eq!(
+            full.unwrap().reserve(B64::MAX..=B64::MAX),
+            Err(Error::InvalidReservedRange)
+        );
+    }
 }
@@ -0,0 +1,380 @@
+//! TOML config files of the machine, available with the `serde` feature.
+//! Every size and address accepts an integer or a string in the notations of
+//! [Base::parse_size], e.g. `"64KiB"` or `"0x1000"`. A missing key keeps the value of
+//! [ConfigBuilder::new] and an unknown key is rejected, so a typo never goes unnoticed.
+//! The `reserved` ranges are added to the layout with [MemoryLayout::reserve], they need a
+//! layout.
+//!
+//! ```toml
+//! stack_depth = "1KiB"
+//! guard_cells = 2
+//! alignment = "strict"
+//! read_policy = "error-on-uninit"
+//! endianness = "little"
+//!
+//! [limits]
+//! max_trace_len = 4096
+//!
+//! [[layout]]
+//! kind = "code"
+//! base = "0x100000"
+//! length = "4KiB"
+//! permission = "read"
+//!
+//! [[reserved]]
+//! first = 0
+//! last = "0xfff"
+//! ```
+extern crate alloc;
+use crate::{
+    base::{Base, B128, B16, B256, B32, B512, B64},
+    config::{
+        AlignmentPolicy, Config, ConfigArgs, ConfigBuilder, CostModel, Endianness, MemoryLayout,
+        MemoryRegion, Permission, ReadPolicy, RegionKind, ResourceLimits,
+    },
+    error::Error,
+};
+use alloc::{string::String, vec::Vec};
+use serde::{de::Error as _, Deserialize, Deserializer};
+
+// Size or address written as an integer or in a notation of [Base::parse_size]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Size {
+    Number(i64),
+    Text(String),
+}
+
+impl Size {
+    fn parse<T: Base<S>, const S: usize>(&self) -> Result<T, Error> {
+        match self {
+            Size::Number(number) => T::try_from(*number),
+            Size::Text(text) => T::parse_size(text),
+        }
+    }
+}
+
+// Region of the layout as written in the file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegionFile {
+    kind: RegionKind,
+    base: Size,
+    length: Size,
+    #[serde(default)]
+    permission: Permission,
+    #[serde(default)]
+    write_once: bool,
+    word_size: Option<Size>,
+}
+
+impl RegionFile {
+    fn into_region<T: Base<S>, const S: usize>(self) -> Result<MemoryRegion<T>, Error> {
+        let region = MemoryRegion::new(self.kind, self.base.parse()?, self.length.parse()?)
+            .with_permission(self.permission)
+            .with_write_once(self.write_once);
+        Ok(match self.word_size {
+            Some(word_size) => region.with_word_size(word_size.parse()?),
+            None => region,
+        })
+    }
+}
+
+fn into_layout<T: Base<S>, const S: usize>(
+    regions: Vec<RegionFile>,
+) -> Result<MemoryLayout<T>, Error> {
+    let regions = regions
+        .into_iter()
+        .map(RegionFile::into_region)
+        .collect::<Result<Vec<_>, _>>()?;
+    MemoryLayout::new(&regions)
+}
+
+// Reserved range of the layout as written in the file, both addresses are inclusive
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReservedFile {
+    first: Size,
+    last: Size,
+}
+
+fn reserve<T: Base<S>, const S: usize>(
+    layout: MemoryLayout<T>,
+    reserved: Vec<ReservedFile>,
+) -> Result<MemoryLayout<T>, Error> {
+    reserved.into_iter().try_fold(layout, |layout, range| {
+        layout.reserve(range.first.parse()?..=range.last.parse()?)
+    })
+}
+
+// Config arguments as written in the file, [None] keeps the default of the builder
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArgsFile {
+    head_layout: Option<bool>,
+    stack_depth: Option<Size>,
+    no_register: Option<Size>,
+    buffer_size: Option<Size>,
+    register_base: Option<Size>,
+    stack_base: Option<Size>,
+    guard_cells: Option<Size>,
+    alignment: Option<AlignmentPolicy>,
+    read_policy: Option<ReadPolicy>,
+    endianness: Option<Endianness>,
+    limits: Option<ResourceLimits>,
+    cost_model: Option<CostModel>,
+    gas_budget: Option<u64>,
+    layout: Option<Vec<RegionFile>>,
+    reserved: Option<Vec<ReservedFile>>,
+}
+
+impl ArgsFile {
+    fn into_args<T: Base<S>, const S: usize>(self) -> Result<ConfigArgs<T>, Error> {
+        let defaults = ConfigBuilder::<T, S>::new().into_args();
+        let size = |size: Option<Size>, default: T| match size {
+            Some(size) => size.parse(),
+            None => Ok(default),
+        };
+        let base = |base: Option<Size>| base.map(|base| base.parse()).transpose();
+        let layout = match (self.layout, self.reserved) {
+            (Some(regions), reserved) => Some(reserve(
+                into_layout(regions)?,
+                reserved.unwrap_or_default(),
+            )?),
+            (None, None) => None,
+            // Without a layout every address is accessible, a reserved range would be ignored
+            (None, Some(_)) => return Err(Error::InvalidConfigFile),
+        };
+        Ok(ConfigArgs {
+            head_layout: self.head_layout.unwrap_or(defaults.head_layout),
+            stack_depth: size(self.stack_depth, defaults.stack_depth)?,
+            no_register: size(self.no_register, defaults.no_register)?,
+            buffer_size: size(self.buffer_size, defaults.buffer_size)?,
+            register_base: base(self.register_base)?,
+            stack_base: base(self.stack_base)?,
+            guard_cells: size(self.guard_cells, defaults.guard_cells)?,
+            alignment: self.alignment.unwrap_or(defaults.alignment),
+            read_policy: self.read_policy.unwrap_or(defaults.read_policy),
+            endianness: self.endianness.unwrap_or(defaults.endianness),
+            limits: self.limits.unwrap_or(defaults.limits),
+            cost_model: self.cost_model.unwrap_or(defaults.cost_model),
+            gas_budget: self.gas_budget,
+            layout,
+        })
+    }
+}
+
+// The sizes are parsed for a concrete base, a generic impl would leave its size unconstrained
+macro_rules! deserialize_config {
+    ($($base:ty),*) => {
+        $(
+            impl<'de> Deserialize<'de> for MemoryRegion<$base> {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    RegionFile::deserialize(deserializer)?
+                        .into_region()
+                        .map_err(D::Error::custom)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for MemoryLayout<$base> {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    into_layout(Vec::<RegionFile>::deserialize(deserializer)?)
+                        .map_err(D::Error::custom)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for ConfigArgs<$base> {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    ArgsFile::deserialize(deserializer)?
+                        .into_args()
+                        .map_err(D::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+deserialize_config!(B16, B32, B64, B128, B256, B512);
+
+impl<T, const S: usize> Config<T, S>
+where
+    T: Base<S>,
+{
+    /// Load the config of a machine whose cells are `S` bytes wide from a TOML file.
+    /// Return [Error::InvalidConfigFile] if the file is not valid TOML or has an unknown key,
+    /// the error of a size, a layout or the first violated rule of [ConfigArgs::validate]
+    pub fn from_toml_str(input: &str) -> Result<Self, Error> {
+        let file: ArgsFile = toml::from_str(input).map_err(|_| Error::InvalidConfigFile)?;
+        Self::try_new(T::WORD_SIZE, file.into_args()?).map_err(|errors| errors[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        base::{Base, B256, B32},
+        config::{
+            AlignmentPolicy, Config, ConfigArgs, Endianness, MemoryLayout, MemoryRegion,
+            Permission, ReadPolicy, RegionKind, DEFAULT_NO_REGISTER,
+        },
+        error::Error,
+    };
+
+    const EXAMPLE: &str = r#"
+        head_layout = true
+        stack_depth = "1KiB"
+        buffer_size = "0x20"
+        guard_cells = 2
+        alignment = "strict"
+        read_policy = "error-on-uninit"
+        endianness = "little"
+        gas_budget = 100000
+
+        [limits]
+        max_trace_len = 4096
+
+        [cost_model]
+        read = 3
+        write = 5
+
+        [[layout]]
+        kind = "code"
+        base = "0x100000"
+        length = "4KiB"
+        permission = "read"
+        word_size = 8
+
+        [[layout]]
+        kind = "heap"
+        base = "0x200000"
+        length = "64KiB"
+        write_once = true
+
+        [[reserved]]
+        first = 0
+        last = "0xfff"
+    "#;
+
+    #[test]
+    fn test_load_example_file() {
+        let config = Config::<B256, 32>::from_toml_str(EXAMPLE).unwrap();
+        assert_eq!(config.stack_depth, B256::from(1024));
+        assert_eq!(config.buffer_size, B256::from(32));
+        assert_eq!(config.alignment, AlignmentPolicy::Strict);
+        assert_eq!(config.read_policy, ReadPolicy::ErrorOnUninit);
+        assert_eq!(config.endianness, Endianness::Little);
+        assert_eq!(config.limits.max_trace_len, Some(4096));
+        assert_eq!(config.limits.max_distinct_cells, None);
+        assert_eq!(
+            (
+                config.cost_model.read,
+                config.cost_model.write,
+                config.cost_model.pop
+            ),
+            (3, 5, 0)
+        );
+        assert_eq!(config.gas_budget, Some(100000));
+        let guard = config.stack_guard.unwrap();
+        assert_eq!(guard.low(), config.stack.high());
+        assert_eq!(guard.high() - guard.low(), B256::from(63));
+        // The missing keys keep their default
+        assert_eq!(
+            config.register.high() - config.register.low(),
+            B256::from(DEFAULT_NO_REGISTER * 32)
+        );
+        let layout = MemoryLayout::new(&[
+            MemoryRegion::new(RegionKind::Code, B256::from(0x100000), B256::from(0x1000))
+                .with_permission(Permission::Read)
+                .with_word_size(B256::from(8)),
+            MemoryRegion::new(RegionKind::Heap, B256::from(0x200000), B256::from(0x10000))
+                .with_write_once(true),
+        ])
+        .unwrap()
+        .reserve(B256::zero()..=B256::from(0xfff))
+        .unwrap();
+        assert_eq!(config.layout, Some(layout));
+
+        // The arguments deserialize on their own as well
+        let args: ConfigArgs<B32> = toml::from_str(EXAMPLE).unwrap();
+        assert_eq!(args.stack_depth, B32::from(1024));
+        assert_eq!(args.guard_cells, B32::from(2));
+        assert_eq!(args.register_base, None);
+        let empty: ConfigArgs<B32> = toml::from_str("").unwrap();
+        assert!(empty.head_layout);
+        assert_eq!(empty.endianness, Endianness::Big);
+        assert_eq!(empty.layout, None);
+    }
+
+    #[test]
+    fn test_reject_unknown_key() {
+        assert_eq!(
+            Config::<B256, 32>::from_toml_str("stack_dept = 16").unwrap_err(),
+            Error::InvalidConfigFile
+        );
+        assert_eq!(
+            Config::<B256, 32>::from_toml_str("[limits]\nmax_trace_length = 16").unwrap_err(),
+            Error::InvalidConfigFile
+        );
+        assert_eq!(
+            Config::<B256, 32>::from_toml_str("alignment = \"loose\"").unwrap_err(),
+            Error::InvalidConfigFile
+        );
+        assert!(toml::from_str::<ConfigArgs<B256>>("stack_dept = 16").is_err());
+    }
+
+    #[test]
+    fn test_reject_invalid_values() {
+        let overlapping = r#"
+            [[layout]]
+            kind = "code"
+            base = "0x100000"
+            length = "4KiB"
+
+            [[layout]]
+            kind = "heap"
+            base = "0x100800"
+            length = "4KiB"
+        "#;
+        assert_eq!(
+            Config::<B256, 32>::from_toml_str(overlapping).unwrap_err(),
+            Error::OverlappingRegions {
+                first: RegionKind::Code,
+                second: RegionKind::Heap,
+            }
+        );
+        assert!(toml::from_str::<ConfigArgs<B256>>(overlapping).is_err());
+        assert_eq!(
+            Config::<B256, 32>::from_toml_str("stack_depth = \"1 KB\"").unwrap_err(),
+            Error::InvalidSizeFormat
+        );
+        assert_eq!(
+            Config::<B32, 4>::from_toml_str("stack_base = \"2^32\"").unwrap_err(),
+            Error::ValueOutOfRange
+        );
+        assert_eq!(
+            Config::<B256, 32>::from_toml_str("stack_depth = 0").unwrap_err(),
+            Error::EmptyStack
+        );
+        let reserved_code = r#"
+            [[layout]]
+            kind = "code"
+            base = 0
+            length = "4KiB"
+
+            [[reserved]]
+            first = "0xff0"
+            last = "0x1fff"
+        "#;
+        assert_eq!(
+            Config::<B256, 32>::from_toml_str(reserved_code).unwrap_err(),
+            Error::ReservedRangeOverlap {
+                region: RegionKind::Code
+            }
+        );
+        // A reserved range needs a layout
+        assert_eq!(
+            Config::<B256, 32>::from_toml_str("[[reserved]]\nfirst = 0\nlast = 16").unwrap_err(),
+            Error::InvalidConfigFile
+        );
+    }
+}
@@ -2,14 +2,22 @@
 extern crate alloc;
 use crate::{
     base::B256,
+    config::ReadPolicy,
     constraints::{
         common::CircuitExtension,
-        gadgets::{ConvertedTraceRecord, LookUpTables, Table, TraceRecordWitnessTable},
+        gadgets::{
+            ConvertedTraceRecord, LookUpTables, Table, TraceRecordWitnessTable, INSTRUCTION_CODES,
+        },
+        layout::CircuitLayoutParams,
         original_memory_circuit::{OriginalMemoryCircuit, OriginalMemoryConfig},
         permutation_circuit::{PermutationCircuit, ShuffleChip, ShuffleConfig},
         sorted_memory_circuit::{SortedMemoryCircuit, SortedMemoryConfig},
     },
-    machine::TraceRecord,
+    machine::{
+        is_sorted_by_address_time, is_sorted_by_time,
+        validate::{canonicalize_trace, validate_trace_with_policy},
+        AbstractTraceRecord, MemoryInstruction, TraceRecord,
+    },
 };
 use alloc::{vec, vec::Vec};
 use core::marker::PhantomData;
@@ -68,10 +76,67 @@ pub(crate) struct MemoryConsistencyCircuit<F: Field + PrimeField + From<B256>> {
     pub(crate) input: Vec<TraceRecord<B256, B256, 32, 32>>,
     /// shuffle_trace: Array after permutations (sorted by address and time_log)
     pub(crate) shuffle: Vec<TraceRecord<B256, B256, 32, 32>>,
+    /// read_policy: The value of the cells read before any write
+    pub(crate) read_policy: ReadPolicy,
+    /// layout: The layout bounding the stack accesses, [None] does not check them
+    pub(crate) layout: Option<CircuitLayoutParams>,
     /// A marker since these fields do not use trait F
     pub(crate) marker: PhantomData<F>,
 }
 
+impl<F: Field + PrimeField + From<B256>> MemoryConsistencyCircuit<F> {
+    /// Create the circuit, the input must be sorted by time log
+    /// and the shuffle by address then time log.
+    /// Debug builds also reject an input that is not well formed, see [validate_trace](crate::machine::validate::validate_trace)
+    pub(crate) fn new(
+        input: Vec<TraceRecord<B256, B256, 32, 32>>,
+        shuffle: Vec<TraceRecord<B256, B256, 32, 32>>,
+    ) -> Result<Self, crate::error::Error> {
+        Self::new_with_read_policy(input, shuffle, ReadPolicy::ErrorOnUninit, None)
+    }
+
+    /// Create the circuit of a trace made under the read policy, see
+    /// [validate_trace_with_policy]. With a layout, every push and pop must access a cell of
+    /// its stack section
+    pub(crate) fn new_with_read_policy(
+        input: Vec<TraceRecord<B256, B256, 32, 32>>,
+        shuffle: Vec<TraceRecord<B256, B256, 32, 32>>,
+        read_policy: ReadPolicy,
+        layout: Option<CircuitLayoutParams>,
+    ) -> Result<Self, crate::error::Error> {
+        if !is_sorted_by_time(&input) {
+            return Err(crate::error::Error::TraceNotSortedByTime);
+        }
+        if !is_sorted_by_address_time(&shuffle) {
+            return Err(crate::error::Error::TraceNotSortedByAddressTime);
+        }
+        #[cfg(debug_assertions)]
+        validate_trace_with_policy(&input, read_policy)
+            .map_err(|_| crate::error::Error::MalformedTrace)?;
+        Ok(Self {
+            input,
+            shuffle,
+            read_policy,
+            layout,
+            marker: PhantomData,
+        })
+    }
+
+    /// Create the circuit of a trace in any order, e.g. stitched from several sources.
+    /// The trace is brought to time order by [canonicalize_trace] and the shuffle is sorted
+    /// from it, see [MemoryConsistencyCircuit::new_with_read_policy]
+    pub fn new_canonical(
+        input: Vec<TraceRecord<B256, B256, 32, 32>>,
+        read_policy: ReadPolicy,
+        layout: Option<CircuitLayoutParams>,
+    ) -> Result<Self, crate::error::Error> {
+        let input = canonicalize_trace(input)?;
+        let mut shuffle = input.clone();
+        shuffle.sort_by_key(|record| (record.address(), record.time_log()));
+        Self::new_with_read_policy(input, shuffle, read_policy, layout)
+    }
+}
+
 /// Implement the circuit extension for memory consistency circuit
 impl<F: Field + PrimeField + From<B256>> CircuitExtension<F> for MemoryConsistencyCircuit<F> {
     fn synthesize_with_layouter(
@@ -80,26 +145,40 @@ impl<F: Field + PrimeField + From<B256>> CircuitExtension<F> for MemoryConsisten
         layouter: &mut impl Layouter<F>,
     ) -> Result<(), Error> {
         let permutation_circuit = PermutationCircuit::<F>::new::<B256, B256, 32, 32>(
-            self.input.clone(),
-            self.shuffle.clone(),
+            self.input.iter().copied(),
+            self.shuffle.iter().copied(),
         );
         permutation_circuit.synthesize_with_layouter(config.permutation_config, layouter)?;
-        let mut sorted_trace_record = vec![];
-        for trace in self.shuffle.clone() {
-            sorted_trace_record.push(ConvertedTraceRecord::<F>::from(trace));
-        }
-        let mut original_trace_record = vec![];
-        for trace in self.input.clone() {
-            original_trace_record.push(ConvertedTraceRecord::<F>::from(trace));
-        }
+        let sorted_trace_record: Vec<ConvertedTraceRecord<F>> = self
+            .shuffle
+            .iter()
+            .map(ConvertedTraceRecord::<F>::from_trace_record)
+            .collect::<Result<_, _>>()
+            .map_err(|_| Error::Synthesis)?;
+        let original_trace_record: Vec<ConvertedTraceRecord<F>> = self
+            .input
+            .iter()
+            .map(ConvertedTraceRecord::<F>::from_trace_record)
+            .collect::<Result<_, _>>()
+            .map_err(|_| Error::Synthesis)?;
+        let stack_addresses = self
+            .input
+            .iter()
+            .map(|record| {
+                matches!(
+                    record.instruction(),
+                    MemoryInstruction::Push | MemoryInstruction::Pop
+                )
+                .then_some(record.address())
+            })
+            .collect();
         let original_memory_circuit = OriginalMemoryCircuit {
             original_trace_record,
+            stack_addresses,
+            layout: self.layout,
             _marker: PhantomData,
         };
-        let sorted_memory_circuit = SortedMemoryCircuit {
-            sorted_trace_record,
-            _marker: PhantomData,
-        };
+        let sorted_memory_circuit = SortedMemoryCircuit::new(sorted_trace_record, self.read_policy);
         sorted_memory_circuit.synthesize_with_layouter(config.sorted_memory_config, layouter)?;
         original_memory_circuit
             .synthesize_with_layouter(config.original_memory_config, layouter)?;
@@ -127,7 +206,7 @@ impl<F: Field + PrimeField + From<B256>> Circuit<F> for MemoryConsistencyCircuit
         let lookup_tables = LookUpTables {
             size256_table: Table::<256>::construct(meta),
             size40_table: Table::<40>::construct(meta),
-            size2_table: Table::<2>::construct(meta),
+            instruction_table: Table::<INSTRUCTION_CODES>::construct(meta),
         };
         // the random challenges
         let alpha = Expression::Constant(F::random(rng));
@@ -0,0 +1,175 @@
+//! Differential testing of the machine against the circuits.
+//! A program accepted by the machine must give a trace accepted by the original and the sorted
+//! memory circuits, a program rejected by the machine must give a would-be trace rejected by
+//! at least one of them
+extern crate alloc;
+use crate::{
+    base::{Base, B256},
+    config::{ConfigBuilder, ReadPolicy},
+    constraints::{
+        gadgets::ConvertedTraceRecord,
+        helper::{minimal_k, sort_trace},
+        original_memory_circuit::OriginalMemoryCircuit,
+        sorted_memory_circuit::SortedMemoryCircuit,
+    },
+    error::Error,
+    machine::{
+        tests::StateMachine, AbstractContext, AbstractMachine, AbstractMemoryMachine,
+        AbstractStackMachine, MemoryInstruction, TraceRecord,
+    },
+};
+use alloc::vec;
+use core::marker::PhantomData;
+use halo2_proofs::{dev::MockProver, plonk::Circuit};
+use halo2curves::pasta::Fp;
+
+/// Operation of a program run by [assert_machine_circuit_agree]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MemOp {
+    /// Read the cell of the given index
+    Read(u64),
+    /// Write the value to the cell of the given index
+    Write(u64, u64),
+    /// Push the value to the stack
+    Push(u64),
+    /// Pop the top of the stack
+    Pop,
+}
+
+// Check whether the circuit is satisfied, a witness that can not be assigned is a rejection
+fn accepts<C: Circuit<Fp>>(circuit: &C, k: u32) -> bool {
+    MockProver::run(k, circuit, vec![])
+        .map(|prover| prover.verify().is_ok())
+        .unwrap_or(false)
+}
+
+// Get whether the original and the sorted memory circuits accept the trace
+fn verdicts(trace: &[TraceRecord<B256, B256, 32, 32>]) -> (bool, bool) {
+    let k = minimal_k(trace.len());
+    let original = OriginalMemoryCircuit::<Fp> {
+        original_trace_record: trace
+            .iter()
+            .map(ConvertedTraceRecord::from_trace_record)
+            .collect::<Result<_, _>>()
+            .unwrap(),
+        stack_addresses: vec![],
+        layout: None,
+        _marker: PhantomData,
+    };
+    let sorted = SortedMemoryCircuit::<Fp>::new(
+        sort_trace(trace.iter().copied())
+            .iter()
+            .map(ConvertedTraceRecord::from_trace_record)
+            .collect::<Result<_, _>>()
+            .unwrap(),
+        ReadPolicy::ErrorOnUninit,
+    );
+    (accepts(&original, k), accepts(&sorted, k))
+}
+
+/// Run the operations on a [StateMachine] that rejects uninitialized reads and check the
+/// verdict of the circuits on its trace. When the machine rejects an operation, the trace is
+/// completed with the record the operation would have made and the circuits must reject it.
+/// Only uninitialized reads have such a record, the other rejections panic
+pub(crate) fn assert_machine_circuit_agree(ops: &[MemOp]) {
+    let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(
+        ConfigBuilder::new()
+            .read_policy(ReadPolicy::ErrorOnUninit)
+            .build()
+            .unwrap(),
+    );
+    let base = sm.base_address();
+    let cell = |index: u64| base + B256::from(index) * B256::WORD_SIZE;
+    for op in ops {
+        let result = match *op {
+            MemOp::Read(index) => sm.read(cell(index)).map(|_| ()),
+            MemOp::Write(index, value) => sm.write(cell(index), B256::from(value)).map(|_| ()),
+            MemOp::Push(value) => sm.push(B256::from(value)).map(|_| ()),
+            MemOp::Pop => sm.pop().map(|_| ()),
+        };
+        let address = match (*op, result) {
+            (_, Ok(())) => continue,
+            (MemOp::Read(index), Err(Error::UninitializedRead { .. })) => cell(index),
+            (op, Err(error)) => panic!("{:?} is rejected by the machine with {}", op, error),
+        };
+        // The read the machine refused, the cell holds zero before its first write
+        let mut trace = sm.trace();
+        trace.push(TraceRecord::new(
+            sm.ro_context().time_log(),
+            sm.ro_context().stack_depth(),
+            MemoryInstruction::Read,
+            address,
+            B256::zero(),
+        ));
+        assert_ne!(
+            verdicts(&trace),
+            (true, true),
+            "The circuits accept {:?} which is rejected by the machine",
+            ops
+        );
+        return;
+    }
+    assert_eq!(
+        verdicts(&sm.trace()),
+        (true, true),
+        "The circuits reject {:?} which is accepted by the machine",
+        ops
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assert_machine_circuit_agree, MemOp};
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    // Number of cells the programs access, few enough for the cells to be accessed again
+    const CELLS: u64 = 4;
+
+    // Generate a program over a few cells, the stack is never popped when it is empty
+    fn random_ops(rng: &mut StdRng, len: usize) -> Vec<MemOp> {
+        let mut depth = 0;
+        let mut ops = Vec::with_capacity(len);
+        for _ in 0..len {
+            let op = match rng.gen_range(0..4) {
+                0 => MemOp::Read(rng.gen_range(0..CELLS)),
+                1 => MemOp::Write(rng.gen_range(0..CELLS), rng.gen()),
+                2 if depth > 0 => {
+                    depth -= 1;
+                    MemOp::Pop
+                }
+                _ => {
+                    depth += 1;
+                    MemOp::Push(rng.gen())
+                }
+            };
+            ops.push(op);
+        }
+        ops
+    }
+
+    #[test]
+    fn test_known_programs() {
+        assert_machine_circuit_agree(&[
+            MemOp::Write(0, 1),
+            MemOp::Read(0),
+            MemOp::Push(2),
+            MemOp::Write(1, 3),
+            MemOp::Pop,
+            MemOp::Read(1),
+        ]);
+        // The read of a cell that was never written is rejected by both sides
+        assert_machine_circuit_agree(&[MemOp::Write(0, 1), MemOp::Read(1)]);
+        assert_machine_circuit_agree(&[MemOp::Read(0)]);
+    }
+
+    #[test]
+    fn test_random_programs() {
+        for seed in 0..200 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let len = rng.gen_range(1..=12);
+            assert_machine_circuit_agree(&random_ops(&mut rng, len));
+        }
+    }
+}
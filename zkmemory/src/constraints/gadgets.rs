@@ -4,10 +4,13 @@
 extern crate alloc;
 use crate::{
     base::{Base, B256},
-    machine::{MemoryInstruction, TraceRecord},
+    constraints::layout::CircuitLayoutParams,
+    machine::{AbstractTraceRecord, MemoryInstruction, TraceRecord},
 };
 use alloc::vec::Vec;
 use alloc::{format, vec};
+#[cfg(feature = "borsh")]
+use borsh::{io, BorshDeserialize, BorshSerialize};
 use core::marker::PhantomData;
 use ff::{Field, PrimeField};
 use halo2_proofs::{
@@ -344,12 +347,217 @@ fn rlc_limb_differences<F: Field + PrimeField>(
     result
 }
 
+#[derive(Clone, Copy, Debug)]
+/// Config for checking that the address of a stack access is a cell of the stack section.
+/// The stack base and the offset of the last stack cell come from [CircuitLayoutParams] and
+/// are fixed columns, like the flag of the rows of stack accesses
+pub(crate) struct StackBoundConfig<F: Field + PrimeField> {
+    pub(crate) stack_access: Column<Fixed>,
+    pub(crate) stack_base: Column<Fixed>,
+    pub(crate) last_offset: Column<Fixed>,
+    pub(crate) offset: [Column<Advice>; 8],
+    pub(crate) room: [Column<Advice>; 8],
+    _marker: PhantomData<F>,
+}
+// the constraints for this circuit are, on the rows of stack accesses:
+// 1) address=stack_base+offset
+// 2) last_offset=offset+room
+// 3) every limb of offset and room is in [0,255], so both are in [0, 2^64)
+impl<F: Field + PrimeField> StackBoundConfig<F> {
+    /// Add the constraints for checking the stack bound
+    pub fn configure(
+        meta: &mut ConstraintSystem<F>,
+        trace_record: TraceRecordWitnessTable<F>,
+        lookup_tables: LookUpTables,
+    ) -> Self {
+        let stack_access = meta.fixed_column();
+        let stack_base = meta.fixed_column();
+        let last_offset = meta.fixed_column();
+        let offset = [0; 8].map(|_| meta.advice_column());
+        let room = [0; 8].map(|_| meta.advice_column());
+
+        meta.create_gate("stack access is a cell of the stack section", |meta| {
+            let stack_access = meta.query_fixed(stack_access, Rotation::cur());
+            let stack_base = meta.query_fixed(stack_base, Rotation::cur());
+            let last_offset = meta.query_fixed(last_offset, Rotation::cur());
+            let address =
+                limbs_expression(&Queries::new(meta, trace_record, Rotation::cur()).address);
+            let offset =
+                limbs_expression(&offset.map(|limb| meta.query_advice(limb, Rotation::cur())));
+            let room = limbs_expression(&room.map(|limb| meta.query_advice(limb, Rotation::cur())));
+            vec![
+                stack_access.clone() * (address - stack_base - offset.clone()),
+                stack_access * (last_offset - offset - room),
+            ]
+        });
+
+        for limb in offset.iter().chain(room.iter()) {
+            lookup_tables.size256_table.range_check(
+                meta,
+                "stack offset limb fits in 0..256",
+                |meta| meta.query_advice(*limb, Rotation::cur()),
+            );
+        }
+
+        StackBoundConfig {
+            stack_access,
+            stack_base,
+            last_offset,
+            offset,
+            room,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Assign the row of a record, `address` is the address of a stack access and [None] for
+    /// the other records. Without a layout no record is checked
+    pub fn assign(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        layout: Option<CircuitLayoutParams>,
+        address: Option<B256>,
+    ) -> Result<(), Error> {
+        let (stack_access, stack_base, last_offset, stack_offset, room) = match layout {
+            Some(layout) => {
+                let last_offset = layout.last_stack_offset();
+                // An address outside of the stack gets a witness that fails the constraints
+                let stack_offset = address
+                    .and_then(|address| address.checked_sub(layout.stack_base))
+                    .unwrap_or(B256::zero());
+                let room = last_offset
+                    .checked_sub(stack_offset)
+                    .unwrap_or(B256::zero());
+                (
+                    F::from(u64::from(address.is_some())),
+                    layout.stack_base,
+                    last_offset,
+                    stack_offset,
+                    room,
+                )
+            }
+            None => (
+                F::ZERO,
+                B256::zero(),
+                B256::zero(),
+                B256::zero(),
+                B256::zero(),
+            ),
+        };
+        let fixed = [
+            (self.stack_access, stack_access),
+            (
+                self.stack_base,
+                field_from_be_bytes(&stack_base.fixed_be_bytes()),
+            ),
+            (
+                self.last_offset,
+                field_from_be_bytes(&last_offset.fixed_be_bytes()),
+            ),
+        ];
+        for (column, value) in fixed {
+            region.assign_fixed(
+                || format!("stack bound{}", offset),
+                column,
+                offset,
+                || Value::known(value),
+            )?;
+        }
+        for (columns, word) in [(self.offset, stack_offset), (self.room, room)] {
+            // Only the 8 least significant bytes fit in the limbs
+            let limbs: [F; 32] = byte_limbs(word.fixed_be_bytes());
+            for (column, limb) in columns.iter().zip(&limbs[24..]) {
+                region.assign_advice(
+                    || format!("stack offset{}", offset),
+                    *column,
+                    offset,
+                    || Value::known(*limb),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Combine big endian byte limbs into a single expression
+fn limbs_expression<F: Field + PrimeField>(limbs: &[Expression<F>]) -> Expression<F> {
+    limbs
+        .iter()
+        .fold(Expression::Constant(F::ZERO), |result, limb| {
+            result * Expression::Constant(F::from(256_u64)) + limb.clone()
+        })
+}
+
+// Combine big endian bytes into a field element, reduced modulo the field order
+pub(crate) fn field_from_be_bytes<F: Field + PrimeField>(bytes: &[u8]) -> F {
+    bytes.iter().fold(F::ZERO, |result, byte| {
+        result * F::from(256_u64) + F::from(u64::from(*byte))
+    })
+}
+
 /// The lookup tables. We have 3 tables of size 256, 40 and 2
 #[derive(Clone, Copy, Debug)]
 pub(crate) struct LookUpTables {
     pub(crate) size256_table: Table<256>,
     pub(crate) size40_table: Table<40>,
-    pub(crate) size2_table: Table<2>,
+    pub(crate) instruction_table: Table<INSTRUCTION_CODES>,
+}
+
+/// Number of instruction codes accepted by the circuits, the valid codes are
+/// `0..INSTRUCTION_CODES`
+pub const INSTRUCTION_CODES: usize = 2;
+
+/// Encoding of the instruction of a trace record as a field element in the circuits.
+/// The codes are the ones of [MemoryInstruction::code], shared with the trace formats and the
+/// KZG commitment. Until the circuits constrain the stack a push is encoded as a write and a
+/// pop as a read, the codes of Push and Pop are not accepted by the circuits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionCode {
+    /// Read from memory
+    Read,
+    /// Write to memory
+    Write,
+}
+
+impl InstructionCode {
+    /// Get the numeric code of the instruction, see [MemoryInstruction::code]
+    pub const fn code(self) -> u64 {
+        match self {
+            InstructionCode::Read => MemoryInstruction::Read.code(),
+            InstructionCode::Write => MemoryInstruction::Write.code(),
+        }
+    }
+
+    /// Encode the instruction as a field element
+    pub fn to_field<F: Field + PrimeField>(self) -> F {
+        F::from(self.code())
+    }
+
+    /// Decode the instruction from a field element, return [None] for an unknown code
+    pub fn try_from_field<F: Field + PrimeField>(element: F) -> Option<Self> {
+        [InstructionCode::Read, InstructionCode::Write]
+            .into_iter()
+            .find(|code| code.to_field::<F>() == element)
+    }
+}
+
+impl From<MemoryInstruction> for InstructionCode {
+    fn from(instruction: MemoryInstruction) -> Self {
+        if instruction.is_write() {
+            InstructionCode::Write
+        } else {
+            InstructionCode::Read
+        }
+    }
+}
+
+impl From<InstructionCode> for MemoryInstruction {
+    fn from(code: InstructionCode) -> Self {
+        match code {
+            InstructionCode::Read => MemoryInstruction::Read,
+            InstructionCode::Write => MemoryInstruction::Write,
+        }
+    }
 }
 
 /// Query the element of a trace record at a specific position
@@ -391,58 +599,398 @@ impl<F: Field + PrimeField> Queries<F> {
     }
 }
 
-/// Trace record struct for Lexicographic ordering circuit
-/// We need every element to be of an array of type F, where each
+/// Trace record in the witness form of the circuits. Every byte of the address, the time log
+/// and the value is a limb, the most significant byte first, the instruction is encoded by
+/// [InstructionCode]. The address has `A` limbs and the value `W` limbs, the circuits take 32
+/// of each. Words narrower than their limbs are padded with leading zero limbs
 #[derive(Debug, Clone)]
-pub(crate) struct ConvertedTraceRecord<F: Field + PrimeField> {
-    pub(crate) address: [F; 32], // 256 bits
-    pub(crate) time_log: [F; 8], // 256 bits
+pub struct ConvertedTraceRecord<F: Field + PrimeField, const A: usize = 32, const W: usize = 32> {
+    pub(crate) address: [F; A],  // A bytes
+    pub(crate) time_log: [F; 8], // 64 bits
     pub(crate) instruction: F,   // 0 or 1
-    pub(crate) value: [F; 32],   // 256 bit
+    pub(crate) value: [F; W],    // W bytes
 }
 
-impl<F: Field + PrimeField> ConvertedTraceRecord<F> {
+impl<F: Field + PrimeField, const A: usize, const W: usize> ConvertedTraceRecord<F, A, W> {
     /// Get the trace record fields in tuple
-    pub fn get_tuple(&self) -> ([F; 32], [F; 8], F, [F; 32]) {
+    pub fn get_tuple(&self) -> ([F; A], [F; 8], F, [F; W]) {
         (self.address, self.time_log, self.instruction, self.value)
     }
+
+    /// Convert a trace record into its witness form, the stack depth is not part of the
+    /// witness. The value of a cell narrower than the word keeps its cell size, the limbs
+    /// above it are zero. The limbs are big endian whatever the byte order of the machine.
+    /// Return [Error::WitnessWordTooWide](crate::error::Error::WitnessWordTooWide) if the
+    /// address has more than `A` bytes or the value more than `W` bytes, e.g. the words of a
+    /// [B512](crate::base::B512) machine do not fit in the limbs of the circuits
+    pub fn from_trace_record<K, V, const S: usize, const T: usize>(
+        record: &TraceRecord<K, V, S, T>,
+    ) -> Result<Self, crate::error::Error>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        let address: [u8; S] = record.address().into();
+        let mut value: [u8; T] = record.value().into();
+        if record.cell_size() != 0 {
+            let padding = T.saturating_sub(record.cell_size() as usize);
+            value[..padding].fill(0);
+        }
+        Ok(Self {
+            address: padded_limbs(&address)?,
+            time_log: byte_limbs(record.time_log().to_be_bytes()),
+            instruction: InstructionCode::from(record.instruction()).to_field(),
+            value: padded_limbs(&value)?,
+        })
+    }
+
+    /// Convert the witness back into a trace record with a zero stack depth and a
+    /// [MemoryInstruction::Write] or [MemoryInstruction::Read] instruction.
+    /// Return [Error::InvalidWitnessLimb](crate::error::Error::InvalidWitnessLimb) if a limb
+    /// is not a byte or the instruction is not an [InstructionCode], and
+    /// [Error::ValueOutOfRange](crate::error::Error::ValueOutOfRange) if the address or the
+    /// value does not fit in its word
+    pub fn try_into_trace_record<K, V, const S: usize, const T: usize>(
+        &self,
+    ) -> Result<TraceRecord<K, V, S, T>, crate::error::Error>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        let bytes: Vec<F> = (0..256u64).map(F::from).collect();
+        // Limbs are indexed in the order address, time log, instruction and value
+        let to_bytes = |limbs: &[F], offset: usize| -> Result<Vec<u8>, crate::error::Error> {
+            limbs
+                .iter()
+                .enumerate()
+                .map(|(index, limb)| {
+                    bytes
+                        .iter()
+                        .position(|byte| byte == limb)
+                        .map(|byte| byte as u8)
+                        .ok_or(crate::error::Error::InvalidWitnessLimb {
+                            index: offset + index,
+                        })
+                })
+                .collect()
+        };
+        let address = word_from_bytes::<K, S>(&to_bytes(&self.address, 0)?)?;
+        let mut time_log = [0u8; 8];
+        time_log.copy_from_slice(&to_bytes(&self.time_log, A)?);
+        let instruction = InstructionCode::try_from_field(self.instruction)
+            .ok_or(crate::error::Error::InvalidWitnessLimb { index: A + 8 })?
+            .into();
+        let value = word_from_bytes::<V, T>(&to_bytes(&self.value, A + 9)?)?;
+        Ok(TraceRecord::new(
+            u64::from_be_bytes(time_log),
+            0,
+            instruction,
+            address,
+            value,
+        ))
+    }
 }
-// convert the original trace record into a converted trace record
-// for serving as the witness of the ciruits
-impl<F: Field + PrimeField> From<TraceRecord<B256, B256, 32, 32>> for ConvertedTraceRecord<F> {
-    fn from(value: TraceRecord<B256, B256, 32, 32>) -> Self {
-        Self {
-            address: value
-                .get_tuple()
-                .3
-                .fixed_be_bytes()
-                .into_iter()
-                .map(|b| F::from(u64::from(b)))
-                .collect::<Vec<F>>()
-                .try_into()
-                .expect("Cannot convert address to [F; 32]"),
-            time_log: value
-                .get_tuple()
-                .0
-                .to_be_bytes()
-                .into_iter()
-                .map(|b| F::from(u64::from(b)))
-                .collect::<Vec<F>>()
-                .try_into()
-                .expect("Cannot convert time_log to [F; 8]"),
-            instruction: match value.get_tuple().2 {
-                MemoryInstruction::Write => F::ONE,
-                MemoryInstruction::Read => F::ZERO,
-            },
-            value: value
-                .get_tuple()
-                .4
-                .fixed_be_bytes()
-                .into_iter()
-                .map(|b| F::from(u64::from(b)))
-                .collect::<Vec<F>>()
-                .try_into()
-                .expect("Cannot convert value to [F; 32]"),
+
+/// Split big endian bytes into one limb per byte, e.g. 64 limbs for a [B512](crate::base::B512)
+pub(crate) fn byte_limbs<F: Field + PrimeField, const N: usize>(bytes: [u8; N]) -> [F; N] {
+    bytes.map(|byte| F::from(u64::from(byte)))
+}
+
+// Split big endian bytes into `N` limbs padded with leading zero limbs
+fn padded_limbs<F: Field + PrimeField, const N: usize>(
+    bytes: &[u8],
+) -> Result<[F; N], crate::error::Error> {
+    let padding = N
+        .checked_sub(bytes.len())
+        .ok_or(crate::error::Error::WitnessWordTooWide {
+            bits: bytes.len() * 8,
+            limbs: N,
+        })?;
+    let mut limbs = [F::ZERO; N];
+    for (limb, byte) in limbs[padding..].iter_mut().zip(bytes) {
+        *limb = F::from(u64::from(*byte));
+    }
+    Ok(limbs)
+}
+
+// Build a word from big endian bytes, the bytes that do not fit in the word must be zero
+fn word_from_bytes<B: Base<S>, const S: usize>(bytes: &[u8]) -> Result<B, crate::error::Error> {
+    let mut word = [0u8; S];
+    if bytes.len() <= S {
+        word[S - bytes.len()..].copy_from_slice(bytes);
+    } else {
+        let (high, low) = bytes.split_at(bytes.len() - S);
+        if high.iter().any(|byte| *byte != 0) {
+            return Err(crate::error::Error::ValueOutOfRange);
+        }
+        word.copy_from_slice(low);
+    }
+    Ok(B::from(word))
+}
+
+// Every field element is encoded by its canonical representation,
+// in the order address, time log, instruction and value
+#[cfg(feature = "borsh")]
+impl<F: Field + PrimeField> BorshSerialize for ConvertedTraceRecord<F> {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.address
+            .iter()
+            .chain(self.time_log.iter())
+            .chain(core::iter::once(&self.instruction))
+            .chain(self.value.iter())
+            .try_for_each(|element| writer.write_all(element.to_repr().as_ref()))
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl<F: Field + PrimeField> BorshDeserialize for ConvertedTraceRecord<F> {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let mut read_element = || -> io::Result<F> {
+            let mut repr = F::Repr::default();
+            reader.read_exact(repr.as_mut())?;
+            Option::from(F::from_repr(repr)).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Non canonical field element")
+            })
+        };
+        let mut address = [F::ZERO; 32];
+        for element in address.iter_mut() {
+            *element = read_element()?;
+        }
+        let mut time_log = [F::ZERO; 8];
+        for element in time_log.iter_mut() {
+            *element = read_element()?;
+        }
+        let instruction = read_element()?;
+        let mut value = [F::ZERO; 32];
+        for element in value.iter_mut() {
+            *element = read_element()?;
         }
+        Ok(Self {
+            address,
+            time_log,
+            instruction,
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{byte_limbs, ConvertedTraceRecord, InstructionCode, INSTRUCTION_CODES};
+    use crate::{
+        base::{Base, B128, B16, B256, B32, B512, B64},
+        error::Error,
+        machine::{AbstractTraceRecord, MemoryInstruction, TraceRecord},
+    };
+    use halo2_proofs::halo2curves::bn256::Fr;
+
+    // Convert a record to its witness form and back
+    fn roundtrip<K, V, const S: usize, const T: usize>(address: K, value: V)
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        let record =
+            TraceRecord::<K, V, S, T>::new(0x0102, 0, MemoryInstruction::Write, address, value);
+        let converted = ConvertedTraceRecord::<Fr>::from_trace_record(&record).unwrap();
+        assert_eq!(converted.try_into_trace_record(), Ok(record));
+    }
+
+    #[test]
+    fn test_converted_trace_record_roundtrip() {
+        roundtrip::<B16, B16, 2, 2>(B16::from(0x1234), B16::MAX);
+        roundtrip::<B32, B64, 4, 8>(B32::MAX, B64::from(7));
+        roundtrip::<B64, B128, 8, 16>(B64::from(64), B128::MAX);
+        roundtrip::<B256, B256, 32, 32>(B256::MAX, B256::from(0x1234));
+
+        let record = TraceRecord::<B256, B256, 32, 32>::new(
+            0x0102,
+            5,
+            MemoryInstruction::Pop,
+            B256::from(0x1234),
+            B256::MAX,
+        );
+        let converted = ConvertedTraceRecord::<Fr>::from_trace_record(&record).unwrap();
+        let (address, time_log, instruction, value) = converted.get_tuple();
+        assert_eq!(address[30..], [Fr::from(0x12), Fr::from(0x34)]);
+        assert!(address[..30].iter().all(|limb| *limb == Fr::from(0)));
+        assert_eq!(time_log[6..], [Fr::from(1), Fr::from(2)]);
+        assert_eq!(instruction, Fr::from(0));
+        assert_eq!(value, [Fr::from(0xff); 32]);
+        // The stack depth is dropped and pops read the stack
+        let restored: TraceRecord<B256, B256, 32, 32> = converted.try_into_trace_record().unwrap();
+        assert_eq!(restored.stack_depth(), 0);
+        assert_eq!(restored.instruction(), MemoryInstruction::Read);
+
+        // A 512-bit value splits into 64 byte limbs
+        let wide = B512::from_halves(B256::from(1), B256::from(0xabcd));
+        let limbs: [Fr; 64] = byte_limbs(wide.into());
+        assert_eq!(limbs[31], Fr::from(1));
+        assert_eq!(limbs[62..], [Fr::from(0xab), Fr::from(0xcd)]);
+        assert_eq!(limbs.iter().filter(|limb| **limb != Fr::from(0)).count(), 3);
+    }
+
+    #[test]
+    fn test_wide_words_witness() {
+        // Two records whose words only differ in the high 256 bits
+        let record = |high: u64| {
+            TraceRecord::<B512, B512, 64, 64>::new(
+                1,
+                0,
+                MemoryInstruction::Write,
+                B512::from_halves(B256::from(high), B256::from(0x20)),
+                B512::from_halves(B256::from(high), B256::MAX),
+            )
+        };
+        let (low, high) = (record(0), record(1));
+        let witness = |record: &TraceRecord<B512, B512, 64, 64>| {
+            ConvertedTraceRecord::<Fr, 64, 64>::from_trace_record(record).unwrap()
+        };
+        let (low_witness, high_witness) = (witness(&low), witness(&high));
+        assert_ne!(low_witness.address, high_witness.address);
+        assert_ne!(low_witness.value, high_witness.value);
+        assert_eq!(high_witness.value[31], Fr::from(1));
+        assert_eq!(low_witness.try_into_trace_record(), Ok(low));
+        assert_eq!(high_witness.try_into_trace_record(), Ok(high));
+
+        // The limbs of the circuits can not hold them
+        assert_eq!(
+            ConvertedTraceRecord::<Fr>::from_trace_record(&high).unwrap_err(),
+            Error::WitnessWordTooWide {
+                bits: 512,
+                limbs: 32
+            }
+        );
+    }
+
+    #[test]
+    fn test_instruction_code() {
+        for code in [InstructionCode::Read, InstructionCode::Write] {
+            assert_eq!(
+                InstructionCode::try_from_field(code.to_field::<Fr>()),
+                Some(code)
+            );
+            assert_eq!(InstructionCode::from(MemoryInstruction::from(code)), code);
+        }
+        assert_eq!(
+            InstructionCode::from(MemoryInstruction::Push),
+            InstructionCode::Write
+        );
+        assert_eq!(
+            InstructionCode::from(MemoryInstruction::Pop),
+            InstructionCode::Read
+        );
+        assert_eq!(
+            InstructionCode::try_from_field(Fr::from(INSTRUCTION_CODES as u64)),
+            None
+        );
+
+        // The circuits, the trace formats and the KZG commitment share the codes, the
+        // circuits encode a push as a write and a pop as a read
+        for instruction in [
+            MemoryInstruction::Read,
+            MemoryInstruction::Write,
+            MemoryInstruction::Push,
+            MemoryInstruction::Pop,
+        ] {
+            let code = InstructionCode::from(instruction);
+            assert_eq!(
+                MemoryInstruction::from_code(instruction.code()),
+                Some(instruction)
+            );
+            assert_eq!(code.code(), MemoryInstruction::from(code).code());
+            assert_eq!(
+                code.code() == instruction.code(),
+                matches!(
+                    instruction,
+                    MemoryInstruction::Read | MemoryInstruction::Write
+                )
+            );
+            let record = TraceRecord::<B256, B256, 32, 32>::new(
+                0,
+                0,
+                instruction,
+                B256::from(0),
+                B256::from(0),
+            );
+            assert_eq!(
+                ConvertedTraceRecord::<Fr>::from_trace_record(&record)
+                    .unwrap()
+                    .instruction,
+                Fr::from(code.code())
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_converted_trace_record() {
+        let record = TraceRecord::<B256, B256, 32, 32>::new(
+            1,
+            0,
+            MemoryInstruction::Write,
+            B256::from(0x12_3456),
+            B256::from(1),
+        );
+        let converted = ConvertedTraceRecord::<Fr>::from_trace_record(&record).unwrap();
+
+        let mut invalid = converted.clone();
+        invalid.value[3] = Fr::from(256);
+        assert_eq!(
+            invalid.try_into_trace_record::<B256, B256, 32, 32>(),
+            Err(Error::InvalidWitnessLimb { index: 44 })
+        );
+        let mut invalid = converted.clone();
+        invalid.instruction = Fr::from(INSTRUCTION_CODES as u64);
+        assert_eq!(
+            invalid.try_into_trace_record::<B256, B256, 32, 32>(),
+            Err(Error::InvalidWitnessLimb { index: 40 })
+        );
+        let mut invalid = converted.clone();
+        invalid.time_log[0] = -Fr::from(1);
+        assert_eq!(
+            invalid.try_into_trace_record::<B256, B256, 32, 32>(),
+            Err(Error::InvalidWitnessLimb { index: 32 })
+        );
+        // The address fits in 32 bits but not in 16 bits
+        assert_eq!(
+            converted.try_into_trace_record::<B32, B16, 4, 2>(),
+            Ok(TraceRecord::new(
+                1,
+                0,
+                MemoryInstruction::Write,
+                B32::from(0x12_3456),
+                B16::from(1)
+            ))
+        );
+        assert_eq!(
+            converted.try_into_trace_record::<B16, B16, 2, 2>(),
+            Err(Error::ValueOutOfRange)
+        );
+    }
+
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn test_converted_trace_record_borsh_roundtrip() {
+        let record =
+            ConvertedTraceRecord::<Fr>::from_trace_record(&TraceRecord::<B256, B256, 32, 32>::new(
+                42,
+                0,
+                MemoryInstruction::Write,
+                B256::from(0x1234),
+                B256::MAX,
+            ))
+            .unwrap();
+        let bytes = borsh::to_vec(&record).unwrap();
+        // 73 field elements of 32 bytes each
+        assert_eq!(bytes.len(), 73 * 32);
+        assert_eq!(borsh::to_vec(&record).unwrap(), bytes);
+
+        let decoded: ConvertedTraceRecord<Fr> = borsh::from_slice(&bytes).unwrap();
+        assert_eq!(decoded.get_tuple(), record.get_tuple());
+
+        // Non canonical encoding of a field element is rejected
+        let mut corrupted = bytes.clone();
+        corrupted[..32].copy_from_slice(&[0xff; 32]);
+        assert!(borsh::from_slice::<ConvertedTraceRecord<Fr>>(&corrupted).is_err());
     }
 }
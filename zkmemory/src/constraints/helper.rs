@@ -1,26 +1,101 @@
 use crate::{
-    base::{Base, B256},
+    base::{Base, B256, B64},
+    config::{ProverBudget, ReadPolicy},
     constraints::consistency_check_circuit::MemoryConsistencyCircuit,
-    machine::{AbstractTraceRecord, TraceRecord},
+    machine::{
+        chunk::{split_trace_into_chunks, TraceChunk},
+        AbstractTraceRecord, TraceRecord,
+    },
 };
 use colored::Colorize;
-use core::marker::PhantomData;
 extern crate alloc;
 use alloc::{vec, vec::Vec};
+use core::ops::Range;
 use halo2_proofs::dev::MockProver;
 use halo2curves::pasta::Fp;
 extern crate std;
 use std::{println, time::Instant};
 
+/// Number of rows used by the largest lookup table
+const LOOKUP_TABLE_ROWS: usize = 256;
+/// Rows reserved by halo2 at the end of a circuit, the circuits query an advice column at
+/// two rotations at most so there are 5 blinding factors and one more row
+const BLINDING_ROWS: usize = 6;
+
+/// Compute the smallest k such that the used rows and the blinding rows fit in 2^k rows
+pub fn circuit_size_k(used_rows: usize) -> u32 {
+    B64::from(used_rows + BLINDING_ROWS - 1).bit_len()
+}
+
+/// Compute the smallest k of the memory consistency circuit for a trace of the given length
+pub fn minimal_k(trace_len: usize) -> u32 {
+    // The sorted and the original memory regions both load the lookup tables,
+    // so they are laid out one after another
+    circuit_size_k(2 * core::cmp::max(trace_len, LOOKUP_TABLE_ROWS))
+}
+
+/// Compute the number of records the memory consistency circuit of size `2^k` holds,
+/// zero if the lookup tables do not fit. It is the largest trace length of [minimal_k] k
+pub fn usable_rows(k: u32) -> usize {
+    let rows = 1usize.checked_shl(k).unwrap_or(usize::MAX);
+    match rows.saturating_sub(BLINDING_ROWS) / 2 {
+        usable if usable >= LOOKUP_TABLE_ROWS => usable,
+        _ => 0,
+    }
+}
+
+/// Split of a trace into chunks that each fit in a circuit of a [ProverBudget]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkPlan {
+    /// Number of records a circuit of the budget holds, see [usable_rows]
+    pub usable_rows: usize,
+    /// Smallest k of a circuit that holds the longest chunk
+    pub k: u32,
+    /// Index ranges of the records of the chunks in the trace, in time order
+    pub chunks: Vec<Range<usize>>,
+}
+
+impl ChunkPlan {
+    /// Split the trace the plan was made for into its chunks with the state of the memory
+    /// at their boundaries, see [split_trace_into_chunks]
+    pub fn split<K, V, const S: usize, const T: usize>(
+        &self,
+        trace: &[TraceRecord<K, V, S, T>],
+    ) -> Vec<TraceChunk<K, V, S, T>>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        split_trace_into_chunks(trace, self.usable_rows)
+    }
+}
+
+/// Plan the chunks of a trace of the given length so every chunk fits in a circuit of the
+/// budget, the last chunk may be shorter. A budget too small for the lookup tables is taken
+/// as the smallest circuit, [ConfigBuilder::build](crate::config::ConfigBuilder::build)
+/// rejects such a budget
+pub fn plan_chunks(trace_len: usize, budget: ProverBudget) -> ChunkPlan {
+    let usable_rows = usable_rows(budget.max_k.max(minimal_k(0)));
+    let chunks: Vec<Range<usize>> = (0..trace_len)
+        .step_by(usable_rows)
+        .map(|start| start..trace_len.min(start + usable_rows))
+        .collect();
+    ChunkPlan {
+        usable_rows,
+        k: minimal_k(trace_len.min(usable_rows)),
+        chunks,
+    }
+}
+
 /// Sort the trace by address -> time_log as keys
 pub fn sort_trace<K, V, const S: usize, const T: usize>(
-    trace: Vec<TraceRecord<K, V, S, T>>,
+    trace: impl IntoIterator<Item = TraceRecord<K, V, S, T>>,
 ) -> Vec<TraceRecord<K, V, S, T>>
 where
     K: Base<S>,
     V: Base<T>,
 {
-    let mut buffer = trace;
+    let mut buffer: Vec<TraceRecord<K, V, S, T>> = trace.into_iter().collect();
     buffer.sort_by(|a, b| {
         if a.address() == b.address() {
             a.time_log().cmp(&b.time_log())
@@ -32,30 +107,49 @@ where
 }
 
 /// Common test function to build and check the consistency circuit
-pub fn build_and_test_circuit(trace: Vec<TraceRecord<B256, B256, 32, 32>>, k: u32) {
+pub fn build_and_test_circuit(
+    trace: impl IntoIterator<Item = TraceRecord<B256, B256, 32, 32>>,
+    k: u32,
+) {
+    let trace: Vec<TraceRecord<B256, B256, 32, 32>> = trace.into_iter().collect();
     // Sort this trace (already sorted by time_log) in address and time_log order
-    let sorted_trace = sort_trace::<B256, B256, 32, 32>(trace.clone());
+    let sorted_trace = sort_trace::<B256, B256, 32, 32>(trace.iter().copied());
+
+    let circuit = MemoryConsistencyCircuit::<Fp>::new(trace, sorted_trace)
+        .expect("The trace must be sorted by time_log");
+
+    let prover = MockProver::run(k, &circuit, vec![]).expect("Cannot run the circuit");
+    assert_eq!(prover.verify(), Ok(()));
+}
 
-    let circuit = MemoryConsistencyCircuit::<Fp> {
-        input: trace.clone(),
-        shuffle: sorted_trace.clone(),
-        marker: PhantomData,
-    };
+/// Common test function to build and check the consistency circuit of a trace in any order,
+/// the trace is canonicalized first, see [canonicalize_trace](crate::machine::validate::canonicalize_trace)
+pub fn build_and_test_canonical_circuit(
+    trace: impl IntoIterator<Item = TraceRecord<B256, B256, 32, 32>>,
+    k: u32,
+) {
+    let circuit = MemoryConsistencyCircuit::<Fp>::new_canonical(
+        trace.into_iter().collect(),
+        ReadPolicy::ErrorOnUninit,
+        None,
+    )
+    .expect("The trace must not have conflicting records");
 
     let prover = MockProver::run(k, &circuit, vec![]).expect("Cannot run the circuit");
     assert_eq!(prover.verify(), Ok(()));
 }
 
 /// Common test function to build and check the consistency circuit
-pub fn build_and_test_circuit_with_time(trace: Vec<TraceRecord<B256, B256, 32, 32>>, k: u32) {
+pub fn build_and_test_circuit_with_time(
+    trace: impl IntoIterator<Item = TraceRecord<B256, B256, 32, 32>>,
+    k: u32,
+) {
+    let trace: Vec<TraceRecord<B256, B256, 32, 32>> = trace.into_iter().collect();
     // Sort this trace (already sorted by time_log) in address and time_log order
-    let sorted_trace = sort_trace::<B256, B256, 32, 32>(trace.clone());
+    let sorted_trace = sort_trace::<B256, B256, 32, 32>(trace.iter().copied());
 
-    let circuit = MemoryConsistencyCircuit::<Fp> {
-        input: trace.clone(),
-        shuffle: sorted_trace.clone(),
-        marker: PhantomData,
-    };
+    let circuit = MemoryConsistencyCircuit::<Fp>::new(trace, sorted_trace)
+        .expect("The trace must be sorted by time_log");
 
     let start = Instant::now();
     let prover = MockProver::run(k, &circuit, vec![]).expect("Cannot run the circuit");
@@ -72,6 +166,87 @@ pub fn build_and_test_circuit_with_time(trace: Vec<TraceRecord<B256, B256, 32, 3
 mod tests {
     use super::*;
     use crate::machine::MemoryInstruction;
+    use core::marker::PhantomData;
+
+    #[test]
+    fn test_minimal_k() {
+        // The lookup tables dominate short traces
+        assert_eq!(minimal_k(0), 10);
+        assert_eq!(minimal_k(256), 10);
+        // Long traces need more rows than the lookup tables
+        assert_eq!(minimal_k(600), 11);
+        assert_eq!(minimal_k(1021), 11);
+        assert_eq!(minimal_k(1022), 12);
+        assert_eq!(circuit_size_k(50), 6);
+        assert_eq!(circuit_size_k(58), 6);
+        assert_eq!(circuit_size_k(59), 7);
+    }
+
+    #[test]
+    fn test_usable_rows() {
+        // Writes to a few cells, the trace is valid for any length
+        let trace = |len: usize| -> Vec<TraceRecord<B256, B256, 32, 32>> {
+            (0..len as u64)
+                .map(|i| {
+                    TraceRecord::new(
+                        i,
+                        0,
+                        MemoryInstruction::Write,
+                        B256::from(32 * (i % 8)),
+                        B256::from(i),
+                    )
+                })
+                .collect()
+        };
+        let accepts = |len: usize, k: u32| {
+            let trace = trace(len);
+            let circuit = MemoryConsistencyCircuit::<Fp>::new(trace.clone(), sort_trace(trace))
+                .expect("The trace is sorted by time_log");
+            MockProver::run(k, &circuit, vec![]).is_ok_and(|prover| prover.verify().is_ok())
+        };
+        assert_eq!(usable_rows(9), 0);
+        assert_eq!(usable_rows(10), 509);
+        assert_eq!(usable_rows(11), 1021);
+        for k in [10, 11] {
+            assert!(accepts(usable_rows(k), k));
+            assert!(!accepts(usable_rows(k) + 1, k));
+            assert_eq!(minimal_k(usable_rows(k)), k);
+            assert_eq!(minimal_k(usable_rows(k) + 1), k + 1);
+        }
+    }
+
+    #[test]
+    fn test_plan_chunks() {
+        let budget = ProverBudget { max_k: 10 };
+        let plan = plan_chunks(1200, budget);
+        assert_eq!(plan.usable_rows, 509);
+        assert_eq!(plan.k, 10);
+        assert_eq!(plan.chunks, vec![0..509, 509..1018, 1018..1200]);
+        // A short trace needs a single chunk
+        let plan = plan_chunks(100, ProverBudget { max_k: 16 });
+        assert_eq!(plan.chunks, vec![0..100]);
+        assert_eq!(plan.k, minimal_k(100));
+        assert!(plan_chunks(0, budget).chunks.is_empty());
+
+        // The chunks of the plan are the ones of the trace windowing API
+        let trace: Vec<TraceRecord<B256, B256, 32, 32>> = (0..600u64)
+            .map(|i| {
+                TraceRecord::new(
+                    i,
+                    0,
+                    MemoryInstruction::Write,
+                    B256::from(32 * (i % 4)),
+                    B256::from(i),
+                )
+            })
+            .collect();
+        let plan = plan_chunks(trace.len(), budget);
+        let chunks = plan.split(&trace);
+        assert_eq!(chunks.len(), plan.chunks.len());
+        for (chunk, range) in chunks.iter().zip(&plan.chunks) {
+            assert_eq!(chunk.records, trace[range.clone()]);
+        }
+    }
 
     #[test]
     #[should_panic]
@@ -85,7 +260,7 @@ mod tests {
         );
 
         // First instruction is read
-        build_and_test_circuit(vec![trace_0], 10);
+        build_and_test_circuit(vec![trace_0], minimal_k(1));
     }
 
     #[test]
@@ -108,7 +283,7 @@ mod tests {
         );
 
         // Read instruction in the unwritten address
-        build_and_test_circuit(vec![trace_0, trace_1], 10);
+        build_and_test_circuit(vec![trace_0, trace_1], minimal_k(2));
     }
 
     #[test]
@@ -131,7 +306,7 @@ mod tests {
         );
 
         // The trace read does not match the previous write in the same address
-        build_and_test_circuit(vec![trace_0, trace_1], 10);
+        build_and_test_circuit(vec![trace_0, trace_1], minimal_k(2));
     }
 
     #[test]
@@ -146,7 +321,7 @@ mod tests {
         );
 
         // The trace does not start at time 0
-        build_and_test_circuit(vec![trace_0], 10);
+        build_and_test_circuit(vec![trace_0], minimal_k(1));
     }
 
     #[test]
@@ -177,7 +352,7 @@ mod tests {
         );
 
         // Initial trace is not sorted by time_log
-        build_and_test_circuit(vec![trace_0, trace_1, trace_2], 10);
+        build_and_test_circuit(vec![trace_0, trace_1, trace_2], minimal_k(3));
     }
 
     #[test]
@@ -226,13 +401,186 @@ mod tests {
         let circuit = MemoryConsistencyCircuit::<Fp> {
             input: trace.clone(),
             shuffle: sorted_trace.clone(),
+            read_policy: ReadPolicy::ErrorOnUninit,
+            layout: None,
             marker: PhantomData,
         };
 
-        let prover = MockProver::run(10, &circuit, vec![]).unwrap();
+        let prover = MockProver::run(minimal_k(trace.len()), &circuit, vec![]).unwrap();
         assert_eq!(prover.verify(), Ok(()));
     }
 
+    #[test]
+    fn test_circuit_rejects_unsorted_traces() {
+        let record = |time_log: u64, address: u64| {
+            TraceRecord::<B256, B256, 32, 32>::new(
+                time_log,
+                0,
+                MemoryInstruction::Write,
+                B256::from(address),
+                B256::from(1),
+            )
+        };
+        let trace = vec![record(0, 0x20), record(1, 0)];
+        let sorted = sort_trace::<B256, B256, 32, 32>(trace.clone());
+        assert!(MemoryConsistencyCircuit::<Fp>::new(trace.clone(), sorted.clone()).is_ok());
+        assert_eq!(
+            MemoryConsistencyCircuit::<Fp>::new(vec![trace[1], trace[0]], sorted).unwrap_err(),
+            crate::error::Error::TraceNotSortedByTime
+        );
+        assert_eq!(
+            MemoryConsistencyCircuit::<Fp>::new(trace.clone(), trace).unwrap_err(),
+            crate::error::Error::TraceNotSortedByAddressTime
+        );
+    }
+
+    #[test]
+    fn test_circuit_from_canonicalized_trace() {
+        let record = |time_log: u64, instruction: MemoryInstruction, value: u64| {
+            TraceRecord::<B256, B256, 32, 32>::new(
+                time_log,
+                0,
+                instruction,
+                B256::from(0x20),
+                B256::from(value),
+            )
+        };
+        let trace = vec![
+            record(1, MemoryInstruction::Read, 1),
+            record(0, MemoryInstruction::Write, 1),
+            record(1, MemoryInstruction::Read, 1),
+            record(2, MemoryInstruction::Write, 2),
+        ];
+        assert_eq!(
+            MemoryConsistencyCircuit::<Fp>::new(trace.clone(), sort_trace(trace.clone()))
+                .unwrap_err(),
+            crate::error::Error::TraceNotSortedByTime
+        );
+        let circuit = MemoryConsistencyCircuit::<Fp>::new_canonical(
+            trace.clone(),
+            ReadPolicy::ErrorOnUninit,
+            None,
+        )
+        .unwrap();
+        assert_eq!(circuit.input.len(), 3);
+        build_and_test_canonical_circuit(trace.clone(), minimal_k(3));
+
+        let mut conflicting = trace;
+        conflicting.push(record(2, MemoryInstruction::Write, 3));
+        assert!(matches!(
+            MemoryConsistencyCircuit::<Fp>::new_canonical(
+                conflicting,
+                ReadPolicy::ErrorOnUninit,
+                None
+            ),
+            Err(crate::error::Error::ConflictingRecords { time_log: 2, .. })
+        ));
+    }
+
+    // Run the MockProver on the trace, a panic while proving counts as a rejection
+    fn accepted_by_mock_prover(trace: Vec<TraceRecord<B256, B256, 32, 32>>) -> bool {
+        let k = minimal_k(trace.len());
+        let circuit = MemoryConsistencyCircuit::<Fp> {
+            shuffle: sort_trace::<B256, B256, 32, 32>(trace.iter().copied()),
+            input: trace,
+            read_policy: ReadPolicy::ErrorOnUninit,
+            layout: None,
+            marker: PhantomData,
+        };
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            MockProver::run(k, &circuit, vec![])
+                .map(|prover| prover.verify().is_ok())
+                .unwrap_or(false)
+        }))
+        .unwrap_or(false)
+    }
+
+    #[test]
+    fn test_validator_agrees_with_mock_prover() {
+        use crate::machine::validate::{validate_trace, ViolationKind};
+        let record = |time_log: u64, instruction: MemoryInstruction, address: u64, value: u64| {
+            TraceRecord::<B256, B256, 32, 32>::new(
+                time_log,
+                0,
+                instruction,
+                B256::from(address),
+                B256::from(value),
+            )
+        };
+        let valid = vec![
+            record(0, MemoryInstruction::Write, 0, 1),
+            record(1, MemoryInstruction::Write, 0x20, 2),
+            record(2, MemoryInstruction::Read, 0, 1),
+        ];
+        assert_eq!(validate_trace(&valid), Ok(()));
+        assert!(accepted_by_mock_prover(valid));
+
+        let invalid = [
+            (
+                ViolationKind::NonZeroStart,
+                vec![record(6, MemoryInstruction::Write, 0, 1)],
+            ),
+            (
+                ViolationKind::NonIncreasingTime,
+                vec![
+                    record(0, MemoryInstruction::Write, 0, 1),
+                    record(2, MemoryInstruction::Write, 0, 1),
+                    record(1, MemoryInstruction::Write, 0x20, 5),
+                ],
+            ),
+            (
+                ViolationKind::ReadBeforeWrite,
+                vec![
+                    record(0, MemoryInstruction::Write, 0, 1),
+                    record(1, MemoryInstruction::Read, 0x20, 0),
+                ],
+            ),
+            (
+                ViolationKind::ReadValueMismatch,
+                vec![
+                    record(0, MemoryInstruction::Write, 0, 1),
+                    record(1, MemoryInstruction::Read, 0, 2),
+                ],
+            ),
+        ];
+        for (kind, trace) in invalid {
+            let violations = validate_trace(&trace).unwrap_err();
+            assert!(violations.iter().any(|violation| violation.kind == kind));
+            assert!(!accepted_by_mock_prover(trace));
+        }
+    }
+
+    #[test]
+    fn test_replay_agrees_with_mock_prover() {
+        use crate::{
+            config::DefaultConfig,
+            machine::{
+                replay::{replay_and_check, ReplayError},
+                tests::StateMachine,
+                AbstractMachine, AbstractMemoryMachine,
+            },
+        };
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let genesis = sm.snapshot();
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+        sm.write(base + B256::from(32), B256::from(2)).unwrap();
+        sm.read(base).unwrap();
+        sm.read(base + B256::from(32)).unwrap();
+        let trace = sm.trace();
+        assert_eq!(replay_and_check(&genesis, &trace), Ok(sm.snapshot()));
+        assert!(accepted_by_mock_prover(trace.clone()));
+
+        // A single tampered read value is caught by both
+        let mut tampered = trace;
+        tampered[2] = TraceRecord::new(2, 0, MemoryInstruction::Read, base, B256::from(9));
+        assert!(matches!(
+            replay_and_check(&genesis, &tampered),
+            Err(ReplayError::ValueMismatch { index: 2, .. })
+        ));
+        assert!(!accepted_by_mock_prover(tampered));
+    }
+
     #[test]
     fn test_basic_read_write() {
         let trace_0 = TraceRecord::<B256, B256, 32, 32>::new(
@@ -294,7 +642,7 @@ mod tests {
             vec![
                 trace_0, trace_1, trace_2, trace_3, trace_4, trace_5, trace_6,
             ],
-            10,
+            minimal_k(7),
         );
     }
 }
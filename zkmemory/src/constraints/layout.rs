@@ -0,0 +1,160 @@
+//! Layout of the machine as constants of the circuits.
+//! [CircuitLayoutParams] carries the sections of a validated [Config] into the circuit
+//! construction, the circuits assign them to fixed columns so they are part of the
+//! verifying key instead of magic numbers in the gates.
+use crate::{
+    base::{Base, B256},
+    config::{section_size, Config, DefaultConfig},
+    constraints::gadgets::INSTRUCTION_CODES,
+};
+
+/// Constants of the layout of a machine used by the circuits. The words are padded or
+/// truncated to 256 bits like the limbs of
+/// [ConvertedTraceRecord](crate::constraints::ConvertedTraceRecord)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitLayoutParams {
+    /// First address of the stack section
+    pub stack_base: B256,
+    /// First address after the stack section
+    pub stack_top: B256,
+    /// Size of the memory section in bytes
+    pub memory_size: B256,
+    /// Size of a cell in bytes
+    pub word_size: B256,
+    /// Number of instruction codes accepted by the circuits, see [INSTRUCTION_CODES]
+    pub instruction_codes: usize,
+}
+
+impl CircuitLayoutParams {
+    /// Get the constants of the layout of a config, the config should be validated with
+    /// [Config::try_new] first
+    pub fn from_config<T: Base<S>, const S: usize>(config: &Config<T, S>) -> Self {
+        let word = |word: T| B256::from(word.fixed_be_bytes());
+        Self {
+            stack_base: word(config.stack.low()),
+            stack_top: word(config.stack.high()),
+            memory_size: word(section_size(config.memory.low(), config.memory.high())),
+            word_size: word(config.word_size),
+            instruction_codes: INSTRUCTION_CODES,
+        }
+    }
+
+    /// Get the offset of the last cell of the stack from the stack base,
+    /// zero if the stack does not hold a single cell
+    pub fn last_stack_offset(&self) -> B256 {
+        self.stack_top
+            .checked_sub(self.stack_base)
+            .and_then(|size| size.checked_sub(self.word_size))
+            .unwrap_or(B256::zero())
+    }
+
+    /// Check whether the whole cell starting at the address is inside the stack section
+    pub fn contains_stack_cell(&self, address: B256) -> bool {
+        let stack_size = self
+            .stack_top
+            .checked_sub(self.stack_base)
+            .unwrap_or(B256::zero());
+        address
+            .checked_sub(self.stack_base)
+            .and_then(|offset| offset.checked_add(self.word_size))
+            .is_some_and(|end| end <= stack_size)
+    }
+}
+
+impl Default for CircuitLayoutParams {
+    fn default() -> Self {
+        Self::from_config(&Config::<B256, 32>::new(
+            B256::WORD_SIZE,
+            DefaultConfig::default_config(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CircuitLayoutParams;
+    use crate::{
+        base::{Base, B256, B32},
+        config::{Config, ConfigBuilder, DEFAULT_STACK_BASE, DEFAULT_STACK_DEPTH},
+        constraints::gadgets::INSTRUCTION_CODES,
+    };
+
+    #[test]
+    fn test_params_from_config() {
+        let params = CircuitLayoutParams::default();
+        assert_eq!(params.stack_base, DEFAULT_STACK_BASE);
+        assert_eq!(
+            params.stack_top,
+            DEFAULT_STACK_BASE + B256::from(DEFAULT_STACK_DEPTH * 32)
+        );
+        assert_eq!(params.word_size, B256::WORD_SIZE);
+        assert_eq!(params.instruction_codes, INSTRUCTION_CODES);
+        assert_eq!(
+            params.last_stack_offset(),
+            B256::from((DEFAULT_STACK_DEPTH - 1) * 32)
+        );
+        assert!(params.contains_stack_cell(params.stack_base));
+        assert!(params.contains_stack_cell(params.stack_top - B256::from(32)));
+        assert!(!params.contains_stack_cell(params.stack_top - B256::from(31)));
+
+        // The words of narrower machines are padded to 256 bits
+        let config: Config<B32, 4> = ConfigBuilder::new()
+            .word_size(32)
+            .stack_depth(4)
+            .memory_size(1 << 10)
+            .build()
+            .unwrap();
+        let params = CircuitLayoutParams::from_config(&config);
+        let word = |word: B32| B256::from(word.fixed_be_bytes());
+        assert_eq!(params.stack_base, word(config.stack.low()));
+        assert_eq!(params.stack_top - params.stack_base, B256::from(16));
+        assert_eq!(params.memory_size, B256::from(1 << 10));
+        assert_eq!(params.word_size, B256::from(4));
+    }
+
+    #[test]
+    fn test_stack_bound_follows_the_config() {
+        use crate::{
+            config::ReadPolicy,
+            constraints::{
+                consistency_check_circuit::MemoryConsistencyCircuit,
+                helper::{minimal_k, sort_trace},
+            },
+            machine::{tests::StateMachine, AbstractMachine, AbstractStackMachine},
+        };
+        use alloc::vec;
+        use halo2_proofs::dev::MockProver;
+        use halo2curves::pasta::Fp;
+
+        let config = |depth| {
+            ConfigBuilder::<B256, 32>::new()
+                .stack_depth(depth)
+                .build()
+                .unwrap()
+        };
+        let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(config(4));
+        for value in 1..=4u64 {
+            sm.push(B256::from(value)).unwrap();
+        }
+        sm.pop().unwrap();
+        let trace = sm.trace();
+
+        let accepts = |depth| {
+            let circuit = MemoryConsistencyCircuit::<Fp>::new_with_read_policy(
+                trace.clone(),
+                sort_trace(trace.clone()),
+                ReadPolicy::ErrorOnUninit,
+                Some(CircuitLayoutParams::from_config(&config(depth))),
+            )
+            .unwrap();
+            MockProver::run(minimal_k(trace.len()), &circuit, vec![])
+                .unwrap()
+                .verify()
+                .is_ok()
+        };
+        assert!(accepts(4));
+        assert!(accepts(8));
+        // The last push is out of a smaller stack
+        assert!(!accepts(2));
+    }
+}
@@ -1,14 +1,21 @@
 /// Common traits for permutation, original and sorted memory
-pub mod common;
+pub(crate) mod common;
 /// The final circuit for memory consistency check
-pub mod consistency_check_circuit;
+pub(crate) mod consistency_check_circuit;
+/// Differential testing of the machine against the circuits
+#[cfg(test)]
+pub(crate) mod differential;
 /// Gadgets supports original, sorted memory constraints and permutation
-pub mod gadgets;
+pub(crate) mod gadgets;
 /// Helper for memory consistency check circuit
 pub mod helper;
+/// Layout of the machine as constants of the circuits
+pub mod layout;
 /// Check the correctness of the original memory
-pub mod original_memory_circuit;
+pub(crate) mod original_memory_circuit;
 /// Permutation circuit for trace record permutation check.
 pub mod permutation_circuit;
 /// Check the correctness of memory sorting
-pub mod sorted_memory_circuit;
+pub(crate) mod sorted_memory_circuit;
+
+pub use gadgets::{ConvertedTraceRecord, InstructionCode};
@@ -1,10 +1,14 @@
 //! Circuit for checking the constraints of the original memory trace record
 extern crate alloc;
-use crate::constraints::{
-    common::CircuitExtension,
-    gadgets::{
-        ConvertedTraceRecord, GreaterThanConfig, LookUpTables, Queries, Table,
-        TraceRecordWitnessTable,
+use crate::{
+    base::B256,
+    constraints::{
+        common::CircuitExtension,
+        gadgets::{
+            ConvertedTraceRecord, GreaterThanConfig, LookUpTables, Queries, StackBoundConfig,
+            Table, TraceRecordWitnessTable, INSTRUCTION_CODES,
+        },
+        layout::CircuitLayoutParams,
     },
 };
 use alloc::{format, vec, vec::Vec};
@@ -26,13 +30,18 @@ pub(crate) struct OriginalMemoryConfig<F: Field + PrimeField> {
     pub(crate) selector_zero: Selector,
     /// The config for checking the current time log is bigger than the previous one
     pub(crate) greater_than: GreaterThanConfig<F, 3>,
+    /// The config for checking that the stack accesses stay in the stack section
+    pub(crate) stack_bound: StackBoundConfig<F>,
     /// The lookup table
     pub(crate) lookup_tables: LookUpTables,
 }
 // Current constraints in this configure are:
 // 1) time[0]=0
 // 2) time[i]<time[i+1]
-// There will be more constraints in the config when we support PUSH and POP
+// 3) the address of a stack access is a cell of the stack section of the layout
+// PUSH and POP are witnessed as WRITE and READ on the cell under the stack pointer,
+// the rows of stack accesses are fixed by the circuit until the instruction codes tell them
+// apart
 impl<F: Field + PrimeField> OriginalMemoryConfig<F> {
     pub fn configure(
         meta: &mut ConstraintSystem<F>,
@@ -52,6 +61,7 @@ impl<F: Field + PrimeField> OriginalMemoryConfig<F> {
             selector,
             false,
         );
+        let stack_bound = StackBoundConfig::<F>::configure(meta, trace_record, lookup_tables);
         // Check that time_log[0]=0
         meta.create_gate("first accessed memory is at time 0", |meta| {
             let selector_zero = meta.query_selector(selector_zero);
@@ -67,6 +77,7 @@ impl<F: Field + PrimeField> OriginalMemoryConfig<F> {
             selector,
             selector_zero,
             greater_than,
+            stack_bound,
             lookup_tables,
         }
     }
@@ -77,6 +88,11 @@ impl<F: Field + PrimeField> OriginalMemoryConfig<F> {
 pub(crate) struct OriginalMemoryCircuit<F: Field + PrimeField> {
     /// The original memory trace record
     pub(crate) original_trace_record: Vec<ConvertedTraceRecord<F>>,
+    /// The address of every stack access, [None] for the other records and the records
+    /// past its end
+    pub(crate) stack_addresses: Vec<Option<B256>>,
+    /// The layout bounding the stack accesses, [None] does not check them
+    pub(crate) layout: Option<CircuitLayoutParams>,
     pub(crate) _marker: PhantomData<F>,
 }
 
@@ -95,7 +111,7 @@ impl<F: Field + PrimeField> CircuitExtension<F> for OriginalMemoryCircuit<F> {
                 }
                 config.lookup_tables.size40_table.load(&mut region)?;
                 config.lookup_tables.size256_table.load(&mut region)?;
-                config.lookup_tables.size2_table.load(&mut region)?;
+                config.lookup_tables.instruction_table.load(&mut region)?;
                 Ok(())
             },
         )?;
@@ -122,7 +138,7 @@ impl<F: Field + PrimeField> Circuit<F> for OriginalMemoryCircuit<F> {
         let lookup_tables = LookUpTables {
             size256_table: Table::<256>::construct(meta),
             size40_table: Table::<40>::construct(meta),
-            size2_table: Table::<2>::construct(meta),
+            instruction_table: Table::<INSTRUCTION_CODES>::construct(meta),
         };
         // The random challenges
         // For debugging purpose, we let alpha to be uniformly distributed
@@ -157,6 +173,12 @@ impl<F: Field + PrimeField> OriginalMemoryCircuit<F> {
         config: OriginalMemoryConfig<F>,
         offset: usize,
     ) -> Result<(), Error> {
+        config.stack_bound.assign(
+            region,
+            offset,
+            self.layout,
+            self.stack_addresses.get(offset).copied().flatten(),
+        )?;
         // Handle the case offset=0
         if offset == 0 {
             let (cur_address, cur_time_log, cur_instruction, cur_value) =
@@ -309,6 +331,8 @@ mod tests {
     fn build_and_test_circuit(trace: Vec<ConvertedTraceRecord<Fp>>, k: u32) {
         let circuit = OriginalMemoryCircuit::<Fp> {
             original_trace_record: trace,
+            stack_addresses: Vec::new(),
+            layout: None,
             _marker: PhantomData,
         };
 
@@ -411,4 +435,110 @@ mod tests {
 
         build_and_test_circuit(vec![trace0, trace1], 10);
     }
+
+    #[test]
+    fn test_register_and_memory_trace() {
+        use crate::{
+            base::B256,
+            config::DefaultConfig,
+            machine::{
+                tests::StateMachine, AbstractMachine, AbstractMemoryMachine,
+                AbstractRegisterMachine,
+            },
+        };
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let (memory_lo, _) = sm.get_memory_address();
+        sm.set_register(0, B256::from(11)).unwrap();
+        sm.write(memory_lo, B256::from(22)).unwrap();
+        sm.get_register(0).unwrap();
+        sm.set_register(1, B256::from(33)).unwrap();
+        sm.read(memory_lo).unwrap();
+        let trace = sm
+            .trace()
+            .iter()
+            .map(ConvertedTraceRecord::<Fp>::from_trace_record)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        build_and_test_circuit(trace, 10);
+    }
+
+    #[test]
+    fn test_merged_traces() {
+        use crate::{
+            base::B256,
+            config::DefaultConfig,
+            machine::{
+                merge::{merge_traces, AddressTag},
+                tests::StateMachine,
+                AbstractMachine, AbstractMemoryMachine, AbstractStackMachine,
+            },
+        };
+        // Main memory, a hash precompile scratchpad and a stack, with overlapping addresses
+        let traces = (1..=3u8)
+            .map(|tag| {
+                let mut sm =
+                    StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+                let base = sm.base_address();
+                sm.write(base, B256::from(tag as u64)).unwrap();
+                sm.push(B256::from(7)).unwrap();
+                sm.read(base).unwrap();
+                sm.pop().unwrap();
+                (AddressTag(tag), sm.trace())
+            })
+            .collect();
+        let trace: Vec<ConvertedTraceRecord<Fp>> = merge_traces(traces)
+            .unwrap()
+            .iter()
+            .map(ConvertedTraceRecord::<Fp>::from_trace_record)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(trace.len(), 12);
+        build_and_test_circuit(trace, 10);
+    }
+
+    #[test]
+    fn test_region_word_sizes() {
+        use crate::{
+            base::B256,
+            config::{ConfigArgs, DefaultConfig, MemoryLayout, MemoryRegion, RegionKind},
+            machine::{
+                tests::StateMachine, AbstractMachine, AbstractMemoryMachine, AbstractTraceRecord,
+            },
+        };
+        // A 256-bit main memory next to an I/O ring buffer of 64-bit cells
+        let heap = B256::from(0x30000);
+        let ring = B256::from(0x50000);
+        let layout = MemoryLayout::new(&[
+            MemoryRegion::new(RegionKind::Heap, heap, B256::from(0x1000)),
+            MemoryRegion::new(RegionKind::Io, ring, B256::from(0x40)).with_word_size(B256::from(8)),
+        ])
+        .unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(ConfigArgs {
+            layout: Some(layout),
+            ..DefaultConfig::default_config()
+        });
+        for i in 0..8u64 {
+            sm.write(ring + B256::from(i * 8), B256::from(u64::MAX - i))
+                .unwrap();
+        }
+        sm.write(heap, B256::MAX).unwrap();
+        sm.read(ring + B256::from(56)).unwrap();
+        sm.read(heap).unwrap();
+        let trace = sm.trace();
+        let cell_sizes: Vec<u8> = trace.iter().map(|record| record.cell_size()).collect();
+        assert_eq!(cell_sizes, [8, 8, 8, 8, 8, 8, 8, 8, 0, 8, 0]);
+
+        let trace: Vec<ConvertedTraceRecord<Fp>> = trace
+            .iter()
+            .map(ConvertedTraceRecord::<Fp>::from_trace_record)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // A ring cell only fills the 8 least significant limbs of the value
+        assert!(trace[0].value[..24]
+            .iter()
+            .all(|limb| *limb == Fp::from(0u64)));
+        assert_eq!(trace[0].value[24..], [Fp::from(0xffu64); 8]);
+        assert_eq!(trace[8].value, [Fp::from(0xffu64); 32]);
+        build_and_test_circuit(trace, 10);
+    }
 }
@@ -5,10 +5,11 @@
 //! with modifications to suit the project's requirements.
 use crate::{
     base::Base,
-    constraints::common::CircuitExtension,
-    machine::{MemoryInstruction, TraceRecord},
+    cancellation::CancellationToken,
+    constraints::{common::CircuitExtension, gadgets::InstructionCode},
+    machine::TraceRecord,
 };
-use core::marker::PhantomData;
+use core::{marker::PhantomData, time::Duration};
 use group::ff::{Field, FromUniformBytes, PrimeField};
 use halo2_proofs::{
     circuit::{Layouter, SimpleFloorPlanner, Value},
@@ -35,6 +36,9 @@ use rand_core::OsRng;
 extern crate alloc;
 use alloc::{vec, vec::Vec};
 
+/// Number of trace records converted to witnesses between two cancellation checks
+const WITNESS_BATCH_SIZE: usize = 1024;
+
 /// Define a chip struct that implements our instructions.
 pub struct ShuffleChip<F: Field + PrimeField> {
     config: ShuffleConfig,
@@ -224,6 +228,54 @@ where
         }
     }
 
+    /// Initialize the parameters for the prover,
+    /// the cancellation token is checked between the setup and key generation stages.
+    /// Return [Error::KeyGenerationFailed](crate::error::Error::KeyGenerationFailed) if the
+    /// keys of the circuit cannot be generated
+    pub fn new_with_cancellation(
+        k: u32,
+        circuit: PermutationCircuit<C::Scalar>,
+        expected: bool,
+        token: &CancellationToken,
+    ) -> Result<Self, crate::error::Error> {
+        token.check()?;
+        let params = ParamsIPA::<C>::new(k);
+        token.check()?;
+        let vk =
+            keygen_vk(&params, &circuit).map_err(|_| crate::error::Error::KeyGenerationFailed)?;
+        token.check()?;
+        let pk = keygen_pk(&params, vk, &circuit)
+            .map_err(|_| crate::error::Error::KeyGenerationFailed)?;
+        token.check()?;
+        Ok(Self {
+            params,
+            pk,
+            circuit,
+            expected,
+        })
+    }
+
+    /// Create proof for the permutation circuit unless the token was cancelled.
+    /// The halo2 prover itself is not interruptible, a proof finished after the
+    /// cancellation is discarded.
+    pub fn create_proof_with_cancellation(
+        &mut self,
+        token: &CancellationToken,
+    ) -> Result<Vec<u8>, crate::error::Error> {
+        token.check()?;
+        let proof = self.create_proof();
+        token.check()?;
+        Ok(proof)
+    }
+
+    /// Create proof for the permutation circuit within the given time budget
+    pub fn prove_with_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, crate::error::Error> {
+        self.create_proof_with_cancellation(&CancellationToken::with_timeout(timeout))
+    }
+
     /// Create proof for the permutation circuit
     pub fn create_proof(&mut self) -> Vec<u8> {
         let mut transcript = Blake2bWrite::<Vec<u8>, C, Challenge255<C>>::init(vec![]);
@@ -268,37 +320,69 @@ where
 impl<F: Field + PrimeField> PermutationCircuit<F> {
     /// Create a new permutation circuit with two traces and a random seed
     pub fn new<K, V, const S: usize, const T: usize>(
-        input_trace: Vec<TraceRecord<K, V, S, T>>,
-        shuffle_trace: Vec<TraceRecord<K, V, S, T>>,
+        input_trace: impl IntoIterator<Item = TraceRecord<K, V, S, T>>,
+        shuffle_trace: impl IntoIterator<Item = TraceRecord<K, V, S, T>>,
     ) -> Self
     where
         K: Base<S>,
         V: Base<T>,
         F: Field + PrimeField + From<K> + From<V>,
     {
-        assert_eq!(
-            input_trace.len(),
-            shuffle_trace.len(),
-            "Two input traces are not equal in length."
-        );
+        Self::new_with_cancellation(input_trace, shuffle_trace, &CancellationToken::new())
+            .expect("Witness generation is never cancelled without a cancellation request")
+    }
 
+    /// Create a new permutation circuit with two traces and a random seed,
+    /// the cancellation token is checked between batches of witness generation
+    pub fn new_with_cancellation<K, V, const S: usize, const T: usize>(
+        input_trace: impl IntoIterator<Item = TraceRecord<K, V, S, T>>,
+        shuffle_trace: impl IntoIterator<Item = TraceRecord<K, V, S, T>>,
+        token: &CancellationToken,
+    ) -> Result<Self, crate::error::Error>
+    where
+        K: Base<S>,
+        V: Base<T>,
+        F: Field + PrimeField + From<K> + From<V>,
+    {
         let mut rng = rand::thread_rng();
         let mut seeds = [0u64; 5];
         rng.fill(&mut seeds);
 
-        Self {
-            input: input_trace
-                .clone()
-                .into_iter()
-                .map(|mut x| x.compress(seeds))
-                .collect(),
-            shuffle: shuffle_trace
-                .clone()
-                .into_iter()
-                .map(|mut x| Value::known(x.compress(seeds)))
-                .collect(),
+        let input = compress_trace::<F, K, V, S, T>(input_trace, seeds, token)?;
+        let shuffle = compress_trace::<F, K, V, S, T>(shuffle_trace, seeds, token)?
+            .into_iter()
+            .map(Value::known)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            input.len(),
+            shuffle.len(),
+            "Two input traces are not equal in length."
+        );
+
+        Ok(Self { input, shuffle })
+    }
+}
+
+// Compress every record of the trace, the cancellation token is checked between batches
+fn compress_trace<F, K, V, const S: usize, const T: usize>(
+    trace: impl IntoIterator<Item = TraceRecord<K, V, S, T>>,
+    seeds: [u64; 5],
+    token: &CancellationToken,
+) -> Result<Vec<F>, crate::error::Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+    F: Field + PrimeField + From<K> + From<V>,
+{
+    let trace = trace.into_iter();
+    let mut compressed = Vec::with_capacity(trace.size_hint().0);
+    for (index, mut record) in trace.enumerate() {
+        if index % WITNESS_BATCH_SIZE == 0 {
+            token.check()?;
         }
+        compressed.push(record.compress::<F>(seeds));
     }
+    Ok(compressed)
 }
 
 // Implement methods for trace records to use for the permutation circuit.
@@ -310,10 +394,7 @@ where
     /// Compress trace elements into a single field element Fp
     pub fn compress<F: From<K> + From<V> + Field + PrimeField>(&mut self, seed: [u64; 5]) -> F {
         let (time_log, stack_depth, instruction, address, value) = self.get_tuple();
-        let instruction = match instruction {
-            MemoryInstruction::Write => F::ONE,
-            MemoryInstruction::Read => F::ZERO,
-        };
+        let instruction: F = InstructionCode::from(instruction).to_field();
         // Dot product between trace record and seed
         F::from(time_log) * F::from(seed[0])
             + F::from(stack_depth) * F::from(seed[1])
@@ -328,10 +409,16 @@ mod tests {
 
     use crate::{
         base::{Base, B256},
-        constraints::permutation_circuit::{PermutationCircuit, PermutationProver},
+        cancellation::CancellationToken,
+        constraints::{
+            gadgets::InstructionCode,
+            helper::circuit_size_k,
+            permutation_circuit::{PermutationCircuit, PermutationProver, WITNESS_BATCH_SIZE},
+        },
+        error::Error,
         machine::{AbstractTraceRecord, MemoryInstruction, TraceRecord},
     };
-    use ff::Field;
+    use core::time::Duration;
     use halo2_proofs::circuit::Value;
     use halo2curves::pasta::{EqAffine, Fp};
     use rand::{seq::SliceRandom, Rng};
@@ -395,9 +482,10 @@ mod tests {
     // Test the functionality of the permutation circuit with a shuffled trace record
     #[test]
     fn check_permutation_with_trace_records() {
-        const K: u32 = 6;
-        // Number of trace elements in a trace, min = 2^K.
+        // Number of trace elements in a trace
         let trace_size = 50;
+        // The number of rows cannot exceed 2^k
+        let k = circuit_size_k(trace_size as usize);
         let mut rng = rand::thread_rng();
         let mut trace_buffer = random_trace::<B256, B256, 32, 32>(trace_size);
 
@@ -408,7 +496,7 @@ mod tests {
         let circuit = PermutationCircuit::<Fp>::new(input_trace, shuffle_trace);
 
         // Test with IPA prover
-        let mut ipa_prover = PermutationProver::<EqAffine>::new(K, circuit, true);
+        let mut ipa_prover = PermutationProver::<EqAffine>::new(k, circuit, true);
         let proof = ipa_prover.create_proof();
         assert!(ipa_prover.verify(proof));
     }
@@ -419,10 +507,7 @@ mod tests {
         for _ in 0..10 {
             let mut record = random_trace_record::<B256, B256, 32, 32>();
             let (time_log, stack_depth, instruction, address, value) = record.get_tuple();
-            let instruction = match instruction {
-                MemoryInstruction::Write => Fp::ONE,
-                MemoryInstruction::Read => Fp::ZERO,
-            };
+            let instruction: Fp = InstructionCode::from(instruction).to_field();
             // Generate a random seed of type [u64; 5]
             let mut rng = rand::thread_rng();
             let mut seeds = [0u64; 5];
@@ -440,9 +525,10 @@ mod tests {
     #[test]
     #[should_panic]
     fn check_wrong_permutation() {
-        const K: u32 = 6;
-        // Number of trace elements in a trace, min = 2^K.
+        // Number of trace elements in a trace
         let trace_size = 50;
+        // The number of rows cannot exceed 2^k
+        let k = circuit_size_k(trace_size as usize);
         let mut rng = rand::thread_rng();
         let mut trace_buffer = random_trace::<B256, B256, 32, 32>(trace_size);
 
@@ -456,7 +542,7 @@ mod tests {
         let circuit = PermutationCircuit::<Fp>::new(input_trace, shuffle_trace);
 
         // Test with IPA prover
-        let mut ipa_prover = PermutationProver::<EqAffine>::new(K, circuit, true);
+        let mut ipa_prover = PermutationProver::<EqAffine>::new(k, circuit, true);
         let proof = ipa_prover.create_proof();
         assert!(ipa_prover.verify(proof));
     }
@@ -464,9 +550,10 @@ mod tests {
     #[test]
     #[should_panic]
     fn test_inequal_lengths() {
-        const K: u32 = 6;
-        // Number of trace elements in a trace, min = 2^K.
+        // Number of trace elements in a trace
         let trace_size = 50;
+        // The number of rows cannot exceed 2^k
+        let k = circuit_size_k(trace_size as usize);
         let mut rng = rand::thread_rng();
         let mut trace_buffer = random_trace::<B256, B256, 32, 32>(trace_size);
         let input_trace = trace_buffer.clone();
@@ -478,8 +565,122 @@ mod tests {
 
         let circuit = PermutationCircuit::<Fp>::new(input_trace, shuffle_trace);
         // Test with IPA prover
-        let mut ipa_prover = PermutationProver::<EqAffine>::new(K, circuit, true);
+        let mut ipa_prover = PermutationProver::<EqAffine>::new(k, circuit, true);
         let proof = ipa_prover.create_proof();
         assert!(!ipa_prover.verify(proof));
     }
+
+    #[test]
+    fn test_cancel_witness_generation() {
+        let trace = random_trace::<B256, B256, 32, 32>(50);
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(matches!(
+            PermutationCircuit::<Fp>::new_with_cancellation(trace.clone(), trace, &token),
+            Err(Error::Cancelled)
+        ));
+
+        // A cancellation requested during the work stops it at the next batch
+        let trace = random_trace::<B256, B256, 32, 32>(3 * WITNESS_BATCH_SIZE as u64);
+        let token = CancellationToken::new();
+        let consumed = core::cell::Cell::new(0);
+        let input = trace.iter().map(|record| {
+            consumed.set(consumed.get() + 1);
+            if consumed.get() == 10 {
+                token.cancel();
+            }
+            *record
+        });
+        assert!(matches!(
+            PermutationCircuit::<Fp>::new_with_cancellation(input, trace.clone(), &token),
+            Err(Error::Cancelled)
+        ));
+        assert_eq!(consumed.get(), WITNESS_BATCH_SIZE + 1);
+    }
+
+    #[test]
+    fn test_prove_with_timeout() {
+        let trace_size = 50;
+        let k = circuit_size_k(trace_size as usize);
+        let mut rng = rand::thread_rng();
+        let mut trace_buffer = random_trace::<B256, B256, 32, 32>(trace_size);
+        let input_trace = trace_buffer.clone();
+        trace_buffer.shuffle(&mut rng);
+        let circuit = PermutationCircuit::<Fp>::new(input_trace, trace_buffer);
+
+        // Key generation is aborted before it starts
+        let token = CancellationToken::new();
+        token.cancel();
+        assert!(matches!(
+            PermutationProver::<EqAffine>::new_with_cancellation(k, circuit.clone(), true, &token),
+            Err(Error::Cancelled)
+        ));
+
+        // An exhausted time budget aborts the proving
+        let mut ipa_prover = PermutationProver::<EqAffine>::new_with_cancellation(
+            k,
+            circuit,
+            true,
+            &CancellationToken::new(),
+        )
+        .expect("Key generation must not be cancelled");
+        assert!(matches!(
+            ipa_prover.prove_with_timeout(Duration::ZERO),
+            Err(Error::Cancelled)
+        ));
+
+        // The prover is left in a usable state after the cancellation
+        let proof = ipa_prover
+            .prove_with_timeout(Duration::from_secs(3600))
+            .expect("Proving must finish within the time budget");
+        assert!(ipa_prover.verify(proof));
+    }
+
+    #[test]
+    fn test_commitment_and_witness_share_the_trace() {
+        use crate::{
+            commitment::kzg::KZGMemoryCommitment,
+            config::DefaultConfig,
+            constraints::helper::sort_trace,
+            machine::{
+                tests::StateMachine, AbstractMachine, AbstractMemoryMachine, AbstractStackMachine,
+            },
+        };
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let (memory_lo, _) = sm.get_memory_address();
+        for i in 0..20u64 {
+            sm.write(
+                memory_lo + B256::from(i % 4) * B256::WORD_SIZE,
+                B256::from(i),
+            )
+            .unwrap();
+            sm.push(B256::from(i)).unwrap();
+        }
+        assert_eq!(sm.trace_len(), 40);
+
+        // Both consumers borrow the trace of the machine
+        let mut kzg_scheme = KZGMemoryCommitment::<B256, B256, 32, 32>::setup_with_seed(3, [7; 32]);
+        let commitments = kzg_scheme
+            .commit_trace_with_cancellation(sm.trace_iter().copied(), &CancellationToken::new())
+            .expect("Commitment must not be cancelled");
+        assert_eq!(commitments.len(), sm.trace_len());
+
+        let k = circuit_size_k(sm.trace_len());
+        let circuit = PermutationCircuit::<Fp>::new(
+            sm.trace_iter().copied(),
+            sort_trace::<B256, B256, 32, 32>(sm.trace_iter().copied()),
+        );
+        let mut ipa_prover = PermutationProver::<EqAffine>::new(k, circuit, true);
+        let proof = ipa_prover.create_proof();
+        assert!(ipa_prover.verify(proof));
+
+        // The trace is moved out once the machine is no longer needed
+        let trace = sm.into_trace();
+        assert_eq!(trace.len(), commitments.len());
+        let proof = kzg_scheme.prove_trace_record(trace[7], commitments[7]);
+        assert_eq!(
+            kzg_scheme.verify_trace_record(trace[7], commitments[7], proof),
+            Ok(())
+        );
+    }
 }
@@ -1,10 +1,13 @@
 //! Circuit for checking the constraints of the sorted memory trace record
 extern crate alloc;
-use crate::constraints::{
-    common::CircuitExtension,
-    gadgets::{
-        ConvertedTraceRecord, GreaterThanConfig, IsZeroConfig, LookUpTables, Queries, Table,
-        TraceRecordWitnessTable,
+use crate::{
+    config::ReadPolicy,
+    constraints::{
+        common::CircuitExtension,
+        gadgets::{
+            ConvertedTraceRecord, GreaterThanConfig, IsZeroConfig, LookUpTables, Queries, Table,
+            TraceRecordWitnessTable, INSTRUCTION_CODES,
+        },
     },
 };
 use alloc::{format, vec, vec::Vec};
@@ -30,16 +33,20 @@ pub(crate) struct SortedMemoryConfig<F: Field + PrimeField> {
     /// The selectors
     pub(crate) selector: Column<Fixed>,
     pub(crate) selector_zero: Selector,
+    /// One if a cell must be written before it is read, zero if it reads as zero
+    pub(crate) read_policy: Column<Fixed>,
     /// The lookup table
     pub(crate) lookup_tables: LookUpTables,
     /// Just the phantom data
     pub(crate) _marker: PhantomData<F>,
 }
 // Current constraints in this configure:
-// 1) instruction[0]=1
+// 1) (instruction[0]-1)*(policy+(1-policy)*val[0])=0
 // 2) address[i+1]||time[i+1]>address[i]||time[i]
 // 3) (addr[i+1]-addr[i])*(instruction[i+1]-1)*(val[i+1]-val[i])=0
-// 4) (addr[i+1]-addr[i])*(instruction[i+1]-1)=0
+// 4) (addr[i+1]-addr[i])*(instruction[i+1]-1)*(policy+(1-policy)*val[i+1])=0
+// where policy=1 if a cell must be written before it is read and val is the sum of
+// the value limbs, so a cell read before any write must hold zero when policy=0
 // There will be more constraints in the config when we support push and pop
 impl<F: Field + PrimeField> SortedMemoryConfig<F> {
     /// Configuration for the circuit
@@ -53,6 +60,7 @@ impl<F: Field + PrimeField> SortedMemoryConfig<F> {
 
         let selector = meta.fixed_column();
         let selector_zero = meta.selector();
+        let read_policy = meta.fixed_column();
         let addr_cur_prev = IsZeroConfig::<F>::configure(meta, selector);
 
         // addr[i+1]>addr[i] OR addr[i+1]=addr[i] and time[i+1]>time[i]
@@ -64,11 +72,13 @@ impl<F: Field + PrimeField> SortedMemoryConfig<F> {
             selector,
             true,
         );
-        // instruction[0]=1
+        // (instruction[0]-1)*(policy+(1-policy)*val[0])=0
         meta.create_gate("instruction of the first access must be write", |meta| {
             let cur = Queries::new(meta, trace_record, Rotation::cur());
             let selector_zero = meta.query_selector(selector_zero);
-            vec![selector_zero * (cur.instruction - one.clone())]
+            let policy = meta.query_fixed(read_policy, Rotation::cur());
+            let uninit = policy.clone() + (one.clone() - policy) * limbs_sum(cur.value);
+            vec![selector_zero * (cur.instruction - one.clone()) * uninit]
         });
 
         // (addr[i+1]-addr[i])*(instruction[i+1]-1)*(val[i+1]-val[i])=0
@@ -85,25 +95,27 @@ impl<F: Field + PrimeField> SortedMemoryConfig<F> {
             selector.clone()*should_be_zero_2]
         });
 
-        // (addr[i+1]-addr[i])*(instruction[i+1]-1)=0
+        // (addr[i+1]-addr[i])*(instruction[i+1]-1)*(policy+(1-policy)*val[i+1])=0
         meta.create_gate(
             "the first time an address is accessed, it instruction must be write",
             |meta| {
                 let selector = meta.query_fixed(selector, Rotation::cur());
+                let policy = meta.query_fixed(read_policy, Rotation::cur());
                 let cur = Queries::new(meta, trace_record, Rotation::cur());
                 let prev = Queries::new(meta, trace_record, Rotation::prev());
                 let addr_diff =
                     limbs_to_expression(cur.address) - limbs_to_expression(prev.address);
-                vec![selector * (cur.instruction - one.clone()) * addr_diff.clone()]
+                let uninit = policy.clone() + (one.clone() - policy) * limbs_sum(cur.value);
+                vec![selector * (cur.instruction - one.clone()) * addr_diff.clone() * uninit]
             },
         );
 
-        // instruction[i] is in [0,1] for all i
-        lookup_tables
-            .size2_table
-            .range_check(meta, "instruction must be in 0..1", |meta| {
-                meta.query_advice(trace_record.instruction, Rotation::cur())
-            });
+        // instruction[i] is an instruction code for all i
+        lookup_tables.instruction_table.range_check(
+            meta,
+            "instruction must be a known code",
+            |meta| meta.query_advice(trace_record.instruction, Rotation::cur()),
+        );
 
         // Each limb of address and value must be in [0..256]
         for (addr, val) in trace_record.address.iter().zip(&trace_record.value) {
@@ -135,6 +147,7 @@ impl<F: Field + PrimeField> SortedMemoryConfig<F> {
             greater_than,
             selector,
             selector_zero,
+            read_policy,
             lookup_tables,
             _marker: PhantomData,
         }
@@ -149,14 +162,36 @@ fn limbs_to_expression<F: Field + PrimeField>(limb: [Expression<F>; 32]) -> Expr
     sum
 }
 
+// Sum of the limbs, the limbs are in [0..256] so it is zero iff every limb is zero
+fn limbs_sum<F: Field + PrimeField>(limb: [Expression<F>; 32]) -> Expression<F> {
+    limb.iter()
+        .fold(Expression::Constant(F::ZERO), |sum, t| sum + t.clone())
+}
+
 /// Circuit for sorted trace record
 #[derive(Default)]
 pub(crate) struct SortedMemoryCircuit<F: PrimeField> {
     /// The sorted memory trace record
     pub(crate) sorted_trace_record: Vec<ConvertedTraceRecord<F>>,
+    /// The value of the cells read before any write
+    pub(crate) read_policy: ReadPolicy,
     pub(crate) _marker: PhantomData<F>,
 }
 
+impl<F: PrimeField> SortedMemoryCircuit<F> {
+    /// Create the circuit of the trace sorted by address then time log
+    pub(crate) fn new(
+        sorted_trace_record: Vec<ConvertedTraceRecord<F>>,
+        read_policy: ReadPolicy,
+    ) -> Self {
+        Self {
+            sorted_trace_record,
+            read_policy,
+            _marker: PhantomData,
+        }
+    }
+}
+
 /// Implement the CircuitExtension trait for the SortedMemoryCircuit
 impl<F: Field + PrimeField> CircuitExtension<F> for SortedMemoryCircuit<F> {
     fn synthesize_with_layouter(
@@ -172,7 +207,7 @@ impl<F: Field + PrimeField> CircuitExtension<F> for SortedMemoryCircuit<F> {
                 }
                 config.lookup_tables.size40_table.load(&mut region)?;
                 config.lookup_tables.size256_table.load(&mut region)?;
-                config.lookup_tables.size2_table.load(&mut region)?;
+                config.lookup_tables.instruction_table.load(&mut region)?;
                 Ok(())
             },
         )?;
@@ -198,7 +233,7 @@ impl<F: Field + PrimeField> Circuit<F> for SortedMemoryCircuit<F> {
         let lookup_tables = LookUpTables {
             size256_table: Table::<256>::construct(meta),
             size40_table: Table::<40>::construct(meta),
-            size2_table: Table::<2>::construct(meta),
+            instruction_table: Table::<INSTRUCTION_CODES>::construct(meta),
         };
         // The random challenges
         // For debugging of testing, we let alpha to be uniformly distributed
@@ -233,6 +268,17 @@ impl<F: Field + PrimeField> SortedMemoryCircuit<F> {
         config: SortedMemoryConfig<F>,
         offset: usize,
     ) -> Result<(), Error> {
+        // Assign the read policy at the current row
+        let policy = match self.read_policy {
+            ReadPolicy::ErrorOnUninit => F::ONE,
+            ReadPolicy::ZeroOnUninit => F::ZERO,
+        };
+        region.assign_fixed(
+            || format!("read policy{}", offset),
+            config.read_policy,
+            offset,
+            || Value::known(policy),
+        )?;
         // Handle the case offset=0
         if offset == 0 {
             let (cur_address, cur_time_log, cur_instruction, cur_value) =
@@ -431,19 +477,29 @@ impl<F: Field + PrimeField> SortedMemoryCircuit<F> {
 
 #[cfg(test)]
 mod test {
-    use crate::constraints::sorted_memory_circuit::{ConvertedTraceRecord, SortedMemoryCircuit};
+    use crate::{
+        config::ReadPolicy,
+        constraints::{
+            gadgets::{InstructionCode, INSTRUCTION_CODES},
+            sorted_memory_circuit::{ConvertedTraceRecord, SortedMemoryCircuit},
+        },
+    };
     use halo2_proofs::dev::MockProver;
     use halo2curves::bn256::Fr as Fp;
     extern crate alloc;
     extern crate std;
     use alloc::{vec, vec::Vec};
-    use std::marker::PhantomData;
     // Common test function to build and the the SortedMemoryCircuit
     fn build_and_test_circuit(trace: Vec<ConvertedTraceRecord<Fp>>, k: u32) {
-        let circuit = SortedMemoryCircuit::<Fp> {
-            sorted_trace_record: trace,
-            _marker: PhantomData,
-        };
+        build_and_test_circuit_with_policy(trace, k, ReadPolicy::ErrorOnUninit);
+    }
+
+    fn build_and_test_circuit_with_policy(
+        trace: Vec<ConvertedTraceRecord<Fp>>,
+        k: u32,
+        read_policy: ReadPolicy,
+    ) {
+        let circuit = SortedMemoryCircuit::<Fp>::new(trace, read_policy);
 
         let prover = MockProver::run(k, &circuit, vec![]).expect("Cannot run the circuit");
         assert_eq!(prover.verify(), Ok(()));
@@ -608,4 +664,57 @@ mod test {
         };
         build_and_test_circuit(vec![trace0, trace1, trace2], 10);
     }
+
+    #[test]
+    #[should_panic]
+    fn test_unknown_instruction_code() {
+        let trace0 = ConvertedTraceRecord {
+            address: [Fp::from(0); 32],
+            time_log: [Fp::from(0); 8],
+            instruction: InstructionCode::Write.to_field(),
+            value: [Fp::from(63); 32],
+        };
+        let mut time_log = [Fp::from(0); 8];
+        time_log[7] = Fp::from(1);
+        let mut trace1 = ConvertedTraceRecord {
+            address: [Fp::from(0); 32],
+            time_log,
+            instruction: InstructionCode::Read.to_field(),
+            value: [Fp::from(63); 32],
+        };
+        build_and_test_circuit(vec![trace0.clone(), trace1.clone()], 10);
+
+        // The first code after the known ones is rejected by the lookup
+        trace1.instruction = Fp::from(INSTRUCTION_CODES as u64);
+        assert_eq!(InstructionCode::try_from_field(trace1.instruction), None);
+        build_and_test_circuit(vec![trace0, trace1], 10);
+    }
+
+    // Cells 0 and 2 are read before any write and hold the value
+    fn uninitialized_reads(value: u64) -> Vec<ConvertedTraceRecord<Fp>> {
+        let record = |address: u64, instruction: u64, value: u64| ConvertedTraceRecord {
+            address: [Fp::from(address); 32],
+            time_log: [Fp::from(address); 8],
+            instruction: Fp::from(instruction),
+            value: [Fp::from(value); 32],
+        };
+        vec![record(0, 0, value), record(1, 1, 63), record(2, 0, value)]
+    }
+
+    #[test]
+    fn test_zero_uninitialized_read() {
+        build_and_test_circuit_with_policy(uninitialized_reads(0), 10, ReadPolicy::ZeroOnUninit);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_non_zero_uninitialized_read() {
+        build_and_test_circuit_with_policy(uninitialized_reads(5), 10, ReadPolicy::ZeroOnUninit);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_error_on_uninitialized_read() {
+        build_and_test_circuit_with_policy(uninitialized_reads(0), 10, ReadPolicy::ErrorOnUninit);
+    }
 }
@@ -1,3 +1,22 @@
+use crate::{
+    config::{Permission, RegionKind, ResourceKind, TraceMode},
+    machine::MemoryInstruction,
+};
+
+/// Fields of a trace record carried by an [Error],
+/// the address and the value are big endian and padded to 32 bytes
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RecordSummary {
+    /// The stack depth of the record
+    pub stack_depth: u64,
+    /// The instruction of the record
+    pub instruction: MemoryInstruction,
+    /// The address of the record
+    pub addr: [u8; 32],
+    /// The value of the record
+    pub value: [u8; 32],
+}
+
 /// State Machine error
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Error {
@@ -11,10 +30,422 @@ pub enum Error {
     RegisterUnableToWrite,
     /// Register unable to assign
     RegisterUnableToAssign,
+    /// Register index is out of the register section
+    RegisterOutOfRange,
     /// Stack overflow
     StackOverflow,
     /// Stack underflow
     StackUnderflow,
+    /// The trace is not well formed, see [crate::machine::validate::validate_trace]
+    MalformedTrace,
+    /// The trace is not strictly increasing by time log
+    TraceNotSortedByTime,
+    /// The trace is not strictly increasing by address then time log
+    TraceNotSortedByAddressTime,
+    /// Storage section has no free slot left
+    StorageExhausted,
+    /// The time log would overflow, the execution must be split
+    TimeLogExhausted,
+    /// The task was cancelled or ran out of time
+    Cancelled,
+    /// The value does not fit in the target type
+    ValueOutOfRange,
+    /// The binary encoding of the trace is malformed
+    InvalidTraceEncoding,
+    /// The size string is not in a supported notation
+    InvalidSizeFormat,
+    /// The encoded trace does not start with the trace magic bytes
+    InvalidTraceMagic,
+    /// The major version of the encoded trace is not supported
+    UnsupportedTraceVersion,
+    /// The word sizes of the encoded trace differ from the expected ones
+    TraceWordSizeMismatch,
+    /// The encoded trace is shorter than its header claims
+    TruncatedTrace,
+    /// The ELF image is truncated or its headers are malformed
+    InvalidElf,
+    /// The ELF class is unknown or its addresses do not fit in the address word
+    UnsupportedElfClass {
+        /// The class byte of the ELF identification, 1 for 32 bits and 2 for 64 bits
+        class: u8,
+    },
+    /// The ELF image is not little endian
+    UnsupportedElfEndianness {
+        /// The data encoding byte of the ELF identification, 2 for big endian
+        data: u8,
+    },
+    /// A pointer was moved below the start of the address space,
+    /// both values are big endian and padded to 32 bytes
+    PointerUnderflow {
+        /// The pointer before the update
+        pointer: [u8; 32],
+        /// The delta that was subtracted
+        attempted_delta: [u8; 32],
+    },
+    /// A pointer was moved above the end of the address space,
+    /// both values are big endian and padded to 32 bytes
+    PointerOverflow {
+        /// The pointer before the update
+        pointer: [u8; 32],
+        /// The delta that was added
+        attempted_delta: [u8; 32],
+    },
+    /// The address is not a multiple of the word size and the alignment policy is strict,
+    /// the address is big endian and padded to 32 bytes
+    UnalignedAccess {
+        /// The accessed address
+        addr: [u8; 32],
+        /// The size of the accessed cell in bytes
+        word_size: usize,
+    },
+    /// The accessed cell is not inside a region of the memory layout,
+    /// the address is big endian and padded to 32 bytes
+    AccessOutsideRegion {
+        /// The accessed address
+        addr: [u8; 32],
+        /// The closest region below the address, [None] if the address is below every region
+        region: Option<RegionKind>,
+    },
+    /// Two regions of the memory layout overlap
+    OverlappingRegions {
+        /// The region that was added first
+        first: RegionKind,
+        /// The region that overlaps it
+        second: RegionKind,
+    },
+    /// A region of the memory layout is empty, duplicated or does not fit in the address space
+    InvalidRegion {
+        /// The invalid region
+        region: RegionKind,
+    },
+    /// The region of the memory layout does not grant the access
+    PermissionDenied {
+        /// The accessed address
+        addr: [u8; 32],
+        /// The region that contains the address
+        region: RegionKind,
+        /// The requested access
+        access: Permission,
+    },
+    /// A line of a JSON Lines trace is not a valid trace record
+    InvalidTraceLine {
+        /// The line number, starting from 1
+        line: usize,
+    },
+    /// The time log of a trace record is not greater than the one of the previous record
+    NonMonotoneTimeLog {
+        /// The line number, starting from 1
+        line: usize,
+    },
+    /// A cell written by a fork or by its parent since the fork point was accessed by the
+    /// other one, the address is big endian and padded to 32 bytes
+    ForkConflict {
+        /// The address of the cell
+        addr: [u8; 32],
+    },
+    /// The most significant byte of an address is already used, so the address cannot be
+    /// tagged, the address is big endian and padded to 32 bytes
+    AddressTagOverflow {
+        /// The address before tagging
+        addr: [u8; 32],
+    },
+    /// Two traces to merge have the same address tag
+    DuplicateAddressTag {
+        /// The duplicated tag
+        tag: u8,
+    },
+    /// The word size is not a positive multiple of 8 bits that fits in the address word
+    InvalidWordSize {
+        /// The requested word size in bits
+        bits: usize,
+    },
+    /// The machines only keep their memory in a sparse map
+    DenseStorageUnsupported,
+    /// The stack, register and memory sections do not fit in the address space
+    SectionsOverflow,
+    /// The stack section is larger than the memory section
+    StackLargerThanMemory,
+    /// The memory section is empty
+    EmptyMemorySection,
+    /// The stack depth is zero
+    EmptyStack,
+    /// The stack and its guard cells do not fit in the address space from their base
+    StackOutOfAddressSpace,
+    /// The base or the size of a section is not a multiple of the word size
+    MisalignedSection {
+        /// The misaligned section
+        region: RegionKind,
+    },
+    /// The config file is not valid TOML or has a key that is not a config argument
+    InvalidConfigFile,
+    /// The word size of a region does not divide the word size of the machine
+    InvalidRegionWordSize {
+        /// The region with the invalid word size
+        region: RegionKind,
+    },
+    /// The memory section is larger than its maximum size
+    MemoryLargerThanMax,
+    /// A cell that was never written is read under [ReadPolicy::ErrorOnUninit](crate::config::ReadPolicy::ErrorOnUninit),
+    /// the address is big endian and padded to 32 bytes
+    UninitializedRead {
+        /// The address of the cell
+        addr: [u8; 32],
+    },
+    /// A limb of a converted trace record is not a byte, or its instruction is not an
+    /// [InstructionCode](crate::constraints::InstructionCode).
+    /// The limbs are indexed in the order address, time log, instruction and value
+    InvalidWitnessLimb {
+        /// The index of the limb
+        index: usize,
+    },
+    /// A word of a trace record has more bytes than the witness has limbs for it,
+    /// see [crate::constraints::ConvertedTraceRecord::from_trace_record]
+    WitnessWordTooWide {
+        /// The width of the word in bits
+        bits: usize,
+        /// The number of byte limbs of the witness
+        limbs: usize,
+    },
+    /// The machine does not keep the commitment to an initial memory image
+    InitialImageUnsupported,
+    /// The initial memory image is installed after the first memory access
+    MachineAlreadyStarted,
+    /// The access would use more of the resource than allowed by the
+    /// [ResourceLimits](crate::config::ResourceLimits)
+    ResourceLimit {
+        /// The exhausted resource
+        which: ResourceKind,
+        /// The limit of the resource
+        limit: u64,
+        /// The amount of the resource the access would use
+        attempted: u64,
+    },
+    /// The input or the output of a streamed trace failed
+    TraceIo,
+    /// The trace is needed but the machine pushes its records to a trace sink
+    TraceInSink,
+    /// The accessed word is not inside the memory section,
+    /// both values are big endian and padded to 32 bytes
+    AddressOutOfBounds {
+        /// The accessed address
+        addr: [u8; 32],
+        /// The size of the memory section in bytes
+        memory_size: [u8; 32],
+    },
+    /// A cell of a write-once region is written a second time,
+    /// the address is big endian and padded to 32 bytes
+    WriteOnceViolation {
+        /// The address of the cell
+        addr: [u8; 32],
+        /// The time log of the first write to the cell
+        first_write_time: u64,
+    },
+    /// Two different records of a trace have the same time log
+    ConflictingRecords {
+        /// The shared time log
+        time_log: u64,
+        /// The record that comes first in the record order
+        first: RecordSummary,
+        /// The other record
+        second: RecordSummary,
+    },
+    /// A cell is written by two routines of a scheduler in the same round,
+    /// the address is big endian and padded to 32 bytes
+    ScheduleConflict {
+        /// The address of the cell
+        addr: [u8; 32],
+        /// The round of the writes, starting from 1
+        round: u64,
+    },
+    /// A checkpoint does not match its digest or is malformed
+    InvalidCheckpoint,
+    /// A checkpoint was written by another version of the format
+    UnsupportedCheckpointVersion,
+    /// The access would use more gas than the gas budget of the machine
+    OutOfGas {
+        /// The gas budget of the machine
+        budget: u64,
+        /// The gas used by the machine before the access
+        used: u64,
+        /// The gas of the access
+        cost: u64,
+    },
+    /// The dense commitment of the memory would walk more than
+    /// [DENSE_COMMITMENT_MAX_CELLS](crate::commitment::merkle::DENSE_COMMITMENT_MAX_CELLS) cells
+    DenseCommitmentTooLarge,
+    /// The memory section of the machine cannot grow
+    MemoryNotGrowable,
+    /// The memory section would grow past its maximum size,
+    /// both values are big endian and padded to 32 bytes
+    MemoryGrowthLimit {
+        /// The maximum size of the memory section in bytes
+        max_memory_size: [u8; 32],
+        /// The size in bytes the memory section would grow to
+        attempted: [u8; 32],
+    },
+    /// The access touches a reserved range of the memory layout
+    ReservedAddress {
+        /// The first reserved address touched by the access
+        addr: [u8; 32],
+    },
+    /// A reserved range of the memory layout is empty, overlaps another reserved range or
+    /// exceeds [MAX_RESERVED_RANGES](crate::config::MAX_RESERVED_RANGES)
+    InvalidReservedRange,
+    /// A reserved range overlaps a region of the memory layout
+    ReservedRangeOverlap {
+        /// The overlapped region
+        region: RegionKind,
+    },
+    /// A persisted trace, snapshot or checkpoint was made under another config, see
+    /// [Config::config_fingerprint](crate::config::Config::config_fingerprint)
+    ConfigMismatch {
+        /// Fingerprint of the config it is loaded under
+        expected: [u8; 32],
+        /// Fingerprint written in the persisted data
+        found: [u8; 32],
+    },
+    /// The full trace is needed but the machine does not keep it, see [TraceMode]
+    TraceNotRetained {
+        /// The trace mode of the machine
+        mode: TraceMode,
+    },
+    /// The circuits of a [ProverBudget](crate::config::ProverBudget) are too small to hold
+    /// the lookup tables
+    ProverBudgetTooSmall {
+        /// The largest k of the budget
+        max_k: u32,
+        /// The smallest k of a circuit
+        min_k: u32,
+    },
+    /// A commitment is opened at an index past the end of the committed records
+    OpeningOutOfRange {
+        /// The opened index
+        index: usize,
+        /// The number of committed records
+        len: usize,
+    },
+    /// The input or the output of KZG params failed
+    KZGParamsIo,
+    /// Persisted KZG params are malformed or do not match their checksum
+    CorruptedKZGParams,
+    /// The KZG params are too small for the requested polynomials
+    KZGParamsTooSmall {
+        /// The k of the params
+        found: u32,
+        /// The requested k
+        requested: u32,
+    },
+    /// A powers of tau transcript ends before its powers
+    TruncatedTranscript,
+    /// The powers of a powers of tau transcript are not arrays of hex strings
+    MalformedTranscript,
+    /// A power of a powers of tau transcript is not a point of the prime order subgroup
+    TranscriptPointNotInSubgroup {
+        /// The group of the point, 1 or 2
+        group: u8,
+        /// The index of the power
+        index: usize,
+    },
+    /// A Merkle proof ends before its last sibling
+    TruncatedMerkleProof,
+    /// A Merkle proof has an unknown version, an index out of its list or trailing bytes
+    MalformedMerkleProof,
+    /// The number of siblings of a Merkle proof does not fit the index of its leaf
+    MerklePathLengthMismatch {
+        /// The number of siblings of the leaf
+        expected: usize,
+        /// The number of siblings of the proof
+        found: usize,
+    },
+    /// The updated leaf is not a leaf of the Merkle tree
+    LeafOutOfRange {
+        /// The updated index
+        index: usize,
+        /// The number of leaves of the tree
+        len: usize,
+    },
+    /// An opening of a batch does not verify
+    BatchOpeningRejected {
+        /// The position of the first rejected opening in the batch
+        index: usize,
+    },
+    /// A streamed polynomial has more coefficients than the powers of the KZG params
+    KZGStreamTooLong {
+        /// The number of powers of the params
+        capacity: usize,
+    },
+    /// The number of children of the nodes of a Merkle tree is not supported
+    InvalidMerkleArity {
+        /// The requested arity
+        arity: usize,
+    },
+    /// The initial state of a transition does not hash to its initial root
+    InitialStateRootMismatch,
+    /// A record of a transition disagrees with the replay of the trace
+    TransitionReplayMismatch {
+        /// Index of the first disagreeing record in the trace
+        index: usize,
+    },
+    /// The replayed state of a transition does not hash to its final root
+    FinalStateRootMismatch,
+    /// A node of a Merkle tree is not in its node store
+    MissingMerkleNode,
+    /// The input or the output of a node store failed
+    NodeStoreIo,
+    /// A persisted node store is malformed
+    CorruptedNodeStore,
+    /// The exit state of a chunk is not the entry state of the next chunk
+    ChunkBoundaryMismatch {
+        /// Index of the chunk in the trace
+        index: usize,
+    },
+    /// An aggregate proof does not open the claims of its chunks
+    AggregateProofRejected,
+    /// A list of records has more elements than the generators of an IPA commitment
+    IPAVectorTooLong {
+        /// The number of records the generators commit
+        capacity: usize,
+    },
+    /// The commitment backend is not supported by the machines
+    CommitmentUnsupported,
+    /// The words of a machine are wider than the field of its commitment backend
+    CommitmentWordTooWide {
+        /// The width of the words in bits
+        bits: usize,
+    },
+    /// The proving or the verifying key of a circuit could not be generated
+    KeyGenerationFailed,
+}
+
+// Write the fields of a record of an error
+fn write_record(f: &mut core::fmt::Formatter<'_>, record: &RecordSummary) -> core::fmt::Result {
+    write!(f, "{:?} of ", record.instruction)?;
+    write_word(f, &record.value)?;
+    write!(f, " at ")?;
+    write_word(f, &record.addr)?;
+    write!(f, " (depth {})", record.stack_depth)
+}
+
+// Write every byte of a fingerprint in hexadecimal
+fn write_fingerprint(
+    f: &mut core::fmt::Formatter<'_>,
+    fingerprint: &[u8; 32],
+) -> core::fmt::Result {
+    fingerprint
+        .iter()
+        .try_for_each(|byte| write!(f, "{:02x}", byte))
+}
+
+// Write a big endian word as a hexadecimal number without the leading zeros
+pub(crate) fn write_word(f: &mut core::fmt::Formatter<'_>, word: &[u8]) -> core::fmt::Result {
+    let start = word
+        .iter()
+        .position(|byte| *byte != 0)
+        .unwrap_or(word.len() - 1);
+    write!(f, "0x{:x}", word[start])?;
+    word[start + 1..]
+        .iter()
+        .try_for_each(|byte| write!(f, "{:02x}", byte))
 }
 
 #[cfg(feature = "std")]
@@ -28,15 +459,379 @@ impl core::fmt::Display for Error {
             Error::RegisterUnableToRead => write!(f, "Register unable to read"),
             Error::RegisterUnableToWrite => write!(f, "Register unable to write"),
             Error::RegisterUnableToAssign => write!(f, "Register unable to assign"),
+            Error::RegisterOutOfRange => write!(f, "Register out of range"),
             Error::StackOverflow => write!(f, "Stack overflow"),
             Error::StackUnderflow => write!(f, "Stack underflow"),
+            Error::MalformedTrace => write!(f, "Malformed trace"),
+            Error::TraceNotSortedByTime => write!(f, "Trace not sorted by time log"),
+            Error::TraceNotSortedByAddressTime => {
+                write!(f, "Trace not sorted by address and time log")
+            }
+            Error::StorageExhausted => write!(f, "Storage exhausted"),
+            Error::TimeLogExhausted => write!(f, "Time log exhausted"),
+            Error::Cancelled => write!(f, "Task cancelled"),
+            Error::ValueOutOfRange => write!(f, "Value out of range"),
+            Error::InvalidTraceEncoding => write!(f, "Invalid trace encoding"),
+            Error::InvalidSizeFormat => write!(f, "Invalid size format"),
+            Error::InvalidTraceMagic => write!(f, "Invalid trace magic"),
+            Error::UnsupportedTraceVersion => write!(f, "Unsupported trace version"),
+            Error::TraceWordSizeMismatch => write!(f, "Trace word size mismatch"),
+            Error::TruncatedTrace => write!(f, "Truncated trace"),
+            Error::InvalidElf => write!(f, "Invalid ELF image"),
+            Error::UnsupportedElfClass { class } => write!(f, "Unsupported ELF class {}", class),
+            Error::UnsupportedElfEndianness { data } => {
+                write!(f, "Unsupported ELF data encoding {}", data)
+            }
+            Error::PointerUnderflow {
+                pointer,
+                attempted_delta,
+            } => {
+                write!(f, "Pointer underflow: ")?;
+                write_word(f, pointer)?;
+                write!(f, " - ")?;
+                write_word(f, attempted_delta)
+            }
+            Error::PointerOverflow {
+                pointer,
+                attempted_delta,
+            } => {
+                write!(f, "Pointer overflow: ")?;
+                write_word(f, pointer)?;
+                write!(f, " + ")?;
+                write_word(f, attempted_delta)
+            }
+            Error::UnalignedAccess { addr, word_size } => {
+                write!(f, "Unaligned access: ")?;
+                write_word(f, addr)?;
+                write!(f, " is not aligned to {} bytes", word_size)
+            }
+            Error::AccessOutsideRegion { addr, region } => {
+                write!(f, "Access outside of memory regions: ")?;
+                write_word(f, addr)?;
+                match region {
+                    Some(region) => write!(f, " after the {} region", region),
+                    None => write!(f, " below every region"),
+                }
+            }
+            Error::OverlappingRegions { first, second } => {
+                write!(f, "The {} region overlaps the {} region", second, first)
+            }
+            Error::InvalidRegion { region } => write!(f, "Invalid {} region", region),
+            Error::PermissionDenied {
+                addr,
+                region,
+                access,
+            } => {
+                write!(f, "Permission denied: {} access to ", access)?;
+                write_word(f, addr)?;
+                write!(f, " in the {} region", region)
+            }
+            Error::InvalidTraceLine { line } => write!(f, "Invalid trace record at line {}", line),
+            Error::NonMonotoneTimeLog { line } => {
+                write!(f, "Time log is not increasing at line {}", line)
+            }
+            Error::ForkConflict { addr } => {
+                write!(f, "Fork conflict: ")?;
+                write_word(f, addr)?;
+                write!(f, " was accessed by both branches")
+            }
+            Error::AddressTagOverflow { addr } => {
+                write!(f, "Address tag overflow: ")?;
+                write_word(f, addr)?;
+                write!(f, " already uses the tag byte")
+            }
+            Error::DuplicateAddressTag { tag } => write!(f, "Duplicate address tag {}", tag),
+            Error::InvalidWordSize { bits } => write!(f, "Invalid word size of {} bits", bits),
+            Error::DenseStorageUnsupported => write!(f, "Dense storage is not supported"),
+            Error::SectionsOverflow => write!(f, "Sections do not fit in the address space"),
+            Error::StackLargerThanMemory => write!(f, "Stack is larger than memory"),
+            Error::EmptyMemorySection => write!(f, "Empty memory section"),
+            Error::EmptyStack => write!(f, "Empty stack section"),
+            Error::StackOutOfAddressSpace => {
+                write!(f, "Stack does not fit in the address space from its base")
+            }
+            Error::MisalignedSection { region } => write!(
+                f,
+                "Misaligned {} section: not a multiple of the word size",
+                region
+            ),
+            Error::InvalidConfigFile => write!(f, "Invalid config file"),
+            Error::InvalidRegionWordSize { region } => write!(
+                f,
+                "Word size of the {} region does not divide the word size of the machine",
+                region
+            ),
+            Error::MemoryLargerThanMax => write!(f, "Memory is larger than its maximum size"),
+            Error::UninitializedRead { addr } => {
+                write!(f, "Uninitialized read: ")?;
+                write_word(f, addr)?;
+                write!(f, " was never written")
+            }
+            Error::InvalidWitnessLimb { index } => write!(f, "Invalid witness limb {}", index),
+            Error::WitnessWordTooWide { bits, limbs } => write!(
+                f,
+                "Witness word too wide: words of {} bits do not fit in {} byte limbs",
+                bits, limbs
+            ),
+            Error::InitialImageUnsupported => write!(f, "Initial memory image is not supported"),
+            Error::MachineAlreadyStarted => write!(f, "Machine already started"),
+            Error::ResourceLimit {
+                which,
+                limit,
+                attempted,
+            } => write!(
+                f,
+                "Resource limit exceeded: {} of {} over the limit of {}",
+                which, attempted, limit
+            ),
+            Error::TraceIo => write!(f, "Trace input or output failed"),
+            Error::TraceInSink => write!(f, "The trace records are pushed to a trace sink"),
+            Error::AddressOutOfBounds { addr, memory_size } => {
+                write!(f, "Address out of bounds: ")?;
+                write_word(f, addr)?;
+                write!(f, " is outside of the memory of ")?;
+                write_word(f, memory_size)?;
+                write!(f, " bytes")
+            }
+            Error::WriteOnceViolation {
+                addr,
+                first_write_time,
+            } => {
+                write!(f, "Write-once violation: ")?;
+                write_word(f, addr)?;
+                write!(f, " was already written at time {}", first_write_time)
+            }
+            Error::ConflictingRecords {
+                time_log,
+                first,
+                second,
+            } => {
+                write!(f, "Conflicting records at time {}: ", time_log)?;
+                write_record(f, first)?;
+                write!(f, " and ")?;
+                write_record(f, second)
+            }
+            Error::ScheduleConflict { addr, round } => {
+                write!(f, "Schedule conflict: ")?;
+                write_word(f, addr)?;
+                write!(f, " is written by two routines in round {}", round)
+            }
+            Error::InvalidCheckpoint => write!(f, "Invalid checkpoint"),
+            Error::UnsupportedCheckpointVersion => write!(f, "Unsupported checkpoint version"),
+            Error::OutOfGas { budget, used, cost } => write!(
+                f,
+                "Out of gas: an access of {} gas after {} gas over the budget of {}",
+                cost, used, budget
+            ),
+            Error::DenseCommitmentTooLarge => write!(f, "Dense commitment too large"),
+            Error::MemoryNotGrowable => write!(f, "Memory is not growable"),
+            Error::MemoryGrowthLimit {
+                max_memory_size,
+                attempted,
+            } => {
+                write!(f, "Memory growth limit: ")?;
+                write_word(f, attempted)?;
+                write!(f, " bytes over the maximum of ")?;
+                write_word(f, max_memory_size)?;
+                write!(f, " bytes")
+            }
+            Error::ReservedAddress { addr } => {
+                write!(f, "Access to a reserved address: ")?;
+                write_word(f, addr)
+            }
+            Error::InvalidReservedRange => write!(f, "Invalid reserved range"),
+            Error::ReservedRangeOverlap { region } => {
+                write!(f, "A reserved range overlaps the {} region", region)
+            }
+            Error::ConfigMismatch { expected, found } => {
+                write!(f, "Config mismatch: expected fingerprint ")?;
+                write_fingerprint(f, expected)?;
+                write!(f, ", found ")?;
+                write_fingerprint(f, found)
+            }
+            Error::TraceNotRetained { mode } => {
+                write!(f, "The trace is not retained in the {} trace mode", mode)
+            }
+            Error::ProverBudgetTooSmall { max_k, min_k } => write!(
+                f,
+                "Prover budget too small: circuits of 2^{} rows, the smallest is 2^{}",
+                max_k, min_k
+            ),
+            Error::OpeningOutOfRange { index, len } => {
+                write!(
+                    f,
+                    "Opening out of range: record {} of {} records",
+                    index, len
+                )
+            }
+            Error::KZGParamsIo => write!(f, "KZG params input or output failed"),
+            Error::CorruptedKZGParams => write!(f, "Corrupted KZG params"),
+            Error::KZGParamsTooSmall { found, requested } => write!(
+                f,
+                "KZG params too small: k is {} but {} is requested",
+                found, requested
+            ),
+            Error::TruncatedTranscript => write!(f, "Truncated powers of tau transcript"),
+            Error::MalformedTranscript => write!(f, "Malformed powers of tau transcript"),
+            Error::TranscriptPointNotInSubgroup { group, index } => write!(
+                f,
+                "Power {} in G{} of the transcript is not in the subgroup",
+                index, group
+            ),
+            Error::TruncatedMerkleProof => write!(f, "Truncated Merkle proof"),
+            Error::MalformedMerkleProof => write!(f, "Malformed Merkle proof"),
+            Error::MerklePathLengthMismatch { expected, found } => write!(
+                f,
+                "Merkle path length mismatch: expected {} siblings, found {}",
+                expected, found
+            ),
+            Error::LeafOutOfRange { index, len } => {
+                write!(f, "Leaf out of range: leaf {} of {} leaves", index, len)
+            }
+            Error::BatchOpeningRejected { index } => {
+                write!(f, "Batch opening rejected: opening {} of the batch", index)
+            }
+            Error::KZGStreamTooLong { capacity } => write!(
+                f,
+                "KZG stream too long: the params commit at most {} coefficients",
+                capacity
+            ),
+            Error::InvalidMerkleArity { arity } => {
+                write!(f, "Invalid Merkle arity: {} children per node", arity)
+            }
+            Error::InitialStateRootMismatch => write!(
+                f,
+                "Initial state root mismatch: the initial state does not hash to the root"
+            ),
+            Error::TransitionReplayMismatch { index } => write!(
+                f,
+                "Transition replay mismatch: record {} disagrees with the replay",
+                index
+            ),
+            Error::FinalStateRootMismatch => write!(
+                f,
+                "Final state root mismatch: the replayed state does not hash to the root"
+            ),
+            Error::MissingMerkleNode => write!(f, "Missing Merkle node in the node store"),
+            Error::NodeStoreIo => write!(f, "Node store input or output failed"),
+            Error::CorruptedNodeStore => write!(f, "Corrupted node store"),
+            Error::ChunkBoundaryMismatch { index } => write!(
+                f,
+                "Chunk boundary mismatch: the exit root of chunk {} is not the entry root of \
+                 the next chunk",
+                index
+            ),
+            Error::AggregateProofRejected => write!(f, "Aggregate proof rejected"),
+            Error::IPAVectorTooLong { capacity } => write!(
+                f,
+                "IPA vector too long: the generators commit at most {} records",
+                capacity
+            ),
+            Error::CommitmentUnsupported => write!(f, "Commitment backend unsupported"),
+            Error::CommitmentWordTooWide { bits } => write!(
+                f,
+                "Commitment word too wide: words of {} bits do not fit the field of the backend",
+                bits
+            ),
+            Error::KeyGenerationFailed => write!(f, "Key generation failed"),
+        }
+    }
+}
+
+/// Reason a commitment verifier rejects an opening or a proof
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum VerifyError {
+    /// The proof hashes to another root than the expected one
+    RootMismatch {
+        /// The root the proof is checked against
+        expected: [u8; 32],
+        /// The root computed from the proof
+        computed: [u8; 32],
+    },
+    /// The proof is for another leaf than the expected one
+    LeafMismatch {
+        /// The leaf the proof is checked against
+        expected: [u8; 32],
+        /// The leaf of the proof
+        found: [u8; 32],
+    },
+    /// The proof does not have one sibling per level, one round per halving or one path per
+    /// covering subtree
+    PathLengthMismatch {
+        /// The number of siblings, rounds or paths the proof must have
+        expected: usize,
+        /// The number of siblings, rounds or paths of the proof
+        found: usize,
+    },
+    /// The opened index is not the index of a committed record
+    IndexOutOfRange {
+        /// The opened index
+        index: usize,
+        /// The number of committed records
+        len: usize,
+    },
+    /// The hash, the arity or the params of the proof are not the ones of the verifier
+    ParameterMismatch,
+    /// The proof opens other evaluations or other commitments than the claimed ones
+    EvaluationMismatch,
+    /// The pairing check of a KZG opening fails
+    PairingCheckFailed,
+    /// The final check of an inner product argument fails
+    InnerProductCheckFailed,
+    /// An inclusion proof is for the zero value, which is proven absent instead
+    ZeroValue,
+    /// The first address of a range is above its last address
+    InvalidRange,
+    /// The proof cannot be decoded or its parts do not fit together
+    DeserializeError,
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for VerifyError {}
+
+impl core::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            VerifyError::RootMismatch { expected, computed } => {
+                write!(f, "Root mismatch: expected ")?;
+                write_fingerprint(f, expected)?;
+                write!(f, ", computed ")?;
+                write_fingerprint(f, computed)
+            }
+            VerifyError::LeafMismatch { expected, found } => {
+                write!(f, "Leaf mismatch: expected ")?;
+                write_fingerprint(f, expected)?;
+                write!(f, ", found ")?;
+                write_fingerprint(f, found)
+            }
+            VerifyError::PathLengthMismatch { expected, found } => write!(
+                f,
+                "Path length mismatch: expected {}, found {}",
+                expected, found
+            ),
+            VerifyError::IndexOutOfRange { index, len } => write!(
+                f,
+                "Index out of range: index {} of {} committed records",
+                index, len
+            ),
+            VerifyError::ParameterMismatch => write!(f, "Parameter mismatch"),
+            VerifyError::EvaluationMismatch => write!(f, "Evaluation mismatch"),
+            VerifyError::PairingCheckFailed => write!(f, "Pairing check failed"),
+            VerifyError::InnerProductCheckFailed => write!(f, "Inner product check failed"),
+            VerifyError::ZeroValue => write!(f, "Zero value: a zero value is proven absent"),
+            VerifyError::InvalidRange => write!(f, "Invalid range: the range is inverted"),
+            VerifyError::DeserializeError => write!(f, "Malformed proof"),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::error::Error;
+    use crate::{
+        config::{Permission, RegionKind, ResourceKind, TraceMode},
+        error::{Error, RecordSummary, VerifyError},
+        machine::MemoryInstruction,
+    };
     extern crate alloc;
 
     use alloc::format;
@@ -63,7 +858,519 @@ mod tests {
             format!("{}", Error::RegisterUnableToAssign),
             "Register unable to assign"
         );
+        assert_eq!(
+            format!("{}", Error::RegisterOutOfRange),
+            "Register out of range"
+        );
         assert_eq!(format!("{}", Error::StackOverflow), "Stack overflow");
         assert_eq!(format!("{}", Error::StackUnderflow), "Stack underflow");
+        assert_eq!(format!("{}", Error::MalformedTrace), "Malformed trace");
+        assert_eq!(
+            format!("{}", Error::TraceNotSortedByTime),
+            "Trace not sorted by time log"
+        );
+        assert_eq!(
+            format!("{}", Error::TraceNotSortedByAddressTime),
+            "Trace not sorted by address and time log"
+        );
+        assert_eq!(format!("{}", Error::StorageExhausted), "Storage exhausted");
+        assert_eq!(format!("{}", Error::TimeLogExhausted), "Time log exhausted");
+        assert_eq!(format!("{}", Error::Cancelled), "Task cancelled");
+        assert_eq!(format!("{}", Error::ValueOutOfRange), "Value out of range");
+        assert_eq!(
+            format!("{}", Error::InvalidTraceEncoding),
+            "Invalid trace encoding"
+        );
+        assert_eq!(
+            format!("{}", Error::InvalidSizeFormat),
+            "Invalid size format"
+        );
+        assert_eq!(
+            format!("{}", Error::InvalidTraceMagic),
+            "Invalid trace magic"
+        );
+        assert_eq!(
+            format!("{}", Error::UnsupportedTraceVersion),
+            "Unsupported trace version"
+        );
+        assert_eq!(
+            format!("{}", Error::TraceWordSizeMismatch),
+            "Trace word size mismatch"
+        );
+        assert_eq!(format!("{}", Error::TruncatedTrace), "Truncated trace");
+        assert_eq!(
+            format!("{}", Error::InvalidCheckpoint),
+            "Invalid checkpoint"
+        );
+        let mut pointer = [0u8; 32];
+        pointer[31] = 0x10;
+        let mut attempted_delta = [0u8; 32];
+        attempted_delta[30] = 0x01;
+        assert_eq!(
+            format!(
+                "{}",
+                Error::PointerUnderflow {
+                    pointer,
+                    attempted_delta
+                }
+            ),
+            "Pointer underflow: 0x10 - 0x100"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::PointerOverflow {
+                    pointer: [0xff; 32],
+                    attempted_delta: [0; 32]
+                }
+            ),
+            format!("Pointer overflow: 0x{} + 0x0", "ff".repeat(32))
+        );
+        let mut addr = [0u8; 32];
+        addr[31] = 0x1f;
+        assert_eq!(
+            format!(
+                "{}",
+                Error::UnalignedAccess {
+                    addr,
+                    word_size: 32
+                }
+            ),
+            "Unaligned access: 0x1f is not aligned to 32 bytes"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::AccessOutsideRegion {
+                    addr,
+                    region: Some(RegionKind::Heap)
+                }
+            ),
+            "Access outside of memory regions: 0x1f after the heap region"
+        );
+        assert_eq!(
+            format!("{}", Error::AccessOutsideRegion { addr, region: None }),
+            "Access outside of memory regions: 0x1f below every region"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::OverlappingRegions {
+                    first: RegionKind::Code,
+                    second: RegionKind::Io
+                }
+            ),
+            "The io region overlaps the code region"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::InvalidRegion {
+                    region: RegionKind::Stack
+                }
+            ),
+            "Invalid stack region"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::PermissionDenied {
+                    addr,
+                    region: RegionKind::Code,
+                    access: Permission::Write
+                }
+            ),
+            "Permission denied: write access to 0x1f in the code region"
+        );
+        assert_eq!(
+            format!("{}", Error::InvalidTraceLine { line: 3 }),
+            "Invalid trace record at line 3"
+        );
+        assert_eq!(
+            format!("{}", Error::NonMonotoneTimeLog { line: 7 }),
+            "Time log is not increasing at line 7"
+        );
+        assert_eq!(
+            format!("{}", Error::UninitializedRead { addr }),
+            "Uninitialized read: 0x1f was never written"
+        );
+        assert_eq!(
+            format!("{}", Error::InvalidWitnessLimb { index: 40 }),
+            "Invalid witness limb 40"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::WitnessWordTooWide {
+                    bits: 512,
+                    limbs: 32
+                }
+            ),
+            "Witness word too wide: words of 512 bits do not fit in 32 byte limbs"
+        );
+        assert_eq!(
+            format!("{}", Error::MachineAlreadyStarted),
+            "Machine already started"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::ResourceLimit {
+                    which: ResourceKind::BytesWritten,
+                    limit: 64,
+                    attempted: 96
+                }
+            ),
+            "Resource limit exceeded: bytes written of 96 over the limit of 64"
+        );
+        assert_eq!(
+            format!("{}", Error::TraceInSink),
+            "The trace records are pushed to a trace sink"
+        );
+        let mut memory_size = [0u8; 32];
+        memory_size[30] = 0x10;
+        assert_eq!(
+            format!("{}", Error::AddressOutOfBounds { addr, memory_size }),
+            "Address out of bounds: 0x1f is outside of the memory of 0x1000 bytes"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::WriteOnceViolation {
+                    addr,
+                    first_write_time: 7
+                }
+            ),
+            "Write-once violation: 0x1f was already written at time 7"
+        );
+        let record = |instruction, value: u8| RecordSummary {
+            stack_depth: 0,
+            instruction,
+            addr,
+            value: [value; 32],
+        };
+        assert_eq!(
+            format!(
+                "{}",
+                Error::ConflictingRecords {
+                    time_log: 3,
+                    first: record(MemoryInstruction::Write, 0),
+                    second: record(MemoryInstruction::Read, 1),
+                }
+            ),
+            "Conflicting records at time 3: Write of 0x0 at 0x1f (depth 0) and \
+             Read of 0x101010101010101010101010101010101010101010101010101010101010101 at 0x1f \
+             (depth 0)"
+        );
+        assert_eq!(
+            format!("{}", Error::ScheduleConflict { addr, round: 2 }),
+            "Schedule conflict: 0x1f is written by two routines in round 2"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::OutOfGas {
+                    budget: 10,
+                    used: 8,
+                    cost: 3
+                }
+            ),
+            "Out of gas: an access of 3 gas after 8 gas over the budget of 10"
+        );
+        assert_eq!(
+            format!("{}", Error::DenseCommitmentTooLarge),
+            "Dense commitment too large"
+        );
+        assert_eq!(format!("{}", Error::EmptyStack), "Empty stack section");
+        assert_eq!(
+            format!("{}", Error::StackOutOfAddressSpace),
+            "Stack does not fit in the address space from its base"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::MisalignedSection {
+                    region: RegionKind::Register
+                }
+            ),
+            "Misaligned register section: not a multiple of the word size"
+        );
+        assert_eq!(
+            format!("{}", Error::InvalidConfigFile),
+            "Invalid config file"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::InvalidRegionWordSize {
+                    region: RegionKind::Io
+                }
+            ),
+            "Word size of the io region does not divide the word size of the machine"
+        );
+        assert_eq!(
+            format!("{}", Error::MemoryLargerThanMax),
+            "Memory is larger than its maximum size"
+        );
+        assert_eq!(
+            format!("{}", Error::MemoryNotGrowable),
+            "Memory is not growable"
+        );
+        let mut attempted = [0u8; 32];
+        attempted[30] = 0x20;
+        assert_eq!(
+            format!(
+                "{}",
+                Error::MemoryGrowthLimit {
+                    max_memory_size: memory_size,
+                    attempted
+                }
+            ),
+            "Memory growth limit: 0x2000 bytes over the maximum of 0x1000 bytes"
+        );
+        assert_eq!(
+            format!("{}", Error::ReservedAddress { addr: [0u8; 32] }),
+            "Access to a reserved address: 0x0"
+        );
+        assert_eq!(
+            format!("{}", Error::InvalidReservedRange),
+            "Invalid reserved range"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::ReservedRangeOverlap {
+                    region: RegionKind::Code
+                }
+            ),
+            "A reserved range overlaps the code region"
+        );
+        let mut expected = [0u8; 32];
+        expected[0] = 0xab;
+        assert_eq!(
+            format!(
+                "{}",
+                Error::ConfigMismatch {
+                    expected,
+                    found: [1u8; 32]
+                }
+            ),
+            format!(
+                "Config mismatch: expected fingerprint ab{}, found {}",
+                "00".repeat(31),
+                "01".repeat(32)
+            )
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::TraceNotRetained {
+                    mode: TraceMode::CommitmentsOnly
+                }
+            ),
+            "The trace is not retained in the commitments-only trace mode"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::ProverBudgetTooSmall {
+                    max_k: 9,
+                    min_k: 10
+                }
+            ),
+            "Prover budget too small: circuits of 2^9 rows, the smallest is 2^10"
+        );
+        assert_eq!(
+            format!("{}", Error::OpeningOutOfRange { index: 5, len: 5 }),
+            "Opening out of range: record 5 of 5 records"
+        );
+        assert_eq!(
+            format!("{}", Error::KZGParamsIo),
+            "KZG params input or output failed"
+        );
+        assert_eq!(
+            format!("{}", Error::CorruptedKZGParams),
+            "Corrupted KZG params"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::KZGParamsTooSmall {
+                    found: 4,
+                    requested: 5
+                }
+            ),
+            "KZG params too small: k is 4 but 5 is requested"
+        );
+        assert_eq!(
+            format!("{}", Error::TruncatedTranscript),
+            "Truncated powers of tau transcript"
+        );
+        assert_eq!(
+            format!("{}", Error::MalformedTranscript),
+            "Malformed powers of tau transcript"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::TranscriptPointNotInSubgroup { group: 2, index: 1 }
+            ),
+            "Power 1 in G2 of the transcript is not in the subgroup"
+        );
+        assert_eq!(
+            format!("{}", Error::TruncatedMerkleProof),
+            "Truncated Merkle proof"
+        );
+        assert_eq!(
+            format!("{}", Error::MalformedMerkleProof),
+            "Malformed Merkle proof"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Error::MerklePathLengthMismatch {
+                    expected: 3,
+                    found: 2
+                }
+            ),
+            "Merkle path length mismatch: expected 3 siblings, found 2"
+        );
+        assert_eq!(
+            format!("{}", Error::LeafOutOfRange { index: 8, len: 8 }),
+            "Leaf out of range: leaf 8 of 8 leaves"
+        );
+        assert_eq!(
+            format!("{}", Error::BatchOpeningRejected { index: 3 }),
+            "Batch opening rejected: opening 3 of the batch"
+        );
+        assert_eq!(
+            format!("{}", Error::KZGStreamTooLong { capacity: 8 }),
+            "KZG stream too long: the params commit at most 8 coefficients"
+        );
+        assert_eq!(
+            format!("{}", Error::InvalidMerkleArity { arity: 1 }),
+            "Invalid Merkle arity: 1 children per node"
+        );
+        assert_eq!(
+            format!("{}", Error::InitialStateRootMismatch),
+            "Initial state root mismatch: the initial state does not hash to the root"
+        );
+        assert_eq!(
+            format!("{}", Error::TransitionReplayMismatch { index: 2 }),
+            "Transition replay mismatch: record 2 disagrees with the replay"
+        );
+        assert_eq!(
+            format!("{}", Error::FinalStateRootMismatch),
+            "Final state root mismatch: the replayed state does not hash to the root"
+        );
+        assert_eq!(
+            format!("{}", Error::MissingMerkleNode),
+            "Missing Merkle node in the node store"
+        );
+        assert_eq!(
+            format!("{}", Error::NodeStoreIo),
+            "Node store input or output failed"
+        );
+        assert_eq!(
+            format!("{}", Error::CorruptedNodeStore),
+            "Corrupted node store"
+        );
+        assert_eq!(
+            format!("{}", Error::ChunkBoundaryMismatch { index: 2 }),
+            "Chunk boundary mismatch: the exit root of chunk 2 is not the entry root of the next \
+             chunk"
+        );
+        assert_eq!(
+            format!("{}", Error::AggregateProofRejected),
+            "Aggregate proof rejected"
+        );
+        assert_eq!(
+            format!("{}", Error::IPAVectorTooLong { capacity: 4 }),
+            "IPA vector too long: the generators commit at most 4 records"
+        );
+        assert_eq!(
+            format!("{}", Error::CommitmentUnsupported),
+            "Commitment backend unsupported"
+        );
+        assert_eq!(
+            format!("{}", Error::CommitmentWordTooWide { bits: 512 }),
+            "Commitment word too wide: words of 512 bits do not fit the field of the backend"
+        );
+        assert_eq!(
+            format!("{}", Error::KeyGenerationFailed),
+            "Key generation failed"
+        );
+    }
+
+    #[test]
+    fn test_verify_error_print() {
+        let mut expected = [0u8; 32];
+        expected[0] = 0xab;
+        assert_eq!(
+            format!(
+                "{}",
+                VerifyError::RootMismatch {
+                    expected,
+                    computed: [1; 32]
+                }
+            ),
+            "Root mismatch: expected \
+             ab00000000000000000000000000000000000000000000000000000000000000, \
+             computed 0101010101010101010101010101010101010101010101010101010101010101"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                VerifyError::LeafMismatch {
+                    expected,
+                    found: [1; 32]
+                }
+            ),
+            "Leaf mismatch: expected \
+             ab00000000000000000000000000000000000000000000000000000000000000, \
+             found 0101010101010101010101010101010101010101010101010101010101010101"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                VerifyError::PathLengthMismatch {
+                    expected: 3,
+                    found: 2
+                }
+            ),
+            "Path length mismatch: expected 3, found 2"
+        );
+        assert_eq!(
+            format!("{}", VerifyError::IndexOutOfRange { index: 5, len: 5 }),
+            "Index out of range: index 5 of 5 committed records"
+        );
+        assert_eq!(
+            format!("{}", VerifyError::ParameterMismatch),
+            "Parameter mismatch"
+        );
+        assert_eq!(
+            format!("{}", VerifyError::EvaluationMismatch),
+            "Evaluation mismatch"
+        );
+        assert_eq!(
+            format!("{}", VerifyError::PairingCheckFailed),
+            "Pairing check failed"
+        );
+        assert_eq!(
+            format!("{}", VerifyError::InnerProductCheckFailed),
+            "Inner product check failed"
+        );
+        assert_eq!(
+            format!("{}", VerifyError::ZeroValue),
+            "Zero value: a zero value is proven absent"
+        );
+        assert_eq!(
+            format!("{}", VerifyError::InvalidRange),
+            "Invalid range: the range is inverted"
+        );
+        assert_eq!(
+            format!("{}", VerifyError::DeserializeError),
+            "Malformed proof"
+        );
     }
 }
@@ -14,6 +14,8 @@
 
 /// Base trait for generic type
 pub mod base;
+/// Cooperative cancellation for long running proving tasks
+pub mod cancellation;
 /// A commitment module that commit to the memory trace through the execution trace
 /// Currently supports: KZG, Merkle Tree, Verkle Tree.
 pub mod commitment;
@@ -25,3 +27,7 @@ pub mod constraints;
 pub mod error;
 /// Definition of abstract machine (instruction, trace and context)
 pub mod machine;
+/// Supported public API, importing the prelude is enough for most use cases
+pub mod prelude;
+
+pub use crate::prelude::*;
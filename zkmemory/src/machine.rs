@@ -1,20 +1,127 @@
 extern crate alloc;
-use crate::{base::Base, error::Error};
-use alloc::vec::Vec;
-use rbtree::RBTree;
+use crate::{
+    base::{chunks_of_words, AddressIterator, Base},
+    commitment::{
+        merkle::{CommitmentBackend, MerkleRoot},
+        selector::{instantiate, CommitmentKind, CommitmentSink, StateCommitter},
+        CommitmentScheme,
+    },
+    config::{
+        section_size, AlignmentPolicy, AllocatedSection, CostModel, Endianness, FingerprintCheck,
+        MemoryLayout, MemoryRegion, Permission, ReadPolicy, RegionKind, ResourceKind,
+        ResourceLimits, TraceMode,
+    },
+    constraints::gadgets::ConvertedTraceRecord,
+    error::Error,
+    machine::{
+        backend::MemoryBackend, fork::Fork, mmio::MmioHandler, observer::MemoryObserver,
+        sink::TraceSink,
+    },
+};
+use alloc::{borrow::Cow, boxed::Box, collections::BTreeMap, vec, vec::Vec};
+#[cfg(feature = "borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
+use core::{
+    cell::{Cell, OnceCell},
+    ops::Range,
+};
+use ff::{Field, PrimeField};
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// Storage backends of the memory cells
+pub mod backend;
+/// Checkpoint of the state of a machine to resume it later
+#[cfg(feature = "std")]
+pub mod checkpoint;
+/// Splitting of the execution trace into chunks proved separately
+pub mod chunk;
+/// Hexdump of the memory contents over an address range
+#[cfg(feature = "std")]
+pub mod dump;
+/// Speculative execution on a copy of a machine
+pub mod fork;
+/// JSON Lines export and import of the execution trace
+#[cfg(feature = "std")]
+pub mod jsonl;
+/// Loading of ELF and raw program images into the memory
+#[cfg(feature = "std")]
+pub mod loader;
+/// Merging of the traces of independent memory spaces
+pub mod merge;
+/// Memory-mapped I/O cells backed by host callbacks
+pub mod mmio;
+/// Hooks on the memory accesses of a machine
+pub mod observer;
+/// Host side replay of the execution trace
+pub mod replay;
+/// Deterministic interleaving of routines sharing one memory
+pub mod scheduler;
+/// Streaming consumers of the execution trace
+pub mod sink;
+/// Runtime state of a machine built from a config
+pub mod state;
+/// Access pattern statistics of the execution trace
+pub mod stats;
+/// Key-value storage map built on top of the RAM machine
+pub mod storage;
+/// Versioned binary format of the execution trace
+pub mod trace_codec;
+/// Host side well-formedness checks of the execution trace
+pub mod validate;
 
 /// Basic Memory Instruction
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize))]
 pub enum MemoryInstruction {
     /// Write to memory
     Write,
 
     /// Read from memory
     Read,
+
+    /// Push to the stack, a write to the cell under the stack pointer
+    Push,
+
+    /// Pop from the stack, a read of the cell under the stack pointer
+    Pop,
+}
+
+impl MemoryInstruction {
+    /// Numeric code of the instruction in the trace format:
+    /// 0 for Read, 1 for Write, 2 for Push and 3 for Pop
+    pub const fn code(&self) -> u64 {
+        match self {
+            MemoryInstruction::Read => 0,
+            MemoryInstruction::Write => 1,
+            MemoryInstruction::Push => 2,
+            MemoryInstruction::Pop => 3,
+        }
+    }
+
+    /// Get the instruction from its numeric code, return [None] for an unknown code
+    pub const fn from_code(code: u64) -> Option<Self> {
+        match code {
+            0 => Some(MemoryInstruction::Read),
+            1 => Some(MemoryInstruction::Write),
+            2 => Some(MemoryInstruction::Push),
+            3 => Some(MemoryInstruction::Pop),
+            _ => None,
+        }
+    }
+
+    /// Check if the instruction modifies the memory,
+    /// the memory consistency circuits treat Push as Write and Pop as Read
+    pub const fn is_write(&self) -> bool {
+        matches!(self, MemoryInstruction::Write | MemoryInstruction::Push)
+    }
 }
 
 /// Trace record struct of [AbstractTraceRecord]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize))]
 pub struct TraceRecord<K, V, const S: usize, const T: usize>
 where
     K: Base<S>,
@@ -25,6 +132,8 @@ where
     instruction: MemoryInstruction,
     address: K,
     value: V,
+    source: u8,
+    cell_size: u8,
 }
 
 impl<K, V, const S: usize, const T: usize> TraceRecord<K, V, S, T>
@@ -42,6 +151,269 @@ where
             self.value,
         )
     }
+
+    /// Tag the record with the source it comes from, e.g. the prover of a part of the trace.
+    /// The tag is not part of the circuit witness
+    pub fn with_source(self, source: u8) -> Self {
+        Self { source, ..self }
+    }
+}
+
+// A record is Copy so it cannot wipe itself on drop, a trace holding secret values is kept in
+// a [zeroize::Zeroizing] to be wiped when it is dropped
+#[cfg(feature = "zeroize")]
+impl<K, V, const S: usize, const T: usize> Zeroize for TraceRecord<K, V, S, T>
+where
+    K: Base<S> + Zeroize,
+    V: Base<T> + Zeroize,
+{
+    fn zeroize(&mut self) {
+        self.time_log.zeroize();
+        self.stack_depth.zeroize();
+        self.instruction = MemoryInstruction::Read;
+        self.address.zeroize();
+        self.value.zeroize();
+        self.source.zeroize();
+        self.cell_size.zeroize();
+    }
+}
+
+/// Saved state of the memory of a machine, see [AbstractMemoryMachine::snapshot]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "borsh", derive(BorshSerialize, BorshDeserialize))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemorySnapshot<K, V> {
+    // Every initialized cell in address order
+    cells: Vec<(K, V)>,
+    stack_ptr: K,
+    stack_depth: u64,
+    time_log: u64,
+    gas_used: u64,
+    config_fingerprint: [u8; 32],
+}
+
+// The words of a snapshot are not bound to be [Zeroize], so it is not wiped on drop, a
+// snapshot of a secret memory is kept in a [zeroize::Zeroizing] to be wiped when it is dropped
+#[cfg(feature = "zeroize")]
+impl<K: Zeroize, V: Zeroize> Zeroize for MemorySnapshot<K, V> {
+    fn zeroize(&mut self) {
+        for (address, value) in self.cells.iter_mut() {
+            address.zeroize();
+            value.zeroize();
+        }
+        self.cells.clear();
+        self.stack_ptr.zeroize();
+        self.stack_depth.zeroize();
+        self.time_log.zeroize();
+        self.gas_used.zeroize();
+        self.config_fingerprint.zeroize();
+    }
+}
+
+impl<K: Copy, V> MemorySnapshot<K, V> {
+    /// Get the initialized cells in address order
+    pub fn cells(&self) -> &[(K, V)] {
+        &self.cells
+    }
+
+    /// Get the saved stack pointer
+    pub fn stack_ptr(&self) -> K {
+        self.stack_ptr
+    }
+
+    /// Get the saved stack depth
+    pub fn stack_depth(&self) -> u64 {
+        self.stack_depth
+    }
+
+    /// Get the time log of the next access after the snapshot
+    pub fn time_log(&self) -> u64 {
+        self.time_log
+    }
+
+    /// Get the gas used by the machine before the snapshot
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    /// Get the fingerprint of the config of the machine the snapshot was taken from,
+    /// see [AbstractMemoryMachine::config_fingerprint]
+    pub fn config_fingerprint(&self) -> [u8; 32] {
+        self.config_fingerprint
+    }
+
+    /// Get the cells whose value differs from this snapshot to the other one, sorted by address.
+    /// Uninitialized cells are read as zero, a cell written back to its value is not a change
+    pub fn diff<const S: usize, const T: usize>(&self, other: &Self) -> Vec<CellDelta<K, V>>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        let mut deltas = Vec::new();
+        let mut before = self.cells.iter().peekable();
+        let mut after = other.cells.iter().peekable();
+        loop {
+            let (addr, old, new) = match (before.peek().copied(), after.peek().copied()) {
+                (Some((a, x)), Some((b, y))) if a == b => {
+                    before.next();
+                    after.next();
+                    (*a, *x, *y)
+                }
+                (Some((a, x)), Some((b, _))) if a < b => {
+                    before.next();
+                    (*a, *x, V::zero())
+                }
+                (Some((a, x)), None) => {
+                    before.next();
+                    (*a, *x, V::zero())
+                }
+                (_, Some((b, y))) => {
+                    after.next();
+                    (*b, V::zero(), *y)
+                }
+                (None, None) => break,
+            };
+            if old != new {
+                deltas.push(CellDelta {
+                    addr,
+                    before: old,
+                    after: new,
+                });
+            }
+        }
+        deltas
+    }
+}
+
+/// Change of a memory cell between two snapshots, see [MemorySnapshot::diff]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellDelta<K, V> {
+    /// Address of the cell
+    pub addr: K,
+    /// Value in the earlier snapshot
+    pub before: V,
+    /// Value in the later snapshot
+    pub after: V,
+}
+
+/// Resources used by a machine, see [AbstractMemoryMachine::resource_usage]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResourceUsage {
+    /// Number of records in the execution trace
+    pub trace_len: u64,
+    /// Number of cells stored in the memory
+    pub distinct_cells: u64,
+    /// Number of bytes written since the machine started
+    pub bytes_written: u64,
+}
+
+/// Encode an execution trace into its compact binary representation,
+/// the encoding is deterministic so equal traces always produce equal bytes
+#[cfg(feature = "borsh")]
+pub fn serialize_trace<K, V, const S: usize, const T: usize>(
+    trace: &[TraceRecord<K, V, S, T>],
+) -> Vec<u8>
+where
+    K: Base<S> + BorshSerialize,
+    V: Base<T> + BorshSerialize,
+{
+    borsh::to_vec(trace).expect("Writing to a vector never fails")
+}
+
+/// Decode an execution trace encoded by [serialize_trace]
+#[cfg(feature = "borsh")]
+pub fn deserialize_trace<K, V, const S: usize, const T: usize>(
+    bytes: &[u8],
+) -> Result<Vec<TraceRecord<K, V, S, T>>, Error>
+where
+    K: Base<S> + BorshDeserialize,
+    V: Base<T> + BorshDeserialize,
+{
+    borsh::from_slice(bytes).map_err(|_| Error::InvalidTraceEncoding)
+}
+
+/// The `(address, time_log)` pairs shared by several trace records
+pub type DuplicateRecords<K> = Vec<(K, u64)>;
+
+/// Sort a copy of the trace by address then time log, the order of the sorted memory circuit.
+/// Return the `(address, time_log)` pairs shared by several records if there is any
+pub fn sort_trace<K, V, const S: usize, const T: usize>(
+    trace: &[TraceRecord<K, V, S, T>],
+) -> Result<Vec<TraceRecord<K, V, S, T>>, DuplicateRecords<K>>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let mut sorted = trace.to_vec();
+    sort_trace_in_place(&mut sorted)?;
+    Ok(sorted)
+}
+
+/// Sort the trace in place by address then time log, see [sort_trace].
+/// The trace is sorted even if duplicated pairs are reported
+pub fn sort_trace_in_place<K, V, const S: usize, const T: usize>(
+    trace: &mut [TraceRecord<K, V, S, T>],
+) -> Result<(), DuplicateRecords<K>>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    // The order of trace records compares the time log only and rejects equal time logs
+    trace.sort_unstable_by_key(|record| (record.address, record.time_log));
+    let mut duplicates: DuplicateRecords<K> = trace
+        .windows(2)
+        .filter(|pair| (pair[0].address, pair[0].time_log) == (pair[1].address, pair[1].time_log))
+        .map(|pair| (pair[0].address, pair[0].time_log))
+        .collect();
+    duplicates.dedup();
+    if duplicates.is_empty() {
+        Ok(())
+    } else {
+        Err(duplicates)
+    }
+}
+
+/// Check that the trace is strictly increasing by address then time log
+pub fn is_sorted_by_address_time<K, V, const S: usize, const T: usize>(
+    trace: &[TraceRecord<K, V, S, T>],
+) -> bool
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    trace
+        .windows(2)
+        .all(|pair| (pair[0].address, pair[0].time_log) < (pair[1].address, pair[1].time_log))
+}
+
+/// Check that the time log of the trace is strictly increasing
+pub fn is_sorted_by_time<K, V, const S: usize, const T: usize>(
+    trace: &[TraceRecord<K, V, S, T>],
+) -> bool
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    trace
+        .windows(2)
+        .all(|pair| pair[0].time_log < pair[1].time_log)
+}
+
+/// Copy the records of the trace tagged with the source, see [TraceRecord::with_source].
+/// The records keep their order
+pub fn filter_source<K, V, const S: usize, const T: usize>(
+    trace: &[TraceRecord<K, V, S, T>],
+    source: u8,
+) -> Vec<TraceRecord<K, V, S, T>>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    trace
+        .iter()
+        .filter(|record| record.source == source)
+        .copied()
+        .collect()
 }
 
 #[derive(Debug)]
@@ -62,8 +434,15 @@ where
     Self: core::fmt::Debug + Sized,
     M: AbstractMachine<K, V>,
 {
-    /// Get the memory
-    fn memory(&mut self) -> &'_ mut RBTree<K, V>;
+    /// Backend of the memory, see [backend]
+    type Memory: MemoryBackend<K, V>;
+
+    /// Get the memory, a map that only holds the written cells,
+    /// so untouched cells of a huge address space take no space and are read as zero
+    fn memory(&mut self) -> &'_ mut Self::Memory;
+
+    /// Get the read only memory
+    fn ro_memory(&self) -> &'_ Self::Memory;
 
     /// Set the stack depth
     fn set_stack_depth(&mut self, stack_depth: u64);
@@ -91,8 +470,9 @@ where
     Self: core::fmt::Debug + Sized,
     M: AbstractMachine<K, V>,
 {
-    /// Execute the instruction on the context
-    fn exec(&self, machine: &mut M::Machine);
+    /// Execute the instruction on the context, an instruction that can not be executed
+    /// returns an error instead of panicking
+    fn exec(&self, machine: &mut M::Machine) -> Result<(), Error>;
 }
 
 /// Trace record
@@ -125,6 +505,26 @@ where
 
     /// Get the instruction
     fn instruction(&self) -> MemoryInstruction;
+
+    /// Get the tag of the source of the record, 0 unless the record was tagged
+    fn source(&self) -> u8 {
+        0
+    }
+
+    /// Get the size in bytes of the accessed cell if its region declares a word size
+    /// narrower than the word of the machine, 0 for a cell of the full word
+    fn cell_size(&self) -> u8 {
+        0
+    }
+
+    /// Set the size of the accessed cell, see [AbstractTraceRecord::cell_size].
+    /// A record that does not keep the size ignores it
+    fn with_cell_size(self, _cell_size: u8) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
 }
 
 /// The abstract machine that will be implemented by particular machine
@@ -163,8 +563,24 @@ where
     /// Get the execution trace
     fn trace(&self) -> Vec<Self::TraceRecord>;
 
-    /// Get the execution trace
-    fn exec(&mut self, instruction: &Self::Instruction);
+    /// Iterate over the execution trace in time order without copying it
+    fn trace_iter(&self) -> Box<dyn Iterator<Item = &'_ Self::TraceRecord> + '_>;
+
+    /// Get the number of records in the execution trace
+    fn trace_len(&self) -> usize {
+        self.trace_iter().count()
+    }
+
+    /// Consume the machine and return its execution trace
+    fn into_trace(self) -> Vec<Self::TraceRecord> {
+        self.trace()
+    }
+
+    /// Discard every trace record with a time log greater than or equal to the given one
+    fn truncate_trace(&mut self, time_log: u64);
+
+    /// Execute the instruction
+    fn exec(&mut self, instruction: &Self::Instruction) -> Result<(), Error>;
 
     /// Get the base address of the memory section
     fn base_address(&self) -> K;
@@ -179,852 +595,4824 @@ where
     fn max_stack_depth(&self) -> u64;
 }
 
-/// Abstract RAM machine
-pub trait AbstractMemoryMachine<K, V, const S: usize, const T: usize>
+/// Move a pointer forward, return [Error::PointerOverflow] instead of wrapping around
+pub(crate) fn pointer_add<K: Base<S>, const S: usize>(pointer: K, delta: K) -> Result<K, Error> {
+    pointer
+        .checked_add(delta)
+        .ok_or_else(|| Error::PointerOverflow {
+            pointer: pointer.fixed_be_bytes(),
+            attempted_delta: delta.fixed_be_bytes(),
+        })
+}
+
+/// Move a pointer backward, return [Error::PointerUnderflow] instead of wrapping around
+pub(crate) fn pointer_sub<K: Base<S>, const S: usize>(pointer: K, delta: K) -> Result<K, Error> {
+    pointer
+        .checked_sub(delta)
+        .ok_or_else(|| Error::PointerUnderflow {
+            pointer: pointer.fixed_be_bytes(),
+            attempted_delta: delta.fixed_be_bytes(),
+        })
+}
+
+/// Apply the alignment policy to an address, return the address to access.
+/// [AlignmentPolicy::Emulate] keeps unaligned addresses, the access is split into two cells
+fn align_address<K: Base<S>, const S: usize>(
+    policy: AlignmentPolicy,
+    address: K,
+    word_size: K,
+) -> Result<K, Error> {
+    let remain = address % word_size;
+    if remain.is_zero() {
+        return Ok(address);
+    }
+    match policy {
+        AlignmentPolicy::Strict => Err(Error::UnalignedAccess {
+            addr: address.fixed_be_bytes(),
+            word_size: word_size.into(),
+        }),
+        AlignmentPolicy::RoundDown => pointer_sub(address, remain),
+        AlignmentPolicy::Emulate => Ok(address),
+    }
+}
+
+/// Apply the alignment policy of the machine to an access to the cell at the address.
+/// An access to a cell narrower than the word can not be split, so it is rejected with
+/// [Error::UnalignedAccess] unless the policy is [AlignmentPolicy::RoundDown]
+fn align_cell_address<M, K, V, const S: usize, const T: usize>(
+    machine: &M,
+    address: K,
+    cell_size: K,
+) -> Result<K, Error>
 where
     K: Base<S>,
     V: Base<T>,
-    Self: AbstractMachine<K, V>,
+    M: AbstractMemoryMachine<K, V, S, T>,
 {
-    /// Read from memory
-    fn read(&mut self, address: K) -> Result<CellInteraction<K, V>, Error> {
-        let remain = address % self.word_size();
-        if remain.is_zero() {
-            // Read on a cell
-            let result = self.dummy_read(address);
-            let time_log = self.ro_context().time_log();
-            self.track(Self::TraceRecord::new(
-                time_log,
-                self.ro_context().stack_depth(),
-                MemoryInstruction::Read,
-                address,
-                result,
-            ));
-            self.context().set_time_log(time_log + 1);
-
-            // Return single cell read
-            Ok(CellInteraction::SingleCell(
-                MemoryInstruction::Read,
-                address,
-                result,
-            ))
-        } else {
-            // Get the address of 2 cells
-            let (addr_lo, addr_hi) = self.compute_address(address, remain);
-            let time_log = self.ro_context().time_log();
-            // Get the 2 cells
-            let val_lo = self.dummy_read(addr_lo);
-            let val_hi = self.dummy_read(addr_hi);
-            let cell_size = self.word_size().into();
-            let part_lo = (address - addr_lo).into();
-            let part_hi = cell_size - part_lo;
-            let mut buf = [0u8; T];
-
-            // Concat values from 2 cells
-            buf[part_hi..cell_size]
-                .copy_from_slice(&<V as Into<[u8; T]>>::into(val_hi)[0..part_lo]);
-            buf[0..part_hi]
-                .copy_from_slice(&<V as Into<[u8; T]>>::into(val_lo)[part_lo..cell_size]);
-
-            // @TODO: Read in the middle of 2 cells need to be translated correctly
-            self.track(Self::TraceRecord::new(
-                time_log,
-                self.ro_context().stack_depth(),
-                MemoryInstruction::Read,
-                addr_lo,
-                val_lo,
-            ));
-
-            self.track(Self::TraceRecord::new(
-                time_log + 1,
-                self.ro_context().stack_depth(),
-                MemoryInstruction::Read,
-                addr_hi,
-                val_hi,
-            ));
-
-            self.context().set_time_log(time_log + 2);
-
-            // Return double cells read
-            Ok(CellInteraction::DoubleCell(
-                MemoryInstruction::Read,
-                address,
-                V::from(buf),
-                addr_lo,
-                val_lo,
-                addr_hi,
-                val_hi,
-            ))
+    match machine.alignment_policy() {
+        AlignmentPolicy::Emulate if cell_size < machine.word_size() => {
+            align_address(AlignmentPolicy::Strict, address, cell_size)
         }
+        policy => align_address(policy, address, cell_size),
     }
+}
 
-    /// Write to memory
-    fn write(&mut self, address: K, value: V) -> Result<CellInteraction<K, V>, Error> {
-        let remain = address % self.word_size();
-        if remain.is_zero() {
-            let time_log = self.ro_context().time_log();
-            // Write on a cell
-            self.context().memory().insert(address, value);
-            self.track(Self::TraceRecord::new(
-                time_log,
-                self.ro_context().stack_depth(),
-                MemoryInstruction::Write,
-                address,
-                value,
-            ));
-
-            self.context().set_time_log(time_log + 1);
-
-            // Return single cell write
-            Ok(CellInteraction::SingleCell(
-                MemoryInstruction::Write,
-                address,
-                value,
-            ))
-        } else {
-            // Get the address of 2 cells
-            let (addr_lo, addr_hi) = self.compute_address(address, remain);
-            let time_log = self.ro_context().time_log();
-            // Calculate memory address and offset
-            let cell_size = self.word_size().into();
-            let part_lo: usize = (address - addr_lo).into();
-            let part_hi = cell_size - part_lo;
-
-            let val: [u8; T] = value.into();
-
-            // Write the low part of value to the buffer
-            let mut buf: [u8; T] = self.dummy_read(addr_lo).into();
-            buf[part_lo..cell_size].copy_from_slice(&val[0..part_hi]);
-            let val_lo = V::from(buf);
-
-            // Write the high part of value to the buffer
-            let mut buf: [u8; T] = self.dummy_read(addr_hi).into();
-            buf[0..part_lo].copy_from_slice(&val[part_hi..cell_size]);
-            let val_hi = V::from(buf);
-
-            self.context().memory().replace_or_insert(addr_lo, val_lo);
-            self.context().memory().replace_or_insert(addr_hi, val_hi);
-
-            // @TODO: Write in the middle of 2 cells need to be translated correctly
-            self.track(Self::TraceRecord::new(
-                time_log,
-                self.ro_context().stack_depth(),
-                MemoryInstruction::Write,
-                addr_lo,
-                val_lo,
-            ));
-
-            self.track(Self::TraceRecord::new(
-                time_log + 1,
-                self.ro_context().stack_depth(),
-                MemoryInstruction::Write,
-                addr_hi,
-                val_hi,
-            ));
-
-            self.context().set_time_log(time_log + 2);
-
-            // Return double cells write
-            Ok(CellInteraction::DoubleCell(
-                MemoryInstruction::Write,
-                address,
-                value,
-                addr_lo,
-                val_lo,
-                addr_hi,
-                val_hi,
-            ))
-        }
+/// Check that a value fits in a cell narrower than the word,
+/// return [Error::ValueOutOfRange] otherwise
+fn check_narrow_value<K: Base<S>, V: Base<T>, const S: usize, const T: usize>(
+    value: V,
+    cell_size: K,
+) -> Result<(), Error> {
+    let cell_size: usize = cell_size.into();
+    let bytes: [u8; T] = value.into();
+    if bytes[..T.saturating_sub(cell_size)]
+        .iter()
+        .all(|byte| *byte == 0)
+    {
+        Ok(())
+    } else {
+        Err(Error::ValueOutOfRange)
     }
+}
 
-    /// Read from memory (only read one whole cell)
-    fn dummy_read(&mut self, address: K) -> V {
-        match self.context().memory().get(&address) {
-            Some(r) => *r,
-            None => V::zero(),
+/// Split a byte range into the cells it touches, return the address of every cell,
+/// the offset of the range in the cell and the part of the range that falls in the cell
+fn byte_cells<K: Base<S>, const S: usize>(
+    address: K,
+    len: usize,
+    word_size: K,
+    cell_size: usize,
+) -> Result<Vec<(K, usize, Range<usize>)>, Error> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    // Reject ranges that do not fit in the address space before touching any cell
+    pointer_add(address, K::from(len - 1))?;
+    let remain = address % word_size;
+    let mut cell = pointer_sub(address, remain)?;
+    let mut offset: usize = remain.into();
+    let mut start = 0;
+    let mut cells = Vec::new();
+    while start < len {
+        let end = core::cmp::min(len, start + cell_size - offset);
+        cells.push((cell, offset, start..end));
+        start = end;
+        offset = 0;
+        if start < len {
+            cell = pointer_add(cell, word_size)?;
         }
     }
+    Ok(cells)
+}
 
-    /// Compute the addresses
-    fn compute_address(&self, address: K, remain: K) -> (K, K) {
-        let base = address - remain;
-        (base, base + self.word_size())
+/// Write a byte buffer to consecutive cells, the tail of the last cell is zero-padded.
+/// Every touched cell is checked before the first write, return the number of cells written
+fn write_chunks<M, K, V, const S: usize, const T: usize>(
+    machine: &mut M,
+    base: K,
+    data: &[u8],
+    access: Option<Permission>,
+) -> Result<usize, Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    let base = align_address(machine.alignment_policy(), base, machine.word_size())?;
+    let endianness = machine.endianness();
+    let padded_len = data.len().div_ceil(T) * T;
+    let cells: Vec<K> = byte_cells(base, padded_len, machine.word_size(), T)?
+        .into_iter()
+        .map(|(cell, _, _)| cell)
+        .collect();
+    machine.check_access(&cells, access)?;
+    for (offset, word) in chunks_of_words::<V, T>(data) {
+        // The chunks are big endian words, laid out again in the byte order of the machine
+        let word: [u8; T] = word.into();
+        let value: V = endianness.from_bytes(word);
+        write_cells(
+            machine,
+            pointer_add(base, K::from(offset))?,
+            value,
+            MemoryInstruction::Write,
+            access,
+        )?;
     }
+    Ok(cells.len())
 }
 
-/// Abstract stack machine
-pub trait AbstractStackMachine<K, V, const S: usize, const T: usize>
+/// Get the addresses of `len_cells` consecutive cells starting at the address,
+/// the range stops at the last cell that fits in the memory section
+pub(crate) fn cells_in_memory<M, K, V, const S: usize, const T: usize>(
+    machine: &M,
+    start: K,
+    len_cells: usize,
+) -> Vec<K>
 where
     K: Base<S>,
     V: Base<T>,
-    Self: AbstractMemoryMachine<K, V, S, T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
 {
-    /// Push the value to the stack and return stack_depth
-    fn push(&mut self, value: V) -> Result<(u64, CellInteraction<K, V>), Error> {
-        // Check for stack overflow
-        if self.ro_context().stack_depth() == self.max_stack_depth() {
-            return Err(Error::StackOverflow);
-        }
-        // Update stack depth and stack pointer
-        let stack_depth = self.ro_context().stack_depth() + 1;
-        self.context().set_stack_depth(stack_depth);
+    let (_, high) = machine.get_memory_address();
+    let last_byte = K::WORD_SIZE - K::from(1u64);
+    AddressIterator::new(start, len_cells)
+        .take_while(|cell| cell.checked_add(last_byte).is_some_and(|last| last <= high))
+        .collect()
+}
 
-        // Push first then update the stack pointer
-        let address = self.ro_context().stack_ptr();
-        let next_address = address + self.word_size();
-        self.context().set_stack_ptr(next_address);
+/// Get the time log of an access to `cells` cells,
+/// return [Error::TimeLogExhausted] if the time log would overflow
+fn next_time_log<M, K, V, const S: usize, const T: usize>(
+    machine: &M,
+    cells: u64,
+) -> Result<u64, Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    if machine.remaining_time_budget() < cells {
+        return Err(Error::TimeLogExhausted);
+    }
+    Ok(machine.ro_context().time_log())
+}
 
-        match self.write(address, value) {
-            Ok(v) => Ok((stack_depth, v)),
-            Err(e) => Err(e),
-        }
-    }
-
-    /// Get value from the stack and return stack_depth and value
-    fn pop(&mut self) -> Result<(u64, CellInteraction<K, V>), Error> {
-        // Check for stack underflow
-        if self.ro_context().stack_depth() == 0 {
-            return Err(Error::StackUnderflow);
-        }
-        // Update stack depth and stack pointer
-        let stack_depth = self.ro_context().stack_depth() - 1;
-        self.context().set_stack_depth(stack_depth);
-        let address = self.ro_context().stack_ptr() - self.word_size();
-        self.context().set_stack_ptr(address);
-
-        match self.read(address) {
-            Ok(v) => Ok((stack_depth, v)),
-            Err(e) => Err(e),
+// Check that `extra` more of the resource stays within the limit
+fn check_limit(
+    which: ResourceKind,
+    limit: Option<u64>,
+    used: impl FnOnce() -> u64,
+    extra: u64,
+) -> Result<(), Error> {
+    match limit {
+        Some(limit) if extra > 0 => {
+            let attempted = used().saturating_add(extra);
+            if attempted > limit {
+                return Err(Error::ResourceLimit {
+                    which,
+                    limit,
+                    attempted,
+                });
+            }
+            Ok(())
         }
+        _ => Ok(()),
     }
 }
 
-/// Virtual register structure
-#[derive(Debug, Clone, Copy)]
-pub struct Register<K>(usize, K);
-
-impl<K> Register<K>
+/// Check that an access recording `records` trace records and writing the cells stays within
+/// the resource limits of the machine, return [Error::ResourceLimit] otherwise
+fn check_resources<M, K, V, const S: usize, const T: usize>(
+    machine: &M,
+    records: u64,
+    written: &[K],
+) -> Result<(), Error>
 where
-    K: Copy,
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
 {
-    /// Create a new register
-    pub fn new(register_index: usize, register_address: K) -> Self {
-        Self(register_index, register_address)
-    }
-
-    /// Get the register address
-    pub fn address(&self) -> K {
-        self.1
-    }
+    let limits = machine.resource_limits();
+    let memory = machine.ro_context().ro_memory();
+    let new_cells = written
+        .iter()
+        .filter(|cell| memory.get(cell).is_none())
+        .count();
+    check_limit(
+        ResourceKind::TraceLen,
+        limits.max_trace_len,
+        || machine.record_count(),
+        records,
+    )?;
+    check_limit(
+        ResourceKind::DistinctCells,
+        limits.max_distinct_cells,
+        || memory.len() as u64,
+        new_cells as u64,
+    )?;
+    check_limit(
+        ResourceKind::BytesWritten,
+        limits.max_bytes_written,
+        || machine.bytes_written(),
+        (written.len() * T) as u64,
+    )
+}
 
-    /// Get the register index
-    pub fn index(&self) -> usize {
-        self.0
-    }
+/// Get the gas used by the machine after an access recording the instruction,
+/// return [Error::OutOfGas] if it exceeds the gas budget of the machine
+fn check_gas<M, K, V, const S: usize, const T: usize>(
+    machine: &M,
+    instruction: MemoryInstruction,
+) -> Result<u64, Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    check_gas_cost(machine, machine.cost_model().cost(instruction))
 }
 
-/// Abstract register machine
-pub trait AbstractRegisterMachine<K, V, const S: usize, const T: usize>
+// Get the gas used by the machine after spending `cost` more gas,
+// return [Error::OutOfGas] if it exceeds the gas budget of the machine
+fn check_gas_cost<M, K, V, const S: usize, const T: usize>(
+    machine: &M,
+    cost: u64,
+) -> Result<u64, Error>
 where
     K: Base<S>,
     V: Base<T>,
-    Self: AbstractMemoryMachine<K, V, S, T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
 {
-    /// Set the value of the register
-    fn set(&mut self, register: Register<K>, value: V) -> Result<CellInteraction<K, V>, Error> {
-        self.write(register.address(), value)
+    let used = machine.gas_used();
+    let total = used.saturating_add(cost);
+    match machine.gas_budget() {
+        Some(budget) if total > budget => Err(Error::OutOfGas { budget, used, cost }),
+        _ => Ok(total),
     }
+}
 
-    /// Get the value of the register
-    fn get(&mut self, register: Register<K>) -> Result<CellInteraction<K, V>, Error> {
-        self.read(register.address())
+/// Check that the machine keeps its full trace, see [TraceMode]
+pub(crate) fn require_full_trace<M, K, V, const S: usize, const T: usize>(
+    machine: &M,
+) -> Result<(), Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    match machine.trace_mode() {
+        TraceMode::Full => Ok(()),
+        mode => Err(Error::TraceNotRetained { mode }),
     }
+}
 
-    /// Create new register from index
-    fn new_register(&self, register_index: usize) -> Option<Register<K>>;
+/// Record an access by the trace mode of the machine. In [TraceMode::Full] the record is
+/// pushed to the trace sink of the machine if it has one and kept in its trace otherwise
+pub(crate) fn emit<M, K, V, const S: usize, const T: usize>(
+    machine: &mut M,
+    record: TraceRecord<K, V, S, T>,
+) -> Result<(), Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    emit_all(machine, &[record])
 }
 
-impl<K, V, const S: usize, const T: usize> AbstractTraceRecord<K, V> for TraceRecord<K, V, S, T>
+/// Record the records of one access like [emit], either every record is recorded or none is,
+/// so an unaligned access rejected by the sink leaves no record of its first cell behind
+pub(crate) fn emit_all<M, K, V, const S: usize, const T: usize>(
+    machine: &mut M,
+    records: &[TraceRecord<K, V, S, T>],
+) -> Result<(), Error>
 where
     K: Base<S>,
     V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
 {
-    fn new(
-        time_log: u64,
-        stack_depth: u64,
-        instruction: MemoryInstruction,
-        address: K,
-        value: V,
-    ) -> Self {
-        Self {
-            time_log,
-            stack_depth,
-            instruction,
-            address,
-            value,
+    match machine.trace_mode() {
+        TraceMode::Full => match machine.trace_sink() {
+            Some(sink) => sink.push_all(records)?,
+            None => {
+                for record in records {
+                    let (time_log, stack_depth, instruction, address, value) = record.get_tuple();
+                    machine.track(
+                        M::TraceRecord::new(time_log, stack_depth, instruction, address, value)
+                            .with_cell_size(record.cell_size),
+                    );
+                    if let Some(counter) = machine.record_counter_mut() {
+                        *counter += 1;
+                    }
+                }
+            }
+        },
+        TraceMode::CommitmentsOnly => {
+            if let Some(hasher) = machine.trace_hasher() {
+                hasher.push_shared(records)?;
+            }
         }
+        TraceMode::Disabled => {}
     }
-
-    fn time_log(&self) -> u64 {
-        self.time_log
-    }
-
-    fn stack_depth(&self) -> u64 {
-        self.stack_depth
+    for record in records {
+        note_first_write(machine, record);
     }
+    Ok(())
+}
 
-    fn address(&self) -> K {
-        self.address
+/// Keep the time log of a write to a cell of a write-once region if it is the first one,
+/// see [AbstractMemoryMachine::first_writes]
+pub(crate) fn note_first_write<M, K, V, const S: usize, const T: usize>(
+    machine: &mut M,
+    record: &TraceRecord<K, V, S, T>,
+) where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    if !record.instruction().is_write() {
+        return;
     }
-
-    fn value(&self) -> V {
-        self.value
+    let write_once = machine
+        .memory_layout()
+        .and_then(|layout| layout.region_of(record.address(), machine.cell_size(record.address())))
+        .is_some_and(|region| region.write_once);
+    if let Some(first_writes) = machine.first_writes_mut().filter(|_| write_once) {
+        first_writes
+            .entry(record.address())
+            .or_insert(record.time_log());
     }
+}
 
-    fn instruction(&self) -> MemoryInstruction {
-        self.instruction
+// Record of an access at the current stack depth of the machine, tagged with the size of the
+// cell if it is narrower than the word of the machine
+fn record_of<M, K, V, const S: usize, const T: usize>(
+    machine: &M,
+    time_log: u64,
+    instruction: MemoryInstruction,
+    address: K,
+    value: V,
+) -> TraceRecord<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    let cell_size = machine.cell_size(address);
+    let record = TraceRecord::new(
+        time_log,
+        machine.ro_context().stack_depth(),
+        instruction,
+        address,
+        value,
+    );
+    if cell_size < machine.word_size() {
+        let cell_size: usize = cell_size.into();
+        record.with_cell_size(cell_size as u8)
+    } else {
+        record
     }
 }
 
-impl<K, V, const S: usize, const T: usize> PartialOrd for TraceRecord<K, V, S, T>
+// Get the kind of the memory-mapped I/O region that contains the cell
+fn mmio_kind<M, K, V, const S: usize, const T: usize>(machine: &M, cell: K) -> Option<RegionKind>
 where
     K: Base<S>,
     V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
 {
-    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
+    machine
+        .mmio_layout()
+        .and_then(|layout| layout.region_of(cell, machine.cell_size(cell)))
+        .map(|region| region.kind)
 }
 
-impl<K, V, const S: usize, const T: usize> Ord for TraceRecord<K, V, S, T>
+// Read a cell from its device if it is memory-mapped and from the memory otherwise
+fn load_cell<M, K, V, const S: usize, const T: usize>(machine: &mut M, cell: K) -> V
 where
     K: Base<S>,
     V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
 {
-    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
-        match self.time_log.cmp(&other.time_log) {
-            core::cmp::Ordering::Equal => {
-                panic!("Time log never been equal")
-            }
-            ord => ord,
+    if let Some(kind) = mmio_kind(machine, cell) {
+        if let Some(handler) = machine.mmio_handler(kind) {
+            return handler.on_read(cell);
         }
     }
+    machine.dummy_read(cell)
 }
 
-// pub trait KZGMemoryCommitment
-
-#[macro_export]
-/// Export macro for implementing [AbstractMemoryMachine](crate::machine::AbstractMemoryMachine) trait
-macro_rules! impl_state_machine {
-    ($machine_struct: ident) => {
-        use zkmemory::machine::AbstractMemoryMachine;
-
-        impl<K, V, const S: usize, const T: usize> AbstractMemoryMachine<K, V, S, T>
-            for $machine_struct<K, V, S, T>
-        where
-            K: Base<S>,
-            V: Base<T>,
-            Self: AbstractMachine<K, V>,
-        {
+// Forward a write to the device of the cell if it is memory-mapped
+fn store_cell<M, K, V, const S: usize, const T: usize>(machine: &mut M, cell: K, value: V)
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    if let Some(kind) = mmio_kind(machine, cell) {
+        if let Some(handler) = machine.mmio_handler(kind) {
+            handler.on_write(cell, value);
         }
-    };
+    }
 }
 
-#[macro_export]
-/// Export macro for implementing [AbstractRegisterMachine](crate::machine::AbstractRegisterMachine) trait
-macro_rules! impl_register_machine {
-    ($machine_struct: ident) => {
-        use zkmemory::machine::AbstractRegisterMachine;
+/// Write a value to one or two cells, the access is checked against the memory layout
+fn write_cells<M, K, V, const S: usize, const T: usize>(
+    machine: &mut M,
+    address: K,
+    value: V,
+    instruction: MemoryInstruction,
+    access: Option<Permission>,
+) -> Result<CellInteraction<K, V>, Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    let cell_size = machine.cell_size(address);
+    let address = align_cell_address(machine, address, cell_size)?;
+    if cell_size < machine.word_size() {
+        check_narrow_value(value, cell_size)?;
+    }
+    let remain = address % cell_size;
+    if remain.is_zero() {
+        machine.check_access(&[address], access)?;
+        let time_log = next_time_log(machine, 1)?;
+        check_resources(machine, 1, &[address])?;
+        let gas_used = check_gas(machine, instruction)?;
+        let old = machine.dummy_read(address);
+        emit(
+            machine,
+            record_of(machine, time_log, instruction, address, value),
+        )?;
+        // Write on a cell
+        machine.context().memory().replace_or_insert(address, value);
+
+        machine.context().set_time_log(time_log + 1);
+        machine.add_bytes_written(T as u64);
+        machine.set_gas_used(gas_used);
+        store_cell(machine, address, value);
+        if let Some(observer) = machine.observer() {
+            observer.on_write(address, old, value, time_log);
+        }
 
-        impl<K, V, const S: usize, const T: usize> AbstractRegisterMachine<K, V, S, T>
-            for $machine_struct<K, V, S, T>
-        where
-            K: Base<S>,
-            V: Base<T>,
-            Self: AbstractMemoryMachine<K, V, S, T>,
-        {
-            fn new_register(
-                &self,
-                register_index: usize,
-            ) -> Option<zkmemory::machine::Register<K>> {
-                Some(Register::new(
-                    register_index,
-                    self.register_start() + K::from(register_index) * K::WORD_SIZE,
-                ))
-            }
+        // Return single cell write
+        Ok(CellInteraction::SingleCell(instruction, address, value))
+    } else {
+        // Get the address of 2 cells
+        let (addr_lo, addr_hi) = machine.compute_address(address, remain)?;
+        machine.check_access(&[addr_lo, addr_hi], access)?;
+        let time_log = next_time_log(machine, 2)?;
+        check_resources(machine, 2, &[addr_lo, addr_hi])?;
+        let gas_used = check_gas(machine, instruction)?;
+        // Calculate memory address and offset
+        let cell_size = machine.word_size().into();
+        let part_lo: usize = (address - addr_lo).into();
+        let part_hi = cell_size - part_lo;
+
+        let endianness = machine.endianness();
+        let val: [u8; T] = endianness.to_bytes(value);
+
+        // Write the low part of value to the buffer
+        let old_lo = machine.dummy_read(addr_lo);
+        let mut buf: [u8; T] = endianness.to_bytes(old_lo);
+        buf[part_lo..cell_size].copy_from_slice(&val[0..part_hi]);
+        let val_lo: V = endianness.from_bytes(buf);
+
+        // Write the high part of value to the buffer
+        let old_hi = machine.dummy_read(addr_hi);
+        let mut buf: [u8; T] = endianness.to_bytes(old_hi);
+        buf[0..part_lo].copy_from_slice(&val[part_hi..cell_size]);
+        let val_hi: V = endianness.from_bytes(buf);
+
+        // @TODO: Write in the middle of 2 cells need to be translated correctly
+        // Both records are recorded or none, the cells are only changed afterwards
+        let records = [
+            record_of(machine, time_log, instruction, addr_lo, val_lo),
+            record_of(machine, time_log + 1, instruction, addr_hi, val_hi),
+        ];
+        emit_all(machine, &records)?;
+
+        machine
+            .context()
+            .memory()
+            .replace_or_insert(addr_lo, val_lo);
+        machine
+            .context()
+            .memory()
+            .replace_or_insert(addr_hi, val_hi);
+
+        machine.context().set_time_log(time_log + 2);
+        machine.add_bytes_written(2 * T as u64);
+        machine.set_gas_used(gas_used);
+        store_cell(machine, addr_lo, val_lo);
+        store_cell(machine, addr_hi, val_hi);
+        if let Some(observer) = machine.observer() {
+            observer.on_write(addr_lo, old_lo, val_lo, time_log);
+            observer.on_write(addr_hi, old_hi, val_hi, time_log + 1);
         }
-    };
+
+        // Return double cells write
+        Ok(CellInteraction::DoubleCell(
+            instruction,
+            address,
+            value,
+            addr_lo,
+            val_lo,
+            addr_hi,
+            val_hi,
+        ))
+    }
 }
 
-#[macro_export]
-/// Export macro for implementing [AbstractStackMachine](crate::machine::AbstractStackMachine) trait
-macro_rules! impl_stack_machine {
-    ($machine_struct: ident) => {
-        use zkmemory::machine::AbstractStackMachine;
+/// Time logs of the first writes to the cells of the write-once regions,
+/// see [AbstractMemoryMachine::first_writes]
+pub type FirstWrites<K> = BTreeMap<K, u64>;
 
-        impl<K, V, const S: usize, const T: usize> AbstractStackMachine<K, V, S, T>
-            for $machine_struct<K, V, S, T>
-        where
-            K: Base<S>,
-            V: Base<T>,
-            Self: AbstractMemoryMachine<K, V, S, T>,
-        {
-        }
-    };
-}
+/// Abstract RAM machine
+pub trait AbstractMemoryMachine<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+    Self: AbstractMachine<K, V>,
+{
+    /// Get the policy for unaligned reads and writes
+    fn alignment_policy(&self) -> AlignmentPolicy {
+        AlignmentPolicy::Emulate
+    }
 
-#[cfg(test)]
-mod tests {
-    use crate::{
-        base::{Base, B256},
-        config::{AllocatedSection, Config, ConfigArgs, DefaultConfig},
-        error::Error,
-        machine::{
-            AbstractContext, AbstractInstruction, AbstractMachine, AbstractMemoryMachine,
-            AbstractRegisterMachine, AbstractStackMachine, CellInteraction, Register, TraceRecord,
-        },
-    };
-    extern crate alloc;
-    extern crate std;
-    use alloc::{vec, vec::Vec};
-    use rbtree::RBTree;
-    use std::marker::PhantomData;
+    /// Get the named regions of the address space, [None] allows every access
+    fn memory_layout(&self) -> Option<MemoryLayout<K>> {
+        None
+    }
 
-    /// My instruction set for the machine
-    #[derive(Debug, Clone, Copy)]
-    pub enum MyInstruction<M, K, V, const S: usize, const T: usize>
-    where
-        K: Base<S>,
-        V: Base<T>,
-    {
-        /// Read from memory
-        Read(K),
-        /// Write to memory
-        Write(K, V),
-        /// Push to stack
-        Push(V),
-        /// Pop from stack
-        Pop(),
-        /// Move from register to register (Mov(r2, r1) moves the value of r1 to r2)
-        Mov(Register<K>, Register<K>),
-        /// Swap value from top stack  to register
-        Swap(Register<K>),
-        /// Load from memory to register
-        Load(Register<K>, K),
-        /// Save from register to memory
-        Save(K, Register<K>),
-        /// Invalid instruction
-        Invalid(PhantomData<M>),
-        /// Add two registers, register 1 = register 1 + register 2
-        Add(Register<K>, Register<K>),
+    /// Get the size in bytes of the cell at the address, the word size of its region of the
+    /// memory layout if the region declares one and the word size of the machine otherwise.
+    /// An access to a narrower cell is never split and its value must fit in the cell
+    fn cell_size(&self, address: K) -> K {
+        self.memory_layout()
+            .and_then(|layout| layout.region_of(address, K::from(1u64)))
+            .and_then(|region| region.word_size)
+            .unwrap_or(self.word_size())
     }
 
-    /// Type alias Instruction
-    pub type Instruction = MyInstruction<StateMachine<B256, B256, 32, 32>, B256, B256, 32, 32>;
+    /// Get the guard cells above the top of the stack, a push into them is rejected with
+    /// [Error::StackOverflow] even if the stack depth was not reached
+    fn stack_guard(&self) -> Option<AllocatedSection<K>> {
+        None
+    }
 
-    /// RAM Machine
-    #[derive(Debug, Clone)]
-    pub struct StateMachine<K, V, const S: usize, const T: usize>
-    where
-        K: Base<S>,
-        V: Base<T>,
-    {
-        // Memory
-        memory: RBTree<K, V>,
-        memory_allocated: AllocatedSection<K>,
-        word_size: K,
-        time_log: u64,
+    /// Get the semantics of reading a cell that was never written
+    fn read_policy(&self) -> ReadPolicy {
+        ReadPolicy::ZeroOnUninit
+    }
 
-        // Stack
-        stack_allocated: AllocatedSection<K>,
-        max_stack_depth: u64,
-        stack_depth: u64,
-        stack_ptr: K,
+    /// Get the byte order of the cells, used wherever the memory is read or written as bytes
+    fn endianness(&self) -> Endianness {
+        Endianness::Big
+    }
 
-        // Register
-        register_allocated: AllocatedSection<K>,
+    /// Get the fingerprint of the config of the machine, see
+    /// [Config::config_fingerprint](crate::config::Config::config_fingerprint).
+    /// It is written in the persisted traces, snapshots and checkpoints, zero for a machine
+    /// that is not built from a config
+    fn config_fingerprint(&self) -> [u8; 32] {
+        [0u8; 32]
+    }
 
-        /// Register r0
-        pub r0: Register<K>,
-        /// Register r1
-        pub r1: Register<K>,
-        /// Register r2
-        pub r2: Register<K>,
-        /// Register r3
-        pub r3: Register<K>,
-        /// Register r4
-        pub r4: Register<K>,
+    /// Get the number of cell accesses left before the time log overflows,
+    /// an unaligned access uses two of them
+    fn remaining_time_budget(&self) -> u64 {
+        u64::MAX - self.ro_context().time_log()
+    }
 
-        // Trace
-        execution_trace: RBTree<TraceRecord<K, V, S, T>, PhantomData<()>>,
+    /// Get the observer notified of every memory access, [None] if the machine is not observed
+    fn observer(&mut self) -> Option<&mut dyn MemoryObserver<K, V>> {
+        None
     }
 
-    impl<M, K, V, const S: usize, const T: usize> AbstractContext<M, K, V> for StateMachine<K, V, S, T>
-    where
-        Self: core::fmt::Debug
-            + Sized
-            + AbstractMachine<K, V, Context = M::Context, Instruction = M::Instruction>,
-        K: Base<S>,
-        V: Base<T>,
-        M: AbstractMachine<K, V, Machine = StateMachine<K, V, S, T>>,
-    {
-        fn set_stack_depth(&mut self, stack_depth: u64) {
-            self.stack_depth = stack_depth;
+    /// Get the sink the trace records are pushed to instead of the trace, [None] if the machine
+    /// keeps its trace. If the sink rejects a record the access fails and the memory is left
+    /// unchanged, the first record of an unaligned access may already be in the sink.
+    /// The records in the sink are not part of the trace, they are not counted by
+    /// [ResourceLimits::max_trace_len] and not seen by [fork::Fork::merge_into_parent]
+    /// when it looks for conflicts. Only a machine in [TraceMode::Full] uses its sink
+    fn trace_sink(&mut self) -> Option<&mut dyn TraceSink<K, V, S, T>> {
+        None
+    }
+
+    /// Get the granularity of the trace collected by the machine, see [TraceMode]
+    fn trace_mode(&self) -> TraceMode {
+        TraceMode::Full
+    }
+
+    /// Get the sink the records are committed into in [TraceMode::CommitmentsOnly] with the
+    /// backend of [AbstractMemoryMachine::commitment_kind], [None] in the other modes
+    fn trace_hasher(&self) -> Option<&CommitmentSink> {
+        None
+    }
+
+    /// Get the commitment to the records made so far in [TraceMode::CommitmentsOnly],
+    /// see [CommitmentSink::digest], [None] in the other modes
+    fn trace_commitment(&self) -> Option<[u8; 32]> {
+        self.trace_hasher().map(CommitmentSink::digest)
+    }
+
+    /// Get the memory-mapped I/O regions, [None] if no device is mapped.
+    /// The regions are only used to find the device of a cell, the accesses are still checked
+    /// against [AbstractMemoryMachine::memory_layout]
+    fn mmio_layout(&self) -> Option<MemoryLayout<K>> {
+        None
+    }
+
+    /// Get the device mapped to the memory-mapped I/O region of the given kind
+    fn mmio_handler(&mut self, _kind: RegionKind) -> Option<&mut dyn MmioHandler<K, V>> {
+        None
+    }
+
+    /// Get the memory-mapped I/O regions in the order of their kind. A device read does not
+    /// have to match the last write of its cell, see [mmio::without_mmio] to prove the trace
+    fn mmio_regions(&self) -> Vec<MemoryRegion<K>> {
+        self.mmio_layout()
+            .map(|layout| layout.all_regions())
+            .unwrap_or_default()
+    }
+
+    /// Get the limits on the resources used by the machine, unlimited by default
+    fn resource_limits(&self) -> ResourceLimits {
+        ResourceLimits::default()
+    }
+
+    /// Get the number of records kept in the trace if the machine counts them as they are
+    /// tracked, [None] if the machine does not count them
+    fn record_counter(&self) -> Option<u64> {
+        None
+    }
+
+    /// Get the counter of [AbstractMemoryMachine::record_counter] to update it
+    fn record_counter_mut(&mut self) -> Option<&mut u64> {
+        None
+    }
+
+    /// Get the number of records kept in the trace, a machine without a record counter walks
+    /// its trace with [AbstractMachine::trace_len]
+    fn record_count(&self) -> u64 {
+        self.record_counter()
+            .unwrap_or_else(|| self.trace_len() as u64)
+    }
+
+    /// Get the resources used by the machine
+    fn resource_usage(&self) -> ResourceUsage {
+        ResourceUsage {
+            trace_len: self.record_count(),
+            distinct_cells: self.ro_context().ro_memory().len() as u64,
+            bytes_written: self.bytes_written(),
         }
+    }
 
-        fn stack_depth(&self) -> u64 {
-            self.stack_depth
+    /// Get the number of bytes written since the machine started, machines that do not count
+    /// them return zero so [ResourceLimits::max_bytes_written] is not enforced
+    fn bytes_written(&self) -> u64 {
+        0
+    }
+
+    /// Count the bytes of a write, see [AbstractMemoryMachine::bytes_written]
+    fn add_bytes_written(&mut self, _bytes: u64) {}
+
+    /// Get the gas charged for every access, free by default
+    fn cost_model(&self) -> CostModel {
+        CostModel::default()
+    }
+
+    /// Get the maximum gas used since the machine started, [None] leaves the gas unlimited
+    fn gas_budget(&self) -> Option<u64> {
+        None
+    }
+
+    /// Get the gas used since the machine started, machines that do not count it return zero
+    /// so [AbstractMemoryMachine::gas_budget] is not enforced
+    fn gas_used(&self) -> u64 {
+        0
+    }
+
+    /// Set the gas used since the machine started, see [AbstractMemoryMachine::gas_used]
+    fn set_gas_used(&mut self, _gas_used: u64) {}
+
+    /// Check whether the memory section can grow with [AbstractMemoryMachine::grow],
+    /// it is fixed by default
+    fn growable(&self) -> bool {
+        false
+    }
+
+    /// Get the maximum size of the memory section in bytes, its current size by default
+    fn max_memory_size(&self) -> K {
+        let (low, high) = self.get_memory_address();
+        section_size(low, high)
+    }
+
+    /// Move the end of the memory section to the given address, see [AbstractMemoryMachine::grow]
+    fn set_memory_end(&mut self, _high: K) {}
+
+    /// Get the commitment to the memory image installed by [AbstractMemoryMachine::init_from],
+    /// [None] if the machine started from a zeroed memory
+    fn initial_commitment(&self) -> Option<MerkleRoot> {
+        None
+    }
+
+    /// Keep the commitment to the initial memory image,
+    /// return [Error::InitialImageUnsupported] if the machine does not keep it
+    fn set_initial_commitment(&mut self, _root: MerkleRoot) -> Result<(), Error> {
+        Err(Error::InitialImageUnsupported)
+    }
+
+    /// Get the cache of [AbstractMemoryMachine::final_state_root], the machine must clear it
+    /// whenever its memory may change. [None] if the root is not cached
+    fn state_root_cache(&self) -> Option<&Cell<Option<(CommitmentBackend, MerkleRoot)>>> {
+        None
+    }
+
+    /// Get the backend committing the memory image and the trace, see
+    /// [Config::commitment](crate::config::Config::commitment)
+    fn commitment_kind(&self) -> CommitmentKind {
+        CommitmentKind::default()
+    }
+
+    /// Get the cell keeping the backend of [AbstractMemoryMachine::commitment_kind] once it is
+    /// instantiated. [None] if the backend is instantiated on every use
+    fn committer_cache(&self) -> Option<&OnceCell<StateCommitter>> {
+        None
+    }
+
+    /// Get the backend of [AbstractMemoryMachine::commitment_kind], it is instantiated on
+    /// first use. Return [Error::CommitmentUnsupported] if the machines do not support it
+    fn state_committer(&self) -> Result<Cow<'_, StateCommitter>, Error> {
+        match self.committer_cache() {
+            Some(cache) => instantiate(cache, self.commitment_kind()).map(Cow::Borrowed),
+            None => StateCommitter::new(self.commitment_kind()).map(Cow::Owned),
         }
+    }
 
-        fn stack_ptr(&self) -> K {
-            self.stack_ptr
+    /// Get the write-once regions of the memory layout, see [MemoryLayout::write_once_regions]
+    fn write_once_regions(&self) -> Vec<MemoryRegion<K>> {
+        self.memory_layout()
+            .map(|layout| layout.write_once_regions())
+            .unwrap_or_default()
+    }
+
+    /// Get the time logs of the first writes to the cells of the write-once regions, pushes
+    /// included. [None] if the machine does not keep them, the trace is searched instead
+    fn first_writes(&self) -> Option<&FirstWrites<K>> {
+        None
+    }
+
+    /// Get the time logs of the first writes to update them, see
+    /// [AbstractMemoryMachine::first_writes]
+    fn first_writes_mut(&mut self) -> Option<&mut FirstWrites<K>> {
+        None
+    }
+
+    /// Get the time log of the first write to the cell of a write-once region. A machine that
+    /// does not keep [AbstractMemoryMachine::first_writes] searches its trace, the records
+    /// pushed to a [TraceSink] or not kept by its [TraceMode] are then not found
+    fn first_write_time(&self, cell: K) -> Option<u64> {
+        match self.first_writes() {
+            Some(first_writes) => first_writes.get(&cell).copied(),
+            None => self
+                .trace_iter()
+                .find(|record| record.address() == cell && record.instruction().is_write())
+                .map(|record| record.time_log()),
         }
+    }
 
-        fn time_log(&self) -> u64 {
-            self.time_log
+    /// Check that every cell is inside a region of the memory layout that grants the access,
+    /// [None] only checks that the cells are inside a region.
+    /// A write to a cell of a write-once region that was already written is rejected with
+    /// [Error::WriteOnceViolation]
+    fn check_access(&self, cells: &[K], access: Option<Permission>) -> Result<(), Error> {
+        let layout = match self.memory_layout() {
+            Some(layout) => layout,
+            None => return Ok(()),
+        };
+        cells.iter().try_for_each(|cell| {
+            let region = layout.check(*cell, self.cell_size(*cell))?;
+            match access {
+                Some(access) if !region.permission.allows(access) => Err(Error::PermissionDenied {
+                    addr: cell.fixed_be_bytes(),
+                    region: region.kind,
+                    access,
+                }),
+                Some(Permission::Write) if region.write_once => {
+                    match self.first_write_time(*cell) {
+                        Some(first_write_time) => Err(Error::WriteOnceViolation {
+                            addr: cell.fixed_be_bytes(),
+                            first_write_time,
+                        }),
+                        None => Ok(()),
+                    }
+                }
+                _ => Ok(()),
+            }
+        })
+    }
+
+    /// Check that the word at the address is inside the memory section,
+    /// return [Error::AddressOutOfBounds] otherwise
+    fn check_bounds(&self, address: K) -> Result<(), Error> {
+        let (low, high) = self.get_memory_address();
+        let one = K::from(1u64);
+        let inside = address >= low
+            && address
+                .checked_add(self.word_size() - one)
+                .is_some_and(|last| last <= high);
+        if inside {
+            return Ok(());
         }
+        Err(Error::AddressOutOfBounds {
+            addr: address.fixed_be_bytes(),
+            memory_size: section_size(low, high).fixed_be_bytes(),
+        })
+    }
 
-        fn set_time_log(&mut self, time_log: u64) {
-            self.time_log = time_log;
+    /// Grow the memory section by `additional_cells` cells like `memory.grow` of WebAssembly,
+    /// return the new last address of the memory section. The section keeps its base, so the
+    /// written cells and the regions of the memory layout stay valid, and the growth is not
+    /// recorded in the trace. Every added cell costs [CostModel::grow] gas.
+    /// Return [Error::MemoryNotGrowable] if the memory section is fixed,
+    /// [Error::MemoryGrowthLimit] if it would exceed [AbstractMemoryMachine::max_memory_size]
+    /// and [Error::OutOfGas] if the growth exceeds the gas budget
+    fn grow(&mut self, additional_cells: u64) -> Result<K, Error> {
+        if !self.growable() {
+            return Err(Error::MemoryNotGrowable);
         }
+        let (low, high) = self.get_memory_address();
+        let max_memory_size = self.max_memory_size();
+        let size = K::try_from(u128::from(additional_cells))
+            .ok()
+            .and_then(|cells| cells.checked_mul(self.word_size()))
+            .and_then(|added| section_size(low, high).checked_add(added));
+        let new_high = match size {
+            Some(size) if size <= max_memory_size => low + (size - K::from(1u64)),
+            _ => {
+                return Err(Error::MemoryGrowthLimit {
+                    max_memory_size: max_memory_size.fixed_be_bytes(),
+                    attempted: size.unwrap_or(K::MAX).fixed_be_bytes(),
+                })
+            }
+        };
+        let cost = self.cost_model().grow.saturating_mul(additional_cells);
+        let gas_used = check_gas_cost(self, cost)?;
+        self.set_memory_end(new_high);
+        self.set_gas_used(gas_used);
+        Ok(new_high)
+    }
 
-        fn set_stack_ptr(&mut self, stack_ptr: K) {
-            self.stack_ptr = stack_ptr;
+    /// Check that every cell can be read under the read policy,
+    /// the memory-mapped I/O cells are always readable
+    fn check_initialized(&self, cells: &[K]) -> Result<(), Error> {
+        if self.read_policy() == ReadPolicy::ZeroOnUninit {
+            return Ok(());
+        }
+        let mmio = self.mmio_layout();
+        match cells.iter().find(|cell| {
+            self.ro_context().ro_memory().get(cell).is_none()
+                && !mmio
+                    .is_some_and(|mmio| mmio.region_of(**cell, self.cell_size(**cell)).is_some())
+        }) {
+            Some(cell) => Err(Error::UninitializedRead {
+                addr: cell.fixed_be_bytes(),
+            }),
+            None => Ok(()),
         }
+    }
 
-        fn memory(&mut self) -> &'_ mut RBTree<K, V> {
-            &mut self.memory
+    /// Read from memory
+    fn read(&mut self, address: K) -> Result<CellInteraction<K, V>, Error> {
+        self.read_with_instruction(address, MemoryInstruction::Read)
+    }
+
+    /// Read from memory and record the given instruction in the trace
+    fn read_with_instruction(
+        &mut self,
+        address: K,
+        instruction: MemoryInstruction,
+    ) -> Result<CellInteraction<K, V>, Error> {
+        let cell_size = self.cell_size(address);
+        let address = align_cell_address(self, address, cell_size)?;
+        let remain = address % cell_size;
+        if remain.is_zero() {
+            self.check_access(&[address], Some(Permission::Read))?;
+            self.check_initialized(&[address])?;
+            let time_log = next_time_log(self, 1)?;
+            check_resources(self, 1, &[])?;
+            let gas_used = check_gas(self, instruction)?;
+            // Read on a cell
+            let result = load_cell(self, address);
+            emit(
+                self,
+                record_of(self, time_log, instruction, address, result),
+            )?;
+            self.context().set_time_log(time_log + 1);
+            self.set_gas_used(gas_used);
+            if let Some(observer) = self.observer() {
+                observer.on_read(address, result, time_log);
+            }
+
+            // Return single cell read
+            Ok(CellInteraction::SingleCell(instruction, address, result))
+        } else {
+            // Get the address of 2 cells
+            let (addr_lo, addr_hi) = self.compute_address(address, remain)?;
+            self.check_access(&[addr_lo, addr_hi], Some(Permission::Read))?;
+            self.check_initialized(&[addr_lo, addr_hi])?;
+            let time_log = next_time_log(self, 2)?;
+            check_resources(self, 2, &[])?;
+            let gas_used = check_gas(self, instruction)?;
+            // Get the 2 cells
+            let val_lo = load_cell(self, addr_lo);
+            let val_hi = load_cell(self, addr_hi);
+            let cell_size = self.word_size().into();
+            let part_lo = (address - addr_lo).into();
+            let part_hi = cell_size - part_lo;
+            let endianness = self.endianness();
+            let mut buf = [0u8; T];
+
+            // Concat values from 2 cells
+            buf[part_hi..cell_size]
+                .copy_from_slice(&endianness.to_bytes::<V, T>(val_hi)[0..part_lo]);
+            buf[0..part_hi]
+                .copy_from_slice(&endianness.to_bytes::<V, T>(val_lo)[part_lo..cell_size]);
+
+            // @TODO: Read in the middle of 2 cells need to be translated correctly
+            // Both records are recorded or none, the time log only moves afterwards
+            let records = [
+                record_of(self, time_log, instruction, addr_lo, val_lo),
+                record_of(self, time_log + 1, instruction, addr_hi, val_hi),
+            ];
+            emit_all(self, &records)?;
+
+            self.context().set_time_log(time_log + 2);
+            self.set_gas_used(gas_used);
+            if let Some(observer) = self.observer() {
+                observer.on_read(addr_lo, val_lo, time_log);
+                observer.on_read(addr_hi, val_hi, time_log + 1);
+            }
+
+            // Return double cells read
+            Ok(CellInteraction::DoubleCell(
+                instruction,
+                address,
+                endianness.from_bytes(buf),
+                addr_lo,
+                val_lo,
+                addr_hi,
+                val_hi,
+            ))
         }
     }
 
-    impl<M, K, V, const S: usize, const T: usize> AbstractInstruction<M, K, V>
-        for MyInstruction<M, K, V, S, T>
-    where
-        Self: core::fmt::Debug + Sized,
-        K: Base<S>,
-        V: Base<T>,
-        M: AbstractMachine<K, V, Machine = StateMachine<K, V, S, T>>,
-    {
-        fn exec(&self, machine: &mut M::Machine) {
-            match self {
-                MyInstruction::Invalid(_) => {
-                    panic!("Invalid instruction")
-                }
-                MyInstruction::Read(addr) => {
-                    if !machine.memory_allocated.contain(*addr) {
-                        panic!("{}", Error::MemoryAccessDeinied);
-                    } else {
-                        machine.read(*addr).expect("Unable to read to memory");
-                    }
-                }
-                MyInstruction::Write(addr, val) => {
-                    if !machine.memory_allocated.contain(*addr) {
-                        panic!("{}", Error::MemoryAccessDeinied);
-                    } else {
-                        machine
-                            .write(*addr, *val)
-                            .expect("Unable to write to memory");
-                    }
-                }
-                MyInstruction::Push(value) => {
-                    machine.push(*value).expect("Unable to push value to stack");
-                }
-                MyInstruction::Pop() => {
-                    machine.pop().expect("Unable to pop value from stack");
-                }
-                MyInstruction::Mov(reg1, reg2) => {
-                    match machine.get(*reg2).expect("Unable to access register 1") {
-                        CellInteraction::SingleCell(_, _, value) => {
-                            machine.set(*reg1, value).expect("Unable to set register 2");
-                        }
-                        _ => panic!("Register unable to be two cells"),
-                    }
-                    // Mov value from register 2 to register 1
-                }
-                MyInstruction::Swap(reg) => {
-                    match machine.pop().expect("Unable to pop value from stack") {
-                        (_, CellInteraction::SingleCell(_op, _addr, value)) => {
-                            machine
-                                .push(value)
-                                .expect("Unable to push register's value to stack");
-                            machine.set(*reg, value).expect("Unable to set register");
-                        }
-                        _ => panic!("Stack unable to be two cells"),
-                    };
-                }
-                MyInstruction::Load(reg, addr) => {
-                    match machine.read(*addr).expect("Unable to read memory") {
-                        CellInteraction::SingleCell(_, _, value) => {
-                            machine.set(*reg, value).expect("Unable to set register");
-                        }
-                        CellInteraction::DoubleCell(_, _, cvalue, _, _, _, _) => {
-                            machine.set(*reg, cvalue).expect("Unable to set register");
-                        }
-                    };
-                }
-                MyInstruction::Save(address, reg) => {
-                    match machine.get(*reg).expect("Unable to access register") {
-                        CellInteraction::SingleCell(_, _, value) => {
-                            machine
-                                .write(*address, value)
-                                .expect("Unable to write to memory");
-                        }
-                        _ => panic!("Register unable to be two cells"),
-                    }
+    /// Write to memory
+    fn write(&mut self, address: K, value: V) -> Result<CellInteraction<K, V>, Error> {
+        self.write_with_instruction(address, value, MemoryInstruction::Write)
+    }
+
+    /// Write to memory and record the given instruction in the trace
+    fn write_with_instruction(
+        &mut self,
+        address: K,
+        value: V,
+        instruction: MemoryInstruction,
+    ) -> Result<CellInteraction<K, V>, Error> {
+        write_cells(self, address, value, instruction, Some(Permission::Write))
+    }
+
+    /// Write to memory while the program image is installed, the write is recorded in the
+    /// trace like any other write but ignores the permissions of the memory regions
+    fn write_unchecked_init(
+        &mut self,
+        address: K,
+        value: V,
+    ) -> Result<CellInteraction<K, V>, Error> {
+        write_cells(self, address, value, MemoryInstruction::Write, None)
+    }
+
+    /// Write a byte buffer to memory starting at any byte address, the bytes of a cell follow
+    /// [AbstractMemoryMachine::endianness].
+    /// Fully covered cells are written directly, partially covered cells are read first and
+    /// written back with the spliced bytes, so the trace holds a Read then a Write for them
+    fn write_bytes(
+        &mut self,
+        address: K,
+        bytes: &[u8],
+    ) -> Result<Vec<CellInteraction<K, V>>, Error> {
+        let endianness = self.endianness();
+        let mut interactions = Vec::new();
+        for (cell, offset, range) in byte_cells(address, bytes.len(), self.word_size(), T)? {
+            let mut buf = [0u8; T];
+            if range.len() < T {
+                let interaction = self.read(cell)?;
+                match interaction {
+                    CellInteraction::SingleCell(_, _, value) => buf = endianness.to_bytes(value),
+                    _ => return Err(Error::MemoryInvalidInteraction),
                 }
-                MyInstruction::Add(reg1, reg2) => {
-                    match machine.get(*reg1).expect("Unable to access register 1") {
-                        CellInteraction::SingleCell(_, _, value1) => {
-                            match machine.get(*reg2).expect("Unable to access register 2") {
-                                CellInteraction::SingleCell(_, _, value2) => {
-                                    machine
-                                        .set(*reg1, value1 + value2)
-                                        .expect("Unable to set register 1");
-                                }
-                                _ => panic!("Register unable to be two cells"),
-                            }
-                        }
-                        _ => panic!("Register unable to be two cells"),
-                    }
+                interactions.push(interaction);
+            }
+            buf[offset..offset + range.len()].copy_from_slice(&bytes[range]);
+            interactions.push(self.write(cell, endianness.from_bytes(buf))?);
+        }
+        Ok(interactions)
+    }
+
+    /// Read `len` bytes from memory starting at any byte address, the bytes of a cell follow
+    /// [AbstractMemoryMachine::endianness]. Every touched cell is recorded as a Read in the trace
+    fn read_bytes(&mut self, address: K, len: usize) -> Result<Vec<u8>, Error> {
+        let endianness = self.endianness();
+        let mut bytes = Vec::with_capacity(len);
+        for (cell, offset, range) in byte_cells(address, len, self.word_size(), T)? {
+            match self.read(cell)? {
+                CellInteraction::SingleCell(_, _, value) => {
+                    let buf: [u8; T] = endianness.to_bytes(value);
+                    bytes.extend_from_slice(&buf[offset..offset + range.len()]);
                 }
+                _ => return Err(Error::MemoryInvalidInteraction),
             }
         }
+        Ok(bytes)
     }
 
-    impl<K, V, const S: usize, const T: usize> StateMachine<K, V, S, T>
-    where
-        K: Base<S>,
-        V: Base<T>,
-    {
-        /// Create a new RAM machine
-        pub fn new(config: ConfigArgs<K>) -> Self {
-            let config = Config::new(K::WORD_SIZE, config);
-            Self {
-                // Memory section
-                memory: RBTree::new(),
-                memory_allocated: config.memory,
-                word_size: config.word_size,
-                time_log: 0,
+    /// Copy `len` bytes from the source to the destination address, like the MCOPY instruction.
+    /// Every source cell is read before the first destination cell is written, so overlapping
+    /// ranges are copied as if through an intermediate buffer. The destination is written with
+    /// [AbstractMemoryMachine::write_bytes], return its interactions
+    fn copy(&mut self, dst: K, src: K, len: usize) -> Result<Vec<CellInteraction<K, V>>, Error> {
+        // Reject the destination before the source reads are recorded
+        let cells: Vec<K> = byte_cells(dst, len, self.word_size(), T)?
+            .into_iter()
+            .map(|(cell, _, _)| cell)
+            .collect();
+        self.check_access(&cells, Some(Permission::Write))?;
+        let bytes = self.read_bytes(src, len)?;
+        self.write_bytes(dst, &bytes)
+    }
 
-                // Stack
-                stack_allocated: config.stack,
-                max_stack_depth: config.stack_depth.into(),
-                stack_depth: 0,
-                stack_ptr: K::zero(),
+    /// Set `len` bytes starting at the destination address to the byte, like memset.
+    /// The destination is written with [AbstractMemoryMachine::write_bytes], return its
+    /// interactions
+    fn fill(&mut self, dst: K, byte: u8, len: usize) -> Result<Vec<CellInteraction<K, V>>, Error> {
+        self.write_bytes(dst, &vec![byte; len])
+    }
 
-                // Register
-                register_allocated: config.register,
-                r0: config.create_register(0),
-                r1: config.create_register(1),
-                r2: config.create_register(2),
-                r3: config.create_register(3),
-                r4: config.create_register(4),
+    /// Write a byte buffer to consecutive cells starting at the base address, the tail of the
+    /// last cell is zero-padded. Unlike [AbstractMemoryMachine::write_bytes] no cell is read
+    /// first, so the bytes after the buffer in its last cell are overwritten.
+    /// Every touched cell is checked before the first write, return the number of cells written
+    fn write_slice(&mut self, base: K, data: &[u8]) -> Result<usize, Error> {
+        write_chunks(self, base, data, Some(Permission::Write))
+    }
 
-                // Execution trace
-                execution_trace: RBTree::new(),
+    /// Read `len` bytes from consecutive cells starting at the base address without
+    /// recording any trace, inverse of [AbstractMemoryMachine::write_slice]
+    fn read_slice(&self, base: K, len: usize) -> Result<Vec<u8>, Error> {
+        let base = align_address(self.alignment_policy(), base, self.word_size())?;
+        let cells = byte_cells(base, len, self.word_size(), T)?;
+        let addresses: Vec<K> = cells.iter().map(|(cell, _, _)| *cell).collect();
+        self.check_access(&addresses, Some(Permission::Read))?;
+        let endianness = self.endianness();
+        let memory = self.ro_context().ro_memory();
+        let mut bytes = Vec::with_capacity(len);
+        for (cell, offset, range) in cells {
+            let buf: [u8; T] = endianness.to_bytes(memory.get(&cell).copied().unwrap_or(V::zero()));
+            bytes.extend_from_slice(&buf[offset..offset + range.len()]);
+        }
+        Ok(bytes)
+    }
+
+    /// Get the written cells among `len_cells` consecutive cells starting at the address,
+    /// without recording any trace. The memory is sparse, so the cells that were never written
+    /// are skipped, and the range stops at the end of the memory section
+    fn dump(&self, start: K, len_cells: usize) -> Vec<(K, V)> {
+        let memory = self.ro_context().ro_memory();
+        cells_in_memory(self, start, len_cells)
+            .into_iter()
+            .filter_map(|cell| memory.get(&cell).map(|value| (cell, *value)))
+            .collect()
+    }
+
+    /// Commit the current memory image, stack and register cells included, with the walk and
+    /// the backend of [AbstractMemoryMachine::commitment_kind]. The root is the one of
+    /// [StateCommitter::commit_image] and is cached until the memory changes, see
+    /// [CommitmentBackend::commit_with] for the errors
+    fn final_state_root(&self, backend: CommitmentBackend) -> Result<MerkleRoot, Error> {
+        let cache = self.state_root_cache();
+        if let Some((cached, root)) = cache.and_then(Cell::get) {
+            if cached == backend {
+                return Ok(root);
             }
         }
+        let cells: Vec<(K, V)> = self
+            .ro_context()
+            .ro_memory()
+            .iter()
+            .map(|(address, value)| (*address, *value))
+            .collect();
+        let root = backend.commit_with(&self.state_committer()?, &cells, self.word_size())?;
+        if let Some(cache) = cache {
+            cache.set(Some((backend, root)));
+        }
+        Ok(root)
     }
 
-    impl<K, V, const S: usize, const T: usize> AbstractMachine<K, V> for StateMachine<K, V, S, T>
+    /// Commit the current memory image, stack and register cells included, with a
+    /// commitment scheme. Every cell is committed as a write at time zero, in address order.
+    /// Return [Error::WitnessWordTooWide] if the words are wider than 256 bits
+    fn commit_final_state<F, C>(&self, scheme: &C) -> Result<C::Commitment, Error>
     where
-        K: Base<S>,
-        V: Base<T>,
+        F: Field + PrimeField,
+        C: CommitmentScheme<F>,
     {
-        type Machine = Self;
-        type Context = Self;
-        type Instruction = MyInstruction<Self, K, V, S, T>;
-        type TraceRecord = TraceRecord<K, V, S, T>;
+        let records: Vec<ConvertedTraceRecord<F>> = self
+            .ro_context()
+            .ro_memory()
+            .iter()
+            .map(|(address, value)| {
+                let record = TraceRecord::<K, V, S, T>::new(
+                    0,
+                    0,
+                    MemoryInstruction::Write,
+                    *address,
+                    *value,
+                );
+                ConvertedTraceRecord::from_trace_record(&record)
+            })
+            .collect::<Result<_, _>>()?;
+        scheme.commit(&records)
+    }
 
-        fn context(&mut self) -> &'_ mut Self::Context {
-            self
+    /// Save the memory cells, the stack pointer, the stack depth, the time log, the gas used
+    /// and the config fingerprint
+    fn snapshot(&self) -> MemorySnapshot<K, V> {
+        let context = self.ro_context();
+        MemorySnapshot {
+            cells: context
+                .ro_memory()
+                .iter()
+                .map(|(address, value)| (*address, *value))
+                .collect(),
+            stack_ptr: context.stack_ptr(),
+            stack_depth: context.stack_depth(),
+            time_log: context.time_log(),
+            gas_used: self.gas_used(),
+            config_fingerprint: self.config_fingerprint(),
+        }
+    }
+
+    /// Roll the machine back to a snapshot taken earlier on the same machine.
+    /// The trace records made after the snapshot are discarded and the time log is rewound,
+    /// so the next access continues the trace right after the last kept record.
+    /// Restoring invalidates every snapshot taken after the restored one.
+    /// The records pushed to a trace sink or committed in [TraceMode::CommitmentsOnly] can
+    /// not be taken back, return [Error::TraceInSink] if the machine has a trace sink and
+    /// [Error::TraceNotRetained] if it commits its records
+    fn restore(&mut self, snapshot: &MemorySnapshot<K, V>) -> Result<(), Error> {
+        if self.trace_hasher().is_some() {
+            return Err(Error::TraceNotRetained {
+                mode: self.trace_mode(),
+            });
+        }
+        if self.trace_mode() == TraceMode::Full && self.trace_sink().is_some() {
+            return Err(Error::TraceInSink);
+        }
+        self.truncate_trace(snapshot.time_log);
+        if let Some(first_writes) = self.first_writes_mut() {
+            first_writes.retain(|_, time_log| *time_log < snapshot.time_log);
         }
+        let trace_len = self.trace_len() as u64;
+        if let Some(counter) = self.record_counter_mut() {
+            *counter = trace_len;
+        }
+        let context = self.context();
+        let memory = context.memory();
+        memory.clear();
+        for (address, value) in snapshot.cells.iter() {
+            memory.replace_or_insert(*address, *value);
+        }
+        context.set_stack_ptr(snapshot.stack_ptr);
+        context.set_stack_depth(snapshot.stack_depth);
+        context.set_time_log(snapshot.time_log);
+        self.set_gas_used(snapshot.gas_used);
+        Ok(())
+    }
+
+    /// Write the memory cells, the stack, the time log and the trace of the machine to the
+    /// writer in the format of [checkpoint], return [Error::TraceIo] if the writer fails and
+    /// [Error::TraceNotRetained] if the machine does not keep its trace
+    #[cfg(feature = "std")]
+    fn checkpoint_to<W: Write>(&self, mut writer: W) -> Result<(), Error>
+    where
+        Self: Sized,
+    {
+        require_full_trace(self)?;
+        writer
+            .write_all(&checkpoint::encode_checkpoint(self))
+            .and_then(|_| writer.flush())
+            .map_err(|_| Error::TraceIo)
+    }
+
+    /// Resume the state written by [AbstractMemoryMachine::checkpoint_to] on this machine,
+    /// which must be fresh and built with the same configuration as the checkpointed one.
+    /// The next accesses continue the trace of the checkpoint, see
+    /// [checkpoint::resume_checkpoint] for the errors
+    #[cfg(feature = "std")]
+    fn resume_from<R: Read>(mut self, mut reader: R) -> Result<Self, Error>
+    where
+        Self: Sized,
+    {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(|_| Error::TraceIo)?;
+        let check = FingerprintCheck::Expect(self.config_fingerprint());
+        checkpoint::resume_checkpoint(&mut self, &bytes, check)?;
+        Ok(self)
+    }
+
+    /// Fork the machine to run a speculative branch, the branch is either discarded or
+    /// merged back with [Fork::merge_into_parent]
+    fn fork(&self) -> Fork<Self, K, V>
+    where
+        Self: Clone,
+    {
+        Fork::new(self.clone(), self.snapshot())
+    }
+
+    /// Get the cells changed since the snapshot was taken, see [MemorySnapshot::diff]
+    fn dirty_cells_since(&self, snapshot: &MemorySnapshot<K, V>) -> Vec<CellDelta<K, V>> {
+        snapshot.diff(&self.snapshot())
+    }
+
+    /// Install the cells of the image as the initial memory without recording any trace, the
+    /// stack, the time log and the gas used of the snapshot are ignored. The image is committed
+    /// with the backend of [AbstractMemoryMachine::commitment_kind] so the verifier knows the
+    /// initial state, see
+    /// [AbstractMemoryMachine::initial_commitment]. Every cell must be inside a region of the
+    /// memory layout, return [Error::MachineAlreadyStarted] if the trace is not empty and
+    /// [Error::ConfigMismatch] if the image was taken from a machine with another config
+    fn init_from(&mut self, image: &MemorySnapshot<K, V>) -> Result<(), Error> {
+        let check = FingerprintCheck::Expect(self.config_fingerprint());
+        self.init_from_with_check(image, check)
+    }
+
+    /// Install the cells of the image like [AbstractMemoryMachine::init_from], the config
+    /// fingerprint of the image is checked by `check`. [FingerprintCheck::Force] loads an
+    /// image taken under another config, e.g. to migrate it
+    fn init_from_with_check(
+        &mut self,
+        image: &MemorySnapshot<K, V>,
+        check: FingerprintCheck,
+    ) -> Result<(), Error> {
+        if self.record_count() != 0 {
+            return Err(Error::MachineAlreadyStarted);
+        }
+        check.check(image.config_fingerprint())?;
+        let cells: Vec<K> = image.cells().iter().map(|(address, _)| *address).collect();
+        self.check_access(&cells, None)?;
+        let root = self.state_committer()?.commit_image(image.cells())?;
+        self.set_initial_commitment(root)?;
+        let memory = self.context().memory();
+        memory.clear();
+        for (address, value) in image.cells() {
+            memory.replace_or_insert(*address, *value);
+        }
+        Ok(())
+    }
+
+    /// Initialize consecutive cells starting at the given address with the given values.
+    /// The cells are written with [AbstractMemoryMachine::write_unchecked_init], so read only
+    /// regions such as the program image can be loaded
+    fn init_region(&mut self, start: K, values: &[V]) -> Result<Vec<CellInteraction<K, V>>, Error> {
+        let addresses = AddressIterator::new(start, values.len());
+        if addresses.clone().count() < values.len() {
+            return Err(Error::MemoryAccessDeinied);
+        }
+        addresses
+            .zip(values.iter())
+            .map(|(address, value)| self.write_unchecked_init(address, *value))
+            .collect()
+    }
+
+    /// Dump the values of consecutive cells without recording any trace
+    fn dump_region(&mut self, start: K, cells: usize) -> Vec<(K, V)> {
+        AddressIterator::new(start, cells)
+            .map(|address| (address, self.dummy_read(address)))
+            .collect()
+    }
+
+    /// Get the full execution trace, e.g. to build the witness of the circuits.
+    /// Return [Error::TraceNotRetained] if the machine does not keep its trace
+    fn full_trace(&self) -> Result<Vec<TraceRecord<K, V, S, T>>, Error> {
+        require_full_trace(self)?;
+        Ok(self
+            .trace_iter()
+            .map(|record| {
+                TraceRecord::new(
+                    record.time_log(),
+                    record.stack_depth(),
+                    record.instruction(),
+                    record.address(),
+                    record.value(),
+                )
+                .with_source(record.source())
+                .with_cell_size(record.cell_size())
+            })
+            .collect())
+    }
+
+    /// Stream the execution trace to the writer in the JSON Lines format of [jsonl].
+    /// Fail with an error of kind [std::io::ErrorKind::Unsupported] wrapping
+    /// [Error::TraceNotRetained] if the machine does not keep its trace
+    #[cfg(feature = "std")]
+    fn trace_export_jsonl<W: std::io::Write>(&self, writer: W) -> std::io::Result<()> {
+        require_full_trace(self)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::Unsupported, error))?;
+        jsonl::write_trace_jsonl::<_, K, V, W, S, T>(self.trace().iter(), writer)
+    }
+
+    /// Read from memory (only read one whole cell)
+    fn dummy_read(&mut self, address: K) -> V {
+        match self.ro_context().ro_memory().get(&address) {
+            Some(r) => *r,
+            None => V::zero(),
+        }
+    }
+
+    /// Compute the addresses of the 2 cells around an unaligned address
+    fn compute_address(&self, address: K, remain: K) -> Result<(K, K), Error> {
+        let base = pointer_sub(address, remain)?;
+        Ok((base, pointer_add(base, self.word_size())?))
+    }
+}
+
+/// Abstract stack machine
+pub trait AbstractStackMachine<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+    Self: AbstractMemoryMachine<K, V, S, T>,
+{
+    /// Push the value to the stack and return stack_depth
+    fn push(&mut self, value: V) -> Result<(u64, CellInteraction<K, V>), Error> {
+        // Check for stack overflow
+        if self.ro_context().stack_depth() == self.max_stack_depth() {
+            return Err(Error::StackOverflow);
+        }
+        // Push first then update the stack pointer
+        let address = self.ro_context().stack_ptr();
+        if self
+            .stack_guard()
+            .is_some_and(|guard| guard.contain(address))
+        {
+            return Err(Error::StackOverflow);
+        }
+        let next_address = pointer_add(address, self.word_size())?;
+
+        // Update stack depth and stack pointer
+        let stack_depth = self.ro_context().stack_depth() + 1;
+        self.context().set_stack_depth(stack_depth);
+        self.context().set_stack_ptr(next_address);
+
+        match self.write_with_instruction(address, value, MemoryInstruction::Push) {
+            Ok(v) => Ok((stack_depth, v)),
+            Err(e) => {
+                // Keep the stack untouched when the access is rejected
+                self.context().set_stack_depth(stack_depth - 1);
+                self.context().set_stack_ptr(address);
+                Err(e)
+            }
+        }
+    }
+
+    /// Get value from the stack and return stack_depth and value
+    fn pop(&mut self) -> Result<(u64, CellInteraction<K, V>), Error> {
+        // Check for stack underflow
+        if self.ro_context().stack_depth() == 0 {
+            return Err(Error::StackUnderflow);
+        }
+        // Update stack depth and stack pointer
+        let stack_ptr = self.ro_context().stack_ptr();
+        let address = pointer_sub(stack_ptr, self.word_size())?;
+        let stack_depth = self.ro_context().stack_depth() - 1;
+        self.context().set_stack_depth(stack_depth);
+        self.context().set_stack_ptr(address);
+
+        match self.read_with_instruction(address, MemoryInstruction::Pop) {
+            Ok(v) => Ok((stack_depth, v)),
+            Err(e) => {
+                // Keep the stack untouched when the access is rejected
+                self.context().set_stack_depth(stack_depth + 1);
+                self.context().set_stack_ptr(stack_ptr);
+                Err(e)
+            }
+        }
+    }
+
+    /// Read the value `depth` elements below the top of the stack without popping it,
+    /// `peek(0)` reads the top of the stack. The access is recorded as an ordinary read
+    fn peek(&mut self, depth: u64) -> Result<CellInteraction<K, V>, Error> {
+        if depth >= self.ro_context().stack_depth() {
+            return Err(Error::StackUnderflow);
+        }
+        let offset = K::from(depth + 1)
+            .checked_mul(self.word_size())
+            .ok_or(Error::StackUnderflow)?;
+        let address = pointer_sub(self.ro_context().stack_ptr(), offset)?;
+        self.read(address)
+    }
+}
+
+/// Virtual register structure
+#[derive(Debug, Clone, Copy)]
+pub struct Register<K>(usize, K);
+
+impl<K> Register<K>
+where
+    K: Copy,
+{
+    /// Create a new register
+    pub fn new(register_index: usize, register_address: K) -> Self {
+        Self(register_index, register_address)
+    }
+
+    /// Get the register address
+    pub fn address(&self) -> K {
+        self.1
+    }
+
+    /// Get the register index
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Abstract register machine
+pub trait AbstractRegisterMachine<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+    Self: AbstractMemoryMachine<K, V, S, T>,
+{
+    /// Set the value of the register
+    fn set(&mut self, register: Register<K>, value: V) -> Result<CellInteraction<K, V>, Error> {
+        self.write(register.address(), value)
+    }
+
+    /// Get the value of the register
+    fn get(&mut self, register: Register<K>) -> Result<CellInteraction<K, V>, Error> {
+        self.read(register.address())
+    }
+
+    /// Set the value of the register at the given index
+    fn set_register(
+        &mut self,
+        register_index: usize,
+        value: V,
+    ) -> Result<CellInteraction<K, V>, Error> {
+        let register = self
+            .new_register(register_index)
+            .ok_or(Error::RegisterOutOfRange)?;
+        self.set(register, value)
+    }
+
+    /// Get the value of the register at the given index
+    fn get_register(&mut self, register_index: usize) -> Result<CellInteraction<K, V>, Error> {
+        let register = self
+            .new_register(register_index)
+            .ok_or(Error::RegisterOutOfRange)?;
+        self.get(register)
+    }
+
+    /// Create new register from index, return [None] if the index is out of the register section
+    fn new_register(&self, register_index: usize) -> Option<Register<K>>;
+}
+
+impl<K, V, const S: usize, const T: usize> AbstractTraceRecord<K, V> for TraceRecord<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn new(
+        time_log: u64,
+        stack_depth: u64,
+        instruction: MemoryInstruction,
+        address: K,
+        value: V,
+    ) -> Self {
+        Self {
+            time_log,
+            stack_depth,
+            instruction,
+            address,
+            value,
+            source: 0,
+            cell_size: 0,
+        }
+    }
+
+    fn time_log(&self) -> u64 {
+        self.time_log
+    }
+
+    fn stack_depth(&self) -> u64 {
+        self.stack_depth
+    }
+
+    fn address(&self) -> K {
+        self.address
+    }
+
+    fn value(&self) -> V {
+        self.value
+    }
+
+    fn instruction(&self) -> MemoryInstruction {
+        self.instruction
+    }
+
+    fn source(&self) -> u8 {
+        self.source
+    }
+
+    fn cell_size(&self) -> u8 {
+        self.cell_size
+    }
+
+    fn with_cell_size(self, cell_size: u8) -> Self {
+        Self { cell_size, ..self }
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> PartialOrd for TraceRecord<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> Ord for TraceRecord<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        match self.time_log.cmp(&other.time_log) {
+            core::cmp::Ordering::Equal => {
+                panic!("Time log never been equal")
+            }
+            ord => ord,
+        }
+    }
+}
+
+// pub trait KZGMemoryCommitment
+
+#[macro_export]
+/// Export macro for implementing [AbstractMemoryMachine](crate::machine::AbstractMemoryMachine) trait.
+/// With the name of a [MachineState](crate::machine::state::MachineState) field the hooks of the
+/// trait are taken from the config of the state, otherwise the trait defaults are used
+macro_rules! impl_state_machine {
+    ($machine_struct: ident) => {
+        use zkmemory::machine::AbstractMemoryMachine;
+
+        impl<K, V, const S: usize, const T: usize> AbstractMemoryMachine<K, V, S, T>
+            for $machine_struct<K, V, S, T>
+        where
+            K: Base<S>,
+            V: Base<T>,
+            Self: AbstractMachine<K, V>,
+        {
+        }
+    };
+    ($machine_struct: ident, $state: ident) => {
+        use zkmemory::machine::AbstractMemoryMachine;
+
+        impl<K, V, const S: usize, const T: usize> AbstractMemoryMachine<K, V, S, T>
+            for $machine_struct<K, V, S, T>
+        where
+            K: Base<S>,
+            V: Base<T>,
+            Self: AbstractMachine<K, V>,
+        {
+            fn alignment_policy(&self) -> zkmemory::config::AlignmentPolicy {
+                self.$state.config().alignment
+            }
+
+            fn memory_layout(&self) -> Option<zkmemory::config::MemoryLayout<K>> {
+                self.$state.config().layout
+            }
+
+            fn stack_guard(&self) -> Option<zkmemory::config::AllocatedSection<K>> {
+                self.$state.config().stack_guard
+            }
+
+            fn read_policy(&self) -> zkmemory::config::ReadPolicy {
+                self.$state.config().read_policy
+            }
+
+            fn endianness(&self) -> zkmemory::config::Endianness {
+                self.$state.config().endianness
+            }
+
+            fn config_fingerprint(&self) -> [u8; 32] {
+                self.$state.config_fingerprint()
+            }
+
+            fn trace_mode(&self) -> zkmemory::config::TraceMode {
+                self.$state.config().trace_mode
+            }
+
+            fn trace_hasher(&self) -> Option<&zkmemory::commitment::selector::CommitmentSink> {
+                self.$state.trace_hasher()
+            }
+
+            fn mmio_layout(&self) -> Option<zkmemory::config::MemoryLayout<K>> {
+                self.$state.mmio_layout()
+            }
+
+            fn mmio_handler(
+                &mut self,
+                kind: zkmemory::config::RegionKind,
+            ) -> Option<&mut dyn zkmemory::machine::mmio::MmioHandler<K, V>> {
+                self.$state.mmio_handler(kind)
+            }
+
+            fn resource_limits(&self) -> zkmemory::config::ResourceLimits {
+                self.$state.config().limits
+            }
+
+            fn bytes_written(&self) -> u64 {
+                self.$state.bytes_written()
+            }
+
+            fn add_bytes_written(&mut self, bytes: u64) {
+                self.$state.add_bytes_written(bytes);
+            }
+
+            fn cost_model(&self) -> zkmemory::config::CostModel {
+                self.$state.config().cost_model
+            }
+
+            fn gas_budget(&self) -> Option<u64> {
+                self.$state.config().gas_budget
+            }
+
+            fn gas_used(&self) -> u64 {
+                self.$state.gas_used()
+            }
+
+            fn set_gas_used(&mut self, gas_used: u64) {
+                self.$state.set_gas_used(gas_used);
+            }
+
+            fn first_writes(&self) -> Option<&zkmemory::machine::FirstWrites<K>> {
+                Some(self.$state.first_writes())
+            }
+
+            fn first_writes_mut(&mut self) -> Option<&mut zkmemory::machine::FirstWrites<K>> {
+                Some(self.$state.first_writes_mut())
+            }
+
+            fn record_counter(&self) -> Option<u64> {
+                Some(self.$state.record_counter())
+            }
+
+            fn record_counter_mut(&mut self) -> Option<&mut u64> {
+                Some(self.$state.record_counter_mut())
+            }
+
+            fn growable(&self) -> bool {
+                self.$state.config().growable
+            }
+
+            fn max_memory_size(&self) -> K {
+                self.$state.config().max_memory_size
+            }
+
+            fn set_memory_end(&mut self, high: K) {
+                self.$state.set_memory_end(high);
+            }
+
+            fn initial_commitment(&self) -> Option<zkmemory::commitment::merkle::MerkleRoot> {
+                self.$state.initial_commitment()
+            }
+
+            fn set_initial_commitment(
+                &mut self,
+                root: zkmemory::commitment::merkle::MerkleRoot,
+            ) -> Result<(), zkmemory::error::Error> {
+                self.$state.set_initial_commitment(root);
+                Ok(())
+            }
+
+            fn commitment_kind(&self) -> zkmemory::commitment::selector::CommitmentKind {
+                self.$state.config().commitment
+            }
+
+            fn committer_cache(
+                &self,
+            ) -> Option<&core::cell::OnceCell<zkmemory::commitment::selector::StateCommitter>> {
+                Some(self.$state.committer_cache())
+            }
+        }
+    };
+}
+
+#[macro_export]
+/// Export macro for implementing [AbstractRegisterMachine](crate::machine::AbstractRegisterMachine) trait
+macro_rules! impl_register_machine {
+    ($machine_struct: ident) => {
+        use zkmemory::machine::AbstractRegisterMachine;
+
+        impl<K, V, const S: usize, const T: usize> AbstractRegisterMachine<K, V, S, T>
+            for $machine_struct<K, V, S, T>
+        where
+            K: Base<S>,
+            V: Base<T>,
+            Self: AbstractMemoryMachine<K, V, S, T>,
+        {
+            fn new_register(
+                &self,
+                register_index: usize,
+            ) -> Option<zkmemory::machine::Register<K>> {
+                Some(Register::new(
+                    register_index,
+                    self.register_start() + K::from(register_index) * K::WORD_SIZE,
+                ))
+            }
+        }
+    };
+}
+
+#[macro_export]
+/// Export macro for implementing [AbstractStackMachine](crate::machine::AbstractStackMachine) trait
+macro_rules! impl_stack_machine {
+    ($machine_struct: ident) => {
+        use zkmemory::machine::AbstractStackMachine;
+
+        impl<K, V, const S: usize, const T: usize> AbstractStackMachine<K, V, S, T>
+            for $machine_struct<K, V, S, T>
+        where
+            K: Base<S>,
+            V: Base<T>,
+            Self: AbstractMemoryMachine<K, V, S, T>,
+        {
+        }
+    };
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::{
+        base::{Base, B256},
+        commitment::{
+            merkle::{commit_image, CommitmentBackend, HashKind, MerkleRoot},
+            selector::{CommitmentKind, CommitmentSink, StateCommitter},
+        },
+        config::{
+            AlignmentPolicy, AllocatedSection, Config, ConfigArgs, ConfigBuilder, CostModel,
+            DefaultConfig, Endianness, FingerprintCheck, MemoryLayout, MemoryRegion, Permission,
+            ReadPolicy, RegionKind, ResourceKind, ResourceLimits, TraceMode,
+        },
+        error::Error,
+        machine::{
+            mmio::MmioHandler,
+            observer::MemoryObserver,
+            sink::{HashingSink, TraceSink},
+            AbstractContext, AbstractInstruction, AbstractMachine, AbstractMemoryMachine,
+            AbstractRegisterMachine, AbstractStackMachine, CellDelta, CellInteraction, FirstWrites,
+            Register, ResourceUsage, TraceRecord,
+        },
+    };
+    extern crate alloc;
+    extern crate std;
+    use alloc::{boxed::Box, rc::Rc, vec, vec::Vec};
+    use core::cell::{Cell, OnceCell};
+    use rbtree::RBTree;
+    use std::marker::PhantomData;
+
+    /// My instruction set for the machine
+    #[derive(Debug, Clone, Copy)]
+    pub enum MyInstruction<M, K, V, const S: usize, const T: usize>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        /// Read from memory
+        Read(K),
+        /// Write to memory
+        Write(K, V),
+        /// Push to stack
+        Push(V),
+        /// Pop from stack
+        Pop(),
+        /// Move from register to register (Mov(r2, r1) moves the value of r1 to r2)
+        Mov(Register<K>, Register<K>),
+        /// Swap value from top stack  to register
+        Swap(Register<K>),
+        /// Load from memory to register
+        Load(Register<K>, K),
+        /// Save from register to memory
+        Save(K, Register<K>),
+        /// Invalid instruction
+        Invalid(PhantomData<M>),
+        /// Add two registers, register 1 = register 1 + register 2
+        Add(Register<K>, Register<K>),
+    }
+
+    /// Type alias Instruction
+    pub type Instruction = MyInstruction<StateMachine<B256, B256, 32, 32>, B256, B256, 32, 32>;
+
+    /// RAM Machine keeping its memory in the backend `B`
+    #[derive(Debug)]
+    pub struct StateMachine<K, V, const S: usize, const T: usize, B = RBTree<K, V>>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        // Memory
+        memory: B,
+        memory_allocated: AllocatedSection<K>,
+        // Whether the memory section can grow and its maximum size
+        growable: bool,
+        max_memory_size: K,
+        word_size: K,
+        time_log: u64,
+
+        // Stack and its guard cells
+        stack_allocated: AllocatedSection<K>,
+        stack_guard: Option<AllocatedSection<K>>,
+        max_stack_depth: u64,
+        stack_depth: u64,
+        stack_ptr: K,
+
+        // Register
+        register_allocated: AllocatedSection<K>,
+
+        // Policy for unaligned accesses
+        alignment: AlignmentPolicy,
+        // Semantics of reading a cell that was never written
+        read_policy: ReadPolicy,
+        endianness: Endianness,
+        // Named regions of the address space
+        layout: Option<MemoryLayout<K>>,
+        // Fingerprint of the config the machine was built from
+        config_fingerprint: [u8; 32],
+        // Granularity of the trace and the sink of the records if they are only committed
+        trace_mode: TraceMode,
+        trace_hasher: Option<CommitmentSink>,
+        // Backend of the commitments, instantiated on first use and shared with the sink
+        commitment: CommitmentKind,
+        committer: Rc<OnceCell<StateCommitter>>,
+
+        /// Register r0
+        pub r0: Register<K>,
+        /// Register r1
+        pub r1: Register<K>,
+        /// Register r2
+        pub r2: Register<K>,
+        /// Register r3
+        pub r3: Register<K>,
+        /// Register r4
+        pub r4: Register<K>,
+
+        // Trace
+        execution_trace: RBTree<TraceRecord<K, V, S, T>, PhantomData<()>>,
+        // Observer of the memory accesses
+        observer: Option<Box<dyn MemoryObserver<K, V>>>,
+        // Sink of the trace records, the trace is kept if there is none
+        sink: Option<Box<dyn TraceSink<K, V, S, T>>>,
+        // Memory-mapped I/O regions and the devices mapped to them
+        mmio_layout: Option<MemoryLayout<K>>,
+        mmio: Vec<(RegionKind, Box<dyn MmioHandler<K, V>>)>,
+        // Commitment to the initial memory image
+        initial_commitment: Option<MerkleRoot>,
+        // Limits on the resources and number of bytes written
+        limits: ResourceLimits,
+        bytes_written: u64,
+        // Gas charged for every access, gas budget and gas used
+        cost_model: CostModel,
+        gas_budget: Option<u64>,
+        gas_used: u64,
+        // Time logs of the first writes to the cells of the write-once regions
+        first_writes: FirstWrites<K>,
+        // Number of records in the trace
+        record_counter: u64,
+        // Root of the memory, cleared whenever the memory is borrowed mutably
+        state_root: Cell<Option<(CommitmentBackend, MerkleRoot)>>,
+    }
+
+    impl<M, K, V, B, const S: usize, const T: usize> AbstractContext<M, K, V>
+        for StateMachine<K, V, S, T, B>
+    where
+        Self: core::fmt::Debug
+            + Sized
+            + AbstractMachine<K, V, Context = M::Context, Instruction = M::Instruction>,
+        K: Base<S>,
+        V: Base<T>,
+        B: MemoryBackend<K, V>,
+        M: AbstractMachine<K, V, Machine = StateMachine<K, V, S, T, B>>,
+    {
+        type Memory = B;
+
+        fn set_stack_depth(&mut self, stack_depth: u64) {
+            self.stack_depth = stack_depth;
+        }
+
+        fn stack_depth(&self) -> u64 {
+            self.stack_depth
+        }
+
+        fn stack_ptr(&self) -> K {
+            self.stack_ptr
+        }
+
+        fn time_log(&self) -> u64 {
+            self.time_log
+        }
+
+        fn set_time_log(&mut self, time_log: u64) {
+            self.time_log = time_log;
+        }
+
+        fn set_stack_ptr(&mut self, stack_ptr: K) {
+            self.stack_ptr = stack_ptr;
+        }
+
+        fn memory(&mut self) -> &'_ mut B {
+            self.state_root.set(None);
+            &mut self.memory
+        }
+
+        fn ro_memory(&self) -> &'_ B {
+            &self.memory
+        }
+    }
+
+    impl<M, K, V, B, const S: usize, const T: usize> AbstractInstruction<M, K, V>
+        for MyInstruction<M, K, V, S, T>
+    where
+        Self: core::fmt::Debug + Sized,
+        K: Base<S>,
+        V: Base<T>,
+        B: MemoryBackend<K, V>,
+        M: AbstractMachine<K, V, Machine = StateMachine<K, V, S, T, B>>,
+    {
+        fn exec(&self, machine: &mut M::Machine) -> Result<(), Error> {
+            match self {
+                MyInstruction::Invalid(_) => return Err(Error::MemoryInvalidInteraction),
+                MyInstruction::Read(addr) => {
+                    machine.check_bounds(*addr)?;
+                    machine.read(*addr)?;
+                }
+                MyInstruction::Write(addr, val) => {
+                    machine.check_bounds(*addr)?;
+                    machine.write(*addr, *val)?;
+                }
+                MyInstruction::Push(value) => {
+                    machine.push(*value)?;
+                }
+                MyInstruction::Pop() => {
+                    machine.pop()?;
+                }
+                MyInstruction::Mov(reg1, reg2) => {
+                    // Mov value from register 2 to register 1
+                    match machine.get(*reg2)? {
+                        CellInteraction::SingleCell(_, _, value) => {
+                            machine.set(*reg1, value)?;
+                        }
+                        _ => return Err(Error::RegisterUnableToRead),
+                    }
+                }
+                MyInstruction::Swap(reg) => {
+                    match machine.pop()? {
+                        (_, CellInteraction::SingleCell(_op, _addr, value)) => {
+                            machine.push(value)?;
+                            machine.set(*reg, value)?;
+                        }
+                        _ => return Err(Error::MemoryInvalidInteraction),
+                    };
+                }
+                MyInstruction::Load(reg, addr) => {
+                    machine.check_bounds(*addr)?;
+                    match machine.read(*addr)? {
+                        CellInteraction::SingleCell(_, _, value) => {
+                            machine.set(*reg, value)?;
+                        }
+                        CellInteraction::DoubleCell(_, _, cvalue, _, _, _, _) => {
+                            machine.set(*reg, cvalue)?;
+                        }
+                    };
+                }
+                MyInstruction::Save(address, reg) => {
+                    machine.check_bounds(*address)?;
+                    match machine.get(*reg)? {
+                        CellInteraction::SingleCell(_, _, value) => {
+                            machine.write(*address, value)?;
+                        }
+                        _ => return Err(Error::RegisterUnableToRead),
+                    }
+                }
+                MyInstruction::Add(reg1, reg2) => {
+                    match (machine.get(*reg1)?, machine.get(*reg2)?) {
+                        (
+                            CellInteraction::SingleCell(_, _, value1),
+                            CellInteraction::SingleCell(_, _, value2),
+                        ) => {
+                            let sum = value1.checked_add(value2).ok_or(Error::ValueOutOfRange)?;
+                            machine.set(*reg1, sum)?;
+                        }
+                        _ => return Err(Error::RegisterUnableToRead),
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    // A clone is not observed and keeps its trace, the observer, the sink and the devices of
+    // the original machine are not cloned
+    impl<K, V, B, const S: usize, const T: usize> Clone for StateMachine<K, V, S, T, B>
+    where
+        K: Base<S>,
+        V: Base<T>,
+        B: Clone,
+    {
+        fn clone(&self) -> Self {
+            Self {
+                memory: self.memory.clone(),
+                memory_allocated: self.memory_allocated,
+                growable: self.growable,
+                max_memory_size: self.max_memory_size,
+                word_size: self.word_size,
+                time_log: self.time_log,
+                stack_allocated: self.stack_allocated,
+                stack_guard: self.stack_guard,
+                max_stack_depth: self.max_stack_depth,
+                stack_depth: self.stack_depth,
+                stack_ptr: self.stack_ptr,
+                register_allocated: self.register_allocated,
+                alignment: self.alignment,
+                read_policy: self.read_policy,
+                endianness: self.endianness,
+                layout: self.layout,
+                config_fingerprint: self.config_fingerprint,
+                trace_mode: self.trace_mode,
+                trace_hasher: self.trace_hasher.as_ref().map(CommitmentSink::detached),
+                commitment: self.commitment,
+                committer: self.committer.clone(),
+                r0: self.r0,
+                r1: self.r1,
+                r2: self.r2,
+                r3: self.r3,
+                r4: self.r4,
+                execution_trace: self.execution_trace.clone(),
+                observer: None,
+                sink: None,
+                mmio_layout: None,
+                mmio: Vec::new(),
+                initial_commitment: self.initial_commitment,
+                limits: self.limits,
+                bytes_written: self.bytes_written,
+                cost_model: self.cost_model,
+                gas_budget: self.gas_budget,
+                gas_used: self.gas_used,
+                first_writes: self.first_writes.clone(),
+                record_counter: self.record_counter,
+                state_root: self.state_root.clone(),
+            }
+        }
+    }
+
+    impl<K, V, const S: usize, const T: usize> StateMachine<K, V, S, T>
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        /// Create a new RAM machine, panic if the config is invalid
+        pub fn new(config: ConfigArgs<K>) -> Self {
+            Self::try_new(config).expect("The config of the machine is valid")
+        }
+
+        /// Create a new RAM machine, return every violated rule of [ConfigArgs::validate]
+        pub fn try_new(config: ConfigArgs<K>) -> Result<Self, Vec<Error>> {
+            Config::try_new(K::WORD_SIZE, config).map(Self::from_config)
+        }
+
+        /// Create a new RAM machine from a config built by [crate::config::ConfigBuilder]
+        pub fn from_config(config: Config<K, S>) -> Self {
+            Self::with_memory(config, RBTree::new())
+        }
+
+        /// Create a new RAM machine whose time log starts at the given value
+        pub fn new_with_time_log(config: ConfigArgs<K>, time_log: u64) -> Self {
+            let mut machine = Self::new(config);
+            machine.time_log = time_log;
+            machine
+        }
+    }
+
+    impl<K, V, B, const S: usize, const T: usize> StateMachine<K, V, S, T, B>
+    where
+        K: Base<S>,
+        V: Base<T>,
+        B: MemoryBackend<K, V>,
+    {
+        /// Create a new RAM machine keeping its memory in the given empty backend
+        pub fn with_memory(config: Config<K, S>, memory: B) -> Self {
+            let committer = Rc::new(OnceCell::new());
+            Self {
+                // Memory section
+                memory,
+                memory_allocated: config.memory,
+                growable: config.growable,
+                max_memory_size: config.max_memory_size,
+                word_size: config.word_size,
+                time_log: 0,
+
+                // Stack
+                stack_allocated: config.stack,
+                stack_guard: config.stack_guard,
+                max_stack_depth: config.stack_depth.into(),
+                stack_depth: 0,
+                stack_ptr: config.stack.low(),
+
+                // Register
+                register_allocated: config.register,
+                alignment: config.alignment,
+                read_policy: config.read_policy,
+                endianness: config.endianness,
+                layout: config.layout,
+                config_fingerprint: config.config_fingerprint(),
+                trace_mode: config.trace_mode,
+                trace_hasher: (config.trace_mode == TraceMode::CommitmentsOnly)
+                    .then(|| CommitmentSink::with_committer(config.commitment, committer.clone())),
+                commitment: config.commitment,
+                committer,
+                r0: config.create_register(0),
+                r1: config.create_register(1),
+                r2: config.create_register(2),
+                r3: config.create_register(3),
+                r4: config.create_register(4),
+
+                // Execution trace
+                execution_trace: RBTree::new(),
+                observer: None,
+                sink: None,
+                mmio_layout: None,
+                mmio: Vec::new(),
+                initial_commitment: None,
+                limits: config.limits,
+                bytes_written: 0,
+                cost_model: config.cost_model,
+                gas_budget: config.gas_budget,
+                gas_used: 0,
+                first_writes: FirstWrites::new(),
+                record_counter: 0,
+                state_root: Cell::new(None),
+            }
+        }
+
+        /// Install the observer notified of every memory access
+        pub fn set_observer(&mut self, observer: Box<dyn MemoryObserver<K, V>>) {
+            self.observer = Some(observer);
+        }
+
+        /// Install the sink the trace records are pushed to instead of the trace
+        pub fn set_trace_sink(&mut self, sink: Box<dyn TraceSink<K, V, S, T>>) {
+            self.sink = Some(sink);
+        }
+
+        /// Map the device to the region, the region must not overlap another memory-mapped
+        /// I/O region nor share its kind
+        pub fn register_mmio(
+            &mut self,
+            region: MemoryRegion<K>,
+            handler: Box<dyn MmioHandler<K, V>>,
+        ) -> Result<(), Error> {
+            let mut regions = self
+                .mmio_layout
+                .map(|layout| layout.all_regions())
+                .unwrap_or_default();
+            regions.push(region);
+            self.mmio_layout = Some(MemoryLayout::new(&regions)?);
+            self.mmio.push((region.kind, handler));
+            Ok(())
+        }
+    }
+
+    impl<K, V, B, const S: usize, const T: usize> AbstractMachine<K, V> for StateMachine<K, V, S, T, B>
+    where
+        K: Base<S>,
+        V: Base<T>,
+        B: MemoryBackend<K, V>,
+    {
+        type Machine = Self;
+        type Context = Self;
+        type Instruction = MyInstruction<Self, K, V, S, T>;
+        type TraceRecord = TraceRecord<K, V, S, T>;
+
+        fn context(&mut self) -> &'_ mut Self::Context {
+            self
+        }
+
+        fn word_size(&self) -> K {
+            self.word_size
+        }
+
+        fn register_start(&self) -> K {
+            self.register_allocated.low()
+        }
+
+        fn ro_context(&self) -> &'_ Self::Context {
+            self
+        }
+
+        fn track(&mut self, trace: Self::TraceRecord) {
+            self.execution_trace.insert(trace, PhantomData);
+        }
+
+        fn trace(&self) -> Vec<Self::TraceRecord> {
+            self.execution_trace.keys().copied().collect()
+        }
+
+        fn trace_iter(&self) -> Box<dyn Iterator<Item = &'_ Self::TraceRecord> + '_> {
+            Box::new(self.execution_trace.keys())
+        }
+
+        fn trace_len(&self) -> usize {
+            self.execution_trace.len()
+        }
+
+        fn into_trace(self) -> Vec<Self::TraceRecord> {
+            self.execution_trace
+                .into_iter()
+                .map(|(record, _)| record)
+                .collect()
+        }
+
+        fn truncate_trace(&mut self, time_log: u64) {
+            while let Some((record, _)) = self.execution_trace.get_last() {
+                if record.time_log < time_log {
+                    break;
+                }
+                self.execution_trace.pop_last();
+            }
+        }
+
+        fn exec(&mut self, instruction: &Self::Instruction) -> Result<(), Error> {
+            instruction.exec(self)
+        }
+
+        fn base_address(&self) -> K {
+            self.memory_allocated.low()
+        }
+
+        fn get_memory_address(&self) -> (K, K) {
+            (self.memory_allocated.low(), self.memory_allocated.high())
+        }
+
+        fn get_stack_depth(&self) -> u64 {
+            self.ro_context().stack_depth
+        }
+
+        fn max_stack_depth(&self) -> u64 {
+            self.ro_context().max_stack_depth
+        }
+    }
+
+    impl<K, V, B, const S: usize, const T: usize> AbstractMemoryMachine<K, V, S, T>
+        for StateMachine<K, V, S, T, B>
+    where
+        K: Base<S>,
+        V: Base<T>,
+        B: MemoryBackend<K, V>,
+        Self: AbstractMachine<K, V>,
+    {
+        fn alignment_policy(&self) -> AlignmentPolicy {
+            self.alignment
+        }
+
+        fn memory_layout(&self) -> Option<MemoryLayout<K>> {
+            self.layout
+        }
+
+        fn stack_guard(&self) -> Option<AllocatedSection<K>> {
+            self.stack_guard
+        }
+
+        fn read_policy(&self) -> ReadPolicy {
+            self.read_policy
+        }
+
+        fn endianness(&self) -> Endianness {
+            self.endianness
+        }
+
+        fn config_fingerprint(&self) -> [u8; 32] {
+            self.config_fingerprint
+        }
+
+        fn trace_mode(&self) -> TraceMode {
+            self.trace_mode
+        }
+
+        fn trace_hasher(&self) -> Option<&CommitmentSink> {
+            self.trace_hasher.as_ref()
+        }
+
+        fn observer(&mut self) -> Option<&mut dyn MemoryObserver<K, V>> {
+            match &mut self.observer {
+                Some(observer) => Some(observer.as_mut()),
+                None => None,
+            }
+        }
+
+        fn trace_sink(&mut self) -> Option<&mut dyn TraceSink<K, V, S, T>> {
+            match &mut self.sink {
+                Some(sink) => Some(sink.as_mut()),
+                None => None,
+            }
+        }
+
+        fn mmio_layout(&self) -> Option<MemoryLayout<K>> {
+            self.mmio_layout
+        }
+
+        fn mmio_handler(&mut self, kind: RegionKind) -> Option<&mut dyn MmioHandler<K, V>> {
+            match self.mmio.iter_mut().find(|(mapped, _)| *mapped == kind) {
+                Some((_, handler)) => Some(handler.as_mut()),
+                None => None,
+            }
+        }
+
+        fn resource_limits(&self) -> ResourceLimits {
+            self.limits
+        }
+
+        fn bytes_written(&self) -> u64 {
+            self.bytes_written
+        }
+
+        fn add_bytes_written(&mut self, bytes: u64) {
+            self.bytes_written += bytes;
+        }
+
+        fn cost_model(&self) -> CostModel {
+            self.cost_model
+        }
+
+        fn gas_budget(&self) -> Option<u64> {
+            self.gas_budget
+        }
+
+        fn gas_used(&self) -> u64 {
+            self.gas_used
+        }
+
+        fn set_gas_used(&mut self, gas_used: u64) {
+            self.gas_used = gas_used;
+        }
+
+        fn first_writes(&self) -> Option<&FirstWrites<K>> {
+            Some(&self.first_writes)
+        }
+
+        fn first_writes_mut(&mut self) -> Option<&mut FirstWrites<K>> {
+            Some(&mut self.first_writes)
+        }
+
+        fn record_counter(&self) -> Option<u64> {
+            Some(self.record_counter)
+        }
+
+        fn record_counter_mut(&mut self) -> Option<&mut u64> {
+            Some(&mut self.record_counter)
+        }
+
+        fn growable(&self) -> bool {
+            self.growable
+        }
+
+        fn max_memory_size(&self) -> K {
+            self.max_memory_size
+        }
+
+        fn set_memory_end(&mut self, high: K) {
+            self.memory_allocated = AllocatedSection::new(self.memory_allocated.low(), high);
+        }
+
+        fn initial_commitment(&self) -> Option<MerkleRoot> {
+            self.initial_commitment
+        }
+
+        fn set_initial_commitment(&mut self, root: MerkleRoot) -> Result<(), Error> {
+            self.initial_commitment = Some(root);
+            Ok(())
+        }
+
+        fn state_root_cache(&self) -> Option<&Cell<Option<(CommitmentBackend, MerkleRoot)>>> {
+            Some(&self.state_root)
+        }
+
+        fn commitment_kind(&self) -> CommitmentKind {
+            self.commitment
+        }
+
+        fn committer_cache(&self) -> Option<&OnceCell<StateCommitter>> {
+            Some(&self.committer)
+        }
+    }
+
+    impl<K, V, B, const S: usize, const T: usize> AbstractRegisterMachine<K, V, S, T>
+        for StateMachine<K, V, S, T, B>
+    where
+        K: Base<S>,
+        V: Base<T>,
+        B: MemoryBackend<K, V>,
+        Self: AbstractMemoryMachine<K, V, S, T>,
+    {
+        fn new_register(&self, register_index: usize) -> Option<crate::machine::Register<K>> {
+            let address = K::from(register_index)
+                .checked_mul(K::WORD_SIZE)
+                .and_then(|offset| self.register_start().checked_add(offset))?;
+            if address < self.register_allocated.high() {
+                Some(Register::new(register_index, address))
+            } else {
+                None
+            }
+        }
+    }
+
+    impl<K, V, B, const S: usize, const T: usize> AbstractStackMachine<K, V, S, T>
+        for StateMachine<K, V, S, T, B>
+    where
+        K: Base<S>,
+        V: Base<T>,
+        B: MemoryBackend<K, V>,
+        Self: AbstractMemoryMachine<K, V, S, T>,
+    {
+    }
+
+    #[test]
+    fn test_read_write_one_cell() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let write_chunk = B256::from(1025);
+        let program = vec![
+            Instruction::Write(base + B256::from(32), B256::from(1025)),
+            Instruction::Read(base + B256::from(32)),
+        ];
+        // Execute the program
+        for instruction in program {
+            sm.exec(&instruction).unwrap();
+        }
+        assert_eq!(write_chunk, sm.dummy_read(base + B256::from(32)));
+    }
+
+    #[test]
+    fn test_read_write_two_cells() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let write_chunk = [5u8; 32];
+        let program = vec![
+            Instruction::Write(base + B256::from(1), B256::from(write_chunk)),
+            Instruction::Read(base + B256::from(0)),
+            Instruction::Read(base + B256::from(32)),
+            Instruction::Read(base + B256::from(1)),
+        ];
+        // Execute the program
+        for instruction in program {
+            sm.exec(&instruction).unwrap();
+        }
+        let read_chunk_low = {
+            let mut buffer = [5u8; 32];
+            buffer[0] = 0u8;
+            buffer
+        };
+
+        let read_chunk_high = {
+            let mut buffer = [0u8; 32];
+            buffer[0] = 5u8;
+            buffer
+        };
+
+        assert_eq!(sm.dummy_read(base), B256::from(read_chunk_low));
+        assert_eq!(
+            sm.dummy_read(base + B256::from(32)),
+            B256::from(read_chunk_high)
+        );
+    }
+
+    #[test]
+    fn test_arithmetics() {
+        let chunk1 = [5u8; 32];
+        let chunk2 = [190u8; 32];
+        let add_chunk = [195u8; 32];
+
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+
+        let base = sm.base_address();
+        let program = vec![
+            Instruction::Write(base + B256::from(0), B256::from(chunk1)),
+            Instruction::Write(base + B256::from(32), B256::from(chunk2)),
+            Instruction::Load(sm.r0, base + B256::from(0)),
+            Instruction::Load(sm.r1, base + B256::from(32)),
+            Instruction::Add(sm.r0, sm.r1),
+            Instruction::Save(base + B256::from(64), sm.r0),
+        ];
+        // Execute the program
+        for instruction in program {
+            sm.exec(&instruction).unwrap();
+        }
+
+        assert_eq!(sm.dummy_read(base + B256::from(64)), B256::from(add_chunk));
+    }
+
+    #[test]
+    fn test_stack_machine() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+
+        assert_eq!(sm.stack_allocated.low(), B256::zero());
+        let base = sm.base_address();
+        let program = vec![
+            Instruction::Push(B256::from(1000)),
+            Instruction::Push(B256::from(170)),
+            Instruction::Swap(sm.r0),
+            Instruction::Pop(),
+            Instruction::Swap(sm.r1),
+            Instruction::Pop(),
+            Instruction::Mov(sm.r2, sm.r0),
+            Instruction::Save(base + B256::from(128), sm.r0),
+            Instruction::Save(base + B256::from(160), sm.r1),
+            Instruction::Save(base + B256::from(192), sm.r2),
+        ];
+        // Execute program1
+        for instruction in program {
+            sm.exec(&instruction).unwrap();
+        }
+
+        assert_eq!(sm.dummy_read(base + B256::from(128)), B256::from(170));
+        assert_eq!(sm.dummy_read(base + B256::from(160)), B256::from(1000));
+        assert_eq!(sm.dummy_read(base + B256::from(192)), B256::from(170));
+    }
+
+    #[test]
+    fn test_invalid_instruction() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        assert_eq!(
+            sm.exec(&Instruction::Invalid(PhantomData)),
+            Err(Error::MemoryInvalidInteraction)
+        );
+
+        // An overflowing addition is rejected
+        sm.set(sm.r0, B256::MAX).unwrap();
+        sm.set(sm.r1, B256::from(1)).unwrap();
+        assert_eq!(
+            sm.exec(&Instruction::Add(sm.r0, sm.r1)),
+            Err(Error::ValueOutOfRange)
+        );
+    }
+
+    #[test]
+    fn test_grow_memory() {
+        const MEMORY_SIZE: u64 = 0x100;
+        let config = ConfigBuilder::<B256, 32>::new()
+            .memory_size(MEMORY_SIZE)
+            .growable(true)
+            .max_memory_size(0x180)
+            .cost_model(CostModel {
+                grow: 2,
+                ..CostModel::default()
+            })
+            .build()
+            .unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(config);
+        let base = sm.base_address();
+        let out_of_bounds = |addr: B256, memory_size: u64| {
+            Err(Error::AddressOutOfBounds {
+                addr: addr.fixed_be_bytes(),
+                memory_size: B256::from(memory_size).fixed_be_bytes(),
+            })
+        };
+
+        // Before the growth the word just above the boundary is out of bounds
+        let below = base + B256::from(MEMORY_SIZE - 32);
+        let above = base + B256::from(MEMORY_SIZE);
+        sm.exec(&Instruction::Write(below, B256::from(1))).unwrap();
+        assert_eq!(
+            sm.exec(&Instruction::Write(above, B256::from(2))),
+            out_of_bounds(above, MEMORY_SIZE)
+        );
+
+        // After the growth it is in bounds and the written cells keep their value
+        let high = base + B256::from(MEMORY_SIZE + 63);
+        assert_eq!(sm.grow(2), Ok(high));
+        assert_eq!(sm.get_memory_address(), (base, high));
+        assert_eq!(sm.gas_used(), 4);
+        sm.exec(&Instruction::Write(above, B256::from(2))).unwrap();
+        sm.exec(&Instruction::Read(below)).unwrap();
+        assert_eq!(sm.dummy_read(below), B256::from(1));
+        let end = high + B256::from(1);
+        assert_eq!(
+            sm.exec(&Instruction::Read(end)),
+            out_of_bounds(end, MEMORY_SIZE + 64)
+        );
+        // The growth is not recorded in the trace
+        assert_eq!(sm.trace_len(), 3);
+
+        // The memory section grows up to its maximum size and not beyond
+        assert_eq!(
+            sm.grow(3),
+            Err(Error::MemoryGrowthLimit {
+                max_memory_size: B256::from(0x180).fixed_be_bytes(),
+                attempted: B256::from(0x1a0).fixed_be_bytes(),
+            })
+        );
+        assert_eq!(sm.get_memory_address(), (base, high));
+        assert_eq!(sm.grow(2), Ok(base + B256::from(0x17f)));
+        assert!(sm.grow(1).is_err());
+        assert_eq!(sm.gas_used(), 8);
+
+        // The growth is charged before the memory section changes
+        let config = ConfigBuilder::<B256, 32>::new()
+            .memory_size(MEMORY_SIZE)
+            .growable(true)
+            .cost_model(CostModel {
+                grow: 2,
+                ..CostModel::default()
+            })
+            .gas_budget(3)
+            .build()
+            .unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(config);
+        assert_eq!(
+            sm.grow(2),
+            Err(Error::OutOfGas {
+                budget: 3,
+                used: 0,
+                cost: 4,
+            })
+        );
+        assert_eq!(
+            sm.get_memory_address().1,
+            sm.base_address() + B256::from(MEMORY_SIZE - 1)
+        );
+
+        // A fixed memory section never grows
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        assert_eq!(sm.grow(1), Err(Error::MemoryNotGrowable));
+    }
+
+    #[test]
+    fn test_out_of_bounds() {
+        const MEMORY_SIZE: u64 = 1 << 20;
+        let config = ConfigBuilder::<B256, 32>::new()
+            .memory_size(MEMORY_SIZE)
+            .build()
+            .unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(config);
+        let base = sm.base_address();
+        let out_of_bounds = |addr: B256| {
+            Err(Error::AddressOutOfBounds {
+                addr: addr.fixed_be_bytes(),
+                memory_size: B256::from(MEMORY_SIZE).fixed_be_bytes(),
+            })
+        };
+
+        // The last word of the memory is in bounds
+        let last = base + B256::from(MEMORY_SIZE - 32);
+        sm.exec(&Instruction::Write(last, B256::from(1))).unwrap();
+        sm.exec(&Instruction::Read(last)).unwrap();
+
+        // The first invalid address, a word crossing the end and the end of the address space
+        let end = base + B256::from(MEMORY_SIZE);
+        let crossing = base + B256::from(MEMORY_SIZE - 24);
+        for addr in [end, crossing, B256::MAX] {
+            assert_eq!(
+                sm.exec(&Instruction::Write(addr, B256::from(2))),
+                out_of_bounds(addr)
+            );
+            assert_eq!(sm.exec(&Instruction::Read(addr)), out_of_bounds(addr));
+            assert_eq!(
+                sm.exec(&Instruction::Load(sm.r0, addr)),
+                out_of_bounds(addr)
+            );
+            assert_eq!(
+                sm.exec(&Instruction::Save(addr, sm.r0)),
+                out_of_bounds(addr)
+            );
+        }
+        // Below the memory section
+        let below = base - B256::from(32);
+        assert_eq!(sm.exec(&Instruction::Read(below)), out_of_bounds(below));
+
+        // The rejected instructions left no trace
+        assert_eq!(sm.trace_len(), 2);
+        assert_eq!(sm.dummy_read(end), B256::zero());
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize_trace() {
+        use zeroize::Zeroize;
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        sm.exec(&Instruction::Write(base, B256::from(0xdead)))
+            .unwrap();
+        let mut trace = sm.trace();
+        trace.zeroize();
+        assert!(trace.is_empty());
+
+        let mut record = sm.trace()[0];
+        record.zeroize();
+        assert_eq!(record.get_tuple().3, B256::zero());
+        assert_eq!(record.get_tuple().4, B256::zero());
+
+        // The snapshot of the memory is wiped, the wrapper wipes it again on drop
+        let mut snapshot = zeroize::Zeroizing::new(sm.snapshot());
+        assert_eq!(snapshot.cells(), [(base, B256::from(0xdead))]);
+        snapshot.zeroize();
+        assert!(snapshot.cells().is_empty());
+        assert_eq!(snapshot.time_log(), 0);
+    }
+
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn test_trace_serialization_roundtrip() {
+        use crate::{
+            base::B64,
+            machine::{deserialize_trace, serialize_trace, AbstractTraceRecord, MemoryInstruction},
+        };
+
+        let trace: Vec<TraceRecord<B256, B64, 32, 8>> = (0..100_000u64)
+            .map(|i| {
+                TraceRecord::new(
+                    i,
+                    i % 7,
+                    if i % 3 == 0 {
+                        MemoryInstruction::Write
+                    } else {
+                        MemoryInstruction::Read
+                    },
+                    B256::MAX - B256::from(i * 32),
+                    B64::from(i.wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+                )
+            })
+            .collect();
+
+        // Length prefix, then 8 + 8 + 1 + 32 + 8 + 1 + 1 bytes per record
+        let bytes = serialize_trace(&trace);
+        assert_eq!(bytes.len(), 4 + trace.len() * 59);
+
+        let decoded = deserialize_trace::<B256, B64, 32, 8>(&bytes).unwrap();
+        assert_eq!(decoded, trace);
+
+        // Byte for byte determinism
+        assert_eq!(serialize_trace(&decoded), bytes);
+        assert_eq!(serialize_trace(&trace), bytes);
+
+        // Truncated and trailing input are rejected
+        assert_eq!(
+            deserialize_trace::<B256, B64, 32, 8>(&bytes[..bytes.len() - 1]),
+            Err(Error::InvalidTraceEncoding)
+        );
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert_eq!(
+            deserialize_trace::<B256, B64, 32, 8>(&extended),
+            Err(Error::InvalidTraceEncoding)
+        );
+    }
+
+    #[test]
+    fn test_write_bytes() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let bytes: Vec<u8> = (1..=65u8).collect();
+
+        // Two full cells are written, the last cell is read then written
+        let interactions = sm.write_bytes(base, &bytes).unwrap();
+        assert_eq!(interactions.len(), 4);
+        assert_eq!(sm.trace().len(), 4);
+
+        let first: [u8; 32] = sm.dummy_read(base).into();
+        assert_eq!(first[..], bytes[..32]);
+        let mut tail = [0u8; 32];
+        tail[0] = 65;
+        assert_eq!(sm.dummy_read(base + B256::from(64)), B256::from(tail));
+        assert_eq!(sm.read_bytes(base, 65).unwrap(), bytes);
+
+        // Nothing is written for an empty buffer
+        assert!(sm.write_bytes(base, &[]).unwrap().is_empty());
+        assert!(sm.read_bytes(base, 0).unwrap().is_empty());
+        assert_eq!(sm.trace().len(), 7);
+    }
+
+    #[test]
+    fn test_write_slice_round_trip() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        sm.write(base + B256::from(96), B256::from([0xffu8; 32]))
+            .unwrap();
+        let data: Vec<u8> = (0..100u8).collect();
+
+        // Three full cells and a zero-padded tail, one write per cell
+        assert_eq!(sm.write_slice(base, &data).unwrap(), 4);
+        assert_eq!(sm.trace().len(), 5);
+        let mut tail = [0u8; 32];
+        tail[..4].copy_from_slice(&data[96..]);
+        assert_eq!(sm.dummy_read(base + B256::from(96)), B256::from(tail));
+
+        // Reading back does not record any trace
+        assert_eq!(sm.read_slice(base, data.len()).unwrap(), data);
+        assert_eq!(sm.read_slice(base, 128).unwrap()[100..], [0u8; 28]);
+        assert_eq!(sm.trace().len(), 5);
+
+        assert_eq!(sm.write_slice(base, &[]).unwrap(), 0);
+        assert!(sm.read_slice(base, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_slice_alignment_and_permissions() {
+        let mut sm = machine_with_policy(AlignmentPolicy::Strict);
+        let unaligned = sm.base_address() + B256::from(1);
+        assert!(matches!(
+            sm.write_slice(unaligned, &[1, 2, 3]),
+            Err(Error::UnalignedAccess { .. })
+        ));
+        assert!(matches!(
+            sm.read_slice(unaligned, 3),
+            Err(Error::UnalignedAccess { .. })
+        ));
+
+        // The unaligned chunks of an emulated slice share their cells
+        let mut sm = machine_with_policy(AlignmentPolicy::Emulate);
+        let data: Vec<u8> = (1..=64u8).collect();
+        let unaligned = sm.base_address() + B256::from(16);
+        assert_eq!(sm.write_slice(unaligned, &data).unwrap(), 3);
+        assert_eq!(sm.read_slice(unaligned, 64).unwrap(), data);
+
+        let code = B256::from(0x10000);
+        let io = B256::from(0x50000);
+        let layout = MemoryLayout::new(&[
+            MemoryRegion::new(RegionKind::Code, code, B256::from(0x1000))
+                .with_permission(Permission::Read),
+            MemoryRegion::new(RegionKind::Io, io, B256::from(0x100))
+                .with_permission(Permission::Write),
+        ])
+        .unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(ConfigArgs {
+            layout: Some(layout),
+            ..DefaultConfig::default_config()
+        });
+        assert!(matches!(
+            sm.write_slice(code, &data),
+            Err(Error::PermissionDenied {
+                access: Permission::Write,
+                ..
+            })
+        ));
+        // Nothing is written when the last cell of the slice is outside the region
+        assert!(matches!(
+            sm.write_slice(io + B256::from(0xe0), &data),
+            Err(Error::AccessOutsideRegion { .. })
+        ));
+        assert_eq!(sm.dummy_read(io + B256::from(0xe0)), B256::zero());
+        assert!(sm.trace().is_empty());
+
+        assert_eq!(sm.write_slice(io, &data).unwrap(), 2);
+        assert!(matches!(
+            sm.read_slice(io, 64),
+            Err(Error::PermissionDenied {
+                access: Permission::Read,
+                ..
+            })
+        ));
+        assert_eq!(sm.read_slice(code, 4).unwrap(), vec![0; 4]);
+    }
+
+    #[test]
+    fn test_write_byte_in_the_middle_of_a_cell() {
+        use crate::machine::{AbstractTraceRecord, MemoryInstruction};
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let old = B256::from([0x11u8; 32]);
+        sm.write(base, old).unwrap();
+
+        sm.write_bytes(base + B256::from(5), &[0xaa]).unwrap();
+        let mut expected = [0x11u8; 32];
+        expected[5] = 0xaa;
+        assert_eq!(sm.dummy_read(base), B256::from(expected));
+
+        let trace = sm.trace();
+        assert_eq!(trace.len(), 3);
+        assert_eq!(trace[1].instruction(), MemoryInstruction::Read);
+        assert_eq!((trace[1].address(), trace[1].value()), (base, old));
+        assert_eq!(trace[2].instruction(), MemoryInstruction::Write);
+        assert_eq!(
+            (trace[2].address(), trace[2].value()),
+            (base, B256::from(expected))
+        );
+
+        assert_eq!(
+            sm.read_bytes(base + B256::from(4), 3).unwrap(),
+            vec![0x11, 0xaa, 0x11]
+        );
+        assert_eq!(sm.trace().len(), 4);
+    }
+
+    #[test]
+    fn test_copy_overlapping_ranges() {
+        use crate::machine::{validate::validate_trace, AbstractTraceRecord, MemoryInstruction};
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let mut model: Vec<u8> = (1..=255u8).chain([0]).collect();
+        sm.write_slice(base, &model).unwrap();
+
+        // Forward copy, the destination starts inside the source
+        let start = sm.trace().len();
+        sm.copy(base + B256::from(40), base + B256::from(10), 100)
+            .unwrap();
+        model.copy_within(10..110, 40);
+        assert_eq!(sm.read_slice(base, 256).unwrap(), model);
+
+        // The four source cells are read before the first destination write
+        let instructions: Vec<MemoryInstruction> = sm.trace()[start..]
+            .iter()
+            .map(|record| record.instruction())
+            .collect();
+        assert_eq!(instructions[..4], [MemoryInstruction::Read; 4]);
+        assert_eq!(instructions[4..].len(), 6);
+
+        // Backward copy, the destination ends inside the source
+        sm.copy(base + B256::from(5), base + B256::from(60), 90)
+            .unwrap();
+        model.copy_within(60..150, 5);
+        assert_eq!(sm.read_slice(base, 256).unwrap(), model);
+
+        // An empty copy does not touch the memory
+        let length = sm.trace().len();
+        assert!(sm.copy(base, base + B256::from(1), 0).unwrap().is_empty());
+        assert_eq!(sm.trace().len(), length);
+        assert_eq!(validate_trace(&sm.trace()), Ok(()));
+    }
+
+    #[test]
+    fn test_fill() {
+        use crate::machine::validate::validate_trace;
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let mut model = vec![0x11u8; 128];
+        sm.write_slice(base, &model).unwrap();
+
+        // The two partial cells are read then written, the full one is only written
+        assert_eq!(sm.fill(base + B256::from(3), 0xab, 72).unwrap().len(), 5);
+        model[3..75].fill(0xab);
+        assert_eq!(sm.read_slice(base, 128).unwrap(), model);
+        assert_eq!(sm.trace().len(), 9);
+        assert!(sm.fill(base, 0, 0).unwrap().is_empty());
+        assert_eq!(validate_trace(&sm.trace()), Ok(()));
+    }
+
+    #[test]
+    fn test_write_bytes_across_a_cell_boundary() {
+        use crate::machine::{AbstractTraceRecord, MemoryInstruction};
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let next = base + B256::from(32);
+        sm.write(base, B256::from([0x11u8; 32])).unwrap();
+        sm.write(next, B256::from([0x22u8; 32])).unwrap();
+
+        let bytes: Vec<u8> = (1..=8u8).collect();
+        sm.write_bytes(base + B256::from(28), &bytes).unwrap();
+
+        let mut expected_lo = [0x11u8; 32];
+        expected_lo[28..].copy_from_slice(&bytes[..4]);
+        let mut expected_hi = [0x22u8; 32];
+        expected_hi[..4].copy_from_slice(&bytes[4..]);
+        assert_eq!(sm.dummy_read(base), B256::from(expected_lo));
+        assert_eq!(sm.dummy_read(next), B256::from(expected_hi));
+
+        // Both cells are read then written, the low cell first
+        let records: Vec<(u64, MemoryInstruction, B256)> = sm
+            .trace()
+            .iter()
+            .skip(2)
+            .map(|record| (record.time_log(), record.instruction(), record.address()))
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                (2, MemoryInstruction::Read, base),
+                (3, MemoryInstruction::Write, base),
+                (4, MemoryInstruction::Read, next),
+                (5, MemoryInstruction::Write, next),
+            ]
+        );
+
+        assert_eq!(sm.read_bytes(base + B256::from(28), 8).unwrap(), bytes);
+        assert_eq!(sm.trace().len(), 8);
+
+        // A range that does not fit in the address space is rejected before any access
+        assert!(matches!(
+            sm.write_bytes(B256::MAX - B256::from(2), &bytes),
+            Err(Error::PointerOverflow { .. })
+        ));
+        assert_eq!(sm.trace().len(), 8);
+    }
+
+    #[test]
+    fn test_endianness_differential() {
+        use crate::{
+            base::B32,
+            machine::{
+                dump::MemoryDump,
+                trace_codec::{decode_trace, encode_trace},
+                AbstractTraceRecord,
+            },
+        };
+        // The same byte program, with an unaligned word read, under a byte order
+        let run = |endianness| {
+            let mut sm = StateMachine::<B32, B32, 4, 4>::new(ConfigArgs {
+                endianness,
+                ..DefaultConfig::default_config()
+            });
+            let base = sm.base_address();
+            sm.write_bytes(base + B32::from(1), b"zkmemory").unwrap();
+            sm.write_slice(base + B32::from(12), &[1, 2, 3, 4, 5, 6])
+                .unwrap();
+            let bytes = sm.read_bytes(base, 20).unwrap();
+            assert_eq!(sm.read_slice(base, 20).unwrap(), bytes);
+            let word = match sm.read(base + B32::from(2)).unwrap() {
+                CellInteraction::DoubleCell(_, _, value, ..) => value,
+                _ => panic!("The word crosses a cell boundary"),
+            };
+            let dump = alloc::format!("{}", MemoryDump::new(&sm, base, 5));
+            (sm.trace(), bytes, word, dump)
+        };
+        let (big, big_bytes, big_word, big_dump) = run(Endianness::Big);
+        let (little, little_bytes, little_word, little_dump) = run(Endianness::Little);
+
+        // The memory holds the same bytes, the words read from them are byte-reversed
+        assert_eq!(big_bytes, little_bytes);
+        assert_eq!(&big_bytes[1..9], b"zkmemory");
+        assert_eq!(big_dump, little_dump);
+        assert_eq!(big_word, B32::from(*b"kmem"));
+        assert_eq!(little_word, B32::from(*b"mekm"));
+
+        // The traces only differ by the byte order of the cell values
+        assert_eq!(big.len(), little.len());
+        for (big, little) in big.iter().zip(little.iter()) {
+            assert_eq!(
+                (big.time_log(), big.instruction(), big.address()),
+                (little.time_log(), little.instruction(), little.address())
+            );
+            let value: [u8; 4] = big.value().into();
+            assert_eq!(
+                Endianness::Little.from_bytes::<B32, 4>(value),
+                little.value()
+            );
+        }
+        let encoded = encode_trace(&little, Endianness::Little, [0u8; 32]);
+        assert_eq!(
+            decode_trace::<B32, B32, 4, 4>(&encoded, FingerprintCheck::Expect([0u8; 32])).unwrap(),
+            little
+        );
+    }
+
+    #[test]
+    fn test_init_and_dump_region() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let values = [B256::from(1), B256::from(2), B256::from(3)];
+
+        assert_eq!(sm.init_region(base, &values).unwrap().len(), 3);
+        assert_eq!(sm.trace().len(), 3);
+        assert_eq!(
+            sm.dump_region(base, 4),
+            vec![
+                (base, B256::from(1)),
+                (base + B256::from(32), B256::from(2)),
+                (base + B256::from(64), B256::from(3)),
+                (base + B256::from(96), B256::zero()),
+            ]
+        );
+        // Dumping does not add trace records
+        assert_eq!(sm.trace().len(), 3);
+
+        // The region must fit in the address space
+        assert!(matches!(
+            sm.init_region(B256::MAX - B256::from(31), &values),
+            Err(Error::MemoryAccessDeinied)
+        ));
+        assert_eq!(sm.trace().len(), 3);
+        assert_eq!(sm.dump_region(B256::MAX - B256::from(31), 4).len(), 1);
+    }
+
+    #[test]
+    fn test_pointer_underflow() {
+        type Machine = StateMachine<B256, B256, 32, 32>;
+        let mut sm = Machine::new(DefaultConfig::default_config());
+        // A corrupted context claims one element while the stack pointer is at zero
+        AbstractContext::<Machine, B256, B256>::set_stack_depth(&mut sm, 1);
+        assert_eq!(
+            sm.pop().unwrap_err(),
+            Error::PointerUnderflow {
+                pointer: B256::zero().fixed_be_bytes(),
+                attempted_delta: B256::WORD_SIZE.fixed_be_bytes(),
+            }
+        );
+        // The context is left untouched
+        assert_eq!(sm.get_stack_depth(), 1);
+        assert_eq!(
+            AbstractContext::<Machine, B256, B256>::stack_ptr(&sm),
+            B256::zero()
+        );
+        assert!(sm.trace().is_empty());
+    }
+
+    #[test]
+    fn test_pointer_overflow() {
+        type Machine = StateMachine<B256, B256, 32, 32>;
+        let mut sm = Machine::new(DefaultConfig::default_config());
+        let top = B256::MAX - B256::from(31);
+
+        // Pushing on the last cell of the address space
+        AbstractContext::<Machine, B256, B256>::set_stack_ptr(&mut sm, top);
+        assert_eq!(
+            sm.push(B256::from(1)).unwrap_err(),
+            Error::PointerOverflow {
+                pointer: top.fixed_be_bytes(),
+                attempted_delta: B256::WORD_SIZE.fixed_be_bytes(),
+            }
+        );
+        assert_eq!(sm.get_stack_depth(), 0);
+
+        // Writing across the top of memory
+        assert!(matches!(
+            sm.write(B256::MAX - B256::from(1), B256::from(1)),
+            Err(Error::PointerOverflow { .. })
+        ));
+        assert!(matches!(
+            sm.read(B256::MAX),
+            Err(Error::PointerOverflow { .. })
+        ));
+        assert!(matches!(
+            sm.write_bytes(top, &[1u8; 64]),
+            Err(Error::PointerOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_push_pop_trace() {
+        use crate::machine::{AbstractTraceRecord, MemoryInstruction};
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+
+        let (depth, pushed) = sm.push(B256::from(0xcafe)).unwrap();
+        assert_eq!(depth, 1);
+        assert!(matches!(
+            pushed,
+            CellInteraction::SingleCell(MemoryInstruction::Push, _, _)
+        ));
+        let (depth, popped) = sm.pop().unwrap();
+        assert_eq!(depth, 0);
+        match popped {
+            CellInteraction::SingleCell(MemoryInstruction::Pop, _, value) => {
+                assert_eq!(value, B256::from(0xcafe))
+            }
+            _ => panic!("Pop must be a single cell interaction"),
+        }
+
+        // Both records use the stack pointer address and the new instruction codes
+        let trace = sm.trace();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].instruction(), MemoryInstruction::Push);
+        assert_eq!(trace[1].instruction(), MemoryInstruction::Pop);
+        assert_eq!(trace[0].instruction().code(), 2);
+        assert_eq!(trace[1].instruction().code(), 3);
+        assert_eq!(trace[0].address(), trace[1].address());
+        assert_eq!(trace[0].value(), trace[1].value());
+        assert!(trace[0].instruction().is_write());
+        assert!(!trace[1].instruction().is_write());
+    }
+
+    #[test]
+    fn test_stack_region_bounds() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(ConfigArgs {
+            head_layout: true,
+            stack_depth: B256::from(2),
+            no_register: B256::from(4),
+            buffer_size: B256::from(32),
+            register_base: None,
+            stack_base: None,
+            guard_cells: B256::zero(),
+            alignment: AlignmentPolicy::Emulate,
+            read_policy: ReadPolicy::ZeroOnUninit,
+            endianness: Endianness::Big,
+            limits: ResourceLimits::default(),
+            cost_model: CostModel::default(),
+            gas_budget: None,
+            layout: None,
+        });
+        assert_eq!(sm.pop().unwrap_err(), Error::StackUnderflow);
+        sm.push(B256::from(1)).unwrap();
+        sm.push(B256::from(2)).unwrap();
+        assert_eq!(sm.push(B256::from(3)).unwrap_err(), Error::StackOverflow);
+        assert_eq!(sm.trace().len(), 2);
+    }
+
+    #[test]
+    fn test_stack_guard_cells() {
+        use crate::base::B64;
+        type Machine = StateMachine<B64, B64, 8, 8>;
+        let config = ConfigBuilder::<B64, 8>::new()
+            .stack_depth(4)
+            .guard_cells(1)
+            .build()
+            .unwrap();
+        let (stack, guard) = (config.stack, config.stack_guard.unwrap());
+        let mut sm = Machine::from_config(config);
+        assert_eq!(sm.stack_guard().map(|guard| guard.low()), Some(guard.low()));
+        assert_eq!(
+            (guard.low(), guard.high()),
+            (stack.high(), stack.high() + B64::from(7))
+        );
+        sm.push(B64::from(1)).unwrap();
+
+        // A stack pointer moved past the stack is caught at the guard before the depth limit
+        AbstractContext::<Machine, B64, B64>::set_stack_ptr(&mut sm, guard.low());
+        assert_eq!(sm.push(B64::from(2)).unwrap_err(), Error::StackOverflow);
+        assert_eq!(sm.get_stack_depth(), 1);
+        assert_eq!(sm.trace().len(), 1);
+        // The cell right below the guard is the last cell of the stack
+        AbstractContext::<Machine, B64, B64>::set_stack_ptr(&mut sm, guard.low() - B64::from(8));
+        sm.push(B64::from(3)).unwrap();
+        assert_eq!(sm.push(B64::from(4)).unwrap_err(), Error::StackOverflow);
+    }
+
+    #[test]
+    fn test_custom_stack_base() {
+        use crate::base::B64;
+        let base = B64::from(0x10000);
+        let mut sm = StateMachine::<B64, B64, 8, 8>::from_config(
+            ConfigBuilder::<B64, 8>::new()
+                .memory_size(0x1000)
+                .stack_base(base)
+                .stack_depth(1)
+                .guard_cells(1)
+                .build()
+                .unwrap(),
+        );
+        // A stack of a single cell
+        let address = match sm.push(B64::from(7)).unwrap() {
+            (1, CellInteraction::SingleCell(_, address, _)) => address,
+            _ => panic!("Push must be a single cell interaction"),
+        };
+        assert_eq!(address, base);
+        assert_eq!(sm.push(B64::from(8)).unwrap_err(), Error::StackOverflow);
+        match sm.pop().unwrap() {
+            (0, CellInteraction::SingleCell(_, _, value)) => assert_eq!(value, B64::from(7)),
+            _ => panic!("Pop must be a single cell interaction"),
+        }
+        assert_eq!(sm.pop().unwrap_err(), Error::StackUnderflow);
+    }
+
+    #[test]
+    fn test_invalid_machine_config() {
+        // The alignment is only checked once the sizes are valid
+        let errors = StateMachine::<B256, B256, 32, 32>::try_new(ConfigArgs {
+            stack_depth: B256::zero(),
+            buffer_size: B256::from(16),
+            ..DefaultConfig::default_config()
+        })
+        .unwrap_err();
+        assert_eq!(errors, vec![Error::EmptyStack]);
+        let errors = StateMachine::<B256, B256, 32, 32>::try_new(ConfigArgs {
+            buffer_size: B256::from(16),
+            ..DefaultConfig::default_config()
+        })
+        .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![Error::MisalignedSection {
+                region: RegionKind::Register
+            }]
+        );
+        assert!(
+            StateMachine::<B256, B256, 32, 32>::try_new(DefaultConfig::default_config()).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_narrow_region_cells() {
+        // A ring buffer of 64-bit cells in a 256-bit machine
+        let ring = B256::from(0x50000);
+        let layout =
+            MemoryLayout::new(&[MemoryRegion::new(RegionKind::Io, ring, B256::from(0x40))
+                .with_word_size(B256::from(8))])
+            .unwrap();
+        let config = |alignment| ConfigArgs {
+            alignment,
+            layout: Some(layout),
+            ..DefaultConfig::default_config()
+        };
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(config(AlignmentPolicy::Emulate));
+        assert_eq!(sm.cell_size(ring), B256::from(8));
+        assert_eq!(sm.cell_size(B256::from(0x60000)), B256::from(32));
+
+        // The last cell of the ring is narrower than a word
+        let last = ring + B256::from(0x38);
+        sm.write(last, B256::from(5)).unwrap();
+        match sm.read(last).unwrap() {
+            CellInteraction::SingleCell(_, address, value) => {
+                assert_eq!((address, value), (last, B256::from(5)))
+            }
+            _ => panic!("A narrow cell must be a single cell"),
+        }
+        // A narrow access is never split and its value must fit in the cell
+        assert_eq!(
+            sm.read(ring + B256::from(4)).unwrap_err(),
+            Error::UnalignedAccess {
+                addr: (ring + B256::from(4)).fixed_be_bytes(),
+                word_size: 8,
+            }
+        );
+        assert_eq!(
+            sm.write(ring, B256::from(u64::MAX) + B256::from(1))
+                .unwrap_err(),
+            Error::ValueOutOfRange
+        );
+        sm.write(ring, B256::from(u64::MAX)).unwrap();
+        assert_eq!(sm.trace().len(), 3);
+
+        // Rounding down stays inside the narrow cell
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(config(AlignmentPolicy::RoundDown));
+        match sm.write(last + B256::from(7), B256::from(6)).unwrap() {
+            CellInteraction::SingleCell(_, address, _) => assert_eq!(address, last),
+            _ => panic!("A narrow cell must be a single cell"),
+        }
+
+        // The word size of a region divides the word size of the machine
+        for (word_size, error) in [
+            (
+                0u64,
+                Error::InvalidRegionWordSize {
+                    region: RegionKind::Io,
+                },
+            ),
+            (
+                12,
+                Error::InvalidRegionWordSize {
+                    region: RegionKind::Io,
+                },
+            ),
+            (
+                64,
+                Error::InvalidRegionWordSize {
+                    region: RegionKind::Io,
+                },
+            ),
+            (
+                16,
+                Error::MisalignedSection {
+                    region: RegionKind::Io,
+                },
+            ),
+        ] {
+            let layout = MemoryLayout::new(&[MemoryRegion::new(
+                RegionKind::Io,
+                ring + B256::from(8),
+                B256::from(0x40),
+            )
+            .with_word_size(B256::from(word_size))])
+            .unwrap();
+            let errors = StateMachine::<B256, B256, 32, 32>::try_new(ConfigArgs {
+                layout: Some(layout),
+                ..DefaultConfig::default_config()
+            })
+            .unwrap_err();
+            assert_eq!(errors, vec![error]);
+        }
+    }
+
+    #[test]
+    fn test_programs_under_presets() {
+        use crate::{
+            base::B32,
+            config::{EVM_CALLDATA_BASE, EVM_CALLDATA_SIZE},
+            machine::validate::validate_trace,
+        };
+        // Store a pushed word at an unaligned offset and load the calldata
+        let mut evm = StateMachine::<B256, B256, 32, 32>::from_config(Config::evm());
+        evm.init_region(EVM_CALLDATA_BASE, &[B256::from(0xca11)])
+            .unwrap();
+        evm.push(B256::from(0x20)).unwrap();
+        let offset = evm.base_address() + B256::from(0x41);
+        evm.write(offset, B256::from(0x20)).unwrap();
+        assert!(matches!(
+            evm.read(EVM_CALLDATA_BASE).unwrap(),
+            CellInteraction::SingleCell(_, _, value) if value == B256::from(0xca11)
+        ));
+        assert_eq!(
+            evm.write(EVM_CALLDATA_BASE, B256::zero()).unwrap_err(),
+            Error::PermissionDenied {
+                addr: EVM_CALLDATA_BASE.fixed_be_bytes(),
+                region: RegionKind::Io,
+                access: Permission::Write,
+            }
+        );
+        let past_calldata = EVM_CALLDATA_BASE + B256::from(EVM_CALLDATA_SIZE);
+        assert!(matches!(
+            evm.read(past_calldata),
+            Err(Error::AccessOutsideRegion { .. })
+        ));
+        evm.pop().unwrap();
+
+        // Load an immediate into a register and store it to an aligned word
+        let mut riscv = StateMachine::<B32, B32, 4, 4>::from_config(Config::riscv32());
+        let x1 = riscv.r1;
+        riscv.set(x1, B32::from(5)).unwrap();
+        let store = riscv.base_address() + B32::from(8);
+        riscv.write(store, B32::from(5)).unwrap();
+        assert!(matches!(
+            riscv.write(store + B32::from(1), B32::from(5)),
+            Err(Error::UnalignedAccess { .. })
+        ));
+
+        // Push a constant on the value stack and store it at an unaligned offset
+        let mut wasm = StateMachine::<B32, B32, 4, 4>::from_config(Config::wasm32());
+        wasm.push(B32::from(7)).unwrap();
+        let store = wasm.base_address() + B32::from(2);
+        assert!(matches!(
+            wasm.write(store, B32::from(7)).unwrap(),
+            CellInteraction::DoubleCell(..)
+        ));
+        // The registers are not mapped
+        let x1 = wasm.r1;
+        assert!(matches!(
+            wasm.set(x1, B32::from(1)),
+            Err(Error::AccessOutsideRegion { .. })
+        ));
+        for sm in [riscv, wasm] {
+            assert_eq!(validate_trace(&sm.trace()), Ok(()));
+        }
+        assert_eq!(validate_trace(&evm.trace()), Ok(()));
+    }
+
+    // Push values until the stack region is full, then check every element with peek
+    fn fill_stack<const S: usize, K: Base<S>>() {
+        let mut sm = StateMachine::<K, K, S, S>::new(ConfigArgs {
+            head_layout: true,
+            stack_depth: K::from(16u64),
+            no_register: K::from(4u64),
+            buffer_size: K::from(32u64),
+            register_base: None,
+            stack_base: None,
+            guard_cells: K::zero(),
+            alignment: AlignmentPolicy::Emulate,
+            read_policy: ReadPolicy::ZeroOnUninit,
+            endianness: Endianness::Big,
+            limits: ResourceLimits::default(),
+            cost_model: CostModel::default(),
+            gas_budget: None,
+            layout: None,
+        });
+        for i in 0..16u64 {
+            assert_eq!(sm.push(K::from(i)).unwrap().0, i + 1);
+        }
+        assert_eq!(sm.push(K::from(16u64)).unwrap_err(), Error::StackOverflow);
+        assert_eq!(sm.get_stack_depth(), 16);
+
+        for depth in 0..16u64 {
+            match sm.peek(depth).unwrap() {
+                CellInteraction::SingleCell(_, _, value) => assert_eq!(value, K::from(15 - depth)),
+                _ => panic!("Peek must be a single cell interaction"),
+            }
+        }
+        assert_eq!(sm.peek(16).unwrap_err(), Error::StackUnderflow);
+        // Peeking does not move the stack pointer
+        assert_eq!(sm.get_stack_depth(), 16);
+
+        for i in (0..16u64).rev() {
+            match sm.pop().unwrap() {
+                (_, CellInteraction::SingleCell(_, _, value)) => assert_eq!(value, K::from(i)),
+                _ => panic!("Pop must be a single cell interaction"),
+            }
+        }
+        assert_eq!(sm.pop().unwrap_err(), Error::StackUnderflow);
+        assert_eq!(sm.peek(0).unwrap_err(), Error::StackUnderflow);
+    }
+
+    #[test]
+    fn test_deep_stack_all_widths() {
+        fill_stack::<32, B256>();
+        fill_stack::<8, crate::base::B64>();
+        fill_stack::<4, crate::base::B32>();
+    }
+
+    #[test]
+    fn test_interleaved_stack_and_heap_time_log() {
+        use crate::machine::AbstractTraceRecord;
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+
+        sm.push(B256::from(1)).unwrap();
+        sm.write(base, B256::from(2)).unwrap();
+        sm.push(B256::from(3)).unwrap();
+        sm.peek(1).unwrap();
+        sm.read(base).unwrap();
+        sm.pop().unwrap();
+        // An unaligned write uses two time log slots
+        sm.write(base + B256::from(1), B256::from(4)).unwrap();
+        sm.pop().unwrap();
+
+        let trace = sm.trace();
+        assert_eq!(trace.len(), 9);
+        for (index, record) in trace.iter().enumerate() {
+            assert_eq!(record.time_log(), index as u64);
+        }
+        assert_eq!(sm.trace().last().map(|record| record.time_log()), Some(8));
+    }
+
+    #[test]
+    fn test_register_file() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let (register_lo, register_hi) =
+            (sm.register_allocated.low(), sm.register_allocated.high());
+
+        // Indexed registers are the named registers of the machine
+        for (index, register) in [sm.r0, sm.r1, sm.r2, sm.r3, sm.r4].iter().enumerate() {
+            let indexed = sm.new_register(index).expect("Register must exist");
+            assert_eq!(indexed.address(), register.address());
+        }
+
+        sm.set_register(0, B256::from(7)).unwrap();
+        sm.set_register(31, B256::from(9)).unwrap();
+        match sm.get_register(31).unwrap() {
+            CellInteraction::SingleCell(_, address, value) => {
+                assert_eq!(address, register_lo + B256::from(31 * 32));
+                assert_eq!(value, B256::from(9));
+            }
+            _ => panic!("Register must be a single cell"),
+        }
+
+        // Register accesses are recorded in the register section
+        let trace = sm.trace();
+        assert_eq!(trace.len(), 3);
+        for record in trace.iter() {
+            let (_, _, _, address, _) = record.get_tuple();
+            assert!(address >= register_lo && address < register_hi);
+        }
+
+        assert_eq!(
+            sm.set_register(32, B256::from(1)).unwrap_err(),
+            Error::RegisterOutOfRange
+        );
+        assert_eq!(
+            sm.get_register(usize::MAX).unwrap_err(),
+            Error::RegisterOutOfRange
+        );
+        assert_eq!(sm.trace().len(), 3);
+    }
+
+    #[test]
+    fn test_register_file_custom_base() {
+        let base = B256::from(0x100000);
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(ConfigArgs {
+            register_base: Some(base),
+            ..DefaultConfig::default_config()
+        });
+        match sm.set_register(2, B256::from(5)).unwrap() {
+            CellInteraction::SingleCell(_, address, _) => {
+                assert_eq!(address, base + B256::from(64))
+            }
+            _ => panic!("Register must be a single cell"),
+        }
+    }
+
+    #[test]
+    fn test_trace_iter() {
+        use crate::machine::AbstractTraceRecord;
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+        sm.push(B256::from(2)).unwrap();
+        sm.read(base + B256::from(32)).unwrap();
+        sm.pop().unwrap();
+
+        assert_eq!(sm.trace_len(), 4);
+        let time_logs: Vec<u64> = sm.trace_iter().map(|record| record.time_log()).collect();
+        assert_eq!(time_logs, vec![0, 1, 2, 3]);
+        // Several passes over the same trace
+        assert!(sm.trace_iter().copied().eq(sm.trace()));
+        assert_eq!(
+            sm.trace_iter()
+                .filter(|record| record.instruction().is_write())
+                .count(),
+            2
+        );
+
+        let trace = sm.trace();
+        assert_eq!(sm.into_trace(), trace);
+    }
+
+    #[test]
+    fn test_sparse_memory_backend() {
+        use crate::machine::backend::SparseMemory;
+        use alloc::collections::BTreeMap;
+        use rand::{Rng, SeedableRng};
+        use rand_chacha::ChaCha20Rng;
+        let mut rng = ChaCha20Rng::seed_from_u64(0x5eed);
+        let config = Config::try_new(B256::WORD_SIZE, DefaultConfig::default_config()).unwrap();
+        let mut dense = StateMachine::<B256, B256, 32, 32>::from_config(config);
+        let mut sparse = StateMachine::<B256, B256, 32, 32, SparseMemory<B256, B256>>::with_memory(
+            config,
+            SparseMemory::new(),
+        );
+        // Aligned cells scattered over the whole 256-bit address space
+        let cells: Vec<B256> = (0..64)
+            .map(|_| {
+                let mut address = rng.gen::<[u8; 32]>();
+                address[0] |= 0x80 >> rng.gen_range(0..8);
+                address[31] &= 0xe0;
+                B256::from(address)
+            })
+            .collect();
+
+        // Same random accesses on both backends and on a reference map
+        let mut reference = BTreeMap::new();
+        for _ in 0..2000 {
+            let address = cells[rng.gen_range(0..cells.len())];
+            if rng.gen_bool(0.5) {
+                let value = B256::from(rng.gen::<[u8; 32]>());
+                dense.write(address, value).unwrap();
+                sparse.write(address, value).unwrap();
+                reference.insert(address, value);
+            } else {
+                let expected = reference.get(&address).copied().unwrap_or(B256::zero());
+                for interaction in [dense.read(address).unwrap(), sparse.read(address).unwrap()] {
+                    assert!(matches!(
+                        interaction,
+                        CellInteraction::SingleCell(_, _, value) if value == expected
+                    ));
+                }
+            }
+        }
+        assert_eq!(sparse.trace(), dense.trace());
+        assert_eq!(dense.trace_len(), 2000);
+
+        // Only the written cells are stored
+        let snapshot = dense.snapshot();
+        assert_eq!(sparse.snapshot(), snapshot);
+        assert_eq!(snapshot.cells().len(), reference.len());
+        assert!(snapshot
+            .cells()
+            .iter()
+            .copied()
+            .eq(reference.iter().map(|(address, value)| (*address, *value))));
+        for address in cells {
+            let expected = reference.get(&address).copied().unwrap_or(B256::zero());
+            assert_eq!(dense.dummy_read(address), expected);
+            assert_eq!(sparse.dummy_read(address), expected);
+        }
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        use crate::machine::AbstractTraceRecord;
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let cell = |i: usize| base + B256::from(i) * B256::WORD_SIZE;
+        for i in 0..100 {
+            sm.write(cell(i), B256::from(i + 1)).unwrap();
+        }
+        sm.push(B256::from(7)).unwrap();
+        let snapshot = sm.snapshot();
+        let memory_before = sm.dump_region(base, 1000);
+        let trace_before = sm.trace();
+        assert_eq!(snapshot.time_log(), 101);
+        assert_eq!(snapshot.stack_depth(), 1);
+
+        // Speculative execution that touches 1000 cells and the stack
+        for i in 0..1000 {
+            sm.write(cell(i), B256::from(i * 3)).unwrap();
+        }
+        sm.pop().unwrap();
+        sm.push(B256::from(8)).unwrap();
+        sm.push(B256::from(9)).unwrap();
+        assert_eq!(sm.trace_len(), 1104);
+
+        sm.restore(&snapshot).unwrap();
+        assert_eq!(sm.dump_region(base, 1000), memory_before);
+        assert_eq!(sm.trace(), trace_before);
+        assert_eq!(sm.get_stack_depth(), 1);
+        assert_eq!(sm.snapshot(), snapshot);
+
+        // The trace continues right after the snapshot point
+        sm.write(cell(0), B256::from(42)).unwrap();
+        assert!(matches!(
+            sm.pop().unwrap().1,
+            CellInteraction::SingleCell(_, _, value) if value == B256::from(7)
+        ));
+        let time_logs: Vec<u64> = sm.trace_iter().map(|record| record.time_log()).collect();
+        assert_eq!(time_logs, (0..103).collect::<Vec<u64>>());
+
+        // A snapshot can be restored several times
+        sm.restore(&snapshot).unwrap();
+        assert_eq!(sm.trace(), trace_before);
+        assert_eq!(sm.dummy_read(cell(0)), B256::from(1));
+    }
+
+    #[test]
+    fn test_snapshot_config_fingerprint() {
+        let config = |depth| {
+            ConfigBuilder::<B256, 32>::new()
+                .stack_depth(depth)
+                .build()
+                .unwrap()
+        };
+        let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(config(64));
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+        let image = sm.snapshot();
+        assert_eq!(image.config_fingerprint(), config(64).config_fingerprint());
+        assert_ne!(image.config_fingerprint(), config(32).config_fingerprint());
+
+        // The image loads under the same config only
+        let mut same = StateMachine::<B256, B256, 32, 32>::from_config(config(64));
+        same.init_from(&image).unwrap();
+        assert_eq!(same.snapshot().cells(), image.cells());
+        let mut other = StateMachine::<B256, B256, 32, 32>::from_config(config(32));
+        assert_eq!(
+            other.init_from(&image),
+            Err(Error::ConfigMismatch {
+                expected: config(32).config_fingerprint(),
+                found: image.config_fingerprint()
+            })
+        );
+        other
+            .init_from_with_check(&image, FingerprintCheck::Force)
+            .unwrap();
+        assert_eq!(other.snapshot().cells(), image.cells());
+    }
+
+    #[test]
+    fn test_trace_modes() {
+        let run = |mode| {
+            let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(
+                ConfigBuilder::new().trace_mode(mode).build().unwrap(),
+            );
+            let base = sm.base_address();
+            sm.write(base, B256::from(1)).unwrap();
+            sm.write(base + B256::from(7), B256::from(2)).unwrap();
+            sm.read(base).unwrap();
+            sm.push(B256::from(3)).unwrap();
+            sm.pop().unwrap();
+            sm
+        };
+        let full = run(TraceMode::Full);
+        let commitments = run(TraceMode::CommitmentsOnly);
+        let disabled = run(TraceMode::Disabled);
+
+        // The accesses do not depend on the mode, only the kept records do
+        assert_eq!(commitments.snapshot(), full.snapshot());
+        assert_eq!(disabled.snapshot(), full.snapshot());
+        let trace = full.full_trace().unwrap();
+        assert_eq!(trace, full.trace());
+        assert!(trace.len() > 5);
+        assert_eq!(full.trace_commitment(), None);
+        assert_eq!(commitments.trace_len(), 0);
+        assert_eq!(disabled.trace_len(), 0);
+        assert_eq!(disabled.trace_commitment(), None);
+
+        // The commitment is the one of a hashing sink fed with the full trace
+        let hasher = HashingSink::new();
+        for record in trace {
+            TraceSink::push(&mut hasher.clone(), record).unwrap();
+        }
+        assert_eq!(commitments.trace_commitment(), Some(hasher.digest()));
+        // A clone goes on hashing on its own
+        let mut clone = commitments.clone();
+        clone.read(clone.base_address()).unwrap();
+        assert_ne!(clone.trace_commitment(), commitments.trace_commitment());
+        assert_eq!(commitments.trace_commitment(), Some(hasher.digest()));
+
+        // The APIs that need the full trace fail in the reduced modes
+        for sm in [commitments, disabled] {
+            let error = Error::TraceNotRetained {
+                mode: sm.trace_mode(),
+            };
+            assert_eq!(sm.full_trace(), Err(error));
+            let mut parent = sm.clone();
+            assert_eq!(sm.fork().merge_into_parent(&mut parent), Err(error));
+            #[cfg(feature = "std")]
+            {
+                assert_eq!(sm.checkpoint_to(Vec::new()), Err(error));
+                let export = sm.trace_export_jsonl(Vec::new()).unwrap_err();
+                assert_eq!(export.kind(), std::io::ErrorKind::Unsupported);
+                assert_eq!(export.to_string(), std::format!("{}", error));
+            }
+        }
+    }
+
+    #[test]
+    fn test_init_from_image() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let cell = |i: usize| base + B256::from(i) * B256::WORD_SIZE;
+        for i in 0..10 {
+            sm.write(cell(i), B256::from(i + 1)).unwrap();
+        }
+        let image = sm.snapshot();
+        sm.write(cell(3), B256::from(30)).unwrap();
+        let changed = sm.snapshot();
+        assert_eq!(sm.initial_commitment(), None);
+        assert_eq!(sm.init_from(&image), Err(Error::MachineAlreadyStarted));
+
+        let mut first = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let mut second = first.clone();
+        let mut third = first.clone();
+        first.init_from(&image).unwrap();
+        second.init_from(&image).unwrap();
+        third.init_from(&changed).unwrap();
+        assert!(first.initial_commitment().is_some());
+        assert_eq!(first.initial_commitment(), second.initial_commitment());
+        assert_ne!(first.initial_commitment(), third.initial_commitment());
+
+        // The image cells are read without any write record
+        assert_eq!(first.trace_len(), 0);
+        for i in 0..10 {
+            assert!(matches!(
+                first.read(cell(i)).unwrap(),
+                CellInteraction::SingleCell(_, _, value) if value == B256::from(i + 1)
+            ));
+        }
+        assert_eq!(first.trace_len(), 10);
+        assert_eq!(first.snapshot().cells(), image.cells());
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        use crate::config::ConfigBuilder;
+        let machine = |limits: ResourceLimits| {
+            StateMachine::<B256, B256, 32, 32>::from_config(
+                ConfigBuilder::new().limits(limits).build().unwrap(),
+            )
+        };
+
+        // The 3rd record is allowed, the 4th is rejected
+        let mut sm = machine(ResourceLimits {
+            max_trace_len: Some(3),
+            ..ResourceLimits::default()
+        });
+        let base = sm.base_address();
+        let cell = |i: usize| base + B256::from(i) * B256::WORD_SIZE;
+        sm.write(cell(0), B256::from(1)).unwrap();
+        sm.read(cell(0)).unwrap();
+        sm.read(cell(1)).unwrap();
+        assert_eq!(
+            sm.read(cell(0)).unwrap_err(),
+            Error::ResourceLimit {
+                which: ResourceKind::TraceLen,
+                limit: 3,
+                attempted: 4,
+            }
+        );
+        assert_eq!(
+            sm.resource_usage(),
+            ResourceUsage {
+                trace_len: 3,
+                distinct_cells: 1,
+                bytes_written: 32,
+            }
+        );
 
-        fn word_size(&self) -> K {
-            self.word_size
-        }
+        // The records are counted as they are tracked and recounted when the trace is truncated
+        assert_eq!(sm.record_counter(), Some(3));
+        let mut rewound = machine(ResourceLimits {
+            max_trace_len: Some(3),
+            ..ResourceLimits::default()
+        });
+        let snapshot = rewound.snapshot();
+        rewound.write(cell(0), B256::from(1)).unwrap();
+        rewound.write(cell(1), B256::from(2)).unwrap();
+        rewound.restore(&snapshot).unwrap();
+        assert_eq!(rewound.record_count(), 0);
+        rewound.write(cell(0), B256::from(1)).unwrap();
+        rewound.read(cell(0)).unwrap();
+        rewound.read(cell(0)).unwrap();
+        assert_eq!(rewound.record_count(), 3);
+
+        // Overwriting a stored cell does not use a new one
+        let mut sm = machine(ResourceLimits {
+            max_distinct_cells: Some(2),
+            ..ResourceLimits::default()
+        });
+        sm.write(cell(0), B256::from(1)).unwrap();
+        sm.write(cell(1), B256::from(2)).unwrap();
+        sm.write(cell(0), B256::from(3)).unwrap();
+        sm.read(cell(2)).unwrap();
+        assert_eq!(
+            sm.write(cell(2), B256::from(4)).unwrap_err(),
+            Error::ResourceLimit {
+                which: ResourceKind::DistinctCells,
+                limit: 2,
+                attempted: 3,
+            }
+        );
+        assert_eq!(sm.trace_len(), 4);
+
+        // An unaligned write stores two cells
+        let mut sm = machine(ResourceLimits {
+            max_bytes_written: Some(96),
+            ..ResourceLimits::default()
+        });
+        sm.write(cell(0), B256::from(1)).unwrap();
+        sm.write(cell(1) + B256::from(8), B256::from(2)).unwrap();
+        assert_eq!(sm.resource_usage().bytes_written, 96);
+        assert_eq!(
+            sm.write(cell(0), B256::from(3)).unwrap_err(),
+            Error::ResourceLimit {
+                which: ResourceKind::BytesWritten,
+                limit: 96,
+                attempted: 128,
+            }
+        );
+        assert_eq!(sm.dummy_read(cell(0)), B256::from(1));
+        assert_eq!(sm.resource_usage().bytes_written, 96);
+    }
 
-        fn register_start(&self) -> K {
-            self.register_allocated.low()
+    // Machine charging 1 gas per read, 2 per write, 3 per push and 4 per pop
+    fn metered_machine(budget: Option<u64>) -> StateMachine<B256, B256, 32, 32> {
+        let mut builder = ConfigBuilder::new().cost_model(CostModel {
+            read: 1,
+            write: 2,
+            push: 3,
+            pop: 4,
+            grow: 0,
+        });
+        if let Some(budget) = budget {
+            builder = builder.gas_budget(budget);
         }
+        StateMachine::from_config(builder.build().unwrap())
+    }
 
-        fn ro_context(&self) -> &'_ Self::Context {
-            self
-        }
+    #[test]
+    fn test_gas_budget() {
+        let mut sm = metered_machine(Some(10));
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+        sm.push(B256::from(2)).unwrap();
+        // An unaligned read costs the same as an aligned one
+        sm.read(base + B256::from(8)).unwrap();
+        assert_eq!(sm.gas_used(), 6);
+
+        // The access that reaches the budget exactly is allowed
+        sm.pop().unwrap();
+        assert_eq!(sm.gas_used(), 10);
+        assert_eq!(
+            sm.read(base).unwrap_err(),
+            Error::OutOfGas {
+                budget: 10,
+                used: 10,
+                cost: 1,
+            }
+        );
+        assert_eq!(sm.trace_len(), 5);
+        assert_eq!(sm.gas_used(), 10);
+
+        // A rejected pop leaves the stack untouched
+        let mut sm = metered_machine(Some(9));
+        sm.write(base, B256::from(1)).unwrap();
+        sm.push(B256::from(2)).unwrap();
+        sm.read(base).unwrap();
+        assert_eq!(
+            sm.pop().unwrap_err(),
+            Error::OutOfGas {
+                budget: 9,
+                used: 6,
+                cost: 4,
+            }
+        );
+        assert_eq!(sm.get_stack_depth(), 1);
+        assert_eq!(sm.gas_used(), 6);
 
-        fn track(&mut self, trace: Self::TraceRecord) {
-            self.execution_trace.insert(trace, PhantomData);
+        // Without a budget the gas is only counted
+        let mut sm = metered_machine(None);
+        for _ in 0..10 {
+            sm.push(B256::from(1)).unwrap();
         }
+        assert_eq!(sm.gas_used(), 30);
+    }
 
-        fn trace(&self) -> Vec<Self::TraceRecord> {
-            self.execution_trace.keys().copied().collect()
-        }
+    #[test]
+    fn test_snapshot_restores_gas() {
+        let mut sm = metered_machine(Some(12));
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+        let snapshot = sm.snapshot();
+        assert_eq!(snapshot.gas_used(), 2);
+        sm.write(base, B256::from(2)).unwrap();
+        sm.push(B256::from(3)).unwrap();
+        assert_eq!(sm.gas_used(), 7);
+
+        // The gas of the discarded accesses is refunded
+        sm.restore(&snapshot).unwrap();
+        assert_eq!(sm.gas_used(), 2);
+        assert_eq!(sm.snapshot(), snapshot);
+
+        // A merged branch charges its gas to the parent
+        let mut fork = sm.fork();
+        fork.machine_mut().push(B256::from(4)).unwrap();
+        fork.machine_mut().pop().unwrap();
+        sm.read(base).unwrap();
+        fork.merge_into_parent(&mut sm).unwrap();
+        assert_eq!(sm.gas_used(), 10);
+
+        // A branch over the budget of the parent is rejected
+        let mut fork = sm.fork();
+        fork.machine_mut().write(base, B256::from(5)).unwrap();
+        sm.read(base + B256::WORD_SIZE).unwrap();
+        assert_eq!(
+            fork.merge_into_parent(&mut sm).unwrap_err(),
+            Error::OutOfGas {
+                budget: 12,
+                used: 11,
+                cost: 2,
+            }
+        );
+        assert_eq!(sm.gas_used(), 11);
+    }
 
-        fn exec(&mut self, instruction: &Self::Instruction) {
-            instruction.exec(self);
-        }
+    #[test]
+    fn test_final_state_root() {
+        use crate::{
+            commitment::{merkle::MerkleTraceCommitment, CommitmentScheme},
+            constraints::gadgets::ConvertedTraceRecord,
+            machine::MemoryInstruction,
+        };
+        use halo2curves::pasta::Fp;
 
-        fn base_address(&self) -> K {
-            self.memory_allocated.low()
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let cell = |i: u64| base + B256::from(i) * B256::WORD_SIZE;
+        for i in 0..4 {
+            sm.write(cell(i), B256::from(i + 1)).unwrap();
         }
-
-        fn get_memory_address(&self) -> (K, K) {
-            (self.memory_allocated.low(), self.memory_allocated.high())
+        let root = sm.final_state_root(CommitmentBackend::Sparse).unwrap();
+        assert_eq!(sm.final_state_root(CommitmentBackend::Dense), Ok(root));
+        assert_eq!(commit_image(&sm.dump(base, 4)), root);
+
+        // Reads keep the root, any single cell change gives another one
+        sm.read(cell(2)).unwrap();
+        assert_eq!(sm.final_state_root(CommitmentBackend::Sparse), Ok(root));
+        let snapshot = sm.snapshot();
+        for i in 0..5 {
+            sm.write(cell(i), B256::from(9)).unwrap();
+            let changed = sm.final_state_root(CommitmentBackend::Sparse).unwrap();
+            assert_ne!(changed, root);
+            assert_eq!(sm.final_state_root(CommitmentBackend::Dense), Ok(changed));
+            sm.restore(&snapshot).unwrap();
+            assert_eq!(sm.final_state_root(CommitmentBackend::Sparse), Ok(root));
         }
 
-        fn get_stack_depth(&self) -> u64 {
-            self.ro_context().stack_depth
-        }
+        // A commitment scheme commits the cells as writes in address order
+        let records: Vec<ConvertedTraceRecord<Fp>> = (0..4)
+            .map(|i| {
+                ConvertedTraceRecord::from_trace_record(&TraceRecord::<B256, B256, 32, 32>::new(
+                    0,
+                    0,
+                    MemoryInstruction::Write,
+                    cell(i),
+                    B256::from(i + 1),
+                ))
+                .unwrap()
+            })
+            .collect();
+        let scheme = MerkleTraceCommitment::default();
+        assert_eq!(
+            sm.commit_final_state::<Fp, _>(&scheme),
+            scheme.commit(&records)
+        );
+    }
 
-        fn max_stack_depth(&self) -> u64 {
-            self.ro_context().max_stack_depth
+    #[test]
+    fn test_commitment_kind() {
+        // The same program under every backend, only the config changes
+        let run = |kind: CommitmentKind, mode: TraceMode| {
+            let config = ConfigBuilder::<B256, 32>::new()
+                .commitment(kind)
+                .trace_mode(mode)
+                .build()
+                .unwrap();
+            let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(config);
+            let base = sm.base_address();
+            for i in 0..4u64 {
+                sm.write(base + B256::from(32 * i), B256::from(i + 1))
+                    .unwrap();
+            }
+            sm.read(base + B256::from(32)).unwrap();
+            sm
+        };
+        let kinds = [
+            CommitmentKind::default(),
+            CommitmentKind::Merkle {
+                hash: HashKind::Poseidon,
+            },
+            CommitmentKind::SparseMerkle,
+            CommitmentKind::Kzg {
+                k: 6,
+                seed: [5; 32],
+            },
+            CommitmentKind::Pedersen { k: 6 },
+        ];
+        let mut roots = Vec::new();
+        for kind in kinds {
+            let sm = run(kind, TraceMode::CommitmentsOnly);
+            assert_eq!(sm.commitment_kind(), kind);
+            let root = sm.final_state_root(CommitmentBackend::Sparse).unwrap();
+            assert_eq!(sm.final_state_root(CommitmentBackend::Dense), Ok(root));
+            let cells = sm.dump(sm.base_address(), 4);
+            let committer = StateCommitter::new(kind).unwrap();
+            assert_eq!(committer.commit_image(&cells), Ok(root));
+            assert!(!roots.contains(&root));
+            roots.push(root);
+
+            // The trace is committed by the same backend
+            let sink = CommitmentSink::new(kind);
+            for record in run(kind, TraceMode::Full).trace() {
+                TraceSink::push(&mut sink.clone(), record).unwrap();
+            }
+            assert_eq!(sm.trace_commitment(), Some(sink.digest()));
+
+            // And so is an initial image
+            let config = ConfigBuilder::<B256, 32>::new()
+                .commitment(kind)
+                .build()
+                .unwrap();
+            let mut fresh = StateMachine::<B256, B256, 32, 32>::from_config(config);
+            fresh.init_from(&sm.snapshot()).unwrap();
+            assert_eq!(fresh.initial_commitment(), Some(root));
         }
     }
 
-    impl<K, V, const S: usize, const T: usize> AbstractMemoryMachine<K, V, S, T>
-        for StateMachine<K, V, S, T>
-    where
-        K: Base<S>,
-        V: Base<T>,
-        Self: AbstractMachine<K, V>,
-    {
+    #[test]
+    fn test_sort_trace() {
+        use crate::machine::{
+            is_sorted_by_address_time, is_sorted_by_time, sort_trace, sort_trace_in_place,
+            AbstractTraceRecord, MemoryInstruction,
+        };
+        let record = |time_log: u64, address: u64| {
+            TraceRecord::<B256, B256, 32, 32>::new(
+                time_log,
+                0,
+                MemoryInstruction::Write,
+                B256::from(address),
+                B256::from(time_log),
+            )
+        };
+        // Equal addresses keep the time order, equal times across addresses are allowed
+        let trace = vec![
+            record(0, 0x40),
+            record(1, 0x20),
+            record(2, 0x40),
+            record(3, 0x20),
+            record(3, 0x60),
+            record(5, 0x00),
+        ];
+        assert!(!is_sorted_by_time(&trace));
+        assert!(is_sorted_by_time(&trace[..4]));
+        let sorted = sort_trace(&trace).unwrap();
+        assert_eq!(
+            sorted,
+            vec![
+                record(5, 0x00),
+                record(1, 0x20),
+                record(3, 0x20),
+                record(0, 0x40),
+                record(2, 0x40),
+                record(3, 0x60),
+            ]
+        );
+        assert!(is_sorted_by_address_time(&sorted));
+        assert!(!is_sorted_by_address_time(&trace));
+        assert!(is_sorted_by_address_time::<B256, B256, 32, 32>(&[]));
+
+        // Duplicated (address, time_log) pairs are listed once each
+        let mut duplicated = vec![
+            record(4, 0x20),
+            record(1, 0x40),
+            record(4, 0x20),
+            record(1, 0x40),
+            record(4, 0x20),
+            record(2, 0x40),
+        ];
+        assert_eq!(
+            sort_trace(&duplicated),
+            Err(vec![(B256::from(0x20), 4), (B256::from(0x40), 1)])
+        );
+        assert!(sort_trace_in_place(&mut duplicated).is_err());
+        assert!(!is_sorted_by_address_time(&duplicated));
+        assert_eq!(duplicated[0].address, B256::from(0x20));
+        assert_eq!(duplicated[5].time_log, 2);
     }
 
-    impl<K, V, const S: usize, const T: usize> AbstractRegisterMachine<K, V, S, T>
-        for StateMachine<K, V, S, T>
-    where
-        K: Base<S>,
-        V: Base<T>,
-        Self: AbstractMemoryMachine<K, V, S, T>,
-    {
-        fn new_register(&self, register_index: usize) -> Option<crate::machine::Register<K>> {
-            Some(Register::new(
-                register_index,
-                self.register_start() + K::from(register_index) * K::WORD_SIZE,
-            ))
-        }
+    #[test]
+    fn test_filter_source() {
+        use crate::machine::{
+            filter_source,
+            merge::{merge_traces, AddressTag},
+            sort_trace, AbstractTraceRecord, MemoryInstruction,
+        };
+        let record = |time_log: u64, address: u64, source: u8| {
+            TraceRecord::<B256, B256, 32, 32>::new(
+                time_log,
+                0,
+                MemoryInstruction::Write,
+                B256::from(address),
+                B256::from(time_log),
+            )
+            .with_source(source)
+        };
+        let trace = vec![
+            record(0, 0x40, 1),
+            record(1, 0x20, 0),
+            record(2, 0x40, 2),
+            record(3, 0x20, 1),
+        ];
+        assert_eq!(trace[0].source(), 1);
+        assert_eq!(
+            record(0, 0x40, 0),
+            TraceRecord::new(
+                0,
+                0,
+                MemoryInstruction::Write,
+                B256::from(0x40),
+                B256::zero()
+            )
+        );
+        assert_eq!(
+            filter_source(&trace, 1),
+            vec![record(0, 0x40, 1), record(3, 0x20, 1)]
+        );
+        assert_eq!(filter_source(&trace, 0), vec![record(1, 0x20, 0)]);
+        assert!(filter_source(&trace, 3).is_empty());
+
+        // The tags follow their records through sorting and merging
+        let sorted = sort_trace(&trace).unwrap();
+        assert_eq!(
+            filter_source(&sorted, 1),
+            vec![record(3, 0x20, 1), record(0, 0x40, 1)]
+        );
+        let merged = merge_traces(vec![(AddressTag(1), trace.clone())]).unwrap();
+        let sources: Vec<u8> = merged.iter().map(|record| record.source()).collect();
+        assert_eq!(sources, [1, 0, 2, 1]);
     }
 
-    impl<K, V, const S: usize, const T: usize> AbstractStackMachine<K, V, S, T>
-        for StateMachine<K, V, S, T>
-    where
-        K: Base<S>,
-        V: Base<T>,
-        Self: AbstractMemoryMachine<K, V, S, T>,
-    {
+    #[test]
+    fn test_time_log_exhausted() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new_with_time_log(
+            DefaultConfig::default_config(),
+            u64::MAX - 3,
+        );
+        let base = sm.base_address();
+        assert_eq!(sm.remaining_time_budget(), 3);
+        sm.write(base, B256::from(1)).unwrap();
+        // An unaligned access needs two time logs
+        sm.read(base + B256::from(1)).unwrap();
+        assert_eq!(sm.remaining_time_budget(), 0);
+
+        assert_eq!(
+            sm.write(base, B256::from(2)).unwrap_err(),
+            Error::TimeLogExhausted
+        );
+        assert_eq!(sm.read(base).unwrap_err(), Error::TimeLogExhausted);
+        assert_eq!(sm.push(B256::from(3)).unwrap_err(), Error::TimeLogExhausted);
+        // The rejected accesses leave the machine untouched
+        assert_eq!(sm.dummy_read(base), B256::from(1));
+        assert_eq!(sm.get_stack_depth(), 0);
+        assert_eq!(sm.trace_len(), 3);
+        assert_eq!(
+            sm.trace().last().map(|record| record.time_log),
+            Some(u64::MAX - 1)
+        );
+
+        // Without room for both cells an unaligned access is rejected as a whole
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new_with_time_log(
+            DefaultConfig::default_config(),
+            u64::MAX - 1,
+        );
+        assert_eq!(
+            sm.write(base + B256::from(1), B256::from(4)).unwrap_err(),
+            Error::TimeLogExhausted
+        );
+        assert_eq!(sm.trace_len(), 0);
+        sm.write(base, B256::from(4)).unwrap();
     }
 
     #[test]
-    fn test_read_write_one_cell() {
+    fn test_snapshot_diff() {
         let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
         let base = sm.base_address();
-        let write_chunk = B256::from(1025);
-        let program = vec![
-            Instruction::Write(base + B256::from(32), B256::from(1025)),
-            Instruction::Read(base + B256::from(32)),
-        ];
-        // Execute the program
-        for instruction in program {
-            sm.exec(&instruction);
+        let cell = |i: usize| base + B256::from(i) * B256::WORD_SIZE;
+        for i in 0..4 {
+            sm.write(cell(i), B256::from(i + 1)).unwrap();
         }
-        assert_eq!(write_chunk, sm.dummy_read(base + B256::from(32)));
+        let before = sm.snapshot();
+        assert_eq!(sm.dirty_cells_since(&before), vec![]);
+
+        // Cell 1 is written back to its value, cell 3 is cleared and cell 9 is new
+        sm.write(cell(9), B256::from(90)).unwrap();
+        sm.write(cell(1), B256::from(10)).unwrap();
+        sm.write(cell(1), B256::from(2)).unwrap();
+        sm.write(cell(3), B256::zero()).unwrap();
+        sm.write(cell(0), B256::from(100)).unwrap();
+        sm.read(cell(2)).unwrap();
+        let after = sm.snapshot();
+
+        let delta = |i: usize, before: u64, after: u64| CellDelta {
+            addr: cell(i),
+            before: B256::from(before),
+            after: B256::from(after),
+        };
+        let expected = vec![delta(0, 1, 100), delta(3, 4, 0), delta(9, 0, 90)];
+        assert_eq!(before.diff(&after), expected);
+        assert_eq!(sm.dirty_cells_since(&before), expected);
+        // The reverse diff undoes the changes
+        assert!(after
+            .diff(&before)
+            .iter()
+            .zip(expected.iter())
+            .all(|(reverse, delta)| reverse.addr == delta.addr
+                && reverse.before == delta.after
+                && reverse.after == delta.before));
     }
 
+    #[cfg(feature = "borsh")]
     #[test]
-    fn test_read_write_two_cells() {
+    fn test_snapshot_borsh_roundtrip() {
         let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
         let base = sm.base_address();
-        let write_chunk = [5u8; 32];
-        let program = vec![
-            Instruction::Write(base + B256::from(1), B256::from(write_chunk)),
-            Instruction::Read(base + B256::from(0)),
-            Instruction::Read(base + B256::from(32)),
-            Instruction::Read(base + B256::from(1)),
-        ];
-        // Execute the program
-        for instruction in program {
-            sm.exec(&instruction);
+        sm.write(base, B256::from(1)).unwrap();
+        sm.push(B256::from(2)).unwrap();
+        let snapshot = sm.snapshot();
+        let bytes = borsh::to_vec(&snapshot).unwrap();
+        assert_eq!(
+            borsh::from_slice::<crate::machine::MemorySnapshot<B256, B256>>(&bytes).unwrap(),
+            snapshot
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot_serde_roundtrip() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Message {
+            snapshot: crate::machine::MemorySnapshot<B256, B256>,
         }
-        let read_chunk_low = {
-            let mut buffer = [5u8; 32];
-            buffer[0] = 0u8;
-            buffer
-        };
 
-        let read_chunk_high = {
-            let mut buffer = [0u8; 32];
-            buffer[0] = 5u8;
-            buffer
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+        sm.push(B256::from(2)).unwrap();
+        let message = Message {
+            snapshot: sm.snapshot(),
         };
+        let encoded = toml::to_string(&message).unwrap();
+        assert_eq!(toml::from_str::<Message>(&encoded).unwrap(), message);
+    }
 
-        assert_eq!(sm.dummy_read(base), B256::from(read_chunk_low));
+    #[test]
+    fn test_restore_rejects_records_it_can_not_take_back() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(
+            ConfigBuilder::new()
+                .trace_mode(TraceMode::CommitmentsOnly)
+                .build()
+                .unwrap(),
+        );
+        let base = sm.base_address();
+        let snapshot = sm.snapshot();
+        sm.write(base, B256::from(1)).unwrap();
+        let commitment = sm.trace_commitment();
         assert_eq!(
-            sm.dummy_read(base + B256::from(32)),
-            B256::from(read_chunk_high)
+            sm.restore(&snapshot),
+            Err(Error::TraceNotRetained {
+                mode: TraceMode::CommitmentsOnly
+            })
         );
+        assert_eq!(sm.trace_commitment(), commitment);
+        assert_eq!(sm.dummy_read(base), B256::from(1));
+
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        sm.set_trace_sink(Box::new(Vec::<TraceRecord<B256, B256, 32, 32>>::new()));
+        let snapshot = sm.snapshot();
+        sm.write(base, B256::from(1)).unwrap();
+        assert_eq!(sm.restore(&snapshot), Err(Error::TraceInSink));
+        assert_eq!(sm.dummy_read(base), B256::from(1));
+
+        // Nothing is recorded when the trace is disabled
+        let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(
+            ConfigBuilder::new()
+                .trace_mode(TraceMode::Disabled)
+                .build()
+                .unwrap(),
+        );
+        let snapshot = sm.snapshot();
+        sm.write(base, B256::from(1)).unwrap();
+        sm.restore(&snapshot).unwrap();
+        assert_eq!(sm.snapshot(), snapshot);
+    }
+
+    fn machine_with_policy(alignment: AlignmentPolicy) -> StateMachine<B256, B256, 32, 32> {
+        StateMachine::new(ConfigArgs {
+            alignment,
+            ..DefaultConfig::default_config()
+        })
     }
 
     #[test]
-    fn test_arithmetics() {
-        let chunk1 = [5u8; 32];
-        let chunk2 = [190u8; 32];
-        let add_chunk = [195u8; 32];
+    fn test_alignment_strict() {
+        let mut sm = machine_with_policy(AlignmentPolicy::Strict);
+        let base = sm.base_address();
+        let unaligned = base + B256::WORD_SIZE - B256::from(1);
+        let expected = Error::UnalignedAccess {
+            addr: unaligned.fixed_be_bytes(),
+            word_size: 32,
+        };
+        assert_eq!(sm.read(unaligned).unwrap_err(), expected);
+        assert_eq!(sm.write(unaligned, B256::from(1)).unwrap_err(), expected);
+        // The first cell of the address space is aligned, WORD_SIZE - 1 is not
+        assert!(sm.write(B256::zero(), B256::from(1)).is_ok());
+        assert!(matches!(
+            sm.read(B256::WORD_SIZE - B256::from(1)),
+            Err(Error::UnalignedAccess { word_size: 32, .. })
+        ));
+        assert_eq!(sm.trace().len(), 1);
+    }
 
-        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+    #[test]
+    fn test_alignment_round_down() {
+        let mut sm = machine_with_policy(AlignmentPolicy::RoundDown);
+        let base = sm.base_address();
+        let unaligned = base + B256::WORD_SIZE - B256::from(1);
+        assert!(matches!(
+            sm.write(unaligned, B256::from(7)).unwrap(),
+            CellInteraction::SingleCell(_, address, _) if address == base
+        ));
+        assert_eq!(sm.dummy_read(base), B256::from(7));
+        assert_eq!(sm.dummy_read(base + B256::WORD_SIZE), B256::zero());
+        assert!(matches!(
+            sm.read(B256::WORD_SIZE - B256::from(1)).unwrap(),
+            CellInteraction::SingleCell(_, address, _) if address == B256::zero()
+        ));
+        assert_eq!(sm.trace().len(), 2);
+    }
 
+    #[test]
+    fn test_alignment_emulate() {
+        let mut sm = machine_with_policy(AlignmentPolicy::Emulate);
         let base = sm.base_address();
-        let program = vec![
-            Instruction::Write(base + B256::from(0), B256::from(chunk1)),
-            Instruction::Write(base + B256::from(32), B256::from(chunk2)),
-            Instruction::Load(sm.r0, base + B256::from(0)),
-            Instruction::Load(sm.r1, base + B256::from(32)),
-            Instruction::Add(sm.r0, sm.r1),
-            Instruction::Save(base + B256::from(64), sm.r0),
+        let unaligned = base + B256::WORD_SIZE - B256::from(1);
+        assert!(matches!(
+            sm.write(unaligned, B256::from([0xffu8; 32])).unwrap(),
+            CellInteraction::DoubleCell(..)
+        ));
+        let mut expected_lo = [0u8; 32];
+        expected_lo[31] = 0xff;
+        let mut expected_hi = [0xffu8; 32];
+        expected_hi[31] = 0;
+        assert_eq!(sm.dummy_read(base), B256::from(expected_lo));
+        assert_eq!(
+            sm.dummy_read(base + B256::WORD_SIZE),
+            B256::from(expected_hi)
+        );
+        assert!(matches!(
+            sm.read(unaligned).unwrap(),
+            CellInteraction::DoubleCell(_, _, value, ..) if value == B256::from([0xffu8; 32])
+        ));
+        assert_eq!(sm.trace().len(), 4);
+    }
+
+    #[test]
+    fn test_memory_layout_enforcement() {
+        let regions = [
+            (RegionKind::Code, 0x10000u64, 0x1000u64),
+            (RegionKind::Stack, 0x20000, 0x800),
+            (RegionKind::Heap, 0x30000, 0x10000),
+            (RegionKind::Io, 0x50000, 0x100),
         ];
-        // Execute the program
-        for instruction in program {
-            sm.exec(&instruction);
+        let layout = MemoryLayout::new(
+            &regions
+                .iter()
+                .map(|(kind, base, length)| {
+                    MemoryRegion::new(*kind, B256::from(*base), B256::from(*length))
+                })
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(ConfigArgs {
+            layout: Some(layout),
+            ..DefaultConfig::default_config()
+        });
+
+        // First and last valid cell of every region
+        for (_, base, length) in regions.iter() {
+            let first = B256::from(*base);
+            let last = B256::from(base + length) - B256::WORD_SIZE;
+            sm.write(first, B256::from(1)).unwrap();
+            sm.write(last, B256::from(2)).unwrap();
+            assert!(sm.read(first).is_ok());
+            assert!(sm.read(last).is_ok());
         }
+        assert_eq!(sm.trace().len(), 16);
 
-        assert_eq!(sm.dummy_read(base + B256::from(64)), B256::from(add_chunk));
+        // Gap between the code and the stack regions
+        let gap = B256::from(0x11000);
+        assert_eq!(
+            sm.write(gap, B256::from(3)).unwrap_err(),
+            Error::AccessOutsideRegion {
+                addr: gap.fixed_be_bytes(),
+                region: Some(RegionKind::Code),
+            }
+        );
+        assert_eq!(sm.dummy_read(gap), B256::zero());
+        // Below every region
+        assert!(matches!(
+            sm.read(B256::zero()),
+            Err(Error::AccessOutsideRegion { region: None, .. })
+        ));
+        // An unaligned access that crosses the end of a region touches a cell outside
+        assert!(matches!(
+            sm.write(B256::from(0x10ff0), B256::from(4)),
+            Err(Error::AccessOutsideRegion { .. })
+        ));
+        assert_eq!(sm.trace().len(), 16);
     }
 
     #[test]
-    fn test_stack_machine() {
-        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+    fn test_reserved_null_page() {
+        let layout = MemoryLayout::new(&[MemoryRegion::new(
+            RegionKind::Heap,
+            B256::from(0x1000),
+            B256::from(0x1000),
+        )])
+        .unwrap()
+        .reserve(B256::zero()..=B256::from(0xfff))
+        .unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(ConfigArgs {
+            layout: Some(layout),
+            ..DefaultConfig::default_config()
+        });
+        let reserved = |address: u64| Error::ReservedAddress {
+            addr: B256::from(address).fixed_be_bytes(),
+        };
 
-        assert_eq!(sm.stack_allocated.low(), B256::zero());
+        // First and last cell of the reserved range, read or written
+        assert_eq!(sm.read(B256::zero()).unwrap_err(), reserved(0));
+        assert_eq!(
+            sm.write(B256::zero(), B256::from(1)).unwrap_err(),
+            reserved(0)
+        );
+        assert_eq!(sm.read(B256::from(0xfe0)).unwrap_err(), reserved(0xfe0));
+        assert_eq!(
+            sm.write(B256::from(0xfe0), B256::from(1)).unwrap_err(),
+            reserved(0xfe0)
+        );
+        // An unaligned access that only ends in the range is rejected as well
+        assert_eq!(
+            sm.write(B256::from(0xff0), B256::from(1)).unwrap_err(),
+            reserved(0xfe0)
+        );
+        assert_eq!(sm.trace().len(), 0);
+
+        // The cell right after the range is in the heap
+        sm.write(B256::from(0x1000), B256::from(2)).unwrap();
+        assert!(sm.read(B256::from(0x1000)).is_ok());
+        assert_eq!(sm.trace().len(), 2);
+    }
+
+    #[test]
+    fn test_machine_from_builder() {
+        use crate::config::ConfigBuilder;
+        let config = ConfigBuilder::<B256, 32>::new()
+            .stack_depth(2)
+            .memory_size(1 << 10)
+            .alignment(AlignmentPolicy::Strict)
+            .build()
+            .unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(config);
         let base = sm.base_address();
-        let program = vec![
-            Instruction::Push(B256::from(1000)),
-            Instruction::Push(B256::from(170)),
-            Instruction::Swap(sm.r0),
-            Instruction::Pop(),
-            Instruction::Swap(sm.r1),
-            Instruction::Pop(),
-            Instruction::Mov(sm.r2, sm.r0),
-            Instruction::Save(base + B256::from(128), sm.r0),
-            Instruction::Save(base + B256::from(160), sm.r1),
-            Instruction::Save(base + B256::from(192), sm.r2),
-        ];
-        // Execute program1
-        for instruction in program {
-            sm.exec(&instruction);
+        assert!(matches!(
+            sm.write(base + B256::from(1), B256::from(1)),
+            Err(Error::UnalignedAccess { .. })
+        ));
+        sm.push(B256::from(1)).unwrap();
+        sm.push(B256::from(2)).unwrap();
+        assert_eq!(sm.push(B256::from(3)).unwrap_err(), Error::StackOverflow);
+    }
+
+    #[test]
+    fn test_512_bit_machine() {
+        use crate::base::B512;
+        let mut sm = StateMachine::<B512, B512, 64, 64>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let wide = B512::from_halves(B256::MAX, B256::from(7));
+        sm.write(base, wide).unwrap();
+        sm.write(base + B512::from(64), B512::from(1)).unwrap();
+        assert!(matches!(
+            sm.read(base).unwrap(),
+            CellInteraction::SingleCell(_, _, value) if value == wide
+        ));
+        sm.push(wide).unwrap();
+        assert!(matches!(
+            sm.pop().unwrap().1,
+            CellInteraction::SingleCell(_, _, value) if value == wide
+        ));
+
+        // A misaligned write spans two cells
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&[0xaa; 32]);
+        sm.write(base + B512::from(32), B512::from(bytes)).unwrap();
+        let first: [u8; 64] = sm.dummy_read(base).into();
+        assert_eq!(first[32..], [0xaa; 32]);
+        assert_eq!(first[..32], [0xff; 32]);
+
+        let trace = sm.trace();
+        assert_eq!(trace.len(), 7);
+        assert_eq!(trace[2].value, wide);
+        assert_eq!(crate::machine::validate::validate_trace(&trace), Ok(()));
+    }
+
+    #[test]
+    fn test_region_permissions() {
+        let code = B256::from(0x10000);
+        let io = B256::from(0x50000);
+        let layout = MemoryLayout::new(&[
+            MemoryRegion::new(RegionKind::Code, code, B256::from(0x1000))
+                .with_permission(Permission::Read),
+            MemoryRegion::new(RegionKind::Heap, B256::from(0x30000), B256::from(0x10000)),
+            MemoryRegion::new(RegionKind::Io, io, B256::from(0x100))
+                .with_permission(Permission::Write),
+        ])
+        .unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(ConfigArgs {
+            layout: Some(layout),
+            ..DefaultConfig::default_config()
+        });
+
+        // The program image is installed before the execution
+        let image = [B256::from(0x60), B256::from(0x80), B256::from(0x40)];
+        assert_eq!(sm.init_region(code, &image).unwrap().len(), 3);
+        sm.write_unchecked_init(code + B256::from(96), B256::from(0x52))
+            .unwrap();
+        for (offset, value) in image.iter().enumerate() {
+            assert!(matches!(
+                sm.read(code + B256::from(offset) * B256::WORD_SIZE).unwrap(),
+                CellInteraction::SingleCell(_, _, read) if read == *value
+            ));
         }
 
-        assert_eq!(sm.dummy_read(base + B256::from(128)), B256::from(170));
-        assert_eq!(sm.dummy_read(base + B256::from(160)), B256::from(1000));
-        assert_eq!(sm.dummy_read(base + B256::from(192)), B256::from(170));
+        // The code region is read only
+        assert_eq!(
+            sm.write(code, B256::from(1)).unwrap_err(),
+            Error::PermissionDenied {
+                addr: code.fixed_be_bytes(),
+                region: RegionKind::Code,
+                access: Permission::Write,
+            }
+        );
+        assert_eq!(sm.dummy_read(code), B256::from(0x60));
+
+        // The io region is write only
+        sm.write(io, B256::from(2)).unwrap();
+        assert!(matches!(
+            sm.read(io),
+            Err(Error::PermissionDenied {
+                region: RegionKind::Io,
+                access: Permission::Read,
+                ..
+            })
+        ));
+
+        // The initialization path still checks the bounds of the regions
+        assert!(matches!(
+            sm.write_unchecked_init(B256::from(0x11000), B256::from(3)),
+            Err(Error::AccessOutsideRegion { .. })
+        ));
+        assert_eq!(sm.trace().len(), 8);
     }
 
     #[test]
-    #[should_panic]
-    fn test_invalid_instruction() {
-        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
-        let program = vec![Instruction::Invalid(PhantomData)];
+    fn test_write_once_region() {
+        let heap = B256::from(0x30000);
+        let layout = MemoryLayout::new(&[
+            MemoryRegion::new(RegionKind::Stack, B256::from(0x20000), B256::from(0x1000)),
+            MemoryRegion::new(RegionKind::Heap, heap, B256::from(0x1000)).with_write_once(true),
+        ])
+        .unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(ConfigArgs {
+            layout: Some(layout),
+            ..DefaultConfig::default_config()
+        });
+        assert_eq!(
+            sm.write_once_regions(),
+            vec![
+                MemoryRegion::new(RegionKind::Heap, heap, B256::from(0x1000)).with_write_once(true)
+            ]
+        );
 
-        for instruction in program {
-            sm.exec(&instruction);
+        // Reads are allowed before and after the single write of a cell
+        sm.read(heap).unwrap();
+        sm.write(heap, B256::from(1)).unwrap();
+        sm.read(heap).unwrap();
+        sm.write(heap + B256::WORD_SIZE, B256::from(2)).unwrap();
+        sm.read(heap).unwrap();
+
+        let first_write_time = sm.trace()[1].time_log;
+        assert_eq!(
+            sm.write(heap, B256::from(3)).unwrap_err(),
+            Error::WriteOnceViolation {
+                addr: heap.fixed_be_bytes(),
+                first_write_time,
+            }
+        );
+        assert_eq!(sm.dummy_read(heap), B256::from(1));
+
+        // The other regions are rewritten freely
+        let stack = B256::from(0x20000);
+        sm.write(stack, B256::from(4)).unwrap();
+        sm.write(stack, B256::from(5)).unwrap();
+        assert_eq!(sm.dummy_read(stack), B256::from(5));
+        assert_eq!(
+            sm.first_writes()
+                .unwrap()
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+            [heap, heap + B256::WORD_SIZE]
+        );
+
+        // A restored write can be made again
+        let snapshot = sm.snapshot();
+        sm.write(heap + B256::from(64), B256::from(6)).unwrap();
+        sm.restore(&snapshot).unwrap();
+        sm.write(heap + B256::from(64), B256::from(7)).unwrap();
+
+        // The first writes are kept when the trace is not
+        for mode in [TraceMode::CommitmentsOnly, TraceMode::Disabled] {
+            let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(
+                ConfigBuilder::new()
+                    .layout(layout)
+                    .trace_mode(mode)
+                    .build()
+                    .unwrap(),
+            );
+            sm.write(heap, B256::from(1)).unwrap();
+            assert_eq!(
+                sm.write(heap, B256::from(2)).unwrap_err(),
+                Error::WriteOnceViolation {
+                    addr: heap.fixed_be_bytes(),
+                    first_write_time: 0,
+                }
+            );
         }
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(ConfigArgs {
+            layout: Some(layout),
+            ..DefaultConfig::default_config()
+        });
+        sm.set_trace_sink(Box::new(HashingSink::new()));
+        sm.write(heap, B256::from(1)).unwrap();
+        assert!(matches!(
+            sm.write(heap, B256::from(2)),
+            Err(Error::WriteOnceViolation { .. })
+        ));
     }
 }
@@ -0,0 +1,137 @@
+//! Storage backends of the memory cells of a machine.
+//! A context picks its backend with [crate::machine::AbstractContext::Memory]. Every backend
+//! only keeps the written cells and the cells that were never written are read as zero, so
+//! the trace of an access sequence does not depend on the backend.
+extern crate alloc;
+use alloc::{boxed::Box, collections::BTreeMap};
+use core::fmt::Debug;
+use rbtree::RBTree;
+
+/// Map from the written cells to their values
+pub trait MemoryBackend<K, V>: Debug {
+    /// Get the value of a written cell, [None] if the cell was never written
+    fn get(&self, address: &K) -> Option<&V>;
+
+    /// Set the value of a cell, replacing the value it had
+    fn replace_or_insert(&mut self, address: K, value: V);
+
+    /// Forget every written cell
+    fn clear(&mut self);
+
+    /// Get the number of written cells
+    fn len(&self) -> usize;
+
+    /// Check if no cell was written
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the written cells in address order
+    fn iter(&self) -> Box<dyn Iterator<Item = (&'_ K, &'_ V)> + '_>;
+}
+
+impl<K: Ord + Debug, V: Debug> MemoryBackend<K, V> for RBTree<K, V> {
+    fn get(&self, address: &K) -> Option<&V> {
+        RBTree::get(self, address)
+    }
+
+    fn replace_or_insert(&mut self, address: K, value: V) {
+        RBTree::replace_or_insert(self, address, value);
+    }
+
+    fn clear(&mut self) {
+        RBTree::clear(self)
+    }
+
+    fn len(&self) -> usize {
+        RBTree::len(self)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&'_ K, &'_ V)> + '_> {
+        Box::new(RBTree::iter(self))
+    }
+}
+
+/// Memory backed by a [BTreeMap], for machines whose written cells are scattered over a huge
+/// address space
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseMemory<K, V> {
+    cells: BTreeMap<K, V>,
+}
+
+impl<K, V> SparseMemory<K, V> {
+    /// Create an empty memory
+    pub fn new() -> Self {
+        Self {
+            cells: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K, V> Default for SparseMemory<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Debug, V: Debug> MemoryBackend<K, V> for SparseMemory<K, V> {
+    fn get(&self, address: &K) -> Option<&V> {
+        self.cells.get(address)
+    }
+
+    fn replace_or_insert(&mut self, address: K, value: V) {
+        self.cells.insert(address, value);
+    }
+
+    fn clear(&mut self) {
+        self.cells.clear()
+    }
+
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&'_ K, &'_ V)> + '_> {
+        Box::new(self.cells.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryBackend, SparseMemory};
+    use crate::base::B256;
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use rbtree::RBTree;
+
+    // Same writes on a backend, return the cells in address order
+    fn fill<B: MemoryBackend<B256, B256>>(memory: &mut B) -> Vec<(B256, B256)> {
+        for (address, value) in [(64, 1), (0, 2), (64, 3), (32, 4)] {
+            memory.replace_or_insert(B256::from(address), B256::from(value));
+        }
+        memory
+            .iter()
+            .map(|(address, value)| (*address, *value))
+            .collect()
+    }
+
+    #[test]
+    fn test_backends_agree() {
+        let mut dense = RBTree::new();
+        let mut sparse = SparseMemory::new();
+        let cells = fill(&mut dense);
+        assert_eq!(fill(&mut sparse), cells);
+        assert_eq!(
+            cells,
+            [(0, 2), (32, 4), (64, 3)]
+                .map(|(address, value)| (B256::from(address), B256::from(value)))
+        );
+        assert_eq!(sparse.len(), 3);
+        assert_eq!(sparse.get(&B256::from(96)), None);
+        assert_eq!(sparse.get(&B256::from(64)), Some(&B256::from(3)));
+
+        sparse.clear();
+        assert!(sparse.is_empty());
+        assert_eq!(sparse, SparseMemory::default());
+    }
+}
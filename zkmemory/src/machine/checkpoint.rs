@@ -0,0 +1,430 @@
+//! Checkpoint of the state of a machine, used to resume an interrupted proving session.
+//!
+//! | Field              | Size                | Description                                    |
+//! |--------------------|---------------------|------------------------------------------------|
+//! | magic              | 8                   | [CHECKPOINT_MAGIC]                             |
+//! | version            | 1                   | Must be equal to [CHECKPOINT_VERSION]          |
+//! | reserved           | 1                   | Zero                                           |
+//! | address size       | 1                   | Size of the address word in bytes              |
+//! | value size         | 1                   | Size of the value word in bytes                |
+//! | config fingerprint | 32                  | Fingerprint of the config of the machine       |
+//! | time log           | 8                   | Little endian                                  |
+//! | stack depth        | 8                   | Little endian                                  |
+//! | stack pointer      | address size        | Big endian                                     |
+//! | bytes written      | 8                   | Little endian                                  |
+//! | gas used           | 8                   | Little endian                                  |
+//! | memory end         | address size        | Big endian last address of the memory section  |
+//! | initial commitment | 1 or 33             | 0, or 1 followed by the Merkle root            |
+//! | cell count         | 8                   | Little endian                                  |
+//! | cells              | count * (S + T)     | Big endian address and value, in address order |
+//! | trace size         | 8                   | Little endian size of the trace in bytes       |
+//! | trace              | trace size          | [crate::machine::trace_codec] encoding         |
+//! | digest             | 32                  | BLAKE2b of every field above                   |
+//!
+//! A checkpoint that does not match its digest is rejected as corrupted and a checkpoint of a
+//! machine with another config, see [AbstractMemoryMachine::config_fingerprint], is rejected
+//! unless the resume is forced with [FingerprintCheck::Force].
+//! The observer and the trace sink of the machine are not part of the checkpoint.
+extern crate alloc;
+use crate::{
+    base::Base,
+    commitment::merkle::MerkleRoot,
+    config::{section_size, FingerprintCheck},
+    error::Error,
+    machine::{
+        backend::MemoryBackend,
+        note_first_write, require_full_trace,
+        trace_codec::{decode_trace, encode_trace},
+        AbstractContext, AbstractMachine, AbstractMemoryMachine, AbstractTraceRecord, TraceRecord,
+    },
+};
+use alloc::vec::Vec;
+use blake2b_simd::Params;
+
+/// Magic bytes at the start of every checkpoint
+pub const CHECKPOINT_MAGIC: [u8; 8] = *b"ZKMCKPT\0";
+
+/// Version of the format, a checkpoint of any other version is rejected
+pub const CHECKPOINT_VERSION: u8 = 4;
+
+// Size of the header in bytes
+const HEADER_SIZE: usize = 12;
+
+// Size of the digest in bytes
+const DIGEST_SIZE: usize = 32;
+
+// Personalization of the digest
+const PERSONAL: &[u8] = b"zkmemory-ckpt";
+
+// Get the digest of the checkpoint fields
+fn digest(bytes: &[u8]) -> [u8; DIGEST_SIZE] {
+    let mut digest = [0u8; DIGEST_SIZE];
+    digest.copy_from_slice(
+        Params::new()
+            .hash_length(DIGEST_SIZE)
+            .personal(PERSONAL)
+            .hash(bytes)
+            .as_bytes(),
+    );
+    digest
+}
+
+/// Encode the state of the machine, see the module documentation for the layout
+pub fn encode_checkpoint<M, K, V, const S: usize, const T: usize>(machine: &M) -> Vec<u8>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    let context = machine.ro_context();
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&CHECKPOINT_MAGIC);
+    bytes.extend_from_slice(&[CHECKPOINT_VERSION, 0, S as u8, T as u8]);
+    bytes.extend_from_slice(&machine.config_fingerprint());
+    bytes.extend_from_slice(&context.time_log().to_le_bytes());
+    bytes.extend_from_slice(&context.stack_depth().to_le_bytes());
+    let stack_ptr: [u8; S] = context.stack_ptr().into();
+    bytes.extend_from_slice(&stack_ptr);
+    bytes.extend_from_slice(&machine.bytes_written().to_le_bytes());
+    bytes.extend_from_slice(&machine.gas_used().to_le_bytes());
+    let memory_end: [u8; S] = machine.get_memory_address().1.into();
+    bytes.extend_from_slice(&memory_end);
+    match machine.initial_commitment() {
+        Some(root) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&root);
+        }
+        None => bytes.push(0),
+    }
+    let memory = context.ro_memory();
+    bytes.extend_from_slice(&(memory.len() as u64).to_le_bytes());
+    for (address, value) in memory.iter() {
+        let address: [u8; S] = (*address).into();
+        let value: [u8; T] = (*value).into();
+        bytes.extend_from_slice(&address);
+        bytes.extend_from_slice(&value);
+    }
+    let trace: Vec<TraceRecord<K, V, S, T>> = machine
+        .trace_iter()
+        .map(|record| {
+            TraceRecord::new(
+                record.time_log(),
+                record.stack_depth(),
+                record.instruction(),
+                record.address(),
+                record.value(),
+            )
+            .with_source(record.source())
+            .with_cell_size(record.cell_size())
+        })
+        .collect();
+    let trace = encode_trace(&trace, machine.endianness(), machine.config_fingerprint());
+    bytes.extend_from_slice(&(trace.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&trace);
+    let digest = digest(&bytes);
+    bytes.extend_from_slice(&digest);
+    bytes
+}
+
+// Reader of the fields of a checkpoint, running out of bytes is a corrupted checkpoint
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(Error::InvalidCheckpoint);
+        }
+        let (field, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(field)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        self.array().map(u64::from_le_bytes)
+    }
+
+    fn len(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.u64()?).map_err(|_| Error::InvalidCheckpoint)
+    }
+}
+
+/// Install the state of a checkpoint on a fresh machine built with the same configuration.
+/// Return [Error::MachineAlreadyStarted] if the trace of the machine is not empty,
+/// [Error::InvalidCheckpoint] if the checkpoint is corrupted or its memory section cannot
+/// grow from the one of the machine,
+/// [Error::UnsupportedCheckpointVersion] and [Error::TraceWordSizeMismatch] if it was
+/// written by another version or for other word sizes and [Error::ConfigMismatch] if the check
+/// rejects its config fingerprint. Return [Error::TraceNotRetained] if the machine does not
+/// keep its trace, the resumed machine could not continue the trace of the checkpoint
+pub fn resume_checkpoint<M, K, V, const S: usize, const T: usize>(
+    machine: &mut M,
+    bytes: &[u8],
+    check: FingerprintCheck,
+) -> Result<(), Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    if machine.record_count() != 0 {
+        return Err(Error::MachineAlreadyStarted);
+    }
+    require_full_trace(machine)?;
+    if bytes.len() < HEADER_SIZE + DIGEST_SIZE || bytes[..8] != CHECKPOINT_MAGIC {
+        return Err(Error::InvalidCheckpoint);
+    }
+    if bytes[8] != CHECKPOINT_VERSION {
+        return Err(Error::UnsupportedCheckpointVersion);
+    }
+    if bytes[10] as usize != S || bytes[11] as usize != T {
+        return Err(Error::TraceWordSizeMismatch);
+    }
+    let (body, expected) = bytes.split_at(bytes.len() - DIGEST_SIZE);
+    if digest(body) != expected {
+        return Err(Error::InvalidCheckpoint);
+    }
+
+    let mut fields = Fields(&body[HEADER_SIZE..]);
+    check.check(fields.array()?)?;
+    let time_log = fields.u64()?;
+    let stack_depth = fields.u64()?;
+    let stack_ptr = K::from(fields.array()?);
+    let bytes_written = fields.u64()?;
+    let gas_used = fields.u64()?;
+    let memory_end = K::from(fields.array()?);
+    let initial_commitment: Option<MerkleRoot> = match fields.take(1)?[0] {
+        0 => None,
+        1 => Some(fields.array()?),
+        _ => return Err(Error::InvalidCheckpoint),
+    };
+    let count = fields.len()?;
+    let mut cells = Vec::new();
+    for _ in 0..count {
+        cells.push((K::from(fields.array()?), V::from(fields.array()?)));
+    }
+    let trace_size = fields.len()?;
+    // The fingerprint of the trace is the one of the checkpoint
+    let trace = decode_trace::<K, V, S, T>(fields.take(trace_size)?, FingerprintCheck::Force)
+        .map_err(|_| Error::InvalidCheckpoint)?;
+    if !fields.0.is_empty() {
+        return Err(Error::InvalidCheckpoint);
+    }
+
+    // The memory section of the checkpoint grew from the one of the fresh machine
+    let (low, high) = machine.get_memory_address();
+    if memory_end != high {
+        if !machine.growable()
+            || memory_end < high
+            || section_size(low, memory_end) > machine.max_memory_size()
+        {
+            return Err(Error::InvalidCheckpoint);
+        }
+        machine.set_memory_end(memory_end);
+    }
+    if let Some(root) = initial_commitment {
+        machine.set_initial_commitment(root)?;
+    }
+    let context = machine.context();
+    let memory = context.memory();
+    memory.clear();
+    for (address, value) in cells {
+        memory.replace_or_insert(address, value);
+    }
+    context.set_stack_ptr(stack_ptr);
+    context.set_stack_depth(stack_depth);
+    context.set_time_log(time_log);
+    machine.add_bytes_written(bytes_written);
+    machine.set_gas_used(gas_used);
+    if let Some(counter) = machine.record_counter_mut() {
+        *counter = trace.len() as u64;
+    }
+    for record in trace {
+        note_first_write(machine, &record);
+        let (time_log, stack_depth, instruction, address, value) = record.get_tuple();
+        machine.track(
+            M::TraceRecord::new(time_log, stack_depth, instruction, address, value)
+                .with_cell_size(record.cell_size()),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_checkpoint, resume_checkpoint, CHECKPOINT_VERSION};
+    use crate::{
+        base::{B256, B64},
+        config::{ConfigBuilder, DefaultConfig, FingerprintCheck},
+        error::Error,
+        machine::{
+            tests::{Instruction, StateMachine},
+            AbstractMachine, AbstractMemoryMachine, AbstractStackMachine,
+        },
+    };
+    extern crate alloc;
+    use alloc::{vec, vec::Vec};
+
+    fn program(base: B256) -> Vec<Instruction> {
+        vec![
+            Instruction::Write(base, B256::from(1)),
+            Instruction::Push(B256::from(2)),
+            Instruction::Write(base + B256::from(48), B256::from(3)),
+            Instruction::Read(base + B256::from(16)),
+            Instruction::Pop(),
+            Instruction::Write(base, B256::from(4)),
+        ]
+    }
+
+    #[test]
+    fn test_resume_continues_the_trace() {
+        let mut uninterrupted =
+            StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let program = program(uninterrupted.base_address());
+        for instruction in program.iter() {
+            uninterrupted.exec(instruction).unwrap();
+        }
+
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        for instruction in program[..3].iter() {
+            sm.exec(instruction).unwrap();
+        }
+        let mut file = Vec::new();
+        sm.checkpoint_to(&mut file).unwrap();
+        drop(sm);
+
+        let mut resumed = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config())
+            .resume_from(file.as_slice())
+            .unwrap();
+        for instruction in program[3..].iter() {
+            resumed.exec(instruction).unwrap();
+        }
+        assert_eq!(resumed.trace(), uninterrupted.trace());
+        assert_eq!(resumed.snapshot(), uninterrupted.snapshot());
+        assert_eq!(
+            encode_checkpoint(&resumed),
+            encode_checkpoint(&uninterrupted)
+        );
+    }
+
+    #[test]
+    fn test_resume_keeps_the_grown_memory() {
+        let config = ConfigBuilder::<B256, 32>::new()
+            .memory_size(0x100)
+            .growable(true)
+            .max_memory_size(0x180)
+            .build()
+            .unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(config);
+        let base = sm.base_address();
+        let high = sm.grow(2).unwrap();
+        let above = base + B256::from(0x100);
+        sm.exec(&Instruction::Write(above, B256::from(1))).unwrap();
+        let file = encode_checkpoint(&sm);
+
+        // The resumed machine accesses the cells above the memory section of its config
+        let mut resumed = StateMachine::<B256, B256, 32, 32>::from_config(config)
+            .resume_from(file.as_slice())
+            .unwrap();
+        assert_eq!(resumed.get_memory_address(), (base, high));
+        resumed.exec(&Instruction::Read(above)).unwrap();
+        sm.exec(&Instruction::Read(above)).unwrap();
+        assert_eq!(encode_checkpoint(&resumed), encode_checkpoint(&sm));
+
+        // A machine that cannot grow to the memory section of the checkpoint rejects it
+        let fixed = ConfigBuilder::<B256, 32>::new()
+            .memory_size(0x100)
+            .build()
+            .unwrap();
+        assert_eq!(
+            resume_checkpoint(
+                &mut StateMachine::<B256, B256, 32, 32>::from_config(fixed),
+                &file,
+                FingerprintCheck::Force
+            ),
+            Err(Error::InvalidCheckpoint)
+        );
+    }
+
+    #[test]
+    fn test_invalid_checkpoints() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+        sm.push(B256::from(2)).unwrap();
+        let file = encode_checkpoint(&sm);
+        let fresh = || StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        assert!(fresh().resume_from(file.as_slice()).is_ok());
+
+        // Every flipped byte is detected
+        for index in [0, 20, file.len() / 2, file.len() - 1] {
+            let mut corrupted = file.clone();
+            corrupted[index] ^= 1;
+            assert!(fresh().resume_from(corrupted.as_slice()).is_err());
+        }
+        let mut corrupted = file.clone();
+        corrupted[20] ^= 1;
+        assert_eq!(
+            fresh().resume_from(corrupted.as_slice()).unwrap_err(),
+            Error::InvalidCheckpoint
+        );
+        assert_eq!(
+            fresh().resume_from(&file[..file.len() - 1]).unwrap_err(),
+            Error::InvalidCheckpoint
+        );
+        assert_eq!(
+            fresh().resume_from(&file[..4]).unwrap_err(),
+            Error::InvalidCheckpoint
+        );
+
+        let mut newer = file.clone();
+        newer[8] = CHECKPOINT_VERSION + 1;
+        assert_eq!(
+            fresh().resume_from(newer.as_slice()).unwrap_err(),
+            Error::UnsupportedCheckpointVersion
+        );
+        assert_eq!(
+            StateMachine::<B64, B64, 8, 8>::new(DefaultConfig::default_config())
+                .resume_from(file.as_slice())
+                .unwrap_err(),
+            Error::TraceWordSizeMismatch
+        );
+        assert_eq!(
+            sm.resume_from(file.as_slice()).unwrap_err(),
+            Error::MachineAlreadyStarted
+        );
+    }
+
+    #[test]
+    fn test_config_mismatch() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+        sm.push(B256::from(2)).unwrap();
+        let file = encode_checkpoint(&sm);
+        let other = || {
+            StateMachine::<B256, B256, 32, 32>::from_config(
+                ConfigBuilder::new().stack_depth(16).build().unwrap(),
+            )
+        };
+        assert_ne!(other().config_fingerprint(), sm.config_fingerprint());
+        assert_eq!(
+            other().resume_from(file.as_slice()).unwrap_err(),
+            Error::ConfigMismatch {
+                expected: other().config_fingerprint(),
+                found: sm.config_fingerprint()
+            }
+        );
+
+        // Migration tooling can still force the resume
+        let mut forced = other();
+        resume_checkpoint(&mut forced, &file, FingerprintCheck::Force).unwrap();
+        assert_eq!(forced.trace(), sm.trace());
+        assert_eq!(forced.snapshot().cells(), sm.snapshot().cells());
+    }
+}
@@ -0,0 +1,184 @@
+//! Splitting of the execution trace into chunks that are proved separately.
+//! The state of the memory at a boundary is the value of every cell accessed before it, as
+//! left by its last access: a write stores its value and a read returns the value the cell
+//! holds, so the state is right even for a machine started from an initial image.
+//! A chunk circuit takes the entry state of its chunk as committed initial values and the
+//! exit state of a chunk is the entry state of the next one.
+extern crate alloc;
+use crate::{
+    base::Base,
+    machine::{AbstractTraceRecord, TraceRecord},
+};
+use alloc::{collections::BTreeMap, vec::Vec};
+
+/// Part of a trace with the state of the memory at its boundaries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceChunk<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Records of the chunk in time order
+    pub records: Vec<TraceRecord<K, V, S, T>>,
+    /// Value of every cell accessed before the first record, in address order
+    pub entry_state: Vec<(K, V)>,
+    /// Value of every cell accessed up to the last record, in address order
+    pub exit_state: Vec<(K, V)>,
+}
+
+// Apply the records to the memory state, a record leaves its value in its cell
+fn apply<K, V, const S: usize, const T: usize>(
+    state: &mut BTreeMap<K, V>,
+    records: &[TraceRecord<K, V, S, T>],
+) where
+    K: Base<S>,
+    V: Base<T>,
+{
+    for record in records {
+        state.insert(record.address(), record.value());
+    }
+}
+
+// Get the cells of the memory state in address order
+fn cells<K: Copy, V: Copy>(state: &BTreeMap<K, V>) -> Vec<(K, V)> {
+    state
+        .iter()
+        .map(|(address, value)| (*address, *value))
+        .collect()
+}
+
+/// Extract the records of a trace in time order with a time log in `start_time..end_time`,
+/// with the state of the memory at `start_time` and at `end_time`
+pub fn trace_window<K, V, const S: usize, const T: usize>(
+    trace: &[TraceRecord<K, V, S, T>],
+    start_time: u64,
+    end_time: u64,
+) -> TraceChunk<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let start = trace.partition_point(|record| record.time_log() < start_time);
+    let end = trace
+        .partition_point(|record| record.time_log() < end_time)
+        .max(start);
+    let mut state = BTreeMap::new();
+    apply(&mut state, &trace[..start]);
+    let entry_state = cells(&state);
+    apply(&mut state, &trace[start..end]);
+    TraceChunk {
+        records: trace[start..end].to_vec(),
+        entry_state,
+        exit_state: cells(&state),
+    }
+}
+
+/// Split a trace in time order into chunks of `chunk_len` records, the last chunk may be
+/// shorter. The exit state of every chunk is the entry state of the next one.
+/// A chunk length of zero is taken as one
+pub fn split_trace_into_chunks<K, V, const S: usize, const T: usize>(
+    trace: &[TraceRecord<K, V, S, T>],
+    chunk_len: usize,
+) -> Vec<TraceChunk<K, V, S, T>>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let mut state = BTreeMap::new();
+    trace
+        .chunks(chunk_len.max(1))
+        .map(|records| {
+            let entry_state = cells(&state);
+            apply(&mut state, records);
+            TraceChunk {
+                records: records.to_vec(),
+                entry_state,
+                exit_state: cells(&state),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_trace_into_chunks, trace_window};
+    use crate::{
+        base::{Base, B256},
+        config::DefaultConfig,
+        machine::{
+            replay::replay_and_check, tests::StateMachine, AbstractMachine, AbstractMemoryMachine,
+            AbstractStackMachine, AbstractTraceRecord, MemorySnapshot,
+        },
+    };
+    extern crate alloc;
+    use alloc::vec::Vec;
+    use rand::Rng;
+
+    // Drop the cells holding zero, the replay and the snapshots do not keep the cells that
+    // were only read
+    fn non_zero(cells: &[(B256, B256)]) -> Vec<(B256, B256)> {
+        cells
+            .iter()
+            .copied()
+            .filter(|(_, value)| !value.is_zero())
+            .collect()
+    }
+
+    #[test]
+    fn test_state_continuity() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let mut rng = rand::thread_rng();
+        let base = sm.base_address();
+        for _ in 0..100 {
+            let address = base + B256::from(rng.gen_range(0..256u64));
+            match rng.gen_range(0..3) {
+                0 => {
+                    sm.read(address).unwrap();
+                }
+                1 => {
+                    sm.push(B256::from(rng.gen::<u64>())).unwrap();
+                }
+                _ => {
+                    sm.write(address, B256::from(rng.gen::<u64>())).unwrap();
+                }
+            }
+        }
+        let trace = sm.trace();
+        let chunk_len = trace.len().div_ceil(3);
+        let chunks = split_trace_into_chunks(&trace, chunk_len);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[0].entry_state.is_empty());
+        assert_eq!(
+            non_zero(&chunks[2].exit_state),
+            non_zero(sm.snapshot().cells())
+        );
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            if let Some(next) = chunks.get(index + 1) {
+                assert_eq!(chunk.exit_state, next.entry_state);
+            }
+            // Every chunk replays from its entry state to its exit state
+            let start_time = chunk.records[0].time_log();
+            let entry = MemorySnapshot {
+                cells: chunk.entry_state.clone(),
+                stack_ptr: B256::zero(),
+                stack_depth: 0,
+                time_log: start_time,
+                gas_used: 0,
+                config_fingerprint: [0u8; 32],
+            };
+            let exit = replay_and_check(&entry, &chunk.records).unwrap();
+            assert_eq!(non_zero(exit.cells()), non_zero(&chunk.exit_state));
+
+            // The chunk is the window of its time logs
+            let end_time = start_time + chunk.records.len() as u64;
+            assert_eq!(&trace_window(&trace, start_time, end_time), chunk);
+        }
+
+        // An empty window keeps the state of its start
+        let empty = trace_window(&trace, 10, 5);
+        assert!(empty.records.is_empty());
+        assert_eq!(empty.entry_state, empty.exit_state);
+        assert!(split_trace_into_chunks::<B256, B256, 32, 32>(&[], 4).is_empty());
+    }
+}
@@ -0,0 +1,183 @@
+//! Hexdump of the memory contents over an address range, used to debug failed constraints.
+//! Every line shows the address of its first byte, 16 bytes in the byte order of the machine
+//! and their ASCII gutter.
+//! The cells that were never written are shown as `..` and left blank in the gutter.
+extern crate alloc;
+use crate::{
+    base::Base,
+    config::Endianness,
+    machine::{backend::MemoryBackend, cells_in_memory, AbstractMachine, AbstractMemoryMachine},
+};
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+// Number of bytes on a line of the dump
+const BYTES_PER_LINE: usize = 16;
+
+/// Cells of a range of the memory, see [MemoryDump::new]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryDump<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    start: K,
+    cells: Vec<(K, Option<V>)>,
+    endianness: Endianness,
+}
+
+impl<K, V, const S: usize, const T: usize> MemoryDump<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Dump `len_cells` consecutive cells starting at the address, which should be aligned
+    /// to the word size. The range stops at the end of the memory section
+    pub fn new<M>(machine: &M, start: K, len_cells: usize) -> Self
+    where
+        M: AbstractMemoryMachine<K, V, S, T>,
+    {
+        let memory = machine.ro_context().ro_memory();
+        Self {
+            start,
+            cells: cells_in_memory(machine, start, len_cells)
+                .into_iter()
+                .map(|cell| (cell, memory.get(&cell).copied()))
+                .collect(),
+            endianness: machine.endianness(),
+        }
+    }
+
+    /// Get the dumped cells in address order, [None] for the cells that were never written
+    pub fn cells(&self) -> &[(K, Option<V>)] {
+        &self.cells
+    }
+
+    // Get the bytes of the dump in address order, [None] for the bytes of unwritten cells
+    fn bytes(&self) -> Vec<Option<u8>> {
+        let mut bytes = Vec::with_capacity(self.cells.len() * T);
+        for (_, value) in self.cells.iter() {
+            match value {
+                Some(value) => {
+                    let buf: [u8; T] = self.endianness.to_bytes(*value);
+                    bytes.extend(buf.iter().copied().map(Some));
+                }
+                None => bytes.extend(core::iter::repeat(None).take(T)),
+            }
+        }
+        bytes
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> Display for MemoryDump<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        // The fixed width encoding keeps at most 32 bytes of the address
+        let address_size = S.min(32);
+        for (index, line) in self.bytes().chunks(BYTES_PER_LINE).enumerate() {
+            let address = self.start + K::from(index * BYTES_PER_LINE);
+            for byte in &address.fixed_be_bytes()[32 - address_size..] {
+                write!(f, "{:02x}", byte)?;
+            }
+            write!(f, ":")?;
+            for byte in line {
+                match byte {
+                    Some(byte) => write!(f, " {:02x}", byte)?,
+                    None => write!(f, " ..")?,
+                }
+            }
+            // Align the gutter of a short last line
+            for _ in line.len()..BYTES_PER_LINE {
+                write!(f, "   ")?;
+            }
+            write!(f, "  |")?;
+            for byte in line {
+                let shown = match byte {
+                    Some(byte) if byte.is_ascii_graphic() || *byte == b' ' => *byte as char,
+                    Some(_) => '.',
+                    None => ' ',
+                };
+                write!(f, "{}", shown)?;
+            }
+            writeln!(f, "|")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryDump;
+    use crate::{
+        base::{Base, B256, B32},
+        config::DefaultConfig,
+        machine::{tests::StateMachine, AbstractMachine, AbstractMemoryMachine},
+    };
+    extern crate alloc;
+    use alloc::{format, vec};
+
+    #[test]
+    fn test_dump_mapped_and_unmapped_cells() {
+        let mut sm = StateMachine::<B32, B32, 4, 4>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        sm.write_bytes(base + B32::from(4), b"ABCD").unwrap();
+        sm.write_bytes(base + B32::from(12), &[0, 0, 0, 1]).unwrap();
+        sm.write_bytes(base + B32::from(20), b"zk!?").unwrap();
+        let trace_len = sm.trace_len();
+
+        assert_eq!(
+            sm.dump(base, 4),
+            vec![
+                (base + B32::from(4), B32::from(*b"ABCD")),
+                (base + B32::from(12), B32::from([0, 0, 0, 1])),
+            ]
+        );
+
+        let dump = MemoryDump::new(&sm, base, 6);
+        assert_eq!(dump.cells().len(), 6);
+        assert_eq!(dump.cells()[0], (base, None));
+        let address = |offset: u64| {
+            let bytes = (base + B32::from(offset)).fixed_be_bytes();
+            hex::encode(&bytes[28..])
+        };
+        assert_eq!(
+            format!("{}", dump),
+            format!(
+                "{}: .. .. .. .. 41 42 43 44 .. .. .. .. 00 00 00 01  |    ABCD    ....|\n\
+                 {}: .. .. .. .. 7a 6b 21 3f                          |    zk!?|\n",
+                address(0),
+                address(16)
+            )
+        );
+        // Dumping does not record any trace
+        assert_eq!(sm.trace_len(), trace_len);
+    }
+
+    #[test]
+    fn test_dump_past_the_end_of_memory() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let (_, high) = sm.get_memory_address();
+        let last = high - B256::from(31);
+        let before_last = last - B256::from(32);
+        sm.write(before_last, B256::from(7)).unwrap();
+
+        assert_eq!(sm.dump(before_last, 10), vec![(before_last, B256::from(7))]);
+        let dump = MemoryDump::new(&sm, before_last, 10);
+        assert_eq!(
+            dump.cells(),
+            [(before_last, Some(B256::from(7))), (last, None)]
+        );
+        // A cell crossing the end of the memory is not dumped
+        assert!(sm.dump(last + B256::from(1), 2).is_empty());
+        assert!(MemoryDump::new(&sm, last + B256::from(1), 2)
+            .cells()
+            .is_empty());
+        assert!(MemoryDump::new(&sm, B256::MAX, usize::MAX)
+            .cells()
+            .is_empty());
+        assert_eq!(format!("{}", MemoryDump::new(&sm, last, 0)), "");
+    }
+}
@@ -0,0 +1,250 @@
+//! Speculative execution on a copy of a machine.
+//! A [Fork] is a clone of its parent taken at a fork point, its trace is the trace of the
+//! parent up to the fork point followed by the records of the branch. Both machines continue
+//! the time log from the fork point. When the branch is merged, its records are rebased after
+//! the records made by the parent since the fork point and replayed on the parent.
+extern crate alloc;
+use crate::{
+    base::Base,
+    error::Error,
+    machine::{
+        backend::MemoryBackend, emit, require_full_trace, AbstractContext, AbstractMemoryMachine,
+        AbstractTraceRecord, MemorySnapshot, TraceRecord,
+    },
+};
+use alloc::collections::BTreeSet;
+
+/// Branch of a machine forked by [AbstractMemoryMachine::fork]
+#[derive(Debug)]
+pub struct Fork<M, K, V> {
+    machine: M,
+    point: MemorySnapshot<K, V>,
+}
+
+// Cells written and cells accessed by one side since the fork point
+struct Footprint<K> {
+    written: BTreeSet<K>,
+    touched: BTreeSet<K>,
+}
+
+impl<K: Ord> Footprint<K> {
+    // Get a cell that is written by one side and accessed by the other one
+    fn conflict<'a>(&'a self, other: &'a Self) -> Option<&'a K> {
+        self.written
+            .intersection(&other.touched)
+            .chain(other.written.intersection(&self.touched))
+            .next()
+    }
+}
+
+// Collect the cells accessed by the machine since the time log
+fn footprint<M, K, V, const S: usize, const T: usize>(machine: &M, time_log: u64) -> Footprint<K>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    let mut footprint = Footprint {
+        written: BTreeSet::new(),
+        touched: BTreeSet::new(),
+    };
+    for record in machine
+        .trace_iter()
+        .filter(|record| record.time_log() >= time_log)
+    {
+        if record.instruction().is_write() {
+            footprint.written.insert(record.address());
+        }
+        footprint.touched.insert(record.address());
+    }
+    footprint
+}
+
+impl<M, K: Copy, V> Fork<M, K, V> {
+    pub(crate) fn new(machine: M, point: MemorySnapshot<K, V>) -> Self {
+        Self { machine, point }
+    }
+
+    /// Get the forked machine
+    pub fn machine(&self) -> &M {
+        &self.machine
+    }
+
+    /// Get the forked machine to run the branch
+    pub fn machine_mut(&mut self) -> &mut M {
+        &mut self.machine
+    }
+
+    /// Get the state of the parent at the fork point
+    pub fn point(&self) -> &MemorySnapshot<K, V> {
+        &self.point
+    }
+
+    /// Drop the branch, the parent is left untouched
+    pub fn discard(self) {}
+
+    /// Append the records of the branch to the trace of the parent and apply its writes.
+    /// Return [Error::ForkConflict] and leave the parent untouched if a cell written by one
+    /// side since the fork point was accessed by the other one, or if both sides moved the
+    /// stack, and [Error::OutOfGas] if the gas used by the branch exceeds the budget of the
+    /// parent. The parent must be the machine the branch was forked from, its observer is not
+    /// notified of the merged accesses. The merged records go to the trace sink of the parent
+    /// if it has one, an error of the sink stops the merge after the records already pushed.
+    /// The merge replays the trace of the branch, it fails with [Error::TraceNotRetained]
+    /// if the branch does not keep its trace
+    pub fn merge_into_parent<const S: usize, const T: usize>(
+        self,
+        parent: &mut M,
+    ) -> Result<(), Error>
+    where
+        K: Base<S>,
+        V: Base<T>,
+        M: AbstractMemoryMachine<K, V, S, T>,
+    {
+        require_full_trace(&self.machine)?;
+        let start = self.point.time_log;
+        let branch = self.machine.ro_context();
+        let stack_moved = |stack_ptr: K, stack_depth: u64| {
+            stack_ptr != self.point.stack_ptr || stack_depth != self.point.stack_depth
+        };
+        let branch_moved = stack_moved(branch.stack_ptr(), branch.stack_depth());
+        if branch_moved
+            && stack_moved(
+                parent.ro_context().stack_ptr(),
+                parent.ro_context().stack_depth(),
+            )
+        {
+            return Err(Error::ForkConflict {
+                addr: self.point.stack_ptr.fixed_be_bytes(),
+            });
+        }
+        let conflict = footprint(&self.machine, start)
+            .conflict(&footprint(parent, start))
+            .copied();
+        if let Some(addr) = conflict {
+            return Err(Error::ForkConflict {
+                addr: addr.fixed_be_bytes(),
+            });
+        }
+        if parent.remaining_time_budget() < branch.time_log() - start {
+            return Err(Error::TimeLogExhausted);
+        }
+        let used = parent.gas_used();
+        let cost = self.machine.gas_used().saturating_sub(self.point.gas_used);
+        match parent.gas_budget() {
+            Some(budget) if used.saturating_add(cost) > budget => {
+                return Err(Error::OutOfGas { budget, used, cost })
+            }
+            _ => {}
+        }
+        let offset = parent.ro_context().time_log() - start;
+        for record in self
+            .machine
+            .trace_iter()
+            .filter(|record| record.time_log() >= start)
+        {
+            emit(
+                parent,
+                TraceRecord::new(
+                    record.time_log() + offset,
+                    record.stack_depth(),
+                    record.instruction(),
+                    record.address(),
+                    record.value(),
+                )
+                .with_source(record.source())
+                .with_cell_size(record.cell_size()),
+            )?;
+            if record.instruction().is_write() {
+                parent
+                    .context()
+                    .memory()
+                    .replace_or_insert(record.address(), record.value());
+            }
+        }
+        let time_log = branch.time_log() + offset;
+        parent.context().set_time_log(time_log);
+        parent.set_gas_used(used.saturating_add(cost));
+        if branch_moved {
+            parent.context().set_stack_ptr(branch.stack_ptr());
+            parent.context().set_stack_depth(branch.stack_depth());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        base::{Base, B256},
+        config::DefaultConfig,
+        error::Error,
+        machine::{
+            tests::StateMachine, validate::validate_trace, AbstractMachine, AbstractMemoryMachine,
+            AbstractStackMachine,
+        },
+    };
+
+    #[test]
+    fn test_merge_non_conflicting_branch() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let cell = |i: u64| base + B256::from(i * 32);
+        sm.write(cell(0), B256::from(1)).unwrap();
+        sm.write(cell(1), B256::from(2)).unwrap();
+
+        let mut taken = sm.fork();
+        let mut skipped = sm.fork();
+        assert_eq!(taken.point().time_log(), 2);
+
+        // Both sides continue the time log from the fork point
+        sm.write(cell(2), B256::from(3)).unwrap();
+        sm.read(cell(0)).unwrap();
+        let branch = taken.machine_mut();
+        branch.write(cell(3), B256::from(4)).unwrap();
+        branch.read(cell(1)).unwrap();
+        branch.push(B256::from(5)).unwrap();
+        assert_eq!(branch.trace()[2].time_log, 2);
+        skipped.machine_mut().write(cell(2), B256::from(6)).unwrap();
+        skipped.discard();
+
+        taken.merge_into_parent(&mut sm).unwrap();
+        assert_eq!(sm.trace().len(), 7);
+        assert_eq!(validate_trace(&sm.trace()), Ok(()));
+        assert_eq!(sm.dummy_read(cell(2)), B256::from(3));
+        assert_eq!(sm.dummy_read(cell(3)), B256::from(4));
+        // The stack moved on the branch only
+        assert_eq!(sm.pop().unwrap().0, 0);
+        assert_eq!(validate_trace(&sm.trace()), Ok(()));
+    }
+
+    #[test]
+    fn test_merge_conflicting_branch() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+
+        // The branch reads a cell the parent wrote after the fork point
+        let mut fork = sm.fork();
+        fork.machine_mut().read(base).unwrap();
+        sm.write(base, B256::from(2)).unwrap();
+        assert_eq!(
+            fork.merge_into_parent(&mut sm),
+            Err(Error::ForkConflict {
+                addr: base.fixed_be_bytes(),
+            })
+        );
+        assert_eq!(sm.trace().len(), 2);
+        assert_eq!(sm.dummy_read(base), B256::from(2));
+
+        // Both sides moved the stack
+        let mut fork = sm.fork();
+        fork.machine_mut().push(B256::from(3)).unwrap();
+        sm.push(B256::from(4)).unwrap();
+        assert!(matches!(
+            fork.merge_into_parent(&mut sm),
+            Err(Error::ForkConflict { .. })
+        ));
+        assert_eq!(sm.trace().len(), 3);
+    }
+}
@@ -0,0 +1,384 @@
+//! JSON Lines encoding of the execution trace for debugging.
+//! Every record is written on its own line as a flat object of hexadecimal strings:
+//! `{"time_log":"0x2","stack_depth":"0x0","instruction":"0x1","address":"0x20","value":"0xff","source":"0x0","cell_size":"0x0"}`.
+//! The source tag was added by the second version of the format, a line without it is read
+//! as a record of the source 0. The cell size was added by the third version, a line without
+//! it is read as an access to a cell of the full word.
+//! Records are streamed to the writer, the whole dump is never kept in memory.
+extern crate alloc;
+use crate::{
+    base::Base,
+    error::{write_word, Error},
+    machine::{AbstractTraceRecord, MemoryInstruction, TraceRecord},
+};
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+use std::io::{self, BufRead, Write};
+
+// Field names in the order they are written
+const FIELDS: [&str; 7] = [
+    "time_log",
+    "stack_depth",
+    "instruction",
+    "address",
+    "value",
+    "source",
+    "cell_size",
+];
+
+// Hexadecimal representation of a big endian word of any width
+struct Hex<const N: usize>([u8; N]);
+
+impl<const N: usize> Display for Hex<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write_word(f, &self.0)
+    }
+}
+
+/// Write the trace records to the writer, one JSON object per line
+pub fn write_trace_jsonl<'a, R, K, V, W, const S: usize, const T: usize>(
+    trace: impl IntoIterator<Item = &'a R>,
+    mut writer: W,
+) -> io::Result<()>
+where
+    R: AbstractTraceRecord<K, V> + 'a,
+    K: Base<S>,
+    V: Base<T>,
+    W: Write,
+{
+    for record in trace {
+        let address: [u8; S] = record.address().into();
+        let value: [u8; T] = record.value().into();
+        writeln!(
+            writer,
+            r#"{{"time_log":"{:#x}","stack_depth":"{:#x}","instruction":"{:#x}","address":"{}","value":"{}","source":"{:#x}","cell_size":"{:#x}"}}"#,
+            record.time_log(),
+            record.stack_depth(),
+            record.instruction().code(),
+            Hex(address),
+            Hex(value),
+            record.source(),
+            record.cell_size(),
+        )?;
+    }
+    writer.flush()
+}
+
+/// Read a trace written by [crate::machine::AbstractMemoryMachine::trace_export_jsonl].
+/// Blank lines are skipped, the time log must be strictly increasing from one record to the next.
+/// Return [Error::InvalidTraceLine] for a malformed line, including a line that is not UTF-8,
+/// and [Error::TraceIo] if the reader fails
+pub fn trace_import_jsonl<K, V, R, const S: usize, const T: usize>(
+    reader: R,
+) -> Result<Vec<TraceRecord<K, V, S, T>>, Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+    R: BufRead,
+{
+    let mut trace: Vec<TraceRecord<K, V, S, T>> = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line_number = index + 1;
+        let invalid = Error::InvalidTraceLine { line: line_number };
+        let line = line.map_err(|error| match error.kind() {
+            io::ErrorKind::InvalidData => invalid,
+            _ => Error::TraceIo,
+        })?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = parse_record(&line).ok_or(invalid)?;
+        if let Some(previous) = trace.last() {
+            if record.time_log() <= previous.time_log() {
+                return Err(Error::NonMonotoneTimeLog { line: line_number });
+            }
+        }
+        trace.push(record);
+    }
+    Ok(trace)
+}
+
+// Parse a single line, return [None] if any field is missing, duplicated, unknown or malformed
+fn parse_record<K, V, const S: usize, const T: usize>(line: &str) -> Option<TraceRecord<K, V, S, T>>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let body = line.trim().strip_prefix('{')?.strip_suffix('}')?;
+    let mut values: [Option<&str>; 7] = [None; 7];
+    for entry in body.split(',') {
+        let (key, value) = entry.split_once(':')?;
+        let position = FIELDS
+            .iter()
+            .position(|field| Some(*field) == unquote(key))?;
+        if values[position].replace(unquote(value)?).is_some() {
+            return None;
+        }
+    }
+    let [time_log, stack_depth, instruction, address, value, source, cell_size] = values;
+    let source = match source {
+        Some(source) => u8::try_from(parse_hex(source)?).ok()?,
+        None => 0,
+    };
+    let cell_size = match cell_size {
+        Some(cell_size) => u8::try_from(parse_hex(cell_size)?)
+            .ok()
+            .filter(|cell_size| (*cell_size as usize) < T)?,
+        None => 0,
+    };
+    Some(
+        TraceRecord::new(
+            parse_hex(time_log?)?,
+            parse_hex(stack_depth?)?,
+            MemoryInstruction::from_code(parse_hex(instruction?)?)?,
+            parse_word::<K, S>(address?)?,
+            parse_word::<V, T>(value?)?,
+        )
+        .with_source(source)
+        .with_cell_size(cell_size),
+    )
+}
+
+// Strip the surrounding quotes of a JSON string
+fn unquote(input: &str) -> Option<&str> {
+    input.trim().strip_prefix('"')?.strip_suffix('"')
+}
+
+// Split a `0x` prefixed string into its hexadecimal digits
+fn hex_digits(input: &str) -> Option<&str> {
+    let digits = input.strip_prefix("0x")?;
+    if digits.is_empty() || !digits.chars().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(digits)
+}
+
+fn parse_hex(input: &str) -> Option<u64> {
+    u64::from_str_radix(hex_digits(input)?, 16).ok()
+}
+
+fn parse_word<B: Base<S>, const S: usize>(input: &str) -> Option<B> {
+    hex_digits(input)?;
+    B::parse_size(input).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{trace_import_jsonl, write_trace_jsonl};
+    use crate::{
+        base::{Base, B256, B512, B64},
+        config::DefaultConfig,
+        error::Error,
+        machine::{
+            tests::StateMachine, AbstractMachine, AbstractMemoryMachine, AbstractStackMachine,
+            AbstractTraceRecord, MemoryInstruction, TraceRecord,
+        },
+    };
+    extern crate alloc;
+    use alloc::{format, string::String, vec::Vec};
+    use rand::Rng;
+
+    type Record = TraceRecord<B256, B256, 32, 32>;
+
+    fn import(input: &str) -> Result<Vec<Record>, Error> {
+        trace_import_jsonl::<B256, B256, _, 32, 32>(input.as_bytes())
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let mut rng = rand::thread_rng();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let (memory_lo, _) = sm.get_memory_address();
+        for _ in 0..10_000 {
+            let address = memory_lo + B256::from(rng.gen_range(0..4096u64));
+            let value = B256::from(rng.gen::<[u8; 32]>());
+            match rng.gen_range(0..4) {
+                0 => {
+                    sm.write(address, value).unwrap();
+                }
+                1 => {
+                    sm.read(address).unwrap();
+                }
+                2 if sm.get_stack_depth() < sm.max_stack_depth() => {
+                    sm.push(value).unwrap();
+                }
+                _ if sm.get_stack_depth() > 0 => {
+                    sm.pop().unwrap();
+                }
+                _ => {
+                    sm.read(address).unwrap();
+                }
+            }
+        }
+        let trace = sm.trace();
+
+        let mut output = Vec::new();
+        sm.trace_export_jsonl(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), trace.len());
+        assert_eq!(import(&output).unwrap(), trace);
+    }
+
+    #[test]
+    fn test_jsonl_format() {
+        let trace = [TraceRecord::<B64, B64, 8, 8>::new(
+            2,
+            1,
+            MemoryInstruction::Push,
+            B64::from(0x20),
+            B64::MAX,
+        )];
+        let mut output = Vec::new();
+        write_trace_jsonl::<_, B64, B64, _, 8, 8>(trace.iter(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"time_log\":\"0x2\",\"stack_depth\":\"0x1\",\"instruction\":\"0x2\",\"address\":\"0x20\",\"value\":\"0xffffffffffffffff\",\"source\":\"0x0\",\"cell_size\":\"0x0\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_jsonl_wide_words() {
+        // Words wider than 256 bits are written in full
+        let trace = [TraceRecord::<B512, B512, 64, 64>::new(
+            1,
+            0,
+            MemoryInstruction::Write,
+            B512::MAX - B512::from(1),
+            B512::MAX,
+        )];
+        let mut output = Vec::new();
+        write_trace_jsonl::<_, B512, B512, _, 64, 64>(trace.iter(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains(&format!(r#""value":"0x{}""#, "f".repeat(128))));
+        assert_eq!(
+            trace_import_jsonl::<B512, B512, _, 64, 64>(output.as_bytes()).unwrap(),
+            trace
+        );
+    }
+
+    #[test]
+    fn test_jsonl_source_tags() {
+        let trace: Vec<TraceRecord<B64, B64, 8, 8>> = (0..4u64)
+            .map(|i| {
+                TraceRecord::new(
+                    i,
+                    0,
+                    MemoryInstruction::Write,
+                    B64::from(i * 8),
+                    B64::from(i),
+                )
+                .with_source(i as u8 * 0x55)
+                .with_cell_size(i as u8 * 2)
+            })
+            .collect();
+        let mut output = Vec::new();
+        write_trace_jsonl::<_, B64, B64, _, 8, 8>(trace.iter(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output
+            .lines()
+            .last()
+            .unwrap()
+            .ends_with(r#""source":"0xff","cell_size":"0x6"}"#));
+        let imported = trace_import_jsonl::<B64, B64, _, 8, 8>(output.as_bytes()).unwrap();
+        assert_eq!(imported, trace);
+        assert_eq!(
+            imported
+                .iter()
+                .map(|record| record.source())
+                .collect::<Vec<_>>(),
+            [0, 0x55, 0xaa, 0xff]
+        );
+
+        // A source tag wider than a byte is rejected
+        let wide = r#"{"time_log":"0x0","stack_depth":"0x0","instruction":"0x1","address":"0x20","value":"0x1","source":"0x100"}"#;
+        assert_eq!(import(wide), Err(Error::InvalidTraceLine { line: 1 }));
+        // A cell is narrower than the value word
+        let wide = r#"{"time_log":"0x0","stack_depth":"0x0","instruction":"0x1","address":"0x20","value":"0x1","cell_size":"0x20"}"#;
+        assert_eq!(import(wide), Err(Error::InvalidTraceLine { line: 1 }));
+    }
+
+    #[test]
+    fn test_jsonl_malformed_line() {
+        let valid = r#"{"time_log":"0x0","stack_depth":"0x0","instruction":"0x1","address":"0x20","value":"0x1"}"#;
+        // Fields may come in any order and blank lines are ignored
+        let reordered = r#" { "value" : "0x2", "address":"0x40","instruction":"0x0","stack_depth":"0x0","time_log":"0x1" } "#;
+        let trace = import(&format!("{}\n\n{}\n", valid, reordered)).unwrap();
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[1].get_tuple().4, B256::from(2));
+        // Lines of the first version of the format have no source tag nor cell size
+        assert_eq!(trace[0].source(), 0);
+        assert_eq!(trace[0].cell_size(), 0);
+
+        for malformed in [
+            "not json",
+            r#"{"time_log":"0x1","stack_depth":"0x0","instruction":"0x1","address":"0x20"}"#,
+            r#"{"time_log":"0x1","stack_depth":"0x0","instruction":"0x9","address":"0x20","value":"0x1"}"#,
+            r#"{"time_log":"1","stack_depth":"0x0","instruction":"0x1","address":"0x20","value":"0x1"}"#,
+            r#"{"time_log":"0x1","stack_depth":"0x0","instruction":"0x1","address":"0xzz","value":"0x1"}"#,
+            r#"{"time_log":"0x1","time_log":"0x2","instruction":"0x1","address":"0x20","value":"0x1"}"#,
+        ] {
+            assert_eq!(
+                import(&format!("{}\n{}\n", valid, malformed)),
+                Err(Error::InvalidTraceLine { line: 2 })
+            );
+        }
+
+        // A value wider than the word size is rejected
+        let wide = format!(
+            r#"{{"time_log":"0x1","stack_depth":"0x0","instruction":"0x1","address":"0x20","value":"0x1{}"}}"#,
+            "0".repeat(16)
+        );
+        assert_eq!(
+            trace_import_jsonl::<B256, B64, _, 32, 8>(format!("{}\n{}", valid, wide).as_bytes()),
+            Err(Error::InvalidTraceLine { line: 2 })
+        );
+    }
+
+    // Reader whose input fails after the given bytes
+    struct FailingReader<'a>(&'a [u8]);
+
+    impl std::io::Read for FailingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "disconnected",
+                ));
+            }
+            std::io::Read::read(&mut self.0, buf)
+        }
+    }
+
+    #[test]
+    fn test_jsonl_io_errors() {
+        let line = r#"{"time_log":"0x0","stack_depth":"0x0","instruction":"0x1","address":"0x20","value":"0x1"}"#;
+        let input = format!("{}\n{}", line, line);
+        let reader = std::io::BufReader::new(FailingReader(input.as_bytes()));
+        assert_eq!(
+            trace_import_jsonl::<B256, B256, _, 32, 32>(reader),
+            Err(Error::TraceIo)
+        );
+
+        // A line that is not UTF-8 is malformed, not a failure of the input
+        let mut input = format!("{}\n", line).into_bytes();
+        input.extend_from_slice(&[0xff, 0xfe, b'\n']);
+        assert_eq!(
+            trace_import_jsonl::<B256, B256, _, 32, 32>(input.as_slice()),
+            Err(Error::InvalidTraceLine { line: 2 })
+        );
+    }
+
+    #[test]
+    fn test_jsonl_time_log_must_increase() {
+        let line = |time_log: u64| {
+            format!(
+                r#"{{"time_log":"{:#x}","stack_depth":"0x0","instruction":"0x0","address":"0x20","value":"0x0"}}"#,
+                time_log
+            )
+        };
+        let input = [line(0), line(1), line(5), line(5)].join("\n");
+        assert_eq!(import(&input), Err(Error::NonMonotoneTimeLog { line: 4 }));
+        let input = [line(3), line(2)].join("\n");
+        assert_eq!(import(&input), Err(Error::NonMonotoneTimeLog { line: 2 }));
+    }
+}
@@ -0,0 +1,334 @@
+//! Loading of guest programs into the memory of a machine.
+//! The images are written with the initialization path of
+//! [crate::machine::AbstractMemoryMachine::write_unchecked_init], so they can be loaded into
+//! read only regions, but every segment must still fit in a region of the memory layout.
+//! Only little endian ELF images are supported, ELF32 needs addresses of at least 4 bytes
+//! and ELF64 addresses of at least 8 bytes.
+use crate::{
+    base::Base,
+    error::Error,
+    machine::{write_chunks, AbstractMemoryMachine},
+};
+
+// Identification bytes at the start of every ELF image
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+// Values of the class byte of the identification
+const ELF_CLASS_32: u8 = 1;
+const ELF_CLASS_64: u8 = 2;
+
+// Value of the data encoding byte of the identification for little endian images
+const ELF_DATA_LSB: u8 = 1;
+
+// Type of the program headers of loadable segments
+const PT_LOAD: u64 = 1;
+
+// Offsets and sizes of the fields read from the headers, they depend on the class
+struct ElfLayout {
+    header_size: usize,
+    word: usize,
+    entry: usize,
+    phoff: usize,
+    phentsize: usize,
+    phnum: usize,
+    phdr_size: usize,
+    p_offset: usize,
+    p_vaddr: usize,
+    p_filesz: usize,
+    p_memsz: usize,
+}
+
+const ELF32_LAYOUT: ElfLayout = ElfLayout {
+    header_size: 52,
+    word: 4,
+    entry: 24,
+    phoff: 28,
+    phentsize: 42,
+    phnum: 44,
+    phdr_size: 32,
+    p_offset: 4,
+    p_vaddr: 8,
+    p_filesz: 16,
+    p_memsz: 20,
+};
+
+const ELF64_LAYOUT: ElfLayout = ElfLayout {
+    header_size: 64,
+    word: 8,
+    entry: 24,
+    phoff: 32,
+    phentsize: 54,
+    phnum: 56,
+    phdr_size: 56,
+    p_offset: 8,
+    p_vaddr: 16,
+    p_filesz: 32,
+    p_memsz: 40,
+};
+
+/// Write a raw image to consecutive cells starting at the base address,
+/// return the number of cells written
+pub fn load_raw_image<M, K, V, const S: usize, const T: usize>(
+    machine: &mut M,
+    base: K,
+    image: &[u8],
+) -> Result<usize, Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    write_chunks(machine, base, image, None)
+}
+
+/// Write the loadable segments of an ELF image at their virtual addresses and return the
+/// entry point. The part of a segment after its file size is not written, it reads as zero
+/// on a fresh machine. The tail of the last cell of every segment is zero-padded
+pub fn load_elf<M, K, V, const S: usize, const T: usize>(
+    machine: &mut M,
+    elf: &[u8],
+) -> Result<K, Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMemoryMachine<K, V, S, T>,
+{
+    if elf.len() < 6 || elf[..4] != ELF_MAGIC {
+        return Err(Error::InvalidElf);
+    }
+    let layout = match elf[4] {
+        ELF_CLASS_32 if S >= 4 => &ELF32_LAYOUT,
+        ELF_CLASS_64 if S >= 8 => &ELF64_LAYOUT,
+        class => return Err(Error::UnsupportedElfClass { class }),
+    };
+    if elf[5] != ELF_DATA_LSB {
+        return Err(Error::UnsupportedElfEndianness { data: elf[5] });
+    }
+    if elf.len() < layout.header_size {
+        return Err(Error::InvalidElf);
+    }
+    let entry = read_field(elf, layout.entry, layout.word)?;
+    let phoff = to_usize(read_field(elf, layout.phoff, layout.word)?)?;
+    let phentsize = to_usize(read_field(elf, layout.phentsize, 2)?)?;
+    let phnum = to_usize(read_field(elf, layout.phnum, 2)?)?;
+    if phnum > 0 && phentsize < layout.phdr_size {
+        return Err(Error::InvalidElf);
+    }
+    for index in 0..phnum {
+        let header = index
+            .checked_mul(phentsize)
+            .and_then(|offset| offset.checked_add(phoff))
+            .ok_or(Error::InvalidElf)?;
+        if read_field(elf, header, 4)? != PT_LOAD {
+            continue;
+        }
+        let field = |offset: usize| header.checked_add(offset).ok_or(Error::InvalidElf);
+        let offset = to_usize(read_field(elf, field(layout.p_offset)?, layout.word)?)?;
+        let vaddr = read_field(elf, field(layout.p_vaddr)?, layout.word)?;
+        let filesz = read_field(elf, field(layout.p_filesz)?, layout.word)?;
+        if read_field(elf, field(layout.p_memsz)?, layout.word)? < filesz {
+            return Err(Error::InvalidElf);
+        }
+        let filesz = to_usize(filesz)?;
+        let data = offset
+            .checked_add(filesz)
+            .and_then(|end| elf.get(offset..end))
+            .ok_or(Error::InvalidElf)?;
+        write_chunks(machine, K::from(vaddr), data, None)?;
+    }
+    Ok(K::from(entry))
+}
+
+// Read a little endian field of `size` bytes
+fn read_field(elf: &[u8], offset: usize, size: usize) -> Result<u64, Error> {
+    let bytes = offset
+        .checked_add(size)
+        .and_then(|end| elf.get(offset..end))
+        .ok_or(Error::InvalidElf)?;
+    let mut buf = [0u8; 8];
+    buf[..size].copy_from_slice(bytes);
+    Ok(u64::from_le_bytes(buf))
+}
+
+// Convert an offset or a size of the image to usize
+fn to_usize(value: u64) -> Result<usize, Error> {
+    usize::try_from(value).map_err(|_| Error::InvalidElf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_elf, load_raw_image};
+    use crate::{
+        base::{B256, B32},
+        config::{ConfigArgs, DefaultConfig, MemoryLayout, MemoryRegion, Permission, RegionKind},
+        error::Error,
+        machine::{tests::StateMachine, AbstractMachine, AbstractMemoryMachine},
+    };
+    extern crate alloc;
+    use alloc::{vec, vec::Vec};
+
+    const CODE: u64 = 0x10000;
+    const DATA: u64 = 0x30000;
+    const ENTRY: u64 = 0x10040;
+
+    // Program headers of the fixture: type, file offset, virtual address, file and memory size
+    const SEGMENTS: [(u32, u64, u64, u64, u64); 3] = [
+        (1, 0x100, CODE, 0x45, 0x45),
+        // PT_NOTE, not loaded
+        (4, 0x100, 0x20000, 0x10, 0x10),
+        (1, 0x180, DATA, 0x08, 0x40),
+    ];
+
+    // Hand-crafted little endian ELF64 executable, the code segment is 0x45 bytes of 0..0x45
+    // at 0x100 and the data segment is 8 bytes of 0xd0..0xd8 at 0x180
+    fn elf64_fixture() -> Vec<u8> {
+        let mut elf = vec![0u8; 0x188];
+        elf[..16].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        elf[16..18].copy_from_slice(&2u16.to_le_bytes());
+        elf[18..20].copy_from_slice(&0xf3u16.to_le_bytes());
+        elf[20..24].copy_from_slice(&1u32.to_le_bytes());
+        elf[24..32].copy_from_slice(&ENTRY.to_le_bytes());
+        elf[32..40].copy_from_slice(&64u64.to_le_bytes());
+        elf[52..54].copy_from_slice(&64u16.to_le_bytes());
+        elf[54..56].copy_from_slice(&56u16.to_le_bytes());
+        elf[56..58].copy_from_slice(&(SEGMENTS.len() as u16).to_le_bytes());
+        for (index, (kind, offset, vaddr, filesz, memsz)) in SEGMENTS.iter().enumerate() {
+            let header = 64 + index * 56;
+            elf[header..header + 4].copy_from_slice(&kind.to_le_bytes());
+            elf[header + 8..header + 16].copy_from_slice(&offset.to_le_bytes());
+            elf[header + 16..header + 24].copy_from_slice(&vaddr.to_le_bytes());
+            elf[header + 24..header + 32].copy_from_slice(&vaddr.to_le_bytes());
+            elf[header + 32..header + 40].copy_from_slice(&filesz.to_le_bytes());
+            elf[header + 40..header + 48].copy_from_slice(&memsz.to_le_bytes());
+        }
+        for (index, byte) in elf[0x100..0x145].iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        for (index, byte) in elf[0x180..0x188].iter_mut().enumerate() {
+            *byte = 0xd0 + index as u8;
+        }
+        elf
+    }
+
+    // Same program as a little endian ELF32 executable
+    fn elf32_fixture() -> Vec<u8> {
+        let elf64 = elf64_fixture();
+        let mut elf = vec![0u8; 0x188];
+        elf[..16].copy_from_slice(&elf64[..16]);
+        elf[4] = 1;
+        elf[24..28].copy_from_slice(&(ENTRY as u32).to_le_bytes());
+        elf[28..32].copy_from_slice(&52u32.to_le_bytes());
+        elf[42..44].copy_from_slice(&32u16.to_le_bytes());
+        elf[44..46].copy_from_slice(&(SEGMENTS.len() as u16).to_le_bytes());
+        for (index, (kind, offset, vaddr, filesz, memsz)) in SEGMENTS.iter().enumerate() {
+            let header = 52 + index * 32;
+            let fields = [*kind as u64, *offset, *vaddr, *vaddr, *filesz, *memsz];
+            for (field, value) in fields.iter().enumerate() {
+                let start = header + field * 4;
+                elf[start..start + 4].copy_from_slice(&(*value as u32).to_le_bytes());
+            }
+        }
+        elf[0x100..].copy_from_slice(&elf64[0x100..]);
+        elf
+    }
+
+    fn machine() -> StateMachine<B256, B256, 32, 32> {
+        let layout = MemoryLayout::new(&[
+            MemoryRegion::new(RegionKind::Code, B256::from(CODE), B256::from(0x1000))
+                .with_permission(Permission::Read),
+            MemoryRegion::new(RegionKind::Heap, B256::from(DATA), B256::from(0x1000)),
+        ])
+        .unwrap();
+        StateMachine::new(ConfigArgs {
+            layout: Some(layout),
+            ..DefaultConfig::default_config()
+        })
+    }
+
+    fn check_image(sm: &StateMachine<B256, B256, 32, 32>) {
+        let code: Vec<u8> = (0..0x45u8).collect();
+        let mut padded = code.clone();
+        padded.resize(0x60, 0);
+        assert_eq!(sm.read_slice(B256::from(CODE), 0x60).unwrap(), padded);
+        let mut data: Vec<u8> = (0xd0..0xd8u8).collect();
+        data.resize(0x40, 0);
+        assert_eq!(sm.read_slice(B256::from(DATA), 0x40).unwrap(), data);
+        assert_eq!(sm.snapshot().cells().len(), 4);
+        assert_eq!(sm.trace().len(), 4);
+    }
+
+    #[test]
+    fn test_load_elf() {
+        let mut sm = machine();
+        assert_eq!(load_elf(&mut sm, &elf64_fixture()), Ok(B256::from(ENTRY)));
+        check_image(&sm);
+        // The code region stays read only for the program
+        assert!(matches!(
+            sm.write(B256::from(CODE), B256::from(1)),
+            Err(Error::PermissionDenied { .. })
+        ));
+
+        let mut sm = machine();
+        assert_eq!(load_elf(&mut sm, &elf32_fixture()), Ok(B256::from(ENTRY)));
+        check_image(&sm);
+    }
+
+    #[test]
+    fn test_load_raw_image() {
+        let mut sm = machine();
+        let image: Vec<u8> = (0..0x45u8).collect();
+        assert_eq!(load_raw_image(&mut sm, B256::from(CODE), &image), Ok(3));
+        assert_eq!(sm.read_slice(B256::from(CODE), 0x45).unwrap(), image);
+        // The image must fit in a region
+        assert!(matches!(
+            load_raw_image(&mut sm, B256::from(CODE + 0xfe0), &image),
+            Err(Error::AccessOutsideRegion { .. })
+        ));
+        assert_eq!(sm.trace().len(), 3);
+    }
+
+    #[test]
+    fn test_rejected_elf_images() {
+        let elf = elf64_fixture();
+        let load = |elf: &[u8]| load_elf(&mut machine(), elf);
+
+        let mut big_endian = elf.clone();
+        big_endian[5] = 2;
+        assert_eq!(
+            load(&big_endian),
+            Err(Error::UnsupportedElfEndianness { data: 2 })
+        );
+
+        let mut unknown_class = elf.clone();
+        unknown_class[4] = 3;
+        assert_eq!(
+            load(&unknown_class),
+            Err(Error::UnsupportedElfClass { class: 3 })
+        );
+        // 64 bits addresses do not fit in a 32 bits machine
+        let mut sm = StateMachine::<B32, B32, 4, 4>::new(DefaultConfig::default_config());
+        assert_eq!(
+            load_elf(&mut sm, &elf),
+            Err(Error::UnsupportedElfClass { class: 2 })
+        );
+
+        let mut wrong_magic = elf.clone();
+        wrong_magic[1] = b'e';
+        assert_eq!(load(&wrong_magic), Err(Error::InvalidElf));
+        for length in [0, 5, 63, 100, 0x187] {
+            assert_eq!(load(&elf[..length]), Err(Error::InvalidElf));
+        }
+        let mut short_header = elf.clone();
+        short_header[54] = 32;
+        assert_eq!(load(&short_header), Err(Error::InvalidElf));
+        let mut short_memory = elf.clone();
+        short_memory[64 + 40] = 0x44;
+        assert_eq!(load(&short_memory), Err(Error::InvalidElf));
+        // A program header table at the end of the address space does not wrap around
+        for phoff in [u64::MAX - 3, u64::MAX - 56] {
+            let mut wrapping = elf.clone();
+            wrapping[32..40].copy_from_slice(&phoff.to_le_bytes());
+            assert_eq!(load(&wrapping), Err(Error::InvalidElf));
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Merging of the traces of independent memory spaces into a single trace.
+//! Every memory space is identified by an [AddressTag] stored in the most significant byte
+//! of its addresses, so the merged address spaces are disjoint. The traces are concatenated
+//! in the given order and their time logs are rebased to keep the merged trace strictly
+//! increasing, the merged trace can be proven by a single circuit.
+extern crate alloc;
+use crate::{
+    base::Base,
+    error::Error,
+    machine::{is_sorted_by_time, AbstractTraceRecord, TraceRecord},
+};
+use alloc::{collections::BTreeSet, vec::Vec};
+
+/// Tag of a memory space, stored in the most significant byte of its addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AddressTag(pub u8);
+
+impl AddressTag {
+    /// Move the address into the memory space of the tag,
+    /// return [Error::AddressTagOverflow] if its most significant byte is already used
+    pub fn apply<K: Base<S>, const S: usize>(&self, address: K) -> Result<K, Error> {
+        let mut bytes: [u8; S] = address.into();
+        if bytes[0] != 0 {
+            return Err(Error::AddressTagOverflow {
+                addr: address.fixed_be_bytes(),
+            });
+        }
+        bytes[0] = self.0;
+        Ok(K::from(bytes))
+    }
+}
+
+/// Merge the traces of independent memory spaces, every trace must be sorted by time log.
+/// The records of a trace keep their order and the gaps between their time logs, the first
+/// record of a trace comes right after the last record of the previous one
+pub fn merge_traces<K, V, const S: usize, const T: usize>(
+    traces: Vec<(AddressTag, Vec<TraceRecord<K, V, S, T>>)>,
+) -> Result<Vec<TraceRecord<K, V, S, T>>, Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let mut tags = BTreeSet::new();
+    if let Some((tag, _)) = traces.iter().find(|(tag, _)| !tags.insert(*tag)) {
+        return Err(Error::DuplicateAddressTag { tag: tag.0 });
+    }
+    let mut merged = Vec::with_capacity(traces.iter().map(|(_, trace)| trace.len()).sum());
+    let mut next_time_log = Some(0u64);
+    for (tag, trace) in traces {
+        if !is_sorted_by_time(&trace) {
+            return Err(Error::TraceNotSortedByTime);
+        }
+        let first = match trace.first() {
+            Some(record) => record.time_log(),
+            None => continue,
+        };
+        let offset = next_time_log.ok_or(Error::TimeLogExhausted)?;
+        for record in trace {
+            let time_log = offset
+                .checked_add(record.time_log() - first)
+                .ok_or(Error::TimeLogExhausted)?;
+            merged.push(
+                TraceRecord::new(
+                    time_log,
+                    record.stack_depth(),
+                    record.instruction(),
+                    tag.apply(record.address())?,
+                    record.value(),
+                )
+                .with_source(record.source())
+                .with_cell_size(record.cell_size()),
+            );
+            next_time_log = time_log.checked_add(1);
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_traces, AddressTag};
+    use crate::{
+        base::{Base, B256, B32},
+        error::Error,
+        machine::{
+            is_sorted_by_time, validate::validate_trace, AbstractTraceRecord, MemoryInstruction,
+            TraceRecord,
+        },
+    };
+    extern crate alloc;
+    use alloc::{vec, vec::Vec};
+
+    fn record(time_log: u64, address: u64, value: u64) -> TraceRecord<B32, B32, 4, 4> {
+        TraceRecord::new(
+            time_log,
+            0,
+            MemoryInstruction::Write,
+            B32::from(address),
+            B32::from(value),
+        )
+    }
+
+    #[test]
+    fn test_merge_traces() {
+        let merged = merge_traces(vec![
+            (AddressTag(1), vec![record(0, 0x20, 1), record(1, 0x40, 2)]),
+            (AddressTag(2), vec![]),
+            (AddressTag(3), vec![record(5, 0x20, 3), record(8, 0x20, 4)]),
+        ])
+        .unwrap();
+        assert!(is_sorted_by_time(&merged));
+        assert_eq!(validate_trace(&merged), Ok(()));
+        let records: Vec<(u64, B32)> = merged
+            .iter()
+            .map(|record| (record.time_log(), record.address()))
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                (0, B32::from(0x0100_0020u64)),
+                (1, B32::from(0x0100_0040u64)),
+                (2, B32::from(0x0300_0020u64)),
+                (5, B32::from(0x0300_0020u64)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejected_merges() {
+        let trace = vec![record(0, 0x20, 1)];
+        assert_eq!(
+            merge_traces(vec![
+                (AddressTag(1), trace.clone()),
+                (AddressTag(1), trace.clone())
+            ]),
+            Err(Error::DuplicateAddressTag { tag: 1 })
+        );
+        assert_eq!(
+            merge_traces(vec![(AddressTag(1), vec![record(0, 0x0100_0000, 1)])]),
+            Err(Error::AddressTagOverflow {
+                addr: B32::from(0x0100_0000u64).fixed_be_bytes(),
+            })
+        );
+        assert_eq!(
+            merge_traces(vec![(
+                AddressTag(1),
+                vec![record(1, 0x20, 1), record(0, 0x40, 1)]
+            )]),
+            Err(Error::TraceNotSortedByTime)
+        );
+        assert_eq!(
+            merge_traces(vec![
+                (
+                    AddressTag(1),
+                    vec![record(0, 0x20, 1), record(u64::MAX, 0x20, 1)]
+                ),
+                (AddressTag(2), trace),
+            ]),
+            Err(Error::TimeLogExhausted)
+        );
+        assert_eq!(
+            merge_traces::<B256, B256, 32, 32>(vec![(AddressTag(1), vec![])]),
+            Ok(vec![])
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Memory-mapped I/O cells backed by host callbacks.
+//! A read of a cell inside a region of [crate::machine::AbstractMemoryMachine::mmio_layout]
+//! returns the value of the device instead of the memory, a write is stored in the memory and
+//! forwarded to the device. Both are recorded in the trace as ordinary reads and writes with
+//! the values the program observed. The handlers only see the address and the value of the
+//! access, they can not touch the memory or the time log of the machine.
+//! A device read does not have to match the last write of its cell, so a trace with device
+//! accesses fails the memory consistency constraints. Remove them with [without_mmio] before
+//! the trace is proved, the device values are then not covered by the proof
+extern crate alloc;
+use crate::{
+    base::Base,
+    config::MemoryLayout,
+    machine::{AbstractTraceRecord, TraceRecord},
+};
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+/// Device mapped to a region of the memory
+pub trait MmioHandler<K, V>: Debug {
+    /// Called when a cell of the region is read, return the value observed by the machine
+    fn on_read(&mut self, address: K) -> V;
+
+    /// Called after a cell of the region was written
+    fn on_write(&mut self, address: K, value: V);
+}
+
+/// Get the records of the trace that do not access a memory-mapped I/O region, see
+/// [crate::machine::AbstractMemoryMachine::mmio_layout]. The records keep their time log
+pub fn without_mmio<K, V, const S: usize, const T: usize>(
+    trace: &[TraceRecord<K, V, S, T>],
+    mmio: Option<MemoryLayout<K>>,
+) -> Vec<TraceRecord<K, V, S, T>>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    match mmio {
+        Some(mmio) => trace
+            .iter()
+            .filter(|record| mmio.region_of(record.address(), K::from(1u64)).is_none())
+            .copied()
+            .collect(),
+        None => trace.to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{without_mmio, MmioHandler};
+    use crate::{
+        base::{Base, B256},
+        config::{ConfigArgs, DefaultConfig, MemoryLayout, MemoryRegion, RegionKind},
+        constraints::helper::{build_and_test_circuit, minimal_k},
+        error::Error,
+        machine::{
+            tests::StateMachine,
+            validate::{validate_trace, ViolationKind},
+            AbstractMachine, AbstractMemoryMachine, AbstractTraceRecord, CellInteraction,
+            MemoryInstruction,
+        },
+    };
+    extern crate alloc;
+    use alloc::{boxed::Box, rc::Rc, vec, vec::Vec};
+    use core::cell::RefCell;
+
+    // Device whose reads return incrementing values and whose writes are kept as output
+    #[derive(Debug, Default)]
+    struct Counter {
+        next: u64,
+        output: Rc<RefCell<Vec<(B256, B256)>>>,
+    }
+
+    impl MmioHandler<B256, B256> for Counter {
+        fn on_read(&mut self, _address: B256) -> B256 {
+            self.next += 1;
+            B256::from(self.next)
+        }
+
+        fn on_write(&mut self, address: B256, value: B256) {
+            self.output.borrow_mut().push((address, value));
+        }
+    }
+
+    fn io_region() -> MemoryRegion<B256> {
+        MemoryRegion::new(RegionKind::Io, B256::from(0x50000), B256::from(0x40))
+    }
+
+    #[test]
+    fn test_counter_device() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let output = Rc::new(RefCell::new(Vec::new()));
+        sm.register_mmio(
+            io_region(),
+            Box::new(Counter {
+                next: 0,
+                output: output.clone(),
+            }),
+        )
+        .unwrap();
+        assert_eq!(sm.mmio_regions(), vec![io_region()]);
+        let port = io_region().base;
+        let base = sm.base_address();
+
+        let mut observed = Vec::new();
+        for _ in 0..3 {
+            match sm.read(port).unwrap() {
+                CellInteraction::SingleCell(_, _, value) => observed.push(value),
+                _ => panic!("expected a single cell read"),
+            }
+        }
+        assert_eq!(observed, [B256::from(1), B256::from(2), B256::from(3)]);
+
+        // Writes reach the device and the memory, the ordinary memory is not mapped
+        sm.write(port + B256::WORD_SIZE, B256::from(9)).unwrap();
+        sm.write(base, B256::from(7)).unwrap();
+        assert_eq!(*output.borrow(), [(port + B256::WORD_SIZE, B256::from(9))]);
+        assert_eq!(sm.dummy_read(port + B256::WORD_SIZE), B256::from(9));
+
+        // The trace holds the observed values with consecutive time logs
+        let trace = sm.trace();
+        assert_eq!(trace.len(), 5);
+        for (time_log, (record, value)) in trace.iter().zip(observed.iter()).enumerate() {
+            assert_eq!(record.time_log(), time_log as u64);
+            assert_eq!(record.instruction(), MemoryInstruction::Read);
+            assert_eq!((record.address(), record.value()), (port, *value));
+        }
+        assert_eq!(trace[3].value(), B256::from(9));
+        assert_eq!(sm.snapshot().time_log(), 5);
+    }
+
+    #[test]
+    fn test_overlapping_devices() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        sm.register_mmio(io_region(), Box::<Counter>::default())
+            .unwrap();
+        let overlapping =
+            MemoryRegion::new(RegionKind::Heap, B256::from(0x50020), B256::from(0x40));
+        assert_eq!(
+            sm.register_mmio(overlapping, Box::<Counter>::default()),
+            Err(Error::OverlappingRegions {
+                first: RegionKind::Io,
+                second: RegionKind::Heap,
+            })
+        );
+        assert_eq!(sm.mmio_regions(), vec![io_region()]);
+
+        // The devices are not cloned, the clone reads its memory
+        let layout = MemoryLayout::new(&[io_region()]).unwrap();
+        let mut clone = sm.clone();
+        assert_eq!(clone.mmio_layout(), None);
+        assert_eq!(sm.mmio_layout(), Some(layout));
+        assert!(matches!(
+            clone.read(io_region().base).unwrap(),
+            CellInteraction::SingleCell(_, _, value) if value == B256::zero()
+        ));
+    }
+
+    #[test]
+    fn test_configured_layout_still_applies() {
+        let layout = MemoryLayout::new(&[io_region()]).unwrap();
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(ConfigArgs {
+            layout: Some(layout),
+            ..DefaultConfig::default_config()
+        });
+        sm.register_mmio(io_region(), Box::<Counter>::default())
+            .unwrap();
+        assert!(matches!(
+            sm.read(B256::from(0x60000)),
+            Err(Error::AccessOutsideRegion { .. })
+        ));
+        assert!(sm.trace().is_empty());
+    }
+
+    #[test]
+    fn test_prove_without_mmio() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        sm.register_mmio(io_region(), Box::<Counter>::default())
+            .unwrap();
+        let port = io_region().base;
+        let base = sm.base_address();
+        sm.write(base, B256::from(7)).unwrap();
+        sm.read(port).unwrap();
+        sm.write(port, B256::from(9)).unwrap();
+        sm.read(port).unwrap();
+        sm.read(base).unwrap();
+
+        // The device reads do not match the memory
+        let trace = sm.trace();
+        let violations = validate_trace(&trace).unwrap_err();
+        assert_eq!(
+            violations
+                .iter()
+                .map(|violation| violation.kind)
+                .collect::<Vec<_>>(),
+            [
+                ViolationKind::ReadBeforeWrite,
+                ViolationKind::ReadValueMismatch
+            ]
+        );
+
+        // Without the device accesses the trace is consistent and proved
+        let proved = without_mmio(&trace, sm.mmio_layout());
+        assert_eq!(proved, vec![trace[0], trace[4]]);
+        assert_eq!(validate_trace(&proved), Ok(()));
+        build_and_test_circuit(proved.iter().copied(), minimal_k(proved.len()));
+        assert_eq!(without_mmio(&trace, None), trace);
+    }
+}
@@ -0,0 +1,171 @@
+//! Hooks on the memory accesses of a machine, used for custom metrics and watchpoints.
+//! The observer of [crate::machine::AbstractMemoryMachine::observer] is notified once per
+//! touched cell, after the trace records of the access were appended, so the time log it
+//! receives is the one of the matching trace record.
+extern crate alloc;
+use crate::config::Permission;
+use alloc::{rc::Rc, vec::Vec};
+use core::{cell::RefCell, fmt::Debug};
+
+/// Observer of the memory accesses of a machine
+pub trait MemoryObserver<K, V>: Debug {
+    /// Called after a cell was read
+    fn on_read(&mut self, address: K, value: V, time_log: u64);
+
+    /// Called after a cell was written, with its previous and its new value
+    fn on_write(&mut self, address: K, old: V, new: V, time_log: u64);
+}
+
+/// Observer that records the time logs of the accesses to a single cell.
+/// Clones share the recorded hits, keep a clone to inspect the watchpoint
+/// installed on the machine
+#[derive(Debug, Clone)]
+pub struct Watchpoint<K> {
+    address: K,
+    access: Permission,
+    hits: Rc<RefCell<Vec<u64>>>,
+}
+
+impl<K: Copy> Watchpoint<K> {
+    /// Watch the accesses granted by the permission to the cell starting at the address,
+    /// e.g. [Permission::Write] only watches the writes
+    pub fn new(address: K, access: Permission) -> Self {
+        Self {
+            address,
+            access,
+            hits: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Get the watched cell
+    pub fn address(&self) -> K {
+        self.address
+    }
+
+    /// Get the time logs of the watched accesses in time order
+    pub fn hits(&self) -> Vec<u64> {
+        self.hits.borrow().clone()
+    }
+
+    // Record the access if it is watched
+    fn hit(&self, address: K, access: Permission, time_log: u64)
+    where
+        K: PartialEq,
+    {
+        if address == self.address && self.access.allows(access) {
+            self.hits.borrow_mut().push(time_log);
+        }
+    }
+}
+
+impl<K, V> MemoryObserver<K, V> for Watchpoint<K>
+where
+    K: Copy + PartialEq + Debug,
+{
+    fn on_read(&mut self, address: K, _value: V, time_log: u64) {
+        self.hit(address, Permission::Read, time_log);
+    }
+
+    fn on_write(&mut self, address: K, _old: V, _new: V, time_log: u64) {
+        self.hit(address, Permission::Write, time_log);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryObserver, Watchpoint};
+    use crate::{
+        base::{Base, B256},
+        config::{DefaultConfig, Permission},
+        machine::{
+            tests::StateMachine, AbstractMachine, AbstractMemoryMachine, AbstractStackMachine,
+        },
+    };
+    extern crate alloc;
+    use alloc::{boxed::Box, rc::Rc, vec, vec::Vec};
+    use core::cell::RefCell;
+
+    // Metrics collected by a custom observer
+    #[derive(Debug, Default)]
+    struct Metrics {
+        reads: usize,
+        // Address, old value, new value and time log of every write
+        writes: Vec<(B256, B256, B256, u64)>,
+    }
+
+    #[derive(Debug)]
+    struct MetricsObserver(Rc<RefCell<Metrics>>);
+
+    impl MemoryObserver<B256, B256> for MetricsObserver {
+        fn on_read(&mut self, _address: B256, _value: B256, _time_log: u64) {
+            self.0.borrow_mut().reads += 1;
+        }
+
+        fn on_write(&mut self, address: B256, old: B256, new: B256, time_log: u64) {
+            self.0
+                .borrow_mut()
+                .writes
+                .push((address, old, new, time_log));
+        }
+    }
+
+    #[test]
+    fn test_watchpoint_fires_once_per_access() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let watched = base + B256::from(64);
+        let on_write = Watchpoint::new(watched, Permission::Write);
+        sm.set_observer(Box::new(on_write.clone()));
+
+        sm.write(watched, B256::from(1)).unwrap();
+        sm.read(watched).unwrap();
+        sm.write(base, B256::from(2)).unwrap();
+        // An unaligned write touches the watched cell once
+        sm.write(watched + B256::from(16), B256::from(3)).unwrap();
+        sm.write(watched - B256::from(16), B256::from(4)).unwrap();
+        sm.dummy_read(watched);
+        assert_eq!(on_write.hits(), vec![0, 3, 6]);
+
+        // The time logs are the ones of the trace records
+        for time_log in on_write.hits() {
+            assert!(sm
+                .trace_iter()
+                .any(|record| record.time_log == time_log && record.address == watched));
+        }
+
+        let on_access = Watchpoint::new(watched, Permission::ReadWrite);
+        sm.set_observer(Box::new(on_access.clone()));
+        sm.read(watched).unwrap();
+        sm.write(watched, B256::from(5)).unwrap();
+        sm.read(base).unwrap();
+        assert_eq!(on_access.hits(), vec![7, 8]);
+        assert_eq!(on_write.hits().len(), 3);
+    }
+
+    #[test]
+    fn test_custom_observer() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let metrics = Rc::new(RefCell::new(Metrics::default()));
+        sm.set_observer(Box::new(MetricsObserver(metrics.clone())));
+        let base = sm.base_address();
+
+        sm.write(base, B256::from(1)).unwrap();
+        sm.write(base, B256::from(2)).unwrap();
+        sm.read(base).unwrap();
+        sm.push(B256::from(3)).unwrap();
+        sm.pop().unwrap();
+
+        let metrics = metrics.borrow();
+        assert_eq!(metrics.reads, 2);
+        assert_eq!(metrics.writes.len(), 3);
+        assert_eq!(
+            metrics.writes[..2],
+            [
+                (base, B256::zero(), B256::from(1), 0),
+                (base, B256::from(1), B256::from(2), 1)
+            ]
+        );
+        assert_eq!(metrics.writes[2].2, B256::from(3));
+        assert_eq!(metrics.writes[2].3, 3);
+    }
+}
@@ -0,0 +1,505 @@
+//! Host side replay of the execution trace, independent of the circuits.
+//! The records are applied in order to the memory of a snapshot: writes and pushes store
+//! their value, reads and pops must return the value the replayed memory holds, an
+//! uninitialized cell holds zero like in [crate::machine::AbstractMemoryMachine::dummy_read].
+//! [fill_read_values] uses the same replay to complete a trace that only knows its writes and
+//! [check_state_transition] checks a trace against the roots of the states around it.
+extern crate alloc;
+use crate::{
+    base::Base,
+    commitment::merkle::{CommitmentBackend, MerkleRoot},
+    config::ReadPolicy,
+    error::Error,
+    machine::{
+        is_sorted_by_time, AbstractTraceRecord, MemoryInstruction, MemorySnapshot, TraceRecord,
+    },
+};
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::fmt::{Display, Formatter};
+
+/// First record of the trace that disagrees with the replay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayError<V> {
+    /// The time log of the record is not the next one of the replay
+    TimeLogMismatch {
+        /// Index of the record in the trace
+        index: usize,
+        /// Time log of the next access of the replay
+        expected: u64,
+        /// Time log of the record
+        actual: u64,
+    },
+    /// The record reads another value than the one held by the replayed memory
+    ValueMismatch {
+        /// Index of the record in the trace
+        index: usize,
+        /// Value held by the replayed memory
+        expected: V,
+        /// Value of the record
+        actual: V,
+    },
+    /// The push moves the stack pointer past the end of the address space
+    StackPointerOverflow {
+        /// Index of the record in the trace
+        index: usize,
+    },
+}
+
+impl<V: Display> Display for ReplayError<V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ReplayError::TimeLogMismatch {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Record {}: expected time log {}, found {}",
+                index, expected, actual
+            ),
+            ReplayError::ValueMismatch {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Record {}: expected value {}, found {}",
+                index, expected, actual
+            ),
+            ReplayError::StackPointerOverflow { index } => {
+                write!(f, "Record {}: the stack pointer overflows", index)
+            }
+        }
+    }
+}
+
+/// Replay the trace, in time order, on top of the snapshot it was recorded from and return
+/// the state after the last record. The trace must continue the time log of the snapshot.
+/// The stack pointer follows the pushes and the pops, the stack depth is the one of the
+/// last record. The trace does not tell the gas of the accesses, the gas used and the config
+/// fingerprint of the snapshot are kept
+pub fn replay_and_check<K, V, const S: usize, const T: usize>(
+    initial: &MemorySnapshot<K, V>,
+    trace: &[TraceRecord<K, V, S, T>],
+) -> Result<MemorySnapshot<K, V>, ReplayError<V>>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let mut memory: BTreeMap<K, V> = initial.cells.iter().copied().collect();
+    let mut stack_ptr = initial.stack_ptr;
+    let mut stack_depth = initial.stack_depth;
+    let mut time_log = initial.time_log;
+    for (index, record) in trace.iter().enumerate() {
+        if record.time_log() != time_log {
+            return Err(ReplayError::TimeLogMismatch {
+                index,
+                expected: time_log,
+                actual: record.time_log(),
+            });
+        }
+        if record.instruction().is_write() {
+            memory.insert(record.address(), record.value());
+        } else {
+            let expected = memory.get(&record.address()).copied().unwrap_or(V::zero());
+            if expected != record.value() {
+                return Err(ReplayError::ValueMismatch {
+                    index,
+                    expected,
+                    actual: record.value(),
+                });
+            }
+        }
+        match record.instruction() {
+            MemoryInstruction::Push => {
+                stack_ptr = record
+                    .address()
+                    .checked_add(K::WORD_SIZE)
+                    .ok_or(ReplayError::StackPointerOverflow { index })?
+            }
+            MemoryInstruction::Pop => stack_ptr = record.address(),
+            _ => {}
+        }
+        stack_depth = record.stack_depth();
+        time_log += 1;
+    }
+    Ok(MemorySnapshot {
+        cells: memory.into_iter().collect(),
+        stack_ptr,
+        stack_depth,
+        time_log,
+        gas_used: initial.gas_used,
+        config_fingerprint: initial.config_fingerprint,
+    })
+}
+
+/// Check that the trace moves the memory from the initial root to the final root. The initial
+/// state must hash to the initial root with the backend, the trace must replay on top of it,
+/// see [replay_and_check], and the replayed state must hash to the final root. The cells are
+/// committed like [commit_image](crate::commitment::merkle::commit_image), the dense walk
+/// steps by [Base::WORD_SIZE]. Return [Error::InitialStateRootMismatch],
+/// [Error::TransitionReplayMismatch] or [Error::FinalStateRootMismatch] for the failed check
+/// and [Error::DenseCommitmentTooLarge] if the dense walk of a state is too long
+pub fn check_state_transition<K, V, const S: usize, const T: usize>(
+    initial_root: &MerkleRoot,
+    initial_state: &MemorySnapshot<K, V>,
+    trace: &[TraceRecord<K, V, S, T>],
+    final_root: &MerkleRoot,
+    backend: CommitmentBackend,
+) -> Result<(), Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    if backend.commit(initial_state.cells(), K::WORD_SIZE)? != *initial_root {
+        return Err(Error::InitialStateRootMismatch);
+    }
+    let replayed = replay_and_check(initial_state, trace).map_err(|error| {
+        let index = match error {
+            ReplayError::TimeLogMismatch { index, .. } => index,
+            ReplayError::ValueMismatch { index, .. } => index,
+            ReplayError::StackPointerOverflow { index } => index,
+        };
+        Error::TransitionReplayMismatch { index }
+    })?;
+    if backend.commit(replayed.cells(), K::WORD_SIZE)? != *final_root {
+        return Err(Error::FinalStateRootMismatch);
+    }
+    Ok(())
+}
+
+/// Fill the values of the reads and pops of a trace built by hand, e.g. imported from an
+/// emulator that only logs its writes. The writes are replayed in time order on top of the
+/// snapshot and every read takes the value its cell holds at its time log. A cell that was
+/// never written reads as zero, or is rejected with [Error::UninitializedRead] by
+/// [ReadPolicy::ErrorOnUninit] if the write it needs comes later.
+/// Return [Error::TraceNotSortedByTime] if the time log of the trace is not strictly increasing
+/// or starts before the snapshot, the trace is left untouched on error
+pub fn fill_read_values<K, V, const S: usize, const T: usize>(
+    trace: &mut [TraceRecord<K, V, S, T>],
+    initial: &MemorySnapshot<K, V>,
+    policy: ReadPolicy,
+) -> Result<(), Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let starts_before = trace
+        .first()
+        .is_some_and(|record| record.time_log < initial.time_log);
+    if starts_before || !is_sorted_by_time(trace) {
+        return Err(Error::TraceNotSortedByTime);
+    }
+    let mut memory: BTreeMap<K, V> = initial.cells.iter().copied().collect();
+    let mut values = Vec::with_capacity(trace.len());
+    for record in trace.iter() {
+        if record.instruction.is_write() {
+            memory.insert(record.address, record.value);
+            values.push(record.value);
+            continue;
+        }
+        values.push(match (memory.get(&record.address), policy) {
+            (Some(value), _) => *value,
+            (None, ReadPolicy::ZeroOnUninit) => V::zero(),
+            (None, ReadPolicy::ErrorOnUninit) => {
+                return Err(Error::UninitializedRead {
+                    addr: record.address.fixed_be_bytes(),
+                })
+            }
+        });
+    }
+    for (record, value) in trace.iter_mut().zip(values) {
+        record.value = value;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_state_transition, fill_read_values, replay_and_check, ReplayError};
+    use crate::{
+        base::{Base, B256},
+        commitment::merkle::{commit_image, CommitmentBackend},
+        config::{DefaultConfig, ReadPolicy},
+        constraints::helper::{build_and_test_circuit, minimal_k},
+        error::Error,
+        machine::{
+            tests::StateMachine, validate::validate_trace, AbstractMachine, AbstractMemoryMachine,
+            AbstractStackMachine, AbstractTraceRecord, MemoryInstruction, TraceRecord,
+        },
+    };
+    extern crate alloc;
+    use alloc::{format, vec::Vec};
+    use rand::Rng;
+
+    // Run random accesses, unaligned ones included
+    fn random_run(sm: &mut StateMachine<B256, B256, 32, 32>, steps: usize) {
+        let mut rng = rand::thread_rng();
+        let base = sm.base_address();
+        for _ in 0..steps {
+            let address = base + B256::from(rng.gen_range(0..512u64));
+            match rng.gen_range(0..4) {
+                0 => {
+                    sm.read(address).unwrap();
+                }
+                1 if sm.snapshot().stack_depth() > 0 => {
+                    sm.pop().unwrap();
+                }
+                2 => {
+                    sm.push(B256::from(rng.gen::<u64>())).unwrap();
+                }
+                _ => {
+                    sm.write(address, B256::from(rng.gen::<u64>())).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_replay_matches_the_machine() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let genesis = sm.snapshot();
+        random_run(&mut sm, 200);
+        let middle = sm.snapshot();
+        let split = sm.trace().len();
+        random_run(&mut sm, 200);
+
+        let trace = sm.trace();
+        assert_eq!(replay_and_check(&genesis, &trace), Ok(sm.snapshot()));
+        // The replay can start from any snapshot of the run
+        assert_eq!(
+            replay_and_check(&middle, &trace[split..]),
+            Ok(sm.snapshot())
+        );
+        assert_eq!(
+            replay_and_check::<B256, B256, 32, 32>(&middle, &[]),
+            Ok(middle)
+        );
+    }
+
+    #[test]
+    fn test_tampered_read_is_caught() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let genesis = sm.snapshot();
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+        sm.write(base + B256::from(32), B256::from(2)).unwrap();
+        sm.read(base).unwrap();
+        sm.read(base + B256::from(64)).unwrap();
+
+        let trace = sm.trace();
+        let mut tampered: Vec<TraceRecord<B256, B256, 32, 32>> = trace.clone();
+        tampered[2] = TraceRecord::new(2, 0, MemoryInstruction::Read, base, B256::from(5));
+        let error = replay_and_check(&genesis, &tampered).unwrap_err();
+        assert_eq!(
+            error,
+            ReplayError::ValueMismatch {
+                index: 2,
+                expected: B256::from(1),
+                actual: B256::from(5),
+            }
+        );
+        assert_eq!(format!("{}", error), "Record 2: expected value 1, found 5");
+
+        // An uninitialized cell reads as zero
+        let mut tampered = trace.clone();
+        tampered[3] = TraceRecord::new(
+            3,
+            0,
+            MemoryInstruction::Read,
+            base + B256::from(64),
+            B256::from(1),
+        );
+        assert!(matches!(
+            replay_and_check(&genesis, &tampered),
+            Err(ReplayError::ValueMismatch { index: 3, expected, .. }) if expected.is_zero()
+        ));
+
+        // The trace must continue the time log of the snapshot
+        assert_eq!(
+            replay_and_check(&sm.snapshot(), &trace),
+            Err(ReplayError::TimeLogMismatch {
+                index: 0,
+                expected: 4,
+                actual: 0,
+            })
+        );
+
+        // A push at the last word moves the stack pointer past the address space
+        let overflow = [TraceRecord::new(
+            0,
+            1,
+            MemoryInstruction::Push,
+            B256::MAX - B256::from(31),
+            B256::from(1),
+        )];
+        let error = replay_and_check(&genesis, &overflow).unwrap_err();
+        assert_eq!(error, ReplayError::StackPointerOverflow { index: 0 });
+        assert_eq!(
+            format!("{}", error),
+            "Record 0: the stack pointer overflows"
+        );
+    }
+
+    #[test]
+    fn test_check_state_transition() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        random_run(&mut sm, 50);
+        let initial = sm.snapshot();
+        let split = sm.trace().len();
+        random_run(&mut sm, 200);
+        let trace = sm.trace()[split..].to_vec();
+        let initial_root = commit_image(initial.cells());
+        let final_root = sm.final_state_root(CommitmentBackend::Sparse).unwrap();
+        for backend in [CommitmentBackend::Sparse, CommitmentBackend::Dense] {
+            assert_eq!(
+                check_state_transition(&initial_root, &initial, &trace, &final_root, backend),
+                Ok(())
+            );
+        }
+
+        let backend = CommitmentBackend::Sparse;
+        let mut tampered = final_root;
+        tampered[0] ^= 1;
+        assert_eq!(
+            check_state_transition(&initial_root, &initial, &trace, &tampered, backend),
+            Err(Error::FinalStateRootMismatch)
+        );
+        assert_eq!(
+            check_state_transition(&final_root, &initial, &trace, &final_root, backend),
+            Err(Error::InitialStateRootMismatch)
+        );
+        assert_eq!(
+            check_state_transition(&initial_root, &sm.snapshot(), &trace, &final_root, backend),
+            Err(Error::InitialStateRootMismatch)
+        );
+
+        // A changed read is caught by the replay and a changed write by the final root
+        let index = trace.len() - 1;
+        let (time_log, stack_depth, _, address, value) = trace[index].get_tuple();
+        for instruction in [MemoryInstruction::Read, MemoryInstruction::Write] {
+            let mut tampered = trace.clone();
+            let value = value + B256::from(1);
+            tampered[index] = TraceRecord::new(time_log, stack_depth, instruction, address, value);
+            let expected = if instruction.is_write() {
+                Error::FinalStateRootMismatch
+            } else {
+                Error::TransitionReplayMismatch { index }
+            };
+            assert_eq!(
+                check_state_transition(&initial_root, &initial, &tampered, &final_root, backend),
+                Err(expected)
+            );
+        }
+        assert_eq!(
+            check_state_transition(&initial_root, &initial, &trace[1..], &final_root, backend),
+            Err(Error::TransitionReplayMismatch { index: 0 })
+        );
+    }
+
+    // Drop the values of the reads, as logged by an emulator that only records its writes
+    fn writes_only(
+        trace: &[TraceRecord<B256, B256, 32, 32>],
+    ) -> Vec<TraceRecord<B256, B256, 32, 32>> {
+        trace
+            .iter()
+            .map(|record| {
+                let (time_log, stack_depth, instruction, address, value) = record.get_tuple();
+                let value = if instruction.is_write() {
+                    value
+                } else {
+                    B256::zero()
+                };
+                TraceRecord::new(time_log, stack_depth, instruction, address, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fill_read_values() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        sm.write(sm.base_address(), B256::from(3)).unwrap();
+        let genesis = sm.snapshot();
+        random_run(&mut sm, 200);
+
+        let trace = sm.trace()[1..].to_vec();
+        let mut imported = writes_only(&trace);
+        fill_read_values(&mut imported, &genesis, ReadPolicy::ZeroOnUninit).unwrap();
+        assert_eq!(imported, trace);
+        assert_eq!(replay_and_check(&genesis, &imported), Ok(sm.snapshot()));
+
+        let full = sm.trace();
+        let mut imported = writes_only(&full);
+        let empty = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        fill_read_values(&mut imported, &empty.snapshot(), ReadPolicy::ZeroOnUninit).unwrap();
+        assert_eq!(imported, full);
+        assert_eq!(validate_trace(&imported), Ok(()));
+        build_and_test_circuit(imported.iter().copied(), minimal_k(imported.len()));
+    }
+
+    #[test]
+    fn test_fill_read_values_errors() {
+        let sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let genesis = sm.snapshot();
+        let base = sm.base_address();
+        let record = |time_log: u64, instruction: MemoryInstruction, address: B256| {
+            TraceRecord::<B256, B256, 32, 32>::new(time_log, 0, instruction, address, B256::from(7))
+        };
+        let trace = [
+            record(0, MemoryInstruction::Write, base),
+            record(1, MemoryInstruction::Read, base + B256::from(32)),
+            record(2, MemoryInstruction::Write, base + B256::from(32)),
+            record(3, MemoryInstruction::Read, base),
+        ];
+
+        // The read of time log 1 comes before the write it needs
+        let mut strict = trace;
+        assert_eq!(
+            fill_read_values(&mut strict, &genesis, ReadPolicy::ErrorOnUninit),
+            Err(Error::UninitializedRead {
+                addr: (base + B256::from(32)).fixed_be_bytes(),
+            })
+        );
+        assert_eq!(strict, trace);
+        let mut lenient = trace;
+        fill_read_values(&mut lenient, &genesis, ReadPolicy::ZeroOnUninit).unwrap();
+        assert_eq!(lenient[1].value(), B256::zero());
+        assert_eq!(lenient[3].value(), B256::from(7));
+
+        // The cells of the snapshot are initialized
+        let mut initialized = sm.clone();
+        initialized
+            .write(base + B256::from(32), B256::from(5))
+            .unwrap();
+        let mut shifted: Vec<_> = trace
+            .iter()
+            .map(|record| {
+                let (time_log, _, instruction, address, value) = record.get_tuple();
+                TraceRecord::new(time_log + 1, 0, instruction, address, value)
+            })
+            .collect();
+        fill_read_values(
+            &mut shifted,
+            &initialized.snapshot(),
+            ReadPolicy::ErrorOnUninit,
+        )
+        .unwrap();
+        assert_eq!(shifted[1].value(), B256::from(5));
+
+        // The trace must be sorted by time and start after the snapshot
+        let mut unsorted = [trace[1], trace[0]];
+        assert_eq!(
+            fill_read_values(&mut unsorted, &genesis, ReadPolicy::ZeroOnUninit),
+            Err(Error::TraceNotSortedByTime)
+        );
+        let mut early = trace;
+        assert_eq!(
+            fill_read_values(
+                &mut early,
+                &initialized.snapshot(),
+                ReadPolicy::ZeroOnUninit
+            ),
+            Err(Error::TraceNotSortedByTime)
+        );
+    }
+}
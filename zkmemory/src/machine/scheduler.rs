@@ -0,0 +1,298 @@
+//! Deterministic interleaving of cooperating routines over one shared memory.
+//! Every routine is a program of the machine with its own stack pointer and stack depth, the
+//! memory and the time log are shared. The routines run round-robin, one instruction of every
+//! unfinished routine per round in the order they were added, so the combined trace only
+//! depends on the programs. Every access takes the next time log of the machine, whichever
+//! routine performs it.
+extern crate alloc;
+use crate::{
+    base::Base,
+    error::Error,
+    machine::{
+        require_full_trace, AbstractContext, AbstractMachine, AbstractMemoryMachine,
+        AbstractTraceRecord,
+    },
+};
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::marker::PhantomData;
+
+// Program of a routine and its own stack context
+#[derive(Debug)]
+struct Routine<I, K> {
+    program: Vec<I>,
+    next: usize,
+    stack_ptr: K,
+    stack_depth: u64,
+}
+
+/// Round-robin scheduler of routines sharing the memory of a machine
+#[derive(Debug)]
+pub struct Scheduler<M, K, V>
+where
+    K: Ord,
+    M: AbstractMachine<K, V>,
+{
+    machine: M,
+    routines: Vec<Routine<M::Instruction, K>>,
+    round: u64,
+    _marker: PhantomData<V>,
+}
+
+impl<M, K, V> Scheduler<M, K, V>
+where
+    K: Ord + Copy,
+    M: AbstractMachine<K, V>,
+{
+    /// Schedule routines on the machine
+    pub fn new(machine: M) -> Self {
+        Self {
+            machine,
+            routines: Vec::new(),
+            round: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Add a routine that starts from the current stack context of the machine,
+    /// return its index in the schedule
+    pub fn add_routine(&mut self, program: Vec<M::Instruction>) -> usize {
+        let context = self.machine.ro_context();
+        self.routines.push(Routine {
+            program,
+            next: 0,
+            stack_ptr: context.stack_ptr(),
+            stack_depth: context.stack_depth(),
+        });
+        self.routines.len() - 1
+    }
+
+    /// Get the shared machine
+    pub fn machine(&self) -> &M {
+        &self.machine
+    }
+
+    /// Consume the scheduler and return the shared machine
+    pub fn into_machine(self) -> M {
+        self.machine
+    }
+
+    /// Get the number of rounds run so far
+    pub fn round(&self) -> u64 {
+        self.round
+    }
+
+    /// Check whether every routine ran its whole program
+    pub fn is_finished(&self) -> bool {
+        self.routines
+            .iter()
+            .all(|routine| routine.next == routine.program.len())
+    }
+
+    /// Run one instruction of every unfinished routine, return false if there was none.
+    /// Return [Error::ScheduleConflict] once the round is over if a cell was written by two
+    /// routines during the round, the error of an instruction stops the round at once.
+    /// The conflicts are found in the trace, return [Error::TraceNotRetained] if the machine
+    /// does not keep it and [Error::TraceInSink] if it pushes its records to a trace sink
+    pub fn run_round<const S: usize, const T: usize>(&mut self) -> Result<bool, Error>
+    where
+        K: Base<S>,
+        V: Base<T>,
+        M: AbstractMemoryMachine<K, V, S, T>,
+    {
+        require_full_trace(&self.machine)?;
+        if self.machine.trace_sink().is_some() {
+            return Err(Error::TraceInSink);
+        }
+        // Routine and first time log of every step of the round, the records of the round
+        // follow the ones already in the trace
+        let mut steps = Vec::new();
+        let kept = self.machine.trace_len();
+        for (index, routine) in self.routines.iter_mut().enumerate() {
+            let instruction = match routine.program.get(routine.next) {
+                Some(instruction) => instruction,
+                None => continue,
+            };
+            let context = self.machine.context();
+            context.set_stack_ptr(routine.stack_ptr);
+            context.set_stack_depth(routine.stack_depth);
+            steps.push((index, context.time_log()));
+            self.machine.exec(instruction)?;
+            let context = self.machine.ro_context();
+            routine.stack_ptr = context.stack_ptr();
+            routine.stack_depth = context.stack_depth();
+            routine.next += 1;
+        }
+        if steps.is_empty() {
+            return Ok(false);
+        }
+        self.round += 1;
+        // Routine that wrote every cell during the round
+        let mut writers = BTreeMap::new();
+        for record in self
+            .machine
+            .trace_iter()
+            .skip(kept)
+            .filter(|record| record.instruction().is_write())
+        {
+            let step = steps.partition_point(|(_, time_log)| *time_log <= record.time_log());
+            let routine = steps[step - 1].0;
+            match writers.insert(record.address(), routine) {
+                Some(other) if other != routine => {
+                    return Err(Error::ScheduleConflict {
+                        addr: record.address().fixed_be_bytes(),
+                        round: self.round,
+                    })
+                }
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+
+    /// Run the rounds until every routine ran its whole program
+    pub fn run<const S: usize, const T: usize>(&mut self) -> Result<(), Error>
+    where
+        K: Base<S>,
+        V: Base<T>,
+        M: AbstractMemoryMachine<K, V, S, T>,
+    {
+        while self.run_round()? {}
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scheduler;
+    use crate::{
+        base::{Base, B256},
+        config::{ConfigBuilder, DefaultConfig, TraceMode},
+        constraints::helper::{build_and_test_circuit, minimal_k},
+        error::Error,
+        machine::{
+            tests::{Instruction, StateMachine},
+            validate::validate_trace,
+            AbstractMachine, AbstractMemoryMachine, AbstractTraceRecord, TraceRecord,
+        },
+    };
+    extern crate alloc;
+    use alloc::{boxed::Box, vec, vec::Vec};
+
+    // Routines exchanging values through the memory
+    fn run_pair() -> Vec<TraceRecord<B256, B256, 32, 32>> {
+        let sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let a = sm.base_address();
+        let b = a + B256::from(32);
+        let mut scheduler = Scheduler::<_, B256, B256>::new(sm);
+        scheduler.add_routine(vec![
+            Instruction::Write(a, B256::from(1)),
+            Instruction::Read(b),
+            Instruction::Write(a, B256::from(3)),
+        ]);
+        scheduler.add_routine(vec![
+            Instruction::Write(b, B256::from(2)),
+            Instruction::Read(a),
+        ]);
+        scheduler.run().unwrap();
+        assert!(scheduler.is_finished());
+        assert_eq!(scheduler.round(), 3);
+        scheduler.into_machine().trace()
+    }
+
+    #[test]
+    fn test_round_robin_is_deterministic() {
+        let trace = run_pair();
+        assert_eq!(trace, run_pair());
+        assert_eq!(validate_trace(&trace), Ok(()));
+
+        // The routines take turns and the time logs are global
+        let base = trace[0].address();
+        let accesses: Vec<(u64, B256, B256)> = trace
+            .iter()
+            .map(|record| (record.time_log(), record.address() - base, record.value()))
+            .collect();
+        assert_eq!(
+            accesses,
+            [
+                (0, B256::zero(), B256::from(1)),
+                (1, B256::from(32), B256::from(2)),
+                (2, B256::from(32), B256::from(2)),
+                (3, B256::zero(), B256::from(1)),
+                (4, B256::zero(), B256::from(3)),
+            ]
+        );
+
+        build_and_test_circuit(trace.iter().copied(), minimal_k(trace.len()));
+    }
+
+    #[test]
+    fn test_write_conflict() {
+        let sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let a = sm.base_address();
+        let mut scheduler = Scheduler::<_, B256, B256>::new(sm);
+        scheduler.add_routine(vec![
+            Instruction::Write(a + B256::from(32), B256::from(1)),
+            Instruction::Write(a, B256::from(1)),
+        ]);
+        scheduler.add_routine(vec![
+            Instruction::Read(a + B256::from(32)),
+            Instruction::Write(a, B256::from(2)),
+        ]);
+        // A cell read by one routine and written by the other one is not a conflict
+        assert_eq!(scheduler.run_round(), Ok(true));
+        assert_eq!(
+            scheduler.run_round(),
+            Err(Error::ScheduleConflict {
+                addr: a.fixed_be_bytes(),
+                round: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_trace_must_be_retained() {
+        let sm = StateMachine::<B256, B256, 32, 32>::from_config(
+            ConfigBuilder::new()
+                .trace_mode(TraceMode::CommitmentsOnly)
+                .build()
+                .unwrap(),
+        );
+        let a = sm.base_address();
+        let mut scheduler = Scheduler::<_, B256, B256>::new(sm);
+        scheduler.add_routine(vec![Instruction::Write(a, B256::from(1))]);
+        scheduler.add_routine(vec![Instruction::Write(a, B256::from(2))]);
+        assert_eq!(
+            scheduler.run_round(),
+            Err(Error::TraceNotRetained {
+                mode: TraceMode::CommitmentsOnly
+            })
+        );
+        assert_eq!(scheduler.round(), 0);
+
+        // The records pushed to a sink are not in the trace either
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let a = sm.base_address();
+        sm.set_trace_sink(Box::new(Vec::<TraceRecord<B256, B256, 32, 32>>::new()));
+        let mut scheduler = Scheduler::<_, B256, B256>::new(sm);
+        scheduler.add_routine(vec![Instruction::Write(a, B256::from(1))]);
+        scheduler.add_routine(vec![Instruction::Write(a, B256::from(2))]);
+        assert_eq!(scheduler.run_round(), Err(Error::TraceInSink));
+        assert_eq!(scheduler.round(), 0);
+    }
+
+    #[test]
+    fn test_routines_keep_their_stack() {
+        use crate::machine::AbstractStackMachine;
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        sm.push(B256::from(7)).unwrap();
+        let mut scheduler = Scheduler::<_, B256, B256>::new(sm);
+        scheduler.add_routine(vec![Instruction::Pop()]);
+        scheduler.add_routine(vec![Instruction::Pop()]);
+        // Both routines pop the value pushed before they were added
+        scheduler.run().unwrap();
+        let trace = scheduler.into_machine().trace();
+        assert_eq!(trace.len(), 3);
+        assert_eq!(trace[1].address(), trace[2].address());
+        assert_eq!(trace[2].value(), B256::from(7));
+    }
+}
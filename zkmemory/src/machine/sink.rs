@@ -0,0 +1,467 @@
+//! Streaming consumers of the execution trace.
+//! A machine with a [TraceSink], see [crate::machine::AbstractMemoryMachine::trace_sink],
+//! forwards every trace record to the sink instead of keeping it, so the length of the trace
+//! is not bounded by the memory of the host. Clones of the sinks below share their state like
+//! [crate::machine::observer::Watchpoint], keep a clone to inspect the sink installed on the
+//! machine.
+extern crate alloc;
+use crate::{
+    base::Base,
+    config::Endianness,
+    error::Error,
+    machine::{trace_codec::encode_record, TraceRecord},
+};
+use alloc::{rc::Rc, vec::Vec};
+use blake2b_simd::{Params, State};
+use core::{cell::RefCell, fmt::Debug};
+#[cfg(feature = "std")]
+use {
+    crate::machine::trace_codec::{encode_header, COUNT_OFFSET},
+    std::io::{Seek, SeekFrom, Write},
+};
+
+// Personalization of the hash of [HashingSink]
+const PERSONAL: &[u8] = b"zkmemory-trace";
+
+/// Consumer of the trace records of a machine, the records are pushed in time order
+pub trait TraceSink<K, V, const S: usize, const T: usize>: Debug
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Consume the next trace record
+    fn push(&mut self, record: TraceRecord<K, V, S, T>) -> Result<(), Error>;
+
+    /// Consume the records of one access, e.g. the two cells of an unaligned access. Either
+    /// every record is consumed or none is. The default pushes them one by one, a sink whose
+    /// push can fail should override it
+    fn push_all(&mut self, records: &[TraceRecord<K, V, S, T>]) -> Result<(), Error> {
+        records.iter().try_for_each(|record| self.push(*record))
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> TraceSink<K, V, S, T> for Vec<TraceRecord<K, V, S, T>>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn push(&mut self, record: TraceRecord<K, V, S, T>) -> Result<(), Error> {
+        Vec::push(self, record);
+        Ok(())
+    }
+}
+
+/// Sink that keeps the records in memory, like a machine without a sink
+#[derive(Debug, Clone)]
+pub struct VecSink<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    records: Rc<RefCell<Vec<TraceRecord<K, V, S, T>>>>,
+}
+
+impl<K, V, const S: usize, const T: usize> Default for VecSink<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn default() -> Self {
+        Self {
+            records: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> VecSink<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Create an empty sink
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the records pushed so far
+    pub fn records(&self) -> Vec<TraceRecord<K, V, S, T>> {
+        self.records.borrow().clone()
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> TraceSink<K, V, S, T> for VecSink<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn push(&mut self, record: TraceRecord<K, V, S, T>) -> Result<(), Error> {
+        self.records.borrow_mut().push(record);
+        Ok(())
+    }
+}
+
+/// Sink that only keeps a BLAKE2b commitment to the records,
+/// every record is hashed in the big endian encoding of [crate::machine::trace_codec], so the
+/// commitment does not depend on the byte order of the machine
+#[derive(Debug, Clone)]
+pub struct HashingSink {
+    state: Rc<RefCell<(State, u64)>>,
+}
+
+impl Default for HashingSink {
+    fn default() -> Self {
+        let state = Params::new().hash_length(32).personal(PERSONAL).to_state();
+        Self {
+            state: Rc::new(RefCell::new((state, 0))),
+        }
+    }
+}
+
+impl HashingSink {
+    /// Create a sink that has not hashed any record
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copy the sink into one that does not share its state, the copy goes on from the records
+    /// hashed so far
+    pub fn detached(&self) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(self.state.borrow().clone())),
+        }
+    }
+
+    /// Get the number of records hashed so far
+    pub fn len(&self) -> u64 {
+        self.state.borrow().1
+    }
+
+    /// Check if no record was hashed
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the commitment to the records pushed so far and their number
+    pub fn digest(&self) -> [u8; 32] {
+        let (state, count) = &*self.state.borrow();
+        let mut state = state.clone();
+        state.update(&count.to_le_bytes());
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(state.finalize().as_bytes());
+        digest
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> TraceSink<K, V, S, T> for HashingSink
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn push(&mut self, record: TraceRecord<K, V, S, T>) -> Result<(), Error> {
+        let mut bytes = Vec::new();
+        encode_record(&record, Endianness::Big, &mut bytes);
+        let (state, count) = &mut *self.state.borrow_mut();
+        state.update(&bytes);
+        *count += 1;
+        Ok(())
+    }
+}
+
+/// Sink that streams the records to a writer in the binary format of
+/// [crate::machine::trace_codec], the record count of the header is only written by
+/// [CodecSink::finish]. Read the records back with [crate::machine::trace_codec::TraceReader]
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct CodecSink<W, const S: usize, const T: usize> {
+    inner: Rc<RefCell<CodecState<W>>>,
+}
+
+// A clone writes to the same writer, the writer itself is not cloned
+#[cfg(feature = "std")]
+impl<W, const S: usize, const T: usize> Clone for CodecSink<W, S, T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+// Writer of a [CodecSink] with the position of the header, the byte order of the records
+// and the number of records
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct CodecState<W> {
+    writer: W,
+    header: u64,
+    endianness: Endianness,
+    count: u64,
+}
+
+#[cfg(feature = "std")]
+impl<W, const S: usize, const T: usize> CodecSink<W, S, T>
+where
+    W: Write + Seek + Debug,
+{
+    /// Write the header of an empty trace whose records are in the byte order, made under
+    /// the config of the fingerprint. Return [Error::TraceIo] if the writer fails
+    pub fn new(
+        mut writer: W,
+        endianness: Endianness,
+        fingerprint: [u8; 32],
+    ) -> Result<Self, Error> {
+        let header = writer
+            .stream_position()
+            .and_then(|header| {
+                writer.write_all(&encode_header::<S, T>(0, endianness, fingerprint))?;
+                Ok(header)
+            })
+            .map_err(|_| Error::TraceIo)?;
+        Ok(Self {
+            inner: Rc::new(RefCell::new(CodecState {
+                writer,
+                header,
+                endianness,
+                count: 0,
+            })),
+        })
+    }
+
+    /// Get the number of records written so far
+    pub fn len(&self) -> u64 {
+        self.inner.borrow().count
+    }
+
+    /// Check if no record was written
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the record count into the header and flush the writer, the sink can still be
+    /// used and finished again afterwards
+    pub fn finish(&self) -> Result<(), Error> {
+        let state = &mut *self.inner.borrow_mut();
+        let writer = &mut state.writer;
+        writer
+            .stream_position()
+            .and_then(|end| {
+                writer.seek(SeekFrom::Start(state.header + COUNT_OFFSET as u64))?;
+                writer.write_all(&state.count.to_le_bytes())?;
+                writer.seek(SeekFrom::Start(end))?;
+                writer.flush()
+            })
+            .map_err(|_| Error::TraceIo)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V, W, const S: usize, const T: usize> TraceSink<K, V, S, T> for CodecSink<W, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+    W: Write + Seek + Debug,
+{
+    fn push(&mut self, record: TraceRecord<K, V, S, T>) -> Result<(), Error> {
+        TraceSink::<K, V, S, T>::push_all(self, &[record])
+    }
+
+    // The records are written at once, a failed write is cut off the end of the writer
+    fn push_all(&mut self, records: &[TraceRecord<K, V, S, T>]) -> Result<(), Error> {
+        let state = &mut *self.inner.borrow_mut();
+        let mut bytes = Vec::new();
+        for record in records {
+            encode_record(record, state.endianness, &mut bytes);
+        }
+        let writer = &mut state.writer;
+        let end = writer.stream_position().map_err(|_| Error::TraceIo)?;
+        if writer.write_all(&bytes).is_err() {
+            // Seek back so the next records are written over the partial write
+            writer
+                .seek(SeekFrom::Start(end))
+                .map_err(|_| Error::TraceIo)?;
+            return Err(Error::TraceIo);
+        }
+        state.count += records.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HashingSink, TraceSink, VecSink};
+    use crate::{
+        base::{Base, B256},
+        config::{DefaultConfig, Endianness},
+        error::Error,
+        machine::{
+            tests::StateMachine, AbstractMachine, AbstractMemoryMachine, AbstractStackMachine,
+            TraceRecord,
+        },
+    };
+    extern crate alloc;
+    use alloc::boxed::Box;
+
+    type Machine = StateMachine<B256, B256, 32, 32>;
+
+    // Aligned and unaligned accesses followed by a push and a pop
+    fn run(sm: &mut Machine) {
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+        sm.write(base + B256::from(40), B256::from(2)).unwrap();
+        sm.read(base + B256::from(7)).unwrap();
+        sm.push(B256::from(3)).unwrap();
+        sm.pop().unwrap();
+    }
+
+    #[derive(Debug)]
+    struct Rejecting;
+
+    impl TraceSink<B256, B256, 32, 32> for Rejecting {
+        fn push(&mut self, _record: TraceRecord<B256, B256, 32, 32>) -> Result<(), Error> {
+            Err(Error::TraceIo)
+        }
+    }
+
+    #[test]
+    fn test_vec_sink() {
+        let mut buffered = Machine::new(DefaultConfig::default_config());
+        run(&mut buffered);
+
+        let sink = VecSink::new();
+        let mut streamed = Machine::new(DefaultConfig::default_config());
+        streamed.set_trace_sink(Box::new(sink.clone()));
+        run(&mut streamed);
+        assert_eq!(streamed.trace_len(), 0);
+        assert_eq!(sink.records(), buffered.trace());
+    }
+
+    #[test]
+    fn test_hashing_sink() {
+        let digest = |extra: bool| {
+            let sink = HashingSink::new();
+            let mut sm = Machine::new(DefaultConfig::default_config());
+            sm.set_trace_sink(Box::new(sink.clone()));
+            run(&mut sm);
+            if extra {
+                sm.read(sm.base_address()).unwrap();
+            }
+            (sink.len(), sink.digest())
+        };
+        let (len, first) = digest(false);
+        assert_eq!(len, 7);
+        assert_eq!(digest(false).1, first);
+        assert_ne!(digest(true).1, first);
+        assert!(HashingSink::new().is_empty());
+
+        // A detached copy no longer shares the state of the sink
+        let sink = HashingSink::new();
+        let detached = sink.detached();
+        let record = TraceRecord::<B256, B256, 32, 32>::new(
+            0,
+            0,
+            crate::machine::MemoryInstruction::Write,
+            B256::zero(),
+            B256::from(1),
+        );
+        TraceSink::push(&mut sink.clone(), record).unwrap();
+        assert_eq!(sink.len(), 1);
+        assert!(detached.is_empty());
+    }
+
+    #[test]
+    fn test_rejected_record() {
+        let mut sm = Machine::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        sm.set_trace_sink(Box::new(Rejecting));
+        assert_eq!(sm.write(base, B256::from(1)).unwrap_err(), Error::TraceIo);
+        assert_eq!(sm.dummy_read(base), B256::zero());
+
+        // The rejected access did not use the time log
+        let sink = VecSink::new();
+        sm.set_trace_sink(Box::new(sink.clone()));
+        sm.write(base, B256::from(1)).unwrap();
+        assert_eq!(sink.records()[0].time_log, 0);
+    }
+
+    // Sink that takes the records of an access only if they all fit
+    #[derive(Debug)]
+    struct Bounded(VecSink<B256, B256, 32, 32>, usize);
+
+    impl TraceSink<B256, B256, 32, 32> for Bounded {
+        fn push(&mut self, record: TraceRecord<B256, B256, 32, 32>) -> Result<(), Error> {
+            self.push_all(&[record])
+        }
+
+        fn push_all(&mut self, records: &[TraceRecord<B256, B256, 32, 32>]) -> Result<(), Error> {
+            if self.0.records().len() + records.len() > self.1 {
+                return Err(Error::TraceIo);
+            }
+            records.iter().try_for_each(|record| self.0.push(*record))
+        }
+    }
+
+    #[test]
+    fn test_unaligned_access_is_recorded_whole() {
+        let mut sm = Machine::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        let sink = VecSink::new();
+        sm.set_trace_sink(Box::new(Bounded(sink.clone(), 2)));
+        sm.write(base, B256::from(1)).unwrap();
+
+        // The second cell does not fit, so the first one is not recorded either
+        let unaligned = base + B256::from(40);
+        assert_eq!(
+            sm.write(unaligned, B256::from(2)).unwrap_err(),
+            Error::TraceIo
+        );
+        assert_eq!(sm.read(unaligned).unwrap_err(), Error::TraceIo);
+        assert_eq!(sink.records().len(), 1);
+        assert_eq!(sm.dummy_read(base + B256::from(32)), B256::zero());
+        assert_eq!(sm.dummy_read(base + B256::from(64)), B256::zero());
+
+        // The time log was not used by the rejected accesses
+        sm.write(base, B256::from(3)).unwrap();
+        assert_eq!(sink.records()[1].time_log, 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_file_sink() {
+        use super::CodecSink;
+        use crate::config::FingerprintCheck;
+        use crate::machine::trace_codec::{decode_trace, TraceReader};
+        use std::{fs, fs::File, vec::Vec};
+
+        let path =
+            std::env::temp_dir().join(std::format!("zkmemory-sink-{}.bin", std::process::id()));
+        let mut streamed = Machine::new(DefaultConfig::default_config());
+        let fingerprint = streamed.config_fingerprint();
+        let sink = CodecSink::new(
+            File::create(&path).unwrap(),
+            Endianness::Little,
+            fingerprint,
+        )
+        .unwrap();
+        streamed.set_trace_sink(Box::new(sink.clone()));
+        run(&mut streamed);
+        sink.finish().unwrap();
+        assert_eq!(sink.len(), 7);
+        assert_eq!(streamed.trace_len(), 0);
+
+        let vec_sink = VecSink::new();
+        let mut buffered = Machine::new(DefaultConfig::default_config());
+        buffered.set_trace_sink(Box::new(vec_sink.clone()));
+        run(&mut buffered);
+
+        let check = FingerprintCheck::Expect(fingerprint);
+        let reader =
+            TraceReader::<_, B256, B256, 32, 32>::new(File::open(&path).unwrap(), check).unwrap();
+        assert_eq!(reader.remaining(), 7);
+        let read: Vec<_> = reader.map(Result::unwrap).collect();
+        assert_eq!(read, vec_sink.records());
+        assert_eq!(
+            decode_trace::<B256, B256, 32, 32>(&fs::read(&path).unwrap(), check).unwrap(),
+            vec_sink.records()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
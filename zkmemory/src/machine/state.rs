@@ -0,0 +1,211 @@
+//! Runtime state of a machine built from a [Config].
+//! A machine keeping a [MachineState] gets the hooks of
+//! [crate::machine::AbstractMemoryMachine] from its config with
+//! `impl_state_machine!(Machine, state)`, where `state` is the field holding the state:
+//! the policies, the memory layout, the limits, the gas and the commitment backend of the
+//! config apply to it, it counts the trace records, the bytes written and the gas used, it
+//! keeps the first writes to the write-once cells and the devices mapped with
+//! [MachineState::register_mmio].
+//! The memory section is kept in the state so it can grow, the machine must return
+//! [MachineState::memory_section] from [crate::machine::AbstractMachine::get_memory_address]
+extern crate alloc;
+use crate::{
+    base::Base,
+    commitment::{
+        merkle::MerkleRoot,
+        selector::{CommitmentSink, StateCommitter},
+    },
+    config::{AllocatedSection, Config, MemoryLayout, MemoryRegion, RegionKind, TraceMode},
+    error::Error,
+    machine::{mmio::MmioHandler, FirstWrites},
+};
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use core::{cell::OnceCell, marker::PhantomData};
+
+/// Runtime state of a machine built from a [Config]
+#[derive(Debug)]
+pub struct MachineState<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    config: Config<K, S>,
+    config_fingerprint: [u8; 32],
+    // Memory section, its end moves when the memory grows
+    memory: AllocatedSection<K>,
+    bytes_written: u64,
+    gas_used: u64,
+    first_writes: FirstWrites<K>,
+    record_counter: u64,
+    initial_commitment: Option<MerkleRoot>,
+    // Sink of the records in [TraceMode::CommitmentsOnly], sharing the backend of the machine
+    trace_hasher: Option<CommitmentSink>,
+    committer: Rc<OnceCell<StateCommitter>>,
+    // Memory-mapped I/O regions and the devices mapped to them
+    mmio_layout: Option<MemoryLayout<K>>,
+    mmio: Vec<(RegionKind, Box<dyn MmioHandler<K, V>>)>,
+    marker: PhantomData<V>,
+}
+
+// A clone counts from the same state, its trace hasher does not share its state with the
+// hasher of the original machine and the devices are not cloned
+impl<K, V, const S: usize, const T: usize> Clone for MachineState<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            config: self.config,
+            config_fingerprint: self.config_fingerprint,
+            memory: self.memory,
+            bytes_written: self.bytes_written,
+            gas_used: self.gas_used,
+            first_writes: self.first_writes.clone(),
+            record_counter: self.record_counter,
+            initial_commitment: self.initial_commitment,
+            trace_hasher: self.trace_hasher.as_ref().map(CommitmentSink::detached),
+            committer: self.committer.clone(),
+            mmio_layout: None,
+            mmio: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> MachineState<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Create the state of a machine that has not run yet
+    pub fn new(config: Config<K, S>) -> Self {
+        let committer = Rc::new(OnceCell::new());
+        Self {
+            config,
+            config_fingerprint: config.config_fingerprint(),
+            memory: config.memory,
+            bytes_written: 0,
+            gas_used: 0,
+            first_writes: FirstWrites::new(),
+            record_counter: 0,
+            initial_commitment: None,
+            trace_hasher: (config.trace_mode == TraceMode::CommitmentsOnly)
+                .then(|| CommitmentSink::with_committer(config.commitment, committer.clone())),
+            committer,
+            mmio_layout: None,
+            mmio: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Get the config the machine was built from
+    pub fn config(&self) -> &Config<K, S> {
+        &self.config
+    }
+
+    /// Get the fingerprint of the config, see [Config::config_fingerprint]
+    pub fn config_fingerprint(&self) -> [u8; 32] {
+        self.config_fingerprint
+    }
+
+    /// Get the current memory section, the section of the config until the memory grows
+    pub fn memory_section(&self) -> AllocatedSection<K> {
+        self.memory
+    }
+
+    /// Move the end of the memory section
+    pub fn set_memory_end(&mut self, high: K) {
+        self.memory = AllocatedSection::new(self.memory.low(), high);
+    }
+
+    /// Get the number of bytes written since the machine started
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Count the bytes of a write
+    pub fn add_bytes_written(&mut self, bytes: u64) {
+        self.bytes_written = self.bytes_written.saturating_add(bytes);
+    }
+
+    /// Get the gas used since the machine started
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    /// Set the gas used since the machine started
+    pub fn set_gas_used(&mut self, gas_used: u64) {
+        self.gas_used = gas_used;
+    }
+
+    /// Get the time logs of the first writes to the cells of the write-once regions
+    pub fn first_writes(&self) -> &FirstWrites<K> {
+        &self.first_writes
+    }
+
+    /// Get the time logs of the first writes to update them
+    pub fn first_writes_mut(&mut self) -> &mut FirstWrites<K> {
+        &mut self.first_writes
+    }
+
+    /// Get the number of records kept in the trace of the machine
+    pub fn record_counter(&self) -> u64 {
+        self.record_counter
+    }
+
+    /// Get the number of records kept in the trace to update it
+    pub fn record_counter_mut(&mut self) -> &mut u64 {
+        &mut self.record_counter
+    }
+
+    /// Get the commitment to the initial memory image, [None] if it was not installed
+    pub fn initial_commitment(&self) -> Option<MerkleRoot> {
+        self.initial_commitment
+    }
+
+    /// Keep the commitment to the initial memory image
+    pub fn set_initial_commitment(&mut self, root: MerkleRoot) {
+        self.initial_commitment = Some(root);
+    }
+
+    /// Get the sink the records are committed into in [TraceMode::CommitmentsOnly]
+    pub fn trace_hasher(&self) -> Option<&CommitmentSink> {
+        self.trace_hasher.as_ref()
+    }
+
+    /// Get the cell keeping the commitment backend of the config once it is instantiated
+    pub fn committer_cache(&self) -> &OnceCell<StateCommitter> {
+        &self.committer
+    }
+
+    /// Map the device to the region, the region must not overlap another memory-mapped
+    /// I/O region nor share its kind
+    pub fn register_mmio(
+        &mut self,
+        region: MemoryRegion<K>,
+        handler: Box<dyn MmioHandler<K, V>>,
+    ) -> Result<(), Error> {
+        let mut regions = self
+            .mmio_layout
+            .map(|layout| layout.all_regions())
+            .unwrap_or_default();
+        regions.push(region);
+        self.mmio_layout = Some(MemoryLayout::new(&regions)?);
+        self.mmio.push((region.kind, handler));
+        Ok(())
+    }
+
+    /// Get the memory-mapped I/O regions, [None] if no device is mapped
+    pub fn mmio_layout(&self) -> Option<MemoryLayout<K>> {
+        self.mmio_layout
+    }
+
+    /// Get the device mapped to the memory-mapped I/O region of the given kind
+    pub fn mmio_handler(&mut self, kind: RegionKind) -> Option<&mut dyn MmioHandler<K, V>> {
+        match self.mmio.iter_mut().find(|(mapped, _)| *mapped == kind) {
+            Some((_, handler)) => Some(handler.as_mut()),
+            None => None,
+        }
+    }
+}
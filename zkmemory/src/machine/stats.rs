@@ -0,0 +1,192 @@
+//! Access pattern statistics of the execution trace, used to choose the circuit parameters.
+extern crate alloc;
+use crate::{
+    base::Base,
+    machine::{AbstractTraceRecord, TraceRecord},
+};
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{
+    cmp::Reverse,
+    fmt::{Display, Formatter},
+};
+
+/// Number of addresses kept in [TraceStats::hottest] by [TraceStats::compute]
+pub const DEFAULT_HOTTEST_ADDRESSES: usize = 8;
+
+/// Statistics of the accesses of a trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStats<K> {
+    /// Number of records
+    pub len: usize,
+    /// Number of distinct accessed cells
+    pub distinct_addresses: usize,
+    /// Number of reads, pops included
+    pub reads: usize,
+    /// Number of writes, pushes included
+    pub writes: usize,
+    /// Length of the longest run of consecutive records on the same cell
+    pub longest_run: usize,
+    /// Most accessed cells with their number of accesses, the most accessed first
+    /// and the lowest address first on ties
+    pub hottest: Vec<(K, usize)>,
+}
+
+impl<K: Copy + Ord> TraceStats<K> {
+    /// Compute the statistics of the trace in time order,
+    /// keep the [DEFAULT_HOTTEST_ADDRESSES] most accessed cells
+    pub fn compute<V, const S: usize, const T: usize>(trace: &[TraceRecord<K, V, S, T>]) -> Self
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        Self::compute_top(trace, DEFAULT_HOTTEST_ADDRESSES)
+    }
+
+    /// Compute the statistics of the trace in time order, keep the `top` most accessed cells
+    pub fn compute_top<V, const S: usize, const T: usize>(
+        trace: &[TraceRecord<K, V, S, T>],
+        top: usize,
+    ) -> Self
+    where
+        K: Base<S>,
+        V: Base<T>,
+    {
+        let mut accesses: BTreeMap<K, usize> = BTreeMap::new();
+        let mut writes = 0;
+        let mut longest_run = 0;
+        let mut run = 0;
+        let mut previous = None;
+        for record in trace {
+            *accesses.entry(record.address()).or_default() += 1;
+            if record.instruction().is_write() {
+                writes += 1;
+            }
+            run = if previous == Some(record.address()) {
+                run + 1
+            } else {
+                1
+            };
+            longest_run = longest_run.max(run);
+            previous = Some(record.address());
+        }
+        let distinct_addresses = accesses.len();
+        let mut hottest: Vec<(K, usize)> = accesses.into_iter().collect();
+        // The sort is stable, the addresses with the same count stay in address order
+        hottest.sort_by_key(|(_, count)| Reverse(*count));
+        hottest.truncate(top);
+        Self {
+            len: trace.len(),
+            distinct_addresses,
+            reads: trace.len() - writes,
+            writes,
+            longest_run,
+            hottest,
+        }
+    }
+
+    /// Get the number of reads per write, [None] if the trace has no write
+    pub fn read_write_ratio(&self) -> Option<f64> {
+        if self.writes == 0 {
+            return None;
+        }
+        Some(self.reads as f64 / self.writes as f64)
+    }
+}
+
+impl<K: Display> Display for TraceStats<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "{:<20}{}", "records", self.len)?;
+        writeln!(f, "{:<20}{}", "distinct addresses", self.distinct_addresses)?;
+        writeln!(
+            f,
+            "{:<20}{} / {}",
+            "reads / writes", self.reads, self.writes
+        )?;
+        write!(f, "{:<20}{}", "longest run", self.longest_run)?;
+        for (address, count) in self.hottest.iter() {
+            write!(f, "\n  {:<18}{}", address, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TraceStats;
+    use crate::{
+        base::B64,
+        machine::{AbstractTraceRecord, MemoryInstruction, TraceRecord},
+    };
+    extern crate alloc;
+    use alloc::{format, vec, vec::Vec};
+
+    // Synthetic trace: 0x10 is written then read 3 times in a row, 0x20 and 0x30 are
+    // written, 0x20 is read back and 0x10 is overwritten
+    fn synthetic_trace() -> Vec<TraceRecord<B64, B64, 8, 8>> {
+        let accesses = [
+            (MemoryInstruction::Write, 0x10),
+            (MemoryInstruction::Read, 0x10),
+            (MemoryInstruction::Read, 0x10),
+            (MemoryInstruction::Read, 0x10),
+            (MemoryInstruction::Write, 0x20),
+            (MemoryInstruction::Push, 0x30),
+            (MemoryInstruction::Pop, 0x30),
+            (MemoryInstruction::Read, 0x20),
+            (MemoryInstruction::Write, 0x10),
+        ];
+        accesses
+            .iter()
+            .enumerate()
+            .map(|(time_log, (instruction, address))| {
+                TraceRecord::new(
+                    time_log as u64,
+                    0,
+                    *instruction,
+                    B64::from(*address as u64),
+                    B64::from(1),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_trace_stats() {
+        let trace = synthetic_trace();
+        let stats = TraceStats::compute(&trace);
+        assert_eq!(
+            stats,
+            TraceStats {
+                len: 9,
+                distinct_addresses: 3,
+                reads: 5,
+                writes: 4,
+                longest_run: 4,
+                hottest: vec![
+                    (B64::from(0x10), 5),
+                    (B64::from(0x20), 2),
+                    (B64::from(0x30), 2),
+                ],
+            }
+        );
+        assert_eq!(stats.read_write_ratio(), Some(1.25));
+        assert_eq!(
+            TraceStats::compute_top(&trace, 1).hottest,
+            vec![(B64::from(0x10), 5)]
+        );
+        assert_eq!(
+            format!("{}", stats),
+            "records             9\n\
+             distinct addresses  3\n\
+             reads / writes      5 / 4\n\
+             longest run         4\n  \
+             16                5\n  \
+             32                2\n  \
+             48                2"
+        );
+
+        let empty = TraceStats::<B64>::compute::<B64, 8, 8>(&[]);
+        assert_eq!((empty.len, empty.longest_run), (0, 0));
+        assert!(empty.hottest.is_empty());
+        assert_eq!(empty.read_write_ratio(), None);
+    }
+}
@@ -0,0 +1,417 @@
+//! Key-value "account storage" built on top of the RAM machine.
+//! Every storage key is bound to a dedicated memory cell the first time it is stored,
+//! so `sload`/`sstore` are translated into ordinary read/write trace records and the
+//! storage consistency is proven by the existing memory circuits.
+//! A key that was never stored is not bound to a cell, its load reads the zero cell at the
+//! start of the section which is never bound to a key. The final storage is committed by the
+//! root of [StorageMap::storage_tree] which proves the values and the absent keys.
+extern crate alloc;
+use crate::{
+    base::Base,
+    commitment::{
+        merkle::{HashKind, MerkleRoot},
+        sparse::SparseMerkleTree,
+    },
+    config::AllocatedSection,
+    error::Error,
+    machine::{
+        backend::MemoryBackend, pointer_add, AbstractContext, AbstractMachine,
+        AbstractMemoryMachine, CellInteraction,
+    },
+};
+use alloc::vec::Vec;
+use rbtree::RBTree;
+
+/// Access kind of a storage slot, used by the cost model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageAccess {
+    /// The slot was not accessed before in the current access list
+    Cold,
+    /// The slot was already accessed in the current access list
+    Warm,
+}
+
+/// Storage map from 256-bit keys to values, backed by a section of the machine's memory
+#[derive(Debug, Clone)]
+pub struct StorageMap<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    // Memory section dedicated to the storage slots
+    section: AllocatedSection<K>,
+    // Key to cell address binding, slots are allocated in order of first store
+    slots: RBTree<V, K>,
+    // Whether the zero cell was written, it is written with zero before its first read
+    zero_written: bool,
+    // Next free cell in the section, none once the address space is exhausted
+    next_slot: Option<K>,
+    // Keys accessed since the last reset of the access list
+    accessed: RBTree<V, ()>,
+}
+
+impl<K, V, const S: usize, const T: usize> StorageMap<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Create a new storage map over the given memory section, its first cell is the zero cell
+    pub fn new(section: AllocatedSection<K>) -> Self {
+        Self {
+            section,
+            slots: RBTree::new(),
+            zero_written: false,
+            next_slot: pointer_add(section.low(), K::WORD_SIZE).ok(),
+            accessed: RBTree::new(),
+        }
+    }
+
+    /// Load the value of a storage key, unset keys are read as zero.
+    /// The load of a key that was never stored reads the zero cell, so every load is a read
+    /// record of the trace. The zero cell is written with zero before its first read, its
+    /// load is proven whatever the read policy of the machine
+    pub fn sload<M>(&mut self, machine: &mut M, key: V) -> Result<(V, StorageAccess), Error>
+    where
+        M: AbstractMemoryMachine<K, V, S, T>,
+    {
+        let address = match self.address_of(key) {
+            Some(address) => address,
+            None => {
+                if !self.zero_written {
+                    machine.write(self.zero_cell(), V::zero())?;
+                    self.zero_written = true;
+                }
+                self.zero_cell()
+            }
+        };
+        let access = self.touch(key);
+        match machine.read(address)? {
+            CellInteraction::SingleCell(_, _, value) => Ok((value, access)),
+            _ => Err(Error::MemoryInvalidInteraction),
+        }
+    }
+
+    /// Store a value to a storage key
+    pub fn sstore<M>(&mut self, machine: &mut M, key: V, value: V) -> Result<StorageAccess, Error>
+    where
+        M: AbstractMemoryMachine<K, V, S, T>,
+    {
+        let address = self.slot(key)?;
+        let access = self.touch(key);
+        match machine.write(address, value)? {
+            CellInteraction::SingleCell(_, _, _) => Ok(access),
+            _ => Err(Error::MemoryInvalidInteraction),
+        }
+    }
+
+    /// Get the cell address bound to a storage key, if the key was ever stored
+    pub fn address_of(&self, key: V) -> Option<K> {
+        self.slots.get(&key).copied()
+    }
+
+    /// Get the address of the zero cell read by the loads of the keys that were never stored
+    pub fn zero_cell(&self) -> K {
+        self.section.low()
+    }
+
+    /// Get the number of bound storage keys
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Check if no storage key was bound yet
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Clear the access list, every key will be cold on its next access
+    pub fn reset_access_list(&mut self) {
+        self.accessed = RBTree::new();
+    }
+
+    /// Get the final storage entries sorted by key, the values are taken from the memory
+    /// without recording a read
+    pub fn entries<M>(&self, machine: &M) -> Vec<(V, V)>
+    where
+        M: AbstractMemoryMachine<K, V, S, T>,
+    {
+        let memory = machine.ro_context().ro_memory();
+        self.slots
+            .iter()
+            .map(|(key, address)| (*key, memory.get(address).copied().unwrap_or(V::zero())))
+            .collect()
+    }
+
+    /// Get the sparse Merkle tree of the final storage keyed by storage key, the keys that hold
+    /// zero are absent from the tree
+    pub fn storage_tree<M>(&self, machine: &M, hash: HashKind) -> SparseMerkleTree<V, V, T, T>
+    where
+        M: AbstractMemoryMachine<K, V, S, T>,
+    {
+        let mut tree = SparseMerkleTree::new(hash);
+        for (key, value) in self.entries(machine) {
+            tree.update(key, value);
+        }
+        tree
+    }
+
+    /// Get the root of the final storage, see [StorageMap::storage_tree]
+    pub fn storage_root<M>(&self, machine: &M, hash: HashKind) -> MerkleRoot
+    where
+        M: AbstractMemoryMachine<K, V, S, T>,
+    {
+        self.storage_tree(machine, hash).root()
+    }
+
+    // Bind a storage key to a cell, allocating a new cell on the first access
+    fn slot(&mut self, key: V) -> Result<K, Error> {
+        if let Some(address) = self.slots.get(&key) {
+            return Ok(*address);
+        }
+        let address = match self.next_slot {
+            Some(address) if self.section.contain(address) => address,
+            _ => return Err(Error::StorageExhausted),
+        };
+        self.slots.insert(key, address);
+        self.next_slot = pointer_add(address, K::WORD_SIZE).ok();
+        Ok(address)
+    }
+
+    // Mark a key as accessed and return its access kind
+    fn touch(&mut self, key: V) -> StorageAccess {
+        if self.accessed.get(&key).is_some() {
+            StorageAccess::Warm
+        } else {
+            self.accessed.insert(key, ());
+            StorageAccess::Cold
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StorageAccess, StorageMap};
+    use crate::{
+        base::{Base, B256},
+        commitment::{merkle::HashKind, sparse::SparseMerkleTree},
+        config::{AllocatedSection, ConfigBuilder, DefaultConfig, ReadPolicy},
+        constraints::helper::{build_and_test_circuit, minimal_k},
+        error::Error,
+        machine::{tests::StateMachine, AbstractMachine, AbstractTraceRecord, MemoryInstruction},
+    };
+    extern crate alloc;
+    use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+    type Machine = StateMachine<B256, B256, 32, 32>;
+
+    fn new_storage(machine: &Machine, cells: usize) -> StorageMap<B256, B256, 32, 32> {
+        let (low, _) = machine.get_memory_address();
+        StorageMap::new(AllocatedSection::new(
+            low,
+            low + B256::from(cells - 1) * B256::WORD_SIZE,
+        ))
+    }
+
+    #[test]
+    fn test_sload_sstore() {
+        let mut sm = Machine::new(DefaultConfig::default_config());
+        let mut storage = new_storage(&sm, 16);
+        let key_a = B256::from([0xaau8; 32]);
+        let key_b = B256::from([0xbbu8; 32]);
+
+        // SSTORE a, SSTORE b, SLOAD a, SSTORE a, SLOAD a, SLOAD b
+        storage.sstore(&mut sm, key_a, B256::from(1)).unwrap();
+        storage.sstore(&mut sm, key_b, B256::from(2)).unwrap();
+        assert_eq!(storage.sload(&mut sm, key_a).unwrap().0, B256::from(1));
+        storage.sstore(&mut sm, key_a, B256::from(3)).unwrap();
+        assert_eq!(storage.sload(&mut sm, key_a).unwrap().0, B256::from(3));
+        assert_eq!(storage.sload(&mut sm, key_b).unwrap().0, B256::from(2));
+
+        // Unset keys are read from the zero cell without binding them to a cell
+        assert_eq!(
+            storage.sload(&mut sm, B256::from(7)).unwrap().0,
+            B256::zero()
+        );
+        assert_eq!(
+            storage.sload(&mut sm, B256::from(8)).unwrap().0,
+            B256::zero()
+        );
+        assert_eq!(storage.address_of(B256::from(7)), None);
+        assert_eq!(storage.len(), 2);
+
+        // Every storage access is an ordinary trace record, the zero cell is written once
+        let trace = sm.trace();
+        assert_eq!(trace.len(), 9);
+        let instructions: Vec<MemoryInstruction> =
+            trace.iter().map(|record| record.instruction()).collect();
+        assert_eq!(
+            instructions,
+            vec![
+                MemoryInstruction::Write,
+                MemoryInstruction::Write,
+                MemoryInstruction::Read,
+                MemoryInstruction::Write,
+                MemoryInstruction::Read,
+                MemoryInstruction::Read,
+                MemoryInstruction::Write,
+                MemoryInstruction::Read,
+                MemoryInstruction::Read,
+            ]
+        );
+        for record in &trace[6..] {
+            assert_eq!(record.address(), storage.zero_cell());
+            assert_eq!(record.value(), B256::zero());
+        }
+        assert_eq!(
+            trace[0].address(),
+            storage.address_of(key_a).expect("Key a must be bound")
+        );
+        assert_eq!(
+            trace[1].address(),
+            storage.address_of(key_b).expect("Key b must be bound")
+        );
+        build_and_test_circuit(trace.iter().copied(), minimal_k(trace.len()));
+    }
+
+    #[test]
+    fn test_unset_load_with_strict_read_policy() {
+        let mut sm = Machine::from_config(
+            ConfigBuilder::new()
+                .read_policy(ReadPolicy::ErrorOnUninit)
+                .build()
+                .unwrap(),
+        );
+        let mut storage = new_storage(&sm, 16);
+        assert_eq!(
+            storage.sload(&mut sm, B256::from(7)).unwrap(),
+            (B256::zero(), StorageAccess::Cold)
+        );
+        assert_eq!(sm.trace().len(), 2);
+    }
+
+    #[test]
+    fn test_cold_warm_access() {
+        let mut sm = Machine::new(DefaultConfig::default_config());
+        let mut storage = new_storage(&sm, 16);
+        let key = B256::from(42);
+
+        assert_eq!(storage.sload(&mut sm, key).unwrap().1, StorageAccess::Cold);
+        assert_eq!(
+            storage.sstore(&mut sm, key, B256::from(1)).unwrap(),
+            StorageAccess::Warm
+        );
+        assert_eq!(storage.sload(&mut sm, key).unwrap().1, StorageAccess::Warm);
+
+        // A new transaction starts with a cold access list
+        storage.reset_access_list();
+        assert_eq!(
+            storage.sstore(&mut sm, key, B256::from(2)).unwrap(),
+            StorageAccess::Cold
+        );
+    }
+
+    #[test]
+    fn test_storage_exhausted() {
+        let mut sm = Machine::new(DefaultConfig::default_config());
+        // The first cell of the section is the zero cell
+        let mut storage = new_storage(&sm, 3);
+        storage
+            .sstore(&mut sm, B256::from(1), B256::from(1))
+            .unwrap();
+        storage
+            .sstore(&mut sm, B256::from(2), B256::from(2))
+            .unwrap();
+        // Rebinding an existing key does not need a new slot
+        storage
+            .sstore(&mut sm, B256::from(1), B256::from(3))
+            .unwrap();
+        assert_eq!(
+            storage.sstore(&mut sm, B256::from(3), B256::from(3)),
+            Err(Error::StorageExhausted)
+        );
+    }
+
+    #[test]
+    fn test_entries_agree_with_reference_map() {
+        let mut sm = Machine::new(DefaultConfig::default_config());
+        let mut storage = new_storage(&sm, 64);
+        let mut reference = BTreeMap::new();
+
+        for i in 0..200u64 {
+            let key = B256::from((i * 7919) % 37);
+            let value = B256::from(i * 3 + 1);
+            if i % 3 == 0 {
+                let (loaded, _) = storage.sload(&mut sm, key).unwrap();
+                assert_eq!(loaded, *reference.get(&key).unwrap_or(&B256::zero()));
+            } else {
+                storage.sstore(&mut sm, key, value).unwrap();
+                reference.insert(key, value);
+            }
+        }
+
+        let entries: Vec<(B256, B256)> = storage
+            .entries(&sm)
+            .into_iter()
+            .filter(|(_, value)| !value.is_zero())
+            .collect();
+        let expected: Vec<(B256, B256)> = reference.into_iter().collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[test]
+    fn test_storage_at_the_top_of_memory() {
+        let mut sm = Machine::new(DefaultConfig::default_config());
+        let mut storage =
+            StorageMap::new(AllocatedSection::new(B256::MAX - B256::from(95), B256::MAX));
+        storage
+            .sstore(&mut sm, B256::from(1), B256::from(1))
+            .unwrap();
+        storage
+            .sstore(&mut sm, B256::from(2), B256::from(2))
+            .unwrap();
+        assert_eq!(
+            storage.sstore(&mut sm, B256::from(3), B256::from(3)),
+            Err(Error::StorageExhausted)
+        );
+    }
+
+    #[test]
+    fn test_storage_root_matches_accesses() {
+        let mut sm = Machine::new(DefaultConfig::default_config());
+        let mut storage = new_storage(&sm, 64);
+        let mut reference = SparseMerkleTree::<B256, B256, 32, 32>::new(HashKind::Blake2b);
+        let mut stored = BTreeMap::new();
+
+        for i in 0..100u64 {
+            let key = B256::from((i * 31) % 23);
+            if i % 4 == 0 {
+                let (loaded, _) = storage.sload(&mut sm, key).unwrap();
+                assert_eq!(loaded, reference.get(key));
+            } else {
+                // Storing zero removes the key from the tree
+                let value = B256::from(i % 5);
+                storage.sstore(&mut sm, key, value).unwrap();
+                reference.update(key, value);
+                stored.insert(key, value);
+            }
+        }
+        let root = storage.storage_root(&sm, HashKind::Blake2b);
+        assert_eq!(root, reference.root());
+
+        // Every key is proven against the root, present or not
+        let tree = storage.storage_tree(&sm, HashKind::Blake2b);
+        for key in (0..30u64).map(B256::from) {
+            match tree.get_with_proof(key) {
+                Some((value, proof)) => {
+                    assert_eq!(proof.verify_inclusion(&root, key, value), Ok(()))
+                }
+                None => assert_eq!(
+                    tree.prove_absence(key).unwrap().verify_absence(&root, key),
+                    Ok(())
+                ),
+            }
+        }
+        // Only the stored keys are bound to a cell
+        assert_eq!(storage.len(), stored.len());
+    }
+}
@@ -0,0 +1,521 @@
+//! Stable binary format of the execution trace, used to hand traces over to a remote prover.
+//!
+//! | Field         | Size                                     | Description                               |
+//! |---------------|------------------------------------------|-------------------------------------------|
+//! | magic         | 8                                        | [TRACE_MAGIC]                             |
+//! | major version | 1                                        | Must be equal to [TRACE_VERSION_MAJOR]    |
+//! | minor version | 1                                        | Any value, minor versions keep the layout |
+//! | address size  | 1                                        | Size of the address word in bytes         |
+//! | value size    | 1                                        | Size of the value word in bytes           |
+//! | byte order    | 1                                        | [Endianness::code] of the words           |
+//! | fingerprint   | 32                                       | Fingerprint of the config of the machine  |
+//! | record count  | 8                                        | Little endian                             |
+//! | records       | count * (19 + address size + value size) | Fixed width records                       |
+//!
+//! Every record is the little endian time log and stack depth, the instruction code, the
+//! source tag, the cell size and the address and value in the byte order of the header.
+//! The fingerprint is the one of [crate::config::Config::config_fingerprint], a decoder
+//! rejects a trace made under another config unless it is forced with
+//! [FingerprintCheck::Force]. The cell size is 0 for a cell of the full word and smaller than
+//! the value size otherwise. The decoded records hold the same words whatever the byte order.
+//! With the `std` feature, [TraceReader] decodes the records one at a time from a reader,
+//! e.g. a file written by [crate::machine::sink::CodecSink].
+extern crate alloc;
+use crate::{
+    base::Base,
+    config::{Endianness, FingerprintCheck},
+    error::Error,
+    machine::{AbstractTraceRecord, MemoryInstruction, TraceRecord},
+};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+/// Magic bytes at the start of every encoded trace
+pub const TRACE_MAGIC: [u8; 8] = *b"ZKMTRACE";
+
+/// Major version of the format, a decoder rejects any other major version
+pub const TRACE_VERSION_MAJOR: u8 = 1;
+
+/// Minor version of the format, written by the encoder and ignored by the decoder
+pub const TRACE_VERSION_MINOR: u8 = 0;
+
+// Size of the header in bytes
+pub(crate) const HEADER_SIZE: usize = 53;
+
+// Offset of the byte order in the header
+const ENDIANNESS_OFFSET: usize = 12;
+
+// Offset of the config fingerprint in the header
+const FINGERPRINT_OFFSET: usize = 13;
+
+// Offset of the record count in the header
+pub(crate) const COUNT_OFFSET: usize = 45;
+
+// Size of the time log, stack depth, instruction, source and cell size of a record
+const RECORD_FIXED_SIZE: usize = 19;
+
+/// Encode the trace records into the binary format, the addresses and values are written in
+/// the byte order, usually [crate::machine::AbstractMemoryMachine::endianness], and the
+/// fingerprint is the one of the config of the machine, see
+/// [crate::machine::AbstractMemoryMachine::config_fingerprint]
+pub fn encode_trace<K, V, const S: usize, const T: usize>(
+    trace: &[TraceRecord<K, V, S, T>],
+    endianness: Endianness,
+    fingerprint: [u8; 32],
+) -> Vec<u8>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + trace.len() * (RECORD_FIXED_SIZE + S + T));
+    bytes.extend_from_slice(&encode_header::<S, T>(
+        trace.len() as u64,
+        endianness,
+        fingerprint,
+    ));
+    for record in trace {
+        encode_record(record, endianness, &mut bytes);
+    }
+    bytes
+}
+
+// Encode the header of a trace of `count` records in the byte order for the config fingerprint
+pub(crate) fn encode_header<const S: usize, const T: usize>(
+    count: u64,
+    endianness: Endianness,
+    fingerprint: [u8; 32],
+) -> [u8; HEADER_SIZE] {
+    let mut header = [0u8; HEADER_SIZE];
+    header[..8].copy_from_slice(&TRACE_MAGIC);
+    header[8..FINGERPRINT_OFFSET].copy_from_slice(&[
+        TRACE_VERSION_MAJOR,
+        TRACE_VERSION_MINOR,
+        S as u8,
+        T as u8,
+        endianness.code(),
+    ]);
+    header[FINGERPRINT_OFFSET..COUNT_OFFSET].copy_from_slice(&fingerprint);
+    header[COUNT_OFFSET..].copy_from_slice(&count.to_le_bytes());
+    header
+}
+
+// Append the fixed width encoding of the record in the byte order to the bytes
+pub(crate) fn encode_record<K, V, const S: usize, const T: usize>(
+    record: &TraceRecord<K, V, S, T>,
+    endianness: Endianness,
+    bytes: &mut Vec<u8>,
+) where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let address: [u8; S] = endianness.to_bytes(record.address());
+    let value: [u8; T] = endianness.to_bytes(record.value());
+    bytes.extend_from_slice(&record.time_log().to_le_bytes());
+    bytes.extend_from_slice(&record.stack_depth().to_le_bytes());
+    bytes.push(record.instruction().code() as u8);
+    bytes.push(record.source());
+    bytes.push(record.cell_size());
+    bytes.extend_from_slice(&address);
+    bytes.extend_from_slice(&value);
+}
+
+// Check the header for the expected word sizes and config fingerprint,
+// return the record count and the byte order
+fn decode_header<const S: usize, const T: usize>(
+    header: &[u8],
+    check: FingerprintCheck,
+) -> Result<(u64, Endianness), Error> {
+    if header[..8] != TRACE_MAGIC {
+        return Err(Error::InvalidTraceMagic);
+    }
+    if header[8] != TRACE_VERSION_MAJOR {
+        return Err(Error::UnsupportedTraceVersion);
+    }
+    if header[10] as usize != S || header[11] as usize != T {
+        return Err(Error::TraceWordSizeMismatch);
+    }
+    let endianness =
+        Endianness::from_code(header[ENDIANNESS_OFFSET]).ok_or(Error::InvalidTraceEncoding)?;
+    check.check(read_array(&header[FINGERPRINT_OFFSET..]))?;
+    Ok((
+        u64::from_le_bytes(read_array(&header[COUNT_OFFSET..])),
+        endianness,
+    ))
+}
+
+// Decode a record of exactly `RECORD_FIXED_SIZE + S + T` bytes in the byte order
+fn decode_record<K, V, const S: usize, const T: usize>(
+    record: &[u8],
+    endianness: Endianness,
+) -> Result<TraceRecord<K, V, S, T>, Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let instruction =
+        MemoryInstruction::from_code(record[16] as u64).ok_or(Error::InvalidTraceEncoding)?;
+    if record[18] as usize >= T {
+        return Err(Error::InvalidTraceEncoding);
+    }
+    Ok(TraceRecord::new(
+        u64::from_le_bytes(read_array(&record[..8])),
+        u64::from_le_bytes(read_array(&record[8..16])),
+        instruction,
+        endianness.from_bytes(read_array(&record[RECORD_FIXED_SIZE..])),
+        endianness.from_bytes(read_array(&record[RECORD_FIXED_SIZE + S..])),
+    )
+    .with_source(record[17])
+    .with_cell_size(record[18]))
+}
+
+/// Decode a trace encoded by [encode_trace] for the expected word sizes, in any byte order.
+/// Return [Error::ConfigMismatch] if the check rejects the fingerprint of the trace
+pub fn decode_trace<K, V, const S: usize, const T: usize>(
+    bytes: &[u8],
+    check: FingerprintCheck,
+) -> Result<Vec<TraceRecord<K, V, S, T>>, Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    if bytes.len() < HEADER_SIZE {
+        return Err(Error::TruncatedTrace);
+    }
+    let (header, body) = bytes.split_at(HEADER_SIZE);
+    let (count, endianness) = decode_header::<S, T>(header, check)?;
+    let record_size = RECORD_FIXED_SIZE + S + T;
+    let body_size = usize::try_from(count)
+        .ok()
+        .and_then(|count| count.checked_mul(record_size))
+        .ok_or(Error::TruncatedTrace)?;
+    if body.len() < body_size {
+        return Err(Error::TruncatedTrace);
+    }
+    if body.len() > body_size {
+        return Err(Error::InvalidTraceEncoding);
+    }
+    body.chunks_exact(record_size)
+        .map(|record| decode_record(record, endianness))
+        .collect()
+}
+
+// Fill the buffer from the reader, a premature end of the input is a truncated trace
+#[cfg(feature = "std")]
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), Error> {
+    reader.read_exact(buf).map_err(|error| match error.kind() {
+        io::ErrorKind::UnexpectedEof => Error::TruncatedTrace,
+        _ => Error::TraceIo,
+    })
+}
+
+/// Streaming decoder of the binary format, only one record is kept in memory at a time.
+/// The records are yielded in the order they were encoded, the reader stops after the
+/// record count of the header and does not check that the input ends there
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct TraceReader<R, K, V, const S: usize, const T: usize> {
+    reader: R,
+    remaining: u64,
+    endianness: Endianness,
+    _marker: PhantomData<(K, V)>,
+}
+
+#[cfg(feature = "std")]
+impl<R, K, V, const S: usize, const T: usize> TraceReader<R, K, V, S, T>
+where
+    R: Read,
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Read the header for the expected word sizes and check its config fingerprint
+    pub fn new(mut reader: R, check: FingerprintCheck) -> Result<Self, Error> {
+        let mut header = [0u8; HEADER_SIZE];
+        read_exact(&mut reader, &mut header)?;
+        let (remaining, endianness) = decode_header::<S, T>(&header, check)?;
+        Ok(Self {
+            reader,
+            remaining,
+            endianness,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Get the number of records left to read
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R, K, V, const S: usize, const T: usize> Iterator for TraceReader<R, K, V, S, T>
+where
+    R: Read,
+    K: Base<S>,
+    V: Base<T>,
+{
+    type Item = Result<TraceRecord<K, V, S, T>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let mut record = alloc::vec![0u8; RECORD_FIXED_SIZE + S + T];
+        let result = read_exact(&mut self.reader, &mut record)
+            .and_then(|_| decode_record(&record, self.endianness));
+        // Stop at the first error
+        self.remaining = match result {
+            Ok(_) => self.remaining - 1,
+            Err(_) => 0,
+        };
+        Some(result)
+    }
+}
+
+// Copy the first N bytes of the slice, the caller checked the length
+fn read_array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut array = [0u8; N];
+    array.copy_from_slice(&bytes[..N]);
+    array
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_trace, encode_trace, COUNT_OFFSET, HEADER_SIZE, TRACE_MAGIC};
+    use crate::{
+        base::{Base, B128, B16, B256, B32, B64},
+        config::{Endianness, FingerprintCheck},
+        error::Error,
+        machine::{AbstractTraceRecord, MemoryInstruction, TraceRecord},
+    };
+    extern crate alloc;
+    use alloc::{vec, vec::Vec};
+    use rand::Rng;
+
+    const FINGERPRINT: [u8; 32] = [7u8; 32];
+    const EXPECT: FingerprintCheck = FingerprintCheck::Expect(FINGERPRINT);
+
+    fn sample_trace<K: Base<S>, V: Base<T>, const S: usize, const T: usize>(
+    ) -> Vec<TraceRecord<K, V, S, T>> {
+        let instructions = [
+            MemoryInstruction::Write,
+            MemoryInstruction::Read,
+            MemoryInstruction::Push,
+            MemoryInstruction::Pop,
+        ];
+        (0..64u64)
+            .map(|i| {
+                TraceRecord::new(
+                    i,
+                    i % 3,
+                    instructions[i as usize % 4],
+                    K::MAX - K::from(i),
+                    V::from(i * 0x0101),
+                )
+                .with_source(i as u8 % 5)
+                .with_cell_size((i as usize % T) as u8)
+            })
+            .collect()
+    }
+
+    fn round_trip<K: Base<S>, V: Base<T>, const S: usize, const T: usize>() {
+        let trace = sample_trace::<K, V, S, T>();
+        let bytes = encode_trace(&trace, Endianness::Big, FINGERPRINT);
+        assert_eq!(bytes.len(), HEADER_SIZE + trace.len() * (19 + S + T));
+        assert_eq!(bytes[..8], TRACE_MAGIC);
+        assert_eq!(decode_trace::<K, V, S, T>(&bytes, EXPECT).unwrap(), trace);
+        let little = encode_trace(&trace, Endianness::Little, FINGERPRINT);
+        assert_eq!(little.len(), bytes.len());
+        assert_eq!(decode_trace::<K, V, S, T>(&little, EXPECT).unwrap(), trace);
+    }
+
+    #[test]
+    fn test_round_trip_all_widths() {
+        round_trip::<B256, B256, 32, 32>();
+        round_trip::<B128, B128, 16, 16>();
+        round_trip::<B64, B64, 8, 8>();
+        round_trip::<B32, B32, 4, 4>();
+        round_trip::<B16, B16, 2, 2>();
+        round_trip::<B256, B64, 32, 8>();
+        round_trip::<B32, B256, 4, 32>();
+        assert_eq!(
+            decode_trace::<B64, B64, 8, 8>(
+                &encode_trace::<B64, B64, 8, 8>(&[], Endianness::Big, FINGERPRINT),
+                EXPECT
+            )
+            .unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_rejected_headers() {
+        let bytes = encode_trace(
+            &sample_trace::<B64, B64, 8, 8>(),
+            Endianness::Big,
+            FINGERPRINT,
+        );
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[0] ^= 0xff;
+        assert_eq!(
+            decode_trace::<B64, B64, 8, 8>(&wrong_magic, EXPECT),
+            Err(Error::InvalidTraceMagic)
+        );
+
+        assert_eq!(
+            decode_trace::<B256, B64, 32, 8>(&bytes, EXPECT),
+            Err(Error::TraceWordSizeMismatch)
+        );
+        assert_eq!(
+            decode_trace::<B64, B32, 8, 4>(&bytes, EXPECT),
+            Err(Error::TraceWordSizeMismatch)
+        );
+
+        for length in [0, HEADER_SIZE - 1, HEADER_SIZE, bytes.len() - 1] {
+            assert_eq!(
+                decode_trace::<B64, B64, 8, 8>(&bytes[..length], EXPECT),
+                Err(Error::TruncatedTrace)
+            );
+        }
+
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert_eq!(
+            decode_trace::<B64, B64, 8, 8>(&extended, EXPECT),
+            Err(Error::InvalidTraceEncoding)
+        );
+
+        let mut wrong_instruction = bytes.clone();
+        wrong_instruction[HEADER_SIZE + 16] = 4;
+        assert_eq!(
+            decode_trace::<B64, B64, 8, 8>(&wrong_instruction, EXPECT),
+            Err(Error::InvalidTraceEncoding)
+        );
+
+        // A cell is narrower than the value word
+        let mut wide_cell = bytes.clone();
+        wide_cell[HEADER_SIZE + 18] = 8;
+        assert_eq!(
+            decode_trace::<B64, B64, 8, 8>(&wide_cell, EXPECT),
+            Err(Error::InvalidTraceEncoding)
+        );
+
+        let mut wrong_order = bytes.clone();
+        wrong_order[12] = 2;
+        assert_eq!(
+            decode_trace::<B64, B64, 8, 8>(&wrong_order, EXPECT),
+            Err(Error::InvalidTraceEncoding)
+        );
+    }
+
+    #[test]
+    fn test_config_fingerprint() {
+        let trace = sample_trace::<B64, B64, 8, 8>();
+        let bytes = encode_trace(&trace, Endianness::Big, FINGERPRINT);
+        assert_eq!(
+            decode_trace::<B64, B64, 8, 8>(&bytes, EXPECT).unwrap(),
+            trace
+        );
+
+        // A trace made under another config is rejected unless the load is forced
+        let other = [8u8; 32];
+        assert_eq!(
+            decode_trace::<B64, B64, 8, 8>(&bytes, FingerprintCheck::Expect(other)),
+            Err(Error::ConfigMismatch {
+                expected: other,
+                found: FINGERPRINT
+            })
+        );
+        assert_eq!(
+            decode_trace::<B64, B64, 8, 8>(&bytes, FingerprintCheck::Force).unwrap(),
+            trace
+        );
+        #[cfg(feature = "std")]
+        assert_eq!(
+            super::TraceReader::<_, B64, B64, 8, 8>::new(
+                bytes.as_slice(),
+                FingerprintCheck::Expect(other)
+            )
+            .unwrap_err(),
+            Error::ConfigMismatch {
+                expected: other,
+                found: FINGERPRINT
+            }
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_trace_reader() {
+        use super::TraceReader;
+        let trace = sample_trace::<B256, B64, 32, 8>();
+        let bytes = encode_trace(&trace, Endianness::Little, FINGERPRINT);
+        let reader = TraceReader::<_, B256, B64, 32, 8>::new(bytes.as_slice(), EXPECT).unwrap();
+        assert_eq!(reader.remaining(), 64);
+        let decoded: Result<Vec<_>, _> = reader.collect();
+        assert_eq!(decoded.unwrap(), trace);
+
+        // The reader stops at the first missing byte
+        let mut reader =
+            TraceReader::<_, B256, B64, 32, 8>::new(&bytes[..bytes.len() - 1], EXPECT).unwrap();
+        assert_eq!(reader.by_ref().take(63).filter(Result::is_ok).count(), 63);
+        assert_eq!(reader.next(), Some(Err(Error::TruncatedTrace)));
+        assert_eq!(reader.next(), None);
+        assert_eq!(
+            TraceReader::<_, B64, B64, 8, 8>::new(bytes.as_slice(), EXPECT).unwrap_err(),
+            Error::TraceWordSizeMismatch
+        );
+    }
+
+    #[test]
+    fn test_version_compatibility() {
+        let trace = sample_trace::<B32, B32, 4, 4>();
+        let bytes = encode_trace(&trace, Endianness::Big, FINGERPRINT);
+
+        // A newer minor version keeps the layout
+        let mut newer_minor = bytes.clone();
+        newer_minor[9] = 0xff;
+        assert_eq!(
+            decode_trace::<B32, B32, 4, 4>(&newer_minor, EXPECT).unwrap(),
+            trace
+        );
+
+        for major in [0, 2, 0xff] {
+            let mut other_major = bytes.clone();
+            other_major[8] = major;
+            assert_eq!(
+                decode_trace::<B32, B32, 4, 4>(&other_major, EXPECT),
+                Err(Error::UnsupportedTraceVersion)
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_random_bytes() {
+        let mut rng = rand::thread_rng();
+        let valid = encode_trace(
+            &sample_trace::<B16, B16, 2, 2>(),
+            Endianness::Big,
+            FINGERPRINT,
+        );
+        for _ in 0..10_000 {
+            let length = rng.gen_range(0..128);
+            let mut bytes: Vec<u8> = (0..length).map(|_| rng.gen()).collect();
+            // Keep a valid header prefix half of the time to reach the record decoding
+            if rng.gen_bool(0.5) {
+                let prefix = HEADER_SIZE.min(bytes.len());
+                bytes[..prefix].copy_from_slice(&valid[..prefix]);
+                if bytes.len() >= HEADER_SIZE {
+                    let count = ((bytes.len() - HEADER_SIZE) / 23) as u64;
+                    bytes[COUNT_OFFSET..HEADER_SIZE].copy_from_slice(&count.to_le_bytes());
+                }
+            }
+            if let Ok(trace) = decode_trace::<B16, B16, 2, 2>(&bytes, EXPECT) {
+                assert_eq!(encode_trace(&trace, Endianness::Big, FINGERPRINT), bytes);
+            }
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//! Host side checks of the trace, run before the circuits to get readable diagnostics.
+//! A trace is well formed if its time log starts at 0 and is strictly increasing, the first
+//! access to every cell is a write and every read returns the value of the last write.
+//! Under [ReadPolicy::ZeroOnUninit] the first access to a cell may also be a read of zero.
+//! The instruction of a [TraceRecord] is always a valid code, so it is not checked.
+//! Traces stitched from several sources can be brought to time order first with
+//! [canonicalize_trace].
+extern crate alloc;
+use crate::{
+    base::Base,
+    config::ReadPolicy,
+    error::{Error, RecordSummary},
+    machine::{AbstractTraceRecord, TraceRecord},
+};
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+use core::fmt::{Display, Formatter};
+
+/// Class of a [TraceViolation]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The time log of the first record is not 0
+    NonZeroStart,
+    /// The time log is not greater than the one of the previous record
+    NonIncreasingTime,
+    /// The first access to a cell is a read under [ReadPolicy::ErrorOnUninit]
+    ReadBeforeWrite,
+    /// The first access to a cell is a read of a non zero value under [ReadPolicy::ZeroOnUninit]
+    NonZeroUninitializedRead,
+    /// A read does not return the value of the last write to the cell
+    ReadValueMismatch,
+}
+
+/// Well-formedness violation found by [validate_trace]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceViolation {
+    /// Class of the violation
+    pub kind: ViolationKind,
+    /// Indices of the offending records in the trace, the earlier record first
+    pub indices: Vec<usize>,
+}
+
+impl Display for TraceViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let last = self.indices.last().copied().unwrap_or_default();
+        match self.kind {
+            ViolationKind::NonZeroStart => {
+                write!(f, "Record {}: the time log does not start at 0", last)
+            }
+            ViolationKind::NonIncreasingTime => write!(
+                f,
+                "Record {}: the time log is not greater than the one of record {}",
+                last, self.indices[0]
+            ),
+            ViolationKind::ReadBeforeWrite => {
+                write!(f, "Record {}: the cell is read before any write", last)
+            }
+            ViolationKind::NonZeroUninitializedRead => write!(
+                f,
+                "Record {}: the cell is read before any write and the value is not zero",
+                last
+            ),
+            ViolationKind::ReadValueMismatch => write!(
+                f,
+                "Record {}: the read value differs from the value written by record {}",
+                last, self.indices[0]
+            ),
+        }
+    }
+}
+
+/// Check that the trace, in time order, is well formed and return every violation otherwise.
+/// The first access to every cell must be a write, as under [ReadPolicy::ErrorOnUninit]
+pub fn validate_trace<K, V, const S: usize, const T: usize>(
+    trace: &[TraceRecord<K, V, S, T>],
+) -> Result<(), Vec<TraceViolation>>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    validate_trace_with_policy(trace, ReadPolicy::ErrorOnUninit)
+}
+
+/// Check that the trace, in time order, was generated under the read policy
+/// and is well formed, return every violation otherwise
+pub fn validate_trace_with_policy<K, V, const S: usize, const T: usize>(
+    trace: &[TraceRecord<K, V, S, T>],
+    read_policy: ReadPolicy,
+) -> Result<(), Vec<TraceViolation>>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    let mut violations = Vec::new();
+    if let Some(first) = trace.first() {
+        if first.time_log() != 0 {
+            violations.push(TraceViolation {
+                kind: ViolationKind::NonZeroStart,
+                indices: vec![0],
+            });
+        }
+    }
+    for (index, pair) in trace.windows(2).enumerate() {
+        if pair[1].time_log() <= pair[0].time_log() {
+            violations.push(TraceViolation {
+                kind: ViolationKind::NonIncreasingTime,
+                indices: vec![index, index + 1],
+            });
+        }
+    }
+    // Index and value of the last write of every cell
+    let mut last_write: BTreeMap<K, (usize, V)> = BTreeMap::new();
+    for (index, record) in trace.iter().enumerate() {
+        if record.instruction().is_write() {
+            last_write.insert(record.address(), (index, record.value()));
+            continue;
+        }
+        match last_write.get(&record.address()) {
+            None if read_policy == ReadPolicy::ErrorOnUninit => violations.push(TraceViolation {
+                kind: ViolationKind::ReadBeforeWrite,
+                indices: vec![index],
+            }),
+            // The cell holds zero until its first write
+            None if !record.value().is_zero() => violations.push(TraceViolation {
+                kind: ViolationKind::NonZeroUninitializedRead,
+                indices: vec![index],
+            }),
+            None => {}
+            Some((write, value)) if *value != record.value() => violations.push(TraceViolation {
+                kind: ViolationKind::ReadValueMismatch,
+                indices: vec![*write, index],
+            }),
+            Some(_) => {}
+        }
+    }
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+// Get the fields of a record carried by an error
+fn summary<K, V, const S: usize, const T: usize>(record: &TraceRecord<K, V, S, T>) -> RecordSummary
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    RecordSummary {
+        stack_depth: record.stack_depth(),
+        instruction: record.instruction(),
+        addr: record.address().fixed_be_bytes(),
+        value: record.value().fixed_be_bytes(),
+    }
+}
+
+/// Sort the trace by time log and remove the exact duplicates of a record, e.g. a record
+/// emitted twice by a logging layer. Two different records with the same time log are
+/// rejected with [Error::ConflictingRecords], so the time log of the result is strictly
+/// increasing
+pub fn canonicalize_trace<K, V, const S: usize, const T: usize>(
+    mut trace: Vec<TraceRecord<K, V, S, T>>,
+) -> Result<Vec<TraceRecord<K, V, S, T>>, Error>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    // Sort by key rather than with the record order, which panics on equal time logs. The
+    // sort is stable, so the duplicates of a record end up next to each other
+    trace.sort_by_key(|record| (record.time_log, record.address));
+    trace.dedup();
+    match trace
+        .windows(2)
+        .find(|pair| pair[1].time_log() <= pair[0].time_log())
+    {
+        Some(pair) => Err(Error::ConflictingRecords {
+            time_log: pair[1].time_log(),
+            first: summary(&pair[0]),
+            second: summary(&pair[1]),
+        }),
+        None => Ok(trace),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        canonicalize_trace, validate_trace, validate_trace_with_policy, TraceViolation,
+        ViolationKind,
+    };
+    use crate::{
+        base::{Base, B256, B64},
+        config::{ConfigBuilder, DefaultConfig, ReadPolicy},
+        error::{Error, RecordSummary},
+        machine::{
+            tests::StateMachine, AbstractMachine, AbstractMemoryMachine, AbstractStackMachine,
+            AbstractTraceRecord, MemoryInstruction, TraceRecord,
+        },
+    };
+    extern crate alloc;
+    use alloc::{format, vec, vec::Vec};
+
+    fn record(
+        time_log: u64,
+        instruction: MemoryInstruction,
+        address: u64,
+        value: u64,
+    ) -> TraceRecord<B64, B64, 8, 8> {
+        TraceRecord::new(
+            time_log,
+            0,
+            instruction,
+            B64::from(address),
+            B64::from(value),
+        )
+    }
+
+    fn kinds(violations: &[TraceViolation]) -> Vec<ViolationKind> {
+        violations.iter().map(|violation| violation.kind).collect()
+    }
+
+    #[test]
+    fn test_machine_trace_is_valid() {
+        let mut sm = StateMachine::<B256, B256, 32, 32>::new(DefaultConfig::default_config());
+        let base = sm.base_address();
+        sm.write(base, B256::from(1)).unwrap();
+        sm.write(base + B256::from(7), B256::from(2)).unwrap();
+        sm.read(base + B256::from(3)).unwrap();
+        sm.push(B256::from(3)).unwrap();
+        sm.pop().unwrap();
+        assert_eq!(validate_trace(&sm.trace()), Ok(()));
+        assert_eq!(validate_trace::<B64, B64, 8, 8>(&[]), Ok(()));
+    }
+
+    #[test]
+    fn test_time_violations() {
+        let trace = [
+            record(2, MemoryInstruction::Write, 0, 1),
+            record(3, MemoryInstruction::Write, 8, 1),
+            record(3, MemoryInstruction::Write, 16, 1),
+            record(1, MemoryInstruction::Write, 24, 1),
+        ];
+        let violations = validate_trace(&trace).unwrap_err();
+        assert_eq!(
+            violations,
+            vec![
+                TraceViolation {
+                    kind: ViolationKind::NonZeroStart,
+                    indices: vec![0],
+                },
+                TraceViolation {
+                    kind: ViolationKind::NonIncreasingTime,
+                    indices: vec![1, 2],
+                },
+                TraceViolation {
+                    kind: ViolationKind::NonIncreasingTime,
+                    indices: vec![2, 3],
+                },
+            ]
+        );
+        assert_eq!(
+            format!("{}", violations[0]),
+            "Record 0: the time log does not start at 0"
+        );
+        assert_eq!(
+            format!("{}", violations[1]),
+            "Record 2: the time log is not greater than the one of record 1"
+        );
+    }
+
+    #[test]
+    fn test_read_violations() {
+        let trace = [
+            record(0, MemoryInstruction::Read, 0, 0),
+            record(1, MemoryInstruction::Write, 8, 5),
+            record(2, MemoryInstruction::Read, 8, 5),
+            record(3, MemoryInstruction::Push, 16, 7),
+            record(4, MemoryInstruction::Pop, 16, 6),
+            record(5, MemoryInstruction::Write, 8, 9),
+            record(6, MemoryInstruction::Read, 8, 5),
+        ];
+        let violations = validate_trace(&trace).unwrap_err();
+        assert_eq!(
+            kinds(&violations),
+            vec![
+                ViolationKind::ReadBeforeWrite,
+                ViolationKind::ReadValueMismatch,
+                ViolationKind::ReadValueMismatch,
+            ]
+        );
+        assert_eq!(violations[0].indices, vec![0]);
+        assert_eq!(violations[1].indices, vec![3, 4]);
+        assert_eq!(violations[2].indices, vec![5, 6]);
+        assert_eq!(
+            format!("{}", violations[0]),
+            "Record 0: the cell is read before any write"
+        );
+        assert_eq!(
+            format!("{}", violations[2]),
+            "Record 6: the read value differs from the value written by record 5"
+        );
+    }
+
+    #[test]
+    fn test_read_policies() {
+        // The cell at 8 is read before its first write
+        let trace = [
+            record(0, MemoryInstruction::Write, 0, 1),
+            record(1, MemoryInstruction::Read, 8, 0),
+            record(2, MemoryInstruction::Write, 8, 2),
+            record(3, MemoryInstruction::Read, 8, 2),
+        ];
+        assert_eq!(
+            validate_trace_with_policy(&trace, ReadPolicy::ZeroOnUninit),
+            Ok(())
+        );
+        let violations = validate_trace_with_policy(&trace, ReadPolicy::ErrorOnUninit).unwrap_err();
+        assert_eq!(kinds(&violations), vec![ViolationKind::ReadBeforeWrite]);
+        assert_eq!(validate_trace(&trace), Err(violations));
+
+        // Uninitialized cells hold zero
+        let trace = [record(0, MemoryInstruction::Read, 8, 3)];
+        let violations = validate_trace_with_policy(&trace, ReadPolicy::ZeroOnUninit).unwrap_err();
+        assert_eq!(
+            kinds(&violations),
+            vec![ViolationKind::NonZeroUninitializedRead]
+        );
+        assert_eq!(
+            format!("{}", violations[0]),
+            "Record 0: the cell is read before any write and the value is not zero"
+        );
+
+        // The machine enforces its read policy
+        for read_policy in [ReadPolicy::ZeroOnUninit, ReadPolicy::ErrorOnUninit] {
+            let config = ConfigBuilder::<B256, 32>::new()
+                .read_policy(read_policy)
+                .build()
+                .unwrap();
+            let mut sm = StateMachine::<B256, B256, 32, 32>::from_config(config);
+            let base = sm.base_address();
+            sm.write(base, B256::from(1)).unwrap();
+            let unwritten = base + B256::from(32);
+            match read_policy {
+                ReadPolicy::ZeroOnUninit => {
+                    sm.read(unwritten).unwrap();
+                    // An unaligned read spans a written and an unwritten cell
+                    sm.read(base + B256::from(8)).unwrap();
+                    assert_eq!(sm.trace().len(), 4);
+                }
+                ReadPolicy::ErrorOnUninit => {
+                    let expected = Error::UninitializedRead {
+                        addr: unwritten.fixed_be_bytes(),
+                    };
+                    assert_eq!(sm.read(unwritten).unwrap_err(), expected);
+                    assert_eq!(sm.read(base + B256::from(8)).unwrap_err(), expected);
+                    sm.read(base).unwrap();
+                    assert_eq!(sm.trace().len(), 2);
+                }
+            }
+            assert_eq!(validate_trace_with_policy(&sm.trace(), read_policy), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_canonicalize_trace() {
+        let trace = vec![
+            record(2, MemoryInstruction::Read, 0, 1),
+            record(0, MemoryInstruction::Write, 0, 1),
+            record(1, MemoryInstruction::Write, 8, 2),
+        ];
+        let canonical = canonicalize_trace(trace.clone()).unwrap();
+        assert_eq!(canonical, vec![trace[1], trace[2], trace[0]]);
+        assert_eq!(validate_trace(&canonical), Ok(()));
+        assert_eq!(canonicalize_trace::<B64, B64, 8, 8>(vec![]), Ok(vec![]));
+
+        // Exact duplicates are removed wherever they are
+        let mut duplicated = trace.clone();
+        duplicated.extend_from_slice(&[trace[0], trace[2], trace[0]]);
+        assert_eq!(canonicalize_trace(duplicated), Ok(canonical));
+    }
+
+    #[test]
+    fn test_canonicalize_conflicting_records() {
+        let write = record(1, MemoryInstruction::Write, 8, 2);
+        let summary = |instruction, address: u64, value: u64| RecordSummary {
+            stack_depth: 0,
+            instruction,
+            addr: B64::from(address).fixed_be_bytes(),
+            value: B64::from(value).fixed_be_bytes(),
+        };
+        let cases = [
+            (
+                record(1, MemoryInstruction::Write, 8, 3),
+                summary(MemoryInstruction::Write, 8, 3),
+            ),
+            (
+                record(1, MemoryInstruction::Write, 16, 2),
+                summary(MemoryInstruction::Write, 16, 2),
+            ),
+            (
+                record(1, MemoryInstruction::Read, 8, 2),
+                summary(MemoryInstruction::Read, 8, 2),
+            ),
+        ];
+        for (other, other_summary) in cases {
+            let trace = vec![record(0, MemoryInstruction::Write, 0, 1), other, write];
+            // Records with the same key keep their order in the trace
+            let (first, second) = if write.address() < other.address() {
+                (summary(MemoryInstruction::Write, 8, 2), other_summary)
+            } else {
+                (other_summary, summary(MemoryInstruction::Write, 8, 2))
+            };
+            assert_eq!(
+                canonicalize_trace(trace),
+                Err(Error::ConflictingRecords {
+                    time_log: 1,
+                    first,
+                    second,
+                })
+            );
+        }
+
+        // The stack depth is part of the record
+        let deeper = TraceRecord::new(1, 1, MemoryInstruction::Write, B64::from(8), B64::from(2));
+        assert!(matches!(
+            canonicalize_trace(vec![write, deeper, write]),
+            Err(Error::ConflictingRecords { time_log: 1, .. })
+        ));
+    }
+}
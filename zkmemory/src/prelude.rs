@@ -0,0 +1,27 @@
+//! The prelude re-exports the supported public API of the crate.
+//! Items reachable from here (or from the crate root) are kept stable,
+//! deep module paths might change when the modules are reorganized.
+//!
+//! ```
+//! use zkmemory::prelude::*;
+//!
+//! let value = B256::from(1024);
+//! assert_eq!(value.bit_len(), 11);
+//! ```
+pub use crate::base::{Base, UIntConvertible, Uint, B128, B16, B256, B32, B512, B64};
+pub use crate::cancellation::CancellationToken;
+pub use crate::commitment::{
+    ipa::IPAMemoryCommitment, kzg::KZGMemoryCommitment, merkle::MerkleTraceCommitment,
+    CommitmentScheme,
+};
+pub use crate::config::{Config, ConfigArgs, ConfigBuilder, DefaultConfig};
+pub use crate::constraints::{
+    permutation_circuit::{PermutationCircuit, PermutationProver},
+    ConvertedTraceRecord,
+};
+pub use crate::error::{Error, VerifyError};
+pub use crate::machine::{
+    state::MachineState, AbstractContext, AbstractInstruction, AbstractMachine,
+    AbstractMemoryMachine, AbstractRegisterMachine, AbstractStackMachine, AbstractTraceRecord,
+    CellInteraction, MemoryInstruction, Register, TraceRecord,
+};
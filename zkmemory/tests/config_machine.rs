@@ -0,0 +1,279 @@
+//! A machine outside of the crate gets the hooks of its config from
+//! `impl_state_machine!(Machine, state)`.
+use rbtree::RBTree;
+use std::marker::PhantomData;
+use zkmemory::{
+    base::{Base, B256},
+    config::{AlignmentPolicy, ConfigBuilder, CostModel, MemoryRegion, RegionKind, TraceMode},
+    error::Error,
+    impl_state_machine,
+    machine::{
+        mmio::{without_mmio, MmioHandler},
+        state::MachineState,
+        AbstractContext, AbstractInstruction, AbstractMachine, AbstractTraceRecord, TraceRecord,
+    },
+};
+
+/// Instruction set of the machine
+#[derive(Debug, Clone, Copy)]
+pub enum Instruction<K, V> {
+    /// Read from memory
+    Read(K),
+    /// Write to memory
+    Write(K, V),
+}
+
+/// RAM machine keeping the state of its config
+#[derive(Debug, Clone)]
+pub struct ConfiguredMachine<K, V, const S: usize, const T: usize>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    memory: RBTree<K, V>,
+    state: MachineState<K, V, S, T>,
+    time_log: u64,
+    stack_depth: u64,
+    stack_ptr: K,
+    execution_trace: RBTree<TraceRecord<K, V, S, T>, PhantomData<()>>,
+}
+
+impl<M, K, V, const S: usize, const T: usize> AbstractContext<M, K, V>
+    for ConfiguredMachine<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+    M: AbstractMachine<K, V>,
+{
+    type Memory = RBTree<K, V>;
+
+    fn memory(&mut self) -> &'_ mut RBTree<K, V> {
+        &mut self.memory
+    }
+
+    fn ro_memory(&self) -> &'_ RBTree<K, V> {
+        &self.memory
+    }
+
+    fn set_stack_depth(&mut self, stack_depth: u64) {
+        self.stack_depth = stack_depth;
+    }
+
+    fn set_time_log(&mut self, time_log: u64) {
+        self.time_log = time_log;
+    }
+
+    fn set_stack_ptr(&mut self, stack_ptr: K) {
+        self.stack_ptr = stack_ptr;
+    }
+
+    fn stack_ptr(&self) -> K {
+        self.stack_ptr
+    }
+
+    fn stack_depth(&self) -> u64 {
+        self.stack_depth
+    }
+
+    fn time_log(&self) -> u64 {
+        self.time_log
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> AbstractInstruction<ConfiguredMachine<K, V, S, T>, K, V>
+    for Instruction<K, V>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    fn exec(&self, machine: &mut ConfiguredMachine<K, V, S, T>) -> Result<(), Error> {
+        match self {
+            Instruction::Read(address) => machine.read(*address).map(|_| ()),
+            Instruction::Write(address, value) => machine.write(*address, *value).map(|_| ()),
+        }
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> ConfiguredMachine<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    /// Create a machine from the config
+    pub fn new(config: zkmemory::config::Config<K, S>) -> Self {
+        Self {
+            memory: RBTree::new(),
+            state: MachineState::new(config),
+            time_log: 0,
+            stack_depth: 0,
+            stack_ptr: config.stack.low(),
+            execution_trace: RBTree::new(),
+        }
+    }
+}
+
+impl<K, V, const S: usize, const T: usize> AbstractMachine<K, V> for ConfiguredMachine<K, V, S, T>
+where
+    K: Base<S>,
+    V: Base<T>,
+{
+    type Machine = Self;
+    type Context = Self;
+    type Instruction = Instruction<K, V>;
+    type TraceRecord = TraceRecord<K, V, S, T>;
+
+    fn context(&mut self) -> &'_ mut Self::Context {
+        self
+    }
+
+    fn ro_context(&self) -> &'_ Self::Context {
+        self
+    }
+
+    fn word_size(&self) -> K {
+        self.state.config().word_size
+    }
+
+    fn register_start(&self) -> K {
+        self.state.config().register.low()
+    }
+
+    fn track(&mut self, trace: Self::TraceRecord) {
+        self.execution_trace.insert(trace, PhantomData);
+    }
+
+    fn trace(&self) -> Vec<Self::TraceRecord> {
+        self.execution_trace.keys().copied().collect()
+    }
+
+    fn trace_iter(&self) -> Box<dyn Iterator<Item = &'_ Self::TraceRecord> + '_> {
+        Box::new(self.execution_trace.keys())
+    }
+
+    fn truncate_trace(&mut self, time_log: u64) {
+        while let Some((record, _)) = self.execution_trace.get_last() {
+            if record.time_log() < time_log {
+                break;
+            }
+            self.execution_trace.pop_last();
+        }
+    }
+
+    fn exec(&mut self, instruction: &Self::Instruction) -> Result<(), Error> {
+        instruction.exec(self)
+    }
+
+    fn base_address(&self) -> K {
+        self.state.memory_section().low()
+    }
+
+    fn get_memory_address(&self) -> (K, K) {
+        let section = self.state.memory_section();
+        (section.low(), section.high())
+    }
+
+    fn get_stack_depth(&self) -> u64 {
+        self.stack_depth
+    }
+
+    fn max_stack_depth(&self) -> u64 {
+        self.state.config().stack_depth.into()
+    }
+}
+
+impl_state_machine!(ConfiguredMachine, state);
+
+#[test]
+fn config_hooks_apply_to_a_macro_machine() {
+    let config = ConfigBuilder::<B256, 32>::new()
+        .memory_size(0x100)
+        .growable(true)
+        .max_memory_size(0x180)
+        .alignment(AlignmentPolicy::Strict)
+        .cost_model(CostModel {
+            read: 1,
+            write: 2,
+            grow: 3,
+            ..CostModel::default()
+        })
+        .gas_budget(11)
+        .build()
+        .unwrap();
+    let mut machine = ConfiguredMachine::<B256, B256, 32, 32>::new(config);
+    let base = machine.base_address();
+    assert_eq!(machine.config_fingerprint(), config.config_fingerprint());
+    assert_eq!(machine.trace_mode(), TraceMode::Full);
+
+    // The alignment policy of the config rejects an unaligned access
+    assert!(matches!(
+        machine.exec(&Instruction::Write(base + B256::from(1), B256::from(1))),
+        Err(Error::UnalignedAccess { .. })
+    ));
+    machine
+        .exec(&Instruction::Write(base, B256::from(7)))
+        .unwrap();
+    machine.exec(&Instruction::Read(base)).unwrap();
+    assert_eq!(machine.gas_used(), 3);
+    assert_eq!(machine.bytes_written(), 32);
+
+    // The memory section grows in the state and the growth is charged
+    let high = base + B256::from(0x100 + 31);
+    assert_eq!(machine.grow(1), Ok(high));
+    assert_eq!(machine.get_memory_address(), (base, high));
+    assert_eq!(machine.gas_used(), 6);
+    assert!(matches!(
+        machine.grow(4),
+        Err(Error::MemoryGrowthLimit { .. })
+    ));
+
+    // The gas budget of the config is enforced
+    machine
+        .exec(&Instruction::Write(base, B256::from(8)))
+        .unwrap();
+    assert_eq!(
+        machine.exec(&Instruction::Write(high - B256::from(31), B256::from(9))),
+        Err(Error::OutOfGas {
+            budget: 11,
+            used: 8,
+            cost: 2,
+        })
+    );
+    machine.exec(&Instruction::Read(base)).unwrap();
+    assert_eq!(machine.gas_used(), 9);
+    assert_eq!(machine.trace().len(), 4);
+}
+
+// Device whose reads return the number of reads so far
+#[derive(Debug, Default)]
+struct Counter(u64);
+
+impl MmioHandler<B256, B256> for Counter {
+    fn on_read(&mut self, _address: B256) -> B256 {
+        self.0 += 1;
+        B256::from(self.0)
+    }
+
+    fn on_write(&mut self, _address: B256, _value: B256) {}
+}
+
+#[test]
+fn devices_are_registered_in_the_state() {
+    let config = ConfigBuilder::<B256, 32>::new().build().unwrap();
+    let mut machine = ConfiguredMachine::<B256, B256, 32, 32>::new(config);
+    let region = MemoryRegion::new(RegionKind::Io, B256::from(0x50000), B256::from(0x40));
+    machine
+        .state
+        .register_mmio(region, Box::<Counter>::default())
+        .unwrap();
+    assert_eq!(machine.mmio_regions(), vec![region]);
+
+    let base = machine.base_address();
+    machine
+        .exec(&Instruction::Write(base, B256::from(7)))
+        .unwrap();
+    machine.exec(&Instruction::Read(region.base)).unwrap();
+    machine.exec(&Instruction::Read(region.base)).unwrap();
+    let trace = machine.trace();
+    assert_eq!(trace[2].value(), B256::from(2));
+    assert_eq!(without_mmio(&trace, machine.mmio_layout()), vec![trace[0]]);
+}
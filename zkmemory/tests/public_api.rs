@@ -0,0 +1,76 @@
+//! Snapshot of the supported public API.
+//! The items of the prelude are listed in `public_api.txt`, adding, removing or renaming one
+//! of them fails the snapshot test and must be a deliberate decision reflected in the list.
+//! The crate root re-exports the prelude.
+#![allow(unused_imports)]
+
+// Prelude
+use zkmemory::prelude::{
+    AbstractContext, AbstractInstruction, AbstractMachine, AbstractMemoryMachine,
+    AbstractRegisterMachine, AbstractStackMachine, AbstractTraceRecord, Base, CancellationToken,
+    CellInteraction, CommitmentScheme, Config, ConfigArgs, ConfigBuilder, ConvertedTraceRecord,
+    DefaultConfig, Error, IPAMemoryCommitment, KZGMemoryCommitment, MachineState,
+    MemoryInstruction, MerkleTraceCommitment, PermutationCircuit, PermutationProver, Register,
+    TraceRecord, UIntConvertible, Uint, VerifyError, B128, B16, B256, B32, B512, B64,
+};
+
+// Exported macros
+use zkmemory::{impl_register_machine, impl_stack_machine, impl_state_machine};
+
+// Names re-exported by the `pub use` items of a source file, sorted
+fn reexported_names(source: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for item in source.split(';') {
+        let paths = match item.find("pub use ") {
+            Some(start) => &item[start + "pub use ".len()..],
+            None => continue,
+        };
+        for path in paths.split(|c| matches!(c, '{' | '}' | ',')) {
+            let path = path.trim();
+            if !path.is_empty() && !path.ends_with("::") {
+                names.push(path.rsplit("::").next().unwrap_or(path).to_string());
+            }
+        }
+    }
+    names.sort();
+    names
+}
+
+#[test]
+fn prelude_matches_the_snapshot() {
+    let snapshot: Vec<&str> = include_str!("public_api.txt").lines().collect();
+    assert_eq!(
+        reexported_names(include_str!("../src/prelude.rs")),
+        snapshot,
+        "The prelude changed, update tests/public_api.txt if the change is intended"
+    );
+    // The crate root re-exports the prelude and nothing else
+    let root = include_str!("../src/lib.rs");
+    assert_eq!(root.matches("pub use ").count(), 1);
+    assert!(root.contains("pub use crate::prelude::*;"));
+}
+
+#[test]
+fn root_and_prelude_export_the_same_items() {
+    let from_root = zkmemory::B256::from(7);
+    let from_prelude = zkmemory::prelude::B256::from(7);
+    assert_eq!(from_root, from_prelude);
+    assert_eq!(
+        zkmemory::Error::StackOverflow,
+        zkmemory::prelude::Error::StackOverflow
+    );
+}
+
+#[test]
+fn trace_record_is_usable_from_the_prelude() {
+    let record = TraceRecord::<B256, B256, 32, 32>::new(
+        0,
+        0,
+        MemoryInstruction::Write,
+        B256::from(32),
+        B256::from(1),
+    );
+    assert_eq!(record.address(), B256::from(32));
+    assert_eq!(record.instruction(), MemoryInstruction::Write);
+    assert!(!CancellationToken::new().is_cancelled());
+}